@@ -4,9 +4,15 @@
 //! arguments, configuration files, validation, and logging setup.
 
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 use clap::Parser;
 use crate::error::{SerialError, ConfigError, Result};
+use crate::protocol::{build_framer, Framer, FramerKind, FramerOptions};
+use crate::serial::{BackpressurePolicy, CaptureFormat, LocalSerialError, WriteRule};
+use crate::tools::maintenance::MaintenanceSchedule;
+use crate::tools::types::ReadPayload;
+use crate::utils::Validator;
 
 /// Command line arguments
 #[derive(Parser, Debug)]
@@ -34,6 +40,10 @@ pub struct Args {
     #[arg(long, default_value = "30")]
     pub connection_timeout: u64,
 
+    /// Seconds a session may go unused without a connection before it is removed, 0 never removes it
+    #[arg(long)]
+    pub session_idle_timeout: Option<u64>,
+
     /// Default baud rate for serial connections
     #[arg(long, default_value = "115200")]
     pub default_baud_rate: u32,
@@ -73,26 +83,29 @@ pub struct Args {
     /// Show current configuration and exit
     #[arg(long)]
     pub show_config: bool,
+
+    /// Register debug tools, such as simulated cable pulls
+    #[arg(long)]
+    pub debug_tools: bool,
 }
 
 /// Main configuration structure
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct Config {
     pub server: ServerConfig,
     pub serial: SerialConfig,
     pub security: SecurityConfig,
     pub logging: LoggingConfig,
-}
-
-impl Default for Config {
-    fn default() -> Self {
-        Self {
-            server: ServerConfig::default(),
-            serial: SerialConfig::default(),
-            security: SecurityConfig::default(),
-            logging: LoggingConfig::default(),
-        }
-    }
+    #[serde(default)]
+    pub capture: CaptureConfig,
+    #[serde(default)]
+    pub maintenance: MaintenanceConfig,
+    /// Device profiles by name, selected with the open tool's `profile` argument or opened with open_profile
+    #[serde(default)]
+    pub profiles: BTreeMap<String, ProfileConfig>,
+    /// Device protocols by name, selected with the open tool's `protocol` argument
+    #[serde(default)]
+    pub protocols: BTreeMap<String, ProtocolConfig>,
 }
 
 impl Config {
@@ -114,6 +127,9 @@ impl Config {
     pub fn merge_args(&mut self, args: &Args) {
         self.server.max_connections = args.max_connections;
         self.server.connection_timeout_seconds = args.connection_timeout;
+        if let Some(seconds) = args.session_idle_timeout {
+            self.server.session_idle_timeout_seconds = seconds;
+        }
         self.serial.default_baud_rate = args.default_baud_rate;
         self.serial.default_timeout_ms = args.default_timeout_ms;
         self.serial.max_buffer_size = args.max_buffer_size;
@@ -123,6 +139,7 @@ impl Config {
         self.security.restrict_ports = args.restrict_ports;
         self.logging.level = args.log_level.clone();
         self.logging.file = args.log_file.clone();
+        self.server.debug_tools |= args.debug_tools;
     }
 
     /// Validate configuration
@@ -144,6 +161,40 @@ impl Config {
             }.into());
         }
 
+        if self.server.heartbeat_interval_seconds > 0
+            && self.server.client_timeout_seconds < self.server.heartbeat_interval_seconds
+        {
+            return Err(ConfigError::ValueOutOfRange {
+                field: "server.client_timeout_seconds".to_string(),
+                value: self.server.client_timeout_seconds.to_string(),
+                min: self.server.heartbeat_interval_seconds.to_string(),
+                max: u64::MAX.to_string(),
+            }.into());
+        }
+
+        if OrphanPolicy::parse(&self.server.orphan_policy).is_err() {
+            return Err(ConfigError::InvalidValue {
+                field: "server.orphan_policy".to_string(),
+                value: self.server.orphan_policy.clone(),
+            }.into());
+        }
+
+        if self.server.safety_deadline_ms > 0 && self.server.safety_deadline_ms < MIN_SAFETY_DEADLINE_MS {
+            return Err(ConfigError::ValueOutOfRange {
+                field: "server.safety_deadline_ms".to_string(),
+                value: self.server.safety_deadline_ms.to_string(),
+                min: MIN_SAFETY_DEADLINE_MS.to_string(),
+                max: u64::MAX.to_string(),
+            }.into());
+        }
+
+        if self.server.session_cleanup_interval_seconds == 0 {
+            return Err(ConfigError::InvalidValue {
+                field: "server.session_cleanup_interval_seconds".to_string(),
+                value: "0".to_string(),
+            }.into());
+        }
+
         // Serial validation
         if self.serial.default_baud_rate == 0 {
             return Err(ConfigError::InvalidValue {
@@ -152,7 +203,7 @@ impl Config {
             }.into());
         }
 
-        let valid_baud_rates = [300, 600, 1200, 2400, 4800, 9600, 14400, 19200, 28800, 38400, 57600, 115200, 230400, 460800, 921600];
+        let valid_baud_rates = [300, 600, 1200, 2400, 4800, 9600, 14400, 19200, 28800, 31250, 38400, 57600, 115200, 230400, 460800, 921600];
         if !valid_baud_rates.contains(&self.serial.default_baud_rate) {
             return Err(ConfigError::InvalidValue {
                 field: "serial.default_baud_rate".to_string(),
@@ -176,6 +227,130 @@ impl Config {
             }.into());
         }
 
+        if self.serial.batch_max_chunks == 0 {
+            return Err(ConfigError::InvalidValue {
+                field: "serial.batch_max_chunks".to_string(),
+                value: "0".to_string(),
+            }.into());
+        }
+
+        if self.serial.discovery_fast_interval_ms == 0
+            || self.serial.discovery_fast_interval_ms > self.serial.discovery_interval_seconds.saturating_mul(1000)
+        {
+            return Err(ConfigError::ValueOutOfRange {
+                field: "serial.discovery_fast_interval_ms".to_string(),
+                value: self.serial.discovery_fast_interval_ms.to_string(),
+                min: "1".to_string(),
+                max: self.serial.discovery_interval_seconds.saturating_mul(1000).to_string(),
+            }.into());
+        }
+
+        if self.serial.subscription_coalesce_ms > MAX_SUBSCRIPTION_COALESCE_MS {
+            return Err(ConfigError::ValueOutOfRange {
+                field: "serial.subscription_coalesce_ms".to_string(),
+                value: self.serial.subscription_coalesce_ms.to_string(),
+                min: "0".to_string(),
+                max: MAX_SUBSCRIPTION_COALESCE_MS.to_string(),
+            }.into());
+        }
+
+        if BackpressurePolicy::parse(&self.serial.backpressure).is_err() {
+            return Err(ConfigError::InvalidValue {
+                field: "serial.backpressure".to_string(),
+                value: self.serial.backpressure.clone(),
+            }.into());
+        }
+
+        // Security validation
+        if self.security.enable_authentication
+            && self.security.auth_token.as_deref().unwrap_or_default().is_empty()
+            && self.security.allowed_clients.is_empty()
+        {
+            return Err(ConfigError::ConflictingSettings(
+                "security.enable_authentication needs security.auth_token or security.allowed_clients".to_string(),
+            ).into());
+        }
+
+        for (name, permission) in &self.security.tool_policy {
+            if ToolPermission::parse(permission).is_err() {
+                return Err(ConfigError::InvalidValue {
+                    field: format!("security.tool_policy.{}", name),
+                    value: permission.clone(),
+                }.into());
+            }
+        }
+
+        for rule in &self.security.write_rules {
+            rule.rule()?;
+        }
+
+        // Capture validation
+        if CaptureFormat::parse(&self.capture.format).is_err() {
+            return Err(ConfigError::InvalidValue {
+                field: "capture.format".to_string(),
+                value: self.capture.format.clone(),
+            }.into());
+        }
+
+        // Maintenance validation
+        MaintenanceSchedule::from_config(&self.maintenance)?;
+
+        // Profile validation
+        for (name, profile) in &self.profiles {
+            if profile.response_settle_ms == 0 || profile.response_settle_ms > MAX_PROFILE_SETTLE_MS {
+                return Err(ConfigError::ValueOutOfRange {
+                    field: format!("profiles.{}.response_settle_ms", name),
+                    value: profile.response_settle_ms.to_string(),
+                    min: "1".to_string(),
+                    max: MAX_PROFILE_SETTLE_MS.to_string(),
+                }.into());
+            }
+            let in_profile = |e: SerialError| SerialError::InvalidConfig(format!("profiles.{}: {}", name, e));
+            if let Some(baud_rate) = profile.baud_rate {
+                Validator::validate_baud_rate(baud_rate).map_err(in_profile)?;
+            }
+            if let Some(data_bits) = profile.data_bits {
+                Validator::validate_data_bits(data_bits).map_err(in_profile)?;
+            }
+            if let Some(stop_bits) = profile.stop_bits {
+                Validator::validate_stop_bits(&stop_bits.to_string()).map_err(in_profile)?;
+            }
+            if let Some(parity) = &profile.parity {
+                Validator::validate_parity(parity).map_err(in_profile)?;
+            }
+            if let Some(flow_control) = &profile.flow_control {
+                Validator::validate_flow_control(flow_control).map_err(in_profile)?;
+            }
+            if let Some(backpressure) = &profile.backpressure {
+                if BackpressurePolicy::parse(backpressure).is_err() {
+                    return Err(ConfigError::InvalidValue {
+                        field: format!("profiles.{}.backpressure", name),
+                        value: backpressure.clone(),
+                    }.into());
+                }
+            }
+            if let Some(protocol) = &profile.protocol {
+                if !self.protocols.contains_key(protocol) {
+                    return Err(SerialError::InvalidConfig(format!("profiles.{}: unknown protocol {}", name, protocol)));
+                }
+            }
+        }
+
+        // Protocol validation
+        for (name, protocol) in &self.protocols {
+            if let Err(e) = protocol.build_framing() {
+                return Err(SerialError::InvalidConfig(format!("protocols.{}: {}", name, e)));
+            }
+            if let Some(encoding) = &protocol.encoding {
+                if ReadPayload::new(&[], encoding).is_err() {
+                    return Err(ConfigError::InvalidValue {
+                        field: format!("protocols.{}.encoding", name),
+                        value: encoding.clone(),
+                    }.into());
+                }
+            }
+        }
+
         // Logging validation
         let valid_levels = ["error", "warn", "info", "debug", "trace"];
         if !valid_levels.contains(&self.logging.level.as_str()) {
@@ -196,13 +371,49 @@ impl Config {
 
 }
 
+/// Shortest safety deadline; the watchdog pings the client several times within it
+const MIN_SAFETY_DEADLINE_MS: u64 = 200;
+
+/// Longest subscription coalescing window accepted
+pub const MAX_SUBSCRIPTION_COALESCE_MS: u64 = 10_000;
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
 pub struct ServerConfig {
     pub max_connections: usize,
     pub connection_timeout_seconds: u64,
     pub worker_threads: Option<usize>,
     pub enable_metrics: bool,
     pub metrics_interval_seconds: u64,
+    /// Seconds between liveness pings to the client, 0 disables them
+    pub heartbeat_interval_seconds: u64,
+    /// Seconds without a ping response after which the client is considered gone
+    pub client_timeout_seconds: u64,
+    /// What happens to a vanished client's connections: "keep", "suspend" or "close"
+    pub orphan_policy: String,
+    /// Milliseconds within which a vanished client's connections get their
+    /// profile's shutdown commands, ports left open; 0 disables the watchdog
+    pub safety_deadline_ms: u64,
+    /// Close the connections a client opened as soon as its transport closes,
+    /// noting why in their session logs, before the rest of the shutdown
+    pub close_on_disconnect: bool,
+    /// Coordinate port ownership with other server instances on this host
+    pub port_locking: bool,
+    /// Directory shared by all instances for port lock files
+    pub lock_directory: PathBuf,
+    /// Name reported to other instances that find a port locked; empty for a generated one
+    pub instance_name: String,
+    /// Register debug tools, such as simulated cable pulls; never enable in production
+    pub debug_tools: bool,
+    /// Seconds without client requests after which discovery and keep-alive pings park, 0 never parks
+    pub idle_after_seconds: u64,
+    /// Directory for state kept between runs, such as device notes and interrupted transfers
+    pub state_directory: PathBuf,
+    /// Seconds between checks for idle sessions
+    pub session_cleanup_interval_seconds: u64,
+    /// Seconds a session may go unused without a connection before it is removed, 0 never removes it;
+    /// set with --session-idle-timeout, as --connection-timeout no longer governs it
+    pub session_idle_timeout_seconds: u64,
 }
 
 impl Default for ServerConfig {
@@ -213,11 +424,58 @@ impl Default for ServerConfig {
             worker_threads: None,
             enable_metrics: false,
             metrics_interval_seconds: 60,
+            heartbeat_interval_seconds: 30,
+            client_timeout_seconds: 90,
+            orphan_policy: "suspend".to_string(),
+            safety_deadline_ms: 0,
+            close_on_disconnect: true,
+            port_locking: true,
+            lock_directory: std::env::temp_dir().join("serial-mcp-locks"),
+            instance_name: String::new(),
+            debug_tools: false,
+            idle_after_seconds: 0,
+            state_directory: PathBuf::from("state"),
+            session_cleanup_interval_seconds: 60,
+            session_idle_timeout_seconds: 30,
+        }
+    }
+}
+
+/// What happens to the connections of a client that stopped responding
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OrphanPolicy {
+    /// Leave connections untouched
+    Keep,
+    /// Pause reception until the client answers again
+    Suspend,
+    /// Close connections and release their ports
+    Close,
+}
+
+impl OrphanPolicy {
+    pub fn parse(value: &str) -> std::result::Result<Self, LocalSerialError> {
+        match value.to_lowercase().as_str() {
+            "keep" => Ok(OrphanPolicy::Keep),
+            "suspend" => Ok(OrphanPolicy::Suspend),
+            "close" => Ok(OrphanPolicy::Close),
+            _ => Err(LocalSerialError::InvalidConfig(format!("Unknown orphan policy: {}", value))),
+        }
+    }
+}
+
+impl std::fmt::Display for OrphanPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OrphanPolicy::Keep => write!(f, "keep"),
+            OrphanPolicy::Suspend => write!(f, "suspend"),
+            OrphanPolicy::Close => write!(f, "close"),
         }
     }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
 pub struct SerialConfig {
     pub default_baud_rate: u32,
     pub default_data_bits: u8,
@@ -230,8 +488,27 @@ pub struct SerialConfig {
     pub retry_delay_ms: u64,
     pub auto_discovery: bool,
     pub discovery_interval_seconds: u64,
+    /// Polling interval in milliseconds right after a port disappears or a
+    /// connection drops; discovery backs off to discovery_interval_seconds
+    pub discovery_fast_interval_ms: u64,
     pub allow_port_sharing: bool,
+    /// Open ports for this process alone (TIOCEXCL on Unix), so another program
+    /// or server instance opening the same device is refused rather than
+    /// reading its traffic; Windows always opens COM ports exclusively
+    pub exclusive_access: bool,
     pub default_line_ending: String,
+    /// Maximum number of chunks delivered by one batched read
+    pub batch_max_chunks: usize,
+    /// Age in milliseconds after which a partial batch is delivered
+    pub batch_max_age_ms: u64,
+    /// Default backpressure policy for new connections: "drop", "block" or "flow_control"
+    pub backpressure: String,
+    /// Window in milliseconds over which received chunks are merged into one
+    /// subscription notification; 0 sends one notification per chunk
+    pub subscription_coalesce_ms: u64,
+    /// On macOS, open the /dev/cu.* callout node when a /dev/tty.* dial-in node
+    /// is requested, instead of only warning that the open may hang
+    pub prefer_callout_device: bool,
 }
 
 impl Default for SerialConfig {
@@ -248,13 +525,21 @@ impl Default for SerialConfig {
             retry_delay_ms: 1000,
             auto_discovery: false,
             discovery_interval_seconds: 5,
+            discovery_fast_interval_ms: 500,
             allow_port_sharing: false,
+            exclusive_access: true,
             default_line_ending: "\n".to_string(),
+            batch_max_chunks: 32,
+            batch_max_age_ms: 100,
+            backpressure: "drop".to_string(),
+            subscription_coalesce_ms: 0,
+            prefer_callout_device: true,
         }
     }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
 pub struct SecurityConfig {
     pub restrict_ports: bool,
     pub allowed_ports: Vec<String>,
@@ -262,8 +547,22 @@ pub struct SecurityConfig {
     pub max_data_size: usize,
     pub rate_limit_enabled: bool,
     pub rate_limit_requests_per_second: u32,
+    /// Refuse clients on initialize unless they pass the checks below
     pub enable_authentication: bool,
+    /// Client names, as sent in `clientInfo.name`, allowed to connect; empty allows any name
     pub allowed_clients: Vec<String>,
+    /// Shared secret clients send as `_meta.authToken` on initialize
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auth_token: Option<String>,
+    /// Tools each client name may call; clients not listed may call every tool
+    pub client_tools: BTreeMap<String, Vec<String>>,
+    /// `allow` or `deny` for every client by tool name, tool category or `*`
+    pub tool_policy: BTreeMap<String, String>,
+    /// Patterns outgoing data must or must not match, per port; refused writes
+    /// are appended to write_audit.jsonl in server.state_directory. Binary
+    /// transfers are checked too, so a rule on a port also applies to the
+    /// YMODEM, ZMODEM, ESP and STM32 loaders' blocks
+    pub write_rules: Vec<WriteRuleConfig>,
 }
 
 impl Default for SecurityConfig {
@@ -277,6 +576,241 @@ impl Default for SecurityConfig {
             rate_limit_requests_per_second: 100,
             enable_authentication: false,
             allowed_clients: vec![],
+            auth_token: None,
+            client_tools: BTreeMap::new(),
+            tool_policy: BTreeMap::new(),
+            write_rules: vec![],
+        }
+    }
+}
+
+/// Whether a policy entry lets its tools be called
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ToolPermission {
+    Allow,
+    Deny,
+}
+
+impl ToolPermission {
+    pub fn parse(value: &str) -> std::result::Result<Self, LocalSerialError> {
+        match value.to_lowercase().as_str() {
+            "allow" => Ok(ToolPermission::Allow),
+            "deny" => Ok(ToolPermission::Deny),
+            _ => Err(LocalSerialError::InvalidConfig(format!("Unknown tool permission: {} (expected allow or deny)", value))),
+        }
+    }
+}
+
+impl std::fmt::Display for ToolPermission {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ToolPermission::Allow => write!(f, "allow"),
+            ToolPermission::Deny => write!(f, "deny"),
+        }
+    }
+}
+
+/// Regular expressions checked against every write to the ports a rule covers
+///
+/// A write matching any `deny` pattern is refused, and so is one matching
+/// none of the `allow` patterns when there are any.
+///
+/// The patterns see every byte written, including the blocks of binary
+/// transfers such as `ymodem_send`, `zmodem_send`, `esp_flash` and
+/// `stm32_write`. An allow-list written for text commands refuses those
+/// blocks, and a short deny pattern can turn up in firmware by chance; keep
+/// ports used for flashing out of such rules, or write patterns with binary
+/// data in mind.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct WriteRuleConfig {
+    /// Port path the rule covers, where `*` matches any run of characters and
+    /// `?` any one, as in "/dev/ttyUSB*"; every port if empty
+    pub port: String,
+    pub allow: Vec<String>,
+    pub deny: Vec<String>,
+}
+
+impl WriteRuleConfig {
+    pub fn rule(&self) -> Result<WriteRule> {
+        WriteRule::new(&self.port, &self.allow, &self.deny)
+            .map_err(|e| SerialError::InvalidConfig(format!("security.write_rules for port {:?}: {}", self.port, e)))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct CaptureConfig {
+    /// Record the traffic of every connection from the moment it opens
+    pub enabled: bool,
+    /// Directory for capture files whose path is not given explicitly
+    pub directory: PathBuf,
+    /// Default capture format: "raw", "hexdump" or "jsonl"
+    pub format: String,
+}
+
+impl Default for CaptureConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            directory: PathBuf::from("captures"),
+            format: "jsonl".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct MaintenanceConfig {
+    /// Minutes before a window starts that the client is warned
+    pub notify_before_minutes: u64,
+    pub windows: Vec<MaintenanceWindowConfig>,
+}
+
+impl Default for MaintenanceConfig {
+    fn default() -> Self {
+        Self {
+            notify_before_minutes: 10,
+            windows: vec![],
+        }
+    }
+}
+
+/// A recurring window during which devices are expected to be unavailable
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MaintenanceWindowConfig {
+    pub name: String,
+    /// Local start time as "HH:MM"
+    pub start: String,
+    pub duration_minutes: u64,
+    /// Weekdays the window starts on, e.g. ["mon", "thu"]; empty means every day
+    #[serde(default)]
+    pub days: Vec<String>,
+    /// Close connections for the window instead of suspending them
+    #[serde(default)]
+    pub release_ports: bool,
+}
+
+/// Longest quiet time a profile may wait for to end a command's response
+const MAX_PROFILE_SETTLE_MS: u64 = 10_000;
+
+/// Settings for a kind of device, applied to connections opened with it
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ProfileConfig {
+    /// Port open_profile opens, e.g. a stable /dev/serial/by-id/... path
+    pub port: Option<String>,
+    /// Baud rate open_profile opens the port at; defaults to serial.default_baud_rate
+    pub baud_rate: Option<u32>,
+    /// 5, 6, 7 or 8; defaults to 8
+    pub data_bits: Option<u8>,
+    /// 1 or 2; defaults to 1
+    pub stop_bits: Option<u8>,
+    /// "none", "odd" or "even"; defaults to none
+    pub parity: Option<String>,
+    /// "none", "software" or "hardware"; defaults to none
+    pub flow_control: Option<String>,
+    /// "drop", "block" or "flow_control"; defaults to serial.backpressure
+    pub backpressure: Option<String>,
+    /// Protocol from `protocols` applied to connections opened with open_profile, e.g. "nmea"
+    pub protocol: Option<String>,
+    /// Commands sent in order right after the port opens, e.g. ["ATE0", "terminal length 0"]
+    pub init_commands: Vec<String>,
+    /// Commands sent in order before the port closes, including on server
+    /// shutdown and orphan cleanup, to leave the device safe, e.g. ["ATH"]
+    pub shutdown_commands: Vec<String>,
+    /// Appended to every command; defaults to serial.default_line_ending
+    pub line_ending: Option<String>,
+    /// Quiet time that ends a command's response
+    pub response_settle_ms: u64,
+}
+
+impl Default for ProfileConfig {
+    fn default() -> Self {
+        Self {
+            port: None,
+            baud_rate: None,
+            data_bits: None,
+            stop_bits: None,
+            parity: None,
+            flow_control: None,
+            backpressure: None,
+            protocol: None,
+            init_commands: vec![],
+            shutdown_commands: vec![],
+            line_ending: None,
+            response_settle_ms: 300,
+        }
+    }
+}
+
+/// How a kind of device frames and encodes its data, applied to connections opened with it
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct ProtocolConfig {
+    /// "line" for line mode, a framer of set_framer such as "slip" or "delimiter", or "none" for plain bytes
+    pub framing: String,
+    /// Delimiter framing: hex bytes ending every frame, e.g. "03"
+    pub delimiter: Option<String>,
+    /// Length framing: size of the length field in bytes, 1, 2 or 4
+    pub length_bytes: Option<usize>,
+    /// Length framing: byte order of the length field, "big" or "little"
+    pub byte_order: Option<String>,
+    /// Length framing: hex bytes every frame starts with
+    pub magic: Option<String>,
+    /// Checksum after every frame's payload, e.g. "crc16_modbus"
+    pub checksum: Option<String>,
+    /// Byte order of the checksum, "big" or "little"
+    pub checksum_byte_order: Option<String>,
+    /// Encoding of reads and writes that name none, e.g. "hex"
+    pub encoding: Option<String>,
+}
+
+impl Default for ProtocolConfig {
+    fn default() -> Self {
+        Self {
+            framing: "none".to_string(),
+            delimiter: None,
+            length_bytes: None,
+            byte_order: None,
+            magic: None,
+            checksum: None,
+            checksum_byte_order: None,
+            encoding: None,
+        }
+    }
+}
+
+/// How a protocol frames received data
+pub enum ProtocolFraming {
+    Plain,
+    Lines,
+    Framer(Box<dyn Framer>, String),
+}
+
+impl ProtocolConfig {
+    /// The framing the protocol describes, or why its options do not fit together
+    pub fn build_framing(&self) -> std::result::Result<ProtocolFraming, String> {
+        let options = FramerOptions {
+            length_bytes: self.length_bytes,
+            byte_order: self.byte_order.as_deref(),
+            magic: self.magic.as_deref(),
+            delimiter: self.delimiter.as_deref(),
+            checksum: self.checksum.as_deref(),
+            checksum_byte_order: self.checksum_byte_order.as_deref(),
+        };
+        match self.framing.to_lowercase().as_str() {
+            "none" | "line" if options != FramerOptions::default() => {
+                Err(format!("framing '{}' takes no framer options", self.framing))
+            }
+            "none" => Ok(ProtocolFraming::Plain),
+            "line" => Ok(ProtocolFraming::Lines),
+            name => {
+                let kind = FramerKind::parse(name).map_err(|e| e.to_string())?;
+                let (framer, description) = build_framer(kind, &options)?;
+                Ok(ProtocolFraming::Framer(framer, description))
+            }
         }
     }
 }
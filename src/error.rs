@@ -236,6 +236,34 @@ impl From<ConfigError> for SerialError {
     }
 }
 
+/// Persisted state errors
+#[derive(Error, Debug)]
+pub enum StateError {
+    #[error("Failed to access state file {path}: {reason}")]
+    Io { path: String, reason: String },
+
+    #[error("Malformed state file {path}: {reason}")]
+    Malformed { path: String, reason: String },
+
+    #[error("State file {path} holds '{actual}' state, expected '{expected}'")]
+    KindMismatch { path: String, expected: String, actual: String },
+
+    #[error("State file {path} was written by a newer server ({kind} format v{version}, this server supports up to v{supported}); it was left untouched")]
+    TooNew { path: String, kind: String, version: u32, supported: u32 },
+
+    #[error("No migration for {kind} format v{from}")]
+    NoMigration { kind: String, from: u32 },
+
+    #[error("State file {path} could not be loaded when the server started, so it is not written this run")]
+    NotLoaded { path: String },
+}
+
+impl From<StateError> for SerialError {
+    fn from(error: StateError) -> Self {
+        SerialError::InternalError(error.to_string())
+    }
+}
+
 /// Data processing errors
 #[derive(Error, Debug)]
 pub enum DataError {
@@ -5,10 +5,13 @@
 //! port discovery, connection management, data transmission, and protocol handling.
 
 pub mod config;
+pub mod durable;
 pub mod error;
+pub mod protocol;
 pub mod utils;
 pub mod serial;
 pub mod session;
+pub mod state;
 pub mod tools;
 
 // Re-export main types for convenience
@@ -2,10 +2,12 @@
 //!
 //! A Model Context Protocol server for serial port communication.
 
+use std::time::Duration;
+
 use clap::Parser;
-use tracing::{info, error, debug};
+use tracing::{info, error, debug, warn};
 use tracing_subscriber::{EnvFilter, fmt};
-use rmcp::{ServiceExt, transport::stdio};
+use rmcp::{ServiceExt, service::QuitReason, transport::stdio};
 
 use serial_mcp_server::{
     Config,
@@ -14,6 +16,9 @@ use serial_mcp_server::{
     Result, SerialError,
 };
 
+/// Longest cleanup may take on the way out, so a stuck device cannot hold the server up
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(15);
+
 #[tokio::main]
 async fn main() -> Result<()> {
     // Parse command line arguments
@@ -69,7 +74,8 @@ async fn main() -> Result<()> {
           config.serial.max_buffer_size);
 
     // Create and serve the handler using rust-sdk standard pattern
-    let service = SerialHandler::new(config.clone())
+    let handler = SerialHandler::new(config.clone());
+    let service = handler.clone()
         .serve(stdio()).await.map_err(|e| {
             error!("Serving error: {:?}", e);
             SerialError::InternalError(format!("Failed to start server: {}", e))
@@ -77,19 +83,74 @@ async fn main() -> Result<()> {
     
     info!("Serial MCP Server started successfully");
     
+    // Stop serving on SIGINT or SIGTERM, cleaning up as when the client disconnects
+    let cancellation = service.cancellation_token();
+    tokio::spawn(async move {
+        let signal = shutdown_signal().await;
+        info!("Received {}, shutting down", signal);
+        cancellation.cancel();
+    });
+    
     // Wait for the service to complete
-    service.waiting().await.map_err(|e| {
+    let quit_reason = service.waiting().await.map_err(|e| {
         error!("Service error: {:?}", e);
         SerialError::InternalError(format!("Service error: {}", e))
     })?;
 
     // Cleanup
     info!("Cleaning up resources...");
+    let cleanup = async {
+        if matches!(quit_reason, QuitReason::Closed) {
+            handler.client_disconnected().await;
+        }
+        handler.shutdown().await;
+    };
+    if tokio::time::timeout(SHUTDOWN_TIMEOUT, cleanup).await.is_err() {
+        warn!("Cleanup did not finish within {}s", SHUTDOWN_TIMEOUT.as_secs());
+    }
 
     info!("Serial MCP Server stopped");
+    if matches!(quit_reason, QuitReason::Cancelled) {
+        // Stdin is still being read on a blocking thread the runtime would wait for
+        std::process::exit(0);
+    }
     Ok(())
 }
 
+/// Wait for a request to stop the server, returning the signal's name
+#[cfg(unix)]
+async fn shutdown_signal() -> &'static str {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate = match signal(SignalKind::terminate()) {
+        Ok(terminate) => terminate,
+        Err(e) => {
+            warn!("SIGTERM not handled: {}", e);
+            interrupt().await;
+            return "SIGINT";
+        }
+    };
+    tokio::select! {
+        _ = interrupt() => "SIGINT",
+        _ = terminate.recv() => "SIGTERM",
+    }
+}
+
+/// Wait for a request to stop the server, returning the signal's name
+#[cfg(not(unix))]
+async fn shutdown_signal() -> &'static str {
+    interrupt().await;
+    "Ctrl-C"
+}
+
+/// Wait for Ctrl-C, or forever if it cannot be listened for
+async fn interrupt() {
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        warn!("Ctrl-C not handled: {}", e);
+        std::future::pending::<()>().await;
+    }
+}
+
 /// Initialize logging system
 fn init_logging(args: &Args) -> Result<()> {
     let env_filter = EnvFilter::try_from_default_env()
@@ -130,7 +191,7 @@ mod tests {
 
     #[test]
     fn test_args_parsing() {
-        let args = Args::parse_from(&[
+        let args = Args::parse_from([
             "serial-mcp-rs",
             "--log-level", "debug",
             "--max-connections", "20",
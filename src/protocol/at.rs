@@ -0,0 +1,195 @@
+//! AT command exchanges
+//!
+//! Sends a command line to a modem and collects its response up to the final
+//! result code (`OK`, `ERROR`, `+CME ERROR: ...` or `+CMS ERROR: ...`), with
+//! the command's echo and blank lines left out. Commands that take a body,
+//! such as `AT+CMGS`, wait for the `>` prompt and end the body with Ctrl-Z.
+
+use std::time::Duration;
+
+use thiserror::Error;
+use tokio::time::Instant;
+
+use crate::serial::{LocalSerialError, SerialConnection};
+
+/// Ends the body of a command that prompted with `>`
+pub const CTRL_Z: u8 = 0x1A;
+
+/// Cancels a command waiting for its body
+const ESC: u8 = 0x1B;
+
+/// Bytes taken from the receive buffer per read
+const AT_READ_CHUNK: usize = 1024;
+
+/// Why an AT exchange failed
+#[derive(Debug, Error)]
+pub enum AtError {
+    #[error("Modem returned {0}")]
+    Rejected(String),
+
+    #[error("No final result code within {timeout_ms}ms, received {received:?}")]
+    Timeout { timeout_ms: u64, received: String },
+
+    #[error("No > prompt within {timeout_ms}ms, received {received:?}")]
+    NoPrompt { timeout_ms: u64, received: String },
+
+    #[error(transparent)]
+    Serial(#[from] LocalSerialError),
+}
+
+/// Information lines a command returned before `OK`
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct AtResponse {
+    pub lines: Vec<String>,
+}
+
+impl AtResponse {
+    /// Values of the lines starting with `prefix`, e.g. `+CMGS:`, prefix removed
+    pub fn values<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.lines.iter().filter_map(move |line| line.strip_prefix(prefix)).map(str::trim)
+    }
+}
+
+/// `Some(Ok)` for `OK`, `Some(Err)` with the code for failures, `None` otherwise
+fn final_result(line: &str) -> Option<Result<(), String>> {
+    match line {
+        "OK" => Some(Ok(())),
+        "ERROR" | "NO CARRIER" | "BUSY" | "NO ANSWER" | "NO DIALTONE" => Some(Err(line.to_string())),
+        _ if line.starts_with("+CME ERROR:") || line.starts_with("+CMS ERROR:") => Some(Err(line.to_string())),
+        _ => None,
+    }
+}
+
+/// Parse received text into a response once it holds a final result code
+///
+/// Lines equal to one of `echoes` or starting with the `>` prompt are the
+/// modem repeating what was sent and are left out. The line after a stored
+/// message header is message text, so a message reading "OK" does not end it.
+pub fn parse_response(text: &str, echoes: &[&str]) -> Option<Result<AtResponse, AtError>> {
+    // Only whole lines; the last one may still be arriving
+    let end = text.rfind(['\r', '\n'])?;
+    let mut response = AtResponse::default();
+    let mut message_text_next = false;
+
+    for line in text[..end].split(['\r', '\n']).map(str::trim).filter(|line| !line.is_empty()) {
+        if message_text_next {
+            message_text_next = false;
+            response.lines.push(line.to_string());
+            continue;
+        }
+        if line.starts_with('>') || echoes.iter().any(|echo| echo.trim().eq_ignore_ascii_case(line)) {
+            continue;
+        }
+        match final_result(line) {
+            Some(Ok(())) => return Some(Ok(response)),
+            Some(Err(code)) => return Some(Err(AtError::Rejected(code))),
+            None => {
+                message_text_next = line.starts_with("+CMGL:") || line.starts_with("+CMGR:");
+                response.lines.push(line.to_string());
+            }
+        }
+    }
+    None
+}
+
+/// Send `command` and wait up to `timeout_ms` for its final result code
+pub async fn command(connection: &SerialConnection, command: &str, timeout_ms: u64) -> Result<AtResponse, AtError> {
+    let _exchange = connection.begin_exchange().await;
+    discard_stale(connection).await;
+    connection.write(format!("{}\r", command).as_bytes()).await?;
+
+    let deadline = Instant::now() + Duration::from_millis(timeout_ms);
+    let mut received = String::new();
+    collect(connection, &[command], &mut received, deadline, timeout_ms).await
+}
+
+/// Send `command`, wait for the `>` prompt, then send `body` ended by Ctrl-Z
+///
+/// Waits up to `timeout_ms` in all. Without a prompt the command is
+/// cancelled with ESC so the modem is not left waiting for a body.
+pub async fn submit(connection: &SerialConnection, command: &str, body: &str, timeout_ms: u64) -> Result<AtResponse, AtError> {
+    let _exchange = connection.begin_exchange().await;
+    discard_stale(connection).await;
+    connection.write(format!("{}\r", command).as_bytes()).await?;
+
+    let deadline = Instant::now() + Duration::from_millis(timeout_ms);
+    let mut received = String::new();
+    let mut failed = None;
+    let prompted = connection
+        .read_until(deadline, |chunk| {
+            received.push_str(&String::from_utf8_lossy(chunk));
+            if let Some(Err(e)) = parse_response(&received, &[command]) {
+                failed = Some(e);
+            }
+            received.trim_end().ends_with('>') || failed.is_some()
+        })
+        .await?;
+    if let Some(e) = failed {
+        return Err(e);
+    }
+    if !prompted {
+        connection.write(&[ESC]).await?;
+        return Err(AtError::NoPrompt { timeout_ms, received });
+    }
+
+    let mut sent = body.as_bytes().to_vec();
+    sent.push(CTRL_Z);
+    connection.write(&sent).await?;
+
+    // Multi-line bodies are echoed a line at a time, each after another prompt
+    let mut echoes: Vec<&str> = body.lines().collect();
+    echoes.push(command);
+    received.clear();
+    collect(connection, &echoes, &mut received, deadline, timeout_ms).await
+}
+
+/// Read until `received` holds a final result code or `deadline` passes
+async fn collect(
+    connection: &SerialConnection,
+    echoes: &[&str],
+    received: &mut String,
+    deadline: Instant,
+    timeout_ms: u64,
+) -> Result<AtResponse, AtError> {
+    let mut result = parse_response(received, echoes);
+    if result.is_none() {
+        connection
+            .read_until(deadline, |chunk| {
+                received.push_str(&String::from_utf8_lossy(chunk));
+                result = parse_response(received, echoes);
+                result.is_some()
+            })
+            .await?;
+    }
+    result.unwrap_or_else(|| Err(AtError::Timeout { timeout_ms, received: std::mem::take(received) }))
+}
+
+/// Drop unsolicited output left over from before the command
+async fn discard_stale(connection: &SerialConnection) {
+    let mut stale = [0u8; AT_READ_CHUNK];
+    while let Ok(n @ 1..) = connection.read(&mut stale, Some(0)).await {
+        connection.record_event(format!("AT: discarded {} stale bytes before command", n)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_response() {
+        assert!(parse_response("AT+CMGF=1\r\r\nO", &["AT+CMGF=1"]).is_none());
+        assert_eq!(parse_response("AT+CMGF=1\r\r\nOK\r\n", &["AT+CMGF=1"]).unwrap().unwrap(), AtResponse::default());
+
+        let listing = "\r\n+CMGL: 1,\"REC READ\",\"+15551234\",,\"24/05/01,10:00:00+08\"\r\nOK\r\n\r\nOK\r\n";
+        let response = parse_response(listing, &[]).unwrap().unwrap();
+        assert_eq!(response.lines.len(), 2);
+        assert_eq!(response.lines[1], "OK");
+
+        let sent = parse_response("> hello\r\n\r\n+CMGS: 17\r\n\r\nOK\r\n", &["hello"]).unwrap().unwrap();
+        assert_eq!(sent.values("+CMGS:").collect::<Vec<_>>(), vec!["17"]);
+
+        let rejected = parse_response("\r\n+CMS ERROR: 500\r\n", &[]).unwrap();
+        assert!(matches!(rejected, Err(AtError::Rejected(code)) if code == "+CMS ERROR: 500"));
+    }
+}
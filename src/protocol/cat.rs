@@ -0,0 +1,393 @@
+//! Transceiver CAT control
+//!
+//! Reads and sets the frequency, mode and PTT of amateur radio transceivers.
+//! Icom radios speak binary CI-V: `FE FE <to> <from> <command> <data> FD`
+//! frames with frequencies in BCD, answered with `FB` (OK) or `FA` (NG), and
+//! on the single-wire bus every frame sent is echoed back. Kenwood and newer
+//! Yaesu radios take ASCII commands ending in `;`, such as `FA;` to read the
+//! frequency, answer reads in the same form, stay silent after a set, and
+//! answer `?;` to a command they cannot run. Older Yaesu radios with 5-byte
+//! binary commands are not covered.
+
+use std::time::Duration;
+
+use thiserror::Error;
+use tokio::time::Instant;
+
+use crate::serial::{LocalSerialError, SerialConnection};
+
+/// CI-V address the controller uses
+const CIV_CONTROLLER: u8 = 0xE0;
+const CIV_PREAMBLE: u8 = 0xFE;
+const CIV_END: u8 = 0xFD;
+const CIV_OK: u8 = 0xFB;
+const CIV_NG: u8 = 0xFA;
+
+/// Default CI-V address, the IC-7300's
+pub const DEFAULT_CIV_ADDRESS: u8 = 0x94;
+
+/// Bytes taken from the receive buffer per read while waiting for replies
+const CAT_READ_CHUNK: usize = 256;
+
+/// How long a set command is given to be rejected; radios answer `?;` at once
+const TEXT_REJECTION_WAIT: Duration = Duration::from_millis(100);
+
+/// Mode codes and names, CI-V command 0x04/0x06
+const ICOM_MODES: &[(&str, &str)] = &[
+    ("00", "LSB"),
+    ("01", "USB"),
+    ("02", "AM"),
+    ("03", "CW"),
+    ("04", "RTTY"),
+    ("05", "FM"),
+    ("06", "WFM"),
+    ("07", "CW-R"),
+    ("08", "RTTY-R"),
+    ("17", "DV"),
+];
+
+/// Mode codes and names, `MD`
+const KENWOOD_MODES: &[(&str, &str)] = &[
+    ("1", "LSB"),
+    ("2", "USB"),
+    ("3", "CW"),
+    ("4", "FM"),
+    ("5", "AM"),
+    ("6", "RTTY"),
+    ("7", "CW-R"),
+    ("9", "RTTY-R"),
+];
+
+/// Mode codes and names, `MD0`
+const YAESU_MODES: &[(&str, &str)] = &[
+    ("1", "LSB"),
+    ("2", "USB"),
+    ("3", "CW"),
+    ("4", "FM"),
+    ("5", "AM"),
+    ("6", "RTTY"),
+    ("7", "CW-R"),
+    ("8", "DATA-LSB"),
+    ("9", "RTTY-R"),
+    ("A", "DATA-FM"),
+    ("B", "FM-N"),
+    ("C", "DATA-USB"),
+    ("D", "AM-N"),
+    ("E", "C4FM"),
+];
+
+#[derive(Debug, Error)]
+pub enum CatError {
+    #[error("Radio rejected {0}")]
+    Rejected(String),
+
+    #[error("No answer to {command} within {timeout_ms}ms")]
+    Timeout { command: String, timeout_ms: u64 },
+
+    #[error("Unexpected answer: {0}")]
+    Malformed(String),
+
+    #[error(transparent)]
+    Serial(#[from] LocalSerialError),
+}
+
+/// The command set a radio speaks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CatProtocol {
+    /// CI-V, with the radio's bus address
+    Icom { address: u8 },
+    Kenwood,
+    Yaesu,
+}
+
+impl CatProtocol {
+    pub fn parse(value: &str, civ_address: Option<u8>) -> Result<Self, LocalSerialError> {
+        match value.to_lowercase().as_str() {
+            "icom" | "civ" | "ci-v" => Ok(CatProtocol::Icom { address: civ_address.unwrap_or(DEFAULT_CIV_ADDRESS) }),
+            "kenwood" => Ok(CatProtocol::Kenwood),
+            "yaesu" => Ok(CatProtocol::Yaesu),
+            _ => Err(LocalSerialError::InvalidConfig(format!("Unknown CAT protocol: {}", value))),
+        }
+    }
+
+    fn modes(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            CatProtocol::Icom { .. } => ICOM_MODES,
+            CatProtocol::Kenwood => KENWOOD_MODES,
+            CatProtocol::Yaesu => YAESU_MODES,
+        }
+    }
+
+    /// Mode names the radio can be set to
+    pub fn mode_names(self) -> Vec<&'static str> {
+        self.modes().iter().map(|(_, name)| *name).collect()
+    }
+}
+
+impl std::fmt::Display for CatProtocol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CatProtocol::Icom { address } => write!(f, "icom (CI-V address 0x{:02X})", address),
+            CatProtocol::Kenwood => write!(f, "kenwood"),
+            CatProtocol::Yaesu => write!(f, "yaesu"),
+        }
+    }
+}
+
+/// The VFO frequency in Hz
+pub async fn frequency(connection: &SerialConnection, protocol: CatProtocol, timeout_ms: u64) -> Result<u64, CatError> {
+    let reply = match protocol {
+        CatProtocol::Icom { address } => {
+            let data = civ_exchange(connection, address, &[0x03], timeout_ms).await?;
+            return from_bcd(&data).ok_or_else(|| CatError::Malformed(format!("frequency {:02X?}", data)));
+        }
+        CatProtocol::Kenwood | CatProtocol::Yaesu => text_exchange(connection, "FA;", "FA", timeout_ms).await?,
+    };
+    reply.parse().map_err(|_| CatError::Malformed(format!("FA{};", reply)))
+}
+
+pub async fn set_frequency(connection: &SerialConnection, protocol: CatProtocol, hz: u64, timeout_ms: u64) -> Result<(), CatError> {
+    match protocol {
+        CatProtocol::Icom { address } => {
+            let mut payload = vec![0x05];
+            payload.extend(to_bcd(hz));
+            civ_exchange(connection, address, &payload, timeout_ms).await.map(drop)
+        }
+        CatProtocol::Kenwood => text_set(connection, &format!("FA{:011};", hz), timeout_ms).await,
+        CatProtocol::Yaesu => text_set(connection, &format!("FA{:09};", hz), timeout_ms).await,
+    }
+}
+
+/// The operating mode, by name, e.g. `USB`
+pub async fn mode(connection: &SerialConnection, protocol: CatProtocol, timeout_ms: u64) -> Result<String, CatError> {
+    let code = match protocol {
+        CatProtocol::Icom { address } => {
+            let data = civ_exchange(connection, address, &[0x04], timeout_ms).await?;
+            let mode = data.first().ok_or_else(|| CatError::Malformed("mode reply without a mode".to_string()))?;
+            format!("{:02X}", mode)
+        }
+        CatProtocol::Kenwood => text_exchange(connection, "MD;", "MD", timeout_ms).await?,
+        CatProtocol::Yaesu => text_exchange(connection, "MD0;", "MD0", timeout_ms).await?,
+    };
+    Ok(protocol
+        .modes()
+        .iter()
+        .find(|(known, _)| *known == code)
+        .map_or_else(|| format!("unknown ({})", code), |(_, name)| name.to_string()))
+}
+
+/// Set the operating mode by name, one of [`CatProtocol::mode_names`]
+pub async fn set_mode(connection: &SerialConnection, protocol: CatProtocol, mode: &str, timeout_ms: u64) -> Result<(), CatError> {
+    let Some((code, _)) = protocol.modes().iter().find(|(_, name)| name.eq_ignore_ascii_case(mode)) else {
+        return Err(CatError::Rejected(format!("mode {}, which it does not have", mode)));
+    };
+    match protocol {
+        CatProtocol::Icom { address } => {
+            let code = u8::from_str_radix(code, 16).expect("mode codes are hex");
+            civ_exchange(connection, address, &[0x06, code], timeout_ms).await.map(drop)
+        }
+        CatProtocol::Kenwood => text_set(connection, &format!("MD{};", code), timeout_ms).await,
+        CatProtocol::Yaesu => text_set(connection, &format!("MD0{};", code), timeout_ms).await,
+    }
+}
+
+/// Whether the radio is transmitting
+pub async fn ptt(connection: &SerialConnection, protocol: CatProtocol, timeout_ms: u64) -> Result<bool, CatError> {
+    let state = match protocol {
+        CatProtocol::Icom { address } => {
+            let data = civ_exchange(connection, address, &[0x1C, 0x00], timeout_ms).await?;
+            return match data.first() {
+                Some(state) => Ok(*state != 0),
+                None => Err(CatError::Malformed("PTT reply without a state".to_string())),
+            };
+        }
+        // The transmit flag is the 28th character of the IF status
+        CatProtocol::Kenwood => text_exchange(connection, "IF;", "IF", timeout_ms).await?.chars().nth(27),
+        CatProtocol::Yaesu => text_exchange(connection, "TX;", "TX", timeout_ms).await?.chars().next(),
+    };
+    match state {
+        Some('0') => Ok(false),
+        Some('1' | '2') => Ok(true),
+        _ => Err(CatError::Malformed("PTT state".to_string())),
+    }
+}
+
+pub async fn set_ptt(connection: &SerialConnection, protocol: CatProtocol, transmit: bool, timeout_ms: u64) -> Result<(), CatError> {
+    match protocol {
+        CatProtocol::Icom { address } => civ_exchange(connection, address, &[0x1C, 0x00, u8::from(transmit)], timeout_ms).await.map(drop),
+        CatProtocol::Kenwood => text_set(connection, if transmit { "TX;" } else { "RX;" }, timeout_ms).await,
+        CatProtocol::Yaesu => text_set(connection, if transmit { "TX1;" } else { "TX0;" }, timeout_ms).await,
+    }
+}
+
+/// Frequency in Hz as 5 BCD bytes, least significant first
+fn to_bcd(hz: u64) -> [u8; 5] {
+    let mut bcd = [0u8; 5];
+    let mut rest = hz;
+    for byte in &mut bcd {
+        *byte = (rest % 10) as u8 | ((rest / 10 % 10) as u8) << 4;
+        rest /= 100;
+    }
+    bcd
+}
+
+/// BCD bytes, least significant first; `None` when a nibble is not a digit
+fn from_bcd(bcd: &[u8]) -> Option<u64> {
+    if bcd.is_empty() {
+        return None;
+    }
+    bcd.iter().rev().try_fold(0u64, |value, byte| {
+        let (high, low) = (u64::from(byte >> 4), u64::from(byte & 0x0F));
+        (high < 10 && low < 10).then_some(value * 100 + high * 10 + low)
+    })
+}
+
+/// A CI-V frame between the preamble and the end byte
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CivFrame {
+    to: u8,
+    from: u8,
+    payload: Vec<u8>,
+}
+
+/// Complete frames in `received`, and how many bytes they used
+fn split_civ_frames(received: &[u8]) -> (Vec<CivFrame>, usize) {
+    let mut frames = Vec::new();
+    let mut used = 0;
+    while let Some(start) = received[used..].windows(2).position(|pair| pair == [CIV_PREAMBLE, CIV_PREAMBLE]) {
+        let start = used + start;
+        let Some(end) = received[start..].iter().position(|b| *b == CIV_END).map(|offset| start + offset) else {
+            return (frames, start);
+        };
+        // Skip any further preamble bytes before the addresses
+        let body: Vec<u8> = received[start..end].iter().copied().skip_while(|b| *b == CIV_PREAMBLE).collect();
+        if let [to, from, payload @ ..] = body.as_slice() {
+            frames.push(CivFrame { to: *to, from: *from, payload: payload.to_vec() });
+        }
+        used = end + 1;
+    }
+    (frames, used)
+}
+
+/// Send `payload` to the radio at `address` and return the data of its reply
+///
+/// The echo of the frame sent and frames addressed to others, such as
+/// transceive broadcasts, are skipped. `FB` replies give no data.
+async fn civ_exchange(connection: &SerialConnection, address: u8, payload: &[u8], timeout_ms: u64) -> Result<Vec<u8>, CatError> {
+    let _exchange = connection.begin_exchange().await;
+    discard_stale(connection).await;
+    let mut frame = vec![CIV_PREAMBLE, CIV_PREAMBLE, address, CIV_CONTROLLER];
+    frame.extend(payload);
+    frame.push(CIV_END);
+    connection.write(&frame).await?;
+
+    let command = format!("CI-V command {:02X?}", payload);
+    let deadline = Instant::now() + Duration::from_millis(timeout_ms);
+    let mut received = Vec::new();
+    let mut answer = None;
+    connection
+        .read_until(deadline, |chunk| {
+            received.extend_from_slice(chunk);
+            let (frames, used) = split_civ_frames(&received);
+            received.drain(..used);
+            answer = frames
+                .into_iter()
+                .filter(|reply| reply.to == CIV_CONTROLLER && reply.from == address)
+                .find_map(|reply| match reply.payload.as_slice() {
+                    [CIV_OK] => Some(Ok(Vec::new())),
+                    [CIV_NG] => Some(Err(CatError::Rejected(command.clone()))),
+                    // Reads are answered with the command, any sub-command, then the data
+                    data if data.starts_with(payload) => Some(Ok(data[payload.len()..].to_vec())),
+                    _ => None,
+                });
+            answer.is_some()
+        })
+        .await?;
+    answer.unwrap_or(Err(CatError::Timeout { command, timeout_ms }))
+}
+
+/// Send a read command such as `FA;` and return what follows `prefix` in the answer
+async fn text_exchange(connection: &SerialConnection, command: &str, prefix: &str, timeout_ms: u64) -> Result<String, CatError> {
+    let _exchange = connection.begin_exchange().await;
+    discard_stale(connection).await;
+    connection.write(command.as_bytes()).await?;
+
+    let deadline = Instant::now() + Duration::from_millis(timeout_ms);
+    let mut received = Vec::new();
+    let mut answer = None;
+    connection
+        .read_until(deadline, |chunk| {
+            received.extend_from_slice(chunk);
+            while let Some(end) = received.iter().position(|b| *b == b';') {
+                let text: Vec<u8> = received.drain(..=end).collect();
+                let text = String::from_utf8_lossy(&text[..end]).trim().to_string();
+                if text == "?" {
+                    answer = Some(Err(CatError::Rejected(command.to_string())));
+                    return true;
+                }
+                // Auto-information reports for other settings may arrive first
+                if let Some(value) = text.strip_prefix(prefix) {
+                    answer = Some(Ok(value.to_string()));
+                    return true;
+                }
+            }
+            false
+        })
+        .await?;
+    answer.unwrap_or_else(|| Err(CatError::Timeout { command: command.to_string(), timeout_ms }))
+}
+
+/// Send a set command, which is answered only if it fails
+///
+/// Waits briefly for a `?;`; callers read the setting back to confirm it took.
+async fn text_set(connection: &SerialConnection, command: &str, timeout_ms: u64) -> Result<(), CatError> {
+    let _exchange = connection.begin_exchange().await;
+    discard_stale(connection).await;
+    connection.write(command.as_bytes()).await?;
+
+    let deadline = Instant::now() + Duration::from_millis(timeout_ms).min(TEXT_REJECTION_WAIT);
+    let mut received = Vec::new();
+    let rejected = connection
+        .read_until(deadline, |chunk| {
+            received.extend_from_slice(chunk);
+            received.windows(2).any(|pair| pair == b"?;")
+        })
+        .await?;
+    if rejected {
+        return Err(CatError::Rejected(command.to_string()));
+    }
+    Ok(())
+}
+
+/// Drop replies to earlier commands that came after their timeout
+async fn discard_stale(connection: &SerialConnection) {
+    let mut stale = [0u8; CAT_READ_CHUNK];
+    while let Ok(1..) = connection.read(&mut stale, Some(0)).await {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bcd_frequencies() {
+        assert_eq!(to_bcd(14_074_000), [0x00, 0x40, 0x07, 0x14, 0x00]);
+        assert_eq!(from_bcd(&[0x00, 0x40, 0x07, 0x14, 0x00]), Some(14_074_000));
+        assert_eq!(from_bcd(&to_bcd(1_296_123_450)), Some(1_296_123_450));
+        assert_eq!(from_bcd(&[0x0A]), None);
+    }
+
+    #[test]
+    fn test_split_civ_frames() {
+        let received = [
+            0xFE, 0xFE, 0x94, 0xE0, 0x03, 0xFD, // echo of the request
+            0xFE, 0xFE, 0xE0, 0x94, 0x03, 0x00, 0x40, 0x07, 0x14, 0x00, 0xFD,
+            0xFE, 0xFE, 0xE0,
+        ];
+        let (frames, used) = split_civ_frames(&received);
+        assert_eq!(used, 17);
+        assert_eq!(frames.len(), 2);
+        assert_eq!((frames[0].to, frames[0].from), (0x94, 0xE0));
+        assert_eq!(frames[1].payload, [0x03, 0x00, 0x40, 0x07, 0x14, 0x00]);
+    }
+}
@@ -0,0 +1,88 @@
+//! Checksummed frames
+//!
+//! Wraps another framer so every payload carries a checksum after it: added
+//! before the frame is encoded, checked and removed once one is decoded.
+//! Frames failing the check are reported as malformed like any other.
+
+use super::framer::{FrameError, Framer};
+use super::length::ByteOrder;
+use crate::utils::Checksum;
+
+#[derive(Debug)]
+pub struct ChecksumFramer {
+    inner: Box<dyn Framer>,
+    checksum: Checksum,
+    byte_order: ByteOrder,
+}
+
+impl ChecksumFramer {
+    pub fn new(inner: Box<dyn Framer>, checksum: Checksum, byte_order: ByteOrder) -> Self {
+        Self { inner, checksum, byte_order }
+    }
+
+    fn checksum_bytes(&self) -> usize {
+        self.checksum.width().div_ceil(8) as usize
+    }
+
+    fn check(&self, frame: Vec<u8>) -> Result<Vec<u8>, FrameError> {
+        let Some(split) = frame.len().checked_sub(self.checksum_bytes()) else {
+            return Err(FrameError::Malformed(format!("{} bytes is shorter than the checksum", frame.len())));
+        };
+        let (payload, received) = frame.split_at(split);
+        let received = self.byte_order.read_uint(received);
+        let computed = self.checksum.compute(payload);
+        if received != computed {
+            return Err(FrameError::Malformed(format!("checksum 0x{:X} does not match computed 0x{:X}", received, computed)));
+        }
+        Ok(payload.to_vec())
+    }
+}
+
+impl Framer for ChecksumFramer {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn encode(&self, payload: &[u8]) -> Vec<u8> {
+        let mut checked = payload.to_vec();
+        checked.extend(self.byte_order.write_uint(self.checksum.compute(payload), self.checksum_bytes()));
+        self.inner.encode(&checked)
+    }
+
+    fn decode(&mut self, data: &[u8]) -> Vec<Result<Vec<u8>, FrameError>> {
+        self.inner.decode(data).into_iter().map(|frame| frame.and_then(|frame| self.check(frame))).collect()
+    }
+
+    fn pending(&self) -> usize {
+        self.inner.pending()
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset();
+    }
+
+    fn max_payload(&self) -> Option<usize> {
+        self.inner.max_payload().map(|max| max.saturating_sub(self.checksum_bytes()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::SlipFramer;
+    use crate::utils::CrcParams;
+
+    #[test]
+    fn test_checksum_added_and_checked() {
+        let mut framer = ChecksumFramer::new(Box::new(SlipFramer::new()), Checksum::Crc(CrcParams::CRC16_MODBUS), ByteOrder::Little);
+        let encoded = framer.encode(b"\x01\x03");
+        let crc = crate::utils::BufferUtils::crc16_modbus(b"\x01\x03").to_le_bytes();
+        assert_eq!(encoded, SlipFramer::new().encode(&[0x01, 0x03, crc[0], crc[1]]));
+        assert_eq!(framer.decode(&encoded), vec![Ok(b"\x01\x03".to_vec())]);
+
+        let mut corrupt = encoded.clone();
+        corrupt[1] = 0x02;
+        assert!(matches!(framer.decode(&corrupt)[0], Err(FrameError::Malformed(_))));
+        assert!(matches!(framer.decode(&[0xC0, 0x01, 0xC0])[0], Err(FrameError::Malformed(_))));
+    }
+}
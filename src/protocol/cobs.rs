@@ -0,0 +1,177 @@
+//! COBS framing (Consistent Overhead Byte Stuffing)
+//!
+//! Payload zero bytes are removed by splitting the payload into blocks, each
+//! led by a code byte giving the distance to the next zero. The encoded frame
+//! then contains no zeros, so a single 0x00 delimits frames.
+
+use super::framer::{FrameError, Framer};
+
+/// Frame delimiter
+pub const COBS_DELIMITER: u8 = 0x00;
+
+/// Longest encoded frame accepted before the partial frame is discarded
+pub const COBS_MAX_FRAME: usize = 64 * 1024;
+
+/// Code byte of a block of 254 data bytes not followed by a zero
+const COBS_FULL_BLOCK: u8 = 0xFF;
+
+#[derive(Debug, Default)]
+pub struct CobsFramer {
+    encoded: Vec<u8>,
+    /// The current frame grew too long and is skipped up to the next delimiter
+    overflowed: bool,
+}
+
+impl CobsFramer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// Encode `payload` without the trailing delimiter
+pub fn cobs_encode(payload: &[u8]) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(payload.len() + payload.len() / 254 + 1);
+    let mut code_index = 0;
+    let mut code = 1u8;
+    encoded.push(0);
+
+    for (index, &byte) in payload.iter().enumerate() {
+        if byte != 0 {
+            encoded.push(byte);
+            code += 1;
+        }
+        if byte == 0 || code == COBS_FULL_BLOCK {
+            encoded[code_index] = code;
+            code = 1;
+            // A full block at the very end needs no empty block after it
+            if byte == 0 || index + 1 < payload.len() {
+                code_index = encoded.len();
+                encoded.push(0);
+            } else {
+                return encoded;
+            }
+        }
+    }
+
+    encoded[code_index] = code;
+    encoded
+}
+
+/// Decode one frame, without its delimiter
+pub fn cobs_decode(encoded: &[u8]) -> Result<Vec<u8>, FrameError> {
+    let mut payload = Vec::with_capacity(encoded.len());
+    let mut index = 0;
+
+    while index < encoded.len() {
+        let code = encoded[index];
+        if code == 0 {
+            return Err(FrameError::Malformed("zero code byte".to_string()));
+        }
+        let end = index + code as usize;
+        if end > encoded.len() {
+            return Err(FrameError::Malformed(format!(
+                "block at offset {} runs past the end of the frame",
+                index
+            )));
+        }
+        payload.extend_from_slice(&encoded[index + 1..end]);
+        index = end;
+        if code != COBS_FULL_BLOCK && index < encoded.len() {
+            payload.push(0);
+        }
+    }
+
+    Ok(payload)
+}
+
+impl Framer for CobsFramer {
+    fn name(&self) -> &'static str {
+        "cobs"
+    }
+
+    fn encode(&self, payload: &[u8]) -> Vec<u8> {
+        let mut encoded = cobs_encode(payload);
+        encoded.push(COBS_DELIMITER);
+        encoded
+    }
+
+    fn decode(&mut self, data: &[u8]) -> Vec<Result<Vec<u8>, FrameError>> {
+        let mut frames = Vec::new();
+
+        for &byte in data {
+            if byte == COBS_DELIMITER {
+                if std::mem::take(&mut self.overflowed) {
+                    frames.push(Err(FrameError::TooLong(COBS_MAX_FRAME)));
+                } else if !self.encoded.is_empty() {
+                    // Back-to-back delimiters delimit empty frames, which are ignored
+                    frames.push(cobs_decode(&self.encoded));
+                }
+                self.encoded.clear();
+            } else if !self.overflowed {
+                if self.encoded.len() == COBS_MAX_FRAME {
+                    self.overflowed = true;
+                    self.encoded.clear();
+                } else {
+                    self.encoded.push(byte);
+                }
+            }
+        }
+
+        frames
+    }
+
+    fn pending(&self) -> usize {
+        self.encoded.len()
+    }
+
+    fn reset(&mut self) {
+        self.encoded.clear();
+        self.overflowed = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cobs_reference_vectors() {
+        let cases: [(&[u8], &[u8]); 5] = [
+            (&[], &[0x01]),
+            (&[0x00], &[0x01, 0x01]),
+            (&[0x11, 0x22, 0x00, 0x33], &[0x03, 0x11, 0x22, 0x02, 0x33]),
+            (&[0x11, 0x00, 0x00, 0x00], &[0x02, 0x11, 0x01, 0x01, 0x01]),
+            (&[0x00, 0x00], &[0x01, 0x01, 0x01]),
+        ];
+        for (payload, encoded) in cases {
+            assert_eq!(cobs_encode(payload), encoded);
+            assert_eq!(cobs_decode(encoded).unwrap(), payload);
+        }
+
+        // 254 non-zero bytes fill one block exactly; one more starts another
+        let full: Vec<u8> = (1..=254).collect();
+        let encoded = cobs_encode(&full);
+        assert_eq!(encoded.len(), 255);
+        assert_eq!(encoded[0], 0xFF);
+        assert_eq!(cobs_decode(&encoded).unwrap(), full);
+
+        let longer: Vec<u8> = (0..300).map(|i| (i % 255 + 1) as u8).collect();
+        assert_eq!(cobs_decode(&cobs_encode(&longer)).unwrap(), longer);
+    }
+
+    #[test]
+    fn test_cobs_framer_stream() {
+        let mut framer = CobsFramer::new();
+        let mut stream = framer.encode(&[0x01, 0x00, 0x02]);
+        stream.extend(framer.encode(b"abc"));
+        stream.extend([0x05, 0x01, COBS_DELIMITER]);
+
+        let (first, second) = stream.split_at(2);
+        assert!(framer.decode(first).is_empty());
+        let frames = framer.decode(second);
+        assert_eq!(frames[0], Ok(vec![0x01, 0x00, 0x02]));
+        assert_eq!(frames[1], Ok(b"abc".to_vec()));
+        assert!(matches!(frames[2], Err(FrameError::Malformed(_))));
+        assert_eq!(framer.pending(), 0);
+    }
+}
@@ -0,0 +1,612 @@
+//! defmt log frames
+//!
+//! Firmware logging with defmt sends compact binary frames rather than text:
+//! a 16-bit index into the table of format strings kept in the firmware ELF's
+//! `.defmt` section, the timestamp, then the arguments. [`DefmtTable`] loads
+//! that table and [`DefmtDecoder`] turns the received bytes back into log
+//! lines. Frames are rzCOBS encoded and end in a zero byte, unless the
+//! firmware was built with defmt's `encoding-raw` feature.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use defmt_parser::{DisplayHint, Fragment, ParserMode, TimePrecision, Type};
+use object::{Object, ObjectSection, ObjectSymbol};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Wire format version understood, as named by the ELF's `_defmt_version_` symbol
+const WIRE_VERSION: &str = "4";
+
+/// Most bytes buffered waiting for the end of a frame before giving up on it
+const MAX_FRAME_BYTES: usize = 16 * 1024;
+
+#[derive(Debug, Error)]
+pub enum DefmtError {
+    #[error("Not a readable ELF file: {0}")]
+    Elf(String),
+
+    #[error("No defmt table in the ELF; is the firmware built with defmt?")]
+    NoTable,
+
+    #[error("The ELF uses defmt wire format {0}, only {WIRE_VERSION} is supported")]
+    Version(String),
+}
+
+/// How frames are put on the wire
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    /// Frames back to back; a lost byte loses sync for good
+    Raw,
+    /// rzCOBS with a zero byte after each frame
+    Rzcobs,
+}
+
+/// One entry of the table: what kind of string it is and the string itself
+#[derive(Debug, Clone)]
+struct Entry {
+    tag: String,
+    format: String,
+}
+
+/// defmt names its table symbols with JSON
+#[derive(Deserialize)]
+struct SymbolName {
+    tag: String,
+    data: String,
+}
+
+/// The format strings from a firmware ELF
+#[derive(Debug)]
+pub struct DefmtTable {
+    entries: HashMap<u16, Entry>,
+    /// Format of the timestamp sent with each message, if the firmware sets one
+    timestamp: Option<String>,
+    pub encoding: Encoding,
+}
+
+impl DefmtTable {
+    /// Load the table from the contents of an ELF file
+    pub fn parse(elf: &[u8]) -> Result<Self, DefmtError> {
+        let file = object::File::parse(elf).map_err(|e| DefmtError::Elf(e.to_string()))?;
+        let section = file.section_by_name(".defmt").ok_or(DefmtError::NoTable)?.index();
+
+        let mut version = None;
+        let mut table = Self { entries: HashMap::new(), timestamp: None, encoding: Encoding::Rzcobs };
+        for symbol in file.symbols() {
+            let Ok(name) = symbol.name() else { continue };
+            if let Some(value) = name.strip_prefix("_defmt_version_ = ") {
+                version = Some(value.to_string());
+            } else if let Some(value) = name.strip_prefix("_defmt_encoding_ = ") {
+                table.encoding = if value == "raw" { Encoding::Raw } else { Encoding::Rzcobs };
+            } else if let Ok(SymbolName { tag, data }) = serde_json::from_str(name) {
+                if tag == "defmt_timestamp" {
+                    table.timestamp = Some(data);
+                } else if symbol.section_index() == Some(section) {
+                    // The symbol's address in the section is its index
+                    table.entries.insert(symbol.address() as u16, Entry { tag, format: data });
+                }
+            }
+        }
+
+        match version {
+            Some(version) if version == WIRE_VERSION => Ok(table),
+            Some(version) => Err(DefmtError::Version(version)),
+            None => Err(DefmtError::NoTable),
+        }
+    }
+
+    /// Number of format strings and interned strings
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn entry(&self, index: u16) -> Result<&Entry, FrameError> {
+        self.entries
+            .get(&index)
+            .ok_or_else(|| FrameError::Malformed(format!("index {} is not in the table; does the ELF match the firmware?", index)))
+    }
+
+    /// Decode one frame from the start of `bytes`, returning the line and the bytes used
+    fn decode_frame(&self, bytes: &[u8]) -> Result<(DefmtLine, usize), FrameError> {
+        let mut reader = Reader { bytes, position: 0 };
+        let entry = self.entry(reader.u16()?)?;
+        let level = match entry.tag.as_str() {
+            "defmt_println" => None,
+            tag => match tag.strip_prefix("defmt_") {
+                Some(level @ ("trace" | "debug" | "info" | "warn" | "error")) => Some(level.to_uppercase()),
+                _ => return Err(FrameError::Malformed(format!("index points at a {} string, not a log message", entry.tag))),
+            },
+        };
+        let timestamp = match &self.timestamp {
+            Some(format) => Some(self.format(format, false, &mut reader)?),
+            None => None,
+        };
+        let message = self.format(&entry.format, false, &mut reader)?;
+        Ok((DefmtLine { timestamp, level, message }, reader.position))
+    }
+
+    /// Read the arguments of `format` and render it
+    ///
+    /// Derived enum formats list their variants separated by `|`, and are
+    /// preceded by the index of the variant sent.
+    fn format(&self, format: &str, derived: bool, reader: &mut Reader) -> Result<String, FrameError> {
+        let variants: Vec<&str> = format.split('|').collect();
+        let format = if derived && variants.len() > 1 {
+            let variant = match variants.len() {
+                0..=0x100 => usize::from(reader.u8()?),
+                0x101..=0x10000 => usize::from(reader.u16()?),
+                _ => reader.u32()? as usize,
+            };
+            variants.get(variant).ok_or_else(|| FrameError::Malformed(format!("enum variant {} out of range", variant)))?
+        } else {
+            format
+        };
+
+        let fragments = defmt_parser::parse(format, ParserMode::ForwardsCompatible)
+            .map_err(|e| FrameError::Malformed(format!("format string {:?}: {}", format, e)))?;
+        let parameters: Vec<_> = fragments
+            .iter()
+            .filter_map(|fragment| match fragment {
+                Fragment::Parameter(parameter) => Some(parameter),
+                Fragment::Literal(_) => None,
+            })
+            .collect();
+
+        // Arguments are sent once each, in argument order
+        let mut arguments = BTreeMap::new();
+        for parameter in &parameters {
+            arguments.entry(parameter.index).or_insert(&parameter.ty);
+        }
+        let mut values = HashMap::new();
+        for (index, ty) in arguments {
+            let value = match ty {
+                Type::BitField(_) => {
+                    let fields = parameters.iter().filter(|parameter| parameter.index == index).copied();
+                    let (lowest, highest) = defmt_parser::get_max_bitfield_range(fields).unwrap_or((0, 8));
+                    let lowest_byte = lowest / 8;
+                    let bytes = match (highest.max(1) - 1) / 8 - lowest_byte + 1 {
+                        1 => 1,
+                        2 => 2,
+                        3..=4 => 4,
+                        5..=8 => 8,
+                        _ => 16,
+                    };
+                    Value::Unsigned(reader.unsigned(bytes)? << (lowest_byte * 8))
+                }
+                ty => self.value(ty, reader)?,
+            };
+            values.insert(index, value);
+        }
+
+        let mut text = String::new();
+        for fragment in &fragments {
+            match fragment {
+                Fragment::Literal(literal) => text.push_str(literal),
+                Fragment::Parameter(parameter) => {
+                    let value = &values[&parameter.index];
+                    match (&parameter.ty, value) {
+                        (Type::BitField(range), Value::Unsigned(value)) => {
+                            let width = u32::from(range.end - range.start);
+                            let field = (value >> range.start) & (u128::MAX >> (128 - width.clamp(1, 128)));
+                            text.push_str(&Value::Unsigned(field).render(parameter.hint.as_ref()));
+                        }
+                        _ => text.push_str(&value.render(parameter.hint.as_ref())),
+                    }
+                }
+            }
+        }
+        Ok(text)
+    }
+
+    fn value(&self, ty: &Type, reader: &mut Reader) -> Result<Value, FrameError> {
+        Ok(match ty {
+            Type::U8 => Value::Unsigned(reader.unsigned(1)?),
+            Type::U16 => Value::Unsigned(reader.unsigned(2)?),
+            Type::U32 | Type::Usize => Value::Unsigned(reader.unsigned(4)?),
+            Type::U64 => Value::Unsigned(reader.unsigned(8)?),
+            Type::U128 => Value::Unsigned(reader.unsigned(16)?),
+            Type::I8 => Value::Signed(reader.unsigned(1)? as u8 as i8 as i128),
+            Type::I16 => Value::Signed(reader.unsigned(2)? as u16 as i16 as i128),
+            Type::I32 | Type::Isize => Value::Signed(reader.unsigned(4)? as u32 as i32 as i128),
+            Type::I64 => Value::Signed(reader.unsigned(8)? as u64 as i64 as i128),
+            Type::I128 => Value::Signed(reader.unsigned(16)? as i128),
+            Type::F32 => Value::Float(f64::from(f32::from_bits(reader.u32()?))),
+            Type::F64 => Value::Float(f64::from_bits(reader.unsigned(8)? as u64)),
+            Type::Bool => Value::Bool(reader.u8()? != 0),
+            Type::Char => {
+                let code = reader.u32()?;
+                Value::Char(char::from_u32(code).ok_or_else(|| FrameError::Malformed(format!("{:#x} is not a character", code)))?)
+            }
+            Type::Str => {
+                let length = reader.u32()? as usize;
+                Value::Text(String::from_utf8_lossy(reader.take(length)?).into_owned())
+            }
+            Type::IStr => Value::Text(self.entry(reader.u16()?)?.format.clone()),
+            Type::U8Slice => {
+                let length = reader.u32()? as usize;
+                Value::Bytes(reader.take(length)?.to_vec())
+            }
+            Type::U8Array(length) => Value::Bytes(reader.take(*length)?.to_vec()),
+            Type::Debug | Type::Display => {
+                let length = reader.remaining().iter().position(|b| *b == 0xFF).ok_or(FrameError::Incomplete)?;
+                let text = String::from_utf8_lossy(reader.take(length)?).into_owned();
+                reader.take(1)?;
+                Value::Formatted(text)
+            }
+            Type::Format => {
+                let entry = self.entry(reader.u16()?)?;
+                Value::Formatted(self.format(&entry.format, entry.tag == "defmt_derived", reader)?)
+            }
+            Type::FormatSlice | Type::FormatArray(_) => {
+                let length = match ty {
+                    Type::FormatArray(length) => *length,
+                    _ => reader.u32()? as usize,
+                };
+                // One index for all the elements
+                let entry = self.entry(reader.u16()?)?;
+                let elements = (0..length)
+                    .map(|_| self.format(&entry.format, entry.tag == "defmt_derived", reader))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Value::Formatted(format!("[{}]", elements.join(", ")))
+            }
+            Type::FormatSequence => {
+                let mut text = String::new();
+                loop {
+                    match reader.u16()? {
+                        0 => break,
+                        index => {
+                            let entry = self.entry(index)?;
+                            text.push_str(&self.format(&entry.format, entry.tag == "defmt_derived", reader)?);
+                        }
+                    }
+                }
+                Value::Formatted(text)
+            }
+            Type::BitField(_) => unreachable!("bitfields are read with their neighbours"),
+        })
+    }
+}
+
+/// A decoded log message
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DefmtLine {
+    /// Rendered with the firmware's timestamp format, when it has one
+    pub timestamp: Option<String>,
+    /// `TRACE` to `ERROR`; none for `println!`
+    pub level: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for DefmtLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(timestamp) = &self.timestamp {
+            write!(f, "{} ", timestamp)?;
+        }
+        if let Some(level) = &self.level {
+            write!(f, "{:<5} ", level)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Turns received bytes into log lines, keeping unfinished frames between reads
+#[derive(Debug)]
+pub struct DefmtDecoder {
+    table: DefmtTable,
+    buffer: Vec<u8>,
+    /// Frames that could not be decoded since the last call to `take_errors`
+    errors: Vec<String>,
+}
+
+impl DefmtDecoder {
+    pub fn new(table: DefmtTable) -> Self {
+        Self { table, buffer: Vec::new(), errors: Vec::new() }
+    }
+
+    pub fn table(&self) -> &DefmtTable {
+        &self.table
+    }
+
+    /// Lines completed by `bytes`, in order
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<DefmtLine> {
+        self.buffer.extend_from_slice(bytes);
+        let mut lines = Vec::new();
+        match self.table.encoding {
+            Encoding::Rzcobs => {
+                while let Some(end) = self.buffer.iter().position(|b| *b == 0) {
+                    let frame: Vec<u8> = self.buffer.drain(..=end).collect();
+                    if end == 0 {
+                        continue;
+                    }
+                    let decoded = rzcobs_decode(&frame[..end]).map(|frame| self.table.decode_frame(&frame));
+                    match decoded {
+                        Some(Ok((line, _))) => lines.push(line),
+                        Some(Err(FrameError::Incomplete)) => self.errors.push("frame ended early".to_string()),
+                        Some(Err(FrameError::Malformed(e))) => self.errors.push(e),
+                        None => self.errors.push("corrupted rzCOBS frame".to_string()),
+                    }
+                }
+            }
+            Encoding::Raw => {
+                while !self.buffer.is_empty() {
+                    match self.table.decode_frame(&self.buffer) {
+                        Ok((line, used)) => {
+                            self.buffer.drain(..used);
+                            lines.push(line);
+                        }
+                        Err(FrameError::Incomplete) => break,
+                        Err(FrameError::Malformed(e)) => {
+                            // Nothing marks where the next frame starts
+                            self.errors.push(format!("{}; raw encoding cannot resynchronise, {} bytes dropped", e, self.buffer.len()));
+                            self.buffer.clear();
+                        }
+                    }
+                }
+            }
+        }
+        if self.buffer.len() > MAX_FRAME_BYTES {
+            self.errors.push(format!("no frame end in {} bytes, dropped", self.buffer.len()));
+            self.buffer.clear();
+        }
+        lines
+    }
+
+    /// Why frames failed to decode since the last call
+    pub fn take_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.errors)
+    }
+}
+
+#[derive(Debug)]
+enum FrameError {
+    /// More bytes are needed
+    Incomplete,
+    Malformed(String),
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn remaining(&self) -> &'a [u8] {
+        &self.bytes[self.position..]
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8], FrameError> {
+        let bytes = self.remaining().get(..length).ok_or(FrameError::Incomplete)?;
+        self.position += length;
+        Ok(bytes)
+    }
+
+    /// A little-endian unsigned integer of `length` bytes
+    fn unsigned(&mut self, length: usize) -> Result<u128, FrameError> {
+        Ok(self.take(length)?.iter().rev().fold(0, |value, b| value << 8 | u128::from(*b)))
+    }
+
+    fn u8(&mut self) -> Result<u8, FrameError> {
+        Ok(self.unsigned(1)? as u8)
+    }
+
+    fn u16(&mut self) -> Result<u16, FrameError> {
+        Ok(self.unsigned(2)? as u16)
+    }
+
+    fn u32(&mut self) -> Result<u32, FrameError> {
+        Ok(self.unsigned(4)? as u32)
+    }
+}
+
+/// A decoded argument
+#[derive(Debug)]
+enum Value {
+    Unsigned(u128),
+    Signed(i128),
+    Float(f64),
+    Bool(bool),
+    Char(char),
+    Text(String),
+    Bytes(Vec<u8>),
+    /// Already rendered: nested formats and `Debug`/`Display` output
+    Formatted(String),
+}
+
+impl Value {
+    fn render(&self, hint: Option<&DisplayHint>) -> String {
+        match self {
+            Value::Unsigned(value) => render_integer(*value, value.to_string(), hint),
+            Value::Signed(value) => render_integer(*value as u128, value.to_string(), hint),
+            Value::Float(value) => value.to_string(),
+            Value::Bool(value) => value.to_string(),
+            Value::Char(value) if hint == Some(&DisplayHint::Debug) => format!("{:?}", value),
+            Value::Char(value) => value.to_string(),
+            Value::Text(text) if hint == Some(&DisplayHint::Debug) => format!("{:?}", text),
+            Value::Text(text) | Value::Formatted(text) => text.clone(),
+            Value::Bytes(bytes) => match hint {
+                Some(DisplayHint::Ascii) => format!("b\"{}\"", bytes.escape_ascii()),
+                _ => {
+                    let elements: Vec<String> = bytes.iter().map(|b| render_integer(u128::from(*b), b.to_string(), hint)).collect();
+                    format!("[{}]", elements.join(", "))
+                }
+            },
+        }
+    }
+}
+
+/// An integer in the form `hint` asks for; `decimal` is its signed rendering
+fn render_integer(value: u128, decimal: String, hint: Option<&DisplayHint>) -> String {
+    match hint {
+        Some(DisplayHint::NoHint { zero_pad }) => format!("{:0>width$}", decimal, width = zero_pad),
+        Some(DisplayHint::Hexadecimal { alternate, uppercase, zero_pad }) => match (alternate, uppercase) {
+            (false, false) => format!("{:0width$x}", value, width = zero_pad),
+            (false, true) => format!("{:0width$X}", value, width = zero_pad),
+            (true, false) => format!("{:#0width$x}", value, width = zero_pad),
+            (true, true) => format!("{:#0width$X}", value, width = zero_pad),
+        },
+        Some(DisplayHint::Octal { alternate, zero_pad }) if *alternate => format!("{:#0width$o}", value, width = zero_pad),
+        Some(DisplayHint::Octal { zero_pad, .. }) => format!("{:0width$o}", value, width = zero_pad),
+        Some(DisplayHint::Binary { alternate, zero_pad }) if *alternate => format!("{:#0width$b}", value, width = zero_pad),
+        Some(DisplayHint::Binary { zero_pad, .. }) => format!("{:0width$b}", value, width = zero_pad),
+        Some(DisplayHint::Seconds(precision)) => {
+            let (scale, digits) = scale(precision);
+            match digits {
+                0 => value.to_string(),
+                _ => format!("{}.{:0digits$}", value / scale, value % scale, digits = digits),
+            }
+        }
+        Some(DisplayHint::Time(precision)) => {
+            let (scale, digits) = scale(precision);
+            let seconds = value / scale;
+            let clock = format!("{:02}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60);
+            match digits {
+                0 => clock,
+                _ => format!("{}.{:0digits$}", clock, value % scale, digits = digits),
+            }
+        }
+        Some(DisplayHint::ISO8601(precision)) => {
+            let millis = match precision {
+                TimePrecision::Seconds => value.saturating_mul(1000),
+                TimePrecision::Millis => value,
+                TimePrecision::Micros => value / 1000,
+            };
+            i64::try_from(millis)
+                .ok()
+                .and_then(chrono::DateTime::from_timestamp_millis)
+                .map(|time| time.to_rfc3339_opts(chrono::SecondsFormat::Millis, true))
+                .unwrap_or(decimal)
+        }
+        _ => decimal,
+    }
+}
+
+/// Units per second and fractional digits at `precision`
+fn scale(precision: &TimePrecision) -> (u128, usize) {
+    match precision {
+        TimePrecision::Micros => (1_000_000, 6),
+        TimePrecision::Millis => (1_000, 3),
+        TimePrecision::Seconds => (1, 0),
+    }
+}
+
+/// Undo defmt's rzCOBS encoding of one frame, without its zero terminator
+///
+/// The frame is read from the end. The result may carry extra zero bytes at
+/// the end, which frame decoding ignores. `None` if the frame is corrupted.
+fn rzcobs_decode(frame: &[u8]) -> Option<Vec<u8>> {
+    let mut decoded = Vec::new();
+    let mut bytes = frame.iter().rev().copied();
+    while let Some(code) = bytes.next() {
+        match code {
+            0 => return None,
+            // Seven bytes, those whose bit is set being zero
+            0x01..=0x7F => {
+                for bit in (0..7).rev() {
+                    decoded.push(if code & (1 << bit) == 0 { bytes.next()? } else { 0 });
+                }
+            }
+            // Run of code - 0x80 + 7 non-zero bytes followed by a zero
+            0x80..=0xFE => {
+                decoded.push(0);
+                for _ in 0..(code & 0x7F) + 7 {
+                    decoded.push(bytes.next()?);
+                }
+            }
+            0xFF => {
+                for _ in 0..134 {
+                    decoded.push(bytes.next()?);
+                }
+            }
+        }
+    }
+    decoded.reverse();
+    Some(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(encoding: Encoding) -> DefmtTable {
+        let entries = [
+            (1, "defmt_prim", "{=u8}"),
+            (2, "defmt_info", "Starting, {=u32} tasks"),
+            (3, "defmt_warn", "Temperature {=i16} at {=[u8]:x} {=?}"),
+            (4, "defmt_derived", "Idle|Busy({=u8})"),
+            (5, "defmt_error", "Flags {0=0..4:b} mode {0=4..8} {1=str:?}"),
+            (6, "defmt_println", "{=istr}"),
+            (7, "defmt_str", "interned"),
+        ];
+        DefmtTable {
+            entries: entries.iter().map(|(index, tag, format)| (*index, Entry { tag: tag.to_string(), format: format.to_string() })).collect(),
+            timestamp: Some("{=u64:us}".to_string()),
+            encoding,
+        }
+    }
+
+    #[test]
+    fn test_decode_frames() {
+        let table = table(Encoding::Raw);
+        let timestamp = 1_234_567u64.to_le_bytes();
+
+        let mut frame = vec![2, 0];
+        frame.extend(timestamp);
+        frame.extend(3u32.to_le_bytes());
+        let (line, used) = table.decode_frame(&frame).unwrap();
+        assert_eq!(used, frame.len());
+        assert_eq!(line.to_string(), "1.234567 INFO  Starting, 3 tasks");
+
+        // Negative integer, byte slice, then an enum through its own index
+        let mut frame = vec![3, 0];
+        frame.extend(timestamp);
+        frame.extend((-5i16).to_le_bytes());
+        frame.extend([2, 0, 0, 0, 0xAB, 0x01]);
+        frame.extend([4, 0, 1, 7]);
+        assert_eq!(table.decode_frame(&frame).unwrap().0.message, "Temperature -5 at [ab, 1] Busy(7)");
+
+        let mut frame = vec![5, 0];
+        frame.extend(timestamp);
+        frame.extend([0x3A, 2, 0, 0, 0, b'o', b'k']);
+        let (line, _) = table.decode_frame(&frame).unwrap();
+        assert_eq!((line.level.as_deref(), line.message.as_str()), (Some("ERROR"), "Flags 1010 mode 3 \"ok\""));
+
+        let mut frame = vec![6, 0];
+        frame.extend(timestamp);
+        frame.extend([7, 0]);
+        assert_eq!(table.decode_frame(&frame).unwrap().0.to_string(), "1.234567 interned");
+
+        assert!(matches!(table.decode_frame(&frame[..5]), Err(FrameError::Incomplete)));
+        assert!(matches!(table.decode_frame(&[9, 0]), Err(FrameError::Malformed(_))));
+        assert!(matches!(table.decode_frame(&[1, 0]), Err(FrameError::Malformed(_))));
+        assert!(matches!(DefmtTable::parse(b"not an ELF"), Err(DefmtError::Elf(_))));
+    }
+
+    #[test]
+    fn test_rzcobs_stream_across_reads() {
+        // Encodings from defmt's own rzCOBS tests
+        assert_eq!(rzcobs_decode(&[0x01, 0x7E]).unwrap(), [1, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(rzcobs_decode(&[0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x81]).unwrap()[..8], [0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88]);
+        assert_eq!(rzcobs_decode(&[0x44, 0x5F, 0xFF, 0x3F]).unwrap()[..14], [0, 0, 0, 0, 0, 0x44, 0, 0, 0, 0, 0, 0, 0, 0xFF]);
+
+        // Frame 2 with timestamp 1 and argument 3: 02 00 01 00 00 00 00 00 00 00 03 00 00 00
+        let encoded = [0x00, 0x02, 0x01, 0x7A, 0x03, 0x77, 0x00];
+        let mut decoder = DefmtDecoder::new(table(Encoding::Rzcobs));
+        assert!(decoder.feed(&encoded[..4]).is_empty());
+        let lines = decoder.feed(&encoded[4..]);
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].to_string(), "0.000001 INFO  Starting, 3 tasks");
+        assert!(decoder.take_errors().is_empty());
+
+        // A corrupted frame is reported and the next one still decodes
+        let mut stream = vec![0x09, 0x7E, 0x00];
+        stream.extend(&encoded[1..]);
+        assert_eq!(decoder.feed(&stream).len(), 1);
+        assert_eq!(decoder.take_errors().len(), 1);
+    }
+}
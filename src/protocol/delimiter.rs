@@ -0,0 +1,109 @@
+//! Delimiter framing
+//!
+//! Each frame ends with a fixed byte sequence, such as `ETX` or a prompt
+//! character, and the payload is sent as is. Payloads must not contain the
+//! delimiter, as nothing escapes it.
+
+use super::framer::{FrameError, Framer};
+
+/// Longest frame accepted before the partial frame is discarded
+pub const DELIMITER_MAX_FRAME: usize = 64 * 1024;
+
+/// Delimiter of the framer built without options: a line feed
+pub const DEFAULT_DELIMITER: &[u8] = b"\n";
+
+#[derive(Debug)]
+pub struct DelimiterFramer {
+    delimiter: Vec<u8>,
+    buffer: Vec<u8>,
+    /// The current frame grew too long and is skipped up to the next delimiter
+    overflowed: bool,
+}
+
+impl DelimiterFramer {
+    /// A framer ending frames with `delimiter`, which must not be empty
+    pub fn new(delimiter: Vec<u8>) -> Option<Self> {
+        if delimiter.is_empty() {
+            return None;
+        }
+        Some(Self { delimiter, buffer: Vec::new(), overflowed: false })
+    }
+
+    pub fn delimiter(&self) -> &[u8] {
+        &self.delimiter
+    }
+}
+
+impl Default for DelimiterFramer {
+    fn default() -> Self {
+        Self { delimiter: DEFAULT_DELIMITER.to_vec(), buffer: Vec::new(), overflowed: false }
+    }
+}
+
+impl Framer for DelimiterFramer {
+    fn name(&self) -> &'static str {
+        "delimiter"
+    }
+
+    fn encode(&self, payload: &[u8]) -> Vec<u8> {
+        let mut encoded = payload.to_vec();
+        encoded.extend_from_slice(&self.delimiter);
+        encoded
+    }
+
+    fn decode(&mut self, data: &[u8]) -> Vec<Result<Vec<u8>, FrameError>> {
+        let mut frames = Vec::new();
+
+        for &byte in data {
+            self.buffer.push(byte);
+            if self.buffer.ends_with(&self.delimiter) {
+                self.buffer.truncate(self.buffer.len() - self.delimiter.len());
+                if std::mem::take(&mut self.overflowed) {
+                    frames.push(Err(FrameError::TooLong(DELIMITER_MAX_FRAME)));
+                } else if !self.buffer.is_empty() {
+                    // Back-to-back delimiters delimit empty frames, which are ignored
+                    frames.push(Ok(std::mem::take(&mut self.buffer)));
+                }
+                self.buffer.clear();
+            } else if self.buffer.len() > DELIMITER_MAX_FRAME + self.delimiter.len() {
+                // Keep the tail, which may be the start of the delimiter
+                self.overflowed = true;
+                self.buffer.drain(..self.buffer.len() - self.delimiter.len());
+            }
+        }
+
+        frames
+    }
+
+    fn pending(&self) -> usize {
+        self.buffer.len()
+    }
+
+    fn reset(&mut self) {
+        self.buffer.clear();
+        self.overflowed = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_multi_byte_delimiter_across_reads() {
+        let mut framer = DelimiterFramer::new(b"\r\n>".to_vec()).unwrap();
+        assert_eq!(framer.encode(b"ver"), b"ver\r\n>");
+        assert!(framer.decode(b"v1.2\r").is_empty());
+        assert_eq!(framer.decode(b"\n>\r\n>ok\r\n>"), vec![Ok(b"v1.2".to_vec()), Ok(b"ok".to_vec())]);
+        assert_eq!(framer.pending(), 0);
+        assert!(DelimiterFramer::new(Vec::new()).is_none());
+    }
+
+    #[test]
+    fn test_overlong_frame_is_reported_once() {
+        let mut framer = DelimiterFramer::default();
+        let frames = framer.decode(&vec![b'x'; DELIMITER_MAX_FRAME + 10]);
+        assert!(frames.is_empty());
+        assert_eq!(framer.decode(b"\nnext\n"), vec![Err(FrameError::TooLong(DELIMITER_MAX_FRAME)), Ok(b"next".to_vec())]);
+    }
+}
@@ -0,0 +1,309 @@
+//! DSMR P1 smart meter telegrams
+//!
+//! Dutch and Belgian electricity meters send a telegram on their P1 port
+//! every second or ten: a `/` identification line, one COSEM object per line
+//! such as `1-0:1.8.1(001234.567*kWh)`, and a `!` line carrying a CRC16 of
+//! everything from the `/` through the `!`. DSMR 2.2 and 3 telegrams end with
+//! a bare `!` and have no CRC. Objects are named by their OBIS reference.
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+use thiserror::Error;
+
+use crate::utils::BufferUtils;
+
+/// Why a telegram was rejected
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum DsmrError {
+    #[error("Malformed telegram: {0}")]
+    Malformed(String),
+
+    #[error("CRC mismatch: telegram says {expected:04X}, computed {computed:04X}")]
+    Crc { expected: u16, computed: u16 },
+}
+
+/// Names for the objects of the electricity meter itself
+const OBIS_NAMES: &[(&str, &str)] = &[
+    ("1-3:0.2.8", "version"),
+    ("0-0:96.1.4", "version"),
+    ("0-0:1.0.0", "timestamp"),
+    ("0-0:96.1.1", "equipment_id"),
+    ("1-0:1.8.1", "energy_delivered_tariff1_kwh"),
+    ("1-0:1.8.2", "energy_delivered_tariff2_kwh"),
+    ("1-0:2.8.1", "energy_returned_tariff1_kwh"),
+    ("1-0:2.8.2", "energy_returned_tariff2_kwh"),
+    ("0-0:96.14.0", "tariff"),
+    ("1-0:1.7.0", "power_delivered_kw"),
+    ("1-0:2.7.0", "power_returned_kw"),
+    ("1-0:1.4.0", "average_demand_kw"),
+    ("1-0:1.6.0", "maximum_demand_month_kw"),
+    ("0-0:17.0.0", "power_limit_kw"),
+    ("0-0:96.3.10", "breaker_state"),
+    ("0-0:96.7.21", "power_failures"),
+    ("0-0:96.7.9", "long_power_failures"),
+    ("1-0:32.32.0", "voltage_sags_l1"),
+    ("1-0:52.32.0", "voltage_sags_l2"),
+    ("1-0:72.32.0", "voltage_sags_l3"),
+    ("1-0:32.36.0", "voltage_swells_l1"),
+    ("1-0:52.36.0", "voltage_swells_l2"),
+    ("1-0:72.36.0", "voltage_swells_l3"),
+    ("0-0:96.13.0", "text_message"),
+    ("1-0:32.7.0", "voltage_l1_v"),
+    ("1-0:52.7.0", "voltage_l2_v"),
+    ("1-0:72.7.0", "voltage_l3_v"),
+    ("1-0:31.7.0", "current_l1_a"),
+    ("1-0:51.7.0", "current_l2_a"),
+    ("1-0:71.7.0", "current_l3_a"),
+    ("1-0:21.7.0", "power_delivered_l1_kw"),
+    ("1-0:41.7.0", "power_delivered_l2_kw"),
+    ("1-0:61.7.0", "power_delivered_l3_kw"),
+    ("1-0:22.7.0", "power_returned_l1_kw"),
+    ("1-0:42.7.0", "power_returned_l2_kw"),
+    ("1-0:62.7.0", "power_returned_l3_kw"),
+];
+
+/// One COSEM object: an OBIS reference and its parenthesised values
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DsmrObject {
+    /// e.g. `1-0:1.8.1`
+    pub obis: String,
+    /// Name of the reading, for the objects this decoder knows
+    pub name: Option<String>,
+    /// Values as sent, e.g. `001234.567*kWh`
+    pub values: Vec<String>,
+}
+
+impl DsmrObject {
+    /// The object's reading: the last value as a number without its unit, a
+    /// timestamp in RFC 3339, or text, with hex-encoded text decoded
+    pub fn reading(&self) -> Value {
+        let Some(raw) = self.values.last() else { return Value::Null };
+        let hex_text = self
+            .name
+            .as_deref()
+            .is_some_and(|name| name.ends_with("equipment_id") || name == "text_message");
+        if hex_text {
+            return Value::String(decode_hex_text(raw).unwrap_or_else(|| raw.clone()));
+        }
+        if let Some(timestamp) = timestamp(raw) {
+            return Value::String(timestamp);
+        }
+        let number = raw.split_once('*').map_or(raw.as_str(), |(number, _)| number);
+        match number.parse::<f64>() {
+            Ok(number) => serde_json::json!(number),
+            Err(_) => Value::String(raw.clone()),
+        }
+    }
+}
+
+/// A telegram whose CRC, when it has one, matched
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Telegram {
+    /// Manufacturer and meter type from the `/` line, e.g. `ISk5\2MT382-1000`
+    pub identification: String,
+    /// Whether the telegram carried a CRC; DSMR 2.2 and 3 meters send none
+    pub crc_checked: bool,
+    pub objects: Vec<DsmrObject>,
+}
+
+impl Telegram {
+    /// Parse one telegram from its `/` through the CRC after its `!`
+    pub fn parse(text: &str) -> Result<Self, DsmrError> {
+        let malformed = |message: &str| Err(DsmrError::Malformed(message.to_string()));
+        let Some(text) = text.trim_end().strip_prefix('/') else {
+            return malformed("does not start with /");
+        };
+        let Some(end) = text.rfind("\n!") else {
+            return malformed("no ! line ends it");
+        };
+        let crc = text[end + 2..].trim();
+        let crc_checked = !crc.is_empty();
+        if crc_checked {
+            let expected = match u16::from_str_radix(crc, 16) {
+                Ok(expected) if crc.len() == 4 => expected,
+                _ => return malformed("CRC is not four hex digits"),
+            };
+            // The CRC covers the leading / and the !
+            let computed = BufferUtils::crc16_arc(format!("/{}", &text[..end + 2]).as_bytes());
+            if expected != computed {
+                return Err(DsmrError::Crc { expected, computed });
+            }
+        }
+
+        let mut lines = text[..end].lines();
+        let identification = lines.next().unwrap_or_default().trim().to_string();
+        let mut objects: Vec<DsmrObject> = Vec::new();
+        for line in lines.map(str::trim).filter(|line| !line.is_empty()) {
+            // DSMR 2.2 puts the gas reading on a line of its own
+            if line.starts_with('(') {
+                let Some(object) = objects.last_mut() else {
+                    return malformed("values before any OBIS reference");
+                };
+                object.values.extend(values(line));
+                continue;
+            }
+            let Some((obis, rest)) = line.split_once('(') else {
+                return Err(DsmrError::Malformed(format!("line is not an OBIS object: {:?}", line)));
+            };
+            objects.push(DsmrObject {
+                obis: obis.to_string(),
+                name: name(obis),
+                values: values(&format!("({}", rest)),
+            });
+        }
+        Ok(Self { identification, crc_checked, objects })
+    }
+
+    /// Every named object's reading, by name
+    pub fn readings(&self) -> Map<String, Value> {
+        self.objects
+            .iter()
+            .filter_map(|object| Some((object.name.clone()?, object.reading())))
+            .collect()
+    }
+}
+
+/// Find the complete telegrams in received text
+///
+/// Text before the first `/`, such as the end of a telegram the read
+/// started in the middle of, is skipped. Returns the telegrams, or why each
+/// was rejected, and how much of `text` they used; an unfinished telegram at
+/// the end is left for the next call.
+pub fn find_telegrams(text: &str) -> (Vec<Result<Telegram, DsmrError>>, usize) {
+    let mut telegrams = Vec::new();
+    let mut start = match text.find('/') {
+        Some(start) => start,
+        None => return (telegrams, text.len()),
+    };
+    loop {
+        // Nothing else in a telegram contains a /, so one starts a new telegram even mid-line
+        let next_start = text[start + 1..].find('/').map(|offset| start + 1 + offset);
+        let Some(end) = text[start..].find("\n!").map(|offset| start + offset + 1) else {
+            return (telegrams, start);
+        };
+        if let Some(next_start) = next_start.filter(|next_start| *next_start < end) {
+            telegrams.push(Err(DsmrError::Malformed("cut off by the next telegram".to_string())));
+            start = next_start;
+            continue;
+        }
+        let Some(line_end) = text[end..].find('\n').map(|offset| end + offset + 1) else {
+            return (telegrams, start);
+        };
+        telegrams.push(Telegram::parse(&text[start..line_end]));
+        match text[line_end..].find('/') {
+            Some(offset) => start = line_end + offset,
+            None => return (telegrams, line_end),
+        }
+    }
+}
+
+/// Values between parentheses, in order
+fn values(text: &str) -> Vec<String> {
+    text.split('(')
+        .skip(1)
+        .map(|value| value.split_once(')').map_or(value, |(value, _)| value).to_string())
+        .collect()
+}
+
+/// Name for `obis`; M-Bus devices such as gas meters get their channel number
+fn name(obis: &str) -> Option<String> {
+    if let Some((_, name)) = OBIS_NAMES.iter().find(|(known, _)| *known == obis) {
+        return Some(name.to_string());
+    }
+    let (channel, object) = obis.strip_prefix("0-")?.split_once(':')?;
+    let channel: u8 = channel.parse().ok().filter(|channel| *channel > 0)?;
+    let name = match object {
+        "24.1.0" => "device_type",
+        "96.1.0" | "96.1.1" => "equipment_id",
+        "24.2.1" | "24.2.3" | "24.3.0" => "delivered",
+        _ => return None,
+    };
+    Some(format!("mbus{}_{}", channel, name))
+}
+
+/// `YYMMDDhhmmssX` in RFC 3339, X being W for winter (CET) or S for summer (CEST)
+fn timestamp(value: &str) -> Option<String> {
+    let (digits, season) = value.split_at_checked(12)?;
+    let offset = match season {
+        "W" => "+01:00",
+        "S" => "+02:00",
+        _ => return None,
+    };
+    if !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some(format!(
+        "20{}-{}-{}T{}:{}:{}{}",
+        &digits[0..2],
+        &digits[2..4],
+        &digits[4..6],
+        &digits[6..8],
+        &digits[8..10],
+        &digits[10..12],
+        offset
+    ))
+}
+
+/// Printable ASCII sent as hex, as equipment IDs and text messages are
+fn decode_hex_text(value: &str) -> Option<String> {
+    if value.is_empty() || !value.len().is_multiple_of(2) {
+        return None;
+    }
+    let bytes = (0..value.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(value.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    bytes
+        .iter()
+        .all(|b| b.is_ascii_graphic() || *b == b' ')
+        .then(|| String::from_utf8_lossy(&bytes).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TELEGRAM: &str = "/ISk5\\2MT382-1000\r\n\r\n1-3:0.2.8(50)\r\n0-0:1.0.0(101209113020W)\r\n\
+        0-0:96.1.1(4B384547303034303436333935353037)\r\n1-0:1.8.1(123456.789*kWh)\r\n1-0:1.8.2(123456.789*kWh)\r\n\
+        0-0:96.14.0(0002)\r\n1-0:1.7.0(01.193*kW)\r\n1-0:32.7.0(220.1*V)\r\n1-0:21.7.0(01.111*kW)\r\n\
+        0-1:24.1.0(003)\r\n0-1:24.2.1(101209112500W)(12785.123*m3)\r\n!4FFA\r\n";
+
+    #[test]
+    fn test_parse_telegram() {
+        let telegram = Telegram::parse(TELEGRAM).unwrap();
+        assert_eq!(telegram.identification, "ISk5\\2MT382-1000");
+        assert!(telegram.crc_checked);
+        assert_eq!(telegram.objects.len(), 11);
+
+        let readings = telegram.readings();
+        assert_eq!(readings["timestamp"], "2010-12-09T11:30:20+01:00");
+        assert_eq!(readings["equipment_id"], "K8EG004046395507");
+        assert_eq!(readings["energy_delivered_tariff1_kwh"], 123456.789);
+        assert_eq!(readings["tariff"], 2.0);
+        assert_eq!(readings["power_delivered_l1_kw"], 1.111);
+        assert_eq!(readings["mbus1_delivered"], 12785.123);
+
+        let corrupted = TELEGRAM.replace("220.1", "220.2");
+        assert!(matches!(Telegram::parse(&corrupted), Err(DsmrError::Crc { expected: 0x4FFA, .. })));
+
+        // DSMR 2.2: no CRC, and the gas reading on the next line
+        let old = Telegram::parse("/KMP5 ZABF001587315111\r\n0-1:24.3.0(121030140000)(00)(60)(1)(0-1:24.2.1)(m3)\r\n(00000.000)\r\n!\r\n").unwrap();
+        assert!(!old.crc_checked);
+        assert_eq!(old.readings()["mbus1_delivered"], 0.0);
+    }
+
+    #[test]
+    fn test_find_telegrams_across_reads() {
+        let stream = format!("6.1.1(4B38)\r\n!1234\r\n{}{}", TELEGRAM, &TELEGRAM[..40]);
+        let (telegrams, used) = find_telegrams(&stream);
+        assert_eq!(telegrams.len(), 1);
+        assert!(telegrams[0].is_ok());
+        assert_eq!(&stream[used..], &TELEGRAM[..40]);
+
+        let (telegrams, used) = find_telegrams(&format!("{}{}", &TELEGRAM[..40], TELEGRAM));
+        assert_eq!(telegrams.len(), 2);
+        assert!(matches!(telegrams[0], Err(DsmrError::Malformed(_))));
+        assert!(telegrams[1].is_ok());
+        assert_eq!(used, 40 + TELEGRAM.len());
+    }
+}
@@ -0,0 +1,246 @@
+//! ESP8266/ESP32 reset sequences
+//!
+//! Development boards wire DTR and RTS through two transistors to the chip's
+//! IO0 (boot mode strap) and EN (reset) pins, so that toggling them in the
+//! right order resets the chip with IO0 held low and the ROM waits for a
+//! download. The sequences here are those of esptool, step for step,
+//! including the passes through intermediate line states that the classic
+//! sequence makes. Chips with a built-in USB Serial/JTAG controller decode
+//! the lines themselves and need their own order.
+
+use std::time::Duration;
+
+use crate::serial::LocalSerialError;
+
+/// How long EN is held low to reset the chip
+const RESET_HOLD: Duration = Duration::from_millis(100);
+
+/// Reset hold for USB Serial/JTAG, which needs longer
+const USB_RESET_HOLD: Duration = Duration::from_millis(200);
+
+/// What the chip should do after the reset
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResetMode {
+    /// Wait in the ROM bootloader for a download
+    Bootloader,
+    /// Run the application in flash
+    Run,
+}
+
+impl ResetMode {
+    pub fn parse(value: &str) -> Result<Self, LocalSerialError> {
+        match value.to_lowercase().as_str() {
+            "bootloader" | "download" => Ok(ResetMode::Bootloader),
+            "run" | "hard_reset" => Ok(ResetMode::Run),
+            _ => Err(LocalSerialError::InvalidConfig(format!("Unknown reset mode: {}", value))),
+        }
+    }
+}
+
+impl std::fmt::Display for ResetMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResetMode::Bootloader => write!(f, "bootloader"),
+            ResetMode::Run => write!(f, "run"),
+        }
+    }
+}
+
+/// How the board's reset circuit is driven
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResetVariant {
+    /// esptool's default: one line at a time
+    Classic,
+    /// Both lines changed together, as esptool does on Unix
+    Tight,
+    /// The USB Serial/JTAG controller of ESP32-C3, -S3 and later
+    UsbJtag,
+}
+
+impl ResetVariant {
+    pub fn parse(value: &str) -> Result<Self, LocalSerialError> {
+        match value.to_lowercase().as_str() {
+            "classic" | "default" => Ok(ResetVariant::Classic),
+            "tight" | "unix" => Ok(ResetVariant::Tight),
+            "usb_jtag" | "usb_jtag_serial" => Ok(ResetVariant::UsbJtag),
+            _ => Err(LocalSerialError::InvalidConfig(format!("Unknown reset variant: {}", value))),
+        }
+    }
+}
+
+impl std::fmt::Display for ResetVariant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResetVariant::Classic => write!(f, "classic"),
+            ResetVariant::Tight => write!(f, "tight"),
+            ResetVariant::UsbJtag => write!(f, "usb_jtag"),
+        }
+    }
+}
+
+/// One step of a reset sequence
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResetStep {
+    /// Line levels to set, `true` being asserted; `None` leaves a line as it is
+    Lines { dtr: Option<bool>, rts: Option<bool> },
+    Wait(Duration),
+}
+
+/// Steps built from esptool's one-line-at-a-time calls, each carrying both levels once known
+#[derive(Debug, Default)]
+struct Sequence {
+    dtr: Option<bool>,
+    rts: Option<bool>,
+    steps: Vec<ResetStep>,
+}
+
+impl Sequence {
+    fn dtr(&mut self, level: bool) -> &mut Self {
+        self.dtr = Some(level);
+        self.push_lines()
+    }
+
+    fn rts(&mut self, level: bool) -> &mut Self {
+        self.rts = Some(level);
+        self.push_lines()
+    }
+
+    fn both(&mut self, dtr: bool, rts: bool) -> &mut Self {
+        self.dtr = Some(dtr);
+        self.rts = Some(rts);
+        self.push_lines()
+    }
+
+    fn wait(&mut self, duration: Duration) -> &mut Self {
+        self.steps.push(ResetStep::Wait(duration));
+        self
+    }
+
+    fn push_lines(&mut self) -> &mut Self {
+        self.steps.push(ResetStep::Lines { dtr: self.dtr, rts: self.rts });
+        self
+    }
+}
+
+/// The steps resetting a chip into `mode`
+///
+/// `reset_delay` is how long IO0 stays low after EN is released when
+/// entering the bootloader; esptool uses 50ms and retries with 550ms for
+/// boards with a slow reset circuit. `inverted` is for boards whose circuit
+/// inverts both lines.
+pub fn reset_sequence(mode: ResetMode, variant: ResetVariant, reset_delay: Duration, inverted: bool) -> Vec<ResetStep> {
+    let mut sequence = Sequence::default();
+    match (mode, variant) {
+        // IO0 is released first so the chip does not come up in the bootloader
+        (ResetMode::Run, ResetVariant::UsbJtag) => sequence.dtr(false).rts(true).wait(USB_RESET_HOLD).rts(false),
+        (ResetMode::Run, _) => sequence.dtr(false).rts(true).wait(RESET_HOLD).rts(false),
+        (ResetMode::Bootloader, ResetVariant::Classic) => sequence
+            .dtr(false) // IO0 high
+            .rts(true) // EN low, chip in reset
+            .wait(RESET_HOLD)
+            .dtr(true) // IO0 low
+            .rts(false) // EN high, chip out of reset
+            .wait(reset_delay)
+            .dtr(false), // IO0 high, done
+        (ResetMode::Bootloader, ResetVariant::Tight) => sequence
+            .both(false, false)
+            .both(true, true)
+            .both(false, true) // IO0 high, EN low: chip in reset
+            .wait(RESET_HOLD)
+            .both(true, false) // IO0 low, EN high: chip out of reset
+            .wait(reset_delay)
+            .both(false, false),
+        (ResetMode::Bootloader, ResetVariant::UsbJtag) => sequence
+            .rts(false)
+            .dtr(false) // idle
+            .wait(RESET_HOLD)
+            .dtr(true) // IO0
+            .rts(false)
+            .wait(RESET_HOLD)
+            // Through (1,1) rather than (0,0) into reset
+            .rts(true)
+            .dtr(false)
+            .rts(true)
+            .wait(RESET_HOLD)
+            .dtr(false)
+            .rts(false), // out of reset
+    };
+
+    if inverted {
+        for step in &mut sequence.steps {
+            if let ResetStep::Lines { dtr, rts } = step {
+                *dtr = dtr.map(|level| !level);
+                *rts = rts.map(|level| !level);
+            }
+        }
+    }
+    sequence.steps
+}
+
+/// Whether the output after a reset shows the ROM waiting for a download
+///
+/// `None` when there is no ROM banner to tell from, e.g. on an ESP8266,
+/// which prints it at 74880 baud.
+pub fn in_download_mode(output: &str) -> Option<bool> {
+    if output.contains("waiting for download") {
+        return Some(true);
+    }
+    // ESP32 ROM banners name the boot mode, e.g. "boot:0x13 (SPI_FAST_FLASH_BOOT)"
+    output.contains("boot:").then_some(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(dtr: bool, rts: bool) -> ResetStep {
+        ResetStep::Lines { dtr: Some(dtr), rts: Some(rts) }
+    }
+
+    #[test]
+    fn test_classic_bootloader_sequence() {
+        let delay = Duration::from_millis(50);
+        let steps = reset_sequence(ResetMode::Bootloader, ResetVariant::Classic, delay, false);
+        assert_eq!(
+            steps,
+            vec![
+                ResetStep::Lines { dtr: Some(false), rts: None },
+                lines(false, true),
+                ResetStep::Wait(RESET_HOLD),
+                lines(true, true),
+                lines(true, false),
+                ResetStep::Wait(delay),
+                lines(false, false),
+            ]
+        );
+
+        let inverted = reset_sequence(ResetMode::Bootloader, ResetVariant::Classic, delay, true);
+        assert_eq!(inverted[0], ResetStep::Lines { dtr: Some(true), rts: None });
+        assert_eq!(inverted[6], lines(true, true));
+    }
+
+    #[test]
+    fn test_other_sequences() {
+        let delay = Duration::from_millis(550);
+        let tight = reset_sequence(ResetMode::Bootloader, ResetVariant::Tight, delay, false);
+        assert_eq!(tight[2], lines(false, true));
+        assert_eq!(tight[4], lines(true, false));
+        assert_eq!(tight[5], ResetStep::Wait(delay));
+
+        // Ends out of reset with IO0 released
+        let usb = reset_sequence(ResetMode::Bootloader, ResetVariant::UsbJtag, delay, false);
+        assert_eq!(usb.last(), Some(&lines(false, false)));
+        assert!(usb.contains(&lines(true, true)));
+
+        let run = reset_sequence(ResetMode::Run, ResetVariant::Classic, delay, false);
+        assert_eq!(run, vec![ResetStep::Lines { dtr: Some(false), rts: None }, lines(false, true), ResetStep::Wait(RESET_HOLD), lines(false, false)]);
+    }
+
+    #[test]
+    fn test_in_download_mode() {
+        let banner = "ets Jun  8 2016 00:22:57\r\n\r\nrst:0x1 (POWERON_RESET),boot:0x3 (DOWNLOAD_BOOT(UART0/UART1/SDIO_REI_REO_V2))\r\nwaiting for download\r\n";
+        assert_eq!(in_download_mode(banner), Some(true));
+        assert_eq!(in_download_mode("rst:0x1 (POWERON_RESET),boot:0x13 (SPI_FAST_FLASH_BOOT)\r\n"), Some(false));
+        assert_eq!(in_download_mode(""), None);
+    }
+}
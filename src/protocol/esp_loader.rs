@@ -0,0 +1,455 @@
+//! ESP ROM serial bootloader protocol
+//!
+//! In download mode the ROM of ESP8266 and ESP32 chips takes SLIP-framed
+//! commands: a sync that locks it onto the baud rate, register reads, and
+//! FLASH_BEGIN/FLASH_DATA/FLASH_END, which erase a region and write an image
+//! into it one 1 KiB block at a time, each carrying an XOR checksum.
+//! SPI_FLASH_MD5 hashes a flash region so a write can be verified. This is
+//! what esptool speaks to the ROM; its faster RAM stub is not used.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use thiserror::Error;
+use tokio::time::Instant;
+
+use super::framer::Framer;
+use super::slip::SlipFramer;
+use crate::serial::{LocalSerialError, SerialConnection};
+
+const FLASH_BEGIN: u8 = 0x02;
+const FLASH_DATA: u8 = 0x03;
+const FLASH_END: u8 = 0x04;
+const SYNC: u8 = 0x08;
+const READ_REG: u8 = 0x0A;
+const SPI_SET_PARAMS: u8 = 0x0B;
+const SPI_ATTACH: u8 = 0x0D;
+const SPI_FLASH_MD5: u8 = 0x13;
+
+/// Commands go out with direction 0, responses come back with 1
+const DIRECTION_REQUEST: u8 = 0x00;
+const DIRECTION_RESPONSE: u8 = 0x01;
+
+/// Seed of the data checksum
+const CHECKSUM_MAGIC: u8 = 0xEF;
+
+/// Register whose value tells the chips apart
+const CHIP_DETECT_MAGIC_REG: u32 = 0x4000_1000;
+
+/// Bytes per FLASH_DATA block the ROM takes
+pub const FLASH_WRITE_SIZE: usize = 0x400;
+
+const FLASH_SECTOR_SIZE: usize = 0x1000;
+
+/// Largest flash chip the ROM can address
+pub const MAX_FLASH_SIZE: usize = 16 * 1024 * 1024;
+
+/// Wait for a response to commands with no size-dependent work
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Wait for each sync response; the ROM answers at once when it is listening
+const SYNC_TIMEOUT: Duration = Duration::from_millis(100);
+
+// Time the ROM may take per megabyte, as esptool allows
+const ERASE_REGION_TIMEOUT_PER_MB: f64 = 30.0;
+const ERASE_WRITE_TIMEOUT_PER_MB: f64 = 40.0;
+const MD5_TIMEOUT_PER_MB: f64 = 8.0;
+
+/// Attempts at each FLASH_DATA block
+const BLOCK_ATTEMPTS: u32 = 3;
+
+/// Bytes taken from the receive buffer per read
+const READ_CHUNK: usize = 1024;
+
+/// Why a loader command failed
+#[derive(Debug, Error)]
+pub enum EspLoaderError {
+    #[error("No response to {command} within {timeout_ms}ms")]
+    Timeout { command: &'static str, timeout_ms: u64 },
+
+    #[error("{command} failed: {reason}")]
+    Failed { command: &'static str, reason: String },
+
+    #[error("No response to sync; the chip is not in download mode or the baud rate is wrong")]
+    NoSync,
+
+    #[error("{0}")]
+    Protocol(String),
+
+    #[error(transparent)]
+    Serial(#[from] LocalSerialError),
+}
+
+/// Chip family, from the magic register value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chip {
+    Esp8266,
+    Esp32,
+    Esp32S2,
+    Esp32S3,
+    Esp32C2,
+    Esp32C3,
+    Esp32C6,
+    Esp32H2,
+    /// A chip this table does not know, treated like the newer ones
+    Unknown(u32),
+}
+
+impl Chip {
+    pub fn from_magic(magic: u32) -> Self {
+        match magic {
+            0xFFF0_C101 => Chip::Esp8266,
+            0x00F0_1D83 => Chip::Esp32,
+            0x0000_07C6 => Chip::Esp32S2,
+            0x0000_0009 => Chip::Esp32S3,
+            0x6F51_306F | 0x7C41_A06F => Chip::Esp32C2,
+            0x6921_506F | 0x1B31_506F | 0x4881_606F | 0x4361_606F => Chip::Esp32C3,
+            0x2CE0_806F => Chip::Esp32C6,
+            0xD7B7_3E80 => Chip::Esp32H2,
+            _ => Chip::Unknown(magic),
+        }
+    }
+
+    /// Bytes of status at the end of each ROM response
+    fn status_len(self) -> usize {
+        if self == Chip::Esp8266 { 2 } else { 4 }
+    }
+
+    /// Whether FLASH_BEGIN takes a fifth word saying whether to encrypt
+    fn flash_begin_encrypt_flag(self) -> bool {
+        !matches!(self, Chip::Esp8266 | Chip::Esp32)
+    }
+
+    /// Whether the ROM has to be told to attach the SPI flash first
+    fn needs_spi_attach(self) -> bool {
+        self != Chip::Esp8266
+    }
+
+    /// Whether the ROM can hash flash; the ESP8266's cannot
+    pub fn supports_md5(self) -> bool {
+        self != Chip::Esp8266
+    }
+}
+
+impl std::fmt::Display for Chip {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Chip::Esp8266 => write!(f, "ESP8266"),
+            Chip::Esp32 => write!(f, "ESP32"),
+            Chip::Esp32S2 => write!(f, "ESP32-S2"),
+            Chip::Esp32S3 => write!(f, "ESP32-S3"),
+            Chip::Esp32C2 => write!(f, "ESP32-C2"),
+            Chip::Esp32C3 => write!(f, "ESP32-C3"),
+            Chip::Esp32C6 => write!(f, "ESP32-C6"),
+            Chip::Esp32H2 => write!(f, "ESP32-H2"),
+            Chip::Unknown(magic) => write!(f, "unknown ESP chip (magic 0x{:08X})", magic),
+        }
+    }
+}
+
+/// Flash size in bytes from a name such as `4MB` or `512KB`
+pub fn parse_flash_size(value: &str) -> Result<u32, LocalSerialError> {
+    let upper = value.trim().to_uppercase();
+    let (digits, unit) = if let Some(digits) = upper.strip_suffix("MB") {
+        (digits, 1024 * 1024)
+    } else if let Some(digits) = upper.strip_suffix("KB") {
+        (digits, 1024)
+    } else {
+        return Err(LocalSerialError::InvalidConfig(format!("Flash size must be like 4MB or 512KB, got {}", value)));
+    };
+    match digits.trim().parse::<u32>() {
+        Ok(size) if size > 0 && (size * unit) as usize <= MAX_FLASH_SIZE && (size * unit).is_power_of_two() => Ok(size * unit),
+        _ => Err(LocalSerialError::InvalidConfig(format!("Unsupported flash size: {}", value))),
+    }
+}
+
+/// XOR checksum of a FLASH_DATA block
+fn checksum(data: &[u8]) -> u32 {
+    data.iter().fold(CHECKSUM_MAGIC, |sum, &byte| sum ^ byte) as u32
+}
+
+/// A command packet before SLIP framing
+fn command_packet(command: u8, data: &[u8], checksum: u32) -> Vec<u8> {
+    let mut packet = vec![DIRECTION_REQUEST, command];
+    packet.extend((data.len() as u16).to_le_bytes());
+    packet.extend(checksum.to_le_bytes());
+    packet.extend(data);
+    packet
+}
+
+/// Words of a command's data, least significant byte first
+fn words(values: &[u32]) -> Vec<u8> {
+    values.iter().flat_map(|value| value.to_le_bytes()).collect()
+}
+
+/// A FLASH_DATA command's data: the block, padded with erased bytes, after its size and number
+fn flash_data(seq: u32, block: &[u8]) -> (Vec<u8>, u32) {
+    let mut padded = block.to_vec();
+    padded.resize(FLASH_WRITE_SIZE, 0xFF);
+    let mut data = words(&[padded.len() as u32, seq, 0, 0]);
+    data.extend(&padded);
+    (data, checksum(&padded))
+}
+
+/// Bytes to erase for a write, working around the ESP8266 ROM erasing
+/// some sectors twice over when the region crosses a 64 KiB block
+fn esp8266_erase_size(offset: usize, size: usize) -> usize {
+    let sectors_per_block = 16;
+    let num_sectors = size.div_ceil(FLASH_SECTOR_SIZE);
+    let start_sector = offset / FLASH_SECTOR_SIZE;
+    let head_sectors = (sectors_per_block - start_sector % sectors_per_block).min(num_sectors);
+    if num_sectors < 2 * head_sectors {
+        num_sectors.div_ceil(2) * FLASH_SECTOR_SIZE
+    } else {
+        (num_sectors - head_sectors) * FLASH_SECTOR_SIZE
+    }
+}
+
+/// How long the ROM may take over `size` bytes at `seconds_per_mb`
+fn timeout_per_mb(seconds_per_mb: f64, size: usize) -> Duration {
+    Duration::from_secs_f64(seconds_per_mb * size as f64 / 1_000_000.0).max(DEFAULT_TIMEOUT)
+}
+
+/// What ROM error codes mean
+fn rom_error(code: u8) -> String {
+    match code {
+        0x05 => "the ROM found the message invalid".to_string(),
+        0x06 => "the ROM could not act on the message".to_string(),
+        0x07 => "invalid checksum".to_string(),
+        0x08 => "flash write error".to_string(),
+        0x09 => "flash read error".to_string(),
+        0x0A => "flash read length error".to_string(),
+        0x0B => "deflate error".to_string(),
+        _ => format!("error 0x{:02X}", code),
+    }
+}
+
+/// A response from the ROM
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Response {
+    command: u8,
+    value: u32,
+    data: Vec<u8>,
+}
+
+fn parse_response(frame: &[u8]) -> Option<Response> {
+    if frame.len() < 8 || frame[0] != DIRECTION_RESPONSE {
+        return None;
+    }
+    let size = u16::from_le_bytes([frame[2], frame[3]]) as usize;
+    let data = frame[8..].get(..size).unwrap_or(&frame[8..]).to_vec();
+    Some(Response { command: frame[1], value: u32::from_le_bytes([frame[4], frame[5], frame[6], frame[7]]), data })
+}
+
+/// A session with the ROM bootloader on a connection
+pub struct EspLoader<'a> {
+    connection: &'a SerialConnection,
+    framer: SlipFramer,
+    frames: VecDeque<Vec<u8>>,
+    chip: Option<Chip>,
+}
+
+impl<'a> EspLoader<'a> {
+    pub fn new(connection: &'a SerialConnection) -> Self {
+        Self { connection, framer: SlipFramer::new(), frames: VecDeque::new(), chip: None }
+    }
+
+    /// Chip found by [`EspLoader::detect_chip`]
+    pub fn chip(&self) -> Option<Chip> {
+        self.chip
+    }
+
+    /// Sync with the ROM, trying up to `attempts` times
+    ///
+    /// The ROM answers one sync with several responses; the extra ones are
+    /// read and discarded.
+    pub async fn sync(&mut self, attempts: u32) -> Result<(), EspLoaderError> {
+        let mut data = vec![0x07, 0x07, 0x12, 0x20];
+        data.extend([0x55; 32]);
+        for _ in 0..attempts {
+            self.discard_input().await;
+            match self.command(SYNC, "SYNC", &data, 0, SYNC_TIMEOUT).await {
+                Ok(_) => {
+                    let deadline = Instant::now() + SYNC_TIMEOUT;
+                    while self.receive(SYNC, deadline).await?.is_some() {}
+                    return Ok(());
+                }
+                Err(EspLoaderError::Timeout { .. } | EspLoaderError::Failed { .. }) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Err(EspLoaderError::NoSync)
+    }
+
+    pub async fn read_reg(&mut self, address: u32) -> Result<u32, EspLoaderError> {
+        Ok(self.command(READ_REG, "READ_REG", &words(&[address]), 0, DEFAULT_TIMEOUT).await?.value)
+    }
+
+    /// Identify the chip, which decides how later commands are sent
+    pub async fn detect_chip(&mut self) -> Result<Chip, EspLoaderError> {
+        let chip = Chip::from_magic(self.read_reg(CHIP_DETECT_MAGIC_REG).await?);
+        self.chip = Some(chip);
+        Ok(chip)
+    }
+
+    /// Prepare the flash for writing: attach it on chips that need it, and
+    /// tell the ROM its size when known
+    pub async fn attach_flash(&mut self, flash_size: Option<u32>) -> Result<(), EspLoaderError> {
+        let chip = self.chip.unwrap_or(Chip::Unknown(0));
+        if chip.needs_spi_attach() {
+            // Default SPI pins; the ROM also takes a legacy flag
+            self.command(SPI_ATTACH, "SPI_ATTACH", &words(&[0, 0]), 0, DEFAULT_TIMEOUT).await?;
+        }
+        if let Some(size) = flash_size {
+            // ID, total size, block, sector and page size, status mask
+            let params = words(&[0, size, 64 * 1024, FLASH_SECTOR_SIZE as u32, 256, 0xFFFF]);
+            self.command(SPI_SET_PARAMS, "SPI_SET_PARAMS", &params, 0, DEFAULT_TIMEOUT).await?;
+        }
+        Ok(())
+    }
+
+    /// Erase the region and write `image` at `offset`, telling `on_block` the bytes written so far
+    pub async fn write_flash(
+        &mut self,
+        offset: u32,
+        image: &[u8],
+        on_block: &mut (dyn FnMut(usize) + Send),
+    ) -> Result<(), EspLoaderError> {
+        let chip = self.chip.unwrap_or(Chip::Unknown(0));
+        let blocks = image.len().div_ceil(FLASH_WRITE_SIZE);
+        let erase_size = if chip == Chip::Esp8266 { esp8266_erase_size(offset as usize, image.len()) } else { image.len() };
+        let mut begin = words(&[erase_size as u32, blocks as u32, FLASH_WRITE_SIZE as u32, offset]);
+        if chip.flash_begin_encrypt_flag() {
+            begin.extend(0u32.to_le_bytes());
+        }
+        let timeout = timeout_per_mb(ERASE_REGION_TIMEOUT_PER_MB, erase_size);
+        self.command(FLASH_BEGIN, "FLASH_BEGIN", &begin, 0, timeout).await?;
+
+        let timeout = timeout_per_mb(ERASE_WRITE_TIMEOUT_PER_MB, FLASH_WRITE_SIZE);
+        let mut written = 0;
+        for (seq, block) in image.chunks(FLASH_WRITE_SIZE).enumerate() {
+            let (data, sum) = flash_data(seq as u32, block);
+            let mut attempt = 1;
+            loop {
+                match self.command(FLASH_DATA, "FLASH_DATA", &data, sum, timeout).await {
+                    Ok(_) => break,
+                    Err(EspLoaderError::Timeout { .. }) if attempt < BLOCK_ATTEMPTS => attempt += 1,
+                    Err(e) => return Err(e),
+                }
+            }
+            written += block.len();
+            on_block(written);
+        }
+        Ok(())
+    }
+
+    /// MD5 of `size` bytes of flash at `offset`, as lowercase hex
+    pub async fn flash_md5(&mut self, offset: u32, size: u32) -> Result<String, EspLoaderError> {
+        let timeout = timeout_per_mb(MD5_TIMEOUT_PER_MB, size as usize);
+        let response = self.command(SPI_FLASH_MD5, "SPI_FLASH_MD5", &words(&[offset, size, 0, 0]), 0, timeout).await?;
+        // The ROM answers in hex, esptool's stub in raw bytes
+        match response.data.len() {
+            32 => Ok(String::from_utf8_lossy(&response.data).to_lowercase()),
+            16 => Ok(response.data.iter().map(|byte| format!("{:02x}", byte)).collect()),
+            len => Err(EspLoaderError::Protocol(format!("SPI_FLASH_MD5 returned {} bytes", len))),
+        }
+    }
+
+    /// End the flash write, leaving the chip in the bootloader unless `reboot`
+    pub async fn flash_end(&mut self, reboot: bool) -> Result<(), EspLoaderError> {
+        self.command(FLASH_END, "FLASH_END", &words(&[u32::from(!reboot)]), 0, DEFAULT_TIMEOUT).await?;
+        Ok(())
+    }
+
+    /// Send a command and wait for its response, returning it with the status stripped
+    async fn command(&mut self, command: u8, name: &'static str, data: &[u8], checksum: u32, timeout: Duration) -> Result<Response, EspLoaderError> {
+        let packet = command_packet(command, data, checksum);
+        self.connection.write(&self.framer.encode(&packet)).await?;
+        let Some(mut response) = self.receive(command, Instant::now() + timeout).await? else {
+            return Err(EspLoaderError::Timeout { command: name, timeout_ms: timeout.as_millis() as u64 });
+        };
+
+        // Before the chip is known, a response with no payload is all status
+        let status_len = self.chip.map_or(response.data.len().min(4), Chip::status_len);
+        if status_len < 2 || response.data.len() < status_len {
+            return Err(EspLoaderError::Protocol(format!("Response to {} is too short", name)));
+        }
+        let status = response.data.split_off(response.data.len() - status_len);
+        if status[0] != 0 {
+            return Err(EspLoaderError::Failed { command: name, reason: rom_error(status[1]) });
+        }
+        Ok(response)
+    }
+
+    /// The next response to `command`, skipping others, `None` once `deadline` passes
+    async fn receive(&mut self, command: u8, deadline: Instant) -> Result<Option<Response>, EspLoaderError> {
+        loop {
+            while let Some(frame) = self.frames.pop_front() {
+                if let Some(response) = parse_response(&frame).filter(|response| response.command == command) {
+                    return Ok(Some(response));
+                }
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Ok(None);
+            }
+            let mut buffer = [0u8; READ_CHUNK];
+            match self.connection.read(&mut buffer, Some(remaining.as_millis().max(1) as u64)).await {
+                Ok(0) | Err(LocalSerialError::ReadTimeout) => return Ok(None),
+                // Boot messages and line noise come out as bad frames
+                Ok(n) => self.frames.extend(self.framer.decode(&buffer[..n]).into_iter().flatten()),
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// Drop anything received so far, such as the ROM's boot banner
+    async fn discard_input(&mut self) {
+        let mut buffer = [0u8; READ_CHUNK];
+        while let Ok(1..) = self.connection.read(&mut buffer, Some(0)).await {}
+        self.framer.reset();
+        self.frames.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_packets() {
+        // SYNC as esptool sends it
+        let mut data = vec![0x07, 0x07, 0x12, 0x20];
+        data.extend([0x55; 32]);
+        let packet = command_packet(SYNC, &data, 0);
+        assert_eq!(&packet[..8], &[0x00, 0x08, 0x24, 0x00, 0, 0, 0, 0]);
+        assert_eq!(packet.len(), 8 + 36);
+
+        let (data, sum) = flash_data(2, &[0x01, 0x02]);
+        assert_eq!(&data[..16], &[0x00, 0x04, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(data.len(), 16 + FLASH_WRITE_SIZE);
+        assert!(data[18..].iter().all(|&b| b == 0xFF));
+        // 0xFF pads an even number of times, so they cancel out
+        assert_eq!(sum, (0xEF ^ 0x01 ^ 0x02) as u32);
+
+        let response = parse_response(&[0x01, 0x0A, 0x04, 0x00, 0x83, 0x1D, 0xF0, 0x00, 0, 0, 0, 0]).unwrap();
+        assert_eq!(response, Response { command: READ_REG, value: 0x00F0_1D83, data: vec![0, 0, 0, 0] });
+        assert_eq!(Chip::from_magic(response.value), Chip::Esp32);
+        assert_eq!(parse_response(&[0x00, 0x0A, 0, 0, 0, 0, 0, 0]), None);
+    }
+
+    #[test]
+    fn test_sizes() {
+        assert_eq!(parse_flash_size("4MB").unwrap(), 4 * 1024 * 1024);
+        assert_eq!(parse_flash_size("512kb").unwrap(), 512 * 1024);
+        assert!(parse_flash_size("3MB").is_err());
+        assert!(parse_flash_size("4").is_err());
+
+        // Within one 64 KiB block the ROM erases twice what it is asked to
+        assert_eq!(esp8266_erase_size(0, 0x3000), 0x2000);
+        assert_eq!(esp8266_erase_size(0x1000, 0x40000), (64 - 15) * FLASH_SECTOR_SIZE);
+
+        assert_eq!(timeout_per_mb(ERASE_REGION_TIMEOUT_PER_MB, 1024), DEFAULT_TIMEOUT);
+        assert_eq!(timeout_per_mb(ERASE_REGION_TIMEOUT_PER_MB, 4_000_000), Duration::from_secs(120));
+    }
+}
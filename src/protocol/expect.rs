@@ -0,0 +1,144 @@
+//! Waiting for text from a device
+//!
+//! Login prompts, bootloaders and menus are driven by waiting for a piece of
+//! text and answering it, as chat(8) and pexpect do. An [`Expecter`] keeps
+//! what arrived after the last match, so the next wait starts from there
+//! rather than from whatever the next read returns.
+
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::serial::{LocalSerialError, SerialConnection};
+
+/// Most unmatched text kept; older text is dropped first
+pub const EXPECT_MAX_PENDING: usize = 64 * 1024;
+
+/// Text received from a connection and not yet matched
+#[derive(Debug, Default)]
+pub struct Expecter {
+    pending: String,
+}
+
+impl Expecter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Text received since the last match
+    pub fn pending(&self) -> &str {
+        &self.pending
+    }
+
+    /// Add received text, dropping the oldest beyond [`EXPECT_MAX_PENDING`]
+    pub fn push(&mut self, text: &str) {
+        self.pending.push_str(text);
+        if self.pending.len() > EXPECT_MAX_PENDING {
+            let mut cut = self.pending.len() - EXPECT_MAX_PENDING;
+            while !self.pending.is_char_boundary(cut) {
+                cut += 1;
+            }
+            self.pending.drain(..cut);
+        }
+    }
+
+    /// The pending text up to and including the first `pattern`, removed from it
+    pub fn take_through(&mut self, pattern: &str) -> Option<String> {
+        self.take_through_any(&[pattern]).map(|(_, text)| text)
+    }
+
+    /// Like [`Expecter::take_through`] for whichever of `patterns` comes first, with its index
+    pub fn take_through_any(&mut self, patterns: &[&str]) -> Option<(usize, String)> {
+        let (start, index) = patterns
+            .iter()
+            .enumerate()
+            .filter_map(|(index, pattern)| self.pending.find(pattern).map(|start| (start, index)))
+            .min()?;
+        let end = start + patterns[index].len();
+        Some((index, self.pending.drain(..end).collect()))
+    }
+
+    /// All the pending text, leaving none
+    pub fn take_pending(&mut self) -> String {
+        std::mem::take(&mut self.pending)
+    }
+
+    /// Wait up to `timeout_ms` for `pattern`, returning the text up to and including it
+    ///
+    /// `None` means the time ran out or reception is paused; what arrived in
+    /// the meantime stays pending.
+    pub async fn expect(&mut self, connection: &SerialConnection, pattern: &str, timeout_ms: u64) -> Result<Option<String>, LocalSerialError> {
+        Ok(self.expect_any(connection, &[pattern], timeout_ms).await?.map(|(_, text)| text))
+    }
+
+    /// Like [`Expecter::expect`] for whichever of `patterns` arrives first, with its index
+    pub async fn expect_any(
+        &mut self,
+        connection: &SerialConnection,
+        patterns: &[&str],
+        timeout_ms: u64,
+    ) -> Result<Option<(usize, String)>, LocalSerialError> {
+        if let Some(matched) = self.take_through_any(patterns) {
+            return Ok(Some(matched));
+        }
+        let deadline = Instant::now() + Duration::from_millis(timeout_ms);
+        let mut matched = None;
+        connection
+            .read_until(deadline, |chunk| {
+                self.push(&String::from_utf8_lossy(chunk));
+                matched = self.take_through_any(patterns);
+                matched.is_some()
+            })
+            .await?;
+        Ok(matched)
+    }
+
+    /// The pending text and whatever arrives within `timeout_ms`
+    ///
+    /// Returns as soon as anything is available, so an empty result means
+    /// nothing arrived in time.
+    pub async fn read(&mut self, connection: &SerialConnection, timeout_ms: u64) -> Result<String, LocalSerialError> {
+        if self.pending.is_empty() {
+            let deadline = Instant::now() + Duration::from_millis(timeout_ms);
+            connection
+                .read_until(deadline, |chunk| {
+                    self.push(&String::from_utf8_lossy(chunk));
+                    true
+                })
+                .await?;
+        }
+        Ok(self.take_pending())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_take_through() {
+        let mut expecter = Expecter::new();
+        expecter.push("U-Boot 2024.01\r\nHit any key to stop autoboot:  3");
+        assert_eq!(expecter.take_through("login:"), None);
+        assert_eq!(expecter.take_through("autoboot:").unwrap(), "U-Boot 2024.01\r\nHit any key to stop autoboot:");
+        assert_eq!(expecter.pending(), "  3");
+        expecter.push(" 2\r\n=> ");
+        assert_eq!(expecter.take_through("=> ").unwrap(), "  3 2\r\n=> ");
+        assert_eq!(expecter.take_pending(), "");
+
+        // The earliest match wins, whichever pattern it is
+        expecter.push("AT+CPIN?\r\r\n+CME ERROR: 10\r\nOK\r\n");
+        assert_eq!(expecter.take_through_any(&["OK", "ERROR"]).unwrap(), (1, "AT+CPIN?\r\r\n+CME ERROR".to_string()));
+        assert_eq!(expecter.take_through_any(&["OK", "ERROR"]).unwrap().0, 0);
+        assert_eq!(expecter.take_through_any(&["OK", "ERROR"]), None);
+    }
+
+    #[test]
+    fn test_pending_is_bounded() {
+        let mut expecter = Expecter::new();
+        expecter.push(&"é".repeat(EXPECT_MAX_PENDING));
+        expecter.push("end");
+        assert!(expecter.pending().len() <= EXPECT_MAX_PENDING);
+        assert!(expecter.pending().ends_with("éend"));
+    }
+}
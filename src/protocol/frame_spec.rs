@@ -0,0 +1,360 @@
+//! Declaratively described frames
+//!
+//! Many vendor protocols are a constant header, an optional length field, the
+//! payload, a checksum over some of those parts and an optional trailer. A
+//! [`FrameSpec`] describes such a layout so frames can be built and checked
+//! without code for each protocol.
+
+use thiserror::Error;
+
+use super::length::ByteOrder;
+use crate::utils::Checksum;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum FrameSpecError {
+    #[error("Invalid frame spec: {0}")]
+    Spec(String),
+
+    #[error("Payload of {0} bytes does not fit the length field")]
+    TooLong(usize),
+
+    #[error("Frame header not found")]
+    NoHeader,
+
+    #[error("Frame cut short: {0}")]
+    Truncated(String),
+
+    #[error("Frame trailer does not match")]
+    Trailer,
+}
+
+/// Consecutive parts of a frame a checksum can cover
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FramePart {
+    Header,
+    Length,
+    Payload,
+}
+
+impl FramePart {
+    pub fn parse(value: &str) -> Result<Self, FrameSpecError> {
+        match value.to_lowercase().as_str() {
+            "header" => Ok(FramePart::Header),
+            "length" => Ok(FramePart::Length),
+            "payload" => Ok(FramePart::Payload),
+            _ => Err(FrameSpecError::Spec(format!("Unknown frame part {}, expected header, length or payload", value))),
+        }
+    }
+}
+
+/// What a length field counts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LengthCounts {
+    Payload,
+    /// The payload and the checksum after it
+    PayloadChecksum,
+    /// Every byte of the frame, header to trailer
+    Frame,
+}
+
+impl LengthCounts {
+    pub fn parse(value: &str) -> Result<Self, FrameSpecError> {
+        match value.to_lowercase().as_str() {
+            "payload" => Ok(LengthCounts::Payload),
+            "payload_checksum" => Ok(LengthCounts::PayloadChecksum),
+            "frame" => Ok(LengthCounts::Frame),
+            _ => Err(FrameSpecError::Spec(format!("Unknown length count {}, expected payload, payload_checksum or frame", value))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LengthField {
+    /// 1, 2 or 4
+    pub bytes: usize,
+    pub byte_order: ByteOrder,
+    pub counts: LengthCounts,
+    /// Added to the counted length to give the field's value
+    pub adjust: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChecksumField {
+    pub algorithm: Checksum,
+    pub byte_order: ByteOrder,
+    /// First and last part covered, inclusive
+    pub from: FramePart,
+    pub to: FramePart,
+}
+
+impl ChecksumField {
+    fn bytes(&self) -> usize {
+        self.algorithm.width().div_ceil(8) as usize
+    }
+}
+
+/// Layout: header, length field, payload, checksum, trailer; every part but the payload optional
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameSpec {
+    header: Vec<u8>,
+    length: Option<LengthField>,
+    checksum: Option<ChecksumField>,
+    trailer: Vec<u8>,
+}
+
+/// A frame taken apart by [`FrameSpec::parse`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsedFrame {
+    pub payload: Vec<u8>,
+    /// Value of the length field, if the spec has one
+    pub length_field: Option<u64>,
+    /// Received and computed checksum, if the spec has one
+    pub checksum: Option<(u64, u64)>,
+    /// Bytes the whole frame took
+    pub frame_len: usize,
+}
+
+impl ParsedFrame {
+    pub fn checksum_ok(&self) -> bool {
+        self.checksum.is_none_or(|(received, computed)| received == computed)
+    }
+}
+
+impl FrameSpec {
+    pub fn new(
+        header: Vec<u8>,
+        length: Option<LengthField>,
+        checksum: Option<ChecksumField>,
+        trailer: Vec<u8>,
+    ) -> Result<Self, FrameSpecError> {
+        if let Some(length) = &length {
+            if !matches!(length.bytes, 1 | 2 | 4) {
+                return Err(FrameSpecError::Spec(format!("Length field must be 1, 2 or 4 bytes, not {}", length.bytes)));
+            }
+        }
+        if let Some(checksum) = &checksum {
+            if checksum.from > checksum.to {
+                return Err(FrameSpecError::Spec("Checksum coverage must run forwards, header to payload".to_string()));
+            }
+            if checksum.bytes() > 8 {
+                return Err(FrameSpecError::Spec("Checksums are limited to 64 bits".to_string()));
+            }
+        }
+        Ok(Self { header, length, checksum, trailer })
+    }
+
+    /// The complete frame carrying `payload`
+    pub fn build(&self, payload: &[u8]) -> Result<Vec<u8>, FrameSpecError> {
+        let mut frame = self.header.clone();
+        if let Some(length) = &self.length {
+            let value = i64::try_from(self.counted_length(length, payload.len()))
+                .ok()
+                .and_then(|counted| counted.checked_add(length.adjust))
+                .and_then(|value| u64::try_from(value).ok())
+                .filter(|value| *value < 1u64 << (length.bytes * 8))
+                .ok_or(FrameSpecError::TooLong(payload.len()))?;
+            frame.extend(length.byte_order.write_uint(value, length.bytes));
+        }
+        frame.extend_from_slice(payload);
+        if let Some(checksum) = &self.checksum {
+            let value = checksum.algorithm.compute(&frame[self.covered(checksum, payload.len())]);
+            frame.extend(checksum.byte_order.write_uint(value, checksum.bytes()));
+        }
+        frame.extend_from_slice(&self.trailer);
+        Ok(frame)
+    }
+
+    /// Take apart the frame at the start of `data`
+    ///
+    /// A checksum mismatch is reported in the result rather than as an error,
+    /// so the payload can still be seen.
+    pub fn parse(&self, data: &[u8]) -> Result<ParsedFrame, FrameSpecError> {
+        if !data.starts_with(&self.header) {
+            return Err(FrameSpecError::NoHeader);
+        }
+        let mut offset = self.header.len();
+
+        let length_field = match &self.length {
+            Some(length) => {
+                let field = data
+                    .get(offset..offset + length.bytes)
+                    .ok_or_else(|| FrameSpecError::Truncated("no length field".to_string()))?;
+                offset += length.bytes;
+                Some((length.byte_order.read_uint(field), length))
+            }
+            None => None,
+        };
+        let checksum_bytes = self.checksum.as_ref().map_or(0, ChecksumField::bytes);
+
+        let payload_len = match length_field {
+            Some((value, length)) => {
+                let counted = i128::from(value) - i128::from(length.adjust);
+                let overhead = match length.counts {
+                    LengthCounts::Payload => 0,
+                    LengthCounts::PayloadChecksum => checksum_bytes,
+                    LengthCounts::Frame => offset + checksum_bytes + self.trailer.len(),
+                };
+                usize::try_from(counted - overhead as i128)
+                    .map_err(|_| FrameSpecError::Truncated(format!("length field {} is shorter than the frame around the payload", value)))?
+            }
+            // Without a length the payload runs to the first trailer, or to the end of the data
+            None => {
+                let rest = &data[offset..];
+                let end = if self.trailer.is_empty() {
+                    rest.len()
+                } else {
+                    // The trailer comes no sooner than there is room for the checksum
+                    let skip = checksum_bytes.min(rest.len());
+                    find(&rest[skip..], &self.trailer)
+                        .map(|position| position + skip)
+                        .ok_or_else(|| FrameSpecError::Truncated("no trailer".to_string()))?
+                };
+                end.checked_sub(checksum_bytes)
+                    .ok_or_else(|| FrameSpecError::Truncated("no room for the checksum".to_string()))?
+            }
+        };
+
+        let frame_len = offset + payload_len + checksum_bytes + self.trailer.len();
+        if data.len() < frame_len {
+            return Err(FrameSpecError::Truncated(format!("{} of {} bytes", data.len(), frame_len)));
+        }
+        let payload = data[offset..offset + payload_len].to_vec();
+        offset += payload_len;
+
+        let checksum = self.checksum.as_ref().map(|checksum| {
+            let received = checksum.byte_order.read_uint(&data[offset..offset + checksum_bytes]);
+            (received, checksum.algorithm.compute(&data[self.covered(checksum, payload_len)]))
+        });
+        offset += checksum_bytes;
+
+        if data[offset..frame_len] != self.trailer[..] {
+            return Err(FrameSpecError::Trailer);
+        }
+        Ok(ParsedFrame {
+            payload,
+            length_field: length_field.map(|(value, _)| value),
+            checksum,
+            frame_len,
+        })
+    }
+
+    /// Every frame in `data`, resynchronising on the header after a bad one
+    ///
+    /// Each entry carries the offset the frame, or the failed attempt, started at.
+    pub fn scan(&self, data: &[u8]) -> Vec<(usize, Result<ParsedFrame, FrameSpecError>)> {
+        let mut results = Vec::new();
+        let mut offset = 0;
+        while offset < data.len() {
+            let start = if self.header.is_empty() {
+                offset
+            } else {
+                match find(&data[offset..], &self.header) {
+                    Some(position) => offset + position,
+                    None => break,
+                }
+            };
+            let result = self.parse(&data[start..]);
+            offset = match &result {
+                Ok(frame) => start + frame.frame_len.max(1),
+                // Without a header there is nothing to resynchronise on
+                Err(_) if self.header.is_empty() => data.len(),
+                Err(_) => start + 1,
+            };
+            results.push((start, result));
+        }
+        results
+    }
+
+    fn counted_length(&self, length: &LengthField, payload_len: usize) -> usize {
+        let checksum_bytes = self.checksum.as_ref().map_or(0, ChecksumField::bytes);
+        match length.counts {
+            LengthCounts::Payload => payload_len,
+            LengthCounts::PayloadChecksum => payload_len + checksum_bytes,
+            LengthCounts::Frame => self.header.len() + length.bytes + payload_len + checksum_bytes + self.trailer.len(),
+        }
+    }
+
+    /// Byte range of the frame a checksum covers
+    fn covered(&self, checksum: &ChecksumField, payload_len: usize) -> std::ops::Range<usize> {
+        let length_bytes = self.length.as_ref().map_or(0, |length| length.bytes);
+        let start = |part: FramePart| match part {
+            FramePart::Header => 0,
+            FramePart::Length => self.header.len(),
+            FramePart::Payload => self.header.len() + length_bytes,
+        };
+        let end = match checksum.to {
+            FramePart::Header => self.header.len(),
+            FramePart::Length => self.header.len() + length_bytes,
+            FramePart::Payload => self.header.len() + length_bytes + payload_len,
+        };
+        start(checksum.from)..end
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::CrcParams;
+
+    /// AA 55, a 1 byte length of the payload, the payload, a little-endian
+    /// Modbus CRC over length and payload, then 0D
+    fn spec() -> FrameSpec {
+        let length = LengthField { bytes: 1, byte_order: ByteOrder::Big, counts: LengthCounts::Payload, adjust: 0 };
+        let checksum = ChecksumField {
+            algorithm: Checksum::Crc(CrcParams::CRC16_MODBUS),
+            byte_order: ByteOrder::Little,
+            from: FramePart::Length,
+            to: FramePart::Payload,
+        };
+        FrameSpec::new(vec![0xAA, 0x55], Some(length), Some(checksum), vec![0x0D]).unwrap()
+    }
+
+    #[test]
+    fn test_build_and_parse() {
+        let frame = spec().build(&[0x01, 0x02]).unwrap();
+        let crc = crate::utils::BufferUtils::crc16_modbus(&[0x02, 0x01, 0x02]).to_le_bytes();
+        assert_eq!(frame, [0xAA, 0x55, 0x02, 0x01, 0x02, crc[0], crc[1], 0x0D]);
+
+        let parsed = spec().parse(&frame).unwrap();
+        assert_eq!((parsed.payload.as_slice(), parsed.length_field, parsed.frame_len), (&[0x01, 0x02][..], Some(2), 8));
+        assert!(parsed.checksum_ok());
+
+        let mut corrupt = frame.clone();
+        corrupt[3] ^= 0xFF;
+        assert!(!spec().parse(&corrupt).unwrap().checksum_ok());
+        assert_eq!(spec().parse(&frame[..6]), Err(FrameSpecError::Truncated("6 of 8 bytes".to_string())));
+        assert!(spec().build(&[0; 256]).is_err());
+    }
+
+    #[test]
+    fn test_scan_resynchronises() {
+        let mut data = vec![0x00, 0xAA, 0x55, 0x09];
+        data.extend(spec().build(b"hi").unwrap());
+        data.extend(spec().build(b"").unwrap());
+
+        let frames = spec().scan(&data);
+        assert_eq!(frames.len(), 3);
+        assert!(frames[0].1.is_err());
+        assert_eq!((frames[1].0, frames[1].1.as_ref().unwrap().payload.as_slice()), (4, &b"hi"[..]));
+        assert!(frames[2].1.as_ref().unwrap().payload.is_empty());
+    }
+
+    #[test]
+    fn test_length_counting_frame_and_trailer_delimited() {
+        let length = LengthField { bytes: 2, byte_order: ByteOrder::Little, counts: LengthCounts::Frame, adjust: -1 };
+        let spec = FrameSpec::new(vec![0x02], Some(length), None, vec![]).unwrap();
+        assert_eq!(spec.build(b"ab").unwrap(), [0x02, 0x04, 0x00, b'a', b'b']);
+        assert_eq!(spec.parse(&[0x02, 0x04, 0x00, b'a', b'b']).unwrap().payload, b"ab");
+
+        let checksum = ChecksumField { algorithm: Checksum::Xor8, byte_order: ByteOrder::Big, from: FramePart::Payload, to: FramePart::Payload };
+        let spec = FrameSpec::new(vec![0x02], None, Some(checksum), vec![0x03]).unwrap();
+        let frame = spec.build(b"ok").unwrap();
+        assert_eq!(frame, [0x02, b'o', b'k', b'o' ^ b'k', 0x03]);
+        assert_eq!(spec.parse(&frame).unwrap().payload, b"ok");
+        assert!(FrameSpec::new(vec![], None, Some(ChecksumField { from: FramePart::Payload, to: FramePart::Header, ..checksum }), vec![]).is_err());
+    }
+}
@@ -0,0 +1,324 @@
+//! The framer trait and framer selection
+
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::checksummed::ChecksumFramer;
+use super::cobs::CobsFramer;
+use super::delimiter::DelimiterFramer;
+use super::hdlc::HdlcFramer;
+use super::length::{ByteOrder, LengthPrefixConfig, LengthPrefixedFramer};
+use super::slip::SlipFramer;
+use super::varint::VarintFramer;
+use crate::serial::LocalSerialError;
+use crate::utils::Checksum;
+
+/// Why a received frame could not be decoded
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum FrameError {
+    #[error("Malformed frame: {0}")]
+    Malformed(String),
+
+    #[error("Frame exceeds {0} bytes")]
+    TooLong(usize),
+}
+
+/// Converts between frame payloads and their encoding on the wire
+///
+/// Decoding is incremental: received bytes are fed in as they arrive, bytes
+/// of an unfinished frame are kept until the rest arrives.
+pub trait Framer: Send + Sync + std::fmt::Debug {
+    /// Name the framer is selected by
+    fn name(&self) -> &'static str;
+
+    /// Wrap one frame's payload for sending
+    fn encode(&self, payload: &[u8]) -> Vec<u8>;
+
+    /// Consume received bytes, returning the frames they complete in order
+    ///
+    /// A malformed frame is returned as an error and decoding carries on with
+    /// the next one.
+    fn decode(&mut self, data: &[u8]) -> Vec<Result<Vec<u8>, FrameError>>;
+
+    /// Bytes held of an unfinished frame
+    fn pending(&self) -> usize;
+
+    /// Discard any unfinished frame
+    fn reset(&mut self);
+
+    /// Largest payload one frame can carry, if the encoding limits it
+    fn max_payload(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// Passes bytes through unchanged; every received chunk is a frame
+#[derive(Debug, Default)]
+pub struct RawFramer;
+
+impl Framer for RawFramer {
+    fn name(&self) -> &'static str {
+        "raw"
+    }
+
+    fn encode(&self, payload: &[u8]) -> Vec<u8> {
+        payload.to_vec()
+    }
+
+    fn decode(&mut self, data: &[u8]) -> Vec<Result<Vec<u8>, FrameError>> {
+        if data.is_empty() {
+            Vec::new()
+        } else {
+            vec![Ok(data.to_vec())]
+        }
+    }
+
+    fn pending(&self) -> usize {
+        0
+    }
+
+    fn reset(&mut self) {}
+}
+
+/// Framers that can be selected for a connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FramerKind {
+    Raw,
+    /// RFC 1055 SLIP
+    Slip,
+    /// Consistent Overhead Byte Stuffing, zero delimited
+    Cobs,
+    /// HDLC-style flags and escaping with a CRC-16 FCS
+    Hdlc,
+    /// Payload behind a length field and optional header magic
+    Length,
+    /// Payload behind a protobuf-style varint length
+    Varint,
+    /// Payload followed by a fixed byte sequence
+    Delimiter,
+}
+
+impl FramerKind {
+    pub fn parse(value: &str) -> Result<Self, LocalSerialError> {
+        match value.to_lowercase().as_str() {
+            "raw" => Ok(FramerKind::Raw),
+            "slip" => Ok(FramerKind::Slip),
+            "cobs" => Ok(FramerKind::Cobs),
+            "hdlc" => Ok(FramerKind::Hdlc),
+            "length" => Ok(FramerKind::Length),
+            "varint" => Ok(FramerKind::Varint),
+            "delimiter" => Ok(FramerKind::Delimiter),
+            _ => Err(LocalSerialError::InvalidConfig(format!("Unknown framer: {}", value))),
+        }
+    }
+
+    /// A fresh framer of this kind, with the default layout where it has options
+    pub fn build(&self) -> Box<dyn Framer> {
+        match self {
+            FramerKind::Raw => Box::new(RawFramer),
+            FramerKind::Slip => Box::new(SlipFramer::new()),
+            FramerKind::Cobs => Box::new(CobsFramer::new()),
+            FramerKind::Hdlc => Box::new(HdlcFramer::new()),
+            FramerKind::Length => Box::new(LengthPrefixedFramer::default()),
+            FramerKind::Varint => Box::new(VarintFramer::new()),
+            FramerKind::Delimiter => Box::new(DelimiterFramer::default()),
+        }
+    }
+}
+
+impl std::fmt::Display for FramerKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FramerKind::Raw => write!(f, "raw"),
+            FramerKind::Slip => write!(f, "slip"),
+            FramerKind::Cobs => write!(f, "cobs"),
+            FramerKind::Hdlc => write!(f, "hdlc"),
+            FramerKind::Length => write!(f, "length"),
+            FramerKind::Varint => write!(f, "varint"),
+            FramerKind::Delimiter => write!(f, "delimiter"),
+        }
+    }
+}
+
+/// A framer plus the decoded frames not yet handed out
+#[derive(Debug)]
+pub struct FrameDecoder {
+    framer: Box<dyn Framer>,
+    ready: VecDeque<Result<Vec<u8>, FrameError>>,
+}
+
+impl FrameDecoder {
+    pub fn new(framer: Box<dyn Framer>) -> Self {
+        Self {
+            framer,
+            ready: VecDeque::new(),
+        }
+    }
+
+    pub fn framer(&self) -> &dyn Framer {
+        self.framer.as_ref()
+    }
+
+    /// Feed received bytes
+    pub fn push(&mut self, data: &[u8]) {
+        self.ready.extend(self.framer.decode(data));
+    }
+
+    /// Take up to `max_frames` decoded frames, oldest first
+    pub fn take(&mut self, max_frames: usize) -> Vec<Result<Vec<u8>, FrameError>> {
+        let count = max_frames.min(self.ready.len());
+        self.ready.drain(..count).collect()
+    }
+
+    pub fn has_frames(&self) -> bool {
+        !self.ready.is_empty()
+    }
+}
+
+/// Options of a framer, from set_framer or a configured protocol
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct FramerOptions<'a> {
+    pub length_bytes: Option<usize>,
+    pub byte_order: Option<&'a str>,
+    pub magic: Option<&'a str>,
+    pub delimiter: Option<&'a str>,
+    pub checksum: Option<&'a str>,
+    pub checksum_byte_order: Option<&'a str>,
+}
+
+/// A framer as set_framer was asked for it, kept to set it up again on a reopened connection
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FramerSpec {
+    /// Framer name, such as "cobs" or "length"
+    pub framer: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub length_bytes: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub byte_order: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub magic: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delimiter: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum_byte_order: Option<String>,
+}
+
+impl FramerSpec {
+    pub fn options(&self) -> FramerOptions<'_> {
+        FramerOptions {
+            length_bytes: self.length_bytes,
+            byte_order: self.byte_order.as_deref(),
+            magic: self.magic.as_deref(),
+            delimiter: self.delimiter.as_deref(),
+            checksum: self.checksum.as_deref(),
+            checksum_byte_order: self.checksum_byte_order.as_deref(),
+        }
+    }
+
+    /// The framer this describes, and how to describe it to the client
+    pub fn build(&self) -> Result<(Box<dyn Framer>, String), String> {
+        let kind = FramerKind::parse(&self.framer).map_err(|e| e.to_string())?;
+        build_framer(kind, &self.options())
+    }
+}
+
+/// A framer of `kind` with `options`, and how to describe it to the client
+pub fn build_framer(kind: FramerKind, options: &FramerOptions<'_>) -> Result<(Box<dyn Framer>, String), String> {
+    let length_options = options.length_bytes.is_some() || options.byte_order.is_some() || options.magic.is_some();
+    let (framer, mut name): (Box<dyn Framer>, String) = match kind {
+        FramerKind::Length => {
+            let config = length_prefix_config(options)?;
+            let name = format!("length ({})", config);
+            (Box::new(LengthPrefixedFramer::new(config)), name)
+        }
+        _ if length_options => return Err("length_bytes, byte_order and magic only apply to the length framer".to_string()),
+        FramerKind::Delimiter => {
+            let framer = match options.delimiter {
+                Some(delimiter) => {
+                    let delimiter = decode_hex(delimiter).map_err(|e| format!("Invalid delimiter - {}", e))?;
+                    DelimiterFramer::new(delimiter).ok_or("The delimiter must not be empty")?
+                }
+                None => DelimiterFramer::default(),
+            };
+            let name = format!("delimiter ({})", hex::encode_upper(framer.delimiter()));
+            (Box::new(framer), name)
+        }
+        _ if options.delimiter.is_some() => return Err("delimiter only applies to the delimiter framer".to_string()),
+        kind => (kind.build(), kind.to_string()),
+    };
+
+    let Some(checksum_name) = options.checksum else {
+        if options.checksum_byte_order.is_some() {
+            return Err("checksum_byte_order needs a checksum".to_string());
+        }
+        return Ok((framer, name));
+    };
+    let checksum = Checksum::named(checksum_name)
+        .ok_or_else(|| format!("Unknown checksum {}, expected one of {}", checksum_name, Checksum::NAMES.join(", ")))?;
+    let byte_order = match options.checksum_byte_order {
+        Some(order) => ByteOrder::parse(order).map_err(|e| e.to_string())?,
+        None => ByteOrder::Big,
+    };
+    name.push_str(&format!(", {} checksum {}", byte_order, checksum_name));
+    Ok((Box::new(ChecksumFramer::new(framer, checksum, byte_order)), name))
+}
+
+/// Header layout of the length framer from set_framer's options
+fn length_prefix_config(options: &FramerOptions<'_>) -> Result<LengthPrefixConfig, String> {
+    let byte_order = match options.byte_order {
+        Some(order) => ByteOrder::parse(order).map_err(|e| e.to_string())?,
+        None => ByteOrder::Big,
+    };
+    let magic = match options.magic {
+        Some(magic) => decode_hex(magic).map_err(|e| format!("Invalid magic - {}", e))?,
+        None => Vec::new(),
+    };
+    LengthPrefixConfig::new(options.length_bytes.unwrap_or(2), byte_order, magic).map_err(|e| e.to_string())
+}
+
+/// Bytes given as hex, spaces allowed between them
+fn decode_hex(text: &str) -> Result<Vec<u8>, String> {
+    hex::decode(text.replace(' ', "")).map_err(|e| format!("Hex decoding error: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_framer_kind_parse() {
+        assert_eq!(FramerKind::parse("RAW").unwrap(), FramerKind::Raw);
+        assert!(FramerKind::parse("morse").is_err());
+        assert_eq!(FramerKind::Raw.to_string(), "raw");
+        assert_eq!(FramerKind::Raw.build().name(), "raw");
+    }
+
+    #[test]
+    fn test_decoder_hands_out_frames_in_order() {
+        let mut decoder = FrameDecoder::new(FramerKind::Raw.build());
+        decoder.push(b"one");
+        decoder.push(b"");
+        decoder.push(b"two");
+        assert!(decoder.has_frames());
+
+        assert_eq!(decoder.take(1), vec![Ok(b"one".to_vec())]);
+        assert_eq!(decoder.take(10), vec![Ok(b"two".to_vec())]);
+        assert!(!decoder.has_frames());
+    }
+
+    #[test]
+    fn test_framer_spec() {
+        let spec: FramerSpec = serde_json::from_str(r#"{"framer": "length", "length_bytes": 1, "magic": "AA"}"#).unwrap();
+        let (framer, name) = spec.build().unwrap();
+        assert_eq!(framer.encode(b"\x01\x02"), [0xAA, 0x02, 0x01, 0x02]);
+        assert_eq!(name, "length (1-byte big-endian length, magic AA)");
+        assert_eq!(serde_json::to_string(&spec).unwrap(), r#"{"framer":"length","length_bytes":1,"magic":"AA"}"#);
+
+        let bad = FramerSpec { delimiter: Some("03".to_string()), ..serde_json::from_str(r#"{"framer": "slip"}"#).unwrap() };
+        assert!(bad.build().is_err());
+    }
+}
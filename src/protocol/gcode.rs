@@ -0,0 +1,442 @@
+//! G-code streaming
+//!
+//! Sends G-code programs to 3D printers and CNC controllers one line at a
+//! time, waiting for each line's `ok` before sending the next so the
+//! firmware's command buffer never overflows. With line numbers on, lines go
+//! out Marlin style as `N<n> <line>*<checksum>` and resend requests after
+//! transmission errors are honoured. GRBL's `error:N` rejects a line; Marlin's
+//! `Error:` is followed by an `ok` or a resend request, unless it reports that
+//! the printer has halted, which ends the job at once.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use thiserror::Error;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+use super::marlin::{fatal_alert, parse_temperatures, Temperatures};
+use crate::serial::{LocalSerialError, SerialConnection};
+
+/// Bytes taken from the receive buffer per read while waiting for replies
+const GCODE_READ_CHUNK: usize = 256;
+
+/// How often a paused connection is checked for replies
+const PAUSED_POLL: Duration = Duration::from_millis(50);
+
+/// Why a single command to the firmware failed
+#[derive(Debug, Error)]
+pub enum FirmwareError {
+    #[error("Firmware returned {0}")]
+    Rejected(String),
+
+    #[error("No answer within {timeout_ms}ms")]
+    Timeout { timeout_ms: u64 },
+
+    #[error("Another exchange, such as a G-code job, is using the connection")]
+    Busy,
+
+    #[error(transparent)]
+    Serial(#[from] LocalSerialError),
+}
+
+/// Send one command line outside a job and collect every reply up to and including its `ok`
+///
+/// Fails rather than waiting while another exchange, such as a job, is running.
+pub async fn command(connection: &SerialConnection, line: &str, timeout_ms: u64) -> Result<Vec<String>, FirmwareError> {
+    let _exchange = connection.try_begin_exchange().ok_or(FirmwareError::Busy)?;
+    connection.write(format!("{}\n", line).as_bytes()).await?;
+
+    let mut deadline = Instant::now() + Duration::from_millis(timeout_ms);
+    let mut replies = ReplyReader::default();
+    let mut output = Vec::new();
+    let mut fault = None;
+    while let Some(reply) = replies.next_line(connection, deadline).await? {
+        match parse_reply(&reply) {
+            GcodeReply::Ok => {
+                return match fault {
+                    Some(fault) => Err(FirmwareError::Rejected(fault)),
+                    None => {
+                        output.push(reply);
+                        Ok(output)
+                    }
+                };
+            }
+            GcodeReply::Rejected(message) => return Err(FirmwareError::Rejected(message)),
+            GcodeReply::Fault(message) => fault = Some(message),
+            GcodeReply::Busy => deadline = Instant::now() + Duration::from_millis(timeout_ms),
+            _ => output.push(reply),
+        }
+    }
+    Err(FirmwareError::Timeout { timeout_ms })
+}
+
+/// Strip comments and surrounding whitespace, `None` when nothing is left to send
+pub fn clean_line(line: &str) -> Option<String> {
+    let mut cleaned = String::new();
+    let mut in_comment = false;
+    for c in line.chars() {
+        match c {
+            ';' if !in_comment => break,
+            '(' => in_comment = true,
+            ')' if in_comment => in_comment = false,
+            _ if !in_comment => cleaned.push(c),
+            _ => {}
+        }
+    }
+    let cleaned = cleaned.trim();
+    // A lone % marks the start and end of a program on CNC controllers
+    (!cleaned.is_empty() && cleaned != "%").then(|| cleaned.to_string())
+}
+
+/// The lines of a program worth sending
+pub fn program_lines(program: &str) -> Vec<String> {
+    program.lines().filter_map(clean_line).collect()
+}
+
+/// `line` with line number `number` and the XOR checksum Marlin checks
+pub fn numbered(number: u64, line: &str) -> String {
+    let body = format!("N{} {}", number, line);
+    let checksum = body.bytes().fold(0u8, |sum, byte| sum ^ byte);
+    format!("{}*{}", body, checksum)
+}
+
+/// What a line received from the firmware means for the line being sent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GcodeReply {
+    /// The line was accepted
+    Ok,
+    /// The line was refused, with no `ok` to follow (GRBL `error:N`)
+    Rejected(String),
+    /// A problem that an `ok` or resend request follows (Marlin `Error:`, `!!`, GRBL `ALARM:`)
+    Fault(String),
+    /// Send again from this line number
+    Resend(u64),
+    /// Still working on a long command, such as homing
+    Busy,
+    /// Anything else, such as temperature reports
+    Other,
+}
+
+pub fn parse_reply(line: &str) -> GcodeReply {
+    let line = line.trim();
+    let lower = line.to_ascii_lowercase();
+
+    if lower == "ok" || lower.starts_with("ok ") {
+        return GcodeReply::Ok;
+    }
+    if let Some(number) = lower.strip_prefix("resend:").or_else(|| lower.strip_prefix("rs ")) {
+        if let Ok(number) = number.trim().trim_start_matches('n').parse() {
+            return GcodeReply::Resend(number);
+        }
+    }
+    if line.starts_with("error:") {
+        return GcodeReply::Rejected(line.to_string());
+    }
+    if line.starts_with("Error:") || line.starts_with("!!") || lower.starts_with("alarm:") || lower.starts_with("echo:unknown command") {
+        return GcodeReply::Fault(line.to_string());
+    }
+    if lower.starts_with("echo:busy") || lower.starts_with("busy:") {
+        return GcodeReply::Busy;
+    }
+    GcodeReply::Other
+}
+
+/// How a job sends its lines
+#[derive(Debug, Clone)]
+pub struct GcodeOptions {
+    /// Send `N<n> ...*<checksum>` lines, restarting the numbering with `M110 N0`
+    pub line_numbers: bool,
+    /// Longest wait for one line's `ok`; busy reports restart it
+    pub line_timeout: Duration,
+    /// End the job at the first rejected line instead of carrying on
+    pub stop_on_error: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JobState {
+    Running,
+    Completed,
+    Failed(String),
+    Cancelled,
+}
+
+impl std::fmt::Display for JobState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JobState::Running => write!(f, "running"),
+            JobState::Completed => write!(f, "completed"),
+            JobState::Failed(reason) => write!(f, "failed: {}", reason),
+            JobState::Cancelled => write!(f, "cancelled"),
+        }
+    }
+}
+
+/// How far a job has got
+#[derive(Debug, Clone)]
+pub struct GcodeProgress {
+    pub total_lines: usize,
+    /// Lines the firmware has answered, accepted or rejected
+    pub acknowledged: usize,
+    /// The line awaiting its `ok`
+    pub current: Option<String>,
+    /// Rejected lines with the firmware's message
+    pub errors: Vec<String>,
+    pub resends: u64,
+    /// The latest temperature report, which Marlin sends while heating and with `M155`
+    pub temperatures: Option<Temperatures>,
+    pub state: JobState,
+}
+
+/// A program being streamed to a connection until done, cancelled or dropped
+#[derive(Debug)]
+pub struct GcodeJob {
+    connection: Arc<SerialConnection>,
+    progress: Arc<Mutex<GcodeProgress>>,
+    started_at: DateTime<Utc>,
+    task: JoinHandle<()>,
+}
+
+impl GcodeJob {
+    pub fn start(connection: Arc<SerialConnection>, lines: Vec<String>, options: GcodeOptions) -> Self {
+        let progress = Arc::new(Mutex::new(GcodeProgress {
+            total_lines: lines.len(),
+            acknowledged: 0,
+            current: None,
+            errors: Vec::new(),
+            resends: 0,
+            temperatures: None,
+            state: JobState::Running,
+        }));
+        let task = tokio::spawn(stream(Arc::clone(&connection), lines, options, Arc::clone(&progress)));
+
+        Self {
+            connection,
+            progress,
+            started_at: Utc::now(),
+            task,
+        }
+    }
+
+    pub fn connection(&self) -> &Arc<SerialConnection> {
+        &self.connection
+    }
+
+    pub fn started_at(&self) -> DateTime<Utc> {
+        self.started_at
+    }
+
+    pub fn progress(&self) -> GcodeProgress {
+        self.progress.lock().unwrap().clone()
+    }
+
+    pub fn is_running(&self) -> bool {
+        !self.task.is_finished()
+    }
+
+    /// Stop sending; moves the firmware already accepted still run
+    pub fn cancel(&self) {
+        self.task.abort();
+        let mut progress = self.progress.lock().unwrap();
+        if progress.state == JobState::Running {
+            progress.state = JobState::Cancelled;
+            progress.current = None;
+        }
+    }
+}
+
+impl Drop for GcodeJob {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn stream(connection: Arc<SerialConnection>, lines: Vec<String>, options: GcodeOptions, progress: Arc<Mutex<GcodeProgress>>) {
+    let result = send_all(&connection, &lines, &options, &progress).await;
+    let event = {
+        let mut progress = progress.lock().unwrap();
+        progress.current = None;
+        progress.state = match result {
+            Ok(()) => JobState::Completed,
+            Err(reason) => JobState::Failed(reason),
+        };
+        format!("G-code job {} after {} of {} lines", progress.state, progress.acknowledged, progress.total_lines)
+    };
+    connection.record_event(event).await;
+}
+
+/// How the firmware answered one line
+enum Ack {
+    Accepted,
+    Rejected(String),
+    Resend(u64),
+}
+
+async fn send_all(connection: &SerialConnection, lines: &[String], options: &GcodeOptions, progress: &Mutex<GcodeProgress>) -> Result<(), String> {
+    let _exchange = connection.begin_exchange().await;
+    let mut replies = ReplyReader::default();
+
+    if options.line_numbers {
+        // Restart the numbering so N1 is expected next
+        match send_line(connection, "M110 N0", &mut replies, options.line_timeout, progress).await? {
+            Ack::Accepted => {}
+            Ack::Rejected(message) => return Err(format!("M110 N0: {}", message)),
+            Ack::Resend(number) => return Err(format!("Resend of line {} requested before any was sent", number)),
+        }
+    }
+
+    let mut index = 0;
+    while index < lines.len() {
+        let line = &lines[index];
+        progress.lock().unwrap().current = Some(line.clone());
+        let text = if options.line_numbers { numbered(index as u64 + 1, line) } else { line.clone() };
+
+        match send_line(connection, &text, &mut replies, options.line_timeout, progress).await? {
+            Ack::Accepted => index += 1,
+            Ack::Rejected(message) => {
+                let error = format!("Line {} ({}): {}", index + 1, line, message);
+                index += 1;
+                let mut progress = progress.lock().unwrap();
+                progress.errors.push(error.clone());
+                if options.stop_on_error {
+                    progress.acknowledged = index;
+                    return Err(error);
+                }
+            }
+            Ack::Resend(number) if options.line_numbers && (1..=index as u64 + 1).contains(&number) => {
+                progress.lock().unwrap().resends += 1;
+                index = number as usize - 1;
+            }
+            Ack::Resend(number) => return Err(format!("Resend of line {} requested, which was never sent", number)),
+        }
+        progress.lock().unwrap().acknowledged = index;
+    }
+    Ok(())
+}
+
+/// Send one line and wait for the firmware's answer
+async fn send_line(
+    connection: &SerialConnection,
+    text: &str,
+    replies: &mut ReplyReader,
+    timeout: Duration,
+    progress: &Mutex<GcodeProgress>,
+) -> Result<Ack, String> {
+    connection
+        .write(format!("{}\n", text).as_bytes())
+        .await
+        .map_err(|e| format!("Sending {:?} failed: {}", text, e))?;
+
+    let mut deadline = Instant::now() + timeout;
+    let mut fault: Option<String> = None;
+    let mut resend = None;
+    loop {
+        let reply = match replies.next_line(connection, deadline).await {
+            Ok(Some(reply)) => reply,
+            Ok(None) => {
+                let mut reason = format!("No ok for {:?} within {}ms", text, timeout.as_millis());
+                if let Some(fault) = fault {
+                    reason.push_str(&format!(", last error: {}", fault));
+                }
+                return Err(reason);
+            }
+            Err(e) => return Err(format!("Reading replies failed: {}", e)),
+        };
+
+        // A halted printer stops answering; no later line should wait for it
+        if let Some(alert) = fatal_alert(&reply) {
+            return Err(format!("Printer halted ({}) at {:?}: {}", alert, text, reply));
+        }
+        if let Some(temperatures) = parse_temperatures(&reply) {
+            progress.lock().unwrap().temperatures = Some(temperatures);
+        }
+        match parse_reply(&reply) {
+            GcodeReply::Ok => {
+                return Ok(match (resend, fault) {
+                    (Some(number), _) => Ack::Resend(number),
+                    (None, Some(fault)) => Ack::Rejected(fault),
+                    (None, None) => Ack::Accepted,
+                });
+            }
+            GcodeReply::Rejected(message) => return Ok(Ack::Rejected(message)),
+            GcodeReply::Fault(message) => fault = Some(message),
+            // The fault before a resend request was the garbled line, not the G-code
+            GcodeReply::Resend(number) => {
+                resend = Some(number);
+                fault = None;
+            }
+            GcodeReply::Busy => deadline = Instant::now() + timeout,
+            GcodeReply::Other => {}
+        }
+    }
+}
+
+/// Splits replies into lines whether or not the connection is in line mode
+#[derive(Debug, Default)]
+pub(crate) struct ReplyReader {
+    pending: String,
+}
+
+impl ReplyReader {
+    /// The next non-blank line, `None` if none is complete by `deadline`
+    pub(crate) async fn next_line(&mut self, connection: &SerialConnection, deadline: Instant) -> Result<Option<String>, LocalSerialError> {
+        loop {
+            while let Some(end) = self.pending.find('\n') {
+                let line: String = self.pending.drain(..=end).collect();
+                if !line.trim().is_empty() {
+                    return Ok(Some(line.trim().to_string()));
+                }
+            }
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Ok(None);
+            }
+            let wait = Some(remaining.as_millis().max(1) as u64);
+            let received = if connection.is_line_mode().await {
+                connection.read_lines(usize::MAX, wait).await.map(|lines| {
+                    lines.iter().map(|line| format!("{}\n", line.text())).collect::<String>()
+                })
+            } else {
+                let mut buffer = [0u8; GCODE_READ_CHUNK];
+                connection.read(&mut buffer, wait).await.map(|n| String::from_utf8_lossy(&buffer[..n]).into_owned())
+            };
+            match received {
+                // Paused: wait for reception to resume or the deadline to pass
+                Ok(text) if text.is_empty() => tokio::time::sleep(PAUSED_POLL.min(remaining)).await,
+                Ok(text) => self.pending.push_str(&text),
+                Err(LocalSerialError::ReadTimeout) => return Ok(None),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clean_and_number_lines() {
+        assert_eq!(
+            program_lines("%\n; header\nG28 ; home\n\nG1 X10 (move) Y5\n(comment only)\n%\n"),
+            vec!["G28".to_string(), "G1 X10  Y5".to_string()]
+        );
+        assert_eq!(numbered(3, "T0"), "N3 T0*57");
+        assert_eq!(numbered(2, "G1 X10"), "N2 G1 X10*83");
+    }
+
+    #[test]
+    fn test_parse_reply() {
+        assert_eq!(parse_reply("ok"), GcodeReply::Ok);
+        assert_eq!(parse_reply("ok T:210.0 /210.0"), GcodeReply::Ok);
+        assert_eq!(parse_reply("Resend: 12"), GcodeReply::Resend(12));
+        assert_eq!(parse_reply("rs N7"), GcodeReply::Resend(7));
+        assert_eq!(parse_reply("error:20"), GcodeReply::Rejected("error:20".to_string()));
+        assert_eq!(parse_reply("Error:checksum mismatch, Last Line: 3"), GcodeReply::Fault("Error:checksum mismatch, Last Line: 3".to_string()));
+        assert_eq!(parse_reply("ALARM:1"), GcodeReply::Fault("ALARM:1".to_string()));
+        assert_eq!(parse_reply("echo:busy: processing"), GcodeReply::Busy);
+        assert_eq!(parse_reply(" T:25.0 /0.0 B:24.8 /0.0"), GcodeReply::Other);
+    }
+}
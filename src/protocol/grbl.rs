@@ -0,0 +1,298 @@
+//! GRBL CNC controller protocol
+//!
+//! Parses `?` status reports (GRBL 1.1 `<Idle|MPos:...|FS:...>` and the older
+//! `<Idle,MPos:...,WPos:...>` form) and `$$` settings dumps, and builds the
+//! single-byte real-time commands and `$J=` jog lines. G-code itself is
+//! streamed, and other commands sent, with [`super::gcode`].
+
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::time::Instant;
+
+use super::gcode::{FirmwareError, ReplyReader};
+use crate::serial::{LocalSerialError, SerialConnection};
+
+/// Commands GRBL acts on the moment the byte arrives, even mid-line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RealtimeCommand {
+    /// `!`: decelerate to a stop, keeping position
+    FeedHold,
+    /// `~`: resume after a feed hold, or start a cycle
+    Resume,
+    /// Ctrl-X: stop at once and reset; position is lost if the machine was moving
+    Reset,
+    /// 0x85: stop a jog and discard the queued ones
+    JogCancel,
+}
+
+impl RealtimeCommand {
+    pub fn parse(value: &str) -> Result<Self, LocalSerialError> {
+        match value.to_lowercase().as_str() {
+            "feed_hold" | "hold" => Ok(RealtimeCommand::FeedHold),
+            "resume" | "cycle_start" => Ok(RealtimeCommand::Resume),
+            "reset" | "soft_reset" => Ok(RealtimeCommand::Reset),
+            "jog_cancel" => Ok(RealtimeCommand::JogCancel),
+            _ => Err(LocalSerialError::InvalidConfig(format!("Unknown real-time command: {}", value))),
+        }
+    }
+
+    pub fn byte(self) -> u8 {
+        match self {
+            RealtimeCommand::FeedHold => b'!',
+            RealtimeCommand::Resume => b'~',
+            RealtimeCommand::Reset => 0x18,
+            RealtimeCommand::JogCancel => 0x85,
+        }
+    }
+}
+
+impl std::fmt::Display for RealtimeCommand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RealtimeCommand::FeedHold => write!(f, "feed_hold"),
+            RealtimeCommand::Resume => write!(f, "resume"),
+            RealtimeCommand::Reset => write!(f, "reset"),
+            RealtimeCommand::JogCancel => write!(f, "jog_cancel"),
+        }
+    }
+}
+
+/// Send a `?` and wait up to `timeout_ms` for the status report
+///
+/// Real-time commands need no exchange, but the report must not be taken by
+/// another reader, so this fails while another exchange is running.
+pub async fn query_status(connection: &SerialConnection, timeout_ms: u64) -> Result<GrblStatus, FirmwareError> {
+    let _exchange = connection.try_begin_exchange().ok_or(FirmwareError::Busy)?;
+    connection.write(b"?").await?;
+
+    let deadline = Instant::now() + Duration::from_millis(timeout_ms);
+    let mut replies = ReplyReader::default();
+    while let Some(line) = replies.next_line(connection, deadline).await? {
+        if let Some(status) = parse_status(&line) {
+            return Ok(status);
+        }
+    }
+    Err(FirmwareError::Timeout { timeout_ms })
+}
+
+/// A status report, answering `?`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GrblStatus {
+    /// Idle, Run, Hold, Jog, Alarm, Door, Check, Home or Sleep
+    pub state: String,
+    /// e.g. 0 in `Hold:0`, hold complete and safe to resume
+    pub sub_state: Option<u8>,
+    pub machine_position: Option<Vec<f64>>,
+    /// Reported, or machine position less the work offset when that came with the report
+    pub work_position: Option<Vec<f64>>,
+    /// Work coordinate offset; GRBL 1.1 includes it only in some reports
+    pub work_offset: Option<Vec<f64>>,
+    pub feed_rate: Option<f64>,
+    pub spindle_speed: Option<f64>,
+    pub planner_blocks_free: Option<u32>,
+    pub rx_bytes_free: Option<u32>,
+    pub line_number: Option<u64>,
+    /// Input pins active, e.g. `XZ` for limit switches or `P` for the probe
+    pub pins: Option<String>,
+    /// Feed, rapid and spindle overrides in percent
+    pub overrides: Option<Vec<u32>>,
+}
+
+/// Parse a `<...>` status report, `None` for any other line
+pub fn parse_status(line: &str) -> Option<GrblStatus> {
+    let body = line.trim().strip_prefix('<')?.strip_suffix('>')?;
+
+    // GRBL 1.1 separates fields with |; 0.9 uses commas throughout, so values
+    // without a name of their own belong to the field before them
+    let fields: Vec<(String, Vec<String>)> = if body.contains('|') {
+        body.split('|')
+            .map(|field| match field.split_once(':') {
+                Some((name, values)) => (name.to_string(), values.split(',').map(str::to_string).collect()),
+                None => (field.to_string(), Vec::new()),
+            })
+            .collect()
+    } else {
+        let mut fields: Vec<(String, Vec<String>)> = Vec::new();
+        for token in body.split(',') {
+            match (token.split_once(':'), fields.last_mut()) {
+                (Some((name, value)), _) => fields.push((name.to_string(), vec![value.to_string()])),
+                (None, Some((_, values))) if !values.is_empty() => values.push(token.to_string()),
+                (None, _) => fields.push((token.to_string(), Vec::new())),
+            }
+        }
+        fields
+    };
+
+    let ((state, state_values), rest) = fields.split_first()?;
+    let mut status = GrblStatus {
+        state: state.clone(),
+        sub_state: state_values.first().and_then(|value| value.parse().ok()),
+        machine_position: None,
+        work_position: None,
+        work_offset: None,
+        feed_rate: None,
+        spindle_speed: None,
+        planner_blocks_free: None,
+        rx_bytes_free: None,
+        line_number: None,
+        pins: None,
+        overrides: None,
+    };
+    for (name, values) in rest {
+        match name.as_str() {
+            "MPos" => status.machine_position = numbers(values),
+            "WPos" => status.work_position = numbers(values),
+            "WCO" => status.work_offset = numbers(values),
+            "F" => status.feed_rate = values.first().and_then(|value| value.parse().ok()),
+            "FS" => {
+                status.feed_rate = values.first().and_then(|value| value.parse().ok());
+                status.spindle_speed = values.get(1).and_then(|value| value.parse().ok());
+            }
+            "Bf" => {
+                status.planner_blocks_free = values.first().and_then(|value| value.parse().ok());
+                status.rx_bytes_free = values.get(1).and_then(|value| value.parse().ok());
+            }
+            "Ln" => status.line_number = values.first().and_then(|value| value.parse().ok()),
+            "Pn" => status.pins = values.first().cloned(),
+            "Ov" => status.overrides = numbers(values),
+            _ => {}
+        }
+    }
+
+    if status.work_position.is_none() {
+        if let (Some(machine), Some(offset)) = (&status.machine_position, &status.work_offset) {
+            status.work_position = Some(machine.iter().zip(offset).map(|(machine, offset)| machine - offset).collect());
+        }
+    }
+    if status.machine_position.is_none() {
+        if let (Some(work), Some(offset)) = (&status.work_position, &status.work_offset) {
+            status.machine_position = Some(work.iter().zip(offset).map(|(work, offset)| work + offset).collect());
+        }
+    }
+    Some(status)
+}
+
+fn numbers<T: std::str::FromStr>(values: &[String]) -> Option<Vec<T>> {
+    values.iter().map(|value| value.parse().ok()).collect()
+}
+
+/// One line of a `$$` settings dump
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GrblSetting {
+    pub number: u16,
+    pub value: String,
+    /// What the setting controls, for the standard GRBL 1.1 settings
+    pub description: Option<&'static str>,
+}
+
+/// Settings in the lines of a `$$` dump, in the order received
+pub fn parse_settings<'a>(lines: impl IntoIterator<Item = &'a str>) -> Vec<GrblSetting> {
+    lines
+        .into_iter()
+        .filter_map(|line| {
+            let (number, value) = line.trim().strip_prefix('$')?.split_once('=')?;
+            let number = number.parse().ok()?;
+            // 0.9 appends a description in parentheses
+            let value = value.split_once(" (").map_or(value, |(value, _)| value).trim().to_string();
+            Some(GrblSetting { number, value, description: setting_description(number) })
+        })
+        .collect()
+}
+
+fn setting_description(number: u16) -> Option<&'static str> {
+    Some(match number {
+        0 => "Step pulse time, microseconds",
+        1 => "Step idle delay, milliseconds",
+        2 => "Step pulse invert, mask",
+        3 => "Step direction invert, mask",
+        4 => "Invert step enable pin",
+        5 => "Invert limit pins",
+        6 => "Invert probe pin",
+        10 => "Status report options, mask",
+        11 => "Junction deviation, mm",
+        12 => "Arc tolerance, mm",
+        13 => "Report in inches",
+        20 => "Soft limits enable",
+        21 => "Hard limits enable",
+        22 => "Homing cycle enable",
+        23 => "Homing direction invert, mask",
+        24 => "Homing locate feed rate, mm/min",
+        25 => "Homing search seek rate, mm/min",
+        26 => "Homing switch debounce delay, milliseconds",
+        27 => "Homing switch pull-off distance, mm",
+        30 => "Maximum spindle speed, RPM",
+        31 => "Minimum spindle speed, RPM",
+        32 => "Laser mode enable",
+        100 => "X-axis steps per mm",
+        101 => "Y-axis steps per mm",
+        102 => "Z-axis steps per mm",
+        110 => "X-axis maximum rate, mm/min",
+        111 => "Y-axis maximum rate, mm/min",
+        112 => "Z-axis maximum rate, mm/min",
+        120 => "X-axis acceleration, mm/sec^2",
+        121 => "Y-axis acceleration, mm/sec^2",
+        122 => "Z-axis acceleration, mm/sec^2",
+        130 => "X-axis maximum travel, mm",
+        131 => "Y-axis maximum travel, mm",
+        132 => "Z-axis maximum travel, mm",
+        _ => return None,
+    })
+}
+
+/// A `$J=` jog line moving the given axes in millimetres at `feed_rate` mm/min
+///
+/// Incremental jogs move by the distances, absolute ones to the work coordinates.
+pub fn jog_command(moves: &[(char, f64)], feed_rate: f64, incremental: bool) -> Result<String, String> {
+    if moves.is_empty() {
+        return Err("A jog needs at least one axis to move".to_string());
+    }
+    if !(feed_rate.is_finite() && feed_rate > 0.0) {
+        return Err(format!("Feed rate must be positive, not {}", feed_rate));
+    }
+    let mut command = format!("$J={} G21", if incremental { "G91" } else { "G90" });
+    for (axis, distance) in moves {
+        if !distance.is_finite() {
+            return Err(format!("{} is not a valid {} position", distance, axis));
+        }
+        command.push_str(&format!(" {}{}", axis, distance));
+    }
+    command.push_str(&format!(" F{}", feed_rate));
+    Ok(command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_status_reports() {
+        let status = parse_status("<Hold:0|MPos:10.000,5.000,-1.000|Bf:15,128|FS:500,12000|WCO:1.000,1.000,0.000|Ov:100,100,100|Pn:XZ>").unwrap();
+        assert_eq!((status.state.as_str(), status.sub_state), ("Hold", Some(0)));
+        assert_eq!(status.machine_position, Some(vec![10.0, 5.0, -1.0]));
+        assert_eq!(status.work_position, Some(vec![9.0, 4.0, -1.0]));
+        assert_eq!((status.feed_rate, status.spindle_speed), (Some(500.0), Some(12000.0)));
+        assert_eq!((status.planner_blocks_free, status.rx_bytes_free), (Some(15), Some(128)));
+        assert_eq!(status.pins.as_deref(), Some("XZ"));
+
+        let old = parse_status("<Idle,MPos:0.000,1.500,0.000,WPos:0.000,0.500,0.000>").unwrap();
+        assert_eq!(old.state, "Idle");
+        assert_eq!(old.machine_position, Some(vec![0.0, 1.5, 0.0]));
+        assert_eq!(old.work_position, Some(vec![0.0, 0.5, 0.0]));
+
+        assert_eq!(parse_status("ok"), None);
+    }
+
+    #[test]
+    fn test_settings_and_jog() {
+        let settings = parse_settings(["$0=10", "$110=500.000 (x max rate, mm/min)", "ok", "$N0="]);
+        assert_eq!(settings.len(), 2);
+        assert_eq!(settings[1].value, "500.000");
+        assert_eq!(settings[1].description, Some("X-axis maximum rate, mm/min"));
+
+        assert_eq!(jog_command(&[('X', 10.0), ('Z', -0.5)], 500.0, true).unwrap(), "$J=G91 G21 X10 Z-0.5 F500");
+        assert!(jog_command(&[], 500.0, true).is_err());
+        assert!(jog_command(&[('X', 1.0)], 0.0, false).is_err());
+        assert_eq!(RealtimeCommand::parse("Feed_Hold").unwrap().byte(), b'!');
+    }
+}
@@ -0,0 +1,160 @@
+//! HDLC-style framing, as used by PPP (RFC 1662) and many radio modems
+//!
+//! Frames are delimited by FLAG; FLAG and ESC inside a frame are sent as ESC
+//! followed by the byte XOR 0x20. Each frame carries a 16-bit frame check
+//! sequence (CRC-16/X-25, low byte first) after its payload, verified on
+//! receipt and stripped from the decoded payload.
+
+use super::framer::{FrameError, Framer};
+use crate::utils::BufferUtils;
+
+pub const HDLC_FLAG: u8 = 0x7E;
+pub const HDLC_ESC: u8 = 0x7D;
+pub const HDLC_ESC_XOR: u8 = 0x20;
+
+/// Length of the frame check sequence
+const HDLC_FCS_LEN: usize = 2;
+
+/// Longest frame accepted before the partial frame is discarded
+pub const HDLC_MAX_FRAME: usize = 64 * 1024;
+
+#[derive(Debug, Default)]
+pub struct HdlcFramer {
+    frame: Vec<u8>,
+    escaped: bool,
+    /// The current frame grew too long and is skipped up to the next FLAG
+    overflowed: bool,
+}
+
+impl HdlcFramer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Check and strip the frame check sequence of an unescaped frame
+    fn verify(frame: &[u8]) -> Result<Vec<u8>, FrameError> {
+        if frame.len() < HDLC_FCS_LEN {
+            return Err(FrameError::Malformed(format!("{} byte frame is too short for an FCS", frame.len())));
+        }
+        let (payload, fcs) = frame.split_at(frame.len() - HDLC_FCS_LEN);
+        let received = u16::from_le_bytes([fcs[0], fcs[1]]);
+        let expected = BufferUtils::crc16_x25(payload);
+        if received != expected {
+            return Err(FrameError::Malformed(format!(
+                "FCS mismatch: received 0x{:04X}, expected 0x{:04X}",
+                received, expected
+            )));
+        }
+        Ok(payload.to_vec())
+    }
+}
+
+impl Framer for HdlcFramer {
+    fn name(&self) -> &'static str {
+        "hdlc"
+    }
+
+    fn encode(&self, payload: &[u8]) -> Vec<u8> {
+        let fcs = BufferUtils::crc16_x25(payload).to_le_bytes();
+        let mut encoded = Vec::with_capacity(payload.len() + 6);
+        encoded.push(HDLC_FLAG);
+        for &byte in payload.iter().chain(fcs.iter()) {
+            if byte == HDLC_FLAG || byte == HDLC_ESC {
+                encoded.extend_from_slice(&[HDLC_ESC, byte ^ HDLC_ESC_XOR]);
+            } else {
+                encoded.push(byte);
+            }
+        }
+        encoded.push(HDLC_FLAG);
+        encoded
+    }
+
+    fn decode(&mut self, data: &[u8]) -> Vec<Result<Vec<u8>, FrameError>> {
+        let mut frames = Vec::new();
+
+        for &byte in data {
+            if byte == HDLC_FLAG {
+                if std::mem::take(&mut self.overflowed) {
+                    frames.push(Err(FrameError::TooLong(HDLC_MAX_FRAME)));
+                } else if self.escaped {
+                    // RFC 1662 abort sequence
+                    frames.push(Err(FrameError::Malformed("frame aborted by ESC FLAG".to_string())));
+                } else if !self.frame.is_empty() {
+                    // Back-to-back FLAGs delimit empty frames, which are ignored
+                    frames.push(Self::verify(&self.frame));
+                }
+                self.frame.clear();
+                self.escaped = false;
+                continue;
+            }
+            if self.overflowed {
+                continue;
+            }
+
+            let decoded = if std::mem::take(&mut self.escaped) {
+                byte ^ HDLC_ESC_XOR
+            } else if byte == HDLC_ESC {
+                self.escaped = true;
+                continue;
+            } else {
+                byte
+            };
+
+            if self.frame.len() == HDLC_MAX_FRAME + HDLC_FCS_LEN {
+                self.overflowed = true;
+                self.frame.clear();
+                continue;
+            }
+            self.frame.push(decoded);
+        }
+
+        frames
+    }
+
+    fn pending(&self) -> usize {
+        self.frame.len()
+    }
+
+    fn reset(&mut self) {
+        self.frame.clear();
+        self.escaped = false;
+        self.overflowed = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hdlc_round_trip() {
+        let payload = [0xFF, 0x03, HDLC_FLAG, 0xC0, HDLC_ESC, 0x21];
+        let mut framer = HdlcFramer::new();
+        let encoded = framer.encode(&payload);
+        assert_eq!(encoded[0], HDLC_FLAG);
+        assert_eq!(&encoded[3..7], &[HDLC_ESC, 0x5E, 0xC0, HDLC_ESC]);
+        assert_eq!(encoded.iter().filter(|&&b| b == HDLC_FLAG).count(), 2);
+
+        // Split mid-escape across two reads
+        let (first, second) = encoded.split_at(4);
+        assert!(framer.decode(first).is_empty());
+        assert_eq!(framer.decode(second), vec![Ok(payload.to_vec())]);
+        assert_eq!(framer.pending(), 0);
+    }
+
+    #[test]
+    fn test_hdlc_rejects_bad_fcs() {
+        let mut framer = HdlcFramer::new();
+        let mut stream = framer.encode(b"ok");
+        let mut corrupted = framer.encode(b"bad");
+        corrupted[2] ^= 0x01;
+        stream.extend(corrupted);
+        stream.extend([HDLC_FLAG, 0x41, HDLC_FLAG]);
+
+        let frames = framer.decode(&stream);
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[0], Ok(b"ok".to_vec()));
+        assert!(matches!(&frames[1], Err(FrameError::Malformed(m)) if m.contains("FCS")));
+        assert!(matches!(&frames[2], Err(FrameError::Malformed(m)) if m.contains("too short")));
+    }
+}
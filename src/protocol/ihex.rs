@@ -0,0 +1,114 @@
+//! Intel HEX firmware images
+//!
+//! Compilers for small microcontrollers emit firmware as lines of hex
+//! records, each carrying an address, data and a checksum. Extended segment
+//! and linear address records move later data above 64 KiB.
+
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum IhexError {
+    #[error("Line {line}: {reason}")]
+    Record { line: usize, reason: String },
+
+    #[error("No end of file record")]
+    MissingEnd,
+}
+
+/// Data at one address
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub address: u32,
+    pub data: Vec<u8>,
+}
+
+/// The data of a HEX file, with consecutive records joined into segments
+pub fn parse(text: &str) -> Result<Vec<Segment>, IhexError> {
+    let mut segments: Vec<Segment> = Vec::new();
+    let mut base = 0u32;
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let error = |reason: &str| IhexError::Record { line: index + 1, reason: reason.to_string() };
+        let digits = line.strip_prefix(':').ok_or_else(|| error("does not start with ':'"))?;
+        if digits.len() % 2 != 0 || digits.len() < 10 {
+            return Err(error("is too short"));
+        }
+        let bytes = (0..digits.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&digits[i..i + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|_| error("has a character that is not hex"))?;
+        let count = bytes[0] as usize;
+        if bytes.len() != count + 5 {
+            return Err(error("length does not match its byte count"));
+        }
+        if bytes.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte)) != 0 {
+            return Err(error("checksum mismatch"));
+        }
+        let offset = u16::from_be_bytes([bytes[1], bytes[2]]) as u32;
+        let data = &bytes[4..4 + count];
+        match bytes[3] {
+            0x00 => {
+                let address = base.wrapping_add(offset);
+                match segments.last_mut() {
+                    Some(last) if last.address + last.data.len() as u32 == address => last.data.extend(data),
+                    _ => segments.push(Segment { address, data: data.to_vec() }),
+                }
+            }
+            0x01 => return Ok(segments),
+            0x02 if count == 2 => base = (u16::from_be_bytes([data[0], data[1]]) as u32) << 4,
+            0x04 if count == 2 => base = (u16::from_be_bytes([data[0], data[1]]) as u32) << 16,
+            // Start addresses say where execution begins, which flashing does not need
+            0x03 | 0x05 => {}
+            _ => return Err(error("has an unknown record type or bad length")),
+        }
+    }
+    Err(IhexError::MissingEnd)
+}
+
+/// The segments as one block starting at the lowest address, gaps filled with `fill`
+pub fn flatten(segments: &[Segment], fill: u8) -> (u32, Vec<u8>) {
+    let Some(start) = segments.iter().map(|segment| segment.address).min() else {
+        return (0, Vec::new());
+    };
+    let end = segments.iter().map(|segment| segment.address + segment.data.len() as u32).max().unwrap_or(start);
+    let mut image = vec![fill; (end - start) as usize];
+    for segment in segments {
+        let at = (segment.address - start) as usize;
+        image[at..at + segment.data.len()].copy_from_slice(&segment.data);
+    }
+    (start, image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let text = ":100000000C9434000C9446000C9446000C9446006A\n\
+                    :020000040001F9\n\
+                    :0400000001020304F2\n\
+                    :00000001FF\n";
+        let segments = parse(text).unwrap();
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[0].address, 0);
+        assert_eq!(&segments[0].data[..4], &[0x0C, 0x94, 0x34, 0x00]);
+        assert_eq!(segments[1], Segment { address: 0x10000, data: vec![1, 2, 3, 4] });
+
+        let (start, image) = flatten(&segments, 0xFF);
+        assert_eq!(start, 0);
+        assert_eq!(image.len(), 0x10004);
+        assert_eq!(image[0x20], 0xFF);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(parse(":0400000001020304F3\n:00000001FF").unwrap_err(), IhexError::Record { line: 1, reason: "checksum mismatch".to_string() });
+        assert_eq!(parse(":0400000001020304F2\n"), Err(IhexError::MissingEnd));
+        assert!(parse("0400000001020304F2").is_err());
+    }
+}
@@ -0,0 +1,278 @@
+//! Length-prefixed framing
+//!
+//! Each frame is an optional fixed header magic, then the payload length as a
+//! 1, 2 or 4 byte integer, then the payload. The magic lets the decoder find
+//! the next frame again after line noise; without one a corrupt length field
+//! leaves nothing to resynchronise on.
+
+use super::framer::{FrameError, Framer};
+use crate::serial::LocalSerialError;
+
+/// Longest payload accepted before the frame is rejected
+pub const LENGTH_PREFIX_MAX_FRAME: usize = 64 * 1024;
+
+/// Byte order of the length field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteOrder {
+    Big,
+    Little,
+}
+
+impl ByteOrder {
+    pub fn parse(value: &str) -> Result<Self, LocalSerialError> {
+        match value.to_lowercase().as_str() {
+            "big" | "be" => Ok(ByteOrder::Big),
+            "little" | "le" => Ok(ByteOrder::Little),
+            _ => Err(LocalSerialError::InvalidConfig(format!("Unknown byte order: {}", value))),
+        }
+    }
+
+    /// The unsigned integer in `field`, up to 8 bytes
+    pub fn read_uint(&self, field: &[u8]) -> u64 {
+        let fold = |value: u64, byte: &u8| value << 8 | u64::from(*byte);
+        match self {
+            ByteOrder::Big => field.iter().fold(0, fold),
+            ByteOrder::Little => field.iter().rev().fold(0, fold),
+        }
+    }
+
+    /// The low `bytes` bytes of `value`, up to 8
+    pub fn write_uint(&self, value: u64, bytes: usize) -> Vec<u8> {
+        match self {
+            ByteOrder::Big => value.to_be_bytes()[8 - bytes..].to_vec(),
+            ByteOrder::Little => value.to_le_bytes()[..bytes].to_vec(),
+        }
+    }
+}
+
+impl std::fmt::Display for ByteOrder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ByteOrder::Big => write!(f, "big-endian"),
+            ByteOrder::Little => write!(f, "little-endian"),
+        }
+    }
+}
+
+/// Layout of the frame header
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LengthPrefixConfig {
+    length_bytes: usize,
+    byte_order: ByteOrder,
+    magic: Vec<u8>,
+}
+
+impl LengthPrefixConfig {
+    pub fn new(length_bytes: usize, byte_order: ByteOrder, magic: Vec<u8>) -> Result<Self, LocalSerialError> {
+        if !matches!(length_bytes, 1 | 2 | 4) {
+            return Err(LocalSerialError::InvalidConfig(format!(
+                "Length field must be 1, 2 or 4 bytes, not {}",
+                length_bytes
+            )));
+        }
+        Ok(Self {
+            length_bytes,
+            byte_order,
+            magic,
+        })
+    }
+
+    fn header_len(&self) -> usize {
+        self.magic.len() + self.length_bytes
+    }
+
+    /// Largest payload the length field can describe, within the frame limit
+    fn max_payload(&self) -> usize {
+        let field_max = match self.length_bytes {
+            1 => u8::MAX as usize,
+            2 => u16::MAX as usize,
+            _ => u32::MAX as usize,
+        };
+        field_max.min(LENGTH_PREFIX_MAX_FRAME)
+    }
+
+    fn read_length(&self, field: &[u8]) -> usize {
+        let mut bytes = [0u8; 4];
+        match self.byte_order {
+            ByteOrder::Big => {
+                bytes[4 - field.len()..].copy_from_slice(field);
+                u32::from_be_bytes(bytes) as usize
+            }
+            ByteOrder::Little => {
+                bytes[..field.len()].copy_from_slice(field);
+                u32::from_le_bytes(bytes) as usize
+            }
+        }
+    }
+
+    fn write_length(&self, length: usize) -> Vec<u8> {
+        let length = length as u32;
+        match self.byte_order {
+            ByteOrder::Big => length.to_be_bytes()[4 - self.length_bytes..].to_vec(),
+            ByteOrder::Little => length.to_le_bytes()[..self.length_bytes].to_vec(),
+        }
+    }
+}
+
+/// Two byte big-endian length, no magic
+impl Default for LengthPrefixConfig {
+    fn default() -> Self {
+        Self {
+            length_bytes: 2,
+            byte_order: ByteOrder::Big,
+            magic: Vec::new(),
+        }
+    }
+}
+
+impl std::fmt::Display for LengthPrefixConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-byte {} length", self.length_bytes, self.byte_order)?;
+        if !self.magic.is_empty() {
+            write!(f, ", magic {}", hex::encode_upper(&self.magic))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct LengthPrefixedFramer {
+    config: LengthPrefixConfig,
+    buffer: Vec<u8>,
+}
+
+impl LengthPrefixedFramer {
+    pub fn new(config: LengthPrefixConfig) -> Self {
+        Self {
+            config,
+            buffer: Vec::new(),
+        }
+    }
+
+    pub fn config(&self) -> &LengthPrefixConfig {
+        &self.config
+    }
+
+    /// Drop bytes before the next header magic
+    ///
+    /// Returns whether the buffer now starts with the magic. Bytes that may be
+    /// the start of a magic split across reads are kept.
+    fn align_to_magic(&mut self, frames: &mut Vec<Result<Vec<u8>, FrameError>>) -> bool {
+        let magic = &self.config.magic;
+        if magic.is_empty() {
+            return true;
+        }
+
+        let (skip, aligned) = match self.buffer.windows(magic.len()).position(|window| window == magic.as_slice()) {
+            Some(position) => (position, true),
+            None => {
+                let kept = (1..magic.len().min(self.buffer.len() + 1))
+                    .rev()
+                    .find(|&len| self.buffer.ends_with(&magic[..len]))
+                    .unwrap_or(0);
+                (self.buffer.len() - kept, false)
+            }
+        };
+        if skip > 0 {
+            self.buffer.drain(..skip);
+            frames.push(Err(FrameError::Malformed(format!("skipped {} bytes before header magic", skip))));
+        }
+        aligned
+    }
+}
+
+impl Framer for LengthPrefixedFramer {
+    fn name(&self) -> &'static str {
+        "length"
+    }
+
+    fn encode(&self, payload: &[u8]) -> Vec<u8> {
+        let mut encoded = Vec::with_capacity(self.config.header_len() + payload.len());
+        encoded.extend_from_slice(&self.config.magic);
+        encoded.extend(self.config.write_length(payload.len()));
+        encoded.extend_from_slice(payload);
+        encoded
+    }
+
+    fn decode(&mut self, data: &[u8]) -> Vec<Result<Vec<u8>, FrameError>> {
+        let mut frames = Vec::new();
+        self.buffer.extend_from_slice(data);
+        let header_len = self.config.header_len();
+
+        while self.align_to_magic(&mut frames) && self.buffer.len() >= header_len {
+            let length = self.config.read_length(&self.buffer[self.config.magic.len()..header_len]);
+            if length > LENGTH_PREFIX_MAX_FRAME {
+                frames.push(Err(FrameError::TooLong(LENGTH_PREFIX_MAX_FRAME)));
+                if self.config.magic.is_empty() {
+                    // Nothing marks where the next frame starts
+                    self.buffer.clear();
+                } else {
+                    self.buffer.drain(..1);
+                }
+                continue;
+            }
+            if self.buffer.len() < header_len + length {
+                break;
+            }
+
+            let frame = self.buffer[header_len..header_len + length].to_vec();
+            self.buffer.drain(..header_len + length);
+            frames.push(Ok(frame));
+        }
+
+        frames
+    }
+
+    fn pending(&self) -> usize {
+        self.buffer.len()
+    }
+
+    fn reset(&mut self) {
+        self.buffer.clear();
+    }
+
+    fn max_payload(&self) -> Option<usize> {
+        Some(self.config.max_payload())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_length_prefix_layouts() {
+        let framer = LengthPrefixedFramer::new(LengthPrefixConfig::new(2, ByteOrder::Big, Vec::new()).unwrap());
+        assert_eq!(framer.encode(b"abc"), vec![0x00, 0x03, b'a', b'b', b'c']);
+
+        let config = LengthPrefixConfig::new(4, ByteOrder::Little, vec![0xAA, 0x55]).unwrap();
+        assert_eq!(config.to_string(), "4-byte little-endian length, magic AA55");
+        let mut framer = LengthPrefixedFramer::new(config);
+        let encoded = framer.encode(&[0x01; 300]);
+        assert_eq!(&encoded[..6], &[0xAA, 0x55, 0x2C, 0x01, 0x00, 0x00]);
+
+        // Split inside the header
+        assert!(framer.decode(&encoded[..4]).is_empty());
+        assert_eq!(framer.decode(&encoded[4..]), vec![Ok(vec![0x01; 300])]);
+        assert_eq!(framer.pending(), 0);
+
+        assert!(LengthPrefixConfig::new(3, ByteOrder::Big, Vec::new()).is_err());
+        assert_eq!(LengthPrefixedFramer::new(LengthPrefixConfig::new(1, ByteOrder::Big, Vec::new()).unwrap()).max_payload(), Some(255));
+    }
+
+    #[test]
+    fn test_length_prefix_resyncs_on_magic() {
+        let config = LengthPrefixConfig::new(1, ByteOrder::Big, vec![0xAA, 0x55]).unwrap();
+        let mut framer = LengthPrefixedFramer::new(config);
+
+        let mut stream = vec![0x13, 0x37, 0xAA];
+        let frames = framer.decode(&stream);
+        assert!(matches!(&frames[..], [Err(FrameError::Malformed(m))] if m.contains("skipped 2 bytes")));
+        assert_eq!(framer.pending(), 1);
+
+        stream = vec![0x55, 0x02, b'h', b'i'];
+        stream.extend(framer.encode(b""));
+        assert_eq!(framer.decode(&stream), vec![Ok(b"hi".to_vec()), Ok(Vec::new())]);
+        assert_eq!(framer.pending(), 0);
+    }
+}
@@ -0,0 +1,168 @@
+//! Marlin 3D printer firmware
+//!
+//! Parses the temperature reports Marlin prints for `M105`, while heating and
+//! when auto-reporting with `M155`, and the `M114` position report, and
+//! recognises the messages Marlin prints as it halts the printer, such as
+//! thermal runaway.
+
+use serde::Serialize;
+
+/// One heater in a temperature report
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Heater {
+    /// Marlin's name for it, e.g. `T0` or `B`
+    pub name: String,
+    /// Degrees Celsius
+    pub current: f64,
+    /// Degrees Celsius, 0 when the heater is off
+    pub target: Option<f64>,
+    /// Heater PWM, 0-127 on most boards
+    pub power: Option<u32>,
+}
+
+/// Heaters in a temperature report
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Temperatures {
+    pub hotends: Vec<Heater>,
+    pub bed: Option<Heater>,
+    pub chamber: Option<Heater>,
+}
+
+/// Parse a temperature report such as `ok T:210.3 /210.0 B:60.1 /60.0 @:127 B@:0`
+///
+/// `None` for lines without one. With several hotends the `T0:`, `T1:` entries
+/// are used and the plain `T:`, a copy of the active hotend, is dropped.
+pub fn parse_temperatures(line: &str) -> Option<Temperatures> {
+    // Targets come as `T:210.3 /210.0` or `T:210.3/210.0`
+    let line = line.replace(" /", "/");
+    let mut heaters = Vec::new();
+    let mut powers = Vec::new();
+    for token in line.split_whitespace() {
+        let Some((name, value)) = token.split_once(':') else { continue };
+        if let Some(heater) = name.strip_prefix('@') {
+            powers.push((format!("T{}", heater), value.parse().ok()));
+        } else if let Some(heater) = name.strip_suffix('@') {
+            powers.push((heater.to_string(), value.parse().ok()));
+        } else if is_heater(name) {
+            let (current, target) = value.split_once('/').map_or((value, None), |(current, target)| (current, Some(target)));
+            let Ok(current) = current.parse() else { continue };
+            heaters.push(Heater {
+                name: name.to_string(),
+                current,
+                target: target.and_then(|target| target.parse().ok()),
+                power: None,
+            });
+        }
+    }
+    if heaters.is_empty() {
+        return None;
+    }
+    if heaters.iter().any(|heater| heater.name.len() > 1 && heater.name.starts_with('T')) {
+        heaters.retain(|heater| heater.name != "T");
+    }
+    for heater in &mut heaters {
+        heater.power = powers.iter().find(|(name, _)| *name == heater.name).and_then(|(_, power)| *power);
+    }
+
+    let mut temperatures = Temperatures { hotends: Vec::new(), bed: None, chamber: None };
+    for heater in heaters {
+        match heater.name.as_str() {
+            "B" => temperatures.bed = Some(heater),
+            "C" => temperatures.chamber = Some(heater),
+            _ => temperatures.hotends.push(heater),
+        }
+    }
+    Some(temperatures)
+}
+
+/// `T`, `T0`..`T9`, `B` for the bed or `C` for the chamber
+fn is_heater(name: &str) -> bool {
+    match name.strip_prefix('T') {
+        Some(index) => index.bytes().all(|b| b.is_ascii_digit()),
+        None => name == "B" || name == "C",
+    }
+}
+
+/// Logical position in millimetres, answering `M114`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Position {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub e: Option<f64>,
+}
+
+/// Parse an `M114` report such as `X:10.00 Y:20.00 Z:0.30 E:0.00 Count X:800 Y:1600 Z:120`
+///
+/// The stepper counts after `Count` are ignored; `None` for any other line.
+pub fn parse_position(line: &str) -> Option<Position> {
+    let logical = line.split(" Count").next().unwrap_or(line);
+    let axis = |name: &str| {
+        logical
+            .split_whitespace()
+            .find_map(|token| token.strip_prefix(name)?.strip_prefix(':')?.parse().ok())
+    };
+    Some(Position { x: axis("X")?, y: axis("Y")?, z: axis("Z")?, e: axis("E") })
+}
+
+/// Messages Marlin prints as it stops the printer, and what they mean
+const FATAL_ALERTS: &[(&str, &str)] = &[
+    ("thermal runaway", "thermal runaway"),
+    ("heating failed", "heating failed"),
+    ("maxtemp", "temperature above maximum"),
+    ("mintemp", "temperature below minimum"),
+    ("printer halted", "printer halted"),
+    ("kill() called", "printer halted"),
+];
+
+/// What went wrong if `line` is an error after which Marlin halts the printer
+///
+/// A halted printer ignores further commands until it is reset, so a job
+/// should stop at once rather than wait for an `ok`.
+pub fn fatal_alert(line: &str) -> Option<&'static str> {
+    let line = line.trim().to_lowercase();
+    if !(line.starts_with("error:") || line.starts_with("!!")) {
+        return None;
+    }
+    FATAL_ALERTS
+        .iter()
+        .find(|(pattern, _)| line.contains(pattern))
+        .map(|(_, alert)| *alert)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_temperatures() {
+        let report = parse_temperatures("ok T:210.3 /210.0 B:60.1 /60.0 @:127 B@:0").unwrap();
+        assert_eq!(report.hotends.len(), 1);
+        assert_eq!((report.hotends[0].current, report.hotends[0].target, report.hotends[0].power), (210.3, Some(210.0), Some(127)));
+        let bed = report.bed.unwrap();
+        assert_eq!((bed.current, bed.target, bed.power), (60.1, Some(60.0), Some(0)));
+        assert_eq!(report.chamber, None);
+
+        let report = parse_temperatures("T:22.0/0.0 B:21.5/0.0 C:25.0/0.0 T0:22.0/0.0 T1:23.5/200.0 @:0 B@:0 @0:0 @1:90").unwrap();
+        let names: Vec<&str> = report.hotends.iter().map(|heater| heater.name.as_str()).collect();
+        assert_eq!(names, ["T0", "T1"]);
+        assert_eq!((report.hotends[1].target, report.hotends[1].power), (Some(200.0), Some(90)));
+        assert_eq!(report.chamber.map(|chamber| chamber.current), Some(25.0));
+
+        assert_eq!(parse_temperatures("ok"), None);
+        assert_eq!(parse_temperatures("echo:busy: processing"), None);
+    }
+
+    #[test]
+    fn test_parse_position_and_alerts() {
+        let position = parse_position("X:10.00 Y:20.00 Z:0.30 E:1.50 Count X:800 Y:1600 Z:120").unwrap();
+        assert_eq!(position, Position { x: 10.0, y: 20.0, z: 0.3, e: Some(1.5) });
+        assert_eq!(parse_position("ok"), None);
+
+        assert_eq!(fatal_alert("Error:Thermal Runaway, system stopped! Heater_ID: bed"), Some("thermal runaway"));
+        assert_eq!(fatal_alert("Error:MAXTEMP triggered, system stopped! Heater_ID: 0"), Some("temperature above maximum"));
+        assert_eq!(fatal_alert("Error:Printer halted. kill() called!"), Some("printer halted"));
+        assert_eq!(fatal_alert("Error:Line Number is not Last Line Number+1, Last Line: 3"), None);
+        assert_eq!(fatal_alert("echo:Thermal Runaway Protection enabled"), None);
+    }
+}
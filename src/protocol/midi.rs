@@ -0,0 +1,275 @@
+//! MIDI over serial
+//!
+//! MIDI is a 31250 baud byte stream of status bytes (high bit set) followed
+//! by data bytes. Senders may omit a repeated channel status (running
+//! status), real-time bytes such as clock may arrive between any two bytes,
+//! and a system exclusive message runs from `F0` to `F7` across as many reads
+//! as it takes. [`MidiParser`] keeps that state between reads.
+
+use serde::Serialize;
+
+/// MIDI's bit rate
+pub const MIDI_BAUD_RATE: u32 = 31_250;
+
+/// Longest system exclusive message kept; longer ones are dropped
+const MAX_SYSEX_BYTES: usize = 64 * 1024;
+
+/// A decoded message; channels are numbered 1 to 16
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MidiMessage {
+    NoteOff { channel: u8, note: u8, velocity: u8 },
+    /// Note on with velocity 0 is decoded as note off, as receivers treat it
+    NoteOn { channel: u8, note: u8, velocity: u8 },
+    PolyPressure { channel: u8, note: u8, pressure: u8 },
+    ControlChange { channel: u8, controller: u8, value: u8 },
+    ProgramChange { channel: u8, program: u8 },
+    ChannelPressure { channel: u8, pressure: u8 },
+    /// -8192 to 8191, 0 being centred
+    PitchBend { channel: u8, value: i16 },
+    /// The bytes between `F0` and `F7`, manufacturer ID first
+    SysEx { data: Vec<u8> },
+    TimeCode { value: u8 },
+    SongPosition { beats: u16 },
+    SongSelect { song: u8 },
+    TuneRequest,
+    Clock,
+    Start,
+    Continue,
+    Stop,
+    ActiveSensing,
+    Reset,
+}
+
+impl MidiMessage {
+    /// The message's bytes, with a status byte of its own
+    ///
+    /// Fails when the channel is not 1 to 16 or a value does not fit its 7 or 14 bits.
+    pub fn encode(&self) -> Result<Vec<u8>, String> {
+        let channel_message = |kind: u8, channel: u8, data: &[u8]| {
+            if !(1..=16).contains(&channel) {
+                return Err(format!("Channel {} is not 1 to 16", channel));
+            }
+            let mut bytes = vec![kind | (channel - 1)];
+            bytes.extend(data);
+            Ok(bytes)
+        };
+        let bytes = match *self {
+            MidiMessage::NoteOff { channel, note, velocity } => channel_message(0x80, channel, &[note, velocity])?,
+            MidiMessage::NoteOn { channel, note, velocity } => channel_message(0x90, channel, &[note, velocity])?,
+            MidiMessage::PolyPressure { channel, note, pressure } => channel_message(0xA0, channel, &[note, pressure])?,
+            MidiMessage::ControlChange { channel, controller, value } => channel_message(0xB0, channel, &[controller, value])?,
+            MidiMessage::ProgramChange { channel, program } => channel_message(0xC0, channel, &[program])?,
+            MidiMessage::ChannelPressure { channel, pressure } => channel_message(0xD0, channel, &[pressure])?,
+            MidiMessage::PitchBend { channel, value } => {
+                if !(-8192..=8191).contains(&value) {
+                    return Err(format!("Pitch bend {} is not -8192 to 8191", value));
+                }
+                let value = (value + 8192) as u16;
+                channel_message(0xE0, channel, &[(value & 0x7F) as u8, (value >> 7) as u8])?
+            }
+            MidiMessage::SysEx { ref data } => {
+                let mut bytes = vec![0xF0];
+                bytes.extend(data);
+                bytes.push(0xF7);
+                bytes
+            }
+            MidiMessage::TimeCode { value } => vec![0xF1, value],
+            MidiMessage::SongPosition { beats } => {
+                if beats > 0x3FFF {
+                    return Err(format!("Song position {} is more than 16383", beats));
+                }
+                vec![0xF2, (beats & 0x7F) as u8, (beats >> 7) as u8]
+            }
+            MidiMessage::SongSelect { song } => vec![0xF3, song],
+            MidiMessage::TuneRequest => vec![0xF6],
+            MidiMessage::Clock => vec![0xF8],
+            MidiMessage::Start => vec![0xFA],
+            MidiMessage::Continue => vec![0xFB],
+            MidiMessage::Stop => vec![0xFC],
+            MidiMessage::ActiveSensing => vec![0xFE],
+            MidiMessage::Reset => vec![0xFF],
+        };
+        let data = match bytes[0] {
+            0xF0 => &bytes[1..bytes.len() - 1],
+            _ => &bytes[1..],
+        };
+        if let Some(byte) = data.iter().find(|b| **b > 0x7F) {
+            return Err(format!("Data byte {} is more than 127", byte));
+        }
+        Ok(bytes)
+    }
+}
+
+/// Decodes a MIDI byte stream, keeping running status and unfinished messages between reads
+#[derive(Debug, Default)]
+pub struct MidiParser {
+    /// Status of the message being received, kept for running status
+    status: Option<u8>,
+    data: Vec<u8>,
+    /// System exclusive bytes received so far
+    sysex: Option<Vec<u8>>,
+    /// Bytes thrown away: data with no status, or unterminated system exclusive messages
+    dropped: u64,
+}
+
+impl MidiParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Messages completed by `bytes`, in order
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<MidiMessage> {
+        let mut messages = Vec::new();
+        for &byte in bytes {
+            match byte {
+                // Real-time bytes interrupt nothing
+                0xF8..=0xFF => messages.extend(realtime(byte)),
+                0xF0 => {
+                    self.end_sysex();
+                    self.status = None;
+                    self.sysex = Some(Vec::new());
+                }
+                0xF7 => match self.sysex.take() {
+                    Some(data) => messages.push(MidiMessage::SysEx { data }),
+                    None => self.dropped += 1,
+                },
+                0x80..=0xF6 => {
+                    self.end_sysex();
+                    self.dropped += self.data.len() as u64;
+                    self.data.clear();
+                    self.status = match byte {
+                        0xF6 => {
+                            messages.push(MidiMessage::TuneRequest);
+                            None
+                        }
+                        // Undefined; whatever follows is dropped
+                        0xF4 | 0xF5 => None,
+                        _ => Some(byte),
+                    };
+                }
+                _ => {
+                    if let Some(sysex) = &mut self.sysex {
+                        if sysex.len() < MAX_SYSEX_BYTES {
+                            sysex.push(byte);
+                        } else {
+                            self.dropped += sysex.len() as u64 + 1;
+                            self.sysex = None;
+                        }
+                        continue;
+                    }
+                    let Some(status) = self.status else {
+                        self.dropped += 1;
+                        continue;
+                    };
+                    self.data.push(byte);
+                    if self.data.len() == data_length(status) {
+                        messages.extend(decode(status, &self.data));
+                        self.data.clear();
+                        // Only channel messages carry running status
+                        if status >= 0xF0 {
+                            self.status = None;
+                        }
+                    }
+                }
+            }
+        }
+        messages
+    }
+
+    /// Bytes dropped since the last call
+    pub fn take_dropped(&mut self) -> u64 {
+        std::mem::take(&mut self.dropped)
+    }
+
+    /// A status byte cut short a system exclusive message
+    fn end_sysex(&mut self) {
+        if let Some(sysex) = self.sysex.take() {
+            self.dropped += sysex.len() as u64 + 1;
+        }
+    }
+}
+
+/// Data bytes following `status`
+fn data_length(status: u8) -> usize {
+    match status {
+        0xC0..=0xDF | 0xF1 | 0xF3 => 1,
+        _ => 2,
+    }
+}
+
+fn decode(status: u8, data: &[u8]) -> Option<MidiMessage> {
+    let channel = (status & 0x0F) + 1;
+    Some(match (status & 0xF0, data) {
+        (0x80, [note, velocity]) => MidiMessage::NoteOff { channel, note: *note, velocity: *velocity },
+        (0x90, [note, 0]) => MidiMessage::NoteOff { channel, note: *note, velocity: 0 },
+        (0x90, [note, velocity]) => MidiMessage::NoteOn { channel, note: *note, velocity: *velocity },
+        (0xA0, [note, pressure]) => MidiMessage::PolyPressure { channel, note: *note, pressure: *pressure },
+        (0xB0, [controller, value]) => MidiMessage::ControlChange { channel, controller: *controller, value: *value },
+        (0xC0, [program]) => MidiMessage::ProgramChange { channel, program: *program },
+        (0xD0, [pressure]) => MidiMessage::ChannelPressure { channel, pressure: *pressure },
+        (0xE0, [low, high]) => MidiMessage::PitchBend { channel, value: ((u16::from(*high) << 7 | u16::from(*low)) as i16) - 8192 },
+        (0xF0, [value]) if status == 0xF1 => MidiMessage::TimeCode { value: *value },
+        (0xF0, [low, high]) if status == 0xF2 => MidiMessage::SongPosition { beats: u16::from(*high) << 7 | u16::from(*low) },
+        (0xF0, [song]) if status == 0xF3 => MidiMessage::SongSelect { song: *song },
+        _ => return None,
+    })
+}
+
+fn realtime(byte: u8) -> Option<MidiMessage> {
+    Some(match byte {
+        0xF8 => MidiMessage::Clock,
+        0xFA => MidiMessage::Start,
+        0xFB => MidiMessage::Continue,
+        0xFC => MidiMessage::Stop,
+        0xFE => MidiMessage::ActiveSensing,
+        0xFF => MidiMessage::Reset,
+        // 0xF9 and 0xFD are undefined
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_running_status_and_realtime() {
+        let mut parser = MidiParser::new();
+        // Note on, running status with a clock in the middle, then velocity 0
+        let messages = parser.feed(&[0x91, 60, 100, 64, 0xF8, 90, 60, 0]);
+        assert_eq!(
+            messages,
+            vec![
+                MidiMessage::NoteOn { channel: 2, note: 60, velocity: 100 },
+                MidiMessage::Clock,
+                MidiMessage::NoteOn { channel: 2, note: 64, velocity: 90 },
+                MidiMessage::NoteOff { channel: 2, note: 60, velocity: 0 },
+            ]
+        );
+        assert_eq!(parser.feed(&[0xE0, 0x00]), vec![]);
+        assert_eq!(parser.feed(&[0x40]), vec![MidiMessage::PitchBend { channel: 1, value: 0 }]);
+        assert_eq!(parser.take_dropped(), 0);
+    }
+
+    #[test]
+    fn test_sysex_across_reads() {
+        let mut parser = MidiParser::new();
+        assert!(parser.feed(&[0xF0, 0x43, 0x10]).is_empty());
+        assert_eq!(parser.feed(&[0x4C, 0xF8, 0xF7]), vec![MidiMessage::Clock, MidiMessage::SysEx { data: vec![0x43, 0x10, 0x4C] }]);
+
+        // A status byte cuts an unterminated message short
+        assert_eq!(parser.feed(&[0xF0, 0x7E, 0xC0, 5]), vec![MidiMessage::ProgramChange { channel: 1, program: 5 }]);
+        assert_eq!(parser.take_dropped(), 2);
+    }
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(MidiMessage::ControlChange { channel: 16, controller: 7, value: 127 }.encode().unwrap(), [0xBF, 7, 127]);
+        assert_eq!(MidiMessage::PitchBend { channel: 1, value: 8191 }.encode().unwrap(), [0xE0, 0x7F, 0x7F]);
+        assert_eq!(MidiMessage::SysEx { data: vec![0x7E, 0x7F] }.encode().unwrap(), [0xF0, 0x7E, 0x7F, 0xF7]);
+        assert!(MidiMessage::NoteOn { channel: 0, note: 60, velocity: 1 }.encode().is_err());
+        assert!(MidiMessage::NoteOn { channel: 1, note: 128, velocity: 1 }.encode().is_err());
+        assert!(MidiMessage::SysEx { data: vec![0x80] }.encode().is_err());
+    }
+}
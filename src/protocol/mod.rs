@@ -0,0 +1,71 @@
+//! Framing and device protocols
+//!
+//! A [`Framer`] cuts a received byte stream into whole frames and wraps
+//! outgoing frames for the wire, and a [`ChecksumFramer`] adds a checksum to
+//! the frames of another. A connection given a framer reads and writes whole
+//! frames instead of raw bytes. [`modbus`] speaks Modbus RTU as a master,
+//! [`modbus_slave`] simulates a slave and [`modbus_gateway`] forwards Modbus
+//! TCP clients' requests to the slaves on a bus. [`nmea`] parses NMEA 0183 sentences
+//! from GPS modules. [`at`] runs AT command exchanges with modems and [`sms`]
+//! encodes and parses the messages they send and store. [`gcode`] streams
+//! programs to 3D printers and CNC controllers, [`grbl`] speaks the GRBL
+//! controller's status, settings and jog commands, [`marlin`] reads
+//! Marlin printers' temperature and position reports, [`dsmr`] decodes the
+//! telegrams smart meters send on their P1 port, [`slcan`] drives LAWICEL
+//! USB-CAN adapters, [`cat`] controls amateur radio transceivers,
+//! [`midi`] decodes and encodes MIDI messages, [`defmt`] decodes the
+//! binary log frames of firmware using defmt, and [`postcard`] and
+//! [`protobuf`] decode payloads against a described shape or registered
+//! message types. [`frame_spec`] builds and parses frames of one-off vendor
+//! protocols from a declarative layout. [`expect`] waits for the prompts of
+//! consoles driven by dialogue. [`ymodem`] sends and receives files as
+//! bootloaders expect, and [`zmodem`] streams them, resuming where an
+//! interrupted transfer stopped. [`esp`] has the DTR/RTS sequences that reset
+//! ESP8266 and ESP32 boards into their ROM bootloader, and [`esp_loader`]
+//! flashes images through that bootloader. [`stm32`] reads, erases and
+//! programs STM32 parts through their system bootloader, and [`stk500`]
+//! flashes Arduino-class AVR boards from the [`ihex`] images their
+//! toolchains produce.
+
+pub mod at;
+pub mod cat;
+pub mod checksummed;
+pub mod cobs;
+pub mod defmt;
+pub mod delimiter;
+pub mod dsmr;
+pub mod esp;
+pub mod esp_loader;
+pub mod expect;
+pub mod frame_spec;
+pub mod framer;
+pub mod gcode;
+pub mod grbl;
+pub mod hdlc;
+pub mod ihex;
+pub mod length;
+pub mod marlin;
+pub mod midi;
+pub mod modbus;
+pub mod modbus_gateway;
+pub mod modbus_slave;
+pub mod nmea;
+pub mod postcard;
+pub mod protobuf;
+pub mod slcan;
+pub mod slip;
+pub mod sms;
+pub mod stk500;
+pub mod stm32;
+pub mod varint;
+pub mod ymodem;
+pub mod zmodem;
+
+pub use checksummed::ChecksumFramer;
+pub use cobs::CobsFramer;
+pub use delimiter::DelimiterFramer;
+pub use framer::{build_framer, FrameDecoder, FrameError, Framer, FramerKind, FramerOptions, FramerSpec, RawFramer};
+pub use hdlc::HdlcFramer;
+pub use length::{ByteOrder, LengthPrefixConfig, LengthPrefixedFramer};
+pub use slip::SlipFramer;
+pub use varint::VarintFramer;
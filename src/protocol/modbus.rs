@@ -0,0 +1,365 @@
+//! Modbus RTU master
+//!
+//! Builds request frames (slave address, PDU, CRC-16 low byte first), runs
+//! request/response exchanges on a connection and checks the replies. Frames
+//! are separated by at least 3.5 character times of silence, fixed at 1.75 ms
+//! above 19200 baud as the specification recommends.
+
+use std::time::Duration;
+
+use thiserror::Error;
+use tokio::time::Instant;
+
+use crate::serial::{ConnectionConfig, LocalSerialError, SerialConnection};
+use crate::utils::BufferUtils;
+
+/// Slave address every slave obeys without replying
+pub const BROADCAST_ADDRESS: u8 = 0;
+
+/// Highest unicast slave address
+pub const MAX_SLAVE_ADDRESS: u8 = 247;
+
+/// Longest RTU frame: address, 253 byte PDU, CRC
+pub(crate) const MAX_ADU: usize = 256;
+
+/// Time slaves get to act on a broadcast before the next request
+pub(crate) const BROADCAST_TURNAROUND: Duration = Duration::from_millis(100);
+
+/// Length of a slave's exception reply
+const EXCEPTION_LEN: usize = 5;
+
+/// Set on the function code of an exception reply
+pub(crate) const EXCEPTION_FLAG: u8 = 0x80;
+
+/// Why a Modbus exchange failed
+#[derive(Debug, Error)]
+pub enum ModbusError {
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+
+    #[error("Slave exception 0x{code:02X} ({})", exception_name(*code))]
+    Exception { code: u8 },
+
+    #[error("CRC mismatch: received 0x{received:04X}, computed 0x{computed:04X}")]
+    Crc { received: u16, computed: u16 },
+
+    #[error("Unexpected response: {0}")]
+    UnexpectedResponse(String),
+
+    #[error("No complete response within {timeout_ms}ms ({received} bytes received)")]
+    Timeout { timeout_ms: u64, received: usize },
+
+    #[error(transparent)]
+    Serial(#[from] LocalSerialError),
+}
+
+/// Name of a standard exception code
+pub fn exception_name(code: u8) -> &'static str {
+    match code {
+        0x01 => "illegal function",
+        0x02 => "illegal data address",
+        0x03 => "illegal data value",
+        0x04 => "slave device failure",
+        0x05 => "acknowledge",
+        0x06 => "slave device busy",
+        0x08 => "memory parity error",
+        0x0A => "gateway path unavailable",
+        0x0B => "gateway target device failed to respond",
+        _ => "unknown exception",
+    }
+}
+
+/// A request the master can send
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ModbusRequest {
+    ReadCoils { address: u16, count: u16 },
+    ReadHoldingRegisters { address: u16, count: u16 },
+    ReadInputRegisters { address: u16, count: u16 },
+    WriteSingleRegister { address: u16, value: u16 },
+    WriteMultipleRegisters { address: u16, values: Vec<u16> },
+}
+
+/// What a successful exchange returned
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ModbusResponse {
+    Coils(Vec<bool>),
+    Registers(Vec<u16>),
+    /// A write was acknowledged; also the result of a broadcast, which gets no reply
+    Written,
+}
+
+impl ModbusRequest {
+    pub fn function_code(&self) -> u8 {
+        match self {
+            ModbusRequest::ReadCoils { .. } => 0x01,
+            ModbusRequest::ReadHoldingRegisters { .. } => 0x03,
+            ModbusRequest::ReadInputRegisters { .. } => 0x04,
+            ModbusRequest::WriteSingleRegister { .. } => 0x06,
+            ModbusRequest::WriteMultipleRegisters { .. } => 0x10,
+        }
+    }
+
+    pub fn function_name(&self) -> &'static str {
+        match self {
+            ModbusRequest::ReadCoils { .. } => "read coils",
+            ModbusRequest::ReadHoldingRegisters { .. } => "read holding registers",
+            ModbusRequest::ReadInputRegisters { .. } => "read input registers",
+            ModbusRequest::WriteSingleRegister { .. } => "write single register",
+            ModbusRequest::WriteMultipleRegisters { .. } => "write multiple registers",
+        }
+    }
+
+    fn is_write(&self) -> bool {
+        matches!(
+            self,
+            ModbusRequest::WriteSingleRegister { .. } | ModbusRequest::WriteMultipleRegisters { .. }
+        )
+    }
+
+    /// Check the request against the protocol's limits
+    pub fn validate(&self, slave: u8) -> Result<(), ModbusError> {
+        let invalid = |message: String| Err(ModbusError::InvalidRequest(message));
+        if slave > MAX_SLAVE_ADDRESS {
+            return invalid(format!("slave address must be 0 to {}, not {}", MAX_SLAVE_ADDRESS, slave));
+        }
+        if slave == BROADCAST_ADDRESS && !self.is_write() {
+            return invalid("broadcast (slave 0) is only allowed for writes".to_string());
+        }
+
+        let max = match self {
+            ModbusRequest::ReadCoils { .. } => 2000,
+            ModbusRequest::ReadHoldingRegisters { .. } | ModbusRequest::ReadInputRegisters { .. } => 125,
+            ModbusRequest::WriteSingleRegister { .. } => return Ok(()),
+            ModbusRequest::WriteMultipleRegisters { .. } => 123,
+        };
+        let (address, count) = self.items();
+        if count == 0 || count > max {
+            return invalid(format!("{} takes 1 to {} items, not {}", self.function_name(), max, count));
+        }
+        if !self.in_address_range() {
+            return invalid(format!("{} items from address {} run past 65535", count, address));
+        }
+        Ok(())
+    }
+
+    /// First address and number of items the request covers
+    fn items(&self) -> (u16, usize) {
+        match self {
+            ModbusRequest::ReadCoils { address, count }
+            | ModbusRequest::ReadHoldingRegisters { address, count }
+            | ModbusRequest::ReadInputRegisters { address, count } => (*address, *count as usize),
+            ModbusRequest::WriteSingleRegister { address, .. } => (*address, 1),
+            ModbusRequest::WriteMultipleRegisters { address, values } => (*address, values.len()),
+        }
+    }
+
+    /// Whether every item the request covers has an address below 65536
+    pub(crate) fn in_address_range(&self) -> bool {
+        let (address, count) = self.items();
+        address as usize + count <= 0x10000
+    }
+
+    /// The request frame for `slave`, CRC included
+    pub fn encode(&self, slave: u8) -> Vec<u8> {
+        let mut frame = vec![slave, self.function_code()];
+        match self {
+            ModbusRequest::ReadCoils { address, count }
+            | ModbusRequest::ReadHoldingRegisters { address, count }
+            | ModbusRequest::ReadInputRegisters { address, count } => {
+                frame.extend(address.to_be_bytes());
+                frame.extend(count.to_be_bytes());
+            }
+            ModbusRequest::WriteSingleRegister { address, value } => {
+                frame.extend(address.to_be_bytes());
+                frame.extend(value.to_be_bytes());
+            }
+            ModbusRequest::WriteMultipleRegisters { address, values } => {
+                frame.extend(address.to_be_bytes());
+                frame.extend((values.len() as u16).to_be_bytes());
+                frame.push((values.len() * 2) as u8);
+                frame.extend(values.iter().flat_map(|value| value.to_be_bytes()));
+            }
+        }
+        frame.extend(BufferUtils::crc16_modbus(&frame).to_le_bytes());
+        frame
+    }
+
+    /// Length of the complete reply, judged from the bytes received so far
+    fn response_len(&self, received: &[u8]) -> usize {
+        if received.len() >= 2 && received[1] & EXCEPTION_FLAG != 0 {
+            return EXCEPTION_LEN;
+        }
+        match self {
+            ModbusRequest::ReadCoils { count, .. } => 5 + (*count as usize).div_ceil(8),
+            ModbusRequest::ReadHoldingRegisters { count, .. } | ModbusRequest::ReadInputRegisters { count, .. } => {
+                5 + *count as usize * 2
+            }
+            ModbusRequest::WriteSingleRegister { .. } | ModbusRequest::WriteMultipleRegisters { .. } => 8,
+        }
+    }
+
+    /// Check a complete reply from `slave` and extract its contents
+    pub fn parse_response(&self, slave: u8, frame: &[u8]) -> Result<ModbusResponse, ModbusError> {
+        let unexpected = |message: String| Err(ModbusError::UnexpectedResponse(message));
+        if frame.len() < EXCEPTION_LEN {
+            return unexpected(format!("{} byte frame is too short", frame.len()));
+        }
+
+        let (body, crc) = frame.split_at(frame.len() - 2);
+        let received = u16::from_le_bytes([crc[0], crc[1]]);
+        let computed = BufferUtils::crc16_modbus(body);
+        if received != computed {
+            return Err(ModbusError::Crc { received, computed });
+        }
+        if body[0] != slave {
+            return unexpected(format!("reply from slave {} instead of {}", body[0], slave));
+        }
+        if body[1] == self.function_code() | EXCEPTION_FLAG {
+            return Err(ModbusError::Exception { code: body[2] });
+        }
+        if body[1] != self.function_code() {
+            return unexpected(format!("function 0x{:02X} instead of 0x{:02X}", body[1], self.function_code()));
+        }
+        if frame.len() != self.response_len(frame) {
+            return unexpected(format!("{} bytes instead of {}", frame.len(), self.response_len(frame)));
+        }
+
+        match self {
+            ModbusRequest::ReadCoils { count, .. } => {
+                let bits = &body[3..];
+                if body[2] as usize != bits.len() {
+                    return unexpected(format!("byte count {} for {} data bytes", body[2], bits.len()));
+                }
+                Ok(ModbusResponse::Coils(
+                    (0..*count as usize).map(|i| bits[i / 8] & (1 << (i % 8)) != 0).collect(),
+                ))
+            }
+            ModbusRequest::ReadHoldingRegisters { .. } | ModbusRequest::ReadInputRegisters { .. } => {
+                let data = &body[3..];
+                if body[2] as usize != data.len() {
+                    return unexpected(format!("byte count {} for {} data bytes", body[2], data.len()));
+                }
+                Ok(ModbusResponse::Registers(
+                    data.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect(),
+                ))
+            }
+            ModbusRequest::WriteSingleRegister { .. } | ModbusRequest::WriteMultipleRegisters { .. } => {
+                // The reply echoes the request's address and value or count
+                let request = self.encode(slave);
+                if body[2..6] != request[2..6] {
+                    return unexpected(format!("echo {} does not match the request", hex::encode_upper(&body[2..6])));
+                }
+                Ok(ModbusResponse::Written)
+            }
+        }
+    }
+}
+
+/// Silence that separates frames: 3.5 character times, 1.75 ms above 19200 baud
+pub fn frame_gap(config: &ConnectionConfig) -> Duration {
+    if config.baud_rate > 19_200 {
+        Duration::from_micros(1750)
+    } else {
+        config.char_time() * 7 / 2
+    }
+}
+
+/// Send `request` to `slave` on `connection` and wait for the reply
+///
+/// Stale received bytes are discarded first, so they cannot be mistaken for
+/// the reply. Broadcasts get no reply and return once slaves have had time to
+/// act on them.
+pub async fn transaction(
+    connection: &SerialConnection,
+    slave: u8,
+    request: &ModbusRequest,
+    timeout_ms: u64,
+) -> Result<ModbusResponse, ModbusError> {
+    request.validate(slave)?;
+    let _exchange = connection.begin_exchange().await;
+
+    let mut stale = [0u8; MAX_ADU];
+    while let Ok(n @ 1..) = connection.read(&mut stale, Some(0)).await {
+        connection.record_event(format!("Modbus: discarded {} stale bytes before request", n)).await;
+    }
+    let gap = frame_gap(connection.config());
+    tokio::time::sleep(gap).await;
+    connection.write(&request.encode(slave)).await?;
+
+    if slave == BROADCAST_ADDRESS {
+        tokio::time::sleep(BROADCAST_TURNAROUND).await;
+        return Ok(ModbusResponse::Written);
+    }
+
+    let deadline = Instant::now() + Duration::from_millis(timeout_ms);
+    let mut reply = vec![0u8; MAX_ADU];
+    let mut len = 0;
+    while len < request.response_len(&reply[..len]) {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let wanted = request.response_len(&reply[..len]).min(MAX_ADU);
+        match connection.read(&mut reply[len..wanted], Some(remaining.as_millis() as u64)).await {
+            Ok(0) | Err(LocalSerialError::ReadTimeout) => {
+                return Err(ModbusError::Timeout { timeout_ms, received: len });
+            }
+            Ok(n) => len += n,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    reply.truncate(len);
+
+    // Keep the line quiet for a gap before anything else is sent
+    tokio::time::sleep(gap).await;
+    request.parse_response(slave, &reply)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_crc(mut frame: Vec<u8>) -> Vec<u8> {
+        frame.extend(BufferUtils::crc16_modbus(&frame).to_le_bytes());
+        frame
+    }
+
+    #[test]
+    fn test_modbus_request_frames() {
+        // Reference frame: read 3 holding registers from 0x006B on slave 17
+        let request = ModbusRequest::ReadHoldingRegisters { address: 0x006B, count: 3 };
+        assert_eq!(request.encode(0x11), vec![0x11, 0x03, 0x00, 0x6B, 0x00, 0x03, 0x76, 0x87]);
+
+        let request = ModbusRequest::WriteMultipleRegisters { address: 1, values: vec![0x000A, 0x0102] };
+        assert_eq!(&request.encode(1)[..11], &[0x01, 0x10, 0x00, 0x01, 0x00, 0x02, 0x04, 0x00, 0x0A, 0x01, 0x02]);
+
+        assert!(request.validate(MAX_SLAVE_ADDRESS + 1).is_err());
+        assert!(ModbusRequest::ReadCoils { address: 0, count: 8 }.validate(BROADCAST_ADDRESS).is_err());
+        assert!(ModbusRequest::ReadHoldingRegisters { address: 0, count: 126 }.validate(1).is_err());
+        assert!(ModbusRequest::ReadInputRegisters { address: 65535, count: 2 }.validate(1).is_err());
+        assert!(request.validate(BROADCAST_ADDRESS).is_ok());
+    }
+
+    #[test]
+    fn test_modbus_response_parsing() {
+        let request = ModbusRequest::ReadHoldingRegisters { address: 0x006B, count: 3 };
+        let reply = with_crc(vec![0x11, 0x03, 0x06, 0x02, 0x2B, 0x00, 0x00, 0x00, 0x64]);
+        assert_eq!(request.response_len(&reply), reply.len());
+        assert_eq!(request.parse_response(0x11, &reply).unwrap(), ModbusResponse::Registers(vec![0x022B, 0, 0x64]));
+        assert!(matches!(request.parse_response(0x12, &reply), Err(ModbusError::UnexpectedResponse(_))));
+
+        let mut corrupted = reply.clone();
+        corrupted[4] ^= 0xFF;
+        assert!(matches!(request.parse_response(0x11, &corrupted), Err(ModbusError::Crc { .. })));
+
+        let exception = with_crc(vec![0x11, 0x83, 0x02]);
+        assert_eq!(request.response_len(&exception[..2]), EXCEPTION_LEN);
+        let error = request.parse_response(0x11, &exception).unwrap_err();
+        assert_eq!(error.to_string(), "Slave exception 0x02 (illegal data address)");
+
+        let coils = ModbusRequest::ReadCoils { address: 0x13, count: 10 };
+        let reply = with_crc(vec![0x11, 0x01, 0x02, 0xCD, 0x01]);
+        let expected = [true, false, true, true, false, false, true, true, true, false];
+        assert_eq!(coils.parse_response(0x11, &reply).unwrap(), ModbusResponse::Coils(expected.to_vec()));
+
+        let write = ModbusRequest::WriteSingleRegister { address: 1, value: 3 };
+        assert_eq!(write.parse_response(0x11, &write.encode(0x11)).unwrap(), ModbusResponse::Written);
+    }
+}
@@ -0,0 +1,356 @@
+//! Modbus TCP to RTU gateway
+//!
+//! Listens for Modbus TCP clients such as SCADA systems and forwards each
+//! request to the slaves on a serial RTU connection, so they share the bus
+//! with the server's own Modbus tools. The unit identifier becomes the slave
+//! address; any function code is forwarded as is. Requests are taken one at
+//! a time per bus, as RTU requires. When a slave does not answer properly the
+//! client gets exception 0x0B, and 0x0A when the bus cannot be used.
+
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::{JoinHandle, JoinSet};
+use tokio::time::Instant;
+
+use super::modbus::{frame_gap, BROADCAST_ADDRESS, BROADCAST_TURNAROUND, EXCEPTION_FLAG, MAX_ADU, MAX_SLAVE_ADDRESS};
+use crate::serial::{LocalSerialError, SerialConnection};
+use crate::utils::BufferUtils;
+
+/// Exception for a unit the gateway cannot reach
+pub const GATEWAY_PATH_UNAVAILABLE: u8 = 0x0A;
+
+/// Exception for a slave that gave no valid reply
+pub const GATEWAY_TARGET_FAILED: u8 = 0x0B;
+
+/// MBAP header: transaction, protocol and length, then the unit identifier
+const MBAP_LEN: usize = 7;
+
+/// Longest PDU, the RTU frame less its address and CRC
+const MAX_PDU: usize = MAX_ADU - 3;
+
+/// Silence that ends a reply to a function whose reply length is not known
+const UNKNOWN_REPLY_IDLE: Duration = Duration::from_millis(50);
+
+/// Bytes in a reply to `function`, as far as `reply` so far tells, or `None`
+/// for a function whose replies can only be delimited by silence
+fn reply_len(function: u8, reply: &[u8]) -> Option<usize> {
+    let Some(&replied) = reply.get(1) else {
+        return Some(2);
+    };
+    if replied == function | EXCEPTION_FLAG {
+        return Some(5);
+    }
+    match function {
+        // Byte count, then that many bytes
+        0x01..=0x04 | 0x0C | 0x11 | 0x14 | 0x15 | 0x17 => Some(reply.get(2).map_or(3, |&count| 5 + count as usize)),
+        0x05 | 0x06 | 0x0F | 0x10 => Some(8),
+        0x16 => Some(10),
+        0x07 => Some(5),
+        0x0B => Some(8),
+        _ => None,
+    }
+}
+
+/// The RTU frame carrying `pdu` to `unit`
+fn rtu_request(unit: u8, pdu: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(pdu.len() + 3);
+    frame.push(unit);
+    frame.extend(pdu);
+    frame.extend(BufferUtils::crc16_modbus(&frame).to_le_bytes());
+    frame
+}
+
+/// The PDU of a reply from `unit` to `function`, if the frame is one
+fn reply_pdu(unit: u8, function: u8, frame: &[u8]) -> Option<&[u8]> {
+    if frame.len() < 4 {
+        return None;
+    }
+    let (body, crc) = frame.split_at(frame.len() - 2);
+    let crc_ok = BufferUtils::crc16_modbus(body).to_le_bytes() == crc;
+    let ours = body[0] == unit && (body[1] == function || body[1] == function | EXCEPTION_FLAG);
+    (crc_ok && ours).then_some(&body[1..])
+}
+
+fn exception_pdu(function: u8, code: u8) -> Vec<u8> {
+    vec![function | EXCEPTION_FLAG, code]
+}
+
+/// The Modbus TCP frame carrying `pdu` back to the client
+fn tcp_reply(transaction: u16, unit: u8, pdu: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(MBAP_LEN + pdu.len());
+    frame.extend(transaction.to_be_bytes());
+    frame.extend(0u16.to_be_bytes());
+    frame.extend(((pdu.len() + 1) as u16).to_be_bytes());
+    frame.push(unit);
+    frame.extend(pdu);
+    frame
+}
+
+/// What the gateway has done so far
+#[derive(Debug, Clone, Default)]
+pub struct GatewayStats {
+    /// TCP connections accepted
+    pub clients: u64,
+    /// TCP connections still open
+    pub open_clients: u64,
+    /// Requests forwarded to the bus, broadcasts included
+    pub requests: u64,
+    /// Replies from slaves passed back, exception replies included
+    pub replies: u64,
+    /// Requests a slave did not answer in time
+    pub timeouts: u64,
+    /// Replies dropped for a bad CRC or from the wrong slave or function
+    pub bad_replies: u64,
+    /// Requests answered by the gateway with an exception of its own
+    pub gateway_exceptions: u64,
+    /// Why the gateway stopped accepting clients, if it failed
+    pub error: Option<String>,
+}
+
+/// A gateway serving Modbus TCP clients until stopped or dropped
+#[derive(Debug)]
+pub struct ModbusGateway {
+    connection: Arc<SerialConnection>,
+    local_addr: SocketAddr,
+    timeout_ms: u64,
+    stats: Arc<Mutex<GatewayStats>>,
+    started_at: DateTime<Utc>,
+    task: JoinHandle<()>,
+}
+
+impl ModbusGateway {
+    /// Serve clients of `listener`, waiting up to `timeout_ms` for each slave reply
+    pub fn start(listener: TcpListener, connection: Arc<SerialConnection>, timeout_ms: u64) -> std::io::Result<Self> {
+        let local_addr = listener.local_addr()?;
+        let stats = Arc::new(Mutex::new(GatewayStats::default()));
+        let task = tokio::spawn(accept(listener, Arc::clone(&connection), timeout_ms, Arc::clone(&stats)));
+
+        Ok(Self {
+            connection,
+            local_addr,
+            timeout_ms,
+            stats,
+            started_at: Utc::now(),
+            task,
+        })
+    }
+
+    pub fn connection(&self) -> &Arc<SerialConnection> {
+        &self.connection
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    pub fn timeout_ms(&self) -> u64 {
+        self.timeout_ms
+    }
+
+    pub fn started_at(&self) -> DateTime<Utc> {
+        self.started_at
+    }
+
+    pub fn stats(&self) -> GatewayStats {
+        self.stats.lock().unwrap().clone()
+    }
+
+    pub fn is_running(&self) -> bool {
+        !self.task.is_finished()
+    }
+
+    /// Stop listening and disconnect every client
+    pub fn stop(&self) {
+        self.task.abort();
+    }
+}
+
+impl Drop for ModbusGateway {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn accept(listener: TcpListener, connection: Arc<SerialConnection>, timeout_ms: u64, stats: Arc<Mutex<GatewayStats>>) {
+    // Client tasks are aborted with this one
+    let mut clients = JoinSet::new();
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, peer)) => {
+                    {
+                        let mut stats = stats.lock().unwrap();
+                        stats.clients += 1;
+                        stats.open_clients += 1;
+                    }
+                    connection.record_event(format!("Modbus gateway: client {} connected", peer)).await;
+                    let connection = Arc::clone(&connection);
+                    let stats = Arc::clone(&stats);
+                    clients.spawn(async move {
+                        let result = serve_client(stream, &connection, timeout_ms, &stats).await;
+                        stats.lock().unwrap().open_clients -= 1;
+                        let reason = result.err().map(|e| format!(": {}", e)).unwrap_or_default();
+                        connection.record_event(format!("Modbus gateway: client {} disconnected{}", peer, reason)).await;
+                    });
+                }
+                Err(e) => {
+                    stats.lock().unwrap().error = Some(e.to_string());
+                    return;
+                }
+            },
+            Some(_) = clients.join_next() => {}
+        }
+    }
+}
+
+/// Answer one client's requests until it disconnects or breaks the protocol
+async fn serve_client(
+    mut stream: TcpStream,
+    connection: &SerialConnection,
+    timeout_ms: u64,
+    stats: &Mutex<GatewayStats>,
+) -> std::io::Result<()> {
+    loop {
+        let mut header = [0u8; MBAP_LEN];
+        match stream.read_exact(&mut header).await {
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e),
+        }
+        let transaction = u16::from_be_bytes([header[0], header[1]]);
+        let protocol = u16::from_be_bytes([header[2], header[3]]);
+        let length = u16::from_be_bytes([header[4], header[5]]) as usize;
+        let unit = header[6];
+        // The stream cannot be resynchronised after a bad header
+        if protocol != 0 || !(2..=MAX_PDU + 1).contains(&length) {
+            let error = format!("invalid MBAP header {}", hex::encode_upper(header));
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, error));
+        }
+        let mut pdu = vec![0u8; length - 1];
+        stream.read_exact(&mut pdu).await?;
+
+        if let Some(reply) = forward(connection, unit, &pdu, timeout_ms, stats).await {
+            stream.write_all(&tcp_reply(transaction, unit, &reply)).await?;
+        }
+    }
+}
+
+/// Pass `pdu` to `unit` on the bus, returning the reply PDU for the client
+///
+/// Broadcasts get no reply, as on the bus.
+async fn forward(connection: &SerialConnection, unit: u8, pdu: &[u8], timeout_ms: u64, stats: &Mutex<GatewayStats>) -> Option<Vec<u8>> {
+    let function = pdu[0];
+    let gateway_exception = |code: u8| {
+        stats.lock().unwrap().gateway_exceptions += 1;
+        Some(exception_pdu(function, code))
+    };
+    if unit > MAX_SLAVE_ADDRESS {
+        return gateway_exception(GATEWAY_PATH_UNAVAILABLE);
+    }
+    stats.lock().unwrap().requests += 1;
+
+    match exchange(connection, unit, pdu, timeout_ms).await {
+        Ok(None) => None,
+        Ok(Some(reply)) => match reply_pdu(unit, function, &reply) {
+            Some(reply) => {
+                stats.lock().unwrap().replies += 1;
+                Some(reply.to_vec())
+            }
+            None => {
+                stats.lock().unwrap().bad_replies += 1;
+                gateway_exception(GATEWAY_TARGET_FAILED)
+            }
+        },
+        Err(LocalSerialError::ReadTimeout) => {
+            stats.lock().unwrap().timeouts += 1;
+            gateway_exception(GATEWAY_TARGET_FAILED)
+        }
+        Err(e) => {
+            connection.record_event(format!("Modbus gateway: request to unit {} failed: {}", unit, e)).await;
+            gateway_exception(GATEWAY_PATH_UNAVAILABLE)
+        }
+    }
+}
+
+/// Send the request frame and collect the reply frame, `None` for a broadcast
+async fn exchange(connection: &SerialConnection, unit: u8, pdu: &[u8], timeout_ms: u64) -> Result<Option<Vec<u8>>, LocalSerialError> {
+    let _exchange = connection.begin_exchange().await;
+    let mut stale = [0u8; MAX_ADU];
+    while let Ok(n @ 1..) = connection.read(&mut stale, Some(0)).await {
+        connection.record_event(format!("Modbus gateway: discarded {} stale bytes before request", n)).await;
+    }
+    let gap = frame_gap(connection.config());
+    tokio::time::sleep(gap).await;
+    connection.write(&rtu_request(unit, pdu)).await?;
+
+    if unit == BROADCAST_ADDRESS {
+        tokio::time::sleep(BROADCAST_TURNAROUND).await;
+        return Ok(None);
+    }
+
+    let function = pdu[0];
+    let deadline = Instant::now() + Duration::from_millis(timeout_ms);
+    let mut reply = vec![0u8; MAX_ADU];
+    let mut len = 0;
+    loop {
+        let expected = reply_len(function, &reply[..len]);
+        if expected.is_some_and(|expected| len >= expected) || len == MAX_ADU {
+            break;
+        }
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        // Past the address and function, a reply of unknown length ends with silence
+        let wait = match expected {
+            None if len >= 2 => remaining.min(UNKNOWN_REPLY_IDLE),
+            _ => remaining,
+        };
+        let wanted = expected.unwrap_or(MAX_ADU).min(MAX_ADU);
+        match connection.read(&mut reply[len..wanted], Some(wait.as_millis() as u64)).await {
+            Ok(0) | Err(LocalSerialError::ReadTimeout) if expected.is_none() && len >= 2 => break,
+            Ok(0) => return Err(LocalSerialError::ReadTimeout),
+            Ok(n) => len += n,
+            Err(e) => return Err(e),
+        }
+    }
+    reply.truncate(len);
+
+    // Keep the line quiet for a gap before anything else is sent
+    tokio::time::sleep(gap).await;
+    Ok(Some(reply))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frames_between_tcp_and_rtu() {
+        // Read 3 holding registers from 0x006B on unit 17
+        let pdu = [0x03, 0x00, 0x6B, 0x00, 0x03];
+        assert_eq!(rtu_request(0x11, &pdu), [0x11, 0x03, 0x00, 0x6B, 0x00, 0x03, 0x76, 0x87]);
+
+        let reply = rtu_request(0x11, &[0x03, 0x06, 0x02, 0x2B, 0x00, 0x00, 0x00, 0x64]);
+        assert_eq!(reply_len(0x03, &reply[..1]), Some(2));
+        assert_eq!(reply_len(0x03, &reply[..2]), Some(3));
+        assert_eq!(reply_len(0x03, &reply[..3]), Some(reply.len()));
+        assert_eq!(reply_pdu(0x11, 0x03, &reply).unwrap(), &reply[1..reply.len() - 2]);
+        assert_eq!(reply_pdu(0x12, 0x03, &reply), None);
+        let mut corrupted = reply.clone();
+        corrupted[4] ^= 1;
+        assert_eq!(reply_pdu(0x11, 0x03, &corrupted), None);
+
+        let exception = rtu_request(0x11, &[0x83, 0x02]);
+        assert_eq!(reply_len(0x03, &exception[..2]), Some(5));
+        assert_eq!(reply_pdu(0x11, 0x03, &exception).unwrap(), [0x83, 0x02]);
+        assert_eq!(reply_len(0x2B, &[0x11, 0x2B]), None);
+
+        assert_eq!(
+            tcp_reply(0x0102, 0x11, &exception_pdu(0x03, GATEWAY_TARGET_FAILED)),
+            [0x01, 0x02, 0x00, 0x00, 0x00, 0x03, 0x11, 0x83, 0x0B]
+        );
+    }
+}
@@ -0,0 +1,396 @@
+//! Modbus RTU slave simulator
+//!
+//! Answers a master's requests on a connection from an in-memory register
+//! map, so masters such as SCADA systems and PLCs can be tested without field
+//! hardware. Unset coils and registers read as zero. Requests are delimited
+//! by the 3.5 character silence and their expected length; frames with a bad
+//! CRC or for another slave are ignored, as a real slave would.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+use super::modbus::{frame_gap, ModbusRequest, ModbusResponse, BROADCAST_ADDRESS, EXCEPTION_FLAG, MAX_ADU};
+use crate::serial::{LocalSerialError, SerialConnection};
+use crate::utils::BufferUtils;
+
+const ILLEGAL_FUNCTION: u8 = 0x01;
+const ILLEGAL_DATA_ADDRESS: u8 = 0x02;
+const ILLEGAL_DATA_VALUE: u8 = 0x03;
+
+/// Silence after which an unfinished request is abandoned
+const PARTIAL_REQUEST_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// How often a paused connection is checked again
+const PAUSED_POLL: Duration = Duration::from_millis(50);
+
+/// One of the simulated data tables
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegisterTable {
+    Coils,
+    Holding,
+    Input,
+}
+
+impl RegisterTable {
+    pub fn parse(value: &str) -> Result<Self, LocalSerialError> {
+        match value.to_lowercase().as_str() {
+            "coils" | "coil" => Ok(RegisterTable::Coils),
+            "holding" => Ok(RegisterTable::Holding),
+            "input" => Ok(RegisterTable::Input),
+            _ => Err(LocalSerialError::InvalidConfig(format!("Unknown register table: {}", value))),
+        }
+    }
+}
+
+impl std::fmt::Display for RegisterTable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RegisterTable::Coils => write!(f, "coils"),
+            RegisterTable::Holding => write!(f, "holding"),
+            RegisterTable::Input => write!(f, "input"),
+        }
+    }
+}
+
+/// Coils and registers of a simulated slave
+#[derive(Debug, Default, Clone)]
+pub struct RegisterMap {
+    coils: BTreeMap<u16, bool>,
+    holding: BTreeMap<u16, u16>,
+    input: BTreeMap<u16, u16>,
+}
+
+impl RegisterMap {
+    /// Values from `address` on, coils as 0 or 1; addresses past 65535 are left out
+    pub fn read(&self, table: RegisterTable, address: u16, count: u16) -> Vec<u16> {
+        let addresses = (address..=u16::MAX).take(count as usize);
+        match table {
+            RegisterTable::Coils => addresses.map(|a| u16::from(self.coils.get(&a).copied().unwrap_or(false))).collect(),
+            RegisterTable::Holding => addresses.map(|a| self.holding.get(&a).copied().unwrap_or(0)).collect(),
+            RegisterTable::Input => addresses.map(|a| self.input.get(&a).copied().unwrap_or(0)).collect(),
+        }
+    }
+
+    /// Store values from `address` on; any non-zero value sets a coil
+    pub fn write(&mut self, table: RegisterTable, address: u16, values: &[u16]) {
+        for (address, &value) in (address..=u16::MAX).zip(values) {
+            match table {
+                RegisterTable::Coils => {
+                    self.coils.insert(address, value != 0);
+                }
+                RegisterTable::Holding => {
+                    self.holding.insert(address, value);
+                }
+                RegisterTable::Input => {
+                    self.input.insert(address, value);
+                }
+            }
+        }
+    }
+
+    /// Carry out a request, returning the reply or an exception code
+    pub fn serve(&mut self, request: &ModbusRequest) -> Result<ModbusResponse, u8> {
+        if request.validate(1).is_err() {
+            return Err(if request.in_address_range() { ILLEGAL_DATA_VALUE } else { ILLEGAL_DATA_ADDRESS });
+        }
+        Ok(match request {
+            ModbusRequest::ReadCoils { address, count } => ModbusResponse::Coils(
+                self.read(RegisterTable::Coils, *address, *count).into_iter().map(|v| v != 0).collect(),
+            ),
+            ModbusRequest::ReadHoldingRegisters { address, count } => {
+                ModbusResponse::Registers(self.read(RegisterTable::Holding, *address, *count))
+            }
+            ModbusRequest::ReadInputRegisters { address, count } => {
+                ModbusResponse::Registers(self.read(RegisterTable::Input, *address, *count))
+            }
+            ModbusRequest::WriteSingleRegister { address, value } => {
+                self.write(RegisterTable::Holding, *address, &[*value]);
+                ModbusResponse::Written
+            }
+            ModbusRequest::WriteMultipleRegisters { address, values } => {
+                self.write(RegisterTable::Holding, *address, values);
+                ModbusResponse::Written
+            }
+        })
+    }
+}
+
+/// Length of the request starting `buffer`, once enough of it has arrived to tell
+fn request_len(buffer: &[u8]) -> Option<usize> {
+    match buffer.get(1)? {
+        0x01..=0x06 => Some(8),
+        0x0F | 0x10 => buffer.get(6).map(|&byte_count| 9 + byte_count as usize),
+        // Unsupported function: the silence that ended the read ends the frame
+        _ => Some(buffer.len()),
+    }
+}
+
+/// Decode a request frame whose CRC has been checked
+///
+/// `Err` carries the exception code to answer with.
+fn decode_request(frame: &[u8]) -> Result<ModbusRequest, u8> {
+    let body = &frame[..frame.len() - 2];
+    if !matches!(body[1], 0x01 | 0x03 | 0x04 | 0x06 | 0x10) {
+        return Err(ILLEGAL_FUNCTION);
+    }
+    let word = |offset: usize| body.get(offset..offset + 2).map(|pair| u16::from_be_bytes([pair[0], pair[1]]));
+    let (Some(address), Some(second)) = (word(2), word(4)) else {
+        return Err(ILLEGAL_DATA_VALUE);
+    };
+
+    match body[1] {
+        0x01 => Ok(ModbusRequest::ReadCoils { address, count: second }),
+        0x03 => Ok(ModbusRequest::ReadHoldingRegisters { address, count: second }),
+        0x04 => Ok(ModbusRequest::ReadInputRegisters { address, count: second }),
+        0x06 => Ok(ModbusRequest::WriteSingleRegister { address, value: second }),
+        0x10 => {
+            let data = body.get(7..).unwrap_or_default();
+            if body.len() < 7 || body[6] as usize != data.len() || data.len() != second as usize * 2 {
+                return Err(ILLEGAL_DATA_VALUE);
+            }
+            let values = data.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect();
+            Ok(ModbusRequest::WriteMultipleRegisters { address, values })
+        }
+        _ => Err(ILLEGAL_FUNCTION),
+    }
+}
+
+/// Reply frame for a served request
+fn encode_reply(slave: u8, request: &ModbusRequest, response: &ModbusResponse) -> Vec<u8> {
+    let mut frame = vec![slave, request.function_code()];
+    match response {
+        ModbusResponse::Coils(coils) => {
+            let mut bytes = vec![0u8; coils.len().div_ceil(8)];
+            for (index, _) in coils.iter().enumerate().filter(|(_, &on)| on) {
+                bytes[index / 8] |= 1 << (index % 8);
+            }
+            frame.push(bytes.len() as u8);
+            frame.extend(bytes);
+        }
+        ModbusResponse::Registers(registers) => {
+            frame.push((registers.len() * 2) as u8);
+            frame.extend(registers.iter().flat_map(|value| value.to_be_bytes()));
+        }
+        // Writes are acknowledged by echoing the address and value or count
+        ModbusResponse::Written => frame.extend(&request.encode(slave)[2..6]),
+    }
+    frame.extend(crc(&frame));
+    frame
+}
+
+/// Exception reply carrying `code`
+fn encode_exception(slave: u8, function: u8, code: u8) -> Vec<u8> {
+    let mut frame = vec![slave, function | EXCEPTION_FLAG, code];
+    frame.extend(crc(&frame));
+    frame
+}
+
+fn crc(data: &[u8]) -> [u8; 2] {
+    BufferUtils::crc16_modbus(data).to_le_bytes()
+}
+
+/// What the simulator has done so far
+#[derive(Debug, Clone, Default)]
+pub struct SimulatorStats {
+    /// Requests addressed to this slave or broadcast
+    pub requests: u64,
+    /// Requests answered with an exception
+    pub exceptions: u64,
+    /// Frames dropped for a bad CRC
+    pub crc_errors: u64,
+    /// Frames for other slaves, ignored
+    pub other_slaves: u64,
+    /// Why the simulator stopped, if it failed
+    pub error: Option<String>,
+}
+
+/// A slave answering requests on a connection until stopped or dropped
+#[derive(Debug)]
+pub struct ModbusSimulator {
+    connection: Arc<SerialConnection>,
+    slave: u8,
+    registers: Arc<Mutex<RegisterMap>>,
+    stats: Arc<Mutex<SimulatorStats>>,
+    started_at: DateTime<Utc>,
+    task: JoinHandle<()>,
+}
+
+impl ModbusSimulator {
+    pub fn start(connection: Arc<SerialConnection>, slave: u8) -> Self {
+        let registers = Arc::new(Mutex::new(RegisterMap::default()));
+        let stats = Arc::new(Mutex::new(SimulatorStats::default()));
+        let task = tokio::spawn(serve(Arc::clone(&connection), slave, Arc::clone(&registers), Arc::clone(&stats)));
+
+        Self {
+            connection,
+            slave,
+            registers,
+            stats,
+            started_at: Utc::now(),
+            task,
+        }
+    }
+
+    pub fn connection(&self) -> &Arc<SerialConnection> {
+        &self.connection
+    }
+
+    pub fn slave(&self) -> u8 {
+        self.slave
+    }
+
+    pub fn started_at(&self) -> DateTime<Utc> {
+        self.started_at
+    }
+
+    /// The register map, to inspect or modify while the simulator runs
+    pub fn registers(&self) -> MutexGuard<'_, RegisterMap> {
+        self.registers.lock().unwrap()
+    }
+
+    pub fn stats(&self) -> SimulatorStats {
+        self.stats.lock().unwrap().clone()
+    }
+
+    pub fn is_running(&self) -> bool {
+        !self.task.is_finished()
+    }
+
+    pub fn stop(&self) {
+        self.task.abort();
+    }
+}
+
+impl Drop for ModbusSimulator {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn serve(
+    connection: Arc<SerialConnection>,
+    slave: u8,
+    registers: Arc<Mutex<RegisterMap>>,
+    stats: Arc<Mutex<SimulatorStats>>,
+) {
+    let gap = frame_gap(connection.config());
+    let mut pending: Vec<u8> = Vec::new();
+    let mut last_rx = Instant::now();
+
+    loop {
+        let data = match connection.read_until_idle(MAX_ADU, gap, None).await {
+            Ok(data) if data.is_empty() => {
+                tokio::time::sleep(PAUSED_POLL).await;
+                continue;
+            }
+            Ok(data) => data,
+            Err(e) => {
+                stats.lock().unwrap().error = Some(e.to_string());
+                return;
+            }
+        };
+        if last_rx.elapsed() > PARTIAL_REQUEST_TIMEOUT {
+            pending.clear();
+        }
+        last_rx = Instant::now();
+        pending.extend(data);
+
+        while let Some(len) = request_len(&pending).filter(|&len| len <= pending.len()) {
+            let frame: Vec<u8> = pending.drain(..len).collect();
+            if let Some(reply) = handle_frame(slave, &frame, &registers, &stats) {
+                if let Err(e) = connection.write(&reply).await {
+                    stats.lock().unwrap().error = Some(e.to_string());
+                    return;
+                }
+            }
+        }
+        if pending.len() >= MAX_ADU {
+            pending.clear();
+        }
+    }
+}
+
+/// Serve one request frame, returning the reply to send if any
+fn handle_frame(
+    slave: u8,
+    frame: &[u8],
+    registers: &Mutex<RegisterMap>,
+    stats: &Mutex<SimulatorStats>,
+) -> Option<Vec<u8>> {
+    let mut stats = stats.lock().unwrap();
+    if frame.len() < 4 || crc(&frame[..frame.len() - 2]) != frame[frame.len() - 2..] {
+        stats.crc_errors += 1;
+        return None;
+    }
+    let address = frame[0];
+    if address != slave && address != BROADCAST_ADDRESS {
+        stats.other_slaves += 1;
+        return None;
+    }
+    stats.requests += 1;
+
+    let result = decode_request(frame).and_then(|request| {
+        let response = registers.lock().unwrap().serve(&request)?;
+        Ok(encode_reply(slave, &request, &response))
+    });
+    let reply = result.unwrap_or_else(|code| {
+        stats.exceptions += 1;
+        encode_exception(slave, frame[1], code)
+    });
+
+    // Broadcasts are carried out without a reply
+    (address != BROADCAST_ADDRESS).then_some(reply)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slave_answers_master_requests() {
+        let registers = Mutex::new(RegisterMap::default());
+        let stats = Mutex::new(SimulatorStats::default());
+        registers.lock().unwrap().write(RegisterTable::Input, 10, &[7, 8]);
+        registers.lock().unwrap().write(RegisterTable::Coils, 0, &[1, 0, 1]);
+
+        let read = ModbusRequest::ReadInputRegisters { address: 10, count: 3 };
+        let reply = handle_frame(5, &read.encode(5), &registers, &stats).unwrap();
+        assert_eq!(read.parse_response(5, &reply).unwrap(), ModbusResponse::Registers(vec![7, 8, 0]));
+
+        let coils = ModbusRequest::ReadCoils { address: 0, count: 3 };
+        let reply = handle_frame(5, &coils.encode(5), &registers, &stats).unwrap();
+        assert_eq!(coils.parse_response(5, &reply).unwrap(), ModbusResponse::Coils(vec![true, false, true]));
+
+        let write = ModbusRequest::WriteMultipleRegisters { address: 100, values: vec![1, 2] };
+        let reply = handle_frame(5, &write.encode(5), &registers, &stats).unwrap();
+        assert_eq!(write.parse_response(5, &reply).unwrap(), ModbusResponse::Written);
+        assert_eq!(registers.lock().unwrap().read(RegisterTable::Holding, 100, 2), vec![1, 2]);
+
+        // Broadcast writes are applied silently; other slaves and bad CRCs are ignored
+        let broadcast = ModbusRequest::WriteSingleRegister { address: 100, value: 9 };
+        assert!(handle_frame(5, &broadcast.encode(0), &registers, &stats).is_none());
+        assert_eq!(registers.lock().unwrap().read(RegisterTable::Holding, 100, 1), vec![9]);
+        assert!(handle_frame(5, &read.encode(6), &registers, &stats).is_none());
+        let mut corrupted = read.encode(5);
+        corrupted[3] ^= 1;
+        assert!(handle_frame(5, &corrupted, &registers, &stats).is_none());
+
+        let too_many = ModbusRequest::ReadHoldingRegisters { address: 0, count: 126 };
+        let reply = handle_frame(5, &too_many.encode(5), &registers, &stats).unwrap();
+        assert!(too_many.parse_response(5, &reply).unwrap_err().to_string().contains("illegal data value"));
+
+        let mut unsupported = vec![5, 0x2B, 0x0E, 0x01, 0x00];
+        unsupported.extend(crc(&unsupported));
+        assert_eq!(request_len(&unsupported), Some(unsupported.len()));
+        let reply = handle_frame(5, &unsupported, &registers, &stats).unwrap();
+        assert_eq!(&reply[..3], &[5, 0xAB, ILLEGAL_FUNCTION]);
+
+        let stats = stats.lock().unwrap();
+        assert_eq!((stats.requests, stats.exceptions, stats.crc_errors, stats.other_slaves), (6, 2, 1, 1));
+    }
+}
@@ -0,0 +1,309 @@
+//! NMEA 0183 sentence parsing
+//!
+//! Splits received text into sentences, validates their checksums and
+//! extracts the fields of the sentences GPS modules send most: GGA (fix),
+//! RMC (recommended minimum) and GSV (satellites in view). Other sentences
+//! are kept as raw fields.
+
+use serde::Serialize;
+use thiserror::Error;
+
+/// Longest sentence the standard allows, `$` to `<LF>` included
+const MAX_SENTENCE_LEN: usize = 82;
+
+/// Why a line is not a valid sentence
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum NmeaError {
+    #[error("Malformed sentence: {0}")]
+    Malformed(String),
+
+    #[error("Checksum mismatch: sentence says {expected:02X}, computed {computed:02X}")]
+    Checksum { expected: u8, computed: u8 },
+}
+
+/// One sentence split into its address and fields
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NmeaSentence {
+    /// Talker ID, e.g. `GP` for GPS or `GN` for combined constellations
+    pub talker: String,
+    /// Sentence type, e.g. `GGA`
+    pub kind: String,
+    pub fields: Vec<String>,
+    /// Whether the sentence carried a checksum; one that does not match is rejected
+    pub checksummed: bool,
+}
+
+impl NmeaSentence {
+    /// Parse one sentence such as `$GPGGA,...*47`, surrounding whitespace allowed
+    pub fn parse(line: &str) -> Result<Self, NmeaError> {
+        let line = line.trim();
+        let malformed = |message: &str| Err(NmeaError::Malformed(format!("{}: {:?}", message, line)));
+        if !line.starts_with(['$', '!']) {
+            return malformed("does not start with $ or !");
+        }
+        if line.len() > MAX_SENTENCE_LEN {
+            return malformed("longer than 82 characters");
+        }
+
+        let (body, checksum) = match line[1..].split_once('*') {
+            Some((body, checksum)) => (body, Some(checksum)),
+            None => (&line[1..], None),
+        };
+        if let Some(checksum) = checksum {
+            let Ok(expected) = u8::from_str_radix(checksum, 16) else {
+                return malformed("checksum is not two hex digits");
+            };
+            let computed = checksum_of(body);
+            if expected != computed {
+                return Err(NmeaError::Checksum { expected, computed });
+            }
+        }
+
+        let mut fields = body.split(',');
+        let address = fields.next().unwrap_or_default();
+        // Proprietary sentences ($P...) have no talker ID
+        let (talker, kind) = if address.starts_with('P') {
+            address.split_at(1)
+        } else if address.len() == 5 && address.is_ascii() {
+            address.split_at(2)
+        } else {
+            return malformed("address is not a talker ID and sentence type");
+        };
+
+        Ok(Self {
+            talker: talker.to_string(),
+            kind: kind.to_string(),
+            fields: fields.map(str::to_string).collect(),
+            checksummed: checksum.is_some(),
+        })
+    }
+
+    /// Field `index` (0 is the first after the address), `None` when absent or empty
+    pub fn field(&self, index: usize) -> Option<&str> {
+        self.fields.get(index).map(String::as_str).filter(|field| !field.is_empty())
+    }
+
+    /// The sentence's contents, decoded where its type is known
+    pub fn message(&self) -> NmeaMessage {
+        match self.kind.as_str() {
+            "GGA" => NmeaMessage::Gga(Gga::from_sentence(self)),
+            "RMC" => NmeaMessage::Rmc(Rmc::from_sentence(self)),
+            "GSV" => NmeaMessage::Gsv(Gsv::from_sentence(self)),
+            _ => NmeaMessage::Other,
+        }
+    }
+}
+
+/// XOR of every character between `$` and `*`
+pub fn checksum_of(body: &str) -> u8 {
+    body.bytes().fold(0, |sum, byte| sum ^ byte)
+}
+
+/// Split received text into sentences, skipping blank lines and text outside sentences
+///
+/// Each line yields a sentence or the reason it was rejected, in order.
+pub fn split_sentences(text: &str) -> Vec<Result<NmeaSentence, NmeaError>> {
+    text.lines()
+        .map(str::trim)
+        .filter_map(|line| {
+            // Start at the sentence marker, so a line cut off at the front still fails its checksum
+            let start = line.find(['$', '!'])?;
+            Some(NmeaSentence::parse(&line[start..]))
+        })
+        .collect()
+}
+
+/// Decoded contents of a sentence
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum NmeaMessage {
+    Gga(Gga),
+    Rmc(Rmc),
+    Gsv(Gsv),
+    Other,
+}
+
+/// GGA: time, position and quality of the fix
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Gga {
+    /// UTC time of the fix, `hhmmss.ss`
+    pub time: Option<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    /// 0 no fix, 1 GPS, 2 DGPS, 4 RTK fixed, 5 RTK float, 6 estimated
+    pub fix_quality: u8,
+    pub satellites: Option<u8>,
+    pub hdop: Option<f64>,
+    /// Altitude above mean sea level in metres
+    pub altitude_m: Option<f64>,
+}
+
+impl Gga {
+    fn from_sentence(sentence: &NmeaSentence) -> Self {
+        Self {
+            time: sentence.field(0).map(str::to_string),
+            latitude: coordinate(sentence.field(1), sentence.field(2)),
+            longitude: coordinate(sentence.field(3), sentence.field(4)),
+            fix_quality: number(sentence.field(5)).unwrap_or(0),
+            satellites: number(sentence.field(6)),
+            hdop: number(sentence.field(7)),
+            altitude_m: number(sentence.field(8)),
+        }
+    }
+
+    pub fn has_fix(&self) -> bool {
+        self.fix_quality > 0 && self.latitude.is_some() && self.longitude.is_some()
+    }
+}
+
+/// RMC: recommended minimum position, speed and course
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Rmc {
+    /// UTC time, `hhmmss.ss`
+    pub time: Option<String>,
+    /// Status `A`: the receiver considers the data valid
+    pub valid: bool,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub speed_knots: Option<f64>,
+    /// Course over ground in degrees true
+    pub course_deg: Option<f64>,
+    /// UTC date, `ddmmyy`
+    pub date: Option<String>,
+}
+
+impl Rmc {
+    fn from_sentence(sentence: &NmeaSentence) -> Self {
+        Self {
+            time: sentence.field(0).map(str::to_string),
+            valid: sentence.field(1) == Some("A"),
+            latitude: coordinate(sentence.field(2), sentence.field(3)),
+            longitude: coordinate(sentence.field(4), sentence.field(5)),
+            speed_knots: number(sentence.field(6)),
+            course_deg: number(sentence.field(7)),
+            date: sentence.field(8).map(str::to_string),
+        }
+    }
+
+    pub fn has_fix(&self) -> bool {
+        self.valid && self.latitude.is_some() && self.longitude.is_some()
+    }
+}
+
+/// GSV: one part of the list of satellites in view
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Gsv {
+    /// Number of GSV sentences in the cycle
+    pub total_messages: Option<u8>,
+    /// Position of this sentence in the cycle, from 1
+    pub message_number: Option<u8>,
+    pub satellites_in_view: Option<u8>,
+    pub satellites: Vec<SatelliteInfo>,
+}
+
+/// A satellite listed in a GSV sentence
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SatelliteInfo {
+    pub prn: u16,
+    pub elevation_deg: Option<u8>,
+    pub azimuth_deg: Option<u16>,
+    /// Signal to noise ratio in dB-Hz, `None` when not tracking
+    pub snr_db: Option<u8>,
+}
+
+impl Gsv {
+    fn from_sentence(sentence: &NmeaSentence) -> Self {
+        // Up to four satellites of four fields each; NMEA 4.1 may append a signal ID
+        let satellites = (0..4)
+            .filter_map(|slot| {
+                let base = 3 + slot * 4;
+                Some(SatelliteInfo {
+                    prn: number(sentence.field(base))?,
+                    elevation_deg: number(sentence.field(base + 1)),
+                    azimuth_deg: number(sentence.field(base + 2)),
+                    snr_db: number(sentence.field(base + 3)),
+                })
+            })
+            .collect();
+
+        Self {
+            total_messages: number(sentence.field(0)),
+            message_number: number(sentence.field(1)),
+            satellites_in_view: number(sentence.field(2)),
+            satellites,
+        }
+    }
+}
+
+fn number<T: std::str::FromStr>(field: Option<&str>) -> Option<T> {
+    field?.parse().ok()
+}
+
+/// Decimal degrees from `ddmm.mmmm` or `dddmm.mmmm` and a hemisphere
+fn coordinate(value: Option<&str>, hemisphere: Option<&str>) -> Option<f64> {
+    let value: f64 = value?.parse().ok()?;
+    let degrees = (value / 100.0).trunc();
+    let decimal = degrees + (value - degrees * 100.0) / 60.0;
+    match hemisphere? {
+        "N" | "E" => Some(decimal),
+        "S" | "W" => Some(-decimal),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_gga_rmc_gsv() {
+        let text = "garbage\r\n\
+            $GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*47\r\n\
+            $GPRMC,123519,A,4807.038,N,01131.000,W,022.4,084.4,230394,003.1,W*78\r\n\
+            $GPGSV,2,1,08,01,40,083,46,02,17,308,,12,07,344,39,14,22,228,45*70\r\n\
+            $GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*48\r\n\
+            PGGA,123519*00\r\n";
+        let sentences = split_sentences(text);
+        assert_eq!(sentences.len(), 4);
+
+        let NmeaMessage::Gga(gga) = sentences[0].as_ref().unwrap().message() else {
+            panic!("not GGA");
+        };
+        assert!(gga.has_fix());
+        assert!((gga.latitude.unwrap() - 48.1173).abs() < 1e-6);
+        assert!((gga.longitude.unwrap() - 11.516_666).abs() < 1e-5);
+        assert_eq!((gga.satellites, gga.altitude_m), (Some(8), Some(545.4)));
+
+        let NmeaMessage::Rmc(rmc) = sentences[1].as_ref().unwrap().message() else {
+            panic!("not RMC");
+        };
+        assert!(rmc.valid);
+        assert!(rmc.longitude.unwrap() < 0.0);
+        assert_eq!(rmc.date.as_deref(), Some("230394"));
+
+        let NmeaMessage::Gsv(gsv) = sentences[2].as_ref().unwrap().message() else {
+            panic!("not GSV");
+        };
+        assert_eq!(gsv.satellites_in_view, Some(8));
+        assert_eq!(gsv.satellites.len(), 4);
+        assert_eq!(gsv.satellites[1].snr_db, None);
+
+        assert_eq!(sentences[3], Err(NmeaError::Checksum { expected: 0x48, computed: 0x47 }));
+    }
+
+    #[test]
+    fn test_sentence_without_checksum_or_fix() {
+        let sentence = NmeaSentence::parse("$GNGGA,,,,,,0,00,99.99,,,,,,").unwrap();
+        assert_eq!((sentence.talker.as_str(), sentence.kind.as_str()), ("GN", "GGA"));
+        assert!(!sentence.checksummed);
+        let NmeaMessage::Gga(gga) = sentence.message() else {
+            panic!("not GGA");
+        };
+        assert!(!gga.has_fix());
+
+        let proprietary = NmeaSentence::parse("$PUBX,00*33").unwrap();
+        assert_eq!((proprietary.talker.as_str(), proprietary.kind.as_str()), ("P", "UBX"));
+        assert_eq!(proprietary.message(), NmeaMessage::Other);
+        assert!(NmeaSentence::parse("$GPGGA,1*ZZ").is_err());
+    }
+}
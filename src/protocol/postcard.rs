@@ -0,0 +1,320 @@
+//! postcard payloads
+//!
+//! postcard is the serde format most embedded Rust host links use. Its
+//! encoding carries no field names or type tags, so decoding needs the shape
+//! of the data: a [`PostcardType`] built from a JSON description such as
+//! `{"struct": [["id", "u32"], ["temps", {"seq": "f32"}]]}`. Integers wider
+//! than a byte are varints (zigzag for signed ones), and lengths, enum
+//! variants and `Option`s are varint prefixes.
+
+use serde_json::{json, Map, Value};
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum PostcardError {
+    #[error("Payload ends inside a {0}")]
+    UnexpectedEnd(&'static str),
+
+    #[error("{0}")]
+    Invalid(String),
+}
+
+/// The shape of a postcard payload
+#[derive(Debug, Clone, PartialEq)]
+pub enum PostcardType {
+    Unit,
+    Bool,
+    U8,
+    U16,
+    U32,
+    U64,
+    U128,
+    I8,
+    I16,
+    I32,
+    I64,
+    I128,
+    F32,
+    F64,
+    Char,
+    String,
+    /// A byte sequence, shown as hex
+    Bytes,
+    Option(Box<PostcardType>),
+    Seq(Box<PostcardType>),
+    /// Fixed-length array: no length prefix
+    Array(Box<PostcardType>, usize),
+    Tuple(Vec<PostcardType>),
+    Struct(Vec<(String, PostcardType)>),
+    Map(Box<PostcardType>, Box<PostcardType>),
+    /// Variants in declaration order, their index being the discriminant
+    Enum(Vec<(String, PostcardType)>),
+}
+
+impl PostcardType {
+    /// Build a type from its JSON description
+    ///
+    /// Scalars are named with strings: `bool`, `u8` to `u128`, `i8` to
+    /// `i128`, `usize`, `isize`, `f32`, `f64`, `char`, `string`, `bytes` and
+    /// `unit`. Compound types are one-key objects: `{"option": T}`,
+    /// `{"seq": T}`, `{"array": [T, length]}`, `{"tuple": [T, ...]}`,
+    /// `{"struct": [[name, T], ...]}`, `{"map": [K, V]}` and
+    /// `{"enum": [[variant, T], ...]}` with `"unit"` for variants without data.
+    pub fn parse(description: &Value) -> Result<Self, String> {
+        match description {
+            Value::String(name) => Self::scalar(name),
+            Value::Object(object) if object.len() == 1 => {
+                let (kind, inner) = object.iter().next().expect("one entry");
+                match kind.as_str() {
+                    "option" => Ok(Self::Option(Box::new(Self::parse(inner)?))),
+                    "seq" => Ok(Self::Seq(Box::new(Self::parse(inner)?))),
+                    "array" => match inner.as_array().map(Vec::as_slice) {
+                        Some([element, Value::Number(length)]) => {
+                            let length = length.as_u64().ok_or_else(|| format!("Array length {} is not a count", length))?;
+                            Ok(Self::Array(Box::new(Self::parse(element)?), length as usize))
+                        }
+                        _ => Err(format!("array takes [type, length], not {}", inner)),
+                    },
+                    "tuple" => match inner {
+                        Value::Array(elements) => Ok(Self::Tuple(elements.iter().map(Self::parse).collect::<Result<_, _>>()?)),
+                        _ => Err(format!("tuple takes a list of types, not {}", inner)),
+                    },
+                    "map" => match inner.as_array().map(Vec::as_slice) {
+                        Some([key, value]) => Ok(Self::Map(Box::new(Self::parse(key)?), Box::new(Self::parse(value)?))),
+                        _ => Err(format!("map takes [key type, value type], not {}", inner)),
+                    },
+                    "struct" => Ok(Self::Struct(named(kind, inner)?)),
+                    "enum" => Ok(Self::Enum(named(kind, inner)?)),
+                    _ => Err(format!("Unknown postcard type {:?}", kind)),
+                }
+            }
+            _ => Err(format!("Expected a type name or a one-key object, not {}", description)),
+        }
+    }
+
+    fn scalar(name: &str) -> Result<Self, String> {
+        Ok(match name {
+            "unit" => Self::Unit,
+            "bool" => Self::Bool,
+            "u8" => Self::U8,
+            "u16" => Self::U16,
+            "u32" => Self::U32,
+            // postcard sends usize and isize as 64-bit varints
+            "u64" | "usize" => Self::U64,
+            "u128" => Self::U128,
+            "i8" => Self::I8,
+            "i16" => Self::I16,
+            "i32" => Self::I32,
+            "i64" | "isize" => Self::I64,
+            "i128" => Self::I128,
+            "f32" => Self::F32,
+            "f64" => Self::F64,
+            "char" => Self::Char,
+            "string" | "str" => Self::String,
+            "bytes" => Self::Bytes,
+            _ => return Err(format!("Unknown postcard type {:?}", name)),
+        })
+    }
+
+    /// Decode a value from the start of `bytes`, returning it and the number of bytes left over
+    pub fn decode(&self, bytes: &[u8]) -> Result<(Value, usize), PostcardError> {
+        let mut reader = Reader { bytes };
+        let value = self.read(&mut reader)?;
+        Ok((value, reader.bytes.len()))
+    }
+
+    fn read(&self, reader: &mut Reader) -> Result<Value, PostcardError> {
+        Ok(match self {
+            Self::Unit => Value::Null,
+            Self::Bool => match reader.byte("bool")? {
+                0 => json!(false),
+                1 => json!(true),
+                other => return Err(PostcardError::Invalid(format!("{} is not a bool", other))),
+            },
+            Self::U8 => json!(reader.byte("u8")?),
+            Self::U16 => json!(reader.varint(3, "u16")? as u16),
+            Self::U32 => json!(reader.varint(5, "u32")? as u32),
+            Self::U64 => json!(reader.varint(10, "u64")? as u64),
+            Self::U128 => wide(reader.varint(19, "u128")?),
+            Self::I8 => json!(reader.byte("i8")? as i8),
+            Self::I16 => json!(zigzag(reader.varint(3, "i16")?) as i16),
+            Self::I32 => json!(zigzag(reader.varint(5, "i32")?) as i32),
+            Self::I64 => json!(zigzag(reader.varint(10, "i64")?) as i64),
+            Self::I128 => {
+                let value = zigzag(reader.varint(19, "i128")?);
+                i64::try_from(value).map_or_else(|_| json!(value.to_string()), |value| json!(value))
+            }
+            Self::F32 => json!(f32::from_le_bytes(reader.take(4, "f32")?.try_into().expect("4 bytes"))),
+            Self::F64 => json!(f64::from_le_bytes(reader.take(8, "f64")?.try_into().expect("8 bytes"))),
+            Self::Char => {
+                let text = reader.string("char")?;
+                let mut chars = text.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => json!(c),
+                    _ => return Err(PostcardError::Invalid(format!("{:?} is not one character", text))),
+                }
+            }
+            Self::String => json!(reader.string("string")?),
+            Self::Bytes => {
+                let length = reader.length("bytes")?;
+                json!(hex::encode_upper(reader.take(length, "bytes")?))
+            }
+            Self::Option(inner) => match reader.byte("option")? {
+                0 => Value::Null,
+                1 => inner.read(reader)?,
+                other => return Err(PostcardError::Invalid(format!("{} is not an option tag", other))),
+            },
+            Self::Seq(element) => {
+                let length = reader.length("seq")?;
+                Value::Array((0..length).map(|_| element.read(reader)).collect::<Result<_, _>>()?)
+            }
+            Self::Array(element, length) => Value::Array((0..*length).map(|_| element.read(reader)).collect::<Result<_, _>>()?),
+            Self::Tuple(elements) => Value::Array(elements.iter().map(|element| element.read(reader)).collect::<Result<_, _>>()?),
+            Self::Struct(fields) => {
+                let mut object = Map::new();
+                for (name, ty) in fields {
+                    object.insert(name.clone(), ty.read(reader)?);
+                }
+                Value::Object(object)
+            }
+            Self::Map(key, value) => {
+                let length = reader.length("map")?;
+                let mut object = Map::new();
+                for _ in 0..length {
+                    let key = match key.read(reader)? {
+                        Value::String(key) => key,
+                        other => other.to_string(),
+                    };
+                    object.insert(key, value.read(reader)?);
+                }
+                Value::Object(object)
+            }
+            Self::Enum(variants) => {
+                let index = reader.varint(5, "enum")?;
+                let (name, ty) = variants
+                    .get(index as usize)
+                    .ok_or_else(|| PostcardError::Invalid(format!("variant {} out of {}", index, variants.len())))?;
+                match ty {
+                    Self::Unit => json!(name),
+                    ty => json!({ name.clone(): ty.read(reader)? }),
+                }
+            }
+        })
+    }
+}
+
+/// `[[name, type], ...]` for structs and enums
+fn named(kind: &str, description: &Value) -> Result<Vec<(String, PostcardType)>, String> {
+    let entries = description.as_array().ok_or_else(|| format!("{} takes a list of [name, type] pairs, not {}", kind, description))?;
+    entries
+        .iter()
+        .map(|entry| match entry.as_array().map(Vec::as_slice) {
+            Some([Value::String(name), ty]) => Ok((name.clone(), PostcardType::parse(ty)?)),
+            _ => Err(format!("{} entries are [name, type] pairs, not {}", kind, entry)),
+        })
+        .collect()
+}
+
+/// A u128 as a JSON number when it fits a u64, otherwise as a string
+fn wide(value: u128) -> Value {
+    u64::try_from(value).map_or_else(|_| json!(value.to_string()), |value| json!(value))
+}
+
+fn zigzag(value: u128) -> i128 {
+    (value >> 1) as i128 ^ -((value & 1) as i128)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize, what: &'static str) -> Result<&'a [u8], PostcardError> {
+        if self.bytes.len() < length {
+            return Err(PostcardError::UnexpectedEnd(what));
+        }
+        let (taken, rest) = self.bytes.split_at(length);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn byte(&mut self, what: &'static str) -> Result<u8, PostcardError> {
+        Ok(self.take(1, what)?[0])
+    }
+
+    /// A LEB128 varint of at most `max_bytes` bytes
+    fn varint(&mut self, max_bytes: usize, what: &'static str) -> Result<u128, PostcardError> {
+        let mut value = 0u128;
+        for index in 0..max_bytes {
+            let byte = self.byte(what)?;
+            value |= u128::from(byte & 0x7F) << (7 * index);
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(PostcardError::Invalid(format!("varint for a {} is longer than {} bytes", what, max_bytes)))
+    }
+
+    /// A length prefix, which cannot exceed the bytes left since elements take at least one each
+    fn length(&mut self, what: &'static str) -> Result<usize, PostcardError> {
+        let length = self.varint(10, what)?;
+        usize::try_from(length)
+            .ok()
+            .filter(|length| *length <= self.bytes.len())
+            .ok_or(PostcardError::UnexpectedEnd(what))
+    }
+
+    fn string(&mut self, what: &'static str) -> Result<String, PostcardError> {
+        let length = self.length(what)?;
+        let bytes = self.take(length, what)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| PostcardError::Invalid(format!("{} is not valid UTF-8", what)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_struct() {
+        let ty = PostcardType::parse(&json!({"struct": [
+            ["temp", "i16"],
+            ["id", "u32"],
+            ["name", "string"],
+            ["readings", {"seq": "f32"}],
+            ["mode", {"enum": [["Idle", "unit"], ["Run", "u8"]]}],
+            ["flag", {"option": "bool"}],
+            ["raw", {"array": ["u8", 2]}],
+        ]}))
+        .unwrap();
+        let payload = [0x05, 0xAC, 0x02, 2, b'h', b'i', 1, 0x00, 0x00, 0xC0, 0x3F, 1, 7, 1, 1, 0xAA, 0xBB, 0xFF];
+        let (value, rest) = ty.decode(&payload).unwrap();
+        assert_eq!(
+            value,
+            json!({"temp": -3, "id": 300, "name": "hi", "readings": [1.5], "mode": {"Run": 7}, "flag": true, "raw": [170, 187]})
+        );
+        assert_eq!(rest, 1);
+
+        assert_eq!(ty.decode(&payload[..4]), Err(PostcardError::UnexpectedEnd("string")));
+        let unit = PostcardType::parse(&json!({"enum": [["Idle", "unit"], ["Run", "u8"]]})).unwrap();
+        assert_eq!(unit.decode(&[0]).unwrap().0, json!("Idle"));
+        assert!(unit.decode(&[2]).is_err());
+    }
+
+    #[test]
+    fn test_parse_descriptions() {
+        assert_eq!(PostcardType::parse(&json!("usize")).unwrap(), PostcardType::U64);
+        assert_eq!(
+            PostcardType::parse(&json!({"map": ["string", {"tuple": ["u8", "bytes"]}]})).unwrap(),
+            PostcardType::Map(Box::new(PostcardType::String), Box::new(PostcardType::Tuple(vec![PostcardType::U8, PostcardType::Bytes])))
+        );
+        assert!(PostcardType::parse(&json!("u24")).is_err());
+        assert!(PostcardType::parse(&json!({"struct": [["a"]]})).is_err());
+        assert!(PostcardType::parse(&json!({"seq": "u8", "option": "u8"})).is_err());
+
+        // u64::MAX as a varint, and a zigzagged i64::MIN
+        let max = [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01];
+        assert_eq!(PostcardType::U64.decode(&max).unwrap().0, json!(u64::MAX));
+        assert_eq!(PostcardType::I64.decode(&max).unwrap().0, json!(i64::MIN));
+    }
+}
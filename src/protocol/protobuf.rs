@@ -0,0 +1,117 @@
+//! Protocol Buffers payloads
+//!
+//! Message types are registered at run time from a compiled
+//! `FileDescriptorSet`, as written by `protoc --include_imports
+//! --descriptor_set_out`, so payloads decode to JSON by message name without
+//! rebuilding the server for each device's schema.
+
+pub use prost_reflect::MessageDescriptor;
+use prost_reflect::{DescriptorPool, DynamicMessage, SerializeOptions};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ProtobufError {
+    #[error("Invalid descriptor set: {0}")]
+    Descriptor(String),
+
+    #[error("Unknown message type {0}; register its descriptor set first")]
+    UnknownMessage(String),
+
+    #[error("Not a valid {message}: {reason}")]
+    Decode { message: String, reason: String },
+}
+
+/// Message types registered so far
+#[derive(Debug, Clone, Default)]
+pub struct ProtobufRegistry {
+    pool: DescriptorPool,
+}
+
+impl ProtobufRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the files of an encoded `FileDescriptorSet`, returning the message types it added
+    ///
+    /// Files already registered with the same content are skipped; a file
+    /// registered again with different content is rejected.
+    pub fn register(&mut self, descriptor_set: &[u8]) -> Result<Vec<String>, ProtobufError> {
+        let before = self.pool.all_messages().len();
+        // Work on a copy so a rejected set leaves the registry as it was
+        let mut pool = self.pool.clone();
+        pool.decode_file_descriptor_set(descriptor_set).map_err(|e| ProtobufError::Descriptor(e.to_string()))?;
+        self.pool = pool;
+        Ok(self.pool.all_messages().skip(before).map(|message| message.full_name().to_string()).collect())
+    }
+
+    /// Fully qualified names of all registered message types
+    pub fn message_names(&self) -> Vec<String> {
+        self.pool.all_messages().map(|message| message.full_name().to_string()).collect()
+    }
+
+    /// The message type named `name`, with or without a leading dot
+    pub fn message(&self, name: &str) -> Result<MessageDescriptor, ProtobufError> {
+        let name = name.trim_start_matches('.');
+        self.pool.get_message_by_name(name).ok_or_else(|| ProtobufError::UnknownMessage(name.to_string()))
+    }
+}
+
+/// Decode one encoded message as JSON
+///
+/// Fields keep their names from the `.proto` file and fields left at their
+/// default are included, so the result shows every field the device sent or
+/// omitted.
+pub fn decode(message: &MessageDescriptor, payload: &[u8]) -> Result<serde_json::Value, ProtobufError> {
+    let decode_failed = |reason: String| ProtobufError::Decode { message: message.full_name().to_string(), reason };
+    let decoded = DynamicMessage::decode(message.clone(), payload).map_err(|e| decode_failed(e.to_string()))?;
+    let options = SerializeOptions::new().use_proto_field_name(true).skip_default_fields(false);
+    decoded
+        .serialize_with_options(serde_json::value::Serializer, &options)
+        .map_err(|e| decode_failed(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost_reflect::prost::Message;
+    use prost_reflect::prost_types::{field_descriptor_proto::Type, DescriptorProto, FieldDescriptorProto, FileDescriptorProto, FileDescriptorSet};
+    use serde_json::json;
+
+    fn descriptor_set() -> Vec<u8> {
+        let field = |name: &str, number: i32, ty: Type| FieldDescriptorProto {
+            name: Some(name.to_string()),
+            number: Some(number),
+            r#type: Some(ty as i32),
+            ..Default::default()
+        };
+        let file = FileDescriptorProto {
+            name: Some("sensor.proto".to_string()),
+            package: Some("sensor".to_string()),
+            syntax: Some("proto3".to_string()),
+            message_type: vec![DescriptorProto {
+                name: Some("Reading".to_string()),
+                field: vec![field("node_id", 1, Type::Uint32), field("temp", 2, Type::Float), field("label", 3, Type::String)],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        FileDescriptorSet { file: vec![file] }.encode_to_vec()
+    }
+
+    #[test]
+    fn test_register_and_decode() {
+        let mut registry = ProtobufRegistry::new();
+        assert_eq!(registry.register(&descriptor_set()).unwrap(), ["sensor.Reading"]);
+        // The same file again adds nothing
+        assert!(registry.register(&descriptor_set()).unwrap().is_empty());
+        assert!(registry.register(b"\x0A\x03bad").is_err());
+        assert_eq!(registry.message_names(), ["sensor.Reading"]);
+
+        let reading = registry.message(".sensor.Reading").unwrap();
+        let payload = [0x08, 0x07, 0x15, 0x00, 0x00, 0xAC, 0x41];
+        assert_eq!(decode(&reading, &payload).unwrap(), json!({"node_id": 7, "temp": 21.5, "label": ""}));
+        assert!(decode(&reading, &[0x08]).is_err());
+        assert!(matches!(registry.message("sensor.Missing"), Err(ProtobufError::UnknownMessage(_))));
+    }
+}
@@ -0,0 +1,247 @@
+//! SLCAN (LAWICEL) CAN adapters
+//!
+//! USB-CAN dongles speaking the LAWICEL protocol take ASCII commands ending
+//! in a carriage return: `S6` sets the bitrate, `O` opens the channel, and
+//! `t1232AABB` sends a frame with ID 0x123 and two data bytes. They answer a
+//! carriage return, `z`/`Z` once a frame is queued, or BEL (0x07) on error,
+//! and report received frames the same way they are sent.
+
+use std::time::Duration;
+
+use serde::Serialize;
+use thiserror::Error;
+use tokio::time::Instant;
+
+use crate::serial::{LocalSerialError, SerialConnection};
+
+/// The adapter's error reply
+pub const BEL: char = '\u{7}';
+
+/// Largest standard (11-bit) and extended (29-bit) identifiers
+pub const MAX_STANDARD_ID: u32 = 0x7FF;
+pub const MAX_EXTENDED_ID: u32 = 0x1FFF_FFFF;
+
+/// Bitrates selected with `S0` to `S8`, in bit/s
+const BITRATES: [u32; 9] = [10_000, 20_000, 50_000, 100_000, 125_000, 250_000, 500_000, 800_000, 1_000_000];
+
+#[derive(Debug, Error)]
+pub enum SlcanError {
+    #[error("Adapter rejected {0:?}")]
+    Rejected(String),
+
+    #[error("No answer to {command:?} within {timeout_ms}ms")]
+    Timeout { command: String, timeout_ms: u64 },
+
+    #[error(transparent)]
+    Serial(#[from] LocalSerialError),
+}
+
+/// The `S` command selecting `bitrate`, which must be one of the standard rates
+pub fn bitrate_command(bitrate: u32) -> Result<String, String> {
+    match BITRATES.iter().position(|rate| *rate == bitrate) {
+        Some(index) => Ok(format!("S{}", index)),
+        None => Err(format!(
+            "Unsupported CAN bitrate {}, use one of {}",
+            bitrate,
+            BITRATES.map(|rate| rate.to_string()).join(", ")
+        )),
+    }
+}
+
+/// A classic CAN frame
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CanFrame {
+    pub id: u32,
+    /// 29-bit identifier rather than 11-bit
+    pub extended: bool,
+    /// Remote transmission request: asks for data and carries none
+    pub rtr: bool,
+    /// Data length code; for remote frames the length requested
+    pub dlc: u8,
+    pub data: Vec<u8>,
+    /// Adapter time stamp in milliseconds, wrapping at 60000, when enabled with `Z1`
+    pub timestamp_ms: Option<u16>,
+}
+
+impl CanFrame {
+    /// A frame to send, checking the identifier fits and the data fits the DLC
+    pub fn new(id: u32, extended: bool, rtr: bool, dlc: u8, data: Vec<u8>) -> Result<Self, String> {
+        let max_id = if extended { MAX_EXTENDED_ID } else { MAX_STANDARD_ID };
+        if id > max_id {
+            return Err(format!("ID 0x{:X} does not fit a {} identifier", id, if extended { "29-bit" } else { "11-bit" }));
+        }
+        if dlc > 8 {
+            return Err(format!("DLC {} is more than 8", dlc));
+        }
+        if rtr && !data.is_empty() {
+            return Err("Remote frames carry no data".to_string());
+        }
+        if !rtr && data.len() != dlc as usize {
+            return Err(format!("{} data bytes given for DLC {}", data.len(), dlc));
+        }
+        Ok(Self { id, extended, rtr, dlc, data, timestamp_ms: None })
+    }
+
+    /// The command sending this frame, without its carriage return
+    pub fn encode(&self) -> String {
+        let kind = match (self.extended, self.rtr) {
+            (false, false) => 't',
+            (true, false) => 'T',
+            (false, true) => 'r',
+            (true, true) => 'R',
+        };
+        let id = if self.extended { format!("{:08X}", self.id) } else { format!("{:03X}", self.id) };
+        let data: String = self.data.iter().map(|b| format!("{:02X}", b)).collect();
+        format!("{}{}{}{}", kind, id, self.dlc, data)
+    }
+
+    /// Parse a received frame such as `t1232AABB` or `T0000012380011223344556677`
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let malformed = |message: &str| Err(format!("{}: {:?}", message, line));
+        let (extended, rtr) = match line.chars().next() {
+            Some('t') => (false, false),
+            Some('T') => (true, false),
+            Some('r') => (false, true),
+            Some('R') => (true, true),
+            _ => return malformed("not a CAN frame"),
+        };
+        let id_end = if extended { 9 } else { 4 };
+        let Some(id) = line.get(1..id_end).and_then(|id| u32::from_str_radix(id, 16).ok()) else {
+            return malformed("identifier is not hex");
+        };
+        let Some(dlc) = line.get(id_end..id_end + 1).and_then(|dlc| dlc.parse::<u8>().ok()).filter(|dlc| *dlc <= 8) else {
+            return malformed("DLC is not 0 to 8");
+        };
+
+        let data_end = id_end + 1 + if rtr { 0 } else { dlc as usize * 2 };
+        let Some(data) = line.get(id_end + 1..data_end).and_then(|data| hex::decode(data).ok()) else {
+            return malformed("data is shorter than the DLC or not hex");
+        };
+        let timestamp_ms = match &line[data_end.min(line.len())..] {
+            "" => None,
+            stamp if stamp.len() == 4 => match u16::from_str_radix(stamp, 16) {
+                Ok(stamp) => Some(stamp),
+                Err(_) => return malformed("time stamp is not hex"),
+            },
+            _ => return malformed("unexpected characters after the data"),
+        };
+
+        let mut frame = Self::new(id, extended, rtr, dlc, data)?;
+        frame.timestamp_ms = timestamp_ms;
+        Ok(frame)
+    }
+}
+
+/// One reply or report from the adapter
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SlcanMessage {
+    /// A bare carriage return: the command succeeded
+    Ok,
+    /// `z` or `Z`: a frame was queued for sending
+    Queued,
+    /// BEL: the command failed
+    Error,
+    Frame(CanFrame),
+    /// A frame line that did not parse, with the reason
+    Invalid(String),
+    /// Anything else, such as a `V` version or `F` status reply
+    Other(String),
+}
+
+/// Split received text into messages, returning how much of `text` was used
+///
+/// An unfinished message at the end is left for the next call.
+pub fn split_messages(text: &str) -> (Vec<SlcanMessage>, usize) {
+    let mut messages = Vec::new();
+    let mut used = 0;
+    while let Some(end) = text[used..].find(['\r', BEL]).map(|offset| used + offset) {
+        let line = text[used..end].trim_matches('\n');
+        messages.push(if text[end..].starts_with(BEL) {
+            SlcanMessage::Error
+        } else {
+            match line {
+                "" => SlcanMessage::Ok,
+                "z" | "Z" => SlcanMessage::Queued,
+                _ if line.starts_with(['t', 'T', 'r', 'R']) => match CanFrame::parse(line) {
+                    Ok(frame) => SlcanMessage::Frame(frame),
+                    Err(e) => SlcanMessage::Invalid(e),
+                },
+                _ => SlcanMessage::Other(line.to_string()),
+            }
+        });
+        used = end + 1;
+    }
+    (messages, used)
+}
+
+/// Send `command` and wait up to `timeout_ms` for the adapter to accept it
+///
+/// Frames received while waiting are returned rather than lost.
+pub async fn command(connection: &SerialConnection, command: &str, timeout_ms: u64) -> Result<Vec<CanFrame>, SlcanError> {
+    let _exchange = connection.begin_exchange().await;
+    connection.write(format!("{}\r", command).as_bytes()).await?;
+
+    let deadline = Instant::now() + Duration::from_millis(timeout_ms);
+    let mut received = String::new();
+    let mut frames = Vec::new();
+    let mut answer = None;
+    connection
+        .read_until(deadline, |chunk| {
+            received.push_str(&String::from_utf8_lossy(chunk));
+            let (messages, used) = split_messages(&received);
+            received.drain(..used);
+            for message in messages {
+                match message {
+                    SlcanMessage::Frame(frame) => frames.push(frame),
+                    SlcanMessage::Ok | SlcanMessage::Queued => answer = answer.or(Some(true)),
+                    SlcanMessage::Error => answer = answer.or(Some(false)),
+                    SlcanMessage::Invalid(_) | SlcanMessage::Other(_) => {}
+                }
+            }
+            answer.is_some()
+        })
+        .await?;
+    match answer {
+        Some(true) => Ok(frames),
+        Some(false) => Err(SlcanError::Rejected(command.to_string())),
+        None => Err(SlcanError::Timeout { command: command.to_string(), timeout_ms }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frames_round_trip() {
+        let frame = CanFrame::new(0x123, false, false, 2, vec![0xAA, 0xBB]).unwrap();
+        assert_eq!(frame.encode(), "t1232AABB");
+        assert_eq!(CanFrame::parse("t1232AABB").unwrap(), frame);
+
+        let extended = CanFrame::parse("T1ABCDEF081122334455667788EA5F").unwrap();
+        assert_eq!((extended.id, extended.extended, extended.dlc), (0x1ABC_DEF0, true, 8));
+        assert_eq!(extended.timestamp_ms, Some(0xEA5F));
+
+        let remote = CanFrame::new(0x7FF, false, true, 4, Vec::new()).unwrap();
+        assert_eq!(remote.encode(), "r7FF4");
+        assert!(CanFrame::parse("R000001234").unwrap().rtr);
+
+        assert!(CanFrame::new(0x800, false, false, 0, Vec::new()).is_err());
+        assert!(CanFrame::new(0x10, false, false, 2, vec![1]).is_err());
+        assert!(CanFrame::parse("t1232AA").is_err());
+        assert!(CanFrame::parse("t12390011223344556677").is_err());
+        assert_eq!(bitrate_command(500_000).unwrap(), "S6");
+        assert!(bitrate_command(33_333).is_err());
+    }
+
+    #[test]
+    fn test_split_messages() {
+        let (messages, used) = split_messages("\rz\r\u{7}t0010\rV1013\rt12");
+        assert_eq!(used, 16);
+        assert_eq!(messages[0], SlcanMessage::Ok);
+        assert_eq!(messages[1], SlcanMessage::Queued);
+        assert_eq!(messages[2], SlcanMessage::Error);
+        assert!(matches!(&messages[3], SlcanMessage::Frame(frame) if frame.id == 1 && frame.data.is_empty()));
+        assert_eq!(messages[4], SlcanMessage::Other("V1013".to_string()));
+    }
+}
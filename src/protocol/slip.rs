@@ -0,0 +1,145 @@
+//! SLIP framing (RFC 1055)
+//!
+//! Frames end with END; END and ESC inside a payload are escaped as
+//! ESC ESC_END and ESC ESC_ESC. Encoded frames also start with END, which
+//! flushes any line noise the receiver picked up before the frame.
+
+use super::framer::{FrameError, Framer};
+
+pub const SLIP_END: u8 = 0xC0;
+pub const SLIP_ESC: u8 = 0xDB;
+pub const SLIP_ESC_END: u8 = 0xDC;
+pub const SLIP_ESC_ESC: u8 = 0xDD;
+
+/// Longest frame accepted before the partial frame is discarded
+pub const SLIP_MAX_FRAME: usize = 64 * 1024;
+
+#[derive(Debug, Default)]
+pub struct SlipFramer {
+    frame: Vec<u8>,
+    escaped: bool,
+    /// The current frame broke a rule and is skipped up to the next END
+    error: Option<FrameError>,
+}
+
+impl SlipFramer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Framer for SlipFramer {
+    fn name(&self) -> &'static str {
+        "slip"
+    }
+
+    fn encode(&self, payload: &[u8]) -> Vec<u8> {
+        let mut encoded = Vec::with_capacity(payload.len() + 2);
+        encoded.push(SLIP_END);
+        for &byte in payload {
+            match byte {
+                SLIP_END => encoded.extend_from_slice(&[SLIP_ESC, SLIP_ESC_END]),
+                SLIP_ESC => encoded.extend_from_slice(&[SLIP_ESC, SLIP_ESC_ESC]),
+                _ => encoded.push(byte),
+            }
+        }
+        encoded.push(SLIP_END);
+        encoded
+    }
+
+    fn decode(&mut self, data: &[u8]) -> Vec<Result<Vec<u8>, FrameError>> {
+        let mut frames = Vec::new();
+
+        for &byte in data {
+            if byte == SLIP_END {
+                if let Some(error) = self.error.take() {
+                    frames.push(Err(error));
+                } else if self.escaped {
+                    frames.push(Err(FrameError::Malformed("frame ends inside an escape".to_string())));
+                } else if !self.frame.is_empty() {
+                    // Back-to-back ENDs delimit empty frames, which are ignored
+                    frames.push(Ok(std::mem::take(&mut self.frame)));
+                }
+                self.frame.clear();
+                self.escaped = false;
+                continue;
+            }
+            if self.error.is_some() {
+                continue;
+            }
+
+            let decoded = if self.escaped {
+                self.escaped = false;
+                match byte {
+                    SLIP_ESC_END => SLIP_END,
+                    SLIP_ESC_ESC => SLIP_ESC,
+                    other => {
+                        self.error = Some(FrameError::Malformed(format!("invalid escape 0x{:02X}", other)));
+                        self.frame.clear();
+                        continue;
+                    }
+                }
+            } else if byte == SLIP_ESC {
+                self.escaped = true;
+                continue;
+            } else {
+                byte
+            };
+
+            if self.frame.len() == SLIP_MAX_FRAME {
+                self.error = Some(FrameError::TooLong(SLIP_MAX_FRAME));
+                self.frame.clear();
+                continue;
+            }
+            self.frame.push(decoded);
+        }
+
+        frames
+    }
+
+    fn pending(&self) -> usize {
+        self.frame.len()
+    }
+
+    fn reset(&mut self) {
+        self.frame.clear();
+        self.escaped = false;
+        self.error = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slip_round_trip() {
+        let payload = [0x01, SLIP_END, 0x02, SLIP_ESC, 0x03];
+        let mut framer = SlipFramer::new();
+        let encoded = framer.encode(&payload);
+        assert_eq!(
+            encoded,
+            vec![SLIP_END, 0x01, SLIP_ESC, SLIP_ESC_END, 0x02, SLIP_ESC, SLIP_ESC_ESC, 0x03, SLIP_END]
+        );
+
+        // Split mid-escape across two reads
+        let (first, second) = encoded.split_at(3);
+        assert!(framer.decode(first).is_empty());
+        assert_eq!(framer.pending(), 1);
+        assert_eq!(framer.decode(second), vec![Ok(payload.to_vec())]);
+        assert_eq!(framer.pending(), 0);
+    }
+
+    #[test]
+    fn test_slip_skips_malformed_frames() {
+        let mut framer = SlipFramer::new();
+        let frames = framer.decode(&[0x41, SLIP_ESC, 0x42, 0x43, SLIP_END, 0x44, SLIP_END, SLIP_END]);
+        assert_eq!(frames.len(), 2);
+        assert!(matches!(frames[0], Err(FrameError::Malformed(_))));
+        assert_eq!(frames[1], Ok(vec![0x44]));
+
+        let mut oversized = vec![0x55; SLIP_MAX_FRAME + 1];
+        oversized.push(SLIP_END);
+        assert_eq!(framer.decode(&oversized), vec![Err(FrameError::TooLong(SLIP_MAX_FRAME))]);
+    }
+}
@@ -0,0 +1,310 @@
+//! SMS on GSM modems (3GPP TS 27.005)
+//!
+//! Builds SMS-SUBMIT PDUs for PDU mode sends, in the GSM 7-bit default
+//! alphabet when the text fits it and UCS-2 otherwise, and parses the stored
+//! message listings modems return in text mode.
+
+use serde::Serialize;
+
+use super::at::AtResponse;
+use crate::serial::LocalSerialError;
+
+/// GSM 03.38 default alphabet, indexed by septet; 0x1B escapes to the extension table
+const GSM7_ALPHABET: &str = "@£$¥èéùìòÇ\nØø\rÅåΔ_ΦΓΛΩΠΨΣΘΞ\u{1b}ÆæßÉ !\"#¤%&'()*+,-./0123456789:;<=>?\
+¡ABCDEFGHIJKLMNOPQRSTUVWXYZÄÖÑÜ§¿abcdefghijklmnopqrstuvwxyzäöñüà";
+
+/// Characters in one single-part message
+const MAX_GSM7_CHARS: usize = 160;
+const MAX_UCS2_CHARS: usize = 70;
+
+/// Relative validity period 0xAA: four days
+const VALIDITY_FOUR_DAYS: u8 = 0xAA;
+
+/// How the modem is told about messages, set with `AT+CMGF`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmsMode {
+    /// Text with the number and text as command arguments
+    Text,
+    /// Hex-encoded PDUs, which also carry texts outside the modem's character set
+    Pdu,
+}
+
+impl SmsMode {
+    pub fn parse(value: &str) -> Result<Self, LocalSerialError> {
+        match value.to_lowercase().as_str() {
+            "text" => Ok(SmsMode::Text),
+            "pdu" => Ok(SmsMode::Pdu),
+            _ => Err(LocalSerialError::InvalidConfig(format!("Unknown SMS mode: {}", value))),
+        }
+    }
+
+    /// `AT+CMGF` value selecting the mode
+    pub fn cmgf(self) -> u8 {
+        match self {
+            SmsMode::Pdu => 0,
+            SmsMode::Text => 1,
+        }
+    }
+}
+
+impl std::fmt::Display for SmsMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SmsMode::Text => write!(f, "text"),
+            SmsMode::Pdu => write!(f, "pdu"),
+        }
+    }
+}
+
+/// Which stored messages to list
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmsStatus {
+    All,
+    Unread,
+    Read,
+    Unsent,
+    Sent,
+}
+
+impl SmsStatus {
+    pub fn parse(value: &str) -> Result<Self, LocalSerialError> {
+        match value.to_lowercase().as_str() {
+            "all" => Ok(SmsStatus::All),
+            "unread" => Ok(SmsStatus::Unread),
+            "read" => Ok(SmsStatus::Read),
+            "unsent" => Ok(SmsStatus::Unsent),
+            "sent" => Ok(SmsStatus::Sent),
+            _ => Err(LocalSerialError::InvalidConfig(format!("Unknown message status: {}", value))),
+        }
+    }
+
+    /// Text mode `AT+CMGL` argument
+    pub fn cmgl(self) -> &'static str {
+        match self {
+            SmsStatus::All => "ALL",
+            SmsStatus::Unread => "REC UNREAD",
+            SmsStatus::Read => "REC READ",
+            SmsStatus::Unsent => "STO UNSENT",
+            SmsStatus::Sent => "STO SENT",
+        }
+    }
+}
+
+impl std::fmt::Display for SmsStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SmsStatus::All => write!(f, "all"),
+            SmsStatus::Unread => write!(f, "unread"),
+            SmsStatus::Read => write!(f, "read"),
+            SmsStatus::Unsent => write!(f, "unsent"),
+            SmsStatus::Sent => write!(f, "sent"),
+        }
+    }
+}
+
+/// A message stored on the modem or SIM
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SmsMessage {
+    /// Storage index, used to read or delete the message
+    pub index: Option<u32>,
+    /// e.g. `REC UNREAD` or `STO SENT`
+    pub status: String,
+    /// Sender of received messages, recipient of stored ones
+    pub number: Option<String>,
+    /// Service centre time stamp, `yy/MM/dd,hh:mm:ss±zz`
+    pub timestamp: Option<String>,
+    pub text: String,
+}
+
+/// Messages in a text mode `AT+CMGL` response
+pub fn parse_message_list(response: &AtResponse) -> Vec<SmsMessage> {
+    let mut messages = Vec::new();
+    let mut lines = response.lines.iter();
+    while let Some(line) = lines.next() {
+        let Some(header) = line.strip_prefix("+CMGL:") else { continue };
+        let fields = split_fields(header);
+        let text = lines.next().cloned().unwrap_or_default();
+        messages.push(SmsMessage {
+            index: fields.first().and_then(|index| index.parse().ok()),
+            status: fields.get(1).cloned().unwrap_or_default(),
+            number: fields.get(2).filter(|field| !field.is_empty()).cloned(),
+            timestamp: fields.get(4).filter(|field| !field.is_empty()).cloned(),
+            text,
+        });
+    }
+    messages
+}
+
+/// The message in a text mode `AT+CMGR` response, `None` when the slot is empty
+pub fn parse_message(index: u32, response: &AtResponse) -> Option<SmsMessage> {
+    let position = response.lines.iter().position(|line| line.starts_with("+CMGR:"))?;
+    let fields = split_fields(&response.lines[position]["+CMGR:".len()..]);
+    Some(SmsMessage {
+        index: Some(index),
+        status: fields.first().cloned().unwrap_or_default(),
+        number: fields.get(1).filter(|field| !field.is_empty()).cloned(),
+        timestamp: fields.get(3).filter(|field| !field.is_empty()).cloned(),
+        text: response.lines.get(position + 1).cloned().unwrap_or_default(),
+    })
+}
+
+/// Comma-separated fields with surrounding quotes removed; commas inside quotes are kept
+fn split_fields(header: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    for c in header.trim().chars() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(String::new()),
+            _ => fields.last_mut().expect("starts with one field").push(c),
+        }
+    }
+    fields
+}
+
+/// Whether `number` is up to 20 digits with an optional leading `+`
+pub fn is_phone_number(number: &str) -> bool {
+    let digits = number.strip_prefix('+').unwrap_or(number);
+    !digits.is_empty() && digits.len() <= 20 && digits.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// A PDU mode SMS-SUBMIT: the hex to send after the prompt and the `AT+CMGS` length
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubmitPdu {
+    pub hex: String,
+    /// Octets after the service centre address
+    pub length: usize,
+    /// `GSM 7-bit` or `UCS-2`
+    pub encoding: &'static str,
+}
+
+/// Encode an SMS-SUBMIT to `number` using the SIM's service centre
+///
+/// Fails when the number is not digits with an optional leading `+`, or the
+/// text is longer than one message.
+pub fn encode_submit(number: &str, text: &str) -> Result<SubmitPdu, String> {
+    if !is_phone_number(number) {
+        return Err(format!("{:?} is not a phone number", number));
+    }
+    let digits = number.strip_prefix('+').unwrap_or(number);
+
+    // First octet: SMS-SUBMIT, relative validity period; message reference set by the modem
+    let mut tpdu = vec![0x11, 0x00, digits.len() as u8, if number.starts_with('+') { 0x91 } else { 0x81 }];
+    tpdu.extend(semi_octets(digits));
+    // Protocol ID, then data coding scheme
+    tpdu.push(0x00);
+
+    let septets: Option<Vec<u8>> = text.chars().map(gsm7_septet).collect();
+    let encoding = match septets {
+        Some(septets) => {
+            if septets.len() > MAX_GSM7_CHARS {
+                return Err(format!("Text is {} characters, one message holds {}", septets.len(), MAX_GSM7_CHARS));
+            }
+            tpdu.extend([0x00, VALIDITY_FOUR_DAYS, septets.len() as u8]);
+            tpdu.extend(pack_septets(&septets));
+            "GSM 7-bit"
+        }
+        None => {
+            let units: Vec<u16> = text.encode_utf16().collect();
+            if units.len() > MAX_UCS2_CHARS {
+                return Err(format!(
+                    "Text is {} UCS-2 characters, one message holds {}",
+                    units.len(),
+                    MAX_UCS2_CHARS
+                ));
+            }
+            tpdu.extend([0x08, VALIDITY_FOUR_DAYS, (units.len() * 2) as u8]);
+            tpdu.extend(units.iter().flat_map(|unit| unit.to_be_bytes()));
+            "UCS-2"
+        }
+    };
+
+    // Leading 00: no service centre address, the modem uses the SIM's
+    let hex: String = std::iter::once(0u8).chain(tpdu.iter().copied()).map(|b| format!("{:02X}", b)).collect();
+    Ok(SubmitPdu { hex, length: tpdu.len(), encoding })
+}
+
+/// Septet for `c` in the default alphabet, `None` when it needs UCS-2
+fn gsm7_septet(c: char) -> Option<u8> {
+    if c == '\u{1b}' {
+        return None;
+    }
+    GSM7_ALPHABET.chars().position(|letter| letter == c).map(|position| position as u8)
+}
+
+/// Digits as swapped nibbles, padded with F
+fn semi_octets(digits: &str) -> Vec<u8> {
+    digits
+        .as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            let low = pair[0] - b'0';
+            let high = pair.get(1).map_or(0x0F, |digit| digit - b'0');
+            high << 4 | low
+        })
+        .collect()
+}
+
+/// Pack 7-bit septets into octets, least significant bits first
+fn pack_septets(septets: &[u8]) -> Vec<u8> {
+    let mut packed = Vec::with_capacity(septets.len() * 7 / 8 + 1);
+    let mut bits = 0u32;
+    let mut held = 0;
+    for &septet in septets {
+        bits |= u32::from(septet) << held;
+        held += 7;
+        while held >= 8 {
+            packed.push(bits as u8);
+            bits >>= 8;
+            held -= 8;
+        }
+    }
+    if held > 0 {
+        packed.push(bits as u8);
+    }
+    packed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_submit() {
+        let pdu = encode_submit("+46708251358", "hellohello").unwrap();
+        assert_eq!(pdu.hex, "0011000B916407281553F80000AA0AE8329BFD4697D9EC37");
+        assert_eq!((pdu.length, pdu.encoding), (23, "GSM 7-bit"));
+
+        let pdu = encode_submit("0123", "Hi €").unwrap();
+        assert_eq!(pdu.hex, "001100048110320008AA0800480069002020AC");
+        assert_eq!((pdu.length, pdu.encoding), (18, "UCS-2"));
+
+        assert!(encode_submit("+1 555", "x").is_err());
+        assert!(encode_submit("5551234", &"x".repeat(161)).is_err());
+    }
+
+    #[test]
+    fn test_parse_stored_messages() {
+        let listing = AtResponse {
+            lines: vec![
+                r#"+CMGL: 1,"REC READ","+15551234",,"24/05/01,10:00:00+08""#.to_string(),
+                "Meet at 5, ok?".to_string(),
+                r#"+CMGL: 3,"STO UNSENT","",,"#.to_string(),
+                "draft".to_string(),
+            ],
+        };
+        let messages = parse_message_list(&listing);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].number.as_deref(), Some("+15551234"));
+        assert_eq!(messages[0].timestamp.as_deref(), Some("24/05/01,10:00:00+08"));
+        assert_eq!(messages[0].text, "Meet at 5, ok?");
+        assert_eq!((messages[1].index, messages[1].number.as_ref()), (Some(3), None));
+
+        let read = AtResponse {
+            lines: vec![r#"+CMGR: "REC UNREAD","+15551234",,"24/05/01,10:00:00+08""#.to_string(), "Hi".to_string()],
+        };
+        let message = parse_message(7, &read).unwrap();
+        assert_eq!((message.index, message.status.as_str(), message.text.as_str()), (Some(7), "REC UNREAD", "Hi"));
+        assert_eq!(parse_message(8, &AtResponse::default()), None);
+    }
+}
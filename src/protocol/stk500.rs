@@ -0,0 +1,394 @@
+//! STK500 programming of AVR bootloaders
+//!
+//! Arduino-class boards take new firmware through a bootloader that speaks
+//! Atmel's STK500 protocol: version 1 (optiboot on the Uno and Nano), with
+//! bare command bytes ended by CRC_EOP and answered between INSYNC and OK,
+//! or version 2 (the Mega 2560), with numbered, checksummed messages. Only
+//! what flashing needs is here: sync, signature, programming mode, and page
+//! writes and reads.
+
+use std::time::Duration;
+
+use thiserror::Error;
+use tokio::time::Instant;
+
+use crate::serial::{LocalSerialError, SerialConnection};
+
+// STK500v1
+const STK_OK: u8 = 0x10;
+const STK_INSYNC: u8 = 0x14;
+const STK_NOSYNC: u8 = 0x15;
+const CRC_EOP: u8 = 0x20;
+const STK_GET_SYNC: u8 = 0x30;
+const STK_GET_PARAMETER: u8 = 0x41;
+const STK_ENTER_PROGMODE: u8 = 0x50;
+const STK_LEAVE_PROGMODE: u8 = 0x51;
+const STK_LOAD_ADDRESS: u8 = 0x55;
+const STK_PROG_PAGE: u8 = 0x64;
+const STK_READ_PAGE: u8 = 0x74;
+const STK_READ_SIGN: u8 = 0x75;
+const PARAM_SW_MAJOR: u8 = 0x81;
+const PARAM_SW_MINOR: u8 = 0x82;
+
+// STK500v2
+const MESSAGE_START: u8 = 0x1B;
+const TOKEN: u8 = 0x0E;
+const CMD_SIGN_ON: u8 = 0x01;
+const CMD_LOAD_ADDRESS: u8 = 0x06;
+const CMD_ENTER_PROGMODE_ISP: u8 = 0x10;
+const CMD_LEAVE_PROGMODE_ISP: u8 = 0x11;
+const CMD_PROGRAM_FLASH_ISP: u8 = 0x13;
+const CMD_READ_FLASH_ISP: u8 = 0x14;
+const CMD_READ_SIGNATURE_ISP: u8 = 0x1B;
+const STATUS_CMD_OK: u8 = 0x00;
+
+/// Wait for each sync reply; the bootloader answers at once when it is running
+const SYNC_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// Wait for the reply to any other command, page writes included
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Largest program an STK500v1 bootloader can address with 16-bit word addresses
+const V1_MAX_FLASH: usize = 128 * 1024;
+
+#[derive(Debug, Error)]
+pub enum Stk500Error {
+    #[error("No response while {stage}")]
+    Timeout { stage: String },
+
+    #[error("No sync with the bootloader; is the board reset into it and the baud rate right?")]
+    NoSync,
+
+    #[error("{stage} failed with status 0x{status:02X}")]
+    Failed { stage: String, status: u8 },
+
+    #[error("{0}")]
+    Protocol(String),
+
+    #[error(transparent)]
+    Serial(#[from] LocalSerialError),
+}
+
+/// Version of the protocol the bootloader speaks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stk500Version {
+    /// optiboot and the older ATmegaBOOT, as on the Uno and Nano
+    V1,
+    /// The Mega 2560's bootloader
+    V2,
+}
+
+impl Stk500Version {
+    pub fn parse(value: &str) -> Result<Self, LocalSerialError> {
+        match value.to_lowercase().as_str() {
+            "stk500v1" | "arduino" | "optiboot" => Ok(Stk500Version::V1),
+            "stk500v2" | "wiring" => Ok(Stk500Version::V2),
+            _ => Err(LocalSerialError::InvalidConfig(format!("Unknown STK500 protocol: {}", value))),
+        }
+    }
+}
+
+impl std::fmt::Display for Stk500Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Stk500Version::V1 => write!(f, "stk500v1"),
+            Stk500Version::V2 => write!(f, "stk500v2"),
+        }
+    }
+}
+
+/// A part known by its signature
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AvrPart {
+    pub name: &'static str,
+    pub flash_size: usize,
+    pub page_size: usize,
+}
+
+/// The part with a signature, for the chips on Arduino-class boards
+pub fn part(signature: [u8; 3]) -> Option<AvrPart> {
+    let (name, flash_size, page_size) = match signature {
+        [0x1E, 0x93, 0x0A] => ("ATmega88", 8 * 1024, 64),
+        [0x1E, 0x94, 0x06] => ("ATmega168", 16 * 1024, 128),
+        [0x1E, 0x94, 0x0B] => ("ATmega168P", 16 * 1024, 128),
+        [0x1E, 0x95, 0x14] => ("ATmega328", 32 * 1024, 128),
+        [0x1E, 0x95, 0x0F] => ("ATmega328P", 32 * 1024, 128),
+        [0x1E, 0x95, 0x16] => ("ATmega328PB", 32 * 1024, 128),
+        [0x1E, 0x96, 0x0A] => ("ATmega644P", 64 * 1024, 256),
+        [0x1E, 0x97, 0x05] => ("ATmega1284P", 128 * 1024, 256),
+        [0x1E, 0x97, 0x03] => ("ATmega1280", 128 * 1024, 256),
+        [0x1E, 0x98, 0x01] => ("ATmega2560", 256 * 1024, 256),
+        _ => return None,
+    };
+    Some(AvrPart { name, flash_size, page_size })
+}
+
+/// An STK500v2 message around `body`
+fn v2_message(seq: u8, body: &[u8]) -> Vec<u8> {
+    let mut message = vec![MESSAGE_START, seq];
+    message.extend((body.len() as u16).to_be_bytes());
+    message.push(TOKEN);
+    message.extend(body);
+    message.push(message.iter().fold(0, |sum, &byte| sum ^ byte));
+    message
+}
+
+/// The body of the first whole STK500v2 message in `buffer` with sequence
+/// number `seq`, consuming what it has looked at; `None` until one is complete
+fn take_v2_message(buffer: &mut Vec<u8>, seq: u8) -> Option<Vec<u8>> {
+    loop {
+        let start = buffer.iter().position(|&byte| byte == MESSAGE_START)?;
+        buffer.drain(..start);
+        if buffer.len() < 5 {
+            return None;
+        }
+        let size = u16::from_be_bytes([buffer[2], buffer[3]]) as usize;
+        if buffer[4] != TOKEN {
+            buffer.remove(0);
+            continue;
+        }
+        if buffer.len() < 6 + size {
+            return None;
+        }
+        let message: Vec<u8> = buffer.drain(..6 + size).collect();
+        if message.iter().fold(0, |sum, &byte| sum ^ byte) == 0 && message[1] == seq {
+            return Some(message[5..5 + size].to_vec());
+        }
+    }
+}
+
+/// A session with a bootloader on a connection
+pub struct Stk500<'a> {
+    connection: &'a SerialConnection,
+    version: Stk500Version,
+    seq: u8,
+}
+
+impl<'a> Stk500<'a> {
+    pub fn new(connection: &'a SerialConnection, version: Stk500Version) -> Self {
+        Self { connection, version, seq: 0 }
+    }
+
+    /// Get the bootloader's attention, trying up to `attempts` times, and
+    /// return its version as reported
+    pub async fn sync(&mut self, attempts: u32) -> Result<String, Stk500Error> {
+        for _ in 0..attempts {
+            self.purge().await;
+            let reply = match self.version {
+                Stk500Version::V1 => self.v1_command(&[STK_GET_SYNC], 0, SYNC_TIMEOUT, "syncing").await.map(|_| String::new()),
+                Stk500Version::V2 => self.v2_command(&[CMD_SIGN_ON], SYNC_TIMEOUT, "signing on").await.map(|body| {
+                    // Status, name length and name, e.g. "AVRISP_2"
+                    body.get(3..).map(|name| String::from_utf8_lossy(name).into_owned()).unwrap_or_default()
+                }),
+            };
+            match reply {
+                Ok(name) => {
+                    self.purge().await;
+                    return self.version_string(name).await;
+                }
+                Err(Stk500Error::Timeout { .. } | Stk500Error::Protocol(_)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Err(Stk500Error::NoSync)
+    }
+
+    async fn version_string(&mut self, name: String) -> Result<String, Stk500Error> {
+        if self.version == Stk500Version::V2 {
+            return Ok(name);
+        }
+        let major = self.v1_command(&[STK_GET_PARAMETER, PARAM_SW_MAJOR], 1, COMMAND_TIMEOUT, "reading the version").await?[0];
+        let minor = self.v1_command(&[STK_GET_PARAMETER, PARAM_SW_MINOR], 1, COMMAND_TIMEOUT, "reading the version").await?[0];
+        Ok(format!("{}.{}", major, minor))
+    }
+
+    pub async fn signature(&mut self) -> Result<[u8; 3], Stk500Error> {
+        match self.version {
+            Stk500Version::V1 => {
+                let reply = self.v1_command(&[STK_READ_SIGN], 3, COMMAND_TIMEOUT, "reading the signature").await?;
+                Ok([reply[0], reply[1], reply[2]])
+            }
+            Stk500Version::V2 => {
+                let mut signature = [0u8; 3];
+                for (index, byte) in signature.iter_mut().enumerate() {
+                    // Return address, then the ISP instruction reading byte `index`
+                    let body = [CMD_READ_SIGNATURE_ISP, 4, 0x30, 0x00, index as u8, 0x00];
+                    let reply = self.v2_command(&body, COMMAND_TIMEOUT, "reading the signature").await?;
+                    *byte = *reply.get(2).ok_or_else(|| Stk500Error::Protocol("Short signature reply".to_string()))?;
+                }
+                Ok(signature)
+            }
+        }
+    }
+
+    pub async fn enter_programming(&mut self) -> Result<(), Stk500Error> {
+        match self.version {
+            Stk500Version::V1 => self.v1_command(&[STK_ENTER_PROGMODE], 0, COMMAND_TIMEOUT, "entering programming mode").await.map(drop),
+            Stk500Version::V2 => {
+                // Timing and the Programming Enable instruction, as avrdude sends them
+                let body = [CMD_ENTER_PROGMODE_ISP, 0xC8, 0x64, 0x19, 0x20, 0x00, 0x53, 0x03, 0xAC, 0x53, 0x00, 0x00];
+                self.v2_command(&body, COMMAND_TIMEOUT, "entering programming mode").await.map(drop)
+            }
+        }
+    }
+
+    pub async fn leave_programming(&mut self) -> Result<(), Stk500Error> {
+        match self.version {
+            Stk500Version::V1 => self.v1_command(&[STK_LEAVE_PROGMODE], 0, COMMAND_TIMEOUT, "leaving programming mode").await.map(drop),
+            Stk500Version::V2 => self.v2_command(&[CMD_LEAVE_PROGMODE_ISP, 1, 1], COMMAND_TIMEOUT, "leaving programming mode").await.map(drop),
+        }
+    }
+
+    /// Write one flash page at byte `address`
+    pub async fn write_page(&mut self, address: usize, page: &[u8]) -> Result<(), Stk500Error> {
+        let stage = format!("writing the page at 0x{:05X}", address);
+        self.load_address(address, &stage).await?;
+        let length = (page.len() as u16).to_be_bytes();
+        match self.version {
+            Stk500Version::V1 => {
+                let mut command = vec![STK_PROG_PAGE, length[0], length[1], b'F'];
+                command.extend(page);
+                self.v1_command(&command, 0, COMMAND_TIMEOUT, &stage).await.map(drop)
+            }
+            Stk500Version::V2 => {
+                // Page mode writing the page, 10ms delay, and the ISP load, write and read instructions
+                let mut body = vec![CMD_PROGRAM_FLASH_ISP, length[0], length[1], 0xC1, 0x0A, 0x40, 0x4C, 0x20, 0x00, 0x00];
+                body.extend(page);
+                self.v2_command(&body, COMMAND_TIMEOUT, &stage).await.map(drop)
+            }
+        }
+    }
+
+    /// Read `length` bytes of flash at byte `address`
+    pub async fn read_page(&mut self, address: usize, length: usize) -> Result<Vec<u8>, Stk500Error> {
+        let stage = format!("reading the page at 0x{:05X}", address);
+        self.load_address(address, &stage).await?;
+        let size = (length as u16).to_be_bytes();
+        match self.version {
+            Stk500Version::V1 => self.v1_command(&[STK_READ_PAGE, size[0], size[1], b'F'], length, COMMAND_TIMEOUT, &stage).await,
+            Stk500Version::V2 => {
+                let reply = self.v2_command(&[CMD_READ_FLASH_ISP, size[0], size[1], 0x20], COMMAND_TIMEOUT, &stage).await?;
+                reply
+                    .get(2..2 + length)
+                    .map(<[u8]>::to_vec)
+                    .ok_or_else(|| Stk500Error::Protocol(format!("Short reply while {}", stage)))
+            }
+        }
+    }
+
+    /// Point the bootloader at a byte address, which it takes in words
+    async fn load_address(&mut self, address: usize, stage: &str) -> Result<(), Stk500Error> {
+        let word = (address / 2) as u32;
+        match self.version {
+            Stk500Version::V1 => {
+                if address >= V1_MAX_FLASH {
+                    return Err(Stk500Error::Protocol(format!("0x{:05X} is beyond what STK500v1 can address", address)));
+                }
+                let bytes = (word as u16).to_le_bytes();
+                self.v1_command(&[STK_LOAD_ADDRESS, bytes[0], bytes[1]], 0, COMMAND_TIMEOUT, stage).await.map(drop)
+            }
+            Stk500Version::V2 => {
+                // The top bit asks for the extended address byte on parts above 128 KiB
+                let word = if address >= V1_MAX_FLASH { word | 0x8000_0000 } else { word };
+                let mut body = vec![CMD_LOAD_ADDRESS];
+                body.extend(word.to_be_bytes());
+                self.v2_command(&body, COMMAND_TIMEOUT, stage).await.map(drop)
+            }
+        }
+    }
+
+    /// Send a v1 command and return the `reply_len` bytes between INSYNC and OK
+    async fn v1_command(&mut self, command: &[u8], reply_len: usize, timeout: Duration, stage: &str) -> Result<Vec<u8>, Stk500Error> {
+        let mut packet = command.to_vec();
+        packet.push(CRC_EOP);
+        self.connection.write(&packet).await?;
+        let mut reply = vec![0u8; reply_len + 2];
+        if !self.read_exact(&mut reply, timeout).await? {
+            return Err(Stk500Error::Timeout { stage: stage.to_string() });
+        }
+        match (reply[0], reply[reply_len + 1]) {
+            (STK_INSYNC, STK_OK) => Ok(reply[1..=reply_len].to_vec()),
+            (STK_NOSYNC, _) => Err(Stk500Error::Protocol(format!("Lost sync while {}", stage))),
+            (first, last) => Err(Stk500Error::Protocol(format!("Unexpected reply 0x{:02X}..0x{:02X} while {}", first, last, stage))),
+        }
+    }
+
+    /// Send a v2 command and return the reply's body, whose second byte is the status
+    async fn v2_command(&mut self, body: &[u8], timeout: Duration, stage: &str) -> Result<Vec<u8>, Stk500Error> {
+        self.seq = self.seq.wrapping_add(1);
+        self.connection.write(&v2_message(self.seq, body)).await?;
+        let deadline = Instant::now() + timeout;
+        let mut buffer = Vec::new();
+        let reply = loop {
+            if let Some(reply) = take_v2_message(&mut buffer, self.seq) {
+                break reply;
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let mut chunk = [0u8; 512];
+            match self.connection.read(&mut chunk, Some(remaining.as_millis().max(1) as u64)).await {
+                Ok(0) | Err(LocalSerialError::ReadTimeout) => return Err(Stk500Error::Timeout { stage: stage.to_string() }),
+                Ok(n) => buffer.extend(&chunk[..n]),
+                Err(e) => return Err(e.into()),
+            }
+        };
+        match reply[..] {
+            [command, STATUS_CMD_OK, ..] if command == body[0] => Ok(reply),
+            [command, status, ..] if command == body[0] => Err(Stk500Error::Failed { stage: stage.to_string(), status }),
+            _ => Err(Stk500Error::Protocol(format!("Unexpected reply while {}", stage))),
+        }
+    }
+
+    /// Fill `buffer`, `false` if the line went quiet first
+    async fn read_exact(&mut self, buffer: &mut [u8], timeout: Duration) -> Result<bool, LocalSerialError> {
+        let deadline = Instant::now() + timeout;
+        let mut len = 0;
+        while len < buffer.len() {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.connection.read(&mut buffer[len..], Some(remaining.as_millis().max(1) as u64)).await {
+                Ok(0) | Err(LocalSerialError::ReadTimeout) => return Ok(false),
+                Ok(n) => len += n,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(true)
+    }
+
+    /// Drop what was received so far, such as replies to earlier sync attempts
+    async fn purge(&mut self) {
+        let mut discard = [0u8; 512];
+        while let Ok(1..) = self.connection.read(&mut discard, Some(0)).await {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_v2_messages() {
+        // CMD_SIGN_ON as avrdude sends it first
+        assert_eq!(v2_message(1, &[CMD_SIGN_ON]), vec![0x1B, 0x01, 0x00, 0x01, 0x0E, 0x01, 0x14]);
+
+        let mut buffer = vec![0x00, 0xFF];
+        let reply = v2_message(1, &[CMD_SIGN_ON, STATUS_CMD_OK, 8, b'A', b'V', b'R', b'I', b'S', b'P', b'_', b'2']);
+        buffer.extend(&reply[..6]);
+        assert_eq!(take_v2_message(&mut buffer, 1), None);
+        buffer.extend(&reply[6..]);
+        assert_eq!(take_v2_message(&mut buffer, 1).unwrap()[3..], *b"AVRISP_2");
+        assert!(buffer.is_empty());
+
+        // A stale reply to an earlier attempt is passed over
+        let mut buffer = v2_message(1, &[CMD_SIGN_ON, STATUS_CMD_OK]);
+        buffer.extend(v2_message(2, &[CMD_SIGN_ON, STATUS_CMD_OK]));
+        assert_eq!(take_v2_message(&mut buffer, 2), Some(vec![CMD_SIGN_ON, STATUS_CMD_OK]));
+    }
+
+    #[test]
+    fn test_parts() {
+        assert_eq!(part([0x1E, 0x95, 0x0F]).map(|part| (part.name, part.page_size)), Some(("ATmega328P", 128)));
+        assert_eq!(part([0x1E, 0x98, 0x01]).unwrap().flash_size, 256 * 1024);
+        assert_eq!(part([0, 0, 0]), None);
+        assert_eq!(Stk500Version::parse("optiboot").unwrap(), Stk500Version::V1);
+        assert_eq!(Stk500Version::parse("wiring").unwrap(), Stk500Version::V2);
+    }
+}
@@ -0,0 +1,355 @@
+//! STM32 system bootloader over USART (AN3155)
+//!
+//! Started from system memory (BOOT0 high at reset), the ROM bootloader
+//! measures the baud rate from a 0x7F byte and then takes one-byte commands
+//! sent with their complement. Each step is acknowledged with 0x79 or
+//! refused with 0x1F, and addresses and data carry an XOR checksum. The
+//! line must use 8 data bits with even parity.
+
+use std::time::Duration;
+
+use thiserror::Error;
+use tokio::time::Instant;
+
+use crate::serial::{LocalSerialError, SerialConnection};
+
+const ACK: u8 = 0x79;
+const NACK: u8 = 0x1F;
+const AUTOBAUD: u8 = 0x7F;
+
+const GET: u8 = 0x00;
+const GET_ID: u8 = 0x02;
+const READ_MEMORY: u8 = 0x11;
+const GO: u8 = 0x21;
+const WRITE_MEMORY: u8 = 0x31;
+const ERASE: u8 = 0x43;
+const EXTENDED_ERASE: u8 = 0x44;
+
+/// Most bytes one READ or WRITE command moves
+pub const MAX_BLOCK: usize = 256;
+
+/// Wait for an acknowledgement of anything but an erase
+const ACK_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Wait for an erase, which on large parts takes tens of seconds
+const ERASE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Pages named in one erase command, as the command's count allows
+const LEGACY_ERASE_PAGES: usize = 255;
+const EXTENDED_ERASE_PAGES: usize = 512;
+
+/// Why a bootloader command failed
+#[derive(Debug, Error)]
+pub enum Stm32Error {
+    #[error("No response while {stage}")]
+    Timeout { stage: String },
+
+    #[error("The bootloader refused {stage}")]
+    Nack { stage: String },
+
+    #[error("{0}")]
+    Protocol(String),
+
+    #[error(transparent)]
+    Serial(#[from] LocalSerialError),
+}
+
+/// What GET reports
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BootloaderInfo {
+    /// Protocol version, e.g. 0x31 for 3.1
+    pub version: u8,
+    /// Command codes the bootloader accepts
+    pub commands: Vec<u8>,
+}
+
+/// What to erase
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Erase {
+    /// All of the flash
+    Mass,
+    /// Pages (sectors on some families) by number
+    Pages(Vec<u16>),
+}
+
+/// Name of a bootloader command
+pub fn command_name(code: u8) -> String {
+    match code {
+        0x00 => "Get".to_string(),
+        0x01 => "Get Version".to_string(),
+        0x02 => "Get ID".to_string(),
+        0x11 => "Read Memory".to_string(),
+        0x21 => "Go".to_string(),
+        0x31 => "Write Memory".to_string(),
+        0x32 => "No-Stretch Write Memory".to_string(),
+        0x43 => "Erase".to_string(),
+        0x44 => "Extended Erase".to_string(),
+        0x45 => "No-Stretch Erase".to_string(),
+        0x63 => "Write Protect".to_string(),
+        0x73 => "Write Unprotect".to_string(),
+        0x82 => "Readout Protect".to_string(),
+        0x92 => "Readout Unprotect".to_string(),
+        0xA1 => "Get Checksum".to_string(),
+        _ => format!("0x{:02X}", code),
+    }
+}
+
+/// Family of a product ID, for the common parts
+pub fn product_name(pid: u16) -> Option<&'static str> {
+    Some(match pid {
+        0x410 => "STM32F10x medium-density",
+        0x412 => "STM32F10x low-density",
+        0x414 => "STM32F10x high-density",
+        0x418 => "STM32F105/107",
+        0x413 => "STM32F405/407/415/417",
+        0x419 => "STM32F42x/43x",
+        0x421 => "STM32F446",
+        0x423 => "STM32F401xB/C",
+        0x431 => "STM32F411",
+        0x433 => "STM32F401xD/E",
+        0x440 => "STM32F030x8/F05x",
+        0x444 => "STM32F03x",
+        0x448 => "STM32F07x",
+        0x435 => "STM32L43x/44x",
+        0x415 => "STM32L47x/48x",
+        0x450 => "STM32H74x/75x",
+        0x460 => "STM32G07x/08x",
+        0x468 => "STM32G43x/44x",
+        0x469 => "STM32G47x/48x",
+        _ => return None,
+    })
+}
+
+/// XOR of the bytes, which the bootloader checks addresses and data against
+fn xor(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, &byte| sum ^ byte)
+}
+
+/// An address as sent, most significant byte first, with its checksum
+fn address_bytes(address: u32) -> Vec<u8> {
+    let mut bytes = address.to_be_bytes().to_vec();
+    bytes.push(xor(&bytes));
+    bytes
+}
+
+/// The erase commands' arguments, one command per batch of pages
+fn erase_arguments(erase: &Erase, extended: bool) -> Vec<Vec<u8>> {
+    match (erase, extended) {
+        (Erase::Mass, true) => vec![vec![0xFF, 0xFF, 0x00]],
+        (Erase::Mass, false) => vec![vec![0xFF, 0x00]],
+        (Erase::Pages(pages), true) => pages
+            .chunks(EXTENDED_ERASE_PAGES)
+            .map(|batch| {
+                let mut bytes = ((batch.len() - 1) as u16).to_be_bytes().to_vec();
+                bytes.extend(batch.iter().flat_map(|page| page.to_be_bytes()));
+                bytes.push(xor(&bytes));
+                bytes
+            })
+            .collect(),
+        (Erase::Pages(pages), false) => pages
+            .chunks(LEGACY_ERASE_PAGES)
+            .map(|batch| {
+                let mut bytes = vec![(batch.len() - 1) as u8];
+                bytes.extend(batch.iter().map(|&page| page as u8));
+                bytes.push(xor(&bytes));
+                bytes
+            })
+            .collect(),
+    }
+}
+
+/// A session with the bootloader on a connection
+pub struct Stm32Bootloader<'a> {
+    connection: &'a SerialConnection,
+}
+
+impl<'a> Stm32Bootloader<'a> {
+    pub fn new(connection: &'a SerialConnection) -> Self {
+        Self { connection }
+    }
+
+    /// Send the autobaud byte, which a bootloader already running refuses
+    /// once it takes a second one for a bad command
+    pub async fn init(&mut self) -> Result<(), Stm32Error> {
+        self.purge().await;
+        for _ in 0..2 {
+            self.connection.write(&[AUTOBAUD]).await?;
+            match self.read_byte(ACK_TIMEOUT).await? {
+                Some(ACK | NACK) => return Ok(()),
+                Some(other) => return Err(Stm32Error::Protocol(format!("Unexpected reply 0x{:02X} to the autobaud byte", other))),
+                None => {}
+            }
+        }
+        Err(Stm32Error::Timeout { stage: "waiting for the autobaud reply".to_string() })
+    }
+
+    pub async fn get(&mut self) -> Result<BootloaderInfo, Stm32Error> {
+        let reply = self.counted_reply(GET, "Get").await?;
+        let (version, commands) = reply.split_first().ok_or_else(|| Stm32Error::Protocol("Get returned nothing".to_string()))?;
+        Ok(BootloaderInfo { version: *version, commands: commands.to_vec() })
+    }
+
+    /// Product ID, e.g. 0x413 for an STM32F407
+    pub async fn get_id(&mut self) -> Result<u16, Stm32Error> {
+        match self.counted_reply(GET_ID, "Get ID").await?[..] {
+            [high, low] => Ok(u16::from_be_bytes([high, low])),
+            ref other => Err(Stm32Error::Protocol(format!("Get ID returned {} bytes", other.len()))),
+        }
+    }
+
+    /// Read `length` bytes from `address` into `done`, which keeps what arrived if it fails
+    pub async fn read(&mut self, address: u32, length: usize, done: &mut Vec<u8>) -> Result<(), Stm32Error> {
+        while done.len() < length {
+            let block = (length - done.len()).min(MAX_BLOCK);
+            let at = address + done.len() as u32;
+            let stage = format!("reading 0x{:08X}", at);
+            self.command(READ_MEMORY, &stage).await?;
+            self.send_acked(&address_bytes(at), ACK_TIMEOUT, &stage).await?;
+            let count = (block - 1) as u8;
+            self.send_acked(&[count, !count], ACK_TIMEOUT, &stage).await?;
+            let mut data = vec![0u8; block];
+            self.read_exact(&mut data, &stage).await?;
+            done.extend(data);
+        }
+        Ok(())
+    }
+
+    /// Write `data` at `address`, telling `on_block` the bytes written so far
+    ///
+    /// The flash must have been erased; blocks are padded to whole words.
+    pub async fn write(&mut self, address: u32, data: &[u8], on_block: &mut (dyn FnMut(usize) + Send)) -> Result<(), Stm32Error> {
+        let mut written = 0;
+        for block in data.chunks(MAX_BLOCK) {
+            let at = address + written as u32;
+            let stage = format!("writing 0x{:08X}", at);
+            self.command(WRITE_MEMORY, &stage).await?;
+            self.send_acked(&address_bytes(at), ACK_TIMEOUT, &stage).await?;
+            let mut payload = block.to_vec();
+            payload.resize(block.len().next_multiple_of(4), 0xFF);
+            let mut bytes = vec![(payload.len() - 1) as u8];
+            bytes.extend(&payload);
+            bytes.push(xor(&bytes));
+            self.send_acked(&bytes, ACK_TIMEOUT, &stage).await?;
+            written += block.len();
+            on_block(written);
+        }
+        Ok(())
+    }
+
+    /// Erase flash with whichever erase command `info` lists
+    pub async fn erase(&mut self, info: &BootloaderInfo, erase: &Erase) -> Result<(), Stm32Error> {
+        let code = if info.commands.contains(&EXTENDED_ERASE) {
+            EXTENDED_ERASE
+        } else if info.commands.contains(&ERASE) {
+            ERASE
+        } else {
+            return Err(Stm32Error::Protocol("The bootloader has no erase command".to_string()));
+        };
+        if let (ERASE, Erase::Pages(pages)) = (code, erase) {
+            if let Some(page) = pages.iter().find(|&&page| page > 0xFF) {
+                return Err(Stm32Error::Protocol(format!("Page {} is beyond what this bootloader's Erase command can name", page)));
+            }
+        }
+        for arguments in erase_arguments(erase, code == EXTENDED_ERASE) {
+            let stage = "erasing".to_string();
+            self.command(code, &stage).await?;
+            self.send_acked(&arguments, ERASE_TIMEOUT, &stage).await?;
+        }
+        Ok(())
+    }
+
+    /// Jump to the code at `address`, leaving the bootloader
+    pub async fn go(&mut self, address: u32) -> Result<(), Stm32Error> {
+        let stage = format!("jumping to 0x{:08X}", address);
+        self.command(GO, &stage).await?;
+        self.send_acked(&address_bytes(address), ACK_TIMEOUT, &stage).await
+    }
+
+    /// Send a command byte with its complement and wait for it to be accepted
+    async fn command(&mut self, code: u8, stage: &str) -> Result<(), Stm32Error> {
+        self.send_acked(&[code, !code], ACK_TIMEOUT, stage).await
+    }
+
+    /// A command whose reply is a count, that many bytes plus one, and an ACK
+    async fn counted_reply(&mut self, code: u8, name: &str) -> Result<Vec<u8>, Stm32Error> {
+        self.command(code, name).await?;
+        let count = self
+            .read_byte(ACK_TIMEOUT)
+            .await?
+            .ok_or_else(|| Stm32Error::Timeout { stage: format!("reading the reply to {}", name) })?;
+        let mut reply = vec![0u8; count as usize + 1];
+        self.read_exact(&mut reply, name).await?;
+        self.wait_ack(ACK_TIMEOUT, name).await?;
+        Ok(reply)
+    }
+
+    async fn send_acked(&mut self, bytes: &[u8], timeout: Duration, stage: &str) -> Result<(), Stm32Error> {
+        self.connection.write(bytes).await?;
+        self.wait_ack(timeout, stage).await
+    }
+
+    async fn wait_ack(&mut self, timeout: Duration, stage: &str) -> Result<(), Stm32Error> {
+        match self.read_byte(timeout).await? {
+            Some(ACK) => Ok(()),
+            Some(NACK) => Err(Stm32Error::Nack { stage: stage.to_string() }),
+            Some(other) => Err(Stm32Error::Protocol(format!("Unexpected reply 0x{:02X} while {}", other, stage))),
+            None => Err(Stm32Error::Timeout { stage: stage.to_string() }),
+        }
+    }
+
+    async fn read_byte(&mut self, timeout: Duration) -> Result<Option<u8>, LocalSerialError> {
+        let mut byte = [0u8; 1];
+        match self.connection.read(&mut byte, Some(timeout.as_millis() as u64)).await {
+            Ok(0) | Err(LocalSerialError::ReadTimeout) => Ok(None),
+            Ok(_) => Ok(Some(byte[0])),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn read_exact(&mut self, buffer: &mut [u8], stage: &str) -> Result<(), Stm32Error> {
+        let deadline = Instant::now() + ACK_TIMEOUT;
+        let mut len = 0;
+        while len < buffer.len() {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.connection.read(&mut buffer[len..], Some(remaining.as_millis().max(1) as u64)).await {
+                Ok(0) | Err(LocalSerialError::ReadTimeout) => {
+                    return Err(Stm32Error::Timeout { stage: format!("{} ({} of {} bytes received)", stage, len, buffer.len()) })
+                }
+                Ok(n) => len += n,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(())
+    }
+
+    /// Drop anything left from before, such as the application's output
+    async fn purge(&mut self) {
+        let mut discard = [0u8; MAX_BLOCK];
+        while let Ok(1..) = self.connection.read(&mut discard, Some(0)).await {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_address_bytes() {
+        // AN3155's example: the checksum is the XOR of the four address bytes
+        assert_eq!(address_bytes(0x0800_0000), vec![0x08, 0x00, 0x00, 0x00, 0x08]);
+        assert_eq!(address_bytes(0x2000_1234), vec![0x20, 0x00, 0x12, 0x34, 0x20 ^ 0x12 ^ 0x34]);
+    }
+
+    #[test]
+    fn test_erase_arguments() {
+        assert_eq!(erase_arguments(&Erase::Mass, true), vec![vec![0xFF, 0xFF, 0x00]]);
+        assert_eq!(erase_arguments(&Erase::Mass, false), vec![vec![0xFF, 0x00]]);
+        assert_eq!(erase_arguments(&Erase::Pages(vec![1, 2]), true), vec![vec![0x00, 0x01, 0x00, 0x01, 0x00, 0x02, 0x02]]);
+        assert_eq!(erase_arguments(&Erase::Pages(vec![1, 2]), false), vec![vec![0x01, 0x01, 0x02, 0x02]]);
+
+        let pages: Vec<u16> = (0..600).collect();
+        let batches = erase_arguments(&Erase::Pages(pages), true);
+        assert_eq!(batches.len(), 2);
+        assert_eq!(&batches[1][..2], &[0x00, 87]);
+    }
+}
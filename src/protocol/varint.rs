@@ -0,0 +1,122 @@
+//! Varint length-delimited framing
+//!
+//! Each payload follows its length as a base-128 varint, least significant
+//! group first, as protobuf's `writeDelimitedTo` and `parseDelimitedFrom`
+//! expect. Nothing marks the start of a frame, so a corrupt length loses the
+//! stream until the framer is reset.
+
+use super::framer::{FrameError, Framer};
+
+/// Longest payload accepted before the stream is given up on
+pub const VARINT_MAX_FRAME: usize = 64 * 1024;
+
+/// Bytes a length up to `VARINT_MAX_FRAME` can take
+const MAX_LENGTH_BYTES: usize = 3;
+
+#[derive(Debug, Default)]
+pub struct VarintFramer {
+    buffer: Vec<u8>,
+}
+
+impl VarintFramer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// The length at the start of `bytes` and the bytes it takes, `None` while incomplete
+fn read_length(bytes: &[u8]) -> Option<Result<(usize, usize), FrameError>> {
+    let mut length = 0usize;
+    for (index, byte) in bytes.iter().enumerate() {
+        if index == MAX_LENGTH_BYTES {
+            return Some(Err(FrameError::TooLong(VARINT_MAX_FRAME)));
+        }
+        length |= usize::from(byte & 0x7F) << (7 * index);
+        if byte & 0x80 == 0 {
+            return Some(Ok((length, index + 1)));
+        }
+    }
+    None
+}
+
+impl Framer for VarintFramer {
+    fn name(&self) -> &'static str {
+        "varint"
+    }
+
+    fn encode(&self, payload: &[u8]) -> Vec<u8> {
+        let mut encoded = Vec::with_capacity(payload.len() + MAX_LENGTH_BYTES);
+        let mut length = payload.len();
+        while length >= 0x80 {
+            encoded.push(length as u8 | 0x80);
+            length >>= 7;
+        }
+        encoded.push(length as u8);
+        encoded.extend_from_slice(payload);
+        encoded
+    }
+
+    fn decode(&mut self, data: &[u8]) -> Vec<Result<Vec<u8>, FrameError>> {
+        let mut frames = Vec::new();
+        self.buffer.extend_from_slice(data);
+
+        while let Some(header) = read_length(&self.buffer) {
+            let header = header.and_then(|(length, header_len)| match length {
+                0..=VARINT_MAX_FRAME => Ok((length, header_len)),
+                _ => Err(FrameError::TooLong(VARINT_MAX_FRAME)),
+            });
+            let (length, header_len) = match header {
+                Ok(header) => header,
+                Err(e) => {
+                    frames.push(Err(e));
+                    // Nothing marks where the next frame starts
+                    self.buffer.clear();
+                    break;
+                }
+            };
+            if self.buffer.len() < header_len + length {
+                break;
+            }
+            frames.push(Ok(self.buffer[header_len..header_len + length].to_vec()));
+            self.buffer.drain(..header_len + length);
+        }
+
+        frames
+    }
+
+    fn pending(&self) -> usize {
+        self.buffer.len()
+    }
+
+    fn reset(&mut self) {
+        self.buffer.clear();
+    }
+
+    fn max_payload(&self) -> Option<usize> {
+        Some(VARINT_MAX_FRAME)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_varint_frames() {
+        let mut framer = VarintFramer::new();
+        assert_eq!(framer.encode(b"hi"), vec![0x02, b'h', b'i']);
+        let encoded = framer.encode(&[0x55; 300]);
+        assert_eq!(&encoded[..2], &[0xAC, 0x02]);
+
+        // Split inside the length, then two frames in one read
+        assert!(framer.decode(&encoded[..1]).is_empty());
+        let mut rest = encoded[1..].to_vec();
+        rest.extend(framer.encode(b""));
+        assert_eq!(framer.decode(&rest), vec![Ok(vec![0x55; 300]), Ok(Vec::new())]);
+        assert_eq!(framer.pending(), 0);
+
+        let frames = framer.decode(&[0xFF, 0xFF, 0x7F, 0x01]);
+        assert_eq!(frames, vec![Err(FrameError::TooLong(VARINT_MAX_FRAME))]);
+        assert_eq!(framer.pending(), 0);
+    }
+}
@@ -0,0 +1,596 @@
+//! YMODEM batch file transfer
+//!
+//! YMODEM sends files in 128 or 1024 byte blocks, each numbered and checked
+//! with CRC-16/XMODEM and acknowledged before the next. Block 0 of each file
+//! carries its name and size; an empty block 0 ends the batch. The receiver
+//! starts every file by sending `C`. Bootloaders such as STM32 IAP and
+//! U-Boot's `loady`, and network gear consoles, take firmware this way.
+//! Plain XMODEM, which simpler bootloaders take, sends one nameless image in
+//! 128 byte blocks, checked by CRC when the receiver starts with `C` or by an
+//! arithmetic checksum when it starts with NAK.
+
+use std::time::Duration;
+
+use thiserror::Error;
+use tokio::time::Instant;
+
+use crate::serial::{LocalSerialError, SerialConnection};
+use crate::utils::{BufferUtils, CrcParams};
+
+const SOH: u8 = 0x01;
+const STX: u8 = 0x02;
+const EOT: u8 = 0x04;
+const ACK: u8 = 0x06;
+const NAK: u8 = 0x15;
+const CAN: u8 = 0x18;
+/// Asks for CRC blocks, starting each file
+const CRC_REQUEST: u8 = b'C';
+/// Pads the last block of a file
+const PAD: u8 = 0x1A;
+
+const SHORT_BLOCK: usize = 128;
+const LONG_BLOCK: usize = 1024;
+
+/// Attempts at each block before giving up
+const MAX_RETRIES: u32 = 10;
+
+/// Wait for each acknowledgement or block once a transfer is under way
+const BLOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often a receiver repeats `C` while waiting for the sender to start
+const START_INTERVAL: Duration = Duration::from_secs(3);
+
+/// Silence after which a garbled block is taken to have ended
+const PURGE_IDLE_MS: u64 = 100;
+
+/// Largest file accepted when receiving
+pub const MAX_YMODEM_FILE: u64 = 64 * 1024 * 1024;
+
+/// Why a transfer failed
+#[derive(Debug, Error)]
+pub enum YmodemError {
+    #[error("No response within {timeout_ms}ms while {stage}")]
+    Timeout { stage: String, timeout_ms: u64 },
+
+    #[error("Cancelled by the other end")]
+    Cancelled,
+
+    #[error("Gave up on {stage} after {MAX_RETRIES} attempts")]
+    Retries { stage: String },
+
+    #[error("{0}")]
+    Protocol(String),
+
+    #[error(transparent)]
+    Serial(#[from] LocalSerialError),
+}
+
+/// A file to send or one that was received
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct YmodemFile {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+/// How far a file got
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransferProgress {
+    pub name: String,
+    pub size: Option<u64>,
+    /// Bytes acknowledged by the receiver, or received so far
+    pub bytes: u64,
+    /// Blocks sent again or asked for again
+    pub retries: u32,
+    pub complete: bool,
+}
+
+impl TransferProgress {
+    pub fn new(name: &str, size: Option<u64>) -> Self {
+        Self { name: name.to_string(), size, bytes: 0, retries: 0, complete: false }
+    }
+}
+
+fn crc16(data: &[u8]) -> u16 {
+    BufferUtils::crc(data, &CrcParams::CRC16_XMODEM) as u16
+}
+
+/// Block `seq` carrying `data`, padded with `pad` to 128 or 1024 bytes
+fn encode_block(seq: u8, data: &[u8], pad: u8) -> Vec<u8> {
+    let (start, size) = if data.len() <= SHORT_BLOCK { (SOH, SHORT_BLOCK) } else { (STX, LONG_BLOCK) };
+    let mut payload = data.to_vec();
+    payload.resize(size, pad);
+    let mut block = vec![start, seq, !seq];
+    block.extend(&payload);
+    block.extend(crc16(&payload).to_be_bytes());
+    block
+}
+
+/// XMODEM block `seq`, always 128 bytes, with a CRC or an 8-bit sum
+fn encode_xmodem_block(seq: u8, data: &[u8], crc: bool) -> Vec<u8> {
+    let mut payload = data.to_vec();
+    payload.resize(SHORT_BLOCK, PAD);
+    let mut block = vec![SOH, seq, !seq];
+    block.extend(&payload);
+    if crc {
+        block.extend(crc16(&payload).to_be_bytes());
+    } else {
+        block.push(payload.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte)));
+    }
+    block
+}
+
+/// Block 0 announcing a file, or ending the batch when `file` is `None`
+fn header_block(file: Option<(&str, u64)>) -> Vec<u8> {
+    let mut data = Vec::new();
+    if let Some((name, size)) = file {
+        data.extend(name.as_bytes());
+        data.push(0);
+        data.extend(size.to_string().as_bytes());
+        data.push(0);
+    }
+    encode_block(0, &data, 0)
+}
+
+/// Name and size from block 0, `None` for the empty block that ends the batch
+pub(crate) fn parse_header(data: &[u8]) -> Option<(String, Option<u64>)> {
+    let name_end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+    if name_end == 0 {
+        return None;
+    }
+    let name = String::from_utf8_lossy(&data[..name_end]).into_owned();
+    // The size may be followed by a space and the modification time
+    let size = data
+        .get(name_end + 1..)
+        .unwrap_or_default()
+        .split(|&b| b == 0 || b == b' ')
+        .next()
+        .and_then(|digits| std::str::from_utf8(digits).ok())
+        .and_then(|digits| digits.parse().ok());
+    Some((name, size))
+}
+
+/// Byte at a time from the connection
+async fn read_byte(connection: &SerialConnection, timeout: Duration) -> Result<Option<u8>, LocalSerialError> {
+    let mut byte = [0u8; 1];
+    match connection.read(&mut byte, Some(timeout.as_millis() as u64)).await {
+        Ok(0) | Err(LocalSerialError::ReadTimeout) => Ok(None),
+        Ok(_) => Ok(Some(byte[0])),
+        Err(e) => Err(e),
+    }
+}
+
+/// Fill `buffer`, `false` if the line went quiet first
+async fn read_exact(connection: &SerialConnection, buffer: &mut [u8], timeout: Duration) -> Result<bool, LocalSerialError> {
+    let deadline = Instant::now() + timeout;
+    let mut len = 0;
+    while len < buffer.len() {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        match connection.read(&mut buffer[len..], Some(remaining.as_millis().max(1) as u64)).await {
+            Ok(0) | Err(LocalSerialError::ReadTimeout) => return Ok(false),
+            Ok(n) => len += n,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+/// Discard input until the line goes quiet
+async fn purge(connection: &SerialConnection) {
+    let mut discard = [0u8; LONG_BLOCK];
+    while let Ok(1..) = connection.read(&mut discard, Some(PURGE_IDLE_MS)).await {}
+}
+
+/// Tell the other end to abandon the transfer
+async fn cancel(connection: &SerialConnection) {
+    let _ = connection.write(&[CAN; 5]).await;
+}
+
+/// A control byte from the receiver
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Reply {
+    Ack,
+    Nak,
+    CrcRequest,
+}
+
+/// Wait up to `timeout` for ACK, NAK or `C`, skipping line noise
+async fn read_reply(connection: &SerialConnection, timeout: Duration) -> Result<Option<Reply>, YmodemError> {
+    let deadline = Instant::now() + timeout;
+    let mut cancels = 0;
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let Some(byte) = read_byte(connection, remaining).await? else {
+            return Ok(None);
+        };
+        match byte {
+            ACK => return Ok(Some(Reply::Ack)),
+            NAK => return Ok(Some(Reply::Nak)),
+            CRC_REQUEST => return Ok(Some(Reply::CrcRequest)),
+            // Two in a row cancel; one alone may be noise
+            CAN if cancels > 0 => return Err(YmodemError::Cancelled),
+            CAN => cancels += 1,
+            _ => cancels = 0,
+        }
+    }
+}
+
+/// Wait for the receiver's `C` that starts a file
+async fn wait_for_start(connection: &SerialConnection, timeout: Duration, stage: &str) -> Result<(), YmodemError> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        match read_reply(connection, remaining).await? {
+            Some(Reply::CrcRequest) => {
+                // Receivers repeat C until answered; the repeats are not replies to what follows
+                let mut repeats = [0u8; SHORT_BLOCK];
+                while let Ok(1..) = connection.read(&mut repeats, Some(0)).await {}
+                return Ok(());
+            }
+            Some(Reply::Nak) => return Err(YmodemError::Protocol("Receiver asked for checksum blocks, YMODEM needs CRC".to_string())),
+            // A late ACK of the previous block
+            Some(Reply::Ack) => {}
+            None => return Err(YmodemError::Timeout { stage: stage.to_string(), timeout_ms: timeout.as_millis() as u64 }),
+        }
+    }
+}
+
+/// Wait for an XMODEM receiver to start, returning whether it asked for CRC blocks
+async fn wait_for_xmodem_start(connection: &SerialConnection, timeout: Duration) -> Result<bool, YmodemError> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let crc = match read_reply(connection, remaining).await? {
+            Some(Reply::CrcRequest) => true,
+            Some(Reply::Nak) => false,
+            Some(Reply::Ack) => continue,
+            None => {
+                let stage = "waiting for the receiver to start".to_string();
+                return Err(YmodemError::Timeout { stage, timeout_ms: timeout.as_millis() as u64 });
+            }
+        };
+        let mut repeats = [0u8; SHORT_BLOCK];
+        while let Ok(1..) = connection.read(&mut repeats, Some(0)).await {}
+        return Ok(crc);
+    }
+}
+
+/// Send `block` until it is acknowledged
+async fn send_block(connection: &SerialConnection, block: &[u8], stage: &str, retries: &mut u32) -> Result<(), YmodemError> {
+    for attempt in 0..MAX_RETRIES {
+        if attempt > 0 {
+            *retries += 1;
+        }
+        connection.write(block).await?;
+        // NAK, C or silence: send again
+        if read_reply(connection, BLOCK_TIMEOUT).await? == Some(Reply::Ack) {
+            return Ok(());
+        }
+    }
+    Err(YmodemError::Retries { stage: stage.to_string() })
+}
+
+/// End a file with EOT, which receivers often NAK once to be sure
+async fn send_eot(connection: &SerialConnection, name: &str) -> Result<(), YmodemError> {
+    for _ in 0..MAX_RETRIES {
+        connection.write(&[EOT]).await?;
+        if read_reply(connection, BLOCK_TIMEOUT).await? == Some(Reply::Ack) {
+            return Ok(());
+        }
+    }
+    Err(YmodemError::Retries { stage: format!("ending {}", name) })
+}
+
+/// Send `files` as one batch, waiting up to `start_timeout` for the receiver to start
+///
+/// `progress` gets an entry per file as it starts, so a failure shows how
+/// far each one got.
+pub async fn send(
+    connection: &SerialConnection,
+    files: &[YmodemFile],
+    start_timeout: Duration,
+    progress: &mut Vec<TransferProgress>,
+) -> Result<(), YmodemError> {
+    let result = send_batch(connection, files, start_timeout, progress).await;
+    if matches!(result, Err(YmodemError::Timeout { .. } | YmodemError::Retries { .. })) {
+        cancel(connection).await;
+    }
+    result
+}
+
+/// Send `data` as one XMODEM transfer, waiting up to `start_timeout` for the receiver
+///
+/// `progress` follows the transfer, and `on_block` is told the bytes
+/// acknowledged after each block.
+pub async fn send_xmodem(
+    connection: &SerialConnection,
+    data: &[u8],
+    start_timeout: Duration,
+    progress: &mut TransferProgress,
+    on_block: &mut (dyn FnMut(u64) + Send),
+) -> Result<(), YmodemError> {
+    let result = send_image(connection, data, start_timeout, progress, on_block).await;
+    if matches!(result, Err(YmodemError::Timeout { .. } | YmodemError::Retries { .. })) {
+        cancel(connection).await;
+    }
+    result
+}
+
+async fn send_image(
+    connection: &SerialConnection,
+    data: &[u8],
+    start_timeout: Duration,
+    progress: &mut TransferProgress,
+    on_block: &mut (dyn FnMut(u64) + Send),
+) -> Result<(), YmodemError> {
+    let crc = wait_for_xmodem_start(connection, start_timeout).await?;
+    for (index, chunk) in data.chunks(SHORT_BLOCK).enumerate() {
+        let block = encode_xmodem_block((index + 1) as u8, chunk, crc);
+        let stage = format!("offset {}", index * SHORT_BLOCK);
+        send_block(connection, &block, &stage, &mut progress.retries).await?;
+        progress.bytes += chunk.len() as u64;
+        on_block(progress.bytes);
+    }
+    send_eot(connection, &progress.name).await?;
+    progress.complete = true;
+    Ok(())
+}
+
+async fn send_batch(
+    connection: &SerialConnection,
+    files: &[YmodemFile],
+    start_timeout: Duration,
+    progress: &mut Vec<TransferProgress>,
+) -> Result<(), YmodemError> {
+    let mut timeout = start_timeout;
+    for file in files {
+        progress.push(TransferProgress::new(&file.name, Some(file.data.len() as u64)));
+        let current = progress.last_mut().expect("just pushed");
+        wait_for_start(connection, timeout, &format!("waiting for the receiver to accept {}", file.name)).await?;
+        timeout = BLOCK_TIMEOUT;
+
+        let header = header_block(Some((&file.name, file.data.len() as u64)));
+        send_block(connection, &header, &format!("the header of {}", file.name), &mut current.retries).await?;
+        wait_for_start(connection, timeout, &format!("waiting for the receiver to start {}", file.name)).await?;
+
+        let mut seq = 1u8;
+        let mut offset = 0;
+        while offset < file.data.len() {
+            let remaining = file.data.len() - offset;
+            let len = if remaining > SHORT_BLOCK { remaining.min(LONG_BLOCK) } else { remaining };
+            let block = encode_block(seq, &file.data[offset..offset + len], PAD);
+            let stage = format!("{} at offset {}", file.name, offset);
+            send_block(connection, &block, &stage, &mut current.retries).await?;
+            offset += len;
+            current.bytes = offset as u64;
+            seq = seq.wrapping_add(1);
+        }
+        send_eot(connection, &file.name).await?;
+        current.complete = true;
+    }
+
+    wait_for_start(connection, timeout, "waiting for the receiver to end the batch").await?;
+    let mut retries = 0;
+    send_block(connection, &header_block(None), "ending the batch", &mut retries).await
+}
+
+/// What the sender sent in place of a block
+#[derive(Debug)]
+enum Received {
+    Block { seq: u8, data: Vec<u8> },
+    Eot,
+    /// Garbled, cut short or failing its CRC
+    Bad,
+}
+
+/// Read one block, `None` if nothing arrived within `timeout`
+async fn read_block(connection: &SerialConnection, timeout: Duration) -> Result<Option<Received>, YmodemError> {
+    let deadline = Instant::now() + timeout;
+    let mut cancels = 0;
+    let size = loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let Some(byte) = read_byte(connection, remaining).await? else {
+            return Ok(None);
+        };
+        match byte {
+            SOH => break SHORT_BLOCK,
+            STX => break LONG_BLOCK,
+            EOT => return Ok(Some(Received::Eot)),
+            CAN if cancels > 0 => return Err(YmodemError::Cancelled),
+            CAN => cancels += 1,
+            _ => cancels = 0,
+        }
+    };
+
+    let mut rest = vec![0u8; size + 4];
+    if !read_exact(connection, &mut rest, BLOCK_TIMEOUT).await? {
+        return Ok(Some(Received::Bad));
+    }
+    let (seq, complement) = (rest[0], rest[1]);
+    let data = &rest[2..2 + size];
+    let crc = u16::from_be_bytes([rest[2 + size], rest[3 + size]]);
+    if seq != !complement || crc != crc16(data) {
+        return Ok(Some(Received::Bad));
+    }
+    Ok(Some(Received::Block { seq, data: data.to_vec() }))
+}
+
+/// Receive a batch of files, waiting up to `start_timeout` for the sender to start
+///
+/// Complete files are added to `files` as they arrive; `progress` shows the
+/// file in transfer when a failure cuts it short.
+pub async fn receive(
+    connection: &SerialConnection,
+    start_timeout: Duration,
+    files: &mut Vec<YmodemFile>,
+    progress: &mut Option<TransferProgress>,
+) -> Result<(), YmodemError> {
+    let result = receive_batch(connection, start_timeout, files, progress).await;
+    if matches!(result, Err(YmodemError::Timeout { .. } | YmodemError::Retries { .. } | YmodemError::Protocol(_))) {
+        cancel(connection).await;
+    }
+    result
+}
+
+async fn receive_batch(
+    connection: &SerialConnection,
+    start_timeout: Duration,
+    files: &mut Vec<YmodemFile>,
+    progress: &mut Option<TransferProgress>,
+) -> Result<(), YmodemError> {
+    let mut timeout = start_timeout;
+    loop {
+        let Some((name, size)) = receive_header(connection, timeout).await? else {
+            return Ok(());
+        };
+        timeout = BLOCK_TIMEOUT;
+        if size.is_some_and(|size| size > MAX_YMODEM_FILE) {
+            return Err(YmodemError::Protocol(format!("{} is larger than the {} byte limit", name, MAX_YMODEM_FILE)));
+        }
+        let current = progress.insert(TransferProgress::new(&name, size));
+        let data = receive_data(connection, current).await?;
+        current.complete = true;
+        files.push(YmodemFile { name, data });
+    }
+}
+
+/// Ask for and acknowledge the next block 0, `None` once the batch has ended
+async fn receive_header(connection: &SerialConnection, timeout: Duration) -> Result<Option<(String, Option<u64>)>, YmodemError> {
+    let deadline = Instant::now() + timeout;
+    let mut bad = 0;
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            let stage = "waiting for the sender to start".to_string();
+            return Err(YmodemError::Timeout { stage, timeout_ms: timeout.as_millis() as u64 });
+        }
+        connection.write(&[CRC_REQUEST]).await?;
+        match read_block(connection, remaining.min(START_INTERVAL)).await? {
+            Some(Received::Block { seq: 0, data }) => {
+                connection.write(&[ACK]).await?;
+                return Ok(parse_header(&data));
+            }
+            // The end of the previous file, repeated because our ACK was lost
+            Some(Received::Eot) => {
+                connection.write(&[ACK]).await?;
+            }
+            Some(Received::Block { .. }) | Some(Received::Bad) => {
+                bad += 1;
+                if bad >= MAX_RETRIES {
+                    return Err(YmodemError::Retries { stage: "receiving a file header".to_string() });
+                }
+                purge(connection).await;
+            }
+            None => {}
+        }
+    }
+}
+
+/// Receive a file's data blocks up to its EOT
+async fn receive_data(connection: &SerialConnection, progress: &mut TransferProgress) -> Result<Vec<u8>, YmodemError> {
+    let mut data = Vec::new();
+    let mut expected = 1u8;
+    let mut eot_seen = false;
+    let mut started = false;
+    let mut failures = 0;
+    connection.write(&[CRC_REQUEST]).await?;
+
+    loop {
+        let received = read_block(connection, BLOCK_TIMEOUT).await?;
+        match received {
+            Some(Received::Block { seq, data: block }) if seq == expected => {
+                data.extend(block);
+                if data.len() as u64 > MAX_YMODEM_FILE {
+                    return Err(YmodemError::Protocol(format!("{} is larger than the {} byte limit", progress.name, MAX_YMODEM_FILE)));
+                }
+                progress.bytes = data.len() as u64;
+                expected = expected.wrapping_add(1);
+                started = true;
+                failures = 0;
+                eot_seen = false;
+                connection.write(&[ACK]).await?;
+                continue;
+            }
+            // A repeat of the block just acknowledged, or of the header before any data
+            Some(Received::Block { seq, .. }) if seq == expected.wrapping_sub(1) => {
+                connection.write(&[ACK]).await?;
+                if !started {
+                    connection.write(&[CRC_REQUEST]).await?;
+                }
+                continue;
+            }
+            Some(Received::Block { seq, .. }) => {
+                let stage = format!("{} at offset {}", progress.name, data.len());
+                return Err(YmodemError::Protocol(format!("Block {} arrived when {} was expected in {}", seq, expected, stage)));
+            }
+            // Confirm the end by asking once more
+            Some(Received::Eot) if !eot_seen => {
+                eot_seen = true;
+                connection.write(&[NAK]).await?;
+                continue;
+            }
+            Some(Received::Eot) => {
+                connection.write(&[ACK]).await?;
+                break;
+            }
+            Some(Received::Bad) => purge(connection).await,
+            None => {}
+        }
+
+        failures += 1;
+        progress.retries += 1;
+        if failures >= MAX_RETRIES {
+            return Err(YmodemError::Retries { stage: format!("{} at offset {}", progress.name, data.len()) });
+        }
+        connection.write(&[if started { NAK } else { CRC_REQUEST }]).await?;
+    }
+
+    match progress.size {
+        Some(size) => data.truncate(size as usize),
+        None => {
+            while data.last() == Some(&PAD) {
+                data.pop();
+            }
+        }
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blocks() {
+        let block = encode_block(1, b"hello", PAD);
+        assert_eq!(block.len(), 3 + SHORT_BLOCK + 2);
+        assert_eq!(&block[..8], &[SOH, 0x01, 0xFE, b'h', b'e', b'l', b'l', b'o']);
+        assert!(block[8..3 + SHORT_BLOCK].iter().all(|&b| b == PAD));
+        assert_eq!(u16::from_be_bytes([block[131], block[132]]), crc16(&block[3..131]));
+
+        let block = encode_block(255, &[0x55; 129], PAD);
+        assert_eq!((block[0], block[1], block[2], block.len()), (STX, 0xFF, 0x00, 3 + LONG_BLOCK + 2));
+    }
+
+    #[test]
+    fn test_xmodem_blocks() {
+        let block = encode_xmodem_block(2, &[1, 2, 3], false);
+        assert_eq!(block.len(), 3 + SHORT_BLOCK + 1);
+        assert_eq!(&block[..6], &[SOH, 0x02, 0xFD, 1, 2, 3]);
+        assert_eq!(block[3 + SHORT_BLOCK], (6 + (SHORT_BLOCK - 3) * PAD as usize) as u8);
+
+        let block = encode_xmodem_block(1, &[0x55; 200], true);
+        assert_eq!(block.len(), 3 + SHORT_BLOCK + 2);
+    }
+
+    #[test]
+    fn test_header_blocks() {
+        let header = header_block(Some(("firmware.bin", 70_000)));
+        assert_eq!(&header[..3], &[SOH, 0x00, 0xFF]);
+        assert_eq!(&header[3..22], b"firmware.bin\x0070000\x00");
+        assert_eq!(parse_header(&header[3..131]), Some(("firmware.bin".to_string(), Some(70_000))));
+
+        // Size followed by a modification time, as sb sends
+        assert_eq!(parse_header(b"a.txt\x00123 14677372013 100644\x00"), Some(("a.txt".to_string(), Some(123))));
+        assert_eq!(parse_header(&header_block(None)[3..131]), None);
+    }
+}
@@ -0,0 +1,968 @@
+//! ZMODEM file transfer
+//!
+//! ZMODEM streams each file as data subpackets without waiting for them to be
+//! acknowledged; the receiver only speaks up to name the offset to go on from
+//! (ZRPOS) after an error. The same request resumes an interrupted transfer,
+//! a receiver holding part of a file asking for the rest. Frames start with a
+//! header, in hex or binary with CRC-16 or CRC-32, and control characters in
+//! binary data are escaped with ZDLE so flow control and terminal servers
+//! leave them alone. `sz` and `rz` from lrzsz speak it, as do many terminals.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use thiserror::Error;
+use tokio::time::Instant;
+
+use super::ymodem::parse_header;
+use crate::serial::{LocalSerialError, SerialConnection};
+use crate::utils::{BufferUtils, CrcParams};
+
+const ZPAD: u8 = b'*';
+/// Escapes the byte after it; five in a row cancel
+const ZDLE: u8 = 0x18;
+const ZBIN: u8 = b'A';
+const ZHEX: u8 = b'B';
+const ZBIN32: u8 = b'C';
+
+const XON: u8 = 0x11;
+const XOFF: u8 = 0x13;
+
+// Frame types
+const ZRQINIT: u8 = 0;
+const ZRINIT: u8 = 1;
+const ZSINIT: u8 = 2;
+const ZACK: u8 = 3;
+const ZFILE: u8 = 4;
+const ZSKIP: u8 = 5;
+const ZNAK: u8 = 6;
+const ZABORT: u8 = 7;
+const ZFIN: u8 = 8;
+const ZRPOS: u8 = 9;
+const ZDATA: u8 = 10;
+const ZEOF: u8 = 11;
+const ZCRC: u8 = 13;
+const ZCHALLENGE: u8 = 14;
+const ZCAN: u8 = 16;
+const ZCOMMAND: u8 = 18;
+
+// Subpacket ends: the frame goes on, wants an ACK, or both
+/// Frame ends, no ACK
+const ZCRCE: u8 = b'h';
+/// Frame goes on, no ACK
+const ZCRCG: u8 = b'i';
+/// Frame goes on, ACK wanted
+const ZCRCQ: u8 = b'j';
+/// Frame ends, ACK wanted
+const ZCRCW: u8 = b'k';
+const ZRUB0: u8 = b'l';
+const ZRUB1: u8 = b'm';
+
+// ZRINIT capabilities
+const CANFDX: u8 = 0x01;
+const CANOVIO: u8 = 0x02;
+const CANFC32: u8 = 0x20;
+const ESCCTL: u8 = 0x40;
+
+// ZFILE conversion options
+const ZCBIN: u8 = 1;
+const ZCRESUM: u8 = 3;
+
+/// Data bytes per subpacket sent
+const SUBPACKET: usize = 1024;
+
+/// Longest subpacket accepted; lrzsz sends up to 8K
+const MAX_SUBPACKET: usize = 8192;
+
+/// Attempts at each step, and errors in a row at one offset, before giving up
+const MAX_RETRIES: u32 = 10;
+
+/// Wait for each reply or header once a transfer is under way
+const BLOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often a sender repeats ZRQINIT while waiting for the receiver
+const START_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Silence after which a garbled frame is taken to have ended
+const PURGE_IDLE_MS: u64 = 100;
+
+/// Bytes taken from the receive buffer per read
+const READ_CHUNK: usize = 4096;
+
+/// Largest file accepted when receiving
+pub const MAX_ZMODEM_FILE: u64 = 64 * 1024 * 1024;
+
+/// Why a transfer failed
+#[derive(Debug, Error)]
+pub enum ZmodemError {
+    #[error("No response within {timeout_ms}ms while {stage}")]
+    Timeout { stage: String, timeout_ms: u64 },
+
+    #[error("Cancelled by the other end")]
+    Cancelled,
+
+    #[error("Gave up on {stage} after {MAX_RETRIES} attempts")]
+    Retries { stage: String },
+
+    #[error("{0}")]
+    Protocol(String),
+
+    #[error(transparent)]
+    Serial(#[from] LocalSerialError),
+}
+
+/// A file to send
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZmodemFile {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+/// How far a sent file got
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SendProgress {
+    pub name: String,
+    pub size: u64,
+    /// Offset the receiver asked to start from, non-zero when it resumed
+    pub start: u64,
+    /// Offset sent up to
+    pub bytes: u64,
+    /// Times the receiver asked to go back
+    pub retries: u32,
+    pub skipped: bool,
+    pub complete: bool,
+}
+
+/// A file received, whole or in part, starting at `offset`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReceivedFile {
+    pub name: String,
+    pub size: Option<u64>,
+    /// Offset asked for, the length of the part already held
+    pub offset: u64,
+    /// What arrived from `offset` on
+    pub data: Vec<u8>,
+    /// Times we asked the sender to go back
+    pub retries: u32,
+    pub complete: bool,
+}
+
+fn crc16(data: &[u8]) -> u16 {
+    BufferUtils::crc(data, &CrcParams::CRC16_XMODEM) as u16
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    BufferUtils::crc(data, &CrcParams::CRC32) as u32
+}
+
+/// A frame type and its four bytes of position or flags
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Header {
+    kind: u8,
+    data: [u8; 4],
+}
+
+impl Header {
+    fn new(kind: u8) -> Self {
+        Self { kind, data: [0; 4] }
+    }
+
+    /// A header carrying an offset, least significant byte first
+    fn at(kind: u8, position: u64) -> Self {
+        Self { kind, data: (position as u32).to_le_bytes() }
+    }
+
+    /// A header carrying flags, ZF0 being the last byte
+    fn with_flags(kind: u8, zf0: u8) -> Self {
+        Self { kind, data: [0, 0, 0, zf0] }
+    }
+
+    fn position(&self) -> u64 {
+        u32::from_le_bytes(self.data) as u64
+    }
+
+    fn zf0(&self) -> u8 {
+        self.data[3]
+    }
+
+    fn bytes(&self) -> [u8; 5] {
+        let [a, b, c, d] = self.data;
+        [self.kind, a, b, c, d]
+    }
+}
+
+/// What the receiver said it can do in its ZRINIT
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ReceiverCaps {
+    crc32: bool,
+    escape_ctl: bool,
+    /// Full duplex and able to take data while writing to disk
+    streaming: bool,
+}
+
+impl ReceiverCaps {
+    fn from_zrinit(header: &Header) -> Self {
+        let flags = header.zf0();
+        let buffer = u16::from_le_bytes([header.data[0], header.data[1]]);
+        Self {
+            crc32: flags & CANFC32 != 0,
+            escape_ctl: flags & ESCCTL != 0,
+            streaming: flags & (CANFDX | CANOVIO) == CANFDX | CANOVIO && buffer == 0,
+        }
+    }
+}
+
+/// Append `byte`, escaped if a modem, flow control or terminal server could act on it
+fn escape_into(out: &mut Vec<u8>, byte: u8, escape_ctl: bool) {
+    let after_at = out.last().is_some_and(|&last| last & 0x7F == b'@');
+    let escape = match byte {
+        ZDLE | 0x10 | 0x90 | XON | 0x91 | XOFF | 0x93 => true,
+        // Telnet's escape sequence
+        0x0D | 0x8D => after_at || escape_ctl,
+        _ => escape_ctl && byte & 0x60 == 0,
+    };
+    if escape {
+        out.extend([ZDLE, byte ^ 0x40]);
+    } else {
+        out.push(byte);
+    }
+}
+
+/// A header in hex, readable on a terminal, as used for replies and session control
+fn hex_header(header: &Header) -> Vec<u8> {
+    let bytes = header.bytes();
+    let mut out = vec![ZPAD, ZPAD, ZDLE, ZHEX];
+    for byte in bytes.iter().chain(&crc16(&bytes).to_be_bytes()) {
+        out.extend(format!("{:02x}", byte).as_bytes());
+    }
+    out.extend(b"\r\x8a");
+    if header.kind != ZFIN && header.kind != ZACK {
+        out.push(XON);
+    }
+    out
+}
+
+/// A binary header, as used before data subpackets
+fn binary_header(header: &Header, caps: &ReceiverCaps) -> Vec<u8> {
+    let bytes = header.bytes();
+    let mut out = vec![ZPAD, ZDLE, if caps.crc32 { ZBIN32 } else { ZBIN }];
+    let check = if caps.crc32 { crc32(&bytes).to_le_bytes().to_vec() } else { crc16(&bytes).to_be_bytes().to_vec() };
+    for &byte in bytes.iter().chain(&check) {
+        escape_into(&mut out, byte, caps.escape_ctl);
+    }
+    out
+}
+
+/// `data` as a subpacket ending with `end`, the CRC covering both
+fn subpacket(data: &[u8], end: u8, caps: &ReceiverCaps) -> Vec<u8> {
+    let mut checked = data.to_vec();
+    checked.push(end);
+    let check = if caps.crc32 { crc32(&checked).to_le_bytes().to_vec() } else { crc16(&checked).to_be_bytes().to_vec() };
+    let mut out = Vec::with_capacity(data.len() + data.len() / 8 + 8);
+    for &byte in data {
+        escape_into(&mut out, byte, caps.escape_ctl);
+    }
+    out.extend([ZDLE, end]);
+    for &byte in &check {
+        escape_into(&mut out, byte, caps.escape_ctl);
+    }
+    out
+}
+
+/// The ZFILE subpacket: name and size, as in YMODEM's block 0
+fn file_info(name: &str, size: u64) -> Vec<u8> {
+    let mut info = name.as_bytes().to_vec();
+    info.push(0);
+    info.extend(size.to_string().as_bytes());
+    info.push(0);
+    info
+}
+
+/// One byte of binary data after undoing ZDLE escapes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Escaped {
+    Byte(u8),
+    /// A subpacket end
+    End(u8),
+    /// ZDLE followed by something that cannot follow it
+    Bad,
+}
+
+/// What arrived where a header was expected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Incoming {
+    Header { header: Header, crc32: bool },
+    /// Garbled or failing its CRC
+    Bad,
+    Timeout,
+}
+
+/// Buffered reads and writes on the connection
+struct Link<'a> {
+    connection: &'a SerialConnection,
+    input: VecDeque<u8>,
+}
+
+impl<'a> Link<'a> {
+    fn new(connection: &'a SerialConnection) -> Self {
+        Self { connection, input: VecDeque::new() }
+    }
+
+    async fn write(&self, bytes: &[u8]) -> Result<(), ZmodemError> {
+        self.connection.write(bytes).await?;
+        Ok(())
+    }
+
+    /// Next byte, `None` once `deadline` passes
+    async fn byte(&mut self, deadline: Instant) -> Result<Option<u8>, ZmodemError> {
+        if let Some(byte) = self.input.pop_front() {
+            return Ok(Some(byte));
+        }
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Ok(None);
+        }
+        let mut buffer = [0u8; READ_CHUNK];
+        match self.connection.read(&mut buffer, Some(remaining.as_millis().max(1) as u64)).await {
+            Ok(0) | Err(LocalSerialError::ReadTimeout) => Ok(None),
+            Ok(n) => {
+                self.input.extend(&buffer[1..n]);
+                Ok(Some(buffer[0]))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Take in whatever has arrived without waiting, `true` if any of it could be a reply
+    ///
+    /// The line ends and XON trailing hex headers cannot start one, so they are dropped.
+    async fn has_reply(&mut self) -> bool {
+        let mut buffer = [0u8; READ_CHUNK];
+        while let Ok(n @ 1..) = self.connection.read(&mut buffer, Some(0)).await {
+            self.input.extend(&buffer[..n]);
+        }
+        while matches!(self.input.front(), Some(&byte) if byte & 0x7F != ZPAD && byte != ZDLE) {
+            self.input.pop_front();
+        }
+        !self.input.is_empty()
+    }
+
+    /// Discard input until the line goes quiet
+    async fn purge(&mut self) {
+        self.input.clear();
+        let mut discard = [0u8; READ_CHUNK];
+        while let Ok(1..) = self.connection.read(&mut discard, Some(PURGE_IDLE_MS)).await {}
+    }
+
+    /// Next byte of binary data, `None` once `deadline` passes
+    async fn escaped(&mut self, deadline: Instant) -> Result<Option<Escaped>, ZmodemError> {
+        loop {
+            let Some(byte) = self.byte(deadline).await? else {
+                return Ok(None);
+            };
+            match byte {
+                ZDLE => break,
+                // Flow control from modems and terminal servers, never data
+                XON | XOFF | 0x91 | 0x93 => {}
+                _ => return Ok(Some(Escaped::Byte(byte))),
+            }
+        }
+        let mut cancels = 1;
+        loop {
+            let Some(byte) = self.byte(deadline).await? else {
+                return Ok(None);
+            };
+            let escaped = match byte {
+                ZDLE => {
+                    cancels += 1;
+                    if cancels >= 5 {
+                        return Err(ZmodemError::Cancelled);
+                    }
+                    continue;
+                }
+                XON | XOFF | 0x91 | 0x93 => continue,
+                ZCRCE | ZCRCG | ZCRCQ | ZCRCW => Escaped::End(byte),
+                ZRUB0 => Escaped::Byte(0x7F),
+                ZRUB1 => Escaped::Byte(0xFF),
+                _ if byte & 0x60 == 0x40 => Escaped::Byte(byte ^ 0x40),
+                _ => Escaped::Bad,
+            };
+            return Ok(Some(escaped));
+        }
+    }
+
+    /// `len` bytes of binary data, `None` if they were cut short or garbled
+    async fn escaped_bytes(&mut self, len: usize, deadline: Instant) -> Result<Option<Vec<u8>>, ZmodemError> {
+        let mut bytes = Vec::with_capacity(len);
+        while bytes.len() < len {
+            match self.escaped(deadline).await? {
+                Some(Escaped::Byte(byte)) => bytes.push(byte),
+                _ => return Ok(None),
+            }
+        }
+        Ok(Some(bytes))
+    }
+
+    /// Wait up to `timeout` for a header, skipping anything before it
+    async fn read_header(&mut self, timeout: Duration) -> Result<Incoming, ZmodemError> {
+        let deadline = Instant::now() + timeout;
+        let mut cancels = 0;
+        let mut after_pad = false;
+        let format = loop {
+            let Some(byte) = self.byte(deadline).await? else {
+                return Ok(Incoming::Timeout);
+            };
+            match byte {
+                ZDLE if after_pad => {
+                    let Some(format) = self.byte(deadline).await? else {
+                        return Ok(Incoming::Timeout);
+                    };
+                    match format {
+                        ZBIN | ZBIN32 | ZHEX => break format,
+                        ZDLE => cancels = 2,
+                        _ => cancels = 0,
+                    }
+                    after_pad = false;
+                }
+                ZDLE => {
+                    cancels += 1;
+                    if cancels >= 5 {
+                        return Err(ZmodemError::Cancelled);
+                    }
+                }
+                _ if byte & 0x7F == ZPAD => {
+                    after_pad = true;
+                    cancels = 0;
+                }
+                _ => {
+                    after_pad = false;
+                    cancels = 0;
+                }
+            }
+        };
+
+        let (bytes, crc32) = match format {
+            ZHEX => {
+                let mut digits = [0u8; 14];
+                for digit in &mut digits {
+                    let Some(byte) = self.byte(deadline).await? else {
+                        return Ok(Incoming::Timeout);
+                    };
+                    *digit = byte & 0x7F;
+                }
+                let Some(bytes) = std::str::from_utf8(&digits)
+                    .ok()
+                    .and_then(|digits| (0..7).map(|i| u8::from_str_radix(&digits[i * 2..i * 2 + 2], 16).ok()).collect::<Option<Vec<u8>>>())
+                else {
+                    return Ok(Incoming::Bad);
+                };
+                if crc16(&bytes[..5]) != u16::from_be_bytes([bytes[5], bytes[6]]) {
+                    return Ok(Incoming::Bad);
+                }
+                (bytes, false)
+            }
+            _ => {
+                let crc32 = format == ZBIN32;
+                let Some(bytes) = self.escaped_bytes(if crc32 { 9 } else { 7 }, deadline).await? else {
+                    return Ok(Incoming::Bad);
+                };
+                let valid = if crc32 {
+                    crc32_matches(&bytes[..5], &bytes[5..])
+                } else {
+                    crc16(&bytes[..5]) == u16::from_be_bytes([bytes[5], bytes[6]])
+                };
+                if !valid {
+                    return Ok(Incoming::Bad);
+                }
+                (bytes, crc32)
+            }
+        };
+        let header = Header { kind: bytes[0], data: [bytes[1], bytes[2], bytes[3], bytes[4]] };
+        Ok(Incoming::Header { header, crc32 })
+    }
+
+    /// A data subpacket and its end, `None` if it was garbled, failed its CRC or stopped arriving
+    async fn read_subpacket(&mut self, crc32: bool, timeout: Duration) -> Result<Option<(Vec<u8>, u8)>, ZmodemError> {
+        let deadline = Instant::now() + timeout;
+        let mut data = Vec::with_capacity(SUBPACKET);
+        let end = loop {
+            match self.escaped(deadline).await? {
+                Some(Escaped::Byte(byte)) if data.len() < MAX_SUBPACKET => data.push(byte),
+                Some(Escaped::End(end)) => break end,
+                _ => return Ok(None),
+            }
+        };
+        let Some(check) = self.escaped_bytes(if crc32 { 4 } else { 2 }, deadline).await? else {
+            return Ok(None);
+        };
+        data.push(end);
+        let valid = if crc32 { crc32_matches(&data, &check) } else { crc16(&data) == u16::from_be_bytes([check[0], check[1]]) };
+        data.pop();
+        Ok(valid.then_some((data, end)))
+    }
+}
+
+fn crc32_matches(data: &[u8], check: &[u8]) -> bool {
+    check.try_into().ok().map(u32::from_le_bytes) == Some(crc32(data))
+}
+
+/// Tell the other end to abandon the transfer, as lrzsz does
+async fn cancel(connection: &SerialConnection) {
+    let mut abort = vec![ZDLE; 8];
+    abort.extend([0x08; 10]);
+    let _ = connection.write(&abort).await;
+}
+
+fn is_cancel(header: &Header) -> bool {
+    header.kind == ZCAN || header.kind == ZABORT
+}
+
+/// Send `files`, waiting up to `start_timeout` for the receiver to start
+///
+/// With `resume`, the receiver is asked to keep the part of each file it
+/// already has and take only the rest. `progress` gets an entry per file as
+/// it starts, so a failure shows how far each one got.
+pub async fn send(
+    connection: &SerialConnection,
+    files: &[ZmodemFile],
+    start_timeout: Duration,
+    resume: bool,
+    progress: &mut Vec<SendProgress>,
+) -> Result<(), ZmodemError> {
+    let mut link = Link::new(connection);
+    let result = send_session(&mut link, files, start_timeout, resume, progress).await;
+    if matches!(result, Err(ZmodemError::Timeout { .. } | ZmodemError::Retries { .. } | ZmodemError::Protocol(_))) {
+        cancel(connection).await;
+    }
+    result
+}
+
+async fn send_session(
+    link: &mut Link<'_>,
+    files: &[ZmodemFile],
+    start_timeout: Duration,
+    resume: bool,
+    progress: &mut Vec<SendProgress>,
+) -> Result<(), ZmodemError> {
+    // Starts rz when the other end is a shell
+    link.write(b"rz\r").await?;
+    let caps = wait_for_receiver(link, start_timeout).await?;
+    for file in files {
+        progress.push(SendProgress {
+            name: file.name.clone(),
+            size: file.data.len() as u64,
+            start: 0,
+            bytes: 0,
+            retries: 0,
+            skipped: false,
+            complete: false,
+        });
+        let current = progress.last_mut().expect("just pushed");
+        send_file(link, file, &caps, resume, current).await?;
+    }
+    end_session(link).await
+}
+
+/// Ask for the receiver's ZRINIT until it comes or `timeout` passes
+async fn wait_for_receiver(link: &mut Link<'_>, timeout: Duration) -> Result<ReceiverCaps, ZmodemError> {
+    let deadline = Instant::now() + timeout;
+    let mut asked = false;
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            let stage = "waiting for the receiver to start".to_string();
+            return Err(ZmodemError::Timeout { stage, timeout_ms: timeout.as_millis() as u64 });
+        }
+        if !asked {
+            link.write(&hex_header(&Header::new(ZRQINIT))).await?;
+            asked = true;
+        }
+        match link.read_header(remaining.min(START_INTERVAL)).await? {
+            Incoming::Header { header, .. } => match header.kind {
+                ZRINIT => return Ok(ReceiverCaps::from_zrinit(&header)),
+                // Proof that a person is not at the other end, echoed back
+                ZCHALLENGE => link.write(&hex_header(&Header { kind: ZACK, data: header.data })).await?,
+                ZRQINIT => return Err(ZmodemError::Protocol("The other end is sending too".to_string())),
+                _ if is_cancel(&header) => return Err(ZmodemError::Cancelled),
+                _ => asked = false,
+            },
+            Incoming::Bad | Incoming::Timeout => asked = false,
+        }
+    }
+}
+
+/// Offer one file and stream it from wherever the receiver asks
+async fn send_file(
+    link: &mut Link<'_>,
+    file: &ZmodemFile,
+    caps: &ReceiverCaps,
+    resume: bool,
+    progress: &mut SendProgress,
+) -> Result<(), ZmodemError> {
+    let size = file.data.len() as u64;
+    let Some(mut position) = offer_file(link, file, caps, resume).await? else {
+        progress.skipped = true;
+        return Ok(());
+    };
+    if position > size {
+        return Err(ZmodemError::Protocol(format!("Receiver asked for offset {} of {}, which is {} bytes", position, file.name, size)));
+    }
+    progress.start = position;
+    let mut failures = 0;
+
+    'frames: loop {
+        link.write(&binary_header(&Header::at(ZDATA, position), caps)).await?;
+        let frame_start = position;
+        loop {
+            let end = (position as usize + SUBPACKET).min(file.data.len());
+            let last = end == file.data.len();
+            let kind = if last {
+                ZCRCE
+            } else if caps.streaming {
+                ZCRCG
+            } else {
+                ZCRCW
+            };
+            link.write(&subpacket(&file.data[position as usize..end], kind, caps)).await?;
+            position = end as u64;
+            progress.bytes = position;
+
+            // Without streaming, each subpacket waits for its ACK
+            let reply = if kind == ZCRCW || link.has_reply().await {
+                Some(link.read_header(BLOCK_TIMEOUT).await?)
+            } else {
+                None
+            };
+            match reply {
+                None => {}
+                Some(Incoming::Header { header, .. }) if header.kind == ZACK => {
+                    failures = 0;
+                    // ZCRCW ended the frame, so the next subpacket needs a new one
+                    if kind == ZCRCW {
+                        continue 'frames;
+                    }
+                }
+                Some(Incoming::Header { header, .. }) if header.kind == ZRPOS => {
+                    position = header.position().min(size);
+                    failures += 1;
+                    progress.retries += 1;
+                    if failures >= MAX_RETRIES {
+                        return Err(ZmodemError::Retries { stage: format!("{} at offset {}", file.name, position) });
+                    }
+                    continue 'frames;
+                }
+                Some(Incoming::Header { header, .. }) if header.kind == ZSKIP => {
+                    progress.skipped = true;
+                    return Ok(());
+                }
+                Some(Incoming::Header { header, .. }) if is_cancel(&header) => return Err(ZmodemError::Cancelled),
+                // A lost ACK: send the frame again
+                Some(_) if kind == ZCRCW => {
+                    position = frame_start;
+                    failures += 1;
+                    progress.retries += 1;
+                    if failures >= MAX_RETRIES {
+                        return Err(ZmodemError::Retries { stage: format!("{} at offset {}", file.name, position) });
+                    }
+                    continue 'frames;
+                }
+                Some(_) => {}
+            }
+            if last {
+                break;
+            }
+        }
+
+        // The receiver answers ZEOF with ZRINIT once it has the whole file
+        for _ in 0..MAX_RETRIES {
+            link.write(&binary_header(&Header::at(ZEOF, size), caps)).await?;
+            while let Incoming::Header { header, .. } = link.read_header(BLOCK_TIMEOUT).await? {
+                match header.kind {
+                    ZRINIT => {
+                        progress.complete = true;
+                        return Ok(());
+                    }
+                    ZRPOS => {
+                        position = header.position().min(size);
+                        failures += 1;
+                        progress.retries += 1;
+                        if failures >= MAX_RETRIES {
+                            return Err(ZmodemError::Retries { stage: format!("{} at offset {}", file.name, position) });
+                        }
+                        continue 'frames;
+                    }
+                    ZSKIP => {
+                        progress.skipped = true;
+                        return Ok(());
+                    }
+                    // Replies to earlier subpackets
+                    ZACK => continue,
+                    _ if is_cancel(&header) => return Err(ZmodemError::Cancelled),
+                    _ => break,
+                }
+            }
+        }
+        return Err(ZmodemError::Retries { stage: format!("ending {}", file.name) });
+    }
+}
+
+/// Send ZFILE until the receiver names an offset to start from, `None` if it skips the file
+async fn offer_file(link: &mut Link<'_>, file: &ZmodemFile, caps: &ReceiverCaps, resume: bool) -> Result<Option<u64>, ZmodemError> {
+    let mut offer = binary_header(&Header::with_flags(ZFILE, if resume { ZCRESUM } else { ZCBIN }), caps);
+    offer.extend(subpacket(&file_info(&file.name, file.data.len() as u64), ZCRCW, caps));
+    for _ in 0..MAX_RETRIES {
+        link.write(&offer).await?;
+        while let Incoming::Header { header, .. } = link.read_header(BLOCK_TIMEOUT).await? {
+            match header.kind {
+                ZRPOS => return Ok(Some(header.position())),
+                ZSKIP => return Ok(None),
+                // The receiver checks a file it has against ours, over its length or the length asked
+                ZCRC => {
+                    let len = match header.position() as usize {
+                        0 => file.data.len(),
+                        len => len.min(file.data.len()),
+                    };
+                    let check = crc32(&file.data[..len]);
+                    link.write(&hex_header(&Header::at(ZCRC, check as u64))).await?;
+                }
+                _ if is_cancel(&header) => return Err(ZmodemError::Cancelled),
+                ZFIN => return Err(ZmodemError::Protocol("Receiver ended the session".to_string())),
+                // ZRINIT or ZNAK: the offer did not arrive
+                _ => break,
+            }
+        }
+    }
+    Err(ZmodemError::Retries { stage: format!("offering {}", file.name) })
+}
+
+/// Exchange ZFIN and sign off with "OO"
+async fn end_session(link: &mut Link<'_>) -> Result<(), ZmodemError> {
+    for _ in 0..MAX_RETRIES {
+        link.write(&hex_header(&Header::new(ZFIN))).await?;
+        loop {
+            match link.read_header(BLOCK_TIMEOUT).await? {
+                Incoming::Header { header, .. } if header.kind == ZFIN => {
+                    link.write(b"OO").await?;
+                    return Ok(());
+                }
+                Incoming::Header { header, .. } if is_cancel(&header) => return Err(ZmodemError::Cancelled),
+                // The ZRINIT ending the last file, repeated
+                Incoming::Header { header, .. } if header.kind == ZRINIT => continue,
+                _ => break,
+            }
+        }
+    }
+    Err(ZmodemError::Retries { stage: "ending the session".to_string() })
+}
+
+/// Receive files, waiting up to `start_timeout` for the sender to start
+///
+/// Nothing is sent until the sender's ZRQINIT arrives, so a shell on the
+/// other end does not see stray headers. `accept` is given each file's
+/// name, size and whether the sender asked to resume, and returns the offset
+/// to start from, or `None` to skip the file. Files go into `files` as they
+/// start, so one cut short by a failure keeps what arrived.
+pub async fn receive<F>(connection: &SerialConnection, start_timeout: Duration, accept: F, files: &mut Vec<ReceivedFile>) -> Result<(), ZmodemError>
+where
+    F: FnMut(&str, Option<u64>, bool) -> Option<u64>,
+{
+    let mut link = Link::new(connection);
+    let result = receive_session(&mut link, start_timeout, accept, files).await;
+    if matches!(result, Err(ZmodemError::Timeout { .. } | ZmodemError::Retries { .. } | ZmodemError::Protocol(_))) {
+        cancel(connection).await;
+    }
+    result
+}
+
+async fn receive_session<F>(link: &mut Link<'_>, start_timeout: Duration, mut accept: F, files: &mut Vec<ReceivedFile>) -> Result<(), ZmodemError>
+where
+    F: FnMut(&str, Option<u64>, bool) -> Option<u64>,
+{
+    let zrinit = hex_header(&Header::with_flags(ZRINIT, CANFDX | CANOVIO | CANFC32));
+    let start_deadline = Instant::now() + start_timeout;
+    let mut started = false;
+    let mut failures = 0;
+    loop {
+        let timeout = if started { BLOCK_TIMEOUT } else { start_deadline.saturating_duration_since(Instant::now()) };
+        let incoming = link.read_header(timeout).await?;
+        let Incoming::Header { header, crc32 } = incoming else {
+            if !started {
+                if incoming == Incoming::Timeout {
+                    let stage = "waiting for the sender to start".to_string();
+                    return Err(ZmodemError::Timeout { stage, timeout_ms: start_timeout.as_millis() as u64 });
+                }
+                continue;
+            }
+            failures += 1;
+            if failures >= MAX_RETRIES {
+                return Err(ZmodemError::Retries { stage: "waiting for the next file".to_string() });
+            }
+            link.write(&zrinit).await?;
+            continue;
+        };
+        started = true;
+
+        match header.kind {
+            ZRQINIT => link.write(&zrinit).await?,
+            // Carries the sender's attention string, which we have no use for
+            ZSINIT => {
+                if link.read_subpacket(crc32, BLOCK_TIMEOUT).await?.is_some() {
+                    link.write(&hex_header(&Header::new(ZACK))).await?;
+                } else {
+                    link.write(&hex_header(&Header::new(ZNAK))).await?;
+                }
+            }
+            ZFILE => {
+                let Some((info, _)) = link.read_subpacket(crc32, BLOCK_TIMEOUT).await? else {
+                    failures += 1;
+                    if failures >= MAX_RETRIES {
+                        return Err(ZmodemError::Retries { stage: "receiving a file header".to_string() });
+                    }
+                    link.write(&hex_header(&Header::new(ZNAK))).await?;
+                    continue;
+                };
+                let Some((name, size)) = parse_header(&info) else {
+                    return Err(ZmodemError::Protocol("Sender offered a file with no name".to_string()));
+                };
+                if size.is_some_and(|size| size > MAX_ZMODEM_FILE) {
+                    return Err(ZmodemError::Protocol(format!("{} is larger than the {} byte limit", name, MAX_ZMODEM_FILE)));
+                }
+                let Some(offset) = accept(&name, size, header.zf0() == ZCRESUM) else {
+                    link.write(&hex_header(&Header::new(ZSKIP))).await?;
+                    continue;
+                };
+                files.push(ReceivedFile { name, size, offset, data: Vec::new(), retries: 0, complete: false });
+                let current = files.last_mut().expect("just pushed");
+                receive_file(link, current).await?;
+                failures = 0;
+                link.write(&zrinit).await?;
+            }
+            ZFIN => {
+                link.write(&hex_header(&Header::new(ZFIN))).await?;
+                // The sender's "OO" sign-off, which may not come
+                let deadline = Instant::now() + Duration::from_millis(PURGE_IDLE_MS * 5);
+                for _ in 0..2 {
+                    if link.byte(deadline).await?.is_none() {
+                        break;
+                    }
+                }
+                return Ok(());
+            }
+            ZCOMMAND => return Err(ZmodemError::Protocol("Sender asked to run a command, which is refused".to_string())),
+            _ if is_cancel(&header) => return Err(ZmodemError::Cancelled),
+            // Left over from a file already finished or skipped
+            _ => link.write(&zrinit).await?,
+        }
+    }
+}
+
+/// Ask for a file from its offset and take data frames until its ZEOF
+async fn receive_file(link: &mut Link<'_>, file: &mut ReceivedFile) -> Result<(), ZmodemError> {
+    let mut position = file.offset;
+    let mut failures = 0;
+    link.write(&hex_header(&Header::at(ZRPOS, position))).await?;
+    loop {
+        let error = match link.read_header(BLOCK_TIMEOUT).await? {
+            Incoming::Header { header, crc32 } => match header.kind {
+                ZDATA if header.position() == position => receive_frame(link, file, &mut position, crc32).await?,
+                // Data from before our last ZRPOS arrived
+                ZDATA => true,
+                ZEOF if header.position() == position => {
+                    file.complete = true;
+                    return Ok(());
+                }
+                ZEOF => false,
+                // Our ZRPOS was lost
+                ZFILE => {
+                    link.read_subpacket(crc32, BLOCK_TIMEOUT).await?;
+                    true
+                }
+                ZFIN => return Err(ZmodemError::Protocol(format!("Sender ended the session part way through {}", file.name))),
+                _ if is_cancel(&header) => return Err(ZmodemError::Cancelled),
+                _ => true,
+            },
+            Incoming::Bad | Incoming::Timeout => true,
+        };
+        if error {
+            failures += 1;
+            file.retries += 1;
+            if failures >= MAX_RETRIES {
+                return Err(ZmodemError::Retries { stage: format!("{} at offset {}", file.name, position) });
+            }
+            if position > file.offset {
+                link.purge().await;
+            }
+            link.write(&hex_header(&Header::at(ZRPOS, position))).await?;
+        } else {
+            failures = 0;
+        }
+    }
+}
+
+/// Take the subpackets of one data frame, `true` if one was bad
+async fn receive_frame(link: &mut Link<'_>, file: &mut ReceivedFile, position: &mut u64, crc32: bool) -> Result<bool, ZmodemError> {
+    loop {
+        let Some((data, end)) = link.read_subpacket(crc32, BLOCK_TIMEOUT).await? else {
+            return Ok(true);
+        };
+        *position += data.len() as u64;
+        if *position > MAX_ZMODEM_FILE {
+            return Err(ZmodemError::Protocol(format!("{} is larger than the {} byte limit", file.name, MAX_ZMODEM_FILE)));
+        }
+        file.data.extend(data);
+        match end {
+            ZCRCW => {
+                link.write(&hex_header(&Header::at(ZACK, *position))).await?;
+                return Ok(false);
+            }
+            ZCRCQ => link.write(&hex_header(&Header::at(ZACK, *position))).await?,
+            ZCRCE => return Ok(false),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_headers() {
+        // As lrzsz's sz starts a session
+        assert_eq!(hex_header(&Header::new(ZRQINIT)), b"**\x18B00000000000000\r\x8a\x11");
+        assert_eq!(hex_header(&Header::at(ZRPOS, 0x1234)), b"**\x18B09341200006367\r\x8a\x11".to_vec());
+        assert!(!hex_header(&Header::new(ZFIN)).ends_with(&[XON]));
+
+        let caps = ReceiverCaps { crc32: false, escape_ctl: false, streaming: true };
+        let header = binary_header(&Header::at(ZDATA, 0x18), &caps);
+        assert_eq!(&header[..4], &[ZPAD, ZDLE, ZBIN, ZDATA]);
+        // The offset byte is ZDLE, so it is escaped
+        assert_eq!(&header[4..6], &[ZDLE, 0x58]);
+
+        let zrinit = Header::with_flags(ZRINIT, CANFDX | CANOVIO | CANFC32);
+        assert_eq!(ReceiverCaps::from_zrinit(&zrinit), ReceiverCaps { crc32: true, escape_ctl: false, streaming: true });
+        let windowed = Header { kind: ZRINIT, data: [0x00, 0x04, 0, CANFDX | CANOVIO] };
+        assert!(!ReceiverCaps::from_zrinit(&windowed).streaming);
+    }
+
+    #[test]
+    fn test_escaping() {
+        let mut out = Vec::new();
+        for byte in [b'a', ZDLE, XON, 0x93, 0x10, b'@', b'\r', 0x01] {
+            escape_into(&mut out, byte, false);
+        }
+        assert_eq!(out, [b'a', ZDLE, 0x58, ZDLE, 0x51, ZDLE, 0xD3, ZDLE, 0x50, b'@', ZDLE, 0x4D, 0x01]);
+
+        out.clear();
+        escape_into(&mut out, 0x01, true);
+        assert_eq!(out, [ZDLE, 0x41]);
+
+        // The CRC covers the data and the end marker
+        let caps = ReceiverCaps { crc32: true, escape_ctl: false, streaming: true };
+        let packet = subpacket(b"xyz", ZCRCG, &caps);
+        assert_eq!(&packet[..5], &[b'x', b'y', b'z', ZDLE, ZCRCG]);
+        assert_eq!(packet[5..].to_vec(), crc32(b"xyzi").to_le_bytes().to_vec());
+        assert_eq!(file_info("fw.bin", 1024), b"fw.bin\x001024\x00");
+    }
+}
+
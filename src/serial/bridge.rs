@@ -0,0 +1,201 @@
+//! Bridge tap
+//!
+//! Forwards everything received on one connection to another and back, and
+//! logs both directions with receive timestamps. Placed between a host
+//! application (typically on one end of a virtual null-modem pair) and the
+//! device, it sniffs their traffic without either side noticing.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+
+use super::connection::SerialConnection;
+
+/// Chunks forwarded per write
+const BRIDGE_MAX_CHUNKS: usize = 64;
+
+/// Bytes forwarded per write
+const BRIDGE_MAX_BYTES: usize = 4096;
+
+/// Wait before looking again while a side's reception is paused
+const BRIDGE_PAUSED_POLL_MS: u64 = 100;
+
+/// Which way a chunk crossed the bridge
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BridgeDirection {
+    HostToDevice,
+    DeviceToHost,
+}
+
+impl std::fmt::Display for BridgeDirection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BridgeDirection::HostToDevice => write!(f, "host->device"),
+            BridgeDirection::DeviceToHost => write!(f, "device->host"),
+        }
+    }
+}
+
+/// One chunk forwarded across the bridge
+#[derive(Debug, Clone)]
+pub struct BridgeRecord {
+    pub timestamp: DateTime<Utc>,
+    pub direction: BridgeDirection,
+    pub data: Vec<u8>,
+}
+
+/// Traffic totals of a bridge, as reported by inspection
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BridgeStats {
+    pub host_to_device_bytes: u64,
+    pub device_to_host_bytes: u64,
+    /// Records logged, including those since dropped from the log
+    pub records: u64,
+    /// Oldest records dropped to stay within the log capacity
+    pub dropped_records: u64,
+    /// Why forwarding stopped, if it failed
+    pub error: Option<String>,
+}
+
+/// Most recent forwarded chunks plus running totals
+#[derive(Debug)]
+struct BridgeLog {
+    records: VecDeque<BridgeRecord>,
+    capacity: usize,
+    stats: BridgeStats,
+}
+
+impl BridgeLog {
+    fn push(&mut self, record: BridgeRecord) {
+        let len = record.data.len() as u64;
+        match record.direction {
+            BridgeDirection::HostToDevice => self.stats.host_to_device_bytes += len,
+            BridgeDirection::DeviceToHost => self.stats.device_to_host_bytes += len,
+        }
+        self.stats.records += 1;
+
+        if self.records.len() == self.capacity {
+            self.records.pop_front();
+            self.stats.dropped_records += 1;
+        }
+        self.records.push_back(record);
+    }
+}
+
+/// Two connections with their traffic forwarded to each other
+#[derive(Debug)]
+pub struct Bridge {
+    host: Arc<SerialConnection>,
+    device: Arc<SerialConnection>,
+    log: Arc<Mutex<BridgeLog>>,
+    tasks: Vec<JoinHandle<()>>,
+    started_at: DateTime<Utc>,
+}
+
+impl Bridge {
+    /// Start forwarding between `host` and `device`, keeping the last `capacity` chunks
+    ///
+    /// Nothing else should read from either connection while the bridge runs.
+    pub fn start(host: Arc<SerialConnection>, device: Arc<SerialConnection>, capacity: usize) -> Self {
+        let log = Arc::new(Mutex::new(BridgeLog {
+            records: VecDeque::new(),
+            capacity: capacity.max(1),
+            stats: BridgeStats::default(),
+        }));
+        let tasks = vec![
+            tokio::spawn(forward(Arc::clone(&host), Arc::clone(&device), BridgeDirection::HostToDevice, Arc::clone(&log))),
+            tokio::spawn(forward(Arc::clone(&device), Arc::clone(&host), BridgeDirection::DeviceToHost, Arc::clone(&log))),
+        ];
+
+        Self {
+            host,
+            device,
+            log,
+            tasks,
+            started_at: Utc::now(),
+        }
+    }
+
+    pub fn host(&self) -> &Arc<SerialConnection> {
+        &self.host
+    }
+
+    pub fn device(&self) -> &Arc<SerialConnection> {
+        &self.device
+    }
+
+    pub fn started_at(&self) -> DateTime<Utc> {
+        self.started_at
+    }
+
+    /// Whether both directions are still forwarding
+    pub fn is_running(&self) -> bool {
+        self.tasks.iter().all(|task| !task.is_finished())
+    }
+
+    /// The last `limit` logged chunks, oldest first
+    pub async fn recent(&self, limit: usize) -> Vec<BridgeRecord> {
+        let log = self.log.lock().await;
+        let skip = log.records.len().saturating_sub(limit);
+        log.records.iter().skip(skip).cloned().collect()
+    }
+
+    pub async fn stats(&self) -> BridgeStats {
+        self.log.lock().await.stats.clone()
+    }
+
+    /// Stop forwarding; the connections stay open
+    pub fn stop(&self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+impl Drop for Bridge {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Copy chunks received on `source` to `sink` until either side fails
+async fn forward(
+    source: Arc<SerialConnection>,
+    sink: Arc<SerialConnection>,
+    direction: BridgeDirection,
+    log: Arc<Mutex<BridgeLog>>,
+) {
+    let error = loop {
+        let chunks = match source.read_batch(BRIDGE_MAX_CHUNKS, BRIDGE_MAX_BYTES, Duration::ZERO, None).await {
+            Ok(chunks) => chunks,
+            Err(e) => break e,
+        };
+        if chunks.is_empty() {
+            // Reception is paused
+            tokio::time::sleep(Duration::from_millis(BRIDGE_PAUSED_POLL_MS)).await;
+            continue;
+        }
+
+        let data: Vec<u8> = chunks.iter().flat_map(|chunk| chunk.data.iter().copied()).collect();
+        if let Err(e) = sink.write(&data).await {
+            break e;
+        }
+
+        let mut log = log.lock().await;
+        for chunk in chunks {
+            log.push(BridgeRecord {
+                timestamp: chunk.timestamp,
+                direction,
+                data: chunk.data,
+            });
+        }
+    };
+
+    log.lock().await.stats.error.get_or_insert(format!("{} stopped: {}", direction, error));
+}
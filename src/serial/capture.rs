@@ -0,0 +1,483 @@
+//! Traffic capture files
+//!
+//! Records every byte a connection sends or receives to a file, for
+//! post-mortem debugging of intermittent device failures.
+
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::error::SerialError;
+use super::history::Direction;
+use crate::durable::{DurableFile, FileIntegrity, DEFAULT_SYNC_INTERVAL};
+use crate::error::StateError;
+use crate::utils::BufferUtils;
+
+/// Version of the capture layout, recorded in the footer
+///
+/// Footers without a version were written before it was recorded, in the
+/// version 1 layout. Bump this and teach [`read_capture`] the old layout when
+/// records change.
+pub const CAPTURE_FORMAT_VERSION: u32 = 1;
+
+/// Start of the footer line of hexdump captures and raw capture sidecars
+const HEXDUMP_FOOTER_PREFIX: &str = "# footer";
+
+/// Layout of a capture file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CaptureFormat {
+    /// Bytes exactly as they crossed the port, both directions interleaved
+    Raw,
+    /// Timestamped, direction-tagged hexdump for reading by eye
+    Hexdump,
+    /// One JSON record per chunk with timestamp, direction and hex data
+    Jsonl,
+}
+
+impl CaptureFormat {
+    pub fn parse(value: &str) -> Result<Self, SerialError> {
+        match value.to_lowercase().as_str() {
+            "raw" => Ok(CaptureFormat::Raw),
+            "hexdump" => Ok(CaptureFormat::Hexdump),
+            "jsonl" => Ok(CaptureFormat::Jsonl),
+            _ => Err(SerialError::InvalidConfig(format!("Unknown capture format: {}", value))),
+        }
+    }
+
+    /// File extension used for generated capture file names
+    pub fn extension(&self) -> &'static str {
+        match self {
+            CaptureFormat::Raw => "bin",
+            CaptureFormat::Hexdump => "hex",
+            CaptureFormat::Jsonl => "jsonl",
+        }
+    }
+}
+
+impl std::fmt::Display for CaptureFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CaptureFormat::Raw => write!(f, "raw"),
+            CaptureFormat::Hexdump => write!(f, "hexdump"),
+            CaptureFormat::Jsonl => write!(f, "jsonl"),
+        }
+    }
+}
+
+/// One line of a JSONL capture
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CaptureRecord {
+    pub timestamp: DateTime<Utc>,
+    pub direction: Direction,
+    /// Chunk contents as lowercase hex
+    pub data: String,
+}
+
+/// An open capture file receiving a connection's traffic
+///
+/// Written through a [`DurableFile`], so a crash leaves whole records up to the
+/// last sync. Closing the capture appends an integrity footer; raw captures get
+/// it in a `.footer` file next to them instead, to keep the raw bytes untouched.
+#[derive(Debug)]
+pub struct CaptureFile {
+    format: CaptureFormat,
+    file: DurableFile,
+}
+
+impl CaptureFile {
+    /// Create `path` and its parent directories, replacing any existing file
+    pub fn create(path: &Path, format: CaptureFormat) -> Result<Self, SerialError> {
+        Ok(Self {
+            format,
+            file: DurableFile::create(path, DEFAULT_SYNC_INTERVAL)?,
+        })
+    }
+
+    /// Timestamped file name for a capture of `port`, e.g. `ttyUSB0-20261018-142501.jsonl`
+    pub fn default_file_name(port: &str, format: CaptureFormat, started: DateTime<Utc>) -> String {
+        let device = port
+            .rsplit(['/', '\\'])
+            .next()
+            .unwrap_or(port)
+            .replace(|c: char| !c.is_ascii_alphanumeric() && c != '-' && c != '_', "_");
+        format!("{}-{}.{}", device, started.format("%Y%m%d-%H%M%S"), format.extension())
+    }
+
+    /// Append one chunk of traffic
+    pub fn record(&mut self, timestamp: DateTime<Utc>, direction: Direction, data: &[u8]) -> Result<(), SerialError> {
+        let entry = match self.format {
+            CaptureFormat::Raw => data.to_vec(),
+            CaptureFormat::Hexdump => hexdump(timestamp, direction, data).into_bytes(),
+            CaptureFormat::Jsonl => {
+                let record = CaptureRecord {
+                    timestamp,
+                    direction,
+                    data: hex::encode(data),
+                };
+                let mut line = serde_json::to_vec(&record)
+                    .map_err(|e| SerialError::EncodingError(e.to_string()))?;
+                line.push(b'\n');
+                line
+            }
+        };
+
+        self.file.append(&entry)?;
+        Ok(())
+    }
+
+    /// Sync recorded traffic to disk if the sync interval has elapsed
+    pub fn sync_if_due(&mut self) -> Result<(), SerialError> {
+        self.file.sync_if_due()?;
+        Ok(())
+    }
+
+    /// Write the integrity footer and close the capture cleanly
+    pub fn finish(&mut self) -> Result<(), SerialError> {
+        let footer = format_footer(self.format, &self.file.integrity());
+        match self.format {
+            CaptureFormat::Raw => {
+                self.file.finish(&[])?;
+                std::fs::write(footer_path(self.file.path()), footer)?;
+            }
+            CaptureFormat::Hexdump | CaptureFormat::Jsonl => self.file.finish(footer.as_bytes())?,
+        }
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        self.file.path()
+    }
+
+    pub fn format(&self) -> CaptureFormat {
+        self.format
+    }
+
+    /// Bytes written to the capture file so far, excluding the footer
+    pub fn bytes_captured(&self) -> u64 {
+        self.file.integrity().bytes
+    }
+}
+
+impl Drop for CaptureFile {
+    fn drop(&mut self) {
+        // Close cleanly even when the connection goes away without stopping the capture
+        let _ = self.finish();
+    }
+}
+
+/// Sidecar file holding the footer of a raw capture
+fn footer_path(path: &Path) -> std::path::PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".footer");
+    name.into()
+}
+
+fn format_footer(format: CaptureFormat, integrity: &FileIntegrity) -> String {
+    match format {
+        CaptureFormat::Jsonl => format!(
+            "{{\"footer\":{{\"records\":{},\"bytes\":{},\"md5\":\"{}\",\"version\":{}}}}}\n",
+            integrity.records, integrity.bytes, integrity.md5, CAPTURE_FORMAT_VERSION
+        ),
+        CaptureFormat::Raw | CaptureFormat::Hexdump => format!(
+            "{} records={} bytes={} md5={} version={}\n",
+            HEXDUMP_FOOTER_PREFIX, integrity.records, integrity.bytes, integrity.md5, CAPTURE_FORMAT_VERSION
+        ),
+    }
+}
+
+/// Parse a footer line into the capture format version and integrity summary
+fn parse_footer(line: &str) -> Option<(u32, FileIntegrity)> {
+    if let Some(fields) = line.strip_prefix(HEXDUMP_FOOTER_PREFIX) {
+        let mut version = 1;
+        let mut integrity = FileIntegrity { records: 0, bytes: 0, md5: String::new() };
+        for field in fields.split_whitespace() {
+            match field.split_once('=')? {
+                ("records", value) => integrity.records = value.parse().ok()?,
+                ("bytes", value) => integrity.bytes = value.parse().ok()?,
+                ("md5", value) => integrity.md5 = value.to_string(),
+                ("version", value) => version = value.parse().ok()?,
+                _ => {}
+            }
+        }
+        return Some((version, integrity));
+    }
+
+    #[derive(Deserialize)]
+    struct Footer {
+        footer: FooterFields,
+    }
+    #[derive(Deserialize)]
+    struct FooterFields {
+        records: u64,
+        bytes: u64,
+        md5: String,
+        #[serde(default = "default_capture_version")]
+        version: u32,
+    }
+    let footer: Footer = serde_json::from_str(line).ok()?;
+    let integrity = FileIntegrity {
+        records: footer.footer.records,
+        bytes: footer.footer.bytes,
+        md5: footer.footer.md5,
+    };
+    Some((footer.footer.version, integrity))
+}
+
+fn default_capture_version() -> u32 { 1 }
+
+/// Whether a capture file was closed cleanly and is intact
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CaptureIntegrity {
+    /// The footer is present and matches the content
+    Verified,
+    /// No footer: the writer stopped without closing, e.g. on a crash
+    Unfinished,
+    /// The footer does not match the content
+    Corrupt(String),
+}
+
+impl std::fmt::Display for CaptureIntegrity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CaptureIntegrity::Verified => write!(f, "verified"),
+            CaptureIntegrity::Unfinished => write!(f, "unfinished (writer did not close the capture)"),
+            CaptureIntegrity::Corrupt(reason) => write!(f, "corrupt ({})", reason),
+        }
+    }
+}
+
+/// Records read back from a capture file
+#[derive(Debug, Clone)]
+pub struct CaptureContents {
+    pub records: Vec<CaptureRecord>,
+    pub integrity: CaptureIntegrity,
+}
+
+/// Read back the records of a JSONL or hexdump capture
+///
+/// Raw captures cannot be read back, as they do not record direction. In an
+/// unfinished capture, a torn final record is skipped.
+pub fn read_capture(path: &Path) -> Result<CaptureContents, SerialError> {
+    let content = std::fs::read(path)?;
+    // JSONL and hexdump captures are plain ASCII, even when torn
+    if std::str::from_utf8(&content).is_err() {
+        let error_msg = format!("{} is a raw capture; only JSONL and hexdump captures keep direction", path.display());
+        return Err(SerialError::EncodingError(error_msg));
+    }
+
+    // The footer, if any, is the last line
+    let trimmed = content.strip_suffix(b"\n").unwrap_or(&content);
+    let last_line_start = trimmed.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+    let footer = std::str::from_utf8(&trimmed[last_line_start..]).ok().and_then(parse_footer);
+
+    let (body, integrity) = match footer {
+        Some((version, _)) if version > CAPTURE_FORMAT_VERSION => {
+            let error = StateError::TooNew {
+                path: path.display().to_string(),
+                kind: "capture".to_string(),
+                version,
+                supported: CAPTURE_FORMAT_VERSION,
+            };
+            return Err(SerialError::EncodingError(error.to_string()));
+        }
+        Some((_, expected)) => {
+            let body = &content[..last_line_start];
+            let md5 = format!("{:x}", md5::compute(body));
+            let integrity = if expected.bytes != body.len() as u64 {
+                CaptureIntegrity::Corrupt(format!("footer expects {} bytes, found {}", expected.bytes, body.len()))
+            } else if expected.md5 != md5 {
+                CaptureIntegrity::Corrupt(format!("footer expects md5 {}, found {}", expected.md5, md5))
+            } else {
+                CaptureIntegrity::Verified
+            };
+            (body, integrity)
+        }
+        None => {
+            let complete = content.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+            (&content[..complete], CaptureIntegrity::Unfinished)
+        }
+    };
+
+    let text = std::str::from_utf8(body).map_err(|e| SerialError::EncodingError(e.to_string()))?;
+
+    let first = text.lines().find(|l| !l.trim().is_empty()).unwrap_or_default();
+    let records = if first.starts_with('{') {
+        text.lines()
+            .filter(|l| !l.trim().is_empty())
+            .enumerate()
+            .map(|(index, line)| {
+                serde_json::from_str(line)
+                    .map_err(|e| SerialError::EncodingError(format!("{} line {}: {}", path.display(), index + 1, e)))
+            })
+            .collect::<Result<Vec<_>, _>>()?
+    } else {
+        parse_hexdump(text)
+            .ok_or_else(|| SerialError::EncodingError(format!("{} is not a JSONL or hexdump capture", path.display())))?
+    };
+
+    Ok(CaptureContents { records, integrity })
+}
+
+/// Parse the output of [`hexdump`] back into records
+fn parse_hexdump(text: &str) -> Option<Vec<CaptureRecord>> {
+    let mut records: Vec<CaptureRecord> = Vec::new();
+
+    for line in text.lines().filter(|l| !l.trim().is_empty()) {
+        let mut fields = line.split_whitespace();
+        let first = fields.next()?;
+        if let Ok(timestamp) = DateTime::parse_from_rfc3339(first) {
+            let direction = match fields.next()? {
+                "TX" => Direction::Tx,
+                "RX" => Direction::Rx,
+                _ => return None,
+            };
+            records.push(CaptureRecord {
+                timestamp: timestamp.with_timezone(&Utc),
+                direction,
+                data: String::new(),
+            });
+        } else {
+            // Row: offset, then hex bytes up to the ASCII column
+            let record = records.last_mut()?;
+            let hex_column = line.get(10..)?.split("  |").next()?;
+            record.data.push_str(&hex_column.split_whitespace().collect::<String>());
+        }
+    }
+
+    Some(records)
+}
+
+/// Render a chunk as a header line followed by offset/hex/ASCII rows
+fn hexdump(timestamp: DateTime<Utc>, direction: Direction, data: &[u8]) -> String {
+    let tag = match direction {
+        Direction::Tx => "TX",
+        Direction::Rx => "RX",
+    };
+    let mut out = format!("{} {} {} bytes\n", timestamp.to_rfc3339(), tag, data.len());
+    out.push_str(&BufferUtils::hexdump(data));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_file_name() {
+        let started = DateTime::parse_from_rfc3339("2026-10-18T14:25:01Z").unwrap().with_timezone(&Utc);
+        assert_eq!(
+            CaptureFile::default_file_name("/dev/ttyUSB0", CaptureFormat::Jsonl, started),
+            "ttyUSB0-20261018-142501.jsonl"
+        );
+        assert_eq!(
+            CaptureFile::default_file_name(r"\\.\COM3", CaptureFormat::Raw, started),
+            "COM3-20261018-142501.bin"
+        );
+    }
+
+    #[test]
+    fn test_read_capture_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let timestamp = Utc::now();
+        let long: Vec<u8> = (0u8..40).collect();
+
+        for format in [CaptureFormat::Jsonl, CaptureFormat::Hexdump] {
+            let path = dir.path().join(format!("trace.{}", format.extension()));
+            let mut capture = CaptureFile::create(&path, format).unwrap();
+            capture.record(timestamp, Direction::Tx, &long).unwrap();
+            capture.record(timestamp, Direction::Rx, b"| ok |").unwrap();
+            capture.finish().unwrap();
+
+            let contents = read_capture(&path).unwrap();
+            assert_eq!(contents.integrity, CaptureIntegrity::Verified);
+            assert_eq!(contents.records.len(), 2);
+            assert_eq!(contents.records[0].direction, Direction::Tx);
+            assert_eq!(hex::decode(&contents.records[0].data).unwrap(), long);
+            assert_eq!(hex::decode(&contents.records[1].data).unwrap(), b"| ok |");
+        }
+
+        let raw = dir.path().join("trace.bin");
+        let mut capture = CaptureFile::create(&raw, CaptureFormat::Raw).unwrap();
+        capture.record(timestamp, Direction::Tx, &[0xff, 0xfe]).unwrap();
+        drop(capture);
+        assert_eq!(std::fs::read(&raw).unwrap(), vec![0xff, 0xfe]);
+        let footer = std::fs::read_to_string(dir.path().join("trace.bin.footer")).unwrap();
+        assert_eq!(parse_footer(footer.trim_end()).unwrap(), (CAPTURE_FORMAT_VERSION, FileIntegrity {
+            records: 1,
+            bytes: 2,
+            md5: format!("{:x}", md5::compute([0xff, 0xfe])),
+        }));
+        assert!(read_capture(&raw).is_err());
+    }
+
+    #[test]
+    fn test_unfinished_and_corrupt_captures() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("trace.jsonl");
+        let mut capture = CaptureFile::create(&path, CaptureFormat::Jsonl).unwrap();
+        capture.record(Utc::now(), Direction::Tx, b"AT\r").unwrap();
+        capture.record(Utc::now(), Direction::Rx, b"OK").unwrap();
+        capture.finish().unwrap();
+        let clean = std::fs::read_to_string(&path).unwrap();
+        let mut lines: Vec<&str> = clean.lines().collect();
+
+        // Crash: no footer and a torn last record
+        let torn = format!("{}\n{}", lines[0], &lines[1][..10]);
+        std::fs::write(&path, torn).unwrap();
+        let contents = read_capture(&path).unwrap();
+        assert_eq!(contents.integrity, CaptureIntegrity::Unfinished);
+        assert_eq!(contents.records.len(), 1);
+
+        // Content altered after the footer was written
+        lines[0] = r#"{"timestamp":"2026-10-18T00:00:00Z","direction":"tx","data":"00"}"#;
+        std::fs::write(&path, lines.join("\n") + "\n").unwrap();
+        assert!(matches!(read_capture(&path).unwrap().integrity, CaptureIntegrity::Corrupt(_)));
+    }
+
+    #[test]
+    fn test_capture_format_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("trace.hex");
+        let body = "2026-10-18T00:00:00+00:00 RX 2 bytes\n00000000  4f 4b                                            |OK|\n";
+        let md5 = format!("{:x}", md5::compute(body));
+
+        // Footer written before versions were recorded
+        std::fs::write(&path, format!("{}# footer records=1 bytes={} md5={}\n", body, body.len(), md5)).unwrap();
+        assert_eq!(read_capture(&path).unwrap().integrity, CaptureIntegrity::Verified);
+
+        std::fs::write(&path, format!("{}# footer records=1 bytes={} md5={} version=2\n", body, body.len(), md5)).unwrap();
+        let err = read_capture(&path).unwrap_err();
+        assert!(err.to_string().contains("written by a newer server"));
+    }
+
+    #[test]
+    fn test_capture_formats() {
+        let dir = tempfile::tempdir().unwrap();
+        let timestamp = Utc::now();
+
+        let jsonl = dir.path().join("trace.jsonl");
+        let mut capture = CaptureFile::create(&jsonl, CaptureFormat::Jsonl).unwrap();
+        capture.record(timestamp, Direction::Tx, b"AT\r").unwrap();
+        capture.record(timestamp, Direction::Rx, b"OK").unwrap();
+        capture.finish().unwrap();
+
+        let content = std::fs::read_to_string(&jsonl).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 3);
+        let record: CaptureRecord = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(record.direction, Direction::Tx);
+        assert_eq!(record.data, "41540d");
+        assert!(lines[2].starts_with(r#"{"footer":{"records":2,"#));
+
+        let dump = dir.path().join("trace.hex");
+        let mut capture = CaptureFile::create(&dump, CaptureFormat::Hexdump).unwrap();
+        capture.record(timestamp, Direction::Rx, b"OK\r\n").unwrap();
+        capture.finish().unwrap();
+        let content = std::fs::read_to_string(&dump).unwrap();
+        assert!(content.contains(" RX 4 bytes\n"));
+        assert!(content.contains("00000000  4f 4b 0d 0a"));
+        assert!(content.contains("|OK..|\n# footer records=1 "));
+    }
+}
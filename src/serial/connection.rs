@@ -1,15 +1,49 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
-use tokio::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, MutexGuard};
+use tokio::task::JoinHandle;
 use tokio::time::timeout;
-use tokio_serial::{SerialPortBuilderExt, SerialStream};
+use tokio_serial::{SerialPort, SerialPortBuilderExt, SerialStream};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
+use tracing::{error, warn};
 
+use super::capture::{CaptureFile, CaptureFormat};
 use super::error::SerialError;
+use super::filter::{ReceiveFilter, ReceivePipeline};
+use super::history::{ConnectionEvent, ConnectionHistory, Direction, TranscriptEntry};
+use super::lines::ReceivedLine;
+use super::lock::PortLock;
+use super::rate_limit::WriteLimiter;
+use super::sequence::CommandSequence;
+use super::reader::{spawn_reader, ReaderShared, RxBuffer, RxChunk, RxSpan, DEFAULT_RX_BUFFER_SIZE};
+use super::stats::RxTimingSummary;
+use super::write_queue::{WriteQueue, WriteTurn};
+use super::write_rules::{RefusedWrite, WriteAudit, WriteRule};
+use crate::protocol::{FrameDecoder, FrameError, Framer};
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+/// XOFF control character used for software flow control
+const XOFF: u8 = 0x13;
+
+/// XON control character used for software flow control
+const XON: u8 = 0x11;
+
+/// Interrupted writes retried before giving up
+const MAX_WRITE_INTERRUPTIONS: u32 = 3;
+
+/// Upper bound on how long a device may keep answering a single command
+const MAX_COMMAND_OUTPUT_MS: u64 = 10_000;
+
+/// Bytes taken from the receive buffer per decoding step when reading frames
+const FRAME_READ_CHUNK: usize = 4096;
+
+/// Bytes handed over at a time by [`SerialConnection::read_until`]
+const READ_UNTIL_CHUNK: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum DataBits {
     #[serde(rename = "5")]
     Five,
@@ -32,7 +66,7 @@ impl From<DataBits> for serialport::DataBits {
     }
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum StopBits {
     #[serde(rename = "1")]
     One,
@@ -49,7 +83,7 @@ impl From<StopBits> for serialport::StopBits {
     }
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum Parity {
     None,
@@ -67,7 +101,7 @@ impl From<Parity> for serialport::Parity {
     }
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum FlowControl {
     None,
@@ -97,6 +131,8 @@ pub struct ConnectionConfig {
     pub parity: Parity,
     #[serde(default = "default_flow_control")]
     pub flow_control: FlowControl,
+    #[serde(default)]
+    pub backpressure: BackpressurePolicy,
 }
 
 fn default_data_bits() -> DataBits { DataBits::Eight }
@@ -104,6 +140,198 @@ fn default_stop_bits() -> StopBits { StopBits::One }
 fn default_parity() -> Parity { Parity::None }
 fn default_flow_control() -> FlowControl { FlowControl::None }
 
+impl ConnectionConfig {
+    /// Time one character occupies on the wire, counting start, parity and stop bits
+    pub fn char_time(&self) -> Duration {
+        let data_bits = match self.data_bits {
+            DataBits::Five => 5,
+            DataBits::Six => 6,
+            DataBits::Seven => 7,
+            DataBits::Eight => 8,
+        };
+        let parity_bits = if matches!(self.parity, Parity::None) { 0 } else { 1 };
+        let stop_bits = match self.stop_bits {
+            StopBits::One => 1,
+            StopBits::Two => 2,
+        };
+        let bits_per_char = 1 + data_bits + parity_bits + stop_bits;
+        Duration::from_micros(bits_per_char * 1_000_000 / u64::from(self.baud_rate.max(1)))
+    }
+
+    /// Conventional short form of the character format, e.g. `8N1`
+    pub fn frame_format(&self) -> String {
+        let data_bits = match self.data_bits {
+            DataBits::Five => '5',
+            DataBits::Six => '6',
+            DataBits::Seven => '7',
+            DataBits::Eight => '8',
+        };
+        let parity = match self.parity {
+            Parity::None => 'N',
+            Parity::Odd => 'O',
+            Parity::Even => 'E',
+        };
+        let stop_bits = match self.stop_bits {
+            StopBits::One => '1',
+            StopBits::Two => '2',
+        };
+        format!("{}{}{}", data_bits, parity, stop_bits)
+    }
+
+    /// Whether both configurations put the same signal on the line
+    pub fn same_line_settings(&self, other: &ConnectionConfig) -> bool {
+        self.baud_rate == other.baud_rate
+            && self.data_bits == other.data_bits
+            && self.stop_bits == other.stop_bits
+            && self.parity == other.parity
+            && self.flow_control == other.flow_control
+    }
+}
+
+/// Signal asserted towards the device while reception is paused
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PauseSignal {
+    /// Stop reading without signalling the device
+    None,
+    /// Deassert RTS so hardware flow control holds the device off
+    Rts,
+    /// Send XOFF, released with XON on resume
+    Xoff,
+}
+
+impl PauseSignal {
+    pub fn parse(value: &str) -> Result<Self, SerialError> {
+        match value.to_lowercase().as_str() {
+            "none" => Ok(PauseSignal::None),
+            "rts" => Ok(PauseSignal::Rts),
+            "xoff" => Ok(PauseSignal::Xoff),
+            _ => Err(SerialError::InvalidConfig(format!("Unknown pause signal: {}", value))),
+        }
+    }
+}
+
+impl std::fmt::Display for PauseSignal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PauseSignal::None => write!(f, "none"),
+            PauseSignal::Rts => write!(f, "rts"),
+            PauseSignal::Xoff => write!(f, "xoff"),
+        }
+    }
+}
+
+/// Status line driven by the device
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModemLine {
+    Cts,
+    Dsr,
+    Cd,
+    Ri,
+}
+
+impl ModemLine {
+    pub fn parse(value: &str) -> Result<Self, SerialError> {
+        match value.to_lowercase().as_str() {
+            "cts" => Ok(ModemLine::Cts),
+            "dsr" => Ok(ModemLine::Dsr),
+            "cd" | "dcd" => Ok(ModemLine::Cd),
+            "ri" => Ok(ModemLine::Ri),
+            _ => Err(SerialError::InvalidConfig(format!("Unknown modem line: {}", value))),
+        }
+    }
+}
+
+impl std::fmt::Display for ModemLine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ModemLine::Cts => write!(f, "cts"),
+            ModemLine::Dsr => write!(f, "dsr"),
+            ModemLine::Cd => write!(f, "cd"),
+            ModemLine::Ri => write!(f, "ri"),
+        }
+    }
+}
+
+/// States of the status lines driven by the device
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ModemLines {
+    pub cts: bool,
+    pub dsr: bool,
+    pub cd: bool,
+    pub ri: bool,
+}
+
+impl ModemLines {
+    pub fn is_asserted(&self, line: ModemLine) -> bool {
+        match line {
+            ModemLine::Cts => self.cts,
+            ModemLine::Dsr => self.dsr,
+            ModemLine::Cd => self.cd,
+            ModemLine::Ri => self.ri,
+        }
+    }
+}
+
+/// What the background reader does when the receive buffer fills up
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackpressurePolicy {
+    /// Keep reading and discard the oldest buffered data, counting what was lost
+    #[default]
+    Drop,
+    /// Stop reading from the port until a consumer makes room
+    Block,
+    /// Hold the device off with RTS or XOFF near the limit and release it once drained
+    FlowControl,
+}
+
+impl BackpressurePolicy {
+    pub fn parse(value: &str) -> Result<Self, SerialError> {
+        match value.to_lowercase().as_str() {
+            "drop" => Ok(BackpressurePolicy::Drop),
+            "block" => Ok(BackpressurePolicy::Block),
+            "flow_control" => Ok(BackpressurePolicy::FlowControl),
+            _ => Err(SerialError::InvalidConfig(format!("Unknown backpressure policy: {}", value))),
+        }
+    }
+}
+
+impl std::fmt::Display for BackpressurePolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BackpressurePolicy::Drop => write!(f, "drop"),
+            BackpressurePolicy::Block => write!(f, "block"),
+            BackpressurePolicy::FlowControl => write!(f, "flow_control"),
+        }
+    }
+}
+
+/// How recent a connection's data is
+///
+/// Tells a quiet device apart from old data still waiting in the receive buffer.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct DataFreshness {
+    /// Age of the newest byte still waiting in the receive buffer, `None` when it is empty
+    pub newest_buffered_age_ms: Option<u64>,
+    /// Time since data was last received, `None` if nothing was received yet
+    pub since_last_rx_ms: Option<u64>,
+    /// Time since data was last sent, `None` if nothing was sent yet
+    pub since_last_tx_ms: Option<u64>,
+}
+
+impl std::fmt::Display for DataFreshness {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let ago = |ms: Option<u64>| ms.map_or_else(|| "never".to_string(), |ms| format!("{}ms ago", ms));
+        write!(f, "Last received: {}\nLast sent: {}", ago(self.since_last_rx_ms), ago(self.since_last_tx_ms))?;
+        if let Some(age) = self.newest_buffered_age_ms {
+            write!(f, "\nStill buffered: newest byte {}ms old", age)?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct ConnectionStatus {
     pub id: String,
@@ -114,6 +342,46 @@ pub struct ConnectionStatus {
     pub parity: Parity,
     pub flow_control: FlowControl,
     pub connected: bool,
+    pub paused: bool,
+    pub line_mode: bool,
+    /// Framer reads and writes go through, if any
+    pub framer: Option<String>,
+    /// Filters received data passes through, in order
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub receive_filters: Vec<ReceiveFilter>,
+    pub backpressure: BackpressurePolicy,
+    /// The device is currently held off because the receive buffer is nearly full
+    pub throttled: bool,
+    /// File the connection's traffic is being recorded to
+    pub capture_file: Option<String>,
+    /// Connection that opened the port, when this is a shared handle onto it
+    pub shared_from: Option<String>,
+    /// MCP client the connection was opened for
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    /// Device profile the connection was opened with
+    pub profile: Option<String>,
+    /// Configured protocol the connection was opened with
+    pub protocol: Option<String>,
+    /// Notes attached to the device on this port
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub notes: BTreeMap<String, String>,
+    /// Received bytes discarded because the receive buffer was full
+    pub dropped_bytes: u64,
+    /// Reads that gave up waiting for data
+    pub read_timeouts: u64,
+    /// Extra write calls needed to get data out: continuations of short writes
+    /// and retries of interrupted ones
+    pub write_retries: u64,
+    /// Writes waiting for their turn or being written
+    pub queued_writes: usize,
+    /// Framing errors the driver counted since the port was opened; `None`
+    /// where the driver or platform does not report them
+    pub framing_errors: Option<u64>,
+    /// Gaps between received chunks and their sizes
+    pub rx_timing: RxTimingSummary,
+    /// Age of buffered data and time since the last traffic each way
+    pub freshness: DataFreshness,
     pub created_at: DateTime<Utc>,
     pub bytes_sent: u64,
     pub bytes_received: u64,
@@ -124,13 +392,60 @@ pub struct SerialConnection {
     id: String,
     config: ConnectionConfig,
     stream: Arc<Mutex<SerialStream>>,
+    reader: Arc<ReaderShared>,
+    /// Task draining the port; shared handles are fed by their origin's task
+    reader_task: Option<JoinHandle<()>>,
+    /// Connection that opened the port, kept open while shared handles exist
+    origin: Option<Arc<SerialConnection>>,
+    pause_signal: Mutex<Option<PauseSignal>>,
+    port_lock: Option<PortLock>,
+    /// MCP client the connection was opened for
+    owner: Option<String>,
+    /// Rules every write must pass, those configured for this port
+    write_rules: Vec<WriteRule>,
+    /// Where writes the rules refuse are recorded
+    write_audit: Option<Arc<WriteAudit>>,
+    /// Rate limit of the session the connection runs on, charged by every write
+    write_limiter: std::sync::Mutex<Option<WriteLimiter>>,
     created_at: DateTime<Utc>,
     bytes_sent: Arc<Mutex<u64>>,
     bytes_received: Arc<Mutex<u64>>,
+    /// When data was last written by this handle
+    last_tx: Mutex<Option<Instant>>,
+    read_timeouts: AtomicU64,
+    write_retries: AtomicU64,
+    /// Overflow drops already reported by `take_new_drops`
+    reported_drops: AtomicU64,
+    /// Driver framing error count when the port was opened
+    framing_baseline: Option<u64>,
+    /// Framer turning received bytes into frames, with frames not yet read
+    framing: Mutex<Option<FrameDecoder>>,
+    /// Held for a request/response exchange so others cannot interleave
+    exchange: Mutex<()>,
+    /// Turns of the write tools, in submission order
+    write_queue: WriteQueue,
+    /// Device profile the connection was opened with
+    profile: Mutex<Option<String>>,
+    /// Configured protocol the connection was opened with
+    protocol: Mutex<Option<String>>,
+    /// Encoding of reads and writes that name none, from the protocol
+    default_encoding: Mutex<Option<String>>,
+    /// Commands that put the device in a safe state before the port closes
+    teardown: Mutex<Option<CommandSequence>>,
 }
 
 impl SerialConnection {
+    /// Open a port for this process alone, see [`Self::open`]
     pub async fn new(config: ConnectionConfig) -> Result<Self, SerialError> {
+        Self::open(config, true).await
+    }
+    
+    /// Open a port, refusing it to other processes while open if `exclusive`
+    ///
+    /// On Unix exclusive access is TIOCEXCL, so opening the device again fails
+    /// with EBUSY for anyone but root. Windows only ever lets one process open
+    /// a COM port, whatever `exclusive` says.
+    pub async fn open(config: ConnectionConfig, exclusive: bool) -> Result<Self, SerialError> {
         // Validate baud rate
         if config.baud_rate == 0 || config.baud_rate > 4_000_000 {
             return Err(SerialError::InvalidBaudRate(config.baud_rate));
@@ -144,59 +459,898 @@ impl SerialConnection {
             .flow_control(config.flow_control.into());
         
         // Open the port
-        let stream = builder.open_native_async()
-            .map_err(|e| SerialError::ConnectionFailed(format!("{}: {}", config.port, e)))?;
+        let stream = builder.open_native_async().map_err(|e| {
+            if held_elsewhere(&e, &config.port) {
+                SerialError::PortLocked(format!("{} is open exclusively in another process", config.port))
+            } else {
+                SerialError::ConnectionFailed(format!("{}: {}", config.port, e))
+            }
+        })?;
+        // The driver claims the port on open; give it back up if asked to share
+        #[cfg(unix)]
+        let stream = {
+            let mut stream = stream;
+            stream.set_exclusive(exclusive)
+                .map_err(|e| SerialError::ConnectionFailed(format!("{}: exclusive access not set: {}", config.port, e)))?;
+            stream
+        };
+        #[cfg(not(unix))]
+        let _ = exclusive;
+        let framing_baseline = driver_framing_errors(&stream);
+        // Non-standard rates such as MIDI's 31250 depend on the driver, which may round them
+        let rounded_baud = stream.baud_rate().ok().filter(|actual| *actual != config.baud_rate);
+        let stream = Arc::new(Mutex::new(stream));
+        
+        // Start draining the port in the background
+        // Under the flow-control policy, hold the device off the same way its flow control expects
+        let throttle = match config.flow_control {
+            FlowControl::Software => PauseSignal::Xoff,
+            FlowControl::None | FlowControl::Hardware => PauseSignal::Rts,
+        };
+        let reader = Arc::new(ReaderShared::new(DEFAULT_RX_BUFFER_SIZE, config.backpressure, throttle));
+        let access = if exclusive { "" } else { ", open to other processes" };
+        reader.history.lock().await.record_event(format!("Opened {} at {} baud{}", config.port, config.baud_rate, access));
+        if let Some(actual) = rounded_baud {
+            warn!("{} opened at {} baud, but the driver reports {}", config.port, config.baud_rate, actual);
+            reader.history.lock().await.record_event(format!("Driver reports {} baud, not the {} requested", actual, config.baud_rate));
+        }
+        let reader_task = spawn_reader(Arc::clone(&stream), Arc::clone(&reader));
         
         Ok(Self {
             id: Uuid::new_v4().to_string(),
             config,
-            stream: Arc::new(Mutex::new(stream)),
+            stream,
+            reader,
+            reader_task: Some(reader_task),
+            origin: None,
+            pause_signal: Mutex::new(None),
+            port_lock: None,
+            owner: None,
+            write_rules: Vec::new(),
+            write_audit: None,
+            write_limiter: std::sync::Mutex::new(None),
             created_at: Utc::now(),
             bytes_sent: Arc::new(Mutex::new(0)),
             bytes_received: Arc::new(Mutex::new(0)),
+            last_tx: Mutex::new(None),
+            read_timeouts: AtomicU64::new(0),
+            write_retries: AtomicU64::new(0),
+            reported_drops: AtomicU64::new(0),
+            framing_baseline,
+            framing: Mutex::new(None),
+            exchange: Mutex::new(()),
+            write_queue: WriteQueue::new(),
+            profile: Mutex::new(None),
+            protocol: Mutex::new(None),
+            default_encoding: Mutex::new(None),
+            teardown: Mutex::new(None),
         })
     }
     
+    /// Open another handle onto the same port
+    ///
+    /// The handle gets its own copy of all data received from now on, with its
+    /// own receive buffer, line mode and history; writes from all handles are
+    /// serialized on the port. The port stays open until every handle is gone.
+    pub async fn share(self: &Arc<Self>) -> SerialConnection {
+        let origin = self.origin.clone().unwrap_or_else(|| Arc::clone(self));
+        let reader = Arc::new(ReaderShared::new(DEFAULT_RX_BUFFER_SIZE, BackpressurePolicy::Drop, PauseSignal::None));
+        origin.reader.add_tap(&reader).await;
+        
+        let id = Uuid::new_v4().to_string();
+        reader.history.lock().await.record_event(format!("Shared handle onto {} opened by {}", origin.config.port, origin.id));
+        origin.record_event(format!("Port shared with connection {}", id)).await;
+        let write_rules = origin.write_rules.clone();
+        let write_audit = origin.write_audit.clone();
+        
+        SerialConnection {
+            id,
+            config: origin.config.clone(),
+            stream: Arc::clone(&origin.stream),
+            reader,
+            reader_task: None,
+            origin: Some(origin),
+            pause_signal: Mutex::new(None),
+            port_lock: None,
+            owner: None,
+            write_rules,
+            write_audit,
+            write_limiter: std::sync::Mutex::new(None),
+            created_at: Utc::now(),
+            bytes_sent: Arc::new(Mutex::new(0)),
+            bytes_received: Arc::new(Mutex::new(0)),
+            last_tx: Mutex::new(None),
+            read_timeouts: AtomicU64::new(0),
+            write_retries: AtomicU64::new(0),
+            reported_drops: AtomicU64::new(0),
+            framing_baseline: None,
+            framing: Mutex::new(None),
+            exchange: Mutex::new(()),
+            write_queue: WriteQueue::new(),
+            profile: Mutex::new(None),
+            protocol: Mutex::new(None),
+            default_encoding: Mutex::new(None),
+            teardown: Mutex::new(None),
+        }
+    }
+    
+    /// Connection that opened the port, if this is a shared handle
+    pub fn origin(&self) -> Option<&Arc<SerialConnection>> {
+        self.origin.as_ref()
+    }
+    
+    /// Hold `lock` for as long as the connection is open
+    pub fn with_port_lock(mut self, lock: PortLock) -> Self {
+        self.port_lock = Some(lock);
+        self
+    }
+    
+    /// Take over the ID of a connection this one replaces, such as after a reconnect
+    pub fn with_id(mut self, id: impl Into<String>) -> Self {
+        self.id = id.into();
+        self
+    }
+    
+    /// Record the MCP client the connection is opened for
+    pub fn with_owner(mut self, owner: impl Into<String>) -> Self {
+        self.owner = Some(owner.into());
+        self
+    }
+    
+    /// MCP client the connection was opened for, if one had connected
+    pub fn owner(&self) -> Option<&str> {
+        self.owner.as_deref()
+    }
+    
+    /// Check every write against `rules`, refusing those that break one
+    pub fn with_write_rules(mut self, rules: Vec<WriteRule>) -> Self {
+        self.write_rules = rules;
+        self
+    }
+    
+    /// Record the writes the rules refuse in `audit`
+    pub fn with_write_audit(mut self, audit: Arc<WriteAudit>) -> Self {
+        self.write_audit = Some(audit);
+        self
+    }
+    
+    /// Charge every write to `limiter`, or to none, replacing the limiter set before
+    pub fn set_write_limiter(&self, limiter: Option<WriteLimiter>) {
+        *self.write_limiter.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = limiter;
+    }
+    
+    /// Refuse `data` if it breaks a write rule, recording why in the event log and the audit file
+    async fn check_write_rules(&self, data: &[u8]) -> Result<(), SerialError> {
+        for rule in &self.write_rules {
+            if let Err(reason) = rule.check(data) {
+                warn!("Refused write of {} bytes to {}: {}", data.len(), self.config.port, reason);
+                self.record_event(format!("Write of {} bytes refused: {}", data.len(), reason)).await;
+                if let Some(audit) = &self.write_audit {
+                    let refused = RefusedWrite {
+                        timestamp: Utc::now(),
+                        connection_id: self.id.clone(),
+                        port: self.config.port.clone(),
+                        client: self.owner.clone(),
+                        bytes: data.len(),
+                        reason: reason.clone(),
+                        data: hex::encode_upper(data),
+                    };
+                    if let Err(e) = audit.record(&refused) {
+                        error!("Refused write not recorded in {}: {}", audit.path().display(), e);
+                    }
+                }
+                return Err(SerialError::WriteRefused(format!("{} bytes to {} {}", data.len(), self.config.port, reason)));
+            }
+        }
+        Ok(())
+    }
+    
     pub fn id(&self) -> &str {
         &self.id
     }
     
+    pub fn config(&self) -> &ConnectionConfig {
+        &self.config
+    }
+    
+    /// Write `data` in its turn behind the writes submitted before it
     pub async fn write(&self, data: &[u8]) -> Result<usize, SerialError> {
+        let turn = self.write_turn(None).await?;
+        self.write_in_turn(&turn, data).await
+    }
+    
+    /// Write `data` in a turn already taken with [`Self::write_turn`]
+    pub async fn write_in_turn(&self, _turn: &WriteTurn<'_>, data: &[u8]) -> Result<usize, SerialError> {
+        self.check_write_rules(data).await?;
+        self.write_checked(data).await
+    }
+    
+    /// Write `data` already checked against the write rules, possibly in another form,
+    /// within the session's rate limit
+    async fn write_checked(&self, data: &[u8]) -> Result<usize, SerialError> {
         use tokio::io::AsyncWriteExt;
         
+        if !self.reader.is_running() {
+            let reason = self.reader.last_error().await.unwrap_or_else(|| "Reader stopped".to_string());
+            return Err(SerialError::ConnectionFailed(format!("{}: {}", self.config.port, reason)));
+        }
+        let limiter = self.write_limiter.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone();
+        if let Some(limiter) = limiter {
+            limiter.take(data.len()).map_err(SerialError::RateLimited)?;
+        }
+        
         let mut stream = self.stream.lock().await;
-        let written = stream.write(data).await?;
+        let mut written = 0;
+        let mut interruptions = 0;
+        while written < data.len() {
+            match stream.write(&data[written..]).await {
+                Ok(0) => break,
+                Ok(n) => {
+                    written += n;
+                    if written < data.len() {
+                        self.write_retries.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted && interruptions < MAX_WRITE_INTERRUPTIONS => {
+                    interruptions += 1;
+                    self.write_retries.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
         stream.flush().await?;
         
         let mut sent = self.bytes_sent.lock().await;
         *sent += written as u64;
+        *self.last_tx.lock().await = Some(Instant::now());
+        self.reader.record_traffic(Direction::Tx, &data[..written], Utc::now()).await;
         
         Ok(written)
     }
     
+    /// Read buffered data received by the background reader
+    ///
+    /// Waits up to `timeout_ms` for data to arrive when the buffer is empty.
+    /// Returns `Ok(0)` straight away if reception is paused and nothing is buffered.
     pub async fn read(&self, buffer: &mut [u8], timeout_ms: Option<u64>) -> Result<usize, SerialError> {
-        use tokio::io::AsyncReadExt;
+        let result = self.read_within(buffer, timeout_ms.map(Duration::from_millis)).await;
+        Ok(self.count_timeout(result)?.0)
+    }
+    
+    /// Like [`SerialConnection::read`], also returning when the bytes read arrived
+    pub async fn read_timed(&self, buffer: &mut [u8], timeout_ms: Option<u64>) -> Result<(usize, Option<RxSpan>), SerialError> {
+        let result = self.read_within(buffer, timeout_ms.map(Duration::from_millis)).await;
+        self.count_timeout(result)
+    }
+    
+    async fn read_within(&self, buffer: &mut [u8], wait: Option<Duration>) -> Result<(usize, Option<RxSpan>), SerialError> {
+        let (bytes_read, span) = self
+            .wait_for_data(wait, |rx| rx.has_raw_data().then(|| rx.take_into_timed(buffer)))
+            .await?
+            .unwrap_or((0, None));
         
-        let mut stream = self.stream.lock().await;
+        let mut received = self.bytes_received.lock().await;
+        *received += bytes_read as u64;
+        
+        Ok((bytes_read, span))
+    }
+    
+    /// Read one frame delimited by silence on the line
+    ///
+    /// Waits up to `timeout_ms` for the first byte, then keeps collecting until
+    /// no data has arrived for `idle` or `max_bytes` have been gathered.
+    pub async fn read_until_idle(&self, max_bytes: usize, idle: Duration, timeout_ms: Option<u64>) -> Result<Vec<u8>, SerialError> {
+        let mut frame = vec![0u8; max_bytes];
+        let mut len = self.read(&mut frame, timeout_ms).await?;
         
-        let read_result = if let Some(ms) = timeout_ms {
-            match timeout(Duration::from_millis(ms), stream.read(buffer)).await {
-                Ok(result) => result,
-                Err(_) => return Err(SerialError::ReadTimeout),
+        while len > 0 && len < max_bytes {
+            let quiet_for = self.reader.since_last_rx().await.unwrap_or(idle);
+            if quiet_for >= idle {
+                break;
             }
-        } else {
-            stream.read(buffer).await
-        };
+            
+            match self.read_within(&mut frame[len..], Some(idle - quiet_for)).await {
+                Ok((0, _)) => break,
+                Ok((n, _)) => len += n,
+                // Quiet for the rest of the gap; the next pass confirms it and stops
+                Err(SerialError::ReadTimeout) => {}
+                Err(e) => return Err(e),
+            }
+        }
         
-        let bytes_read = read_result?;
+        frame.truncate(len);
+        Ok(frame)
+    }
+    
+    /// Read a batch of whole received chunks, preserving chunk boundaries
+    ///
+    /// Waits up to `timeout_ms` for the first chunk, then delivers once the batch
+    /// holds `max_chunks` chunks or `max_bytes` bytes, or its oldest chunk has
+    /// been buffered for `max_age`.
+    pub async fn read_batch(
+        &self,
+        max_chunks: usize,
+        max_bytes: usize,
+        max_age: Duration,
+        timeout_ms: Option<u64>,
+    ) -> Result<Vec<RxChunk>, SerialError> {
+        let ready = self
+            .wait_for_data(timeout_ms.map(Duration::from_millis), |rx| rx.has_raw_data().then_some(()))
+            .await;
+        let ready = self.count_timeout(ready)?;
+        
+        if ready.is_some() {
+            loop {
+                let notified = self.reader.data_ready.notified();
+                tokio::pin!(notified);
+                notified.as_mut().enable();
+                
+                let remaining = {
+                    let rx = self.reader.buffer.lock().await;
+                    let age = rx.oldest_chunk_age().unwrap_or(max_age);
+                    if rx.chunk_count() >= max_chunks || rx.len() >= max_bytes || age >= max_age {
+                        break;
+                    }
+                    max_age - age
+                };
+                
+                if !self.reader.is_running() || timeout(remaining, notified).await.is_err() {
+                    break;
+                }
+            }
+        }
         
+        let chunks = self.reader.buffer.lock().await.take_chunks(max_chunks, max_bytes);
+        self.reader.space_available.notify_one();
         let mut received = self.bytes_received.lock().await;
-        *received += bytes_read as u64;
+        *received += chunks.iter().map(|c| c.data.len() as u64).sum::<u64>();
+        
+        Ok(chunks)
+    }
+    
+    /// Read up to `max_lines` complete lines assembled in line mode
+    ///
+    /// Same waiting rules as [`SerialConnection::read`]; returns an empty list when paused.
+    pub async fn read_lines(&self, max_lines: usize, timeout_ms: Option<u64>) -> Result<Vec<ReceivedLine>, SerialError> {
+        let lines = self
+            .wait_for_data(timeout_ms.map(Duration::from_millis), |rx| (rx.line_count() > 0).then(|| rx.take_lines(max_lines)))
+            .await;
+        let lines = self.count_timeout(lines)?.unwrap_or_default();
+        
+        let mut received = self.bytes_received.lock().await;
+        *received += lines.iter().map(|l| l.data.len() as u64).sum::<u64>();
+        
+        Ok(lines)
+    }
+    
+    /// Read until `done` accepts a chunk of what arrived or `deadline` passes
+    ///
+    /// Returns whether `done` accepted one. Gives up early, as at the deadline,
+    /// if reception is paused with nothing buffered.
+    pub async fn read_until(
+        &self,
+        deadline: tokio::time::Instant,
+        mut done: impl FnMut(&[u8]) -> bool,
+    ) -> Result<bool, SerialError> {
+        let mut buffer = [0u8; READ_UNTIL_CHUNK];
+        while let Some(wait) = wait_until(deadline) {
+            match self.read(&mut buffer, Some(wait)).await {
+                // Paused: nothing more will arrive
+                Ok(0) | Err(SerialError::ReadTimeout) => break,
+                Ok(n) if done(&buffer[..n]) => return Ok(true),
+                Ok(_) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(false)
+    }
+    
+    /// Like [`Self::read_until`] for a connection in line mode, one line at a time
+    ///
+    /// Lines after the one `done` accepts stay buffered for later reads.
+    pub async fn read_lines_until(
+        &self,
+        deadline: tokio::time::Instant,
+        mut done: impl FnMut(ReceivedLine) -> bool,
+    ) -> Result<bool, SerialError> {
+        while let Some(wait) = wait_until(deadline) {
+            match self.read_lines(1, Some(wait)).await {
+                Err(SerialError::ReadTimeout) => break,
+                Ok(lines) => {
+                    let Some(line) = lines.into_iter().next() else {
+                        // Paused: nothing more will arrive
+                        break;
+                    };
+                    if done(line) {
+                        return Ok(true);
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(false)
+    }
+    
+    /// Count a read that timed out waiting for data
+    fn count_timeout<T>(&self, result: Result<T, SerialError>) -> Result<T, SerialError> {
+        if matches!(result, Err(SerialError::ReadTimeout)) {
+            self.read_timeouts.fetch_add(1, Ordering::Relaxed);
+        }
+        result
+    }
+    
+    /// Wait until `take` yields something from the receive buffer
+    ///
+    /// Resolves to `None` without waiting when reception is paused.
+    async fn wait_for_data<T>(
+        &self,
+        wait_for: Option<Duration>,
+        mut take: impl FnMut(&mut RxBuffer) -> Option<T>,
+    ) -> Result<Option<T>, SerialError> {
+        let wait = async {
+            loop {
+                // Register for wakeups before checking, so data arriving in between is not missed
+                let notified = self.reader.data_ready.notified();
+                tokio::pin!(notified);
+                notified.as_mut().enable();
+                
+                if let Some(taken) = take(&mut *self.reader.buffer.lock().await) {
+                    self.reader.space_available.notify_one();
+                    return Ok(Some(taken));
+                }
+                
+                if !self.reader.is_running() {
+                    let reason = self.reader.last_error().await.unwrap_or_else(|| "Reader stopped".to_string());
+                    return Err(SerialError::ConnectionFailed(format!("{}: {}", self.config.port, reason)));
+                }
+                
+                if self.reader.is_paused() {
+                    return Ok(None);
+                }
+                
+                notified.await;
+            }
+        };
+        
+        match wait_for {
+            Some(duration) => timeout(duration, wait).await.map_err(|_| SerialError::ReadTimeout)?,
+            None => wait.await,
+        }
+    }
+    
+    /// Switch the receive buffer between raw bytes and assembled lines
+    pub async fn set_line_mode(&self, enabled: bool) {
+        self.reader.buffer.lock().await.set_line_mode(enabled);
+        self.record_event(if enabled { "Line mode enabled" } else { "Line mode disabled" }).await;
+        // Wake pending readers so they re-check against the new mode
+        self.reader.data_ready.notify_waiters();
+        self.reader.space_available.notify_one();
+    }
+    
+    pub async fn is_line_mode(&self) -> bool {
+        self.reader.buffer.lock().await.is_line_mode()
+    }
+    
+    /// Pass data received from now on through `filters` in order, or through none if empty
+    ///
+    /// Data already buffered is left as it is, and data held back by the
+    /// filters being replaced is let through.
+    pub async fn set_receive_filters(&self, filters: &[ReceiveFilter]) -> Result<(), SerialError> {
+        let pipeline = if filters.is_empty() { None } else { Some(ReceivePipeline::new(filters)?) };
+        let replaced = std::mem::replace(&mut *self.reader.filters.lock().await, pipeline);
+        if let Some(mut replaced) = replaced {
+            self.reader.buffer_received(&replaced.flush(), Utc::now()).await;
+        }
+        let event = match filters {
+            [] => "Receive filters removed".to_string(),
+            _ => format!("Receive filters set to {}", filters.iter().map(ReceiveFilter::to_string).collect::<Vec<_>>().join(", ")),
+        };
+        self.record_event(event).await;
+        Ok(())
+    }
+    
+    /// Filters received data passes through, in order
+    pub async fn receive_filters(&self) -> Vec<ReceiveFilter> {
+        self.reader.filters.lock().await.as_ref().map_or_else(Vec::new, |pipeline| pipeline.filters().to_vec())
+    }
+    
+    /// Decode reads and encode writes with `framer`, or go back to raw bytes with `None`
+    ///
+    /// Frames decoded but not yet read, and any partial frame, are discarded.
+    pub async fn set_framer(&self, framer: Option<Box<dyn Framer>>) {
+        let event = match &framer {
+            Some(framer) => format!("Framer set to {}", framer.name()),
+            None => "Framer removed".to_string(),
+        };
+        *self.framing.lock().await = framer.map(FrameDecoder::new);
+        self.record_event(event).await;
+    }
+    
+    /// Name of the framer in use, if any
+    pub async fn framer_name(&self) -> Option<&'static str> {
+        self.framing.lock().await.as_ref().map(|decoder| decoder.framer().name())
+    }
+    
+    /// Read up to `max_frames` whole frames decoded by the connection's framer
+    ///
+    /// Waits up to `timeout_ms` for a frame to complete; bytes of a frame still
+    /// arriving are kept for the next read. Returns an empty list straight away
+    /// if reception is paused and no complete frame is buffered.
+    pub async fn read_frames(&self, max_frames: usize, timeout_ms: Option<u64>) -> Result<Vec<Result<Vec<u8>, FrameError>>, SerialError> {
+        let deadline = timeout_ms.map(|ms| std::time::Instant::now() + Duration::from_millis(ms));
+        let mut buffer = vec![0u8; FRAME_READ_CHUNK];
+        
+        loop {
+            {
+                let mut framing = self.framing.lock().await;
+                let decoder = framing.as_mut().ok_or_else(|| self.no_framer())?;
+                if decoder.has_frames() {
+                    return Ok(decoder.take(max_frames));
+                }
+            }
+            
+            // The framer lock is not held while waiting, so frames can be written meanwhile
+            let wait = deadline.map(|deadline| deadline.saturating_duration_since(std::time::Instant::now()));
+            let (len, _) = self.count_timeout(self.read_within(&mut buffer, wait).await)?;
+            if len == 0 {
+                return Ok(Vec::new());
+            }
+            
+            let mut framing = self.framing.lock().await;
+            let decoder = framing.as_mut().ok_or_else(|| self.no_framer())?;
+            decoder.push(&buffer[..len]);
+        }
+    }
+    
+    /// Encode `payload` as one frame with the connection's framer and send it
+    ///
+    /// Returns the bytes put on the wire, delimiters and escaping included.
+    pub async fn write_frame(&self, payload: &[u8]) -> Result<Vec<u8>, SerialError> {
+        let turn = self.write_turn(None).await?;
+        self.write_frame_in_turn(&turn, payload).await
+    }
+    
+    /// Send `payload` as one frame in a turn already taken with [`Self::write_turn`]
+    pub async fn write_frame_in_turn(&self, _turn: &WriteTurn<'_>, payload: &[u8]) -> Result<Vec<u8>, SerialError> {
+        // Checked before encoding, which could hide a pattern behind escaping
+        self.check_write_rules(payload).await?;
+        let mut encoded = {
+            let framing = self.framing.lock().await;
+            let framer = framing.as_ref().ok_or_else(|| self.no_framer())?.framer();
+            if let Some(max) = framer.max_payload().filter(|&max| payload.len() > max) {
+                return Err(FrameError::TooLong(max).into());
+            }
+            framer.encode(payload)
+        };
+        let written = self.write_checked(&encoded).await?;
+        encoded.truncate(written);
+        Ok(encoded)
+    }
+    
+    fn no_framer(&self) -> SerialError {
+        SerialError::InvalidConfig(format!("No framer set on connection {}", self.id))
+    }
+    
+    /// Remember the device profile the connection was opened with
+    pub async fn set_profile(&self, name: &str) {
+        *self.profile.lock().await = Some(name.to_string());
+    }
+    
+    pub async fn profile(&self) -> Option<String> {
+        self.profile.lock().await.clone()
+    }
+    
+    /// Remember the protocol the connection was opened with and the encoding it defaults to
+    pub async fn set_protocol(&self, name: &str, encoding: Option<String>) {
+        *self.protocol.lock().await = Some(name.to_string());
+        *self.default_encoding.lock().await = encoding;
+    }
+    
+    pub async fn protocol(&self) -> Option<String> {
+        self.protocol.lock().await.clone()
+    }
+    
+    /// Default to `encoding` where the connection's protocol sets none, as for a session's encoding
+    pub async fn set_fallback_encoding(&self, encoding: &str) {
+        self.default_encoding.lock().await.get_or_insert_with(|| encoding.to_string());
+    }
+    
+    /// `requested`, else the encoding of the connection's protocol or session, else UTF-8
+    pub async fn encoding(&self, requested: Option<String>) -> String {
+        match requested {
+            Some(encoding) => encoding,
+            None => self.default_encoding.lock().await.clone().unwrap_or_else(|| "utf8".to_string()),
+        }
+    }
+    
+    /// Commands to send before the connection closes, replacing any set before
+    pub async fn set_teardown(&self, sequence: CommandSequence) {
+        *self.teardown.lock().await = Some(sequence);
+    }
+    
+    /// Send the teardown commands, if any are set and have not run yet
+    ///
+    /// Returns how many commands were sent, or why the sequence stopped early.
+    pub async fn run_teardown(&self) -> Option<Result<usize, String>> {
+        let sequence = self.teardown.lock().await.take()?;
+        Some(sequence.run(self).await)
+    }
+    
+    /// Whether teardown commands are set and have not run on close yet
+    pub async fn has_teardown(&self) -> bool {
+        self.teardown.lock().await.is_some()
+    }
+    
+    /// Send the teardown commands now, leaving them set to run again on close
+    ///
+    /// Puts the device in a safe state while the port stays open.
+    pub async fn run_safe_state(&self) -> Option<Result<usize, String>> {
+        let sequence = self.teardown.lock().await.clone()?;
+        Some(sequence.run(self).await)
+    }
+    
+    /// Collect received output until no data has arrived for `settle_ms`
+    ///
+    /// Gives up after ten seconds for devices that never go quiet.
+    pub async fn drain_output(&self, settle_ms: u64) -> Result<String, SerialError> {
+        let started = std::time::Instant::now();
+        let mut output = Vec::new();
+        let mut buffer = [0u8; 1024];
+        
+        while started.elapsed().as_millis() < u128::from(MAX_COMMAND_OUTPUT_MS) {
+            let result = if self.is_line_mode().await {
+                self.read_lines(usize::MAX, Some(settle_ms)).await.map(|lines| {
+                    for line in &lines {
+                        output.extend_from_slice(&line.data);
+                        output.push(b'\n');
+                    }
+                    lines.len()
+                })
+            } else {
+                self.read(&mut buffer, Some(settle_ms))
+                    .await
+                    .inspect(|&n| output.extend_from_slice(&buffer[..n]))
+            };
+            
+            match result {
+                Ok(0) | Err(SerialError::ReadTimeout) => break,
+                Ok(_) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        
+        Ok(String::from_utf8_lossy(&output).into_owned())
+    }
+    
+    /// Claim the connection for a request/response exchange
+    ///
+    /// Exchanges holding the guard run one at a time; plain reads and writes
+    /// are not held back.
+    pub async fn begin_exchange(&self) -> MutexGuard<'_, ()> {
+        self.exchange.lock().await
+    }
+    
+    /// Claim the connection for an exchange, `None` if another one is running
+    pub fn try_begin_exchange(&self) -> Option<MutexGuard<'_, ()>> {
+        self.exchange.try_lock().ok()
+    }
+    
+    /// Wait for a turn at writing behind the writes submitted before, for at most `timeout`
+    ///
+    /// Writes made while holding the turn go out before any queued after it.
+    /// Every write takes a turn; write with [`Self::write_in_turn`] while holding one.
+    pub async fn write_turn(&self, timeout: Option<Duration>) -> Result<WriteTurn<'_>, SerialError> {
+        self.write_queue.enter(timeout).await
+    }
+    
+    /// Number of received bytes waiting to be read
+    pub async fn buffered_bytes(&self) -> usize {
+        self.reader.buffer.lock().await.len()
+    }
+    
+    /// Age of buffered data and time since the last traffic each way
+    pub async fn freshness(&self) -> DataFreshness {
+        let millis = |age: Duration| age.as_millis() as u64;
+        DataFreshness {
+            newest_buffered_age_ms: self.reader.buffer.lock().await.newest_age().map(millis),
+            since_last_rx_ms: self.reader.since_last_rx().await.map(millis),
+            since_last_tx_ms: self.last_tx.lock().await.map(|at| millis(at.elapsed())),
+        }
+    }
+    
+    /// Bytes discarded because the receive buffer overflowed
+    pub async fn dropped_bytes(&self) -> u64 {
+        self.reader.buffer.lock().await.dropped()
+    }
+    
+    /// Bytes discarded because the receive buffer overflowed since the last call
+    pub async fn take_new_drops(&self) -> u64 {
+        let dropped = self.dropped_bytes().await;
+        dropped.saturating_sub(self.reported_drops.swap(dropped, Ordering::Relaxed))
+    }
+    
+    pub fn is_paused(&self) -> bool {
+        self.reader.is_paused()
+    }
+    
+    /// Add an entry to the connection's event log
+    pub async fn record_event(&self, message: impl Into<String>) {
+        self.reader.history.lock().await.record_event(message);
+    }
+    
+    /// The most recent `limit` chunks of traffic, oldest first
+    pub async fn recent_transcript(&self, limit: usize) -> Vec<TranscriptEntry> {
+        self.reader.history.lock().await.recent_transcript(limit)
+    }
+    
+    /// Retained connection events, oldest first
+    pub async fn events(&self) -> Vec<ConnectionEvent> {
+        self.reader.history.lock().await.events()
+    }
+    
+    /// Take the connection's transcript and events, to carry over to a later connection
+    pub async fn take_history(&self) -> ConnectionHistory {
+        std::mem::take(&mut *self.reader.history.lock().await)
+    }
+    
+    /// Keep the transcript and events of an earlier connection to the port, before this one's
+    pub async fn continue_history(&self, earlier: ConnectionHistory) {
+        self.reader.history.lock().await.continue_from(earlier);
+    }
+    
+    /// Start recording all traffic to `path`, replacing any capture already running
+    pub async fn start_capture(&self, path: &std::path::Path, format: CaptureFormat) -> Result<(), SerialError> {
+        let file = CaptureFile::create(path, format)?;
+        let previous = self.reader.capture.lock().await.replace(file);
+        if let Some(previous) = previous {
+            self.record_event(format!("Capture to {} stopped", previous.path().display())).await;
+        }
+        self.record_event(format!("Capture to {} started ({})", path.display(), format)).await;
+        Ok(())
+    }
+    
+    /// Stop recording traffic, returning the finished capture file if one was running
+    ///
+    /// The file is closed with its integrity footer; failing to write it is reported.
+    pub async fn stop_capture(&self) -> Result<Option<CaptureFile>, SerialError> {
+        let Some(mut capture) = self.reader.capture.lock().await.take() else {
+            return Ok(None);
+        };
+        let finished = capture.finish();
+        self.record_event(format!("Capture to {} stopped", capture.path().display())).await;
+        finished.map(|()| Some(capture))
+    }
+    
+    /// Path of the running capture file
+    pub async fn capture_path(&self) -> Option<std::path::PathBuf> {
+        self.reader.capture.lock().await.as_ref().map(|c| c.path().to_path_buf())
+    }
+    
+    /// Stop the background reader, optionally signalling the device to hold off
+    ///
+    /// Data already buffered stays readable while paused.
+    pub async fn pause(&self, signal: PauseSignal) -> Result<(), SerialError> {
+        if self.origin.is_some() && signal != PauseSignal::None {
+            return Err(SerialError::InvalidConfig(
+                "Shared handles cannot signal the device; pause the connection that opened the port".to_string()
+            ));
+        }
         
-        Ok(bytes_read)
+        let mut pause_signal = self.pause_signal.lock().await;
+        if pause_signal.is_some() {
+            return Ok(());
+        }
+        
+        self.reader.set_paused(true);
+        self.send_flow_signal(signal, true).await?;
+        *pause_signal = Some(signal);
+        self.record_event(format!("Reception paused (signal: {})", signal)).await;
+        Ok(())
+    }
+    
+    /// Restart the background reader, releasing the signal asserted on pause
+    pub async fn resume(&self) -> Result<(), SerialError> {
+        let mut pause_signal = self.pause_signal.lock().await;
+        let Some(signal) = pause_signal.take() else {
+            return Ok(());
+        };
+        
+        self.send_flow_signal(signal, false).await?;
+        self.reader.set_paused(false);
+        self.record_event("Reception resumed").await;
+        Ok(())
+    }
+    
+    /// Drive the DTR and RTS lines, leaving a line as it is when given `None`
+    ///
+    /// Reset circuits such as those of ESP32 boards sit on these lines, so
+    /// shared handles cannot drive them.
+    pub async fn set_control_lines(&self, dtr: Option<bool>, rts: Option<bool>) -> Result<(), SerialError> {
+        if self.origin.is_some() {
+            return Err(SerialError::InvalidConfig(
+                "Shared handles cannot drive DTR or RTS; use the connection that opened the port".to_string()
+            ));
+        }
+        
+        let mut stream = self.stream.lock().await;
+        write_control_lines(&mut stream, dtr, rts)?;
+        Ok(())
+    }
+    
+    /// Read the status lines the device drives
+    ///
+    /// Fails once the device is gone, even while the reader has not noticed yet.
+    pub async fn modem_lines(&self) -> Result<ModemLines, SerialError> {
+        let mut stream = self.stream.lock().await;
+        Ok(ModemLines {
+            cts: stream.read_clear_to_send()?,
+            dsr: stream.read_data_set_ready()?,
+            cd: stream.read_carrier_detect()?,
+            ri: stream.read_ring_indicator()?,
+        })
+    }
+    
+    async fn send_flow_signal(&self, signal: PauseSignal, hold_off: bool) -> Result<(), SerialError> {
+        let mut stream = self.stream.lock().await;
+        send_flow_signal(&mut stream, signal, hold_off).await?;
+        Ok(())
+    }
+    
+    /// Framing errors counted by the driver since the port was opened
+    async fn framing_errors(&self) -> Option<u64> {
+        // Line errors belong to the port, which the origin opened
+        let opener = self.origin.as_deref().unwrap_or(self);
+        let current = driver_framing_errors(&*opener.stream.lock().await)?;
+        Some(current.saturating_sub(opener.framing_baseline?))
+    }
+    
+    /// Why the connection stopped working, `None` while its reader is still running
+    pub async fn failure(&self) -> Option<String> {
+        if self.reader.is_running() {
+            return None;
+        }
+        Some(self.reader.last_error().await.unwrap_or_else(|| "Reader stopped".to_string()))
+    }
+    
+    /// Drop the connection as if its cable were pulled, for testing recovery paths
+    ///
+    /// The reader stops with an error and reads and writes fail from then on,
+    /// as after a real disconnect. The port itself stays open until the
+    /// connection is closed.
+    pub async fn simulate_disconnect(&self) {
+        self.mark_failed("Simulated cable pull".to_string()).await;
+    }
+    
+    /// Stop the reader and fail the connection, as when its device stopped answering
+    ///
+    /// Reads and writes fail from then on and `failure` reports `reason`, so
+    /// sessions set to reconnect are reconnected.
+    pub async fn mark_failed(&self, reason: String) {
+        if let Some(task) = &self.reader_task {
+            task.abort();
+        }
+        self.reader.fail(reason).await;
+    }
+    
+    /// Finish the capture and stop the background reader, as when the server shuts down
+    ///
+    /// Nothing is read from the port afterwards; it closes once the last
+    /// handle to the connection is dropped.
+    pub async fn shut_down(&self) {
+        if let Err(e) = self.stop_capture().await {
+            warn!("Capture of connection {} not finished: {}", self.id, e);
+        }
+        if let Some(task) = &self.reader_task {
+            task.abort();
+        }
     }
     
     pub async fn status(&self) -> ConnectionStatus {
+        let (line_mode, dropped_bytes) = {
+            let buffer = self.reader.buffer.lock().await;
+            (buffer.is_line_mode(), buffer.dropped())
+        };
+        
         ConnectionStatus {
             id: self.id.clone(),
             port: self.config.port.clone(),
@@ -205,7 +1359,27 @@ impl SerialConnection {
             stop_bits: self.config.stop_bits,
             parity: self.config.parity,
             flow_control: self.config.flow_control,
-            connected: true,
+            connected: self.reader.is_running(),
+            paused: self.reader.is_paused(),
+            line_mode,
+            framer: self.framer_name().await.map(str::to_string),
+            receive_filters: self.receive_filters().await,
+            backpressure: self.config.backpressure,
+            throttled: self.reader.is_throttled(),
+            capture_file: self.capture_path().await.map(|p| p.display().to_string()),
+            shared_from: self.origin.as_ref().map(|origin| origin.id.clone()),
+            owner: self.owner.clone(),
+            profile: self.profile().await,
+            protocol: self.protocol().await,
+            // Kept by the server rather than the connection
+            notes: BTreeMap::new(),
+            dropped_bytes,
+            read_timeouts: self.read_timeouts.load(Ordering::Relaxed),
+            write_retries: self.write_retries.load(Ordering::Relaxed),
+            queued_writes: self.write_queue.len(),
+            framing_errors: self.framing_errors().await,
+            rx_timing: self.reader.rx_timing().await,
+            freshness: self.freshness().await,
             created_at: self.created_at,
             bytes_sent: *self.bytes_sent.lock().await,
             bytes_received: *self.bytes_received.lock().await,
@@ -230,4 +1404,137 @@ impl SerialConnection {
         
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+/// Assert or release a hold-off signal towards the device
+pub(super) async fn send_flow_signal(stream: &mut SerialStream, signal: PauseSignal, hold_off: bool) -> std::io::Result<()> {
+    use tokio::io::AsyncWriteExt;
+    
+    match signal {
+        PauseSignal::None => {}
+        PauseSignal::Rts => stream.write_request_to_send(!hold_off)?,
+        PauseSignal::Xoff => {
+            stream.write_all(&[if hold_off { XOFF } else { XON }]).await?;
+            stream.flush().await?;
+        }
+    }
+    Ok(())
+}
+
+/// Set DTR and RTS together, so the device never sees a state between the two
+#[cfg(target_os = "linux")]
+fn write_control_lines(stream: &mut SerialStream, dtr: Option<bool>, rts: Option<bool>) -> std::io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+    
+    let fd = stream.as_raw_fd();
+    let mut bits: libc::c_int = 0;
+    // SAFETY: TIOCMGET and TIOCMSET read and write a single int of modem line bits
+    if unsafe { libc::ioctl(fd, libc::TIOCMGET, &mut bits as *mut libc::c_int) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    for (level, bit) in [(dtr, libc::TIOCM_DTR), (rts, libc::TIOCM_RTS)] {
+        match level {
+            Some(true) => bits |= bit,
+            Some(false) => bits &= !bit,
+            None => {}
+        }
+    }
+    // SAFETY: as above
+    if unsafe { libc::ioctl(fd, libc::TIOCMSET, &bits as *const libc::c_int) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Set DTR, then RTS
+#[cfg(not(target_os = "linux"))]
+fn write_control_lines(stream: &mut SerialStream, dtr: Option<bool>, rts: Option<bool>) -> std::io::Result<()> {
+    if let Some(dtr) = dtr {
+        stream.write_data_terminal_ready(dtr)?;
+    }
+    if let Some(rts) = rts {
+        stream.write_request_to_send(rts)?;
+        // Windows only passes a DTR change on when RTS is written, so esptool writes DTR again
+        if let Some(dtr) = dtr {
+            stream.write_data_terminal_ready(dtr)?;
+        }
+    }
+    Ok(())
+}
+
+/// Whether an open failed because another process holds the port
+fn held_elsewhere(e: &tokio_serial::Error, port: &str) -> bool {
+    // TIOCEXCL fails the open with EBUSY, which serialport only keeps the description of
+    #[cfg(unix)]
+    {
+        let _ = port;
+        e.kind == tokio_serial::ErrorKind::Unknown && e.description == EBUSY_DESCRIPTION
+    }
+    // The driver refuses a COM port to every process but the one holding it, which
+    // serialport reports as a missing device; a port still listed is held, not gone
+    #[cfg(windows)]
+    {
+        e.kind == tokio_serial::ErrorKind::NoDevice
+            && tokio_serial::available_ports().is_ok_and(|ports| ports.iter().any(|listed| listed.port_name.eq_ignore_ascii_case(port)))
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        let _ = (e, port);
+        false
+    }
+}
+
+/// Milliseconds left until `deadline` to wait for a read, `None` once it has passed
+fn wait_until(deadline: tokio::time::Instant) -> Option<u64> {
+    let remaining = deadline.saturating_duration_since(tokio::time::Instant::now());
+    (!remaining.is_zero()).then(|| remaining.as_micros().div_ceil(1000) as u64)
+}
+
+/// Description serialport gives an EBUSY error, nix's for that errno
+#[cfg(unix)]
+const EBUSY_DESCRIPTION: &str = "Device or resource busy";
+
+/// Framing errors the driver has counted on the port since the driver was loaded
+///
+/// `None` if the driver does not keep line error counters, e.g. for pseudo-terminals.
+#[cfg(target_os = "linux")]
+fn driver_framing_errors(stream: &SerialStream) -> Option<u64> {
+    use std::os::unix::io::AsRawFd;
+    
+    /// Mirror of the kernel's `struct serial_icounter_struct`
+    #[repr(C)]
+    #[derive(Default)]
+    struct SerialIcounter {
+        cts: i32,
+        dsr: i32,
+        rng: i32,
+        dcd: i32,
+        rx: i32,
+        tx: i32,
+        frame: i32,
+        overrun: i32,
+        parity: i32,
+        brk: i32,
+        buf_overrun: i32,
+        reserved: [i32; 9],
+    }
+    
+    let mut counters = SerialIcounter::default();
+    // SAFETY: TIOCGICOUNT writes a serial_icounter_struct, which SerialIcounter mirrors
+    let result = unsafe { libc::ioctl(stream.as_raw_fd(), libc::TIOCGICOUNT, &mut counters as *mut SerialIcounter) };
+    (result == 0).then_some(counters.frame as u32 as u64)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn driver_framing_errors(_stream: &SerialStream) -> Option<u64> {
+    None
+}
+
+impl Drop for SerialConnection {
+    fn drop(&mut self) {
+        // The reader task holds a handle to the port; stop it so the port is released
+        if let Some(task) = &self.reader_task {
+            task.abort();
+        }
+    }
+}
@@ -14,6 +14,9 @@ pub enum SerialError {
     #[error("Connection already exists: {0}")]
     ConnectionExists(String),
     
+    #[error("Port locked: {0}")]
+    PortLocked(String),
+    
     #[error("Invalid baud rate: {0}")]
     InvalidBaudRate(u32),
     
@@ -26,6 +29,15 @@ pub enum SerialError {
     #[error("Write timeout")]
     WriteTimeout,
     
+    #[error("Write refused: {0}")]
+    WriteRefused(String),
+    
+    #[error("Rate limited, retry after {}ms - session {} allows {}", .0.retry_after.as_micros().div_ceil(1000), .0.session_id, .0.limit)]
+    RateLimited(super::rate_limit::RateLimited),
+    
+    #[error(transparent)]
+    Frame(#[from] crate::protocol::FrameError),
+    
     #[error("Encoding error: {0}")]
     EncodingError(String),
     
@@ -0,0 +1,255 @@
+//! Receive filters
+//!
+//! A connection can pass received data through an ordered pipeline of
+//! filters before it reaches the receive buffer and the transcript: stripping
+//! ANSI escape sequences, normalizing line endings to LF, and dropping lines
+//! that match a pattern, such as heartbeats. Each filter keeps its state
+//! across chunks, so a sequence or line split between reads is handled whole.
+
+use regex::bytes::Regex;
+use serde::{Deserialize, Serialize};
+
+use super::error::SerialError;
+
+/// A filter as configured, written as `strip_ansi`, `normalize_crlf` or `drop_lines:<regex>`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum ReceiveFilter {
+    /// Remove ANSI escape sequences, such as colours and cursor movement
+    StripAnsi,
+    /// Turn CRLF and lone CR into LF
+    NormalizeCrlf,
+    /// Drop whole lines matching a regular expression, terminator included
+    DropLines(String),
+}
+
+impl ReceiveFilter {
+    pub fn parse(value: &str) -> Result<Self, SerialError> {
+        if let Some(pattern) = value.strip_prefix("drop_lines:") {
+            Regex::new(pattern).map_err(|e| SerialError::InvalidConfig(format!("Invalid drop_lines pattern {:?}: {}", pattern, e)))?;
+            return Ok(ReceiveFilter::DropLines(pattern.to_string()));
+        }
+        match value.to_lowercase().as_str() {
+            "strip_ansi" => Ok(ReceiveFilter::StripAnsi),
+            "normalize_crlf" => Ok(ReceiveFilter::NormalizeCrlf),
+            _ => Err(SerialError::InvalidConfig(format!(
+                "Unknown receive filter: {} (expected strip_ansi, normalize_crlf or drop_lines:<regex>)",
+                value
+            ))),
+        }
+    }
+}
+
+impl std::fmt::Display for ReceiveFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReceiveFilter::StripAnsi => write!(f, "strip_ansi"),
+            ReceiveFilter::NormalizeCrlf => write!(f, "normalize_crlf"),
+            ReceiveFilter::DropLines(pattern) => write!(f, "drop_lines:{}", pattern),
+        }
+    }
+}
+
+impl TryFrom<String> for ReceiveFilter {
+    type Error = SerialError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::parse(&value)
+    }
+}
+
+impl From<ReceiveFilter> for String {
+    fn from(filter: ReceiveFilter) -> Self {
+        filter.to_string()
+    }
+}
+
+/// Where the ANSI stripper is within an escape sequence
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AnsiState {
+    Text,
+    /// After ESC
+    Escape,
+    /// Within a control sequence, ESC [ ... final byte
+    Csi,
+    /// Within an operating system command, ESC ] ... BEL or ESC \
+    Osc,
+    /// After ESC within an operating system command
+    OscEscape,
+}
+
+/// A filter with what it carries over between chunks
+#[derive(Debug)]
+enum Stage {
+    StripAnsi(AnsiState),
+    /// Whether the last byte was a CR, so an LF after it is already written
+    NormalizeCrlf(bool),
+    /// Pattern and the line received so far
+    DropLines(Regex, Vec<u8>),
+}
+
+impl Stage {
+    fn apply(&mut self, input: &[u8], out: &mut Vec<u8>) {
+        match self {
+            Stage::StripAnsi(state) => {
+                for &byte in input {
+                    *state = match (*state, byte) {
+                        (AnsiState::Text, 0x1b) => AnsiState::Escape,
+                        (AnsiState::Text, _) => {
+                            out.push(byte);
+                            AnsiState::Text
+                        }
+                        (AnsiState::Escape, b'[') => AnsiState::Csi,
+                        (AnsiState::Escape, b']') => AnsiState::Osc,
+                        // Two-byte sequences such as ESC 7 or ESC c
+                        (AnsiState::Escape, _) => AnsiState::Text,
+                        (AnsiState::Csi, 0x40..=0x7e) => AnsiState::Text,
+                        (AnsiState::Csi, _) => AnsiState::Csi,
+                        (AnsiState::Osc | AnsiState::OscEscape, 0x07) => AnsiState::Text,
+                        (AnsiState::OscEscape, b'\\') => AnsiState::Text,
+                        (AnsiState::Osc | AnsiState::OscEscape, 0x1b) => AnsiState::OscEscape,
+                        (AnsiState::Osc | AnsiState::OscEscape, _) => AnsiState::Osc,
+                    };
+                }
+            }
+            Stage::NormalizeCrlf(after_cr) => {
+                for &byte in input {
+                    match byte {
+                        b'\r' => out.push(b'\n'),
+                        b'\n' if *after_cr => {}
+                        _ => out.push(byte),
+                    }
+                    *after_cr = byte == b'\r';
+                }
+            }
+            Stage::DropLines(pattern, line) => {
+                for &byte in input {
+                    line.push(byte);
+                    if byte == b'\n' {
+                        keep_line(pattern, line, out);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Let go of a line held back waiting for its end
+    fn flush(&mut self, out: &mut Vec<u8>) {
+        if let Stage::DropLines(pattern, line) = self {
+            if !line.is_empty() {
+                keep_line(pattern, line, out);
+            }
+        }
+    }
+
+    fn holds_data(&self) -> bool {
+        matches!(self, Stage::DropLines(_, line) if !line.is_empty())
+    }
+}
+
+/// Move `line` to `out` unless its text, without the terminator, matches `pattern`
+fn keep_line(pattern: &Regex, line: &mut Vec<u8>, out: &mut Vec<u8>) {
+    let text = line.strip_suffix(b"\n").unwrap_or(line);
+    let text = text.strip_suffix(b"\r").unwrap_or(text);
+    if !pattern.is_match(text) {
+        out.extend_from_slice(line);
+    }
+    line.clear();
+}
+
+/// Filters applied in order to everything a connection receives
+///
+/// A line-dropping filter holds back a line until its LF arrives, or until
+/// [`ReceivePipeline::flush`] once the port goes quiet, so a prompt without a
+/// line ending still gets through.
+#[derive(Debug)]
+pub struct ReceivePipeline {
+    filters: Vec<ReceiveFilter>,
+    stages: Vec<Stage>,
+}
+
+impl ReceivePipeline {
+    pub fn new(filters: &[ReceiveFilter]) -> Result<Self, SerialError> {
+        let stages = filters
+            .iter()
+            .map(|filter| {
+                Ok(match filter {
+                    ReceiveFilter::StripAnsi => Stage::StripAnsi(AnsiState::Text),
+                    ReceiveFilter::NormalizeCrlf => Stage::NormalizeCrlf(false),
+                    ReceiveFilter::DropLines(pattern) => {
+                        let regex = Regex::new(pattern)
+                            .map_err(|e| SerialError::InvalidConfig(format!("Invalid drop_lines pattern {:?}: {}", pattern, e)))?;
+                        Stage::DropLines(regex, Vec::new())
+                    }
+                })
+            })
+            .collect::<Result<Vec<_>, SerialError>>()?;
+        Ok(Self { filters: filters.to_vec(), stages })
+    }
+
+    pub fn filters(&self) -> &[ReceiveFilter] {
+        &self.filters
+    }
+
+    /// Pass received bytes through every filter, returning what is left of them
+    pub fn apply(&mut self, data: &[u8]) -> Vec<u8> {
+        let mut data = data.to_vec();
+        for stage in &mut self.stages {
+            let mut out = Vec::with_capacity(data.len());
+            stage.apply(&data, &mut out);
+            data = out;
+        }
+        data
+    }
+
+    /// Release data held back by any filter, passing it through the filters after it
+    pub fn flush(&mut self) -> Vec<u8> {
+        let mut data = Vec::new();
+        for stage in &mut self.stages {
+            let mut out = Vec::with_capacity(data.len());
+            stage.apply(&data, &mut out);
+            stage.flush(&mut out);
+            data = out;
+        }
+        data
+    }
+
+    /// Whether any filter holds back data waiting for more
+    pub fn holds_data(&self) -> bool {
+        self.stages.iter().any(Stage::holds_data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_receive_pipeline() {
+        let filters: Vec<ReceiveFilter> = ["strip_ansi", "normalize_crlf", "drop_lines:^HB \\d+$"]
+            .iter()
+            .map(|filter| ReceiveFilter::parse(filter).unwrap())
+            .collect();
+        let mut pipeline = ReceivePipeline::new(&filters).unwrap();
+
+        // Escape sequences and lines split across chunks
+        let mut received = pipeline.apply(b"\x1b[32mOK\x1b[0m\r");
+        received.extend(pipeline.apply(b"\nHB 1"));
+        assert!(pipeline.holds_data());
+        received.extend(pipeline.apply(b"2\r\n\x1b]0;title\x07temp=21\x1b["));
+        received.extend(pipeline.apply(b"1;31m\r\n> "));
+        assert_eq!(received, b"OK\ntemp=21\n");
+        assert_eq!(pipeline.flush(), b"> ");
+        assert!(!pipeline.holds_data());
+
+        // A heartbeat still unterminated when the port goes quiet is dropped as it stands
+        assert!(pipeline.apply(b"HB 3").is_empty());
+        assert!(pipeline.flush().is_empty());
+
+        assert_eq!(filters[2].to_string(), "drop_lines:^HB \\d+$");
+        assert!(ReceiveFilter::parse("drop_lines:(").is_err());
+        assert!(ReceiveFilter::parse("uppercase").is_err());
+        let saved = serde_json::to_string(&filters).unwrap();
+        assert_eq!(serde_json::from_str::<Vec<ReceiveFilter>>(&saved).unwrap(), filters);
+    }
+}
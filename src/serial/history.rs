@@ -0,0 +1,161 @@
+//! Per-connection history
+//!
+//! Keeps a bounded transcript of recent traffic and a log of notable
+//! connection events, so the state of a link can be reconstructed later.
+
+use std::collections::VecDeque;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Number of traffic chunks kept in the transcript
+pub const TRANSCRIPT_CAPACITY: usize = 256;
+
+/// Number of events kept in the event log
+pub const EVENT_CAPACITY: usize = 128;
+
+/// Direction of a transcript entry relative to the server
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// Sent to the device
+    Tx,
+    /// Received from the device
+    Rx,
+}
+
+/// A chunk of traffic as it crossed the port
+#[derive(Debug, Clone)]
+pub struct TranscriptEntry {
+    pub timestamp: DateTime<Utc>,
+    pub direction: Direction,
+    pub data: Vec<u8>,
+}
+
+/// Something that happened to a connection
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectionEvent {
+    pub timestamp: DateTime<Utc>,
+    pub message: String,
+}
+
+/// Bounded transcript and event log of a connection
+#[derive(Debug, Default)]
+pub struct ConnectionHistory {
+    transcript: VecDeque<TranscriptEntry>,
+    events: VecDeque<ConnectionEvent>,
+}
+
+impl ConnectionHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// History made of a transcript and events kept elsewhere, oldest first, keeping the newest that fit
+    pub fn from_parts(transcript: Vec<TranscriptEntry>, events: Vec<ConnectionEvent>) -> Self {
+        let mut transcript = VecDeque::from(transcript);
+        transcript.drain(..transcript.len().saturating_sub(TRANSCRIPT_CAPACITY));
+        let mut events = VecDeque::from(events);
+        events.drain(..events.len().saturating_sub(EVENT_CAPACITY));
+        Self { transcript, events }
+    }
+
+    /// Record traffic in the transcript, evicting the oldest entry when full
+    pub fn record_traffic(&mut self, direction: Direction, data: &[u8], timestamp: DateTime<Utc>) {
+        if self.transcript.len() == TRANSCRIPT_CAPACITY {
+            self.transcript.pop_front();
+        }
+        self.transcript.push_back(TranscriptEntry {
+            timestamp,
+            direction,
+            data: data.to_vec(),
+        });
+    }
+
+    /// Record a connection event, evicting the oldest event when full
+    pub fn record_event(&mut self, message: impl Into<String>) {
+        if self.events.len() == EVENT_CAPACITY {
+            self.events.pop_front();
+        }
+        self.events.push_back(ConnectionEvent {
+            timestamp: Utc::now(),
+            message: message.into(),
+        });
+    }
+
+    /// The most recent `limit` transcript entries, oldest first
+    pub fn recent_transcript(&self, limit: usize) -> Vec<TranscriptEntry> {
+        let skip = self.transcript.len().saturating_sub(limit);
+        self.transcript.iter().skip(skip).cloned().collect()
+    }
+
+    /// All retained events, oldest first
+    pub fn events(&self) -> Vec<ConnectionEvent> {
+        self.events.iter().cloned().collect()
+    }
+
+    /// Put the history of an earlier connection to the same port before this one's
+    pub fn continue_from(&mut self, earlier: ConnectionHistory) {
+        let mut transcript = earlier.transcript;
+        transcript.extend(self.transcript.drain(..));
+        transcript.drain(..transcript.len().saturating_sub(TRANSCRIPT_CAPACITY));
+        self.transcript = transcript;
+
+        let mut events = earlier.events;
+        events.extend(self.events.drain(..));
+        events.drain(..events.len().saturating_sub(EVENT_CAPACITY));
+        self.events = events;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transcript_is_bounded() {
+        let mut history = ConnectionHistory::new();
+        for i in 0..TRANSCRIPT_CAPACITY + 10 {
+            history.record_traffic(Direction::Rx, &[i as u8], Utc::now());
+        }
+
+        let recent = history.recent_transcript(usize::MAX);
+        assert_eq!(recent.len(), TRANSCRIPT_CAPACITY);
+        assert_eq!(recent[0].data, vec![10]);
+
+        let last_two = history.recent_transcript(2);
+        assert_eq!(last_two.len(), 2);
+        assert_eq!(last_two[1].data, vec![(TRANSCRIPT_CAPACITY + 9) as u8]);
+    }
+
+    #[test]
+    fn test_event_log() {
+        let mut history = ConnectionHistory::new();
+        history.record_event("Connection opened");
+        history.record_event("Reception paused");
+
+        let events = history.events();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[1].message, "Reception paused");
+    }
+
+    #[test]
+    fn test_continue_from() {
+        let mut earlier = ConnectionHistory::new();
+        earlier.record_event("Opened");
+        for i in 0..TRANSCRIPT_CAPACITY {
+            earlier.record_traffic(Direction::Rx, &[i as u8], Utc::now());
+        }
+        let mut history = ConnectionHistory::new();
+        history.record_event("Reopened");
+        history.record_traffic(Direction::Tx, b"AT", Utc::now());
+
+        history.continue_from(earlier);
+        let messages: Vec<String> = history.events().into_iter().map(|event| event.message).collect();
+        assert_eq!(messages, ["Opened", "Reopened"]);
+        let transcript = history.recent_transcript(usize::MAX);
+        assert_eq!(transcript.len(), TRANSCRIPT_CAPACITY);
+        assert_eq!(transcript[0].data, vec![1]);
+        assert_eq!(transcript.last().unwrap().data, b"AT");
+    }
+}
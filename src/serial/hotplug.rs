@@ -0,0 +1,152 @@
+//! Native hotplug notifications
+//!
+//! Where the platform can announce device arrival and removal, port discovery
+//! rescans as soon as something changes instead of waiting for its next poll.
+//! On Windows a hidden message-only window receives `WM_DEVICECHANGE` for all
+//! device interface classes; other platforms rely on polling alone.
+
+use std::sync::Arc;
+
+use tokio::sync::Notify;
+
+/// Start watching for device changes
+///
+/// The returned `Notify` is signalled whenever a device arrives or is removed.
+/// `None` where the platform has no native notifications or they could not
+/// be set up.
+pub fn watch() -> Option<Arc<Notify>> {
+    platform::watch()
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::sync::{Arc, OnceLock};
+
+    use tokio::sync::Notify;
+    use tracing::{info, warn};
+    use windows_sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+    use windows_sys::Win32::System::LibraryLoader::GetModuleHandleW;
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        CreateWindowExW, DefWindowProcW, DispatchMessageW, GetMessageW, RegisterClassW, RegisterDeviceNotificationW,
+        DBT_DEVICEARRIVAL, DBT_DEVICEREMOVECOMPLETE, DBT_DEVTYP_DEVICEINTERFACE, DEVICE_NOTIFY_ALL_INTERFACE_CLASSES,
+        DEVICE_NOTIFY_WINDOW_HANDLE, DEV_BROADCAST_DEVICEINTERFACE_W, HWND_MESSAGE, MSG, WM_DEVICECHANGE, WNDCLASSW,
+    };
+
+    /// Signalled by the window procedure; one watcher serves the whole process
+    static EVENTS: OnceLock<Option<Arc<Notify>>> = OnceLock::new();
+
+    pub(super) fn watch() -> Option<Arc<Notify>> {
+        EVENTS
+            .get_or_init(|| match start() {
+                Ok(events) => {
+                    info!("Watching for device changes with WM_DEVICECHANGE");
+                    Some(events)
+                }
+                Err(e) => {
+                    warn!("Device change notifications unavailable, polling only: {}", e);
+                    None
+                }
+            })
+            .clone()
+    }
+
+    /// Create the notification window on its own thread and pump its messages
+    fn start() -> std::io::Result<Arc<Notify>> {
+        let events = Arc::new(Notify::new());
+        let (ready_tx, ready_rx) = std::sync::mpsc::channel();
+
+        std::thread::Builder::new()
+            .name("device-changes".to_string())
+            .spawn(move || {
+                // SAFETY: the window is created and used only on this thread
+                match unsafe { create_window() } {
+                    Ok(window) => {
+                        let _ = ready_tx.send(Ok(()));
+                        unsafe { pump_messages(window) };
+                    }
+                    Err(e) => {
+                        let _ = ready_tx.send(Err(e));
+                    }
+                }
+            })?;
+
+        ready_rx
+            .recv()
+            .map_err(|_| std::io::Error::other("device change thread exited"))??;
+        Ok(events)
+    }
+
+    unsafe fn create_window() -> std::io::Result<HWND> {
+        let class_name: Vec<u16> = "SerialMcpDeviceChanges\0".encode_utf16().collect();
+        let instance = GetModuleHandleW(std::ptr::null());
+
+        let mut class: WNDCLASSW = std::mem::zeroed();
+        class.lpfnWndProc = Some(window_proc);
+        class.hInstance = instance;
+        class.lpszClassName = class_name.as_ptr();
+        if RegisterClassW(&class) == 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        // Message-only windows get no broadcasts, only registered notifications
+        let window = CreateWindowExW(
+            0,
+            class_name.as_ptr(),
+            std::ptr::null(),
+            0,
+            0,
+            0,
+            0,
+            0,
+            HWND_MESSAGE,
+            std::ptr::null_mut(),
+            instance,
+            std::ptr::null(),
+        );
+        if window.is_null() {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        let mut filter: DEV_BROADCAST_DEVICEINTERFACE_W = std::mem::zeroed();
+        filter.dbcc_size = std::mem::size_of::<DEV_BROADCAST_DEVICEINTERFACE_W>() as u32;
+        filter.dbcc_devicetype = DBT_DEVTYP_DEVICEINTERFACE;
+        let registration = RegisterDeviceNotificationW(
+            window,
+            &filter as *const _ as *const std::ffi::c_void,
+            DEVICE_NOTIFY_WINDOW_HANDLE | DEVICE_NOTIFY_ALL_INTERFACE_CLASSES,
+        );
+        if registration.is_null() {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        Ok(window)
+    }
+
+    unsafe fn pump_messages(window: HWND) {
+        let mut message: MSG = std::mem::zeroed();
+        while GetMessageW(&mut message, window, 0, 0) > 0 {
+            DispatchMessageW(&message);
+        }
+    }
+
+    unsafe extern "system" fn window_proc(window: HWND, message: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+        if message == WM_DEVICECHANGE && matches!(wparam as u32, DBT_DEVICEARRIVAL | DBT_DEVICEREMOVECOMPLETE) {
+            if let Some(Some(events)) = EVENTS.get() {
+                events.notify_one();
+            }
+            return 1;
+        }
+        DefWindowProcW(window, message, wparam, lparam)
+    }
+}
+
+#[cfg(not(windows))]
+mod platform {
+    use std::sync::Arc;
+
+    use tokio::sync::Notify;
+
+    pub(super) fn watch() -> Option<Arc<Notify>> {
+        None
+    }
+}
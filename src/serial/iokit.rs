@@ -0,0 +1,118 @@
+//! IOKit port metadata (macOS)
+//!
+//! The enumeration in `serialport` reports USB IDs and strings; IOKit also
+//! knows where the adapter is plugged in, which interface of a multi-port
+//! adapter a node belongs to, and which driver serves it.
+
+use std::collections::HashMap;
+use std::ffi::{c_char, CStr};
+
+use core_foundation::base::{CFType, TCFType};
+use core_foundation::number::CFNumber;
+use core_foundation::string::CFString;
+use core_foundation_sys::base::kCFAllocatorDefault;
+use io_kit_sys::keys::kIOServicePlane;
+use io_kit_sys::serial::keys::{kIOCalloutDeviceKey, kIODialinDeviceKey, kIOSerialBSDServiceValue};
+use io_kit_sys::types::{io_iterator_t, io_object_t, io_registry_entry_t};
+use io_kit_sys::{
+    kIOMasterPortDefault, kIORegistryIterateParents, kIORegistryIterateRecursively, IOIteratorNext, IOObjectGetClass,
+    IOObjectRelease, IORegistryEntryCreateCFProperty, IORegistryEntryGetParentEntry, IORegistryEntrySearchCFProperty,
+    IOServiceGetMatchingServices, IOServiceMatching,
+};
+use mach2::kern_return::KERN_SUCCESS;
+
+/// What IOKit knows about one serial device node
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IoKitMetadata {
+    /// USB location ID, stable for a given physical socket
+    pub location_id: Option<u32>,
+    /// Name of the USB interface the port belongs to
+    pub interface: Option<String>,
+    /// Class of the driver serving the port, e.g. `AppleUSBFTDI`
+    pub driver: Option<String>,
+}
+
+/// Metadata of every serial device node, keyed by both its `cu.` and `tty.` paths
+pub fn metadata_by_path() -> HashMap<String, IoKitMetadata> {
+    let mut metadata = HashMap::new();
+
+    // SAFETY: every object obtained from IOKit is released exactly once below;
+    // the matching dictionary is consumed by IOServiceGetMatchingServices
+    unsafe {
+        let matching = IOServiceMatching(kIOSerialBSDServiceValue);
+        if matching.is_null() {
+            return metadata;
+        }
+        let mut services: io_iterator_t = 0;
+        if IOServiceGetMatchingServices(kIOMasterPortDefault, matching, &mut services) != KERN_SUCCESS {
+            return metadata;
+        }
+
+        loop {
+            let service = IOIteratorNext(services);
+            if service == 0 {
+                break;
+            }
+
+            let info = IoKitMetadata {
+                location_id: search_parents(service, "locationID")
+                    .and_then(|value| value.downcast::<CFNumber>())
+                    .and_then(|number| number.to_i64())
+                    .map(|id| id as u32),
+                interface: search_parents(service, "USB Interface Name")
+                    .and_then(|value| value.downcast::<CFString>())
+                    .map(|name| name.to_string()),
+                driver: provider_class(service),
+            };
+            for key in [kIOCalloutDeviceKey, kIODialinDeviceKey] {
+                if let Some(path) = string_property(service, key) {
+                    metadata.insert(path, info.clone());
+                }
+            }
+
+            IOObjectRelease(service);
+        }
+        IOObjectRelease(services);
+    }
+
+    metadata
+}
+
+unsafe fn string_property(entry: io_registry_entry_t, key: *const c_char) -> Option<String> {
+    let key = CFString::new(CStr::from_ptr(key).to_str().ok()?);
+    let value = IORegistryEntryCreateCFProperty(entry, key.as_concrete_TypeRef(), kCFAllocatorDefault, 0);
+    if value.is_null() {
+        return None;
+    }
+    CFType::wrap_under_create_rule(value).downcast::<CFString>().map(|s| s.to_string())
+}
+
+/// Look `key` up on `entry` and then on its ancestors
+unsafe fn search_parents(entry: io_registry_entry_t, key: &str) -> Option<CFType> {
+    let key = CFString::new(key);
+    let value = IORegistryEntrySearchCFProperty(
+        entry,
+        kIOServicePlane,
+        key.as_concrete_TypeRef(),
+        kCFAllocatorDefault,
+        kIORegistryIterateRecursively | kIORegistryIterateParents,
+    );
+    (!value.is_null()).then(|| CFType::wrap_under_create_rule(value))
+}
+
+/// Class name of the driver that published the serial node
+unsafe fn provider_class(service: io_object_t) -> Option<String> {
+    let mut provider: io_registry_entry_t = 0;
+    if IORegistryEntryGetParentEntry(service, kIOServicePlane, &mut provider) != KERN_SUCCESS {
+        return None;
+    }
+
+    // io_name_t is a 128 byte buffer
+    let mut class_name = [0 as c_char; 128];
+    let result = IOObjectGetClass(provider, class_name.as_mut_ptr());
+    IOObjectRelease(provider);
+    if result != KERN_SUCCESS {
+        return None;
+    }
+    CStr::from_ptr(class_name.as_ptr()).to_str().ok().map(str::to_string)
+}
@@ -0,0 +1,193 @@
+//! Line assembly for received data
+//!
+//! Splits the byte stream into complete lines terminated by CR, LF or CRLF,
+//! numbering each line so clients can detect gaps between reads.
+
+use std::collections::VecDeque;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// A complete line received from the device, without its terminator
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ReceivedLine {
+    /// Sequence number, starting at 1 for the first line of the connection
+    pub seq: u64,
+    /// Arrival time of the chunk that completed the line
+    pub timestamp: DateTime<Utc>,
+    pub data: Vec<u8>,
+}
+
+impl ReceivedLine {
+    /// Line contents with invalid UTF-8 replaced
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.data).into_owned()
+    }
+}
+
+/// Assembles complete lines from arbitrary byte chunks
+#[derive(Debug)]
+pub struct LineAssembler {
+    partial: Vec<u8>,
+    lines: VecDeque<ReceivedLine>,
+    queued_bytes: usize,
+    capacity: usize,
+    next_seq: u64,
+    /// Last chunk ended in CR, so a leading LF in the next one completes a CRLF
+    pending_cr: bool,
+    dropped: u64,
+}
+
+impl LineAssembler {
+    /// Create an assembler holding at most `capacity` bytes of queued lines
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            partial: Vec::new(),
+            lines: VecDeque::new(),
+            queued_bytes: 0,
+            capacity: capacity.max(1),
+            next_seq: 1,
+            pending_cr: false,
+            dropped: 0,
+        }
+    }
+
+    /// Feed bytes received just now into the assembler
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.feed_at(bytes, Utc::now());
+    }
+
+    /// Feed bytes that arrived at `timestamp` into the assembler
+    pub fn feed_at(&mut self, bytes: &[u8], timestamp: DateTime<Utc>) {
+        for &byte in bytes {
+            match byte {
+                b'\n' if self.pending_cr => self.pending_cr = false,
+                b'\r' | b'\n' => {
+                    self.pending_cr = byte == b'\r';
+                    self.complete_line(timestamp);
+                }
+                _ => {
+                    self.pending_cr = false;
+                    self.partial.push(byte);
+                    // A line longer than the whole buffer can never be queued; emit it as is
+                    if self.partial.len() >= self.capacity {
+                        self.complete_line(timestamp);
+                    }
+                }
+            }
+        }
+    }
+
+    fn complete_line(&mut self, timestamp: DateTime<Utc>) {
+        let data = std::mem::take(&mut self.partial);
+        self.queued_bytes += data.len();
+        self.lines.push_back(ReceivedLine { seq: self.next_seq, timestamp, data });
+        self.next_seq += 1;
+
+        while self.queued_bytes > self.capacity {
+            match self.lines.pop_front() {
+                Some(line) => {
+                    self.queued_bytes -= line.data.len();
+                    self.dropped += line.data.len() as u64;
+                }
+                None => break,
+            }
+        }
+    }
+
+    /// Remove up to `max_lines` complete lines
+    pub fn take_lines(&mut self, max_lines: usize) -> Vec<ReceivedLine> {
+        let count = self.lines.len().min(max_lines);
+        let lines: Vec<ReceivedLine> = self.lines.drain(..count).collect();
+        self.queued_bytes -= lines.iter().map(|l| l.data.len()).sum::<usize>();
+        lines
+    }
+
+    /// Number of complete lines waiting to be read
+    pub fn line_count(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// Bytes held in complete lines plus the unterminated tail
+    pub fn len(&self) -> usize {
+        self.queued_bytes + self.partial.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty() && self.partial.is_empty()
+    }
+
+    /// Bytes of whole lines discarded because the queue was full
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Flatten queued lines and the unterminated tail back into raw bytes
+    pub fn into_bytes(self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.len() + self.lines.len());
+        for line in self.lines {
+            bytes.extend_from_slice(&line.data);
+            bytes.push(b'\n');
+        }
+        bytes.extend_from_slice(&self.partial);
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_terminators() {
+        let mut assembler = LineAssembler::new(1024);
+        assembler.feed(b"one\ntwo\r\nthree\rfour");
+
+        let lines = assembler.take_lines(10);
+        let texts: Vec<String> = lines.iter().map(|l| l.text()).collect();
+        assert_eq!(texts, vec!["one", "two", "three"]);
+        assert_eq!(lines.iter().map(|l| l.seq).collect::<Vec<_>>(), vec![1, 2, 3]);
+
+        // Unterminated tail is held back until its terminator arrives
+        assert_eq!(assembler.line_count(), 0);
+        assert!(!assembler.is_empty());
+        assembler.feed(b"\n");
+        assert_eq!(assembler.take_lines(10)[0].text(), "four");
+    }
+
+    #[test]
+    fn test_crlf_split_across_chunks() {
+        let mut assembler = LineAssembler::new(1024);
+        assembler.feed(b"first\r");
+        assembler.feed(b"\nsecond\n");
+
+        let lines = assembler.take_lines(10);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1].text(), "second");
+        assert_eq!(lines[1].seq, 2);
+    }
+
+    #[test]
+    fn test_line_timestamp_is_completion_time() {
+        let mut assembler = LineAssembler::new(1024);
+        let first = Utc::now();
+        let second = first + chrono::TimeDelta::milliseconds(250);
+        assembler.feed_at(b"partial ", first);
+        assembler.feed_at(b"line\n", second);
+
+        let lines = assembler.take_lines(10);
+        assert_eq!(lines[0].text(), "partial line");
+        assert_eq!(lines[0].timestamp, second);
+    }
+
+    #[test]
+    fn test_line_queue_overflow() {
+        let mut assembler = LineAssembler::new(8);
+        assembler.feed(b"aaaa\nbbbb\ncccc\n");
+
+        let lines = assembler.take_lines(10);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].seq, 2);
+        assert_eq!(assembler.dropped(), 4);
+    }
+}
@@ -0,0 +1,158 @@
+//! Port ownership across server instances
+//!
+//! Several server instances on one host (one per MCP host application) would
+//! otherwise fight over the same physical ports. Each open port is guarded by
+//! an exclusive file lock in a shared directory; the lock file records which
+//! process holds it so a refused open can say who owns the port. The operating
+//! system releases the lock when the owning process exits, so crashed
+//! instances never leave ports locked.
+
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::error::SerialError;
+
+/// Owner details written into a held lock file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LockOwner {
+    pub pid: u32,
+    pub instance: String,
+    pub port: String,
+    pub acquired: DateTime<Utc>,
+}
+
+/// Directory of port lock files shared by all instances on the host
+#[derive(Debug, Clone)]
+pub struct PortLockRegistry {
+    directory: PathBuf,
+    instance: String,
+}
+
+impl PortLockRegistry {
+    pub fn new(directory: impl Into<PathBuf>, instance: impl Into<String>) -> Self {
+        Self {
+            directory: directory.into(),
+            instance: instance.into(),
+        }
+    }
+
+    /// Name this instance records in the locks it holds
+    pub fn instance(&self) -> &str {
+        &self.instance
+    }
+
+    /// Take exclusive ownership of `port` for this instance
+    pub fn acquire(&self, port: &str) -> Result<PortLock, SerialError> {
+        std::fs::create_dir_all(&self.directory)?;
+        let path = self.lock_path(port);
+        let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&path)?;
+
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                return Err(match read_owner(&mut file) {
+                    Some(owner) if owner.pid == std::process::id() => SerialError::ConnectionExists(port.to_string()),
+                    Some(owner) => SerialError::PortLocked(format!(
+                        "{} is owned by PID {} (instance {}) since {}",
+                        port,
+                        owner.pid,
+                        owner.instance,
+                        owner.acquired.to_rfc3339()
+                    )),
+                    None => SerialError::PortLocked(format!("{} is owned by another instance ({})", port, path.display())),
+                });
+            }
+            Err(TryLockError::Error(e)) => return Err(e.into()),
+        }
+
+        let owner = LockOwner {
+            pid: std::process::id(),
+            instance: self.instance.clone(),
+            port: port.to_string(),
+            acquired: Utc::now(),
+        };
+        let record = serde_json::to_vec(&owner).map_err(|e| SerialError::EncodingError(e.to_string()))?;
+        file.set_len(0)?;
+        file.write_all(&record)?;
+        file.sync_data()?;
+
+        Ok(PortLock { _file: file, path })
+    }
+
+    /// Lock file guarding `port`; separators and other unsafe characters are replaced
+    fn lock_path(&self, port: &str) -> PathBuf {
+        let name: String = port
+            .trim_start_matches(['/', '\\', '.'])
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+            .collect();
+        self.directory.join(format!("{}.lock", name))
+    }
+}
+
+fn read_owner(file: &mut File) -> Option<LockOwner> {
+    let mut content = String::new();
+    file.rewind().ok()?;
+    file.read_to_string(&mut content).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+/// Held ownership of a port, released when dropped
+#[derive(Debug)]
+pub struct PortLock {
+    _file: File,
+    path: PathBuf,
+}
+
+impl PortLock {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_port_lock_ownership() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = PortLockRegistry::new(dir.path(), "desktop");
+        let second = PortLockRegistry::new(dir.path(), "ide");
+
+        let lock = first.acquire("/dev/ttyUSB0").unwrap();
+        assert!(lock.path().ends_with("dev_ttyUSB0.lock"));
+
+        // Same process: reported as the connection already being open
+        assert!(matches!(second.acquire("/dev/ttyUSB0"), Err(SerialError::ConnectionExists(_))));
+        assert!(second.acquire("/dev/ttyUSB1").is_ok());
+
+        drop(lock);
+        assert!(second.acquire("/dev/ttyUSB0").is_ok());
+    }
+
+    #[test]
+    fn test_lock_owner_message() {
+        let dir = tempfile::tempdir().unwrap();
+        let registry = PortLockRegistry::new(dir.path(), "ide");
+        let _lock = registry.acquire("COM3").unwrap();
+
+        // Pretend another process holds the lock
+        let path = dir.path().join("COM3.lock");
+        let owner = LockOwner {
+            pid: 4242,
+            instance: "desktop".to_string(),
+            port: "COM3".to_string(),
+            acquired: Utc::now(),
+        };
+        std::fs::write(&path, serde_json::to_vec(&owner).unwrap()).unwrap();
+
+        let err = registry.acquire("COM3").unwrap_err();
+        assert!(matches!(err, SerialError::PortLocked(_)));
+        assert!(err.to_string().contains("owned by PID 4242 (instance desktop)"));
+    }
+}
@@ -1,15 +1,45 @@
+pub mod bridge;
+pub mod capture;
 pub mod connection;
 pub mod error;
+pub mod filter;
+pub mod history;
+pub mod hotplug;
+#[cfg(target_os = "macos")]
+pub mod iokit;
+pub mod lines;
+pub mod lock;
 pub mod port;
+pub mod rate_limit;
+pub mod reader;
+pub mod sequence;
+pub mod stats;
+pub mod virtual_pair;
+pub mod write_queue;
+pub mod write_rules;
 
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod tests;
 
 pub use connection::{
-    ConnectionConfig, ConnectionStatus, DataBits, FlowControl, Parity, SerialConnection, StopBits,
+    BackpressurePolicy, ConnectionConfig, ConnectionStatus, DataBits, DataFreshness, FlowControl, ModemLine, ModemLines, Parity,
+    PauseSignal, SerialConnection, StopBits,
 };
+pub use bridge::{Bridge, BridgeDirection, BridgeRecord, BridgeStats};
+pub use capture::{read_capture, CaptureContents, CaptureFile, CaptureFormat, CaptureIntegrity, CaptureRecord};
 pub use error::SerialError as LocalSerialError;
-pub use port::PortInfo;
+pub use filter::{ReceiveFilter, ReceivePipeline};
+pub use history::{ConnectionEvent, ConnectionHistory, Direction, TranscriptEntry};
+pub use lines::ReceivedLine;
+pub use lock::{LockOwner, PortLock, PortLockRegistry};
+pub use reader::{RxChunk, RxSpan};
+pub use sequence::CommandSequence;
+pub use port::{DeviceIdentity, PortInfo};
+pub use rate_limit::{RateLimit, RateLimited, WriteLimiter};
+pub use stats::{DistributionSummary, RxTimingSummary};
+pub use write_queue::{WriteQueue, WriteTurn};
+pub use write_rules::{RefusedWrite, WriteAudit, WriteRule, WRITE_AUDIT_FILE};
 
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -19,16 +49,79 @@ use crate::error::SerialError;
 #[derive(Debug)]
 pub struct ConnectionManager {
     connections: Arc<RwLock<HashMap<String, Arc<SerialConnection>>>>,
+    locks: Option<PortLockRegistry>,
+    port_sharing: bool,
+    exclusive: bool,
+    /// MCP client connections are opened for, once one has connected
+    client: RwLock<Option<String>>,
+    /// Write rules, handed to the connections on the ports they cover
+    write_rules: Vec<WriteRule>,
+    /// Where the connections record the writes their rules refuse
+    write_audit: Option<Arc<WriteAudit>>,
 }
 
 impl ConnectionManager {
     pub fn new() -> Self {
         Self {
             connections: Arc::new(RwLock::new(HashMap::new())),
+            locks: None,
+            port_sharing: false,
+            exclusive: true,
+            client: RwLock::new(None),
+            write_rules: Vec::new(),
+            write_audit: None,
         }
     }
     
-    /// Connect to a serial port with individual parameters (for compatibility with session manager)
+    /// Create a manager that takes a host-wide lock on every port it opens
+    pub fn with_port_locks(locks: PortLockRegistry) -> Self {
+        Self {
+            connections: Arc::new(RwLock::new(HashMap::new())),
+            locks: Some(locks),
+            port_sharing: false,
+            exclusive: true,
+            client: RwLock::new(None),
+            write_rules: Vec::new(),
+            write_audit: None,
+        }
+    }
+    
+    /// Let opening an already open port add a shared handle onto it instead of failing
+    pub fn with_port_sharing(mut self, enabled: bool) -> Self {
+        self.port_sharing = enabled;
+        self
+    }
+    
+    /// Refuse the ports this manager opens to other processes, as it does by default, or share them
+    pub fn with_exclusive_access(mut self, enabled: bool) -> Self {
+        self.exclusive = enabled;
+        self
+    }
+    
+    /// Check writes to the ports each rule covers against it
+    pub fn with_write_rules(mut self, rules: Vec<WriteRule>) -> Self {
+        self.write_rules = rules;
+        self
+    }
+    
+    /// Record the writes the rules refuse in `audit`
+    pub fn with_write_audit(mut self, audit: WriteAudit) -> Self {
+        self.write_audit = Some(Arc::new(audit));
+        self
+    }
+    
+    /// Record `client` as the owner of the connections opened from now on
+    pub async fn set_client(&self, client: impl Into<String>) {
+        *self.client.write().await = Some(client.into());
+    }
+    
+    /// MCP client connections are opened for, if one has connected
+    pub async fn client(&self) -> Option<String> {
+        self.client.read().await.clone()
+    }
+    
+    /// Open a port with individual parameters, as sessions store them, returning the connection ID
+    #[allow(clippy::too_many_arguments)]
     pub async fn connect(
         &self,
         port_name: &str,
@@ -38,7 +131,7 @@ impl ConnectionManager {
         parity: &str,
         flow_control: &str,
         _timeout_ms: u64,
-    ) -> Result<SerialConnection, SerialError> {
+    ) -> Result<String, SerialError> {
         use connection::{DataBits, StopBits, Parity, FlowControl};
         
         let data_bits = match data_bits {
@@ -76,13 +169,51 @@ impl ConnectionManager {
             stop_bits,
             parity,
             flow_control,
+            backpressure: connection::BackpressurePolicy::default(),
         };
         
-        SerialConnection::new(config).await.map_err(|e| SerialError::ConnectionFailed(e.to_string()))
+        self.open(config).await.map_err(|e| match e {
+            LocalSerialError::ConnectionExists(port) => SerialError::ConnectionExists(port),
+            e => SerialError::ConnectionFailed(e.to_string()),
+        })
     }
     
     pub async fn open(&self, config: ConnectionConfig) -> Result<String, LocalSerialError> {
-        let connection = Arc::new(SerialConnection::new(config.clone()).await?);
+        if self.port_sharing {
+            if let Some(id) = self.open_shared(&config).await? {
+                return Ok(id);
+            }
+        }
+        
+        let connection = self.open_port(config, None).await?;
+        Ok(connection.id().to_string())
+    }
+    
+    /// Open a port again under the ID of a connection that failed, once it was discarded
+    pub async fn reopen(&self, id: &str, config: ConnectionConfig) -> Result<Arc<SerialConnection>, LocalSerialError> {
+        self.open_port(config, Some(id)).await
+    }
+    
+    /// Open a port and add it under `id`, or a new ID
+    async fn open_port(&self, config: ConnectionConfig, id: Option<&str>) -> Result<Arc<SerialConnection>, LocalSerialError> {
+        // Claim the port before touching it, so another instance's traffic is never disturbed
+        let lock = self.locks.as_ref().map(|locks| locks.acquire(&config.port)).transpose()?;
+        
+        let mut connection = SerialConnection::open(config.clone(), self.exclusive).await?;
+        if let Some(lock) = lock {
+            connection = connection.with_port_lock(lock);
+        }
+        if let Some(id) = id {
+            connection = connection.with_id(id);
+        }
+        if let Some(client) = self.client().await {
+            connection = connection.with_owner(client);
+        }
+        if let Some(audit) = &self.write_audit {
+            connection = connection.with_write_audit(Arc::clone(audit));
+        }
+        let rules = self.write_rules.iter().filter(|rule| rule.applies_to(&config.port)).cloned().collect();
+        let connection = Arc::new(connection.with_write_rules(rules));
         let id = connection.id().to_string();
         
         let mut connections = self.connections.write().await;
@@ -93,19 +224,92 @@ impl ConnectionManager {
                 return Err(LocalSerialError::ConnectionExists(config.port));
             }
         }
+        if connections.contains_key(&id) {
+            return Err(LocalSerialError::ConnectionExists(id));
+        }
         
-        connections.insert(id.clone(), connection);
-        Ok(id)
+        connections.insert(id, Arc::clone(&connection));
+        Ok(connection)
     }
     
-    pub async fn close(&self, id: &str) -> Result<(), LocalSerialError> {
+    /// Add a shared handle if the port is already open
+    async fn open_shared(&self, config: &ConnectionConfig) -> Result<Option<String>, LocalSerialError> {
         let mut connections = self.connections.write().await;
-        connections
+        let Some(existing) = connections.values().find(|conn| conn.config().port == config.port).cloned() else {
+            return Ok(None);
+        };
+        
+        if !existing.config().same_line_settings(config) {
+            return Err(LocalSerialError::InvalidConfig(format!(
+                "{} is already open at {} baud {}; shared handles must use the same settings",
+                config.port,
+                existing.config().baud_rate,
+                existing.config().frame_format()
+            )));
+        }
+        
+        let mut handle = existing.share().await;
+        if let Some(client) = self.client().await {
+            handle = handle.with_owner(client);
+        }
+        let handle = Arc::new(handle);
+        let id = handle.id().to_string();
+        connections.insert(id.clone(), handle);
+        Ok(Some(id))
+    }
+    
+    /// Close a connection, first sending its teardown commands if it has any
+    pub async fn close(&self, id: &str) -> Result<(), LocalSerialError> {
+        let connection = self
+            .connections
+            .write()
+            .await
             .remove(id)
             .ok_or_else(|| LocalSerialError::InvalidConnection(id.to_string()))?;
+        // Outcomes are logged by the sequence; the port closes either way
+        connection.run_teardown().await;
         Ok(())
     }
     
+    /// Forget a failed connection without running its teardown
+    ///
+    /// Its port is released once nothing else holds the connection, after which
+    /// it can be opened again with [`Self::reopen`].
+    pub async fn discard(&self, id: &str) -> Result<Arc<SerialConnection>, LocalSerialError> {
+        self.connections
+            .write()
+            .await
+            .remove(id)
+            .ok_or_else(|| LocalSerialError::InvalidConnection(id.to_string()))
+    }
+    
+    /// Close every connection, as on server shutdown, returning how many were open
+    ///
+    /// Each gets its teardown commands, then its capture is finished and its
+    /// reader stopped, even while sessions or bridges still hold it.
+    pub async fn close_all(&self) -> usize {
+        let connections: Vec<Arc<SerialConnection>> = self.connections.write().await.drain().map(|(_, c)| c).collect();
+        for connection in &connections {
+            connection.run_teardown().await;
+            connection.shut_down().await;
+        }
+        connections.len()
+    }
+    
+    /// Do the 1200 baud touch on a port this instance does not have open
+    pub async fn touch(&self, port: &str) -> Result<(), LocalSerialError> {
+        if self.connections.read().await.values().any(|conn| conn.config().port == port) {
+            return Err(LocalSerialError::ConnectionExists(port.to_string()));
+        }
+        // Held while touching, so another instance's connection is never reset
+        let _lock = self.locks.as_ref().map(|locks| locks.acquire(port)).transpose()?;
+        let name = port.to_string();
+        tokio::task::spawn_blocking(move || port::touch_1200bps(&name))
+            .await
+            .map_err(|e| LocalSerialError::ConnectionFailed(e.to_string()))?
+            .map_err(|e| LocalSerialError::ConnectionFailed(format!("{}: {}", port, e)))
+    }
+    
     pub async fn get(&self, id: &str) -> Result<Arc<SerialConnection>, LocalSerialError> {
         let connections = self.connections.read().await;
         connections
@@ -114,6 +318,12 @@ impl ConnectionManager {
             .ok_or_else(|| LocalSerialError::InvalidConnection(id.to_string()))
     }
     
+    /// All open connections
+    pub async fn connections(&self) -> Vec<Arc<SerialConnection>> {
+        let connections = self.connections.read().await;
+        connections.values().cloned().collect()
+    }
+    
     pub async fn list(&self) -> Vec<ConnectionStatus> {
         let connections = self.connections.read().await;
         let mut statuses = Vec::new();
@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
-use serialport::{available_ports, SerialPortType};
+use serialport::{available_ports, SerialPortInfo, SerialPortType};
+
+use crate::utils::PortType;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PortInfo {
@@ -8,11 +10,30 @@ pub struct PortInfo {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub hardware_id: Option<String>,
     pub available: bool,
+    pub port_type: PortType,
+    /// Other end of a virtual null-modem pair
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub virtual_peer: Option<String>,
+    /// USB location ID, stable for a given physical socket
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usb_location: Option<String>,
+    /// USB interface the port belongs to, on multi-port adapters
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usb_interface: Option<String>,
+    /// Driver serving the port
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub driver: Option<String>,
+    /// Advice on whether this is the right device node to open
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
 }
 
 impl PortInfo {
     pub fn list_ports() -> Result<Vec<PortInfo>, serialport::Error> {
         let ports = available_ports()?;
+        #[cfg(target_os = "macos")]
+        let iokit = super::iokit::metadata_by_path();
+        let virtual_ports = super::virtual_pair::detect();
         
         Ok(ports
             .into_iter()
@@ -29,17 +50,176 @@ impl PortInfo {
                     SerialPortType::Unknown => None,
                 };
                 
-                PortInfo {
+                let description = get_port_description(&port);
+                let mut info = PortInfo {
                     name: port.port_name.clone(),
-                    description: get_port_description(&port),
+                    port_type: PortType::from_port_info(&port.port_name, Some(&description)),
+                    description,
                     hardware_id,
                     available: true,
+                    virtual_peer: None,
+                    usb_location: None,
+                    usb_interface: None,
+                    driver: None,
+                    note: None,
+                };
+                
+                if let Some(virtual_port) = virtual_ports.get(&info.name) {
+                    info.port_type = PortType::Virtual;
+                    info.virtual_peer = virtual_port.peer.clone();
+                    info.driver = Some(virtual_port.driver.to_string());
+                }
+                
+                #[cfg(target_os = "macos")]
+                {
+                    if let Some(metadata) = iokit.get(&info.name) {
+                        info.usb_location = metadata.location_id.map(|id| format!("0x{:08X}", id));
+                        info.usb_interface = metadata.interface.clone();
+                        info.driver = metadata.driver.clone();
+                    }
+                    info.note = macos_device_note(&info.name);
                 }
+                
+                info
             })
             .collect())
     }
 }
 
+/// What identifies a USB serial device wherever it enumerates
+///
+/// Unplugging an adapter and plugging it back in can bring it up under a
+/// different path (`/dev/ttyUSB0` becoming `/dev/ttyUSB1`), but its vendor and
+/// product IDs and, for most adapters, its serial number stay the same.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceIdentity {
+    pub vid: u16,
+    pub pid: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub serial_number: Option<String>,
+}
+
+impl DeviceIdentity {
+    /// Identity of the USB device at `port`, if that is what it is
+    pub fn of_port(port: &str) -> Option<Self> {
+        let ports = available_ports().ok()?;
+        Self::find(&ports, port)
+    }
+
+    /// Identity of the USB device at `port` among `ports`
+    pub fn find(ports: &[SerialPortInfo], port: &str) -> Option<Self> {
+        // Ports opened through a symlink such as /dev/serial/by-id/... are listed by target
+        let target = std::fs::canonicalize(port).ok();
+        ports
+            .iter()
+            .find(|info| info.port_name == port || target.as_deref() == Some(std::path::Path::new(&info.port_name)))
+            .and_then(|info| match &info.port_type {
+                SerialPortType::UsbPort(usb) => Some(Self {
+                    vid: usb.vid,
+                    pid: usb.pid,
+                    serial_number: usb.serial_number.clone().filter(|serial| !serial.is_empty()),
+                }),
+                _ => None,
+            })
+    }
+
+    /// Path the device is at now, if it is plugged in and no other port looks the same
+    pub fn locate(&self) -> Option<String> {
+        let ports = available_ports().ok()?;
+        self.locate_in(&ports)
+    }
+
+    /// Path of the one port among `ports` with this identity
+    ///
+    /// Without a serial number two adapters of the same model cannot be told
+    /// apart, so neither is picked. On macOS each device also has a dial-in
+    /// node, which is left out in favour of its callout node.
+    pub fn locate_in(&self, ports: &[SerialPortInfo]) -> Option<String> {
+        let mut matches = ports.iter().filter(|info| match &info.port_type {
+            SerialPortType::UsbPort(usb) => {
+                usb.vid == self.vid
+                    && usb.pid == self.pid
+                    && usb.serial_number.as_deref().filter(|serial| !serial.is_empty()) == self.serial_number.as_deref()
+            }
+            _ => false,
+        })
+        .filter(|info| !info.port_name.starts_with("/dev/tty."))
+        .map(|info| info.port_name.clone());
+
+        let found = matches.next()?;
+        matches.next().is_none().then_some(found)
+    }
+}
+
+impl std::fmt::Display for DeviceIdentity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "USB {:04X}:{:04X}", self.vid, self.pid)?;
+        if let Some(serial) = &self.serial_number {
+            write!(f, " serial {}", serial)?;
+        }
+        Ok(())
+    }
+}
+
+/// Baud rate whose open and close asks Arduino's native USB cores to reboot into their bootloader
+pub const TOUCH_BAUD_RATE: u32 = 1200;
+
+/// Open `port` at 1200 baud and close it with DTR dropped
+///
+/// The SAMD, nRF52 and RP2040 Arduino cores watch for this on their USB
+/// serial port and reboot into the bootloader, which often comes up as a
+/// different port.
+pub fn touch_1200bps(port: &str) -> Result<(), serialport::Error> {
+    let mut handle = serialport::new(port, TOUCH_BAUD_RATE).open()?;
+    // Some drivers cannot; closing the port drops DTR anyway
+    let _ = handle.write_data_terminal_ready(false);
+    Ok(())
+}
+
+/// Which of the two device nodes macOS creates per port to open
+///
+/// `tty.*` is the dial-in node: opening it blocks until the modem carrier
+/// detect (DCD) line is asserted, which most devices never do. `cu.*` is the
+/// callout node, which opens immediately.
+pub fn macos_device_note(name: &str) -> Option<String> {
+    if let Some(suffix) = name.strip_prefix("/dev/tty.") {
+        Some(format!(
+            "Dial-in device: opening it waits for carrier detect (DCD) and usually hangs; open /dev/cu.{} instead",
+            suffix
+        ))
+    } else if name.starts_with("/dev/cu.") {
+        Some("Callout device: opens without waiting for carrier detect; use this one".to_string())
+    } else {
+        None
+    }
+}
+
+/// Swap a macOS dial-in node for its callout node
+///
+/// Returns the path to open and a note for the user when `port` is a
+/// `/dev/tty.*` node: the path is rewritten to `/dev/cu.*` if `prefer_callout`
+/// is set, otherwise kept with a warning.
+pub fn prefer_callout_device(port: &str, prefer_callout: bool) -> (String, Option<String>) {
+    let Some(suffix) = port.strip_prefix("/dev/tty.") else {
+        return (port.to_string(), None);
+    };
+    let callout = format!("/dev/cu.{}", suffix);
+
+    if prefer_callout {
+        let note = format!(
+            "Opened {} instead of {}: the tty. dial-in node waits for carrier detect (DCD) and usually hangs. Set serial.prefer_callout_device = false to open it as given",
+            callout, port
+        );
+        (callout, Some(note))
+    } else {
+        let note = format!(
+            "{} is a dial-in node: opening it waits for carrier detect (DCD) and may hang; {} opens immediately",
+            port, callout
+        );
+        (port.to_string(), Some(note))
+    }
+}
+
 fn get_port_description(port: &serialport::SerialPortInfo) -> String {
     match &port.port_type {
         SerialPortType::UsbPort(info) => {
@@ -0,0 +1,161 @@
+//! Per-session write rate limits
+//!
+//! A session can cap the writes and bytes it sends per second, so a slow
+//! device is not flooded. The writes of the last second are kept; a write
+//! that would go over a cap is refused with the time until it would not.
+//! A write larger than the byte cap goes through once the second before it
+//! was quiet, so it is held back rather than refused for good.
+//!
+//! The session hands its limit to each connection it runs on, which charges
+//! every write it sends, whichever tool or protocol made it.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Result, SerialError};
+
+/// Span the limits are counted over
+const WINDOW: Duration = Duration::from_secs(1);
+
+/// Caps on what a session may write
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RateLimit {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub writes_per_second: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bytes_per_second: Option<u64>,
+}
+
+impl RateLimit {
+    pub fn validate(&self) -> Result<()> {
+        if self.writes_per_second.is_none() && self.bytes_per_second.is_none() {
+            return Err(SerialError::InvalidConfig("A rate limit needs writes or bytes per second".to_string()));
+        }
+        if self.writes_per_second == Some(0) || self.bytes_per_second == Some(0) {
+            return Err(SerialError::InvalidConfig("Rate limits must be above 0".to_string()));
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for RateLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut caps = Vec::new();
+        if let Some(writes) = self.writes_per_second {
+            caps.push(format!("{} writes/s", writes));
+        }
+        if let Some(bytes) = self.bytes_per_second {
+            caps.push(format!("{} bytes/s", bytes));
+        }
+        write!(f, "{}", caps.join(", "))
+    }
+}
+
+/// A write refused for going over a session's rate limit
+#[derive(Debug, Clone)]
+pub struct RateLimited {
+    pub session_id: String,
+    pub limit: RateLimit,
+    /// Time until the write would be allowed
+    pub retry_after: Duration,
+}
+
+/// A session's limit with the writes counted against it, shared by the connections it runs on
+#[derive(Debug, Clone)]
+pub struct WriteLimiter {
+    session_id: String,
+    limit: RateLimit,
+    budget: Arc<Mutex<WriteBudget>>,
+}
+
+impl WriteLimiter {
+    pub fn new(session_id: impl Into<String>, limit: RateLimit) -> Self {
+        Self { session_id: session_id.into(), limit, budget: Arc::default() }
+    }
+
+    /// Count a write of `bytes`, or refuse it with how long until it would be allowed
+    pub fn take(&self, bytes: usize) -> std::result::Result<(), RateLimited> {
+        let mut budget = self.budget.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        budget.take(&self.limit, bytes, Instant::now()).map_err(|retry_after| RateLimited {
+            session_id: self.session_id.clone(),
+            limit: self.limit.clone(),
+            retry_after,
+        })
+    }
+}
+
+/// Writes a session made within the last second
+#[derive(Debug, Default)]
+pub struct WriteBudget {
+    recent: VecDeque<(Instant, usize)>,
+}
+
+impl WriteBudget {
+    /// Count a write of `bytes` against `limit`, or return how long until it would fit
+    pub fn take(&mut self, limit: &RateLimit, bytes: usize, now: Instant) -> std::result::Result<(), Duration> {
+        while self.recent.front().is_some_and(|(at, _)| now.duration_since(*at) >= WINDOW) {
+            self.recent.pop_front();
+        }
+
+        // Drop the oldest writes until the new one fits, to see when it would
+        let mut writes = self.recent.len();
+        let mut sent: usize = self.recent.iter().map(|(_, n)| n).sum();
+        let fits = |writes: usize, sent: usize| {
+            let writes_ok = limit.writes_per_second.is_none_or(|max| writes < max as usize);
+            let bytes_ok = limit.bytes_per_second.is_none_or(|max| writes == 0 || (sent + bytes) as u64 <= max);
+            writes_ok && bytes_ok
+        };
+        let mut frees_at = None;
+        for (at, n) in &self.recent {
+            if fits(writes, sent) {
+                break;
+            }
+            writes -= 1;
+            sent -= n;
+            frees_at = Some(*at + WINDOW);
+        }
+        match frees_at {
+            Some(at) => Err(at.duration_since(now)),
+            None => {
+                self.recent.push_back((now, bytes));
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_budget() {
+        let limit = RateLimit { writes_per_second: Some(2), bytes_per_second: Some(100) };
+        let mut budget = WriteBudget::default();
+        let start = Instant::now();
+        assert!(budget.take(&limit, 10, start).is_ok());
+        assert!(budget.take(&limit, 10, start + Duration::from_millis(100)).is_ok());
+
+        // Two writes already this second; the first one leaves the window at 1000ms
+        let retry = budget.take(&limit, 10, start + Duration::from_millis(400)).unwrap_err();
+        assert_eq!(retry, Duration::from_millis(600));
+        assert!(budget.take(&limit, 10, start + Duration::from_millis(1000)).is_ok());
+
+        // Over the byte cap until both earlier writes have left the window
+        let retry = budget.take(&limit, 95, start + Duration::from_millis(1050)).unwrap_err();
+        assert_eq!(retry, Duration::from_millis(950));
+
+        // A write larger than the cap goes alone
+        let mut budget = WriteBudget::default();
+        let bytes_only = RateLimit { writes_per_second: None, bytes_per_second: Some(100) };
+        assert!(budget.take(&bytes_only, 500, start).is_ok());
+        assert!(budget.take(&bytes_only, 1, start + Duration::from_millis(999)).is_err());
+        assert!(budget.take(&bytes_only, 1, start + Duration::from_millis(1000)).is_ok());
+
+        assert!(RateLimit { writes_per_second: None, bytes_per_second: None }.validate().is_err());
+        assert!(RateLimit { writes_per_second: Some(0), bytes_per_second: None }.validate().is_err());
+    }
+}
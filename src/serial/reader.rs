@@ -0,0 +1,530 @@
+//! Background reader for serial connections
+//!
+//! Every open connection owns a task that drains the port into a bounded
+//! receive buffer, so incoming data is captured even when no read tool call
+//! is pending. Read tools consume from that buffer instead of the port.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Utc};
+use tokio::io::AsyncReadExt;
+use tokio::sync::{Mutex, Notify};
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+use tokio_serial::SerialStream;
+use tracing::{debug, warn};
+
+use super::capture::CaptureFile;
+use super::connection::{send_flow_signal, BackpressurePolicy, PauseSignal};
+use super::filter::ReceivePipeline;
+use super::history::{ConnectionHistory, Direction};
+use super::lines::{LineAssembler, ReceivedLine};
+use super::stats::{RxStats, RxTimingSummary};
+
+/// How long the reader holds the port lock while waiting for data.
+/// Bounds the latency writers and control-line operations see.
+const READER_POLL_INTERVAL_MS: u64 = 10;
+
+/// Size of each read issued against the port
+const READER_CHUNK_SIZE: usize = 1024;
+
+/// Default capacity of the receive buffer in bytes
+pub const DEFAULT_RX_BUFFER_SIZE: usize = 8192;
+
+/// Fill level, in quarters of the capacity, at which the flow-control policy holds the device off
+const THROTTLE_HIGH_QUARTERS: usize = 3;
+
+/// Fill level, in quarters of the capacity, below which a held-off device is released
+const THROTTLE_LOW_QUARTERS: usize = 1;
+
+/// A chunk of data as delivered by a single read from the port
+#[derive(Debug, Clone)]
+pub struct RxChunk {
+    pub timestamp: DateTime<Utc>,
+    pub data: Vec<u8>,
+    received: Instant,
+}
+
+impl RxChunk {
+    fn new(data: Vec<u8>, timestamp: DateTime<Utc>) -> Self {
+        Self {
+            timestamp,
+            data,
+            received: Instant::now(),
+        }
+    }
+
+    /// Time since the chunk arrived
+    pub fn age(&self) -> Duration {
+        self.received.elapsed()
+    }
+}
+
+/// Arrival times of the first and last chunk a read drew from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RxSpan {
+    pub first: DateTime<Utc>,
+    pub last: DateTime<Utc>,
+}
+
+/// Bounded FIFO of received chunks; the oldest data is discarded on overflow
+///
+/// In line mode, received bytes are assembled into whole lines instead.
+#[derive(Debug)]
+pub struct RxBuffer {
+    chunks: VecDeque<RxChunk>,
+    raw_len: usize,
+    capacity: usize,
+    dropped: u64,
+    lines: Option<LineAssembler>,
+    /// When the most recent bytes were pushed
+    last_push: Option<Instant>,
+}
+
+impl RxBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            chunks: VecDeque::new(),
+            raw_len: 0,
+            capacity: capacity.max(1),
+            dropped: 0,
+            lines: None,
+            last_push: None,
+        }
+    }
+
+    /// Append bytes received just now, discarding the oldest bytes if the buffer is full
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.push_at(bytes, Utc::now());
+    }
+
+    /// Append bytes that arrived at `timestamp`
+    pub fn push_at(&mut self, bytes: &[u8], timestamp: DateTime<Utc>) {
+        self.last_push = Some(Instant::now());
+        if let Some(lines) = self.lines.as_mut() {
+            lines.feed_at(bytes, timestamp);
+            return;
+        }
+
+        self.raw_len += bytes.len();
+        self.chunks.push_back(RxChunk::new(bytes.to_vec(), timestamp));
+
+        while self.raw_len > self.capacity {
+            let excess = self.raw_len - self.capacity;
+            let Some(front) = self.chunks.front_mut() else { break };
+            let discard = excess.min(front.data.len());
+            front.data.drain(..discard);
+            if front.data.is_empty() {
+                self.chunks.pop_front();
+            }
+            self.raw_len -= discard;
+            self.dropped += discard as u64;
+        }
+    }
+
+    /// Move up to `out.len()` raw bytes into `out`, returning how many were copied
+    ///
+    /// A chunk that does not fit entirely stays at the front with its remainder.
+    pub fn take_into(&mut self, out: &mut [u8]) -> usize {
+        self.take_into_timed(out).0
+    }
+
+    /// Like [`RxBuffer::take_into`], also returning when the bytes copied arrived
+    pub fn take_into_timed(&mut self, out: &mut [u8]) -> (usize, Option<RxSpan>) {
+        let mut copied = 0;
+        let mut span: Option<RxSpan> = None;
+        while copied < out.len() {
+            let Some(front) = self.chunks.front_mut() else { break };
+            let first = span.map_or(front.timestamp, |s| s.first);
+            span = Some(RxSpan { first, last: front.timestamp });
+            let count = front.data.len().min(out.len() - copied);
+            out[copied..copied + count].copy_from_slice(&front.data[..count]);
+            front.data.drain(..count);
+            if front.data.is_empty() {
+                self.chunks.pop_front();
+            }
+            copied += count;
+        }
+        self.raw_len -= copied;
+        (copied, span)
+    }
+
+    /// Remove whole chunks, stopping at `max_chunks` or before exceeding `max_bytes`
+    ///
+    /// At least one chunk is returned when any are buffered, truncated to
+    /// `max_bytes` if it is larger on its own.
+    pub fn take_chunks(&mut self, max_chunks: usize, max_bytes: usize) -> Vec<RxChunk> {
+        let mut taken = Vec::new();
+        let mut bytes = 0;
+
+        while taken.len() < max_chunks {
+            let Some(front) = self.chunks.front_mut() else { break };
+            if bytes + front.data.len() > max_bytes {
+                if taken.is_empty() && max_bytes > 0 {
+                    let head: Vec<u8> = front.data.drain(..max_bytes).collect();
+                    self.raw_len -= head.len();
+                    taken.push(RxChunk { data: head, ..front.clone() });
+                }
+                break;
+            }
+            let Some(chunk) = self.chunks.pop_front() else { break };
+            bytes += chunk.data.len();
+            self.raw_len -= chunk.data.len();
+            taken.push(chunk);
+        }
+
+        taken
+    }
+
+    /// Number of raw chunks waiting to be read
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// Age of the oldest buffered raw chunk
+    pub fn oldest_chunk_age(&self) -> Option<Duration> {
+        self.chunks.front().map(RxChunk::age)
+    }
+
+    /// Age of the newest byte still buffered, raw or in line mode
+    ///
+    /// Reads take the oldest bytes first, so while anything is buffered the
+    /// newest byte is the last one pushed.
+    pub fn newest_age(&self) -> Option<Duration> {
+        if self.is_empty() {
+            return None;
+        }
+        self.last_push.map(|at| at.elapsed())
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Bytes that can still be buffered without discarding anything
+    pub fn free(&self) -> usize {
+        self.capacity.saturating_sub(self.len())
+    }
+
+    pub fn len(&self) -> usize {
+        self.raw_len + self.lines.as_ref().map_or(0, |l| l.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty() && self.lines.as_ref().is_none_or(|l| l.is_empty())
+    }
+
+    /// Total bytes discarded because the buffer was full
+    pub fn dropped(&self) -> u64 {
+        self.dropped + self.lines.as_ref().map_or(0, |l| l.dropped())
+    }
+
+    /// Whether raw bytes are waiting to be read
+    pub fn has_raw_data(&self) -> bool {
+        !self.chunks.is_empty()
+    }
+
+    pub fn is_line_mode(&self) -> bool {
+        self.lines.is_some()
+    }
+
+    /// Switch between raw and line mode
+    ///
+    /// Bytes already buffered are carried over, so nothing is lost by switching.
+    pub fn set_line_mode(&mut self, enabled: bool) {
+        match (enabled, self.lines.take()) {
+            (true, None) => {
+                let mut lines = LineAssembler::new(self.capacity);
+                for chunk in self.chunks.drain(..) {
+                    lines.feed_at(&chunk.data, chunk.timestamp);
+                }
+                self.raw_len = 0;
+                self.lines = Some(lines);
+            }
+            (false, Some(lines)) => {
+                self.dropped += lines.dropped();
+                let pending = lines.into_bytes();
+                self.push(&pending);
+            }
+            (_, current) => self.lines = current,
+        }
+    }
+
+    /// Number of complete lines waiting in line mode
+    pub fn line_count(&self) -> usize {
+        self.lines.as_ref().map_or(0, |l| l.line_count())
+    }
+
+    /// Remove up to `max_lines` complete lines; empty outside line mode
+    pub fn take_lines(&mut self, max_lines: usize) -> Vec<ReceivedLine> {
+        self.lines.as_mut().map_or_else(Vec::new, |l| l.take_lines(max_lines))
+    }
+}
+
+/// State shared between a connection and its reader task
+#[derive(Debug)]
+pub struct ReaderShared {
+    pub(crate) buffer: Mutex<RxBuffer>,
+    pub(crate) data_ready: Notify,
+    pub(crate) history: Mutex<ConnectionHistory>,
+    pub(crate) capture: Mutex<Option<CaptureFile>>,
+    /// Filters received data passes through before it is buffered and recorded
+    pub(crate) filters: Mutex<Option<ReceivePipeline>>,
+    /// Signalled by consumers after taking data out of the buffer
+    pub(crate) space_available: Notify,
+    policy: BackpressurePolicy,
+    throttle: PauseSignal,
+    throttled: AtomicBool,
+    paused: AtomicBool,
+    resume: Notify,
+    running: AtomicBool,
+    last_error: Mutex<Option<String>>,
+    last_rx: Mutex<Option<Instant>>,
+    rx_stats: Mutex<RxStats>,
+    /// Buffers of shared handles that get a copy of every received chunk
+    taps: Mutex<Vec<Weak<ReaderShared>>>,
+}
+
+impl ReaderShared {
+    /// Create shared state for a buffer of `capacity` bytes
+    ///
+    /// `throttle` is the signal used to hold the device off under
+    /// [`BackpressurePolicy::FlowControl`].
+    pub fn new(capacity: usize, policy: BackpressurePolicy, throttle: PauseSignal) -> Self {
+        Self {
+            buffer: Mutex::new(RxBuffer::new(capacity)),
+            data_ready: Notify::new(),
+            history: Mutex::new(ConnectionHistory::new()),
+            capture: Mutex::new(None),
+            filters: Mutex::new(None),
+            space_available: Notify::new(),
+            policy,
+            throttle,
+            throttled: AtomicBool::new(false),
+            paused: AtomicBool::new(false),
+            resume: Notify::new(),
+            running: AtomicBool::new(true),
+            last_error: Mutex::new(None),
+            last_rx: Mutex::new(None),
+            rx_stats: Mutex::new(RxStats::new()),
+            taps: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Whether the reader task is still draining the port
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::SeqCst);
+        if !paused {
+            self.resume.notify_one();
+        }
+    }
+
+    /// Whether the device is currently held off by the flow-control policy
+    pub fn is_throttled(&self) -> bool {
+        self.throttled.load(Ordering::SeqCst)
+    }
+
+    /// Error that stopped the reader, if any
+    pub async fn last_error(&self) -> Option<String> {
+        self.last_error.lock().await.clone()
+    }
+
+    /// Time since the most recent chunk arrived, `None` if nothing was received yet
+    pub async fn since_last_rx(&self) -> Option<Duration> {
+        self.last_rx.lock().await.map(|at| at.elapsed())
+    }
+
+    /// Deliver a copy of all data received from now on to `tap`
+    ///
+    /// The tap is dropped once its owner goes away.
+    pub(crate) async fn add_tap(&self, tap: &Arc<ReaderShared>) {
+        self.taps.lock().await.push(Arc::downgrade(tap));
+    }
+
+    /// Taps still in use, forgetting the ones whose owner went away
+    async fn live_taps(&self) -> Vec<Arc<ReaderShared>> {
+        let mut taps = self.taps.lock().await;
+        taps.retain(|tap| tap.strong_count() > 0);
+        taps.iter().filter_map(Weak::upgrade).collect()
+    }
+
+    /// Record and buffer one received chunk
+    async fn deliver(&self, data: &[u8], received_at: DateTime<Utc>) {
+        let now = Instant::now();
+        let previous = self.last_rx.lock().await.replace(now);
+        self.rx_stats.lock().await.record_chunk(data.len(), previous.map(|at| now - at));
+        let filtered = self.filters.lock().await.as_mut().map(|filters| filters.apply(data));
+        self.buffer_received(filtered.as_deref().unwrap_or(data), received_at).await;
+    }
+
+    /// Record and buffer what the filters held back, once the port has gone quiet
+    async fn flush_filters(&self) {
+        let held = match self.filters.lock().await.as_mut() {
+            Some(filters) if filters.holds_data() => filters.flush(),
+            _ => return,
+        };
+        self.buffer_received(&held, Utc::now()).await;
+    }
+
+    /// Record and buffer received data that has been through the filters
+    pub(crate) async fn buffer_received(&self, data: &[u8], received_at: DateTime<Utc>) {
+        if data.is_empty() {
+            return;
+        }
+        self.record_traffic(Direction::Rx, data, received_at).await;
+        self.buffer.lock().await.push_at(data, received_at);
+        self.data_ready.notify_waiters();
+    }
+
+    /// Inter-arrival gap and chunk size statistics of received data
+    pub async fn rx_timing(&self) -> RxTimingSummary {
+        self.rx_stats.lock().await.summary()
+    }
+
+    /// Add traffic to the transcript and to the capture file, if one is open
+    ///
+    /// A capture that fails to write is closed, so the connection keeps working.
+    pub(crate) async fn record_traffic(&self, direction: Direction, data: &[u8], timestamp: DateTime<Utc>) {
+        let mut history = self.history.lock().await;
+        history.record_traffic(direction, data, timestamp);
+
+        let mut capture = self.capture.lock().await;
+        if let Some(file) = capture.as_mut() {
+            if let Err(e) = file.record(timestamp, direction, data) {
+                warn!("Capture to {} failed: {}", file.path().display(), e);
+                history.record_event(format!("Capture to {} stopped: {}", file.path().display(), e));
+                *capture = None;
+            }
+        }
+    }
+
+    /// Sync the capture file to disk if its sync interval has elapsed
+    async fn sync_capture_if_due(&self) {
+        let mut capture = self.capture.lock().await;
+        if let Some(file) = capture.as_mut() {
+            if let Err(e) = file.sync_if_due() {
+                warn!("Capture to {} failed: {}", file.path().display(), e);
+                self.history.lock().await.record_event(format!("Capture to {} stopped: {}", file.path().display(), e));
+                *capture = None;
+            }
+        }
+    }
+
+    pub(crate) async fn fail(&self, reason: String) {
+        for tap in self.live_taps().await {
+            Box::pin(tap.fail(reason.clone())).await;
+        }
+        self.history.lock().await.record_event(format!("Reader stopped: {}", reason));
+        *self.last_error.lock().await = Some(reason);
+        self.running.store(false, Ordering::SeqCst);
+        self.data_ready.notify_waiters();
+    }
+}
+
+/// Spawn the reader task for a port
+pub fn spawn_reader(stream: Arc<Mutex<SerialStream>>, shared: Arc<ReaderShared>) -> JoinHandle<()> {
+    tokio::spawn(run_reader(stream, shared))
+}
+
+async fn run_reader(stream: Arc<Mutex<SerialStream>>, shared: Arc<ReaderShared>) {
+    let mut chunk = vec![0u8; READER_CHUNK_SIZE];
+
+    loop {
+        if shared.is_paused() {
+            shared.resume.notified().await;
+            continue;
+        }
+
+        let (free, level, capacity) = {
+            let buffer = shared.buffer.lock().await;
+            (buffer.free(), buffer.len(), buffer.capacity())
+        };
+
+        let mut limit = READER_CHUNK_SIZE;
+        match shared.policy {
+            BackpressurePolicy::Drop => {}
+            BackpressurePolicy::Block => {
+                if free == 0 {
+                    // Leave the data with the driver until a consumer makes room
+                    shared.space_available.notified().await;
+                    continue;
+                }
+                limit = limit.min(free);
+            }
+            BackpressurePolicy::FlowControl => {
+                let throttled = shared.is_throttled();
+                let hold_off = if throttled {
+                    level > capacity * THROTTLE_LOW_QUARTERS / 4
+                } else {
+                    level >= capacity * THROTTLE_HIGH_QUARTERS / 4
+                };
+                if hold_off != throttled {
+                    set_throttle(&stream, &shared, hold_off).await;
+                }
+            }
+        }
+
+        let result = {
+            let mut port = stream.lock().await;
+            timeout(Duration::from_millis(READER_POLL_INTERVAL_MS), port.read(&mut chunk[..limit])).await
+        };
+
+        match result {
+            // Nothing arrived within the poll interval; release the lock and go again
+            Err(_) => {
+                shared.flush_filters().await;
+                for tap in shared.live_taps().await {
+                    tap.flush_filters().await;
+                }
+                shared.sync_capture_if_due().await;
+            }
+            Ok(Ok(0)) => {
+                debug!("Serial port reached end of stream");
+                shared.fail("Port closed".to_string()).await;
+                break;
+            }
+            Ok(Ok(n)) => {
+                let received_at = Utc::now();
+                shared.deliver(&chunk[..n], received_at).await;
+                for tap in shared.live_taps().await {
+                    // A paused shared handle just misses the data
+                    if !tap.is_paused() {
+                        tap.deliver(&chunk[..n], received_at).await;
+                    }
+                }
+            }
+            Ok(Err(e)) => {
+                warn!("Serial reader stopped: {}", e);
+                shared.fail(e.to_string()).await;
+                break;
+            }
+        }
+    }
+}
+
+/// Hold the device off or release it under the flow-control policy
+async fn set_throttle(stream: &Mutex<SerialStream>, shared: &ReaderShared, hold_off: bool) {
+    let result = send_flow_signal(&mut *stream.lock().await, shared.throttle, hold_off).await;
+    // Record the new state even on failure, so a broken signal is not retried every poll
+    shared.throttled.store(hold_off, Ordering::SeqCst);
+
+    let message = match (hold_off, result) {
+        (true, Ok(())) => format!("Receive buffer nearly full, device held off ({})", shared.throttle),
+        (false, Ok(())) => format!("Receive buffer drained, device released ({})", shared.throttle),
+        (_, Err(e)) => {
+            warn!("Failed to send {} flow signal: {}", shared.throttle, e);
+            format!("Failed to send {} flow signal: {}", shared.throttle, e)
+        }
+    };
+    shared.history.lock().await.record_event(message);
+}
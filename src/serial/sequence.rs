@@ -0,0 +1,73 @@
+//! Command sequences sent to a device
+//!
+//! A sequence is a list of command lines sent one at a time, each followed by
+//! collecting the device's response until the line goes quiet. Profiles use
+//! them to bring a device into a known state after opening a port and into a
+//! safe state before closing it. Every response is recorded in the
+//! connection's event history.
+
+use tracing::{info, warn};
+
+use super::connection::SerialConnection;
+use crate::utils::DataConverter;
+
+/// Characters of a command's response kept in the event history
+const MAX_RECORDED_RESPONSE: usize = 120;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandSequence {
+    /// Labels the recorded events, e.g. "Init" or "Shutdown"
+    pub phase: &'static str,
+    pub commands: Vec<String>,
+    /// Appended to every command
+    pub line_ending: String,
+    /// Quiet time that ends a command's response
+    pub settle_ms: u64,
+}
+
+impl CommandSequence {
+    /// Send the commands in order, returning how many were sent
+    ///
+    /// Stops at the first command that cannot be sent, returning why.
+    pub async fn run(&self, connection: &SerialConnection) -> Result<usize, String> {
+        for (index, command) in self.commands.iter().enumerate() {
+            let sent = connection.write(format!("{}{}", command, self.line_ending).as_bytes()).await;
+            let outcome = match sent {
+                Ok(_) => connection.drain_output(self.settle_ms).await,
+                Err(e) => Err(e),
+            };
+
+            match outcome {
+                Ok(response) => {
+                    connection
+                        .record_event(format!("{} command {:?}: {}", self.phase, command, describe_response(&response)))
+                        .await;
+                }
+                Err(e) => {
+                    warn!("{} command {:?} on connection {} failed: {}", self.phase, command, connection.id(), e);
+                    let reason = format!("{} command {:?} failed: {}", self.phase, command, e);
+                    connection.record_event(reason.clone()).await;
+                    let skipped = self.commands.len() - index - 1;
+                    return Err(if skipped > 0 { format!("{}; {} later command(s) skipped", reason, skipped) } else { reason });
+                }
+            }
+        }
+        info!("Sent {} {} command(s) on connection {}", self.commands.len(), self.phase.to_lowercase(), connection.id());
+        Ok(self.commands.len())
+    }
+}
+
+/// Response text for the event history, escaped and shortened
+fn describe_response(response: &str) -> String {
+    let response = response.trim();
+    if response.is_empty() {
+        return "no response".to_string();
+    }
+    let escaped = DataConverter::escape_string(response);
+    if escaped.chars().count() > MAX_RECORDED_RESPONSE {
+        let kept: String = escaped.chars().take(MAX_RECORDED_RESPONSE).collect();
+        format!("responded {:?}...", kept)
+    } else {
+        format!("responded {:?}", escaped)
+    }
+}
@@ -0,0 +1,146 @@
+//! Receive timing statistics
+//!
+//! Tracks the gaps between received chunks and the chunk sizes of a
+//! connection, to help tune timeouts and spot devices that stall.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use serde::Serialize;
+
+/// Recent samples kept for percentile estimates
+const STATS_WINDOW: usize = 1024;
+
+/// Running distribution of a measurement
+///
+/// Count, minimum, maximum and mean cover every sample; the 95th percentile
+/// is estimated from the most recent [`STATS_WINDOW`] samples.
+#[derive(Debug, Default)]
+pub struct Distribution {
+    count: u64,
+    sum: f64,
+    min: f64,
+    max: f64,
+    recent: VecDeque<f64>,
+}
+
+impl Distribution {
+    pub fn record(&mut self, value: f64) {
+        if self.count == 0 {
+            self.min = value;
+            self.max = value;
+        } else {
+            self.min = self.min.min(value);
+            self.max = self.max.max(value);
+        }
+        self.count += 1;
+        self.sum += value;
+
+        if self.recent.len() == STATS_WINDOW {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(value);
+    }
+
+    /// Summary of the samples so far, `None` before the first one
+    pub fn summary(&self) -> Option<DistributionSummary> {
+        if self.count == 0 {
+            return None;
+        }
+
+        let mut sorted: Vec<f64> = self.recent.iter().copied().collect();
+        sorted.sort_by(f64::total_cmp);
+        // Nearest-rank percentile
+        let rank = ((sorted.len() as f64) * 0.95).ceil() as usize;
+        let p95 = sorted[rank.clamp(1, sorted.len()) - 1];
+
+        Some(DistributionSummary {
+            count: self.count,
+            min: self.min,
+            avg: self.sum / self.count as f64,
+            max: self.max,
+            p95,
+        })
+    }
+}
+
+/// Summary statistics of a distribution
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DistributionSummary {
+    pub count: u64,
+    pub min: f64,
+    pub avg: f64,
+    pub max: f64,
+    pub p95: f64,
+}
+
+/// Timing of received chunks on one connection
+#[derive(Debug, Default)]
+pub struct RxStats {
+    gaps_ms: Distribution,
+    chunk_bytes: Distribution,
+}
+
+impl RxStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a chunk of `len` bytes that arrived `gap` after the previous one
+    ///
+    /// The first chunk of a connection has no gap.
+    pub fn record_chunk(&mut self, len: usize, gap: Option<Duration>) {
+        if let Some(gap) = gap {
+            self.gaps_ms.record(gap.as_secs_f64() * 1000.0);
+        }
+        self.chunk_bytes.record(len as f64);
+    }
+
+    pub fn summary(&self) -> RxTimingSummary {
+        RxTimingSummary {
+            inter_arrival_ms: self.gaps_ms.summary(),
+            chunk_bytes: self.chunk_bytes.summary(),
+        }
+    }
+}
+
+/// Inter-arrival gap and chunk size statistics, as reported in status
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RxTimingSummary {
+    pub inter_arrival_ms: Option<DistributionSummary>,
+    pub chunk_bytes: Option<DistributionSummary>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_distribution_summary() {
+        let mut distribution = Distribution::default();
+        assert!(distribution.summary().is_none());
+
+        for value in 1..=100 {
+            distribution.record(value as f64);
+        }
+        let summary = distribution.summary().unwrap();
+        assert_eq!(summary.count, 100);
+        assert_eq!(summary.min, 1.0);
+        assert_eq!(summary.max, 100.0);
+        assert_eq!(summary.avg, 50.5);
+        assert_eq!(summary.p95, 95.0);
+    }
+
+    #[test]
+    fn test_rx_stats_skip_first_gap() {
+        let mut stats = RxStats::new();
+        stats.record_chunk(8, None);
+        stats.record_chunk(4, Some(Duration::from_millis(20)));
+
+        let summary = stats.summary();
+        assert_eq!(summary.chunk_bytes.unwrap().count, 2);
+        let gaps = summary.inter_arrival_ms.unwrap();
+        assert_eq!(gaps.count, 1);
+        assert_eq!(gaps.max, 20.0);
+    }
+}
@@ -1,6 +1,6 @@
 #[cfg(test)]
 mod tests {
-    use crate::serial::{ConnectionManager, ConnectionConfig, DataBits, StopBits, Parity, FlowControl, PortInfo};
+    use crate::serial::{BackpressurePolicy, ConnectionManager, ConnectionConfig, DataBits, StopBits, Parity, FlowControl, PortInfo};
     use crate::serial::error::SerialError;
 
     #[tokio::test]
@@ -20,6 +20,7 @@ mod tests {
             stop_bits: StopBits::One,
             parity: Parity::None,
             flow_control: FlowControl::None,
+            backpressure: BackpressurePolicy::Drop,
         };
 
         let result = manager.open(config).await;
@@ -86,6 +87,7 @@ mod tests {
             stop_bits: StopBits::One,
             parity: Parity::None,
             flow_control: FlowControl::None,
+            backpressure: BackpressurePolicy::Drop,
         };
 
         let rt = tokio::runtime::Runtime::new().unwrap();
@@ -139,4 +141,1008 @@ mod tests {
             }
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_macos_device_note() {
+        use crate::serial::port::macos_device_note;
+
+        let note = macos_device_note("/dev/tty.usbserial-A50285BI").unwrap();
+        assert!(note.contains("/dev/cu.usbserial-A50285BI"));
+        assert!(macos_device_note("/dev/cu.usbserial-A50285BI").unwrap().contains("use this one"));
+        assert!(macos_device_note("/dev/ttyUSB0").is_none());
+    }
+
+    #[test]
+    fn test_prefer_callout_device() {
+        use crate::serial::port::prefer_callout_device;
+
+        let (path, note) = prefer_callout_device("/dev/tty.usbmodem1101", true);
+        assert_eq!(path, "/dev/cu.usbmodem1101");
+        assert!(note.unwrap().contains("prefer_callout_device"));
+
+        let (path, note) = prefer_callout_device("/dev/tty.usbmodem1101", false);
+        assert_eq!(path, "/dev/tty.usbmodem1101");
+        assert!(note.unwrap().contains("/dev/cu.usbmodem1101"));
+
+        assert_eq!(prefer_callout_device("/dev/cu.usbmodem1101", true), ("/dev/cu.usbmodem1101".to_string(), None));
+    }
+
+    #[test]
+    fn test_device_identity_relocated() {
+        use crate::serial::DeviceIdentity;
+        use serialport::{SerialPortInfo, SerialPortType, UsbPortInfo};
+
+        let usb = |name: &str, pid: u16, serial: Option<&str>| SerialPortInfo {
+            port_name: name.to_string(),
+            port_type: SerialPortType::UsbPort(UsbPortInfo {
+                vid: 0x0403,
+                pid,
+                serial_number: serial.map(str::to_string),
+                manufacturer: None,
+                product: None,
+            }),
+        };
+        let before = vec![usb("/dev/ttyUSB0", 0x6001, Some("A50285BI")), usb("/dev/ttyUSB1", 0x6015, None)];
+        let identity = DeviceIdentity::find(&before, "/dev/ttyUSB0").unwrap();
+        assert_eq!(identity.to_string(), "USB 0403:6001 serial A50285BI");
+
+        // Replugged and re-enumerated after the other adapter
+        let after = vec![usb("/dev/ttyUSB0", 0x6015, None), usb("/dev/ttyUSB1", 0x6001, Some("A50285BI"))];
+        assert_eq!(identity.locate_in(&after).as_deref(), Some("/dev/ttyUSB1"));
+
+        // The macOS dial-in node of the same device is not a second match
+        let macos = vec![
+            usb("/dev/tty.usbserial-A50285BI", 0x6001, Some("A50285BI")),
+            usb("/dev/cu.usbserial-A50285BI", 0x6001, Some("A50285BI")),
+        ];
+        assert_eq!(identity.locate_in(&macos).as_deref(), Some("/dev/cu.usbserial-A50285BI"));
+
+        // Without a serial number, two adapters of the same model are ambiguous
+        let anonymous = DeviceIdentity::find(&before, "/dev/ttyUSB1").unwrap();
+        let twins = vec![usb("/dev/ttyUSB0", 0x6015, None), usb("/dev/ttyUSB2", 0x6015, None)];
+        assert_eq!(anonymous.locate_in(&twins), None);
+        assert_eq!(anonymous.locate_in(&twins[..1]).as_deref(), Some("/dev/ttyUSB0"));
+
+        assert_eq!(DeviceIdentity::find(&before, "/dev/ttyS0"), None);
+    }
+
+    #[test]
+    fn test_virtual_pairs_from_serialcomm() {
+        use crate::serial::virtual_pair::{virtual_ports, VirtualPort};
+
+        let entries: Vec<(String, String)> = [
+            ("\\Device\\Serial0", "COM1"),
+            ("\\Device\\com0com10", "COM5"),
+            ("\\Device\\com0com20", "COM6"),
+            ("\\Device\\com0com11", "CNCA1"),
+            ("\\Device\\VSPE_Pair1", "COM9"),
+        ]
+        .iter()
+        .map(|(device, port)| (device.to_string(), port.to_string()))
+        .collect();
+
+        let ports = virtual_ports(&entries);
+        assert_eq!(ports.len(), 4);
+        assert!(!ports.contains_key("COM1"));
+        assert_eq!(ports["COM5"], VirtualPort { driver: "com0com", peer: Some("COM6".to_string()) });
+        assert_eq!(ports["COM6"].peer.as_deref(), Some("COM5"));
+        assert_eq!(ports["CNCA1"].peer, None);
+        assert_eq!(ports["COM9"], VirtualPort { driver: "VSPE", peer: None });
+    }
+
+    /// Open a pseudo-terminal pair; the master end plays the device,
+    /// the returned path is opened by the connection under test
+    #[cfg(unix)]
+    fn pty_device() -> (tokio_serial::SerialStream, tokio_serial::SerialStream, String) {
+        use tokio_serial::SerialPort;
+
+        let (device, slave) = tokio_serial::SerialStream::pair().unwrap();
+        let path = slave.name().unwrap();
+        (device, slave, path)
+    }
+
+    /// The device end of a loopback link plus the path of the end under test:
+    /// a pseudo-terminal on Unix, the first com0com pair on Windows. `None`
+    /// when no pair is installed, in which case the test is skipped
+    #[cfg(unix)]
+    fn test_device() -> Option<(tokio_serial::SerialStream, tokio_serial::SerialStream, String)> {
+        Some(pty_device())
+    }
+
+    #[cfg(windows)]
+    fn test_device() -> Option<(tokio_serial::SerialStream, (), String)> {
+        let Some((device_port, path)) = crate::serial::virtual_pair::first_pair() else {
+            eprintln!("No com0com pair installed, skipping");
+            return None;
+        };
+        let device = tokio_serial::SerialStream::open(&tokio_serial::new(device_port, 115200)).unwrap();
+        Some((device, (), path))
+    }
+
+    fn port_config(path: &str) -> ConnectionConfig {
+        ConnectionConfig {
+            port: path.to_string(),
+            baud_rate: 115200,
+            data_bits: DataBits::Eight,
+            stop_bits: StopBits::One,
+            parity: Parity::None,
+            flow_control: FlowControl::None,
+            backpressure: BackpressurePolicy::Drop,
+        }
+    }
+
+    #[cfg(any(unix, windows))]
+    #[tokio::test]
+    async fn test_background_reader_pause_resume() {
+        use crate::serial::{PauseSignal, SerialConnection};
+        use tokio::io::AsyncWriteExt;
+
+        let Some((mut device, _slave, path)) = test_device() else {
+            return;
+        };
+        let connection = SerialConnection::new(port_config(&path)).await.unwrap();
+
+        device.write_all(b"hello").await.unwrap();
+        let mut buffer = [0u8; 16];
+        let n = connection.read(&mut buffer, Some(1000)).await.unwrap();
+        assert_eq!(&buffer[..n], b"hello");
+
+        connection.pause(PauseSignal::None).await.unwrap();
+        assert!(connection.is_paused());
+        assert!(connection.status().await.paused);
+
+        device.write_all(b"later").await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert_eq!(connection.buffered_bytes().await, 0);
+        assert_eq!(connection.read(&mut buffer, Some(100)).await.unwrap(), 0);
+
+        connection.resume().await.unwrap();
+        assert!(!connection.is_paused());
+        let n = connection.read(&mut buffer, Some(1000)).await.unwrap();
+        assert_eq!(&buffer[..n], b"later");
+    }
+
+    #[cfg(any(unix, windows))]
+    #[tokio::test]
+    async fn test_link_health_counters() {
+        use crate::serial::SerialConnection;
+
+        let Some((_device, _slave, path)) = test_device() else {
+            return;
+        };
+        let connection = SerialConnection::new(port_config(&path)).await.unwrap();
+
+        let mut buffer = [0u8; 16];
+        assert!(connection.read(&mut buffer, Some(20)).await.is_err());
+        assert!(connection.read_lines(10, Some(20)).await.is_err());
+        assert_eq!(connection.write(b"AT\r").await.unwrap(), 3);
+
+        let status = connection.status().await;
+        assert_eq!(status.read_timeouts, 2);
+        assert_eq!(status.write_retries, 0);
+        assert_eq!(status.dropped_bytes, 0);
+        // Pseudo-terminals keep no line error counters
+        assert_eq!(status.framing_errors, None);
+    }
+
+    #[cfg(any(unix, windows))]
+    #[tokio::test]
+    async fn test_port_sharing_fans_out_reads() {
+        use crate::serial::ConnectionManager;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let Some((mut device, _slave, path)) = test_device() else {
+            return;
+        };
+        let manager = ConnectionManager::new().with_port_sharing(true);
+        let first = manager.open(port_config(&path)).await.unwrap();
+        let second = manager.open(port_config(&path)).await.unwrap();
+        assert_ne!(first, second);
+
+        let mut other_baud = port_config(&path);
+        other_baud.baud_rate = 9600;
+        assert!(manager.open(other_baud).await.is_err());
+
+        let first = manager.get(&first).await.unwrap();
+        let second = manager.get(&second).await.unwrap();
+        assert_eq!(second.status().await.shared_from.as_deref(), Some(first.id()));
+
+        device.write_all(b"fan-out").await.unwrap();
+        let mut buffer = [0u8; 16];
+        let n = first.read(&mut buffer, Some(1000)).await.unwrap();
+        assert_eq!(&buffer[..n], b"fan-out");
+        let n = second.read(&mut buffer, Some(1000)).await.unwrap();
+        assert_eq!(&buffer[..n], b"fan-out");
+
+        // Writes from both handles reach the device; the port outlives its opener
+        first.write(b"A").await.unwrap();
+        manager.close(first.id()).await.unwrap();
+        drop(first);
+        second.write(b"B").await.unwrap();
+        let mut written = [0u8; 2];
+        device.read_exact(&mut written).await.unwrap();
+        assert_eq!(&written, b"AB");
+
+        // Without sharing the second open is refused
+        let exclusive = ConnectionManager::new();
+        exclusive.open(port_config(&path)).await.unwrap();
+        assert!(exclusive.open(port_config(&path)).await.is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_command_sequences_run_on_open_and_close() {
+        use crate::serial::CommandSequence;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let (mut device, _slave, path) = pty_device();
+        let manager = ConnectionManager::new();
+        let id = manager.open(port_config(&path)).await.unwrap();
+        let connection = manager.get(&id).await.unwrap();
+        let sequence = |phase, commands: &[&str]| CommandSequence {
+            phase,
+            commands: commands.iter().map(|command| command.to_string()).collect(),
+            line_ending: "\r\n".to_string(),
+            settle_ms: 100,
+        };
+
+        let modem = tokio::spawn(async move {
+            let mut command = [0u8; 6];
+            device.read_exact(&mut command).await.unwrap();
+            assert_eq!(&command, b"ATE0\r\n");
+            device.write_all(b"\r\nOK\r\n").await.unwrap();
+            let mut command = [0u8; 5];
+            device.read_exact(&mut command).await.unwrap();
+            assert_eq!(&command, b"ATH\r\n");
+            device
+        });
+        assert_eq!(sequence("Init", &["ATE0"]).run(&connection).await, Ok(1));
+
+        connection.set_teardown(sequence("Shutdown", &["ATH"])).await;
+        manager.close(&id).await.unwrap();
+        let _device = modem.await.unwrap();
+        // Taken by the close, so it never runs twice
+        assert_eq!(connection.run_teardown().await, None);
+
+        let events: Vec<String> = connection.events().await.into_iter().map(|event| event.message).collect();
+        assert!(events.contains(&r#"Init command "ATE0": responded "OK""#.to_string()));
+        assert!(events.contains(&r#"Shutdown command "ATH": no response"#.to_string()));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_at_submit_waits_for_prompt() {
+        use crate::protocol::at;
+        use crate::serial::SerialConnection;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let (mut modem, _slave, path) = pty_device();
+        let connection = SerialConnection::new(port_config(&path)).await.unwrap();
+
+        let fake = tokio::spawn(async move {
+            let mut command = [0u8; 17];
+            modem.read_exact(&mut command).await.unwrap();
+            assert_eq!(&command, b"AT+CMGS=\"+15551\"\r");
+            modem.write_all(b"AT+CMGS=\"+15551\"\r\r\n> ").await.unwrap();
+            let mut body = [0u8; 3];
+            modem.read_exact(&mut body).await.unwrap();
+            assert_eq!(&body, b"Hi\x1a");
+            modem.write_all(b"Hi\r\n+CMGS: 42\r\n\r\nOK\r\n").await.unwrap();
+            modem
+        });
+
+        let response = at::submit(&connection, "AT+CMGS=\"+15551\"", "Hi", 2000).await.unwrap();
+        let _modem = fake.await.unwrap();
+        assert_eq!(response.lines, vec!["+CMGS: 42".to_string()]);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_gcode_job_waits_for_ok_and_resends() {
+        use crate::protocol::gcode::{GcodeJob, GcodeOptions, JobState};
+        use crate::serial::SerialConnection;
+        use std::sync::Arc;
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+        let (printer, _slave, path) = pty_device();
+        let connection = Arc::new(SerialConnection::new(port_config(&path)).await.unwrap());
+
+        let fake = tokio::spawn(async move {
+            let mut printer = BufReader::new(printer);
+            let mut received = Vec::new();
+            let replies: [&[u8]; 4] = [
+                b"ok\n",
+                // Line 1 arrives garbled and is asked for again
+                b"Error:checksum mismatch, Last Line: 0\nResend: 1\nok\n",
+                b"echo:busy: processing\nok\n",
+                b"ok T:25.0 /0.0\n",
+            ];
+            for reply in replies {
+                let mut line = String::new();
+                printer.read_line(&mut line).await.unwrap();
+                received.push(line.trim().to_string());
+                printer.get_mut().write_all(reply).await.unwrap();
+            }
+            (received, printer)
+        });
+
+        let options = GcodeOptions {
+            line_numbers: true,
+            line_timeout: std::time::Duration::from_secs(2),
+            stop_on_error: true,
+        };
+        let job = GcodeJob::start(Arc::clone(&connection), vec!["G28".to_string(), "G1 X10".to_string()], options);
+        let (received, _printer) = fake.await.unwrap();
+        while job.is_running() {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+
+        assert_eq!(received, vec!["M110 N0", "N1 G28*18", "N1 G28*18", "N2 G1 X10*83"]);
+        let progress = job.progress();
+        assert_eq!(progress.state, JobState::Completed);
+        assert_eq!((progress.acknowledged, progress.resends), (2, 1));
+        assert!(progress.errors.is_empty());
+        assert_eq!(progress.temperatures.unwrap().hotends[0].current, 25.0);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_gcode_job_stops_when_printer_halts() {
+        use crate::protocol::gcode::{GcodeJob, GcodeOptions, JobState};
+        use crate::serial::SerialConnection;
+        use std::sync::Arc;
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+        let (printer, _slave, path) = pty_device();
+        let connection = Arc::new(SerialConnection::new(port_config(&path)).await.unwrap());
+
+        let fake = tokio::spawn(async move {
+            let mut printer = BufReader::new(printer);
+            let mut line = String::new();
+            printer.read_line(&mut line).await.unwrap();
+            // A halted printer never answers with ok
+            printer
+                .get_mut()
+                .write_all(b"T:180.2 /210.0 B:60.0 /60.0 @:127 B@:0\nError:Thermal Runaway, system stopped! Heater_ID: 0\n")
+                .await
+                .unwrap();
+            (line, printer)
+        });
+
+        let options = GcodeOptions {
+            line_numbers: false,
+            line_timeout: std::time::Duration::from_secs(30),
+            stop_on_error: false,
+        };
+        let job = GcodeJob::start(Arc::clone(&connection), vec!["M109 S210".to_string(), "G28".to_string()], options);
+        let (line, _printer) = fake.await.unwrap();
+        tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            while job.is_running() {
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("job should stop without waiting for the line timeout");
+
+        assert_eq!(line.trim(), "M109 S210");
+        let progress = job.progress();
+        assert!(matches!(&progress.state, JobState::Failed(reason) if reason.contains("thermal runaway")), "{}", progress.state);
+        assert_eq!(progress.acknowledged, 0);
+        assert_eq!(progress.temperatures.unwrap().bed.unwrap().target, Some(60.0));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_slcan_command_keeps_frames_received_before_answer() {
+        use crate::protocol::slcan::{self, SlcanError};
+        use crate::serial::SerialConnection;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let (mut adapter, _slave, path) = pty_device();
+        let connection = SerialConnection::new(port_config(&path)).await.unwrap();
+
+        let fake = tokio::spawn(async move {
+            let mut command = [0u8; 10];
+            adapter.read_exact(&mut command).await.unwrap();
+            adapter.write_all(b"t7FF10A\rz\r").await.unwrap();
+            let mut close = [0u8; 2];
+            adapter.read_exact(&mut close).await.unwrap();
+            adapter.write_all(b"\x07").await.unwrap();
+            (command, adapter)
+        });
+
+        let frames = slcan::command(&connection, "t1232AABB", 1000).await.unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!((frames[0].id, frames[0].data.as_slice()), (0x7FF, &[0x0A][..]));
+        assert!(matches!(slcan::command(&connection, "C", 1000).await, Err(SlcanError::Rejected(_))));
+
+        let (command, _adapter) = fake.await.unwrap();
+        assert_eq!(&command, b"t1232AABB\r");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_cat_reads_icom_and_kenwood_radios() {
+        use crate::protocol::cat::{self, CatError, CatProtocol};
+        use crate::serial::SerialConnection;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let (mut radio, _slave, path) = pty_device();
+        let connection = SerialConnection::new(port_config(&path)).await.unwrap();
+
+        let fake = tokio::spawn(async move {
+            let mut request = [0u8; 6];
+            radio.read_exact(&mut request).await.unwrap();
+            // Bus echo, a transceive broadcast, then the answer
+            radio.write_all(&request).await.unwrap();
+            radio.write_all(&[0xFE, 0xFE, 0x00, 0x94, 0x00, 0x00, 0x50, 0x07, 0x14, 0x00, 0xFD]).await.unwrap();
+            radio.write_all(&[0xFE, 0xFE, 0xE0, 0x94, 0x03, 0x00, 0x40, 0x07, 0x14, 0x00, 0xFD]).await.unwrap();
+
+            let mut mode = [0u8; 3];
+            radio.read_exact(&mut mode).await.unwrap();
+            radio.write_all(b"MD2;").await.unwrap();
+            let mut set = [0u8; 3];
+            radio.read_exact(&mut set).await.unwrap();
+            radio.write_all(b"?;").await.unwrap();
+            (request, mode, set, radio)
+        });
+
+        let icom = CatProtocol::Icom { address: 0x94 };
+        assert_eq!(cat::frequency(&connection, icom, 1000).await.unwrap(), 14_074_000);
+        assert_eq!(cat::mode(&connection, CatProtocol::Kenwood, 1000).await.unwrap(), "USB");
+        assert!(matches!(cat::set_ptt(&connection, CatProtocol::Kenwood, true, 1000).await, Err(CatError::Rejected(_))));
+
+        let (request, mode, set, _radio) = fake.await.unwrap();
+        assert_eq!(request, [0xFE, 0xFE, 0x94, 0xE0, 0x03, 0xFD]);
+        assert_eq!((&mode, &set), (b"MD;", b"TX;"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_bridge_forwards_and_logs_both_directions() {
+        use crate::serial::{Bridge, BridgeDirection, SerialConnection};
+        use std::sync::Arc;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let (mut host_app, _host_slave, host_path) = pty_device();
+        let (mut device, _device_slave, device_path) = pty_device();
+        let host = Arc::new(SerialConnection::new(port_config(&host_path)).await.unwrap());
+        let device_side = Arc::new(SerialConnection::new(port_config(&device_path)).await.unwrap());
+        let bridge = Bridge::start(host, device_side, 2);
+
+        host_app.write_all(b"AT\r").await.unwrap();
+        let mut request = [0u8; 3];
+        device.read_exact(&mut request).await.unwrap();
+        assert_eq!(&request, b"AT\r");
+
+        device.write_all(b"OK\r").await.unwrap();
+        let mut response = [0u8; 3];
+        host_app.read_exact(&mut response).await.unwrap();
+        assert_eq!(&response, b"OK\r");
+
+        let stats = bridge.stats().await;
+        assert_eq!(stats.host_to_device_bytes, 3);
+        assert_eq!(stats.device_to_host_bytes, 3);
+        let records = bridge.recent(10).await;
+        assert_eq!(records.first().unwrap().direction, BridgeDirection::HostToDevice);
+        assert_eq!(records.last().unwrap().direction, BridgeDirection::DeviceToHost);
+        assert!(bridge.is_running());
+
+        bridge.host().simulate_disconnect().await;
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        assert!(!bridge.is_running());
+        assert!(bridge.stats().await.error.unwrap().contains("host->device"));
+    }
+
+    #[cfg(any(unix, windows))]
+    #[tokio::test]
+    async fn test_framed_reads_and_writes() {
+        use crate::protocol::FramerKind;
+        use crate::serial::SerialConnection;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let Some((mut device, _slave, path)) = test_device() else {
+            return;
+        };
+        let connection = SerialConnection::new(port_config(&path)).await.unwrap();
+        assert!(matches!(connection.write_frame(b"x").await, Err(SerialError::InvalidConfig(_))));
+
+        connection.set_framer(Some(FramerKind::Raw.build())).await;
+        assert_eq!(connection.status().await.framer.as_deref(), Some("raw"));
+
+        assert_eq!(connection.write_frame(b"ping").await.unwrap(), b"ping");
+        let mut sent = [0u8; 4];
+        device.read_exact(&mut sent).await.unwrap();
+        assert_eq!(&sent, b"ping");
+
+        device.write_all(b"pong").await.unwrap();
+        let frames = connection.read_frames(8, Some(1000)).await.unwrap();
+        assert_eq!(frames, vec![Ok(b"pong".to_vec())]);
+        assert!(matches!(connection.read_frames(8, Some(50)).await, Err(SerialError::ReadTimeout)));
+
+        connection.set_framer(None).await;
+        assert!(connection.status().await.framer.is_none());
+    }
+
+    #[cfg(any(unix, windows))]
+    #[tokio::test]
+    async fn test_plain_writes_wait_their_turn() {
+        use crate::serial::SerialConnection;
+        use tokio::io::AsyncReadExt;
+
+        let Some((mut device, _slave, path)) = test_device() else {
+            return;
+        };
+        let connection = std::sync::Arc::new(SerialConnection::new(port_config(&path)).await.unwrap());
+        let turn = connection.write_turn(None).await.unwrap();
+
+        // A write made without asking for a turn still queues behind the one held
+        let queued = {
+            let connection = std::sync::Arc::clone(&connection);
+            tokio::spawn(async move { connection.write(b"second").await.unwrap() })
+        };
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert_eq!(connection.status().await.queued_writes, 2);
+        connection.write_in_turn(&turn, b"first").await.unwrap();
+        drop(turn);
+        queued.await.unwrap();
+
+        let mut sent = [0u8; 11];
+        device.read_exact(&mut sent).await.unwrap();
+        assert_eq!(&sent, b"firstsecond");
+    }
+
+    #[cfg(any(unix, windows))]
+    #[tokio::test]
+    async fn test_write_limiter_charges_every_write() {
+        use crate::protocol::FramerKind;
+        use crate::serial::{RateLimit, SerialConnection, WriteLimiter};
+
+        let Some((_device, _slave, path)) = test_device() else {
+            return;
+        };
+        let connection = SerialConnection::new(port_config(&path)).await.unwrap();
+        let limit = RateLimit { writes_per_second: Some(2), bytes_per_second: None };
+        connection.set_write_limiter(Some(WriteLimiter::new("session", limit)));
+        connection.set_framer(Some(FramerKind::Raw.build())).await;
+
+        // Plain writes and frames draw on the same budget
+        connection.write(b"one").await.unwrap();
+        connection.write_frame(b"two").await.unwrap();
+        match connection.write(b"three").await {
+            Err(SerialError::RateLimited(limited)) => {
+                assert_eq!(limited.session_id, "session");
+                assert!(limited.retry_after <= std::time::Duration::from_secs(1));
+            }
+            other => panic!("expected a rate limit, got {:?}", other),
+        }
+
+        connection.set_write_limiter(None);
+        connection.write(b"three").await.unwrap();
+    }
+
+    #[cfg(any(unix, windows))]
+    #[tokio::test]
+    async fn test_write_rules_check_frame_payloads() {
+        use crate::protocol::FramerKind;
+        use crate::serial::{RefusedWrite, SerialConnection, WriteAudit, WriteRule};
+
+        let Some((_device, _slave, path)) = test_device() else {
+            return;
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let audit = std::sync::Arc::new(WriteAudit::new(dir.path()));
+        let only_ping = WriteRule::new("", &["^ping$".to_string()], &[]).unwrap();
+        let connection = SerialConnection::new(port_config(&path))
+            .await
+            .unwrap()
+            .with_write_rules(vec![only_ping])
+            .with_write_audit(std::sync::Arc::clone(&audit));
+        connection.set_framer(Some(FramerKind::Slip.build())).await;
+
+        // The payload passes; the SLIP frame around it would not
+        assert_eq!(connection.write_frame(b"ping").await.unwrap(), b"\xC0ping\xC0");
+        assert!(matches!(connection.write_frame(b"pong").await, Err(SerialError::WriteRefused(_))));
+        assert!(matches!(connection.write(b"\xC0ping\xC0").await, Err(SerialError::WriteRefused(_))));
+
+        let refused: Vec<RefusedWrite> =
+            std::fs::read_to_string(audit.path()).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(refused.len(), 2);
+        assert_eq!((refused[0].data.as_str(), refused[0].reason.as_str()), ("706F6E67", "matches no allow pattern"));
+        assert_eq!(refused[1].port, path);
+    }
+
+    #[cfg(any(unix, windows))]
+    #[tokio::test]
+    async fn test_modbus_transaction_with_slave() {
+        use crate::protocol::modbus::{transaction, ModbusError, ModbusRequest, ModbusResponse};
+        use crate::serial::SerialConnection;
+        use crate::utils::BufferUtils;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let Some((mut device, _slave, path)) = test_device() else {
+            return;
+        };
+        let connection = SerialConnection::new(port_config(&path)).await.unwrap();
+        let request = ModbusRequest::ReadHoldingRegisters { address: 0x10, count: 2 };
+        let expected_request = request.encode(7);
+
+        // Stale bytes waiting before the request must not be taken for the reply
+        device.write_all(b"noise").await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+        let slave = tokio::spawn(async move {
+            let mut received = vec![0u8; expected_request.len()];
+            device.read_exact(&mut received).await.unwrap();
+            assert_eq!(received, expected_request);
+            let mut reply = vec![7, 0x03, 4, 0x12, 0x34, 0x00, 0x01];
+            reply.extend(BufferUtils::crc16_modbus(&reply).to_le_bytes());
+            // Split the reply to check it is reassembled
+            device.write_all(&reply[..3]).await.unwrap();
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            device.write_all(&reply[3..]).await.unwrap();
+            device
+        });
+
+        let response = transaction(&connection, 7, &request, 1000).await.unwrap();
+        assert_eq!(response, ModbusResponse::Registers(vec![0x1234, 0x0001]));
+        let _device = slave.await.unwrap();
+
+        let error = transaction(&connection, 7, &request, 100).await.unwrap_err();
+        assert!(matches!(error, ModbusError::Timeout { received: 0, .. }));
+    }
+
+    #[cfg(any(unix, windows))]
+    #[tokio::test]
+    async fn test_modbus_simulator_answers_master() {
+        use crate::protocol::modbus::{ModbusRequest, ModbusResponse};
+        use crate::protocol::modbus_slave::{ModbusSimulator, RegisterTable};
+        use crate::serial::SerialConnection;
+        use std::sync::Arc;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let Some((mut master, _slave, path)) = test_device() else {
+            return;
+        };
+        let connection = Arc::new(SerialConnection::new(port_config(&path)).await.unwrap());
+        let simulator = ModbusSimulator::start(connection, 3);
+        simulator.registers().write(RegisterTable::Holding, 0, &[0xBEEF]);
+
+        // Sent in two pieces, as a slow master or USB adapter might
+        let write = ModbusRequest::WriteSingleRegister { address: 1, value: 42 };
+        let frame = write.encode(3);
+        master.write_all(&frame[..4]).await.unwrap();
+        master.write_all(&frame[4..]).await.unwrap();
+        let mut reply = vec![0u8; 8];
+        master.read_exact(&mut reply).await.unwrap();
+        assert_eq!(write.parse_response(3, &reply).unwrap(), ModbusResponse::Written);
+
+        let read = ModbusRequest::ReadHoldingRegisters { address: 0, count: 2 };
+        master.write_all(&read.encode(3)).await.unwrap();
+        let mut reply = vec![0u8; 9];
+        master.read_exact(&mut reply).await.unwrap();
+        assert_eq!(read.parse_response(3, &reply).unwrap(), ModbusResponse::Registers(vec![0xBEEF, 42]));
+
+        assert_eq!(simulator.stats().requests, 2);
+        simulator.stop();
+    }
+
+    #[cfg(any(unix, windows))]
+    #[tokio::test]
+    async fn test_simulated_disconnect() {
+        use crate::serial::SerialConnection;
+
+        let Some((_device, _slave, path)) = test_device() else {
+            return;
+        };
+        let connection = SerialConnection::new(port_config(&path)).await.unwrap();
+        connection.write(b"AT\r").await.unwrap();
+
+        connection.simulate_disconnect().await;
+        assert!(!connection.status().await.connected);
+
+        let mut buffer = [0u8; 16];
+        let err = connection.read(&mut buffer, Some(100)).await.unwrap_err();
+        assert!(err.to_string().contains("Simulated cable pull"));
+        assert!(connection.write(b"AT\r").await.is_err());
+        assert!(connection.events().await.iter().any(|e| e.message.contains("Simulated cable pull")));
+    }
+
+    #[cfg(any(unix, windows))]
+    #[tokio::test]
+    async fn test_block_backpressure_loses_nothing() {
+        use crate::serial::SerialConnection;
+        use crate::serial::reader::DEFAULT_RX_BUFFER_SIZE;
+        use tokio::io::AsyncWriteExt;
+
+        let Some((mut device, _slave, path)) = test_device() else {
+            return;
+        };
+        let mut config = port_config(&path);
+        config.backpressure = BackpressurePolicy::Block;
+        let connection = SerialConnection::new(config).await.unwrap();
+
+        let total = DEFAULT_RX_BUFFER_SIZE + 2048;
+        let payload: Vec<u8> = (0..total).map(|i| (i % 251) as u8).collect();
+        let expected = payload.clone();
+        let writer = tokio::spawn(async move {
+            device.write_all(&payload).await.unwrap();
+            device
+        });
+
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        assert!(connection.buffered_bytes().await <= DEFAULT_RX_BUFFER_SIZE);
+        assert_eq!(connection.status().await.backpressure, BackpressurePolicy::Block);
+
+        let mut received = Vec::new();
+        let mut buffer = [0u8; 1024];
+        while received.len() < total {
+            let n = connection.read(&mut buffer, Some(1000)).await.unwrap();
+            received.extend_from_slice(&buffer[..n]);
+        }
+        let _device = writer.await.unwrap();
+
+        assert_eq!(received, expected);
+        assert_eq!(connection.dropped_bytes().await, 0);
+    }
+
+    #[test]
+    fn test_backpressure_policy_parse() {
+        assert_eq!(BackpressurePolicy::parse("drop").unwrap(), BackpressurePolicy::Drop);
+        assert_eq!(BackpressurePolicy::parse("Block").unwrap(), BackpressurePolicy::Block);
+        assert_eq!(BackpressurePolicy::parse("flow_control").unwrap(), BackpressurePolicy::FlowControl);
+        assert!(BackpressurePolicy::parse("spill").is_err());
+        assert_eq!(BackpressurePolicy::FlowControl.to_string(), "flow_control");
+    }
+
+    #[cfg(any(unix, windows))]
+    #[tokio::test]
+    async fn test_line_mode_reads_whole_lines() {
+        use crate::serial::SerialConnection;
+        use tokio::io::AsyncWriteExt;
+
+        let Some((mut device, _slave, path)) = test_device() else {
+            return;
+        };
+        let connection = SerialConnection::new(port_config(&path)).await.unwrap();
+
+        device.write_all(b"boot ").await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+        // Bytes buffered before switching are carried into the first line
+        connection.set_line_mode(true).await;
+        assert!(connection.status().await.line_mode);
+        device.write_all(b"ok\r\nready\npartial").await.unwrap();
+
+        let mut lines = Vec::new();
+        while lines.len() < 2 {
+            lines.extend(connection.read_lines(10, Some(1000)).await.unwrap());
+        }
+        assert_eq!(lines[0].text(), "boot ok");
+        assert_eq!(lines[1].text(), "ready");
+        assert_eq!(lines[1].seq, 2);
+
+        // The unterminated tail returns to the raw buffer when line mode ends
+        connection.set_line_mode(false).await;
+        let mut buffer = [0u8; 16];
+        let n = connection.read(&mut buffer, Some(1000)).await.unwrap();
+        assert_eq!(&buffer[..n], b"partial");
+    }
+
+    #[test]
+    fn test_char_time() {
+        let mut config = port_config("COM1");
+        assert_eq!(config.char_time(), std::time::Duration::from_micros(86));
+
+        config.baud_rate = 9600;
+        config.parity = Parity::Even;
+        assert_eq!(config.char_time(), std::time::Duration::from_micros(1145));
+    }
+
+    #[test]
+    fn test_frame_format() {
+        let mut config = port_config("COM1");
+        assert_eq!(config.frame_format(), "8N1");
+
+        config.data_bits = DataBits::Seven;
+        config.parity = Parity::Even;
+        config.stop_bits = StopBits::Two;
+        assert_eq!(config.frame_format(), "7E2");
+    }
+
+    #[cfg(any(unix, windows))]
+    #[tokio::test]
+    async fn test_read_until_stops_when_accepted_or_late() {
+        use crate::serial::SerialConnection;
+        use std::time::Duration;
+        use tokio::io::AsyncWriteExt;
+        use tokio::time::Instant;
+
+        let Some((mut device, _slave, path)) = test_device() else {
+            return;
+        };
+        let connection = SerialConnection::new(port_config(&path)).await.unwrap();
+
+        device.write_all(b"login: ").await.unwrap();
+        let mut received = Vec::new();
+        let accepted = connection
+            .read_until(Instant::now() + Duration::from_secs(1), |chunk| {
+                received.extend_from_slice(chunk);
+                received.ends_with(b": ")
+            })
+            .await
+            .unwrap();
+        assert!(accepted);
+        assert_eq!(received, b"login: ");
+
+        // Nothing more arrives before the deadline
+        let started = Instant::now();
+        assert!(!connection.read_until(started + Duration::from_millis(100), |_| true).await.unwrap());
+        assert!(started.elapsed() >= Duration::from_millis(100));
+
+        // A line accepted early leaves the next one buffered
+        connection.set_line_mode(true).await;
+        device.write_all(b"one\ntwo\n").await.unwrap();
+        let mut lines = Vec::new();
+        let accepted = connection
+            .read_lines_until(Instant::now() + Duration::from_secs(1), |line| {
+                lines.push(line.text());
+                true
+            })
+            .await
+            .unwrap();
+        assert!(accepted);
+        assert_eq!(lines, ["one"]);
+        assert_eq!(connection.read_lines(10, Some(1000)).await.unwrap()[0].text(), "two");
+    }
+
+    #[cfg(any(unix, windows))]
+    #[tokio::test]
+    async fn test_read_until_idle_splits_on_silence() {
+        use crate::serial::SerialConnection;
+        use std::time::Duration;
+        use tokio::io::AsyncWriteExt;
+
+        let Some((mut device, _slave, path)) = test_device() else {
+            return;
+        };
+        let connection = SerialConnection::new(port_config(&path)).await.unwrap();
+
+        device.write_all(b"\x01\x03").await.unwrap();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        device.write_all(b"\x02\x00").await.unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        device.write_all(b"\x05").await.unwrap();
+
+        let idle = Duration::from_millis(100);
+        let first = connection.read_until_idle(64, idle, Some(1000)).await.unwrap();
+        assert_eq!(first, vec![0x01, 0x03, 0x02, 0x00]);
+        let second = connection.read_until_idle(64, idle, Some(1000)).await.unwrap();
+        assert_eq!(second, vec![0x05]);
+    }
+
+    #[test]
+    fn test_rx_buffer_keeps_chunk_boundaries() {
+        use crate::serial::reader::RxBuffer;
+
+        let mut rx = RxBuffer::new(16);
+        rx.push(b"abc");
+        rx.push(b"defg");
+        rx.push(b"hi");
+        assert_eq!(rx.chunk_count(), 3);
+
+        // Partial raw read leaves the remainder of the chunk in place
+        let mut out = [0u8; 2];
+        assert_eq!(rx.take_into(&mut out), 2);
+        assert_eq!(&out, b"ab");
+
+        let chunks = rx.take_chunks(10, 4);
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].data, b"c");
+        let chunks = rx.take_chunks(10, 100);
+        assert_eq!(chunks.iter().map(|c| c.data.clone()).collect::<Vec<_>>(), vec![b"defg".to_vec(), b"hi".to_vec()]);
+        assert!(rx.is_empty());
+    }
+
+    #[test]
+    fn test_rx_buffer_read_span() {
+        use crate::serial::reader::RxBuffer;
+        use chrono::{TimeDelta, Utc};
+
+        let first = Utc::now();
+        let second = first + TimeDelta::milliseconds(12);
+        let mut rx = RxBuffer::new(16);
+        rx.push_at(b"ab", first);
+        rx.push_at(b"cd", second);
+
+        let mut out = [0u8; 3];
+        let (n, span) = rx.take_into_timed(&mut out);
+        assert_eq!(n, 3);
+        let span = span.unwrap();
+        assert_eq!((span.first, span.last), (first, second));
+
+        // The remainder keeps the arrival time of its chunk
+        let (_, span) = rx.take_into_timed(&mut out);
+        assert_eq!(span.unwrap().first, second);
+        assert_eq!(rx.take_into_timed(&mut out), (0, None));
+    }
+
+    #[test]
+    fn test_rx_buffer_newest_age() {
+        use crate::serial::reader::RxBuffer;
+        use std::time::Duration;
+
+        let mut rx = RxBuffer::new(16);
+        assert_eq!(rx.newest_age(), None);
+        rx.push(b"old");
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(rx.newest_age().unwrap() >= Duration::from_millis(30));
+
+        // A partial read leaves the newest byte, and its age, in place
+        rx.push(b"new");
+        let mut out = [0u8; 4];
+        rx.take_into(&mut out);
+        assert!(rx.newest_age().unwrap() < Duration::from_millis(30));
+
+        rx.set_line_mode(true);
+        assert!(rx.newest_age().is_some());
+        rx.set_line_mode(false);
+        rx.take_into(&mut out);
+        assert_eq!(rx.newest_age(), None);
+    }
+
+    #[test]
+    fn test_rx_buffer_overflow_drops_oldest() {
+        use crate::serial::reader::RxBuffer;
+
+        let mut rx = RxBuffer::new(4);
+        rx.push(b"abc");
+        rx.push(b"def");
+        assert_eq!(rx.len(), 4);
+        assert_eq!(rx.dropped(), 2);
+
+        let chunks = rx.take_chunks(10, 100);
+        assert_eq!(chunks[0].data, b"c");
+        assert_eq!(chunks[1].data, b"def");
+    }
+
+    #[cfg(any(unix, windows))]
+    #[tokio::test]
+    async fn test_touch_refuses_open_port() {
+        use crate::serial::ConnectionManager;
+
+        let Some((_device, _slave, path)) = test_device() else {
+            return;
+        };
+        let manager = ConnectionManager::new();
+        manager.touch(&path).await.unwrap();
+
+        let id = manager.open(port_config(&path)).await.unwrap();
+        assert!(matches!(manager.touch(&path).await, Err(SerialError::ConnectionExists(_))));
+        manager.close(&id).await.unwrap();
+        manager.touch(&path).await.unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_exclusive_access() {
+        use crate::serial::SerialConnection;
+
+        let (_device, _slave, path) = pty_device();
+        let _exclusive = SerialConnection::open(port_config(&path), true).await.unwrap();
+        // Refused to everyone but root, whom TIOCEXCL does not stop
+        if unsafe { libc::geteuid() } == 0 {
+            eprintln!("Skipping the exclusive open check: running as root, which TIOCEXCL does not stop");
+        } else {
+            match SerialConnection::open(port_config(&path), true).await {
+                Ok(_) => panic!("second exclusive open succeeded"),
+                Err(e) => assert!(matches!(e, SerialError::PortLocked(_)), "{}", e),
+            }
+        }
+
+        let (_device, _slave, path) = pty_device();
+        let shared = SerialConnection::open(port_config(&path), false).await.unwrap();
+        SerialConnection::open(port_config(&path), false).await.unwrap();
+        assert!(shared.events().await[0].message.ends_with("open to other processes"));
+    }
+}
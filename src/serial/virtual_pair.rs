@@ -0,0 +1,126 @@
+//! Virtual null-modem pairs (Windows)
+//!
+//! com0com and VSPE create pairs of COM ports wired back to back: bytes
+//! written to one end are read from the other. Their ports show up in the
+//! `HARDWARE\DEVICEMAP\SERIALCOMM` registry key like any other, keyed by the
+//! kernel device that serves them. com0com names the two ends of pair N
+//! `\Device\com0com1N` and `\Device\com0com2N`, which gives away the peer.
+
+use std::collections::HashMap;
+
+/// A port served by a virtual serial driver
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VirtualPort {
+    /// Driver that created the port, `com0com` or `VSPE`
+    pub driver: &'static str,
+    /// Port at the other end of the pair, when the driver reveals it
+    pub peer: Option<String>,
+}
+
+/// Find virtual ports among `(kernel device, port name)` pairs from SERIALCOMM
+pub fn virtual_ports(entries: &[(String, String)]) -> HashMap<String, VirtualPort> {
+    let mut ends: HashMap<(char, String), String> = HashMap::new();
+    let mut ports = HashMap::new();
+
+    for (device, port) in entries {
+        let device = device.to_lowercase();
+        let name = device.rsplit('\\').next().unwrap_or(&device);
+        if let Some(end) = name.strip_prefix("com0com") {
+            let mut chars = end.chars();
+            if let (Some(side @ ('1' | '2')), pair) = (chars.next(), chars.as_str()) {
+                ends.insert((side, pair.to_string()), port.clone());
+            }
+            ports.insert(port.clone(), VirtualPort { driver: "com0com", peer: None });
+        } else if name.contains("vspe") {
+            // VSPE pairs are set up in its own configuration, not visible here
+            ports.insert(port.clone(), VirtualPort { driver: "VSPE", peer: None });
+        }
+    }
+
+    for ((side, pair), port) in &ends {
+        let other = if *side == '1' { '2' } else { '1' };
+        if let (Some(info), Some(peer)) = (ports.get_mut(port), ends.get(&(other, pair.clone()))) {
+            info.peer = Some(peer.clone());
+        }
+    }
+
+    ports
+}
+
+/// Virtual ports of this machine, keyed by port name
+pub fn detect() -> HashMap<String, VirtualPort> {
+    virtual_ports(&platform::serialcomm_entries())
+}
+
+/// Both ends of the first complete virtual pair, for end-to-end tests
+pub fn first_pair() -> Option<(String, String)> {
+    let mut pairs: Vec<(String, String)> = detect()
+        .into_iter()
+        .filter_map(|(port, info)| info.peer.map(|peer| (port, peer)))
+        .filter(|(port, peer)| port < peer)
+        .collect();
+    pairs.sort();
+    pairs.into_iter().next()
+}
+
+#[cfg(windows)]
+mod platform {
+    use windows_sys::Win32::Foundation::ERROR_SUCCESS;
+    use windows_sys::Win32::System::Registry::{
+        RegCloseKey, RegEnumValueW, RegOpenKeyExW, HKEY, HKEY_LOCAL_MACHINE, KEY_READ, REG_SZ,
+    };
+
+    /// `(kernel device, port name)` values of `HKLM\HARDWARE\DEVICEMAP\SERIALCOMM`
+    pub(super) fn serialcomm_entries() -> Vec<(String, String)> {
+        let mut entries = Vec::new();
+        let path: Vec<u16> = "HARDWARE\\DEVICEMAP\\SERIALCOMM\0".encode_utf16().collect();
+        let mut key: HKEY = std::ptr::null_mut();
+
+        // SAFETY: buffers are passed with their lengths and the key is closed below
+        unsafe {
+            if RegOpenKeyExW(HKEY_LOCAL_MACHINE, path.as_ptr(), 0, KEY_READ, &mut key) != ERROR_SUCCESS {
+                return entries;
+            }
+
+            for index in 0.. {
+                let mut name = [0u16; 256];
+                let mut name_len = name.len() as u32;
+                let mut data = [0u16; 256];
+                let mut data_len = std::mem::size_of_val(&data) as u32;
+                let mut kind = 0;
+                let result = RegEnumValueW(
+                    key,
+                    index,
+                    name.as_mut_ptr(),
+                    &mut name_len,
+                    std::ptr::null(),
+                    &mut kind,
+                    data.as_mut_ptr().cast(),
+                    &mut data_len,
+                );
+                if result != ERROR_SUCCESS {
+                    break;
+                }
+                if kind != REG_SZ {
+                    continue;
+                }
+
+                let device = String::from_utf16_lossy(&name[..name_len as usize]);
+                let data = &data[..data_len as usize / 2];
+                let port = String::from_utf16_lossy(data).trim_end_matches('\0').to_string();
+                entries.push((device, port));
+            }
+
+            RegCloseKey(key);
+        }
+
+        entries
+    }
+}
+
+#[cfg(not(windows))]
+mod platform {
+    pub(super) fn serialcomm_entries() -> Vec<(String, String)> {
+        Vec::new()
+    }
+}
@@ -0,0 +1,106 @@
+//! Ordered write queue
+//!
+//! Writes submitted to a connection at the same time go out whole and in the
+//! order they were submitted. Each takes a turn, waiting behind the writes
+//! queued before it, and may give up if its turn does not come in time.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use tokio::sync::{Mutex, MutexGuard};
+
+use super::error::SerialError;
+
+/// First-come, first-served turns at writing to a connection
+#[derive(Debug, Default)]
+pub struct WriteQueue {
+    /// Handed out in the order it was asked for
+    turn: Mutex<()>,
+    /// Writes waiting for their turn or taking it
+    queued: AtomicUsize,
+}
+
+/// A write's turn, lasting until dropped
+#[derive(Debug)]
+pub struct WriteTurn<'a> {
+    _turn: MutexGuard<'a, ()>,
+    queue: &'a WriteQueue,
+    /// Writes queued before this one
+    pub ahead: usize,
+    /// Time spent waiting for the turn
+    pub waited: Duration,
+}
+
+impl WriteQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Wait for a turn behind the writes already queued, for at most `timeout`
+    pub async fn enter(&self, timeout: Option<Duration>) -> Result<WriteTurn<'_>, SerialError> {
+        let started = Instant::now();
+        let ahead = self.queued.fetch_add(1, Ordering::SeqCst);
+        let turn = match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, self.turn.lock()).await.ok(),
+            None => Some(self.turn.lock().await),
+        };
+        let Some(turn) = turn else {
+            self.queued.fetch_sub(1, Ordering::SeqCst);
+            return Err(SerialError::WriteTimeout);
+        };
+        Ok(WriteTurn { _turn: turn, queue: self, ahead, waited: started.elapsed() })
+    }
+
+    /// Writes waiting for their turn or taking it
+    pub fn len(&self) -> usize {
+        self.queued.load(Ordering::SeqCst)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Drop for WriteTurn<'_> {
+    fn drop(&mut self) {
+        self.queue.queued.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_write_queue_order() {
+        let queue = Arc::new(WriteQueue::new());
+        let order = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let first = queue.enter(None).await.unwrap();
+        assert_eq!(first.ahead, 0);
+
+        let mut writers = Vec::new();
+        for i in 0..3 {
+            let (queue, order) = (Arc::clone(&queue), Arc::clone(&order));
+            writers.push(tokio::spawn(async move {
+                let turn = queue.enter(None).await.unwrap();
+                order.lock().unwrap().push((i, turn.ahead));
+            }));
+            // Submitted one after another
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(queue.len(), 4);
+
+        // Gives up waiting, leaving the queue as it was
+        let late = queue.enter(Some(Duration::from_millis(20))).await;
+        assert!(matches!(late, Err(SerialError::WriteTimeout)));
+        assert_eq!(queue.len(), 4);
+
+        drop(first);
+        for writer in writers {
+            writer.await.unwrap();
+        }
+        assert_eq!(*order.lock().unwrap(), vec![(0, 1), (1, 2), (2, 3)]);
+        assert!(queue.is_empty());
+    }
+}
@@ -0,0 +1,229 @@
+//! Write rules
+//!
+//! Outgoing data can be checked against regular expressions before it is
+//! written, per port: a deny-list refuses writes matching any of its patterns,
+//! such as `erase|format` on a PLC's port, and an allow-list refuses writes
+//! matching none of its patterns. Each write is checked whole, as the tool
+//! hands it over, so the rules catch mistaken commands rather than data split
+//! up on purpose to slip past them.
+//!
+//! A rule covers the port at exactly its `port` path, where `*` stands for
+//! any run of characters and `?` for any one, as in `/dev/ttyUSB*`; a rule
+//! without a port covers every port.
+//!
+//! Refused writes are appended to [`WRITE_AUDIT_FILE`] in the state
+//! directory, one JSON object per line, so they can be reviewed after the
+//! server has stopped; the file is only ever appended to.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use regex::bytes::Regex;
+use serde::{Deserialize, Serialize};
+
+use super::error::SerialError;
+
+/// File in the state directory refused writes are appended to
+pub const WRITE_AUDIT_FILE: &str = "write_audit.jsonl";
+
+/// Allow and deny patterns for the ports matching a path or glob
+#[derive(Debug, Clone)]
+pub struct WriteRule {
+    /// Path or glob of the ports covered, or every port if `None`
+    port: Option<String>,
+    allow: Vec<Regex>,
+    deny: Vec<Regex>,
+    /// Refuse every write, whatever the patterns say
+    deny_all: bool,
+}
+
+impl WriteRule {
+    /// Rule for the ports matching the path or glob `port`, or every port if it is empty
+    pub fn new(port: &str, allow: &[String], deny: &[String]) -> Result<Self, SerialError> {
+        let compile = |patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|pattern| {
+                    Regex::new(pattern).map_err(|e| SerialError::InvalidConfig(format!("Invalid write rule pattern {:?}: {}", pattern, e)))
+                })
+                .collect::<Result<Vec<_>, _>>()
+        };
+        Ok(Self { port: port_pattern(port), allow: compile(allow)?, deny: compile(deny)?, deny_all: false })
+    }
+
+    /// Rule refusing every write to the ports it covers
+    pub fn deny_all(port: &str) -> Self {
+        Self { port: port_pattern(port), allow: Vec::new(), deny: Vec::new(), deny_all: true }
+    }
+
+    /// Whether the rule covers the port named `port`
+    pub fn applies_to(&self, port: &str) -> bool {
+        self.port.as_deref().is_none_or(|glob| glob_matches(glob, port))
+    }
+
+    /// Why `data` may not be written, if it may not
+    pub fn check(&self, data: &[u8]) -> Result<(), String> {
+        if self.deny_all {
+            return Err("is denied, every write to this port is refused".to_string());
+        }
+        if let Some(pattern) = self.deny.iter().find(|pattern| pattern.is_match(data)) {
+            return Err(format!("matches deny pattern {:?}", pattern.as_str()));
+        }
+        if !self.allow.is_empty() && !self.allow.iter().any(|pattern| pattern.is_match(data)) {
+            return Err("matches no allow pattern".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// A write the rules refused, as recorded in the audit file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RefusedWrite {
+    pub timestamp: DateTime<Utc>,
+    pub connection_id: String,
+    pub port: String,
+    /// MCP client the connection was opened for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client: Option<String>,
+    pub bytes: usize,
+    pub reason: String,
+    /// The refused data, hex encoded
+    pub data: String,
+}
+
+/// Append-only JSONL file of refused writes
+#[derive(Debug)]
+pub struct WriteAudit {
+    path: PathBuf,
+    /// Keeps lines from different connections whole
+    append: std::sync::Mutex<()>,
+}
+
+impl WriteAudit {
+    /// Audit into [`WRITE_AUDIT_FILE`] under `state_directory`, created on the first refusal
+    pub fn new(state_directory: &Path) -> Self {
+        Self { path: state_directory.join(WRITE_AUDIT_FILE), append: std::sync::Mutex::new(()) }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append `refused` as one line
+    pub fn record(&self, refused: &RefusedWrite) -> std::io::Result<()> {
+        let mut line = serde_json::to_vec(refused)?;
+        line.push(b'\n');
+        let _append = self.append.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(parent) = self.path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = std::fs::OpenOptions::new().create(true).append(true).open(&self.path)?;
+        file.write_all(&line)?;
+        file.sync_data()
+    }
+}
+
+/// The rule's port glob, `None` if it is empty
+fn port_pattern(glob: &str) -> Option<String> {
+    (!glob.is_empty()).then(|| glob.to_string())
+}
+
+/// Whether `glob` matches the whole of `port`
+fn glob_matches(glob: &str, port: &str) -> bool {
+    let glob: Vec<char> = glob.chars().collect();
+    let port: Vec<char> = port.chars().collect();
+    let (mut g, mut p) = (0, 0);
+    // Where the last `*` was, and how much of the port it has taken so far
+    let mut star = None;
+    while p < port.len() {
+        match glob.get(g) {
+            Some('*') => {
+                star = Some((g, p));
+                g += 1;
+            }
+            Some(&c) if c == '?' || c == port[p] => {
+                g += 1;
+                p += 1;
+            }
+            _ => match star {
+                // Let the last `*` take one more character and try again
+                Some((star_g, star_p)) => {
+                    star = Some((star_g, star_p + 1));
+                    g = star_g + 1;
+                    p = star_p + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    glob[g..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patterns(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(|pattern| pattern.to_string()).collect()
+    }
+
+    #[test]
+    fn test_write_rule() {
+        let plc = WriteRule::new("/dev/ttyUSB1", &[], &patterns(&["(?i)erase|format"])).unwrap();
+        assert!(plc.applies_to("/dev/ttyUSB1"));
+        assert!(!plc.applies_to("/dev/ttyUSB10"));
+        assert!(!plc.applies_to("/dev/ttyACM0"));
+        assert!(plc.check(b"READ D100\r\n").is_ok());
+        assert_eq!(plc.check(b"FORMAT ALL\r\n").unwrap_err(), "matches deny pattern \"(?i)erase|format\"");
+        assert!(plc.check(b"\x02erase\x03").is_err());
+
+        // Only queries get through, and never one the deny-list names
+        let queries = WriteRule::new("", &patterns(&[r"^\?", r"^AT\+\w+\?"]), &patterns(&["CMGD"])).unwrap();
+        assert!(queries.applies_to("COM3"));
+        assert!(queries.check(b"?STATUS").is_ok());
+        assert!(queries.check(b"AT+CSQ?\r").is_ok());
+        assert_eq!(queries.check(b"AT+CSQ=1\r").unwrap_err(), "matches no allow pattern");
+        assert!(queries.check(b"AT+CMGD?\r").is_err());
+
+        let adapters = WriteRule::deny_all("/dev/ttyUSB?");
+        assert!(adapters.applies_to("/dev/ttyUSB7"));
+        assert!(!adapters.applies_to("/dev/ttyUSB10"));
+        let by_id = WriteRule::deny_all("/dev/serial/by-id/usb-FTDI_*-if00-port0");
+        assert!(by_id.applies_to("/dev/serial/by-id/usb-FTDI_FT232R_A1B2-if00-port0"));
+        assert!(!by_id.applies_to("/dev/serial/by-id/usb-FTDI_FT232R_A1B2-if01-port0"));
+        assert!(!WriteRule::deny_all("COM1").applies_to("COM12"));
+        assert!(glob_matches("*", "/dev/ttyS0"));
+        assert!(glob_matches("/dev/tty*USB*", "/dev/ttyXRUSB0"));
+        assert!(glob_matches("COM(1)", "COM(1)"));
+        assert!(!glob_matches("COM(1)", "COM1"));
+        assert!(!glob_matches("/dev/ttyUSB*0", "/dev/ttyUSB01"));
+
+        assert!(WriteRule::new("", &patterns(&["("]), &[]).is_err());
+        assert!(WriteRule::deny_all("").check(b"").is_err());
+    }
+
+    #[test]
+    fn test_write_audit() {
+        let dir = tempfile::tempdir().unwrap();
+        let audit = WriteAudit::new(&dir.path().join("state"));
+        let refused = |data: &[u8]| RefusedWrite {
+            timestamp: Utc::now(),
+            connection_id: "c1".to_string(),
+            port: "/dev/ttyUSB1".to_string(),
+            client: None,
+            bytes: data.len(),
+            reason: "matches deny pattern \"erase\"".to_string(),
+            data: hex::encode_upper(data),
+        };
+        let first = refused(b"erase");
+        let second = refused(b"erase all");
+        audit.record(&first).unwrap();
+        audit.record(&second).unwrap();
+
+        let contents = std::fs::read_to_string(audit.path()).unwrap();
+        let lines: Vec<RefusedWrite> = contents.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(lines, vec![first, second]);
+        assert!(!contents.contains("client"));
+    }
+}
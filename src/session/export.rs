@@ -0,0 +1,149 @@
+//! Session export and import
+//!
+//! A session can be exported as a JSON document holding its settings,
+//! statistics and event log, along with the transcript and events of its
+//! connection, and imported on another server to carry on from there. The
+//! document names its format and version, so one written by a newer server is
+//! refused rather than half understood.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::store::SavedSession;
+use crate::error::{Result, SerialError};
+use crate::serial::{ConnectionEvent, ConnectionHistory, Direction, TranscriptEntry};
+
+/// Format name an export document carries
+pub const EXPORT_FORMAT: &str = "serial-mcp-session";
+
+/// Current export format version
+pub const EXPORT_VERSION: u32 = 1;
+
+/// A session as exported, to be imported elsewhere
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionExport {
+    pub format: String,
+    pub version: u32,
+    pub exported_at: DateTime<Utc>,
+    /// ID the session had where it was exported
+    pub session_id: String,
+    pub session: SavedSession,
+    #[serde(default)]
+    pub history: ExportedHistory,
+}
+
+/// Transcript and events of the session's connection
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ExportedHistory {
+    pub transcript: Vec<ExportedTraffic>,
+    pub events: Vec<ConnectionEvent>,
+}
+
+/// Transcript entry with its exact bytes as hex, and as text to read
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportedTraffic {
+    pub timestamp: DateTime<Utc>,
+    pub direction: Direction,
+    pub hex: String,
+    /// Not read back on import
+    #[serde(default)]
+    pub text: String,
+}
+
+impl SessionExport {
+    pub fn new(session_id: String, session: SavedSession, history: ExportedHistory) -> Self {
+        Self {
+            format: EXPORT_FORMAT.to_string(),
+            version: EXPORT_VERSION,
+            exported_at: Utc::now(),
+            session_id,
+            session,
+            history,
+        }
+    }
+
+    /// Read an export document, refusing other documents and newer formats
+    pub fn parse(document: &str) -> Result<Self> {
+        let export: SessionExport = serde_json::from_str(document)
+            .map_err(|e| SerialError::InvalidConfig(format!("Not a session export: {}", e)))?;
+        if export.format != EXPORT_FORMAT {
+            return Err(SerialError::InvalidConfig(format!("Not a session export: format is {:?}", export.format)));
+        }
+        if export.version > EXPORT_VERSION {
+            return Err(SerialError::InvalidConfig(format!(
+                "Session export version {} was written by a newer server; this one reads up to version {}",
+                export.version, EXPORT_VERSION
+            )));
+        }
+        Ok(export)
+    }
+}
+
+impl ExportedHistory {
+    pub fn new(transcript: Vec<TranscriptEntry>, events: Vec<ConnectionEvent>) -> Self {
+        let transcript = transcript
+            .into_iter()
+            .map(|entry| ExportedTraffic {
+                timestamp: entry.timestamp,
+                direction: entry.direction,
+                hex: hex::encode(&entry.data),
+                text: String::from_utf8_lossy(&entry.data).into_owned(),
+            })
+            .collect();
+        Self { transcript, events }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transcript.is_empty() && self.events.is_empty()
+    }
+
+    /// The history to carry on in the imported session's connection
+    pub fn into_history(self) -> Result<ConnectionHistory> {
+        let transcript = self.transcript
+            .into_iter()
+            .map(|traffic| {
+                let data = hex::decode(&traffic.hex)
+                    .map_err(|e| SerialError::InvalidConfig(format!("Transcript entry at {} is not hex: {}", traffic.timestamp, e)))?;
+                Ok(TranscriptEntry { timestamp: traffic.timestamp, direction: traffic.direction, data })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(ConnectionHistory::from_parts(transcript, self.events))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::session::{SessionConfig, SessionManager};
+
+    #[tokio::test]
+    async fn test_export_import() {
+        let here = SessionManager::new(Config::default());
+        let config = SessionConfig { name: Some("gps".to_string()), port_name: "/dev/ttyUSB0".to_string(), ..Default::default() };
+        let session_id = here.create_session(config).await.unwrap();
+        here.record_session_send(&session_id, 12).await.unwrap();
+        let traffic = TranscriptEntry { timestamp: Utc::now(), direction: Direction::Rx, data: vec![0x24, 0xff] };
+        let history = ExportedHistory::new(vec![traffic], Vec::new());
+        let export = SessionExport::new(session_id.clone(), here.export_session(&session_id).await.unwrap(), history);
+        let document = serde_json::to_string(&export).unwrap();
+
+        let there = SessionManager::new(Config::default());
+        let export = SessionExport::parse(&document).unwrap();
+        let history = export.history.into_history().unwrap();
+        assert_eq!(history.recent_transcript(usize::MAX)[0].data, vec![0x24, 0xff]);
+        let imported = there.import_session(export.session, "Imported".to_string()).await.unwrap();
+        assert_ne!(imported, session_id);
+        let info = there.get_session_info(&imported).await.unwrap();
+        assert_eq!(info.config.name.as_deref(), Some("gps"));
+        assert_eq!(info.stats.bytes_sent, 12);
+        let (events, _) = there.session_events("gps").await.unwrap();
+        let messages: Vec<&str> = events.iter().map(|event| event.message.as_str()).collect();
+        assert_eq!(messages, ["Created for /dev/ttyUSB0", "Imported"]);
+
+        let mut newer: serde_json::Value = serde_json::from_str(&document).unwrap();
+        newer["version"] = (EXPORT_VERSION + 1).into();
+        assert!(SessionExport::parse(&newer.to_string()).is_err());
+        assert!(SessionExport::parse(r#"{"sessions": {}}"#).is_err());
+    }
+}
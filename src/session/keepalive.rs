@@ -0,0 +1,163 @@
+//! Session keepalive probes
+//!
+//! A session with a keepalive checks now and then that its device still
+//! answers: it sends a probe and waits for a reply, or without a probe reads
+//! the modem status lines. Probes are skipped while data keeps arriving on
+//! their own, and while the connection has unread data or an exchange running,
+//! so they never take a reply meant for someone else.
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Result, SerialError};
+use crate::serial::{LocalSerialError, ModemLine, SerialConnection};
+
+/// How a session checks that its device still answers
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeepaliveConfig {
+    /// Milliseconds between checks
+    pub interval_ms: u64,
+    /// Text sent to the device; without one only the modem status lines are checked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub probe: Option<String>,
+    /// Text the reply has to contain; any reply does without one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expect: Option<String>,
+    /// Milliseconds to wait for the reply
+    pub timeout_ms: u64,
+    /// Status line the device has to keep asserted: "cts", "dsr", "cd" or "ri"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line: Option<ModemLine>,
+}
+
+impl KeepaliveConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.interval_ms == 0 || self.timeout_ms == 0 {
+            return Err(SerialError::InvalidConfig("Keepalive interval and timeout must be above 0".to_string()));
+        }
+        if self.probe.as_deref().is_some_and(str::is_empty) {
+            return Err(SerialError::InvalidConfig("Keepalive probe cannot be empty".to_string()));
+        }
+        if self.expect.is_some() && self.probe.is_none() {
+            return Err(SerialError::InvalidConfig("A keepalive reply can only be expected to a probe".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Check the device answers, returning why not if it does not
+    pub async fn check(&self, connection: &SerialConnection) -> std::result::Result<(), String> {
+        // Virtual ports have no status lines, so a probe alone does without them
+        if self.line.is_some() || self.probe.is_none() {
+            let lines = connection.modem_lines().await.map_err(|e| format!("Modem lines unreadable: {}", e))?;
+            if let Some(line) = self.line.filter(|line| !lines.is_asserted(*line)) {
+                return Err(format!("{} not asserted", line.to_string().to_uppercase()));
+            }
+        }
+        let Some(probe) = &self.probe else {
+            return Ok(());
+        };
+
+        let heard_from = connection.freshness().await.since_last_rx_ms.is_some_and(|ms| ms < self.interval_ms);
+        let Some(_exchange) = connection.try_begin_exchange() else {
+            return Ok(());
+        };
+        if heard_from || connection.buffered_bytes().await > 0 {
+            return Ok(());
+        }
+
+        connection.write(probe.as_bytes()).await.map_err(|e| format!("Probe not sent: {}", e))?;
+        let deadline = tokio::time::Instant::now() + Duration::from_millis(self.timeout_ms);
+        let mut reply = Vec::new();
+        let mut buffer = [0u8; 256];
+        loop {
+            let remaining = deadline.saturating_duration_since(tokio::time::Instant::now());
+            match connection.read(&mut buffer, Some(remaining.as_millis() as u64)).await {
+                Ok(n) => reply.extend_from_slice(&buffer[..n]),
+                Err(LocalSerialError::ReadTimeout) => break,
+                Err(e) => return Err(format!("Reply not read: {}", e)),
+            }
+            match &self.expect {
+                Some(expect) if !String::from_utf8_lossy(&reply).contains(expect.as_str()) => {}
+                _ if !reply.is_empty() => return Ok(()),
+                _ => {}
+            }
+            if remaining.is_zero() {
+                break;
+            }
+        }
+        Err(match (&self.expect, reply.is_empty()) {
+            (Some(expect), false) => format!("Reply to probe lacked {:?} after {}ms", expect, self.timeout_ms),
+            _ => format!("No reply to probe within {}ms", self.timeout_ms),
+        })
+    }
+}
+
+impl std::fmt::Display for KeepaliveConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "every {}ms", self.interval_ms)?;
+        if let Some(line) = self.line {
+            write!(f, ", {} asserted", line.to_string().to_uppercase())?;
+        }
+        if let Some(probe) = &self.probe {
+            write!(f, ", probe {:?}", probe)?;
+            if let Some(expect) = &self.expect {
+                write!(f, " expecting {:?}", expect)?;
+            }
+            write!(f, " within {}ms", self.timeout_ms)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::serial::{BackpressurePolicy, ConnectionConfig, DataBits, FlowControl, Parity, StopBits};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_serial::SerialPort;
+
+    #[tokio::test]
+    async fn test_keepalive_probe() {
+        let (mut device, port) = tokio_serial::SerialStream::pair().unwrap();
+        let connection = SerialConnection::new(ConnectionConfig {
+            port: port.name().unwrap(),
+            baud_rate: 115200,
+            data_bits: DataBits::Eight,
+            stop_bits: StopBits::One,
+            parity: Parity::None,
+            flow_control: FlowControl::None,
+            backpressure: BackpressurePolicy::Drop,
+        })
+        .await
+        .unwrap();
+        let keepalive = KeepaliveConfig {
+            interval_ms: 1000,
+            probe: Some("AT\r".to_string()),
+            expect: Some("OK".to_string()),
+            timeout_ms: 200,
+            line: None,
+        };
+        assert!(keepalive.validate().is_ok());
+        assert!(KeepaliveConfig { probe: None, ..keepalive.clone() }.validate().is_err());
+
+        // Nothing answers
+        assert!(keepalive.check(&connection).await.unwrap_err().contains("No reply"));
+
+        let mut probe = [0u8; 3];
+        device.read_exact(&mut probe).await.unwrap();
+        assert_eq!(&probe, b"AT\r");
+        let answer = async {
+            device.read_exact(&mut probe).await.unwrap();
+            device.write_all(b"\r\nOK\r\n").await.unwrap();
+        };
+        let (checked, ()) = tokio::join!(keepalive.check(&connection), answer);
+        assert!(checked.is_ok());
+
+        // Data arrived within the interval, so no probe is needed
+        device.write_all(b"+EVENT\r\n").await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let _ = connection.read(&mut probe, Some(100)).await;
+        assert!(keepalive.check(&connection).await.is_ok());
+    }
+}
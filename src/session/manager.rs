@@ -1,18 +1,31 @@
 //! Session manager implementation
 //! 
 //! Manages multiple serial sessions with lifecycle management, cleanup, and monitoring.
+//! Connections opened by the tools are tracked as sessions under their
+//! connection ID, so session limits, port restrictions and statistics apply
+//! to all traffic, not only to sessions created here.
+//!
+//! Each session keeps a log of its lifecycle events. The logs of the most
+//! recently removed sessions are kept a while longer, so it can still be told
+//! why a session went away.
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::time::Duration;
 use tokio::sync::RwLock;
-use tokio::time::Interval;
 use tracing::{debug, info, warn, error};
 
 use crate::error::{SerialError, SessionError, Result};
 use crate::config::Config;
-use crate::serial::{SerialConnection, ConnectionManager};
-use super::session::{SerialSession, SessionState, SessionConfig, SessionInfo};
+use crate::serial::{DeviceIdentity, ReceiveFilter, SerialConnection, ConnectionManager};
+use super::session::{format_receive_filters, SerialSession, SessionState, SessionConfig, SessionEvent, SessionInfo};
+use super::store::{SavedSession, SavedSessions, SESSIONS_FILE};
+use crate::utils::SessionIdGenerator;
+use crate::state::save_state;
+use crate::protocol::FramerSpec;
+
+/// Number of removed sessions whose event log is kept
+const ENDED_SESSION_LOGS: usize = 32;
 
 /// Session manager for handling multiple serial sessions
 #[derive(Debug)]
@@ -26,8 +39,11 @@ pub struct SessionManager {
     /// Configuration
     config: Config,
     
-    /// Cleanup interval timer
-    cleanup_interval: Option<Interval>,
+    /// File sessions are saved to, if they outlive the server
+    state_path: Option<PathBuf>,
+    
+    /// Event logs of removed sessions, oldest first
+    ended: RwLock<VecDeque<(String, Vec<SessionEvent>)>>,
 }
 
 impl SessionManager {
@@ -37,99 +53,262 @@ impl SessionManager {
             sessions: Arc::new(RwLock::new(HashMap::new())),
             connection_manager: Arc::new(ConnectionManager::new()),
             config,
-            cleanup_interval: None,
+            state_path: None,
+            ended: RwLock::new(VecDeque::new()),
         }
     }
 
-    /// Start the session manager (begins cleanup task)
-    pub async fn start(&mut self) -> Result<()> {
-        info!("Starting session manager");
-        
-        // Start cleanup task
-        let cleanup_interval_secs = 60; // Run cleanup every minute
-        let mut interval = tokio::time::interval(Duration::from_secs(cleanup_interval_secs));
-        
-        let sessions_clone = Arc::clone(&self.sessions);
-        let max_idle_seconds = self.config.server.connection_timeout_seconds as i64;
-        
-        tokio::spawn(async move {
-            loop {
-                interval.tick().await;
-                Self::cleanup_idle_sessions(&sessions_clone, max_idle_seconds).await;
-            }
-        });
-        
-        // Store a new interval for potential cleanup later (though not actually used)
-        self.cleanup_interval = Some(tokio::time::interval(Duration::from_secs(cleanup_interval_secs)));
-        Ok(())
+    /// Save sessions under `state_directory` and restore those saved by the last run
+    ///
+    /// Restored sessions are suspended; their ports are not opened until they are connected.
+    /// If the saved sessions cannot be read, nothing is saved this run, so the file is kept as it is.
+    pub fn with_persistence(mut self, state_directory: &Path) -> Self {
+        let path = state_directory.join(SESSIONS_FILE);
+        let Some(saved) = SavedSessions::load(&path) else {
+            warn!("Sessions will not be saved this run, leaving {} untouched", path.display());
+            return self;
+        };
+        if !saved.sessions.is_empty() {
+            info!("Restoring {} saved session(s)", saved.sessions.len());
+        }
+        let sessions = saved.sessions.into_iter()
+            .map(|(session_id, saved)| (session_id.clone(), SerialSession::restore(session_id, saved)))
+            .collect();
+        self.sessions = Arc::new(RwLock::new(sessions));
+        self.state_path = Some(path);
+        self
     }
 
-    /// Create a new session with the given configuration
-    pub async fn create_session(&self, config: SessionConfig) -> Result<String> {
-        // Validate configuration
-        self.validate_session_config(&config)?;
+    /// Open session connections through a shared connection manager, so the tools can use them
+    pub fn with_connection_manager(mut self, connection_manager: Arc<ConnectionManager>) -> Self {
+        self.connection_manager = connection_manager;
+        self
+    }
+
+    /// Remove sessions left without a connection for longer than the idle timeout
+    ///
+    /// Suspended sessions wait to be resumed and are kept. Returns the removed
+    /// sessions; their saved copies go too, and the others' saved traffic
+    /// statistics are brought up to date.
+    pub async fn reap_idle_sessions(&self) -> Vec<SessionInfo> {
+        let reaped = match self.config.server.session_idle_timeout_seconds {
+            0 => Vec::new(),
+            timeout => self.cleanup_idle_sessions(timeout as i64).await,
+        };
+        if let Some(path) = &self.state_path {
+            Self::save_sessions(&self.sessions, path, !reaped.is_empty()).await;
+        }
+        reaped
+    }
+
+    /// Check a session with this configuration may be opened: its settings,
+    /// the port restrictions, the session limit and port sharing
+    pub async fn admit(&self, config: &SessionConfig) -> Result<()> {
+        self.validate_session_config(config)?;
         
-        // Check session limits
-        let sessions = self.sessions.read().await;
-        if sessions.len() >= self.config.server.max_connections {
+        self.forget_closed_connections().await;
+        if self.sessions.read().await.len() >= self.config.server.max_connections {
             return Err(SerialError::SessionLimitExceeded(self.config.server.max_connections));
         }
-        drop(sessions);
         
-        // Check if port is already in use (if port sharing is disabled)
-        if !self.config.serial.allow_port_sharing {
-            if self.is_port_in_use(&config.port_name).await {
-                return Err(SerialError::ConnectionExists(config.port_name.clone()));
+        if !self.config.serial.allow_port_sharing && self.is_port_in_use(&config.port_name).await {
+            return Err(SerialError::ConnectionExists(config.port_name.clone()));
+        }
+        Ok(())
+    }
+
+    /// Check no other session goes by the name given in `config`
+    async fn check_name_free(&self, config: &SessionConfig) -> Result<()> {
+        if let Some(name) = &config.name {
+            if self.sessions.read().await.values().any(|s| s.name() == Some(name.as_str())) {
+                return Err(SerialError::SessionExists(name.clone()));
             }
         }
+        Ok(())
+    }
+
+    /// Create a new session with the given configuration
+    pub async fn create_session(&self, config: SessionConfig) -> Result<String> {
+        self.admit(&config).await?;
+        self.check_name_free(&config).await?;
         
         // Create new session
-        let session = SerialSession::new(config);
+        let mut session = SerialSession::new(config);
+        session.record_event(format!("Created for {}", session.port_name()));
         let session_id = session.id().to_string();
         
         debug!("Creating session {} for port {}", session_id, session.port_name());
         
         // Add to sessions map
-        let mut sessions = self.sessions.write().await;
-        sessions.insert(session_id.clone(), session);
+        self.sessions.write().await.insert(session_id.clone(), session);
+        self.save().await;
         
         info!("Session {} created successfully", session_id);
         Ok(session_id)
     }
 
+    /// Add a session exported elsewhere under a new ID, suspended until connected
+    ///
+    /// `origin` is recorded as its first event here.
+    pub async fn import_session(&self, saved: SavedSession, origin: String) -> Result<String> {
+        self.admit(&saved.config).await?;
+        self.check_name_free(&saved.config).await?;
+        
+        let mut session = SerialSession::from_saved(SessionIdGenerator::generate(), saved);
+        session.record_event(origin);
+        let session_id = session.id().to_string();
+        self.sessions.write().await.insert(session_id.clone(), session);
+        self.save().await;
+        
+        info!("Session {} imported", session_id);
+        Ok(session_id)
+    }
 
-    /// Connect a session to its serial port
-    pub async fn connect_session(&self, session_id: &str) -> Result<()> {
-        let mut sessions = self.sessions.write().await;
-        let session = sessions.get_mut(session_id)
+    /// What a session would be saved as, to export it
+    pub async fn export_session(&self, session_id: &str) -> Result<SavedSession> {
+        let sessions = self.sessions.read().await;
+        let session = sessions.get(session_id)
             .ok_or_else(|| SerialError::SessionNotFound(session_id.to_string()))?;
+        Ok(session.saved())
+    }
+
+    /// Track a connection opened directly as a session under the connection's ID
+    ///
+    /// The session goes away when the connection is closed, see [`Self::detach`].
+    pub async fn attach(&self, connection: Arc<SerialConnection>) -> Result<String> {
+        let session_id = connection.id().to_string();
+        let mut session = SerialSession::with_id(session_id.clone(), SessionConfig::from_connection(connection.config(), &self.config.serial));
+        session.set_connection(connection)?;
+        session.record_event(format!("Opened {} with open", session.port_name()));
         
-        if session.has_connection() {
-            return Err(SerialError::ConnectionExists("Session already connected".to_string()));
+        debug!("Tracking connection {} on {} as a session", session_id, session.port_name());
+        self.sessions.write().await.insert(session_id.clone(), session);
+        Ok(session_id)
+    }
+
+    /// Forget a closed connection: the session tracking it is removed, and a
+    /// session created with [`Self::create_session`] is left disconnected
+    pub async fn detach(&self, connection_id: &str) {
+        let mut sessions = self.sessions.write().await;
+        if let Some(mut session) = sessions.remove(connection_id) {
+            debug!("Connection {} closed, removing its session", connection_id);
+            drop(sessions);
+            session.record_event("Connection closed, session removed");
+            session.close();
+            self.retire(session).await;
+            return;
+        }
+        for session in sessions.values_mut().filter(|s| s.connection_id() == Some(connection_id)) {
+            debug!("Connection {} closed, disconnecting session {}", connection_id, session.id());
+            session.remove_connection();
+            session.record_event(format!("Connection {} closed", connection_id));
+        }
+    }
+
+    /// ID of the session with this ID or name
+    pub async fn resolve(&self, id_or_name: &str) -> Result<String> {
+        let sessions = self.sessions.read().await;
+        if sessions.contains_key(id_or_name) {
+            return Ok(id_or_name.to_string());
         }
+        sessions.values()
+            .find(|s| s.name() == Some(id_or_name))
+            .map(|s| s.id().to_string())
+            .ok_or_else(|| SerialError::SessionNotFound(id_or_name.to_string()))
+    }
+
+    /// Connect a session to its serial port, or else the first of its failover
+    /// ports that opens, returning the ID of the connection it runs on
+    pub async fn connect_session(&self, session_id: &str) -> Result<String> {
+        let config = {
+            let sessions = self.sessions.read().await;
+            let session = sessions.get(session_id)
+                .ok_or_else(|| SerialError::SessionNotFound(session_id.to_string()))?;
+            if session.has_connection() {
+                return Err(SerialError::ConnectionExists("Session already connected".to_string()));
+            }
+            session.config.clone()
+        };
         
-        debug!("Connecting session {} to port {}", session_id, session.port_name());
+        // Opened without holding the sessions, and through the connection manager the tools share
+        let mut failures = Vec::new();
+        let mut opened = None;
+        for port in config.candidate_ports() {
+            debug!("Connecting session {} to port {}", session_id, port);
+            let connected = self.connection_manager.connect(
+                &port,
+                config.baud_rate,
+                config.data_bits,
+                &config.stop_bits,
+                &config.parity,
+                &config.flow_control,
+                config.timeout_ms,
+            ).await;
+            let connection = match connected {
+                Ok(connection_id) => self.connection_manager.get(&connection_id).await.map_err(|e| SerialError::ConnectionFailed(e.to_string())),
+                Err(e) => Err(e),
+            };
+            match connection {
+                Ok(connection) => {
+                    opened = Some((connection, port));
+                    break;
+                }
+                Err(e) => failures.push((port, e)),
+            }
+        }
+        let device = match &opened {
+            Some((_, port)) => identify_device(port).await,
+            None => None,
+        };
         
-        // Create connection
-        let connection = self.connection_manager.connect(
-            &session.config.port_name,
-            session.config.baud_rate,
-            session.config.data_bits,
-            &session.config.stop_bits,
-            &session.config.parity,
-            &session.config.flow_control,
-            session.config.timeout_ms,
-        ).await.map_err(|e| {
-            error!("Failed to connect session {}: {}", session_id, e);
-            SessionError::CreationFailed(e.to_string())
-        })?;
+        let mut sessions = self.sessions.write().await;
+        let Some(session) = sessions.get_mut(session_id) else {
+            // Removed while connecting
+            drop(sessions);
+            if let Some((connection, _)) = opened {
+                self.close_connection(Some(connection.id().to_string())).await;
+            }
+            return Err(SerialError::SessionNotFound(session_id.to_string()));
+        };
+        if session.has_connection() {
+            // Connected by another call while this one opened the port, which sharing lets both do
+            drop(sessions);
+            if let Some((connection, _)) = opened {
+                self.close_connection(Some(connection.id().to_string())).await;
+            }
+            return Err(SerialError::ConnectionExists("Session already connected".to_string()));
+        }
+        for (port, e) in &failures {
+            session.record_event(format!("Connect to {} failed: {}", port, e));
+        }
+        let Some((connection, port)) = opened else {
+            let error = match failures.as_slice() {
+                [(_, e)] => e.to_string(),
+                _ => failures.iter().map(|(port, e)| format!("{}: {}", port, e)).collect::<Vec<_>>().join("; "),
+            };
+            error!("Failed to connect session {}: {}", session_id, error);
+            session.set_error(error.clone());
+            return Err(SessionError::CreationFailed(error).into());
+        };
+        let connection_id = connection.id().to_string();
         
         // Set connection in session
+        if let Some(encoding) = &config.encoding {
+            connection.set_fallback_encoding(encoding).await;
+        }
+        apply_receive_filters(&connection, &config.receive_filters).await;
         session.set_connection(connection)?;
         session.reset_reconnect_attempts();
+        if device.is_some() {
+            session.device = device;
+        }
+        let failover = if failures.is_empty() { "" } else { ", failing over" };
+        session.record_event(format!("Connected to {} as connection {}{}", port, connection_id, failover));
+        drop(sessions);
+        self.save().await;
         
         info!("Session {} connected successfully", session_id);
-        Ok(())
+        Ok(connection_id)
     }
 
     /// Disconnect a session
@@ -140,19 +319,49 @@ impl SessionManager {
         
         debug!("Disconnecting session {}", session_id);
         
+        let connection_id = session.connection_id().map(str::to_string);
         session.remove_connection();
+        session.record_event("Disconnected");
+        drop(sessions);
+        self.close_connection(connection_id).await;
+        self.save().await;
         
         info!("Session {} disconnected", session_id);
         Ok(())
     }
 
+    /// Take a session's connection away to release its port, keeping the session suspended
+    ///
+    /// Returns the connection, for the caller to close without its teardown.
+    pub async fn suspend_session(&self, session_id: &str) -> Result<Arc<SerialConnection>> {
+        let mut sessions = self.sessions.write().await;
+        let session = sessions.get_mut(session_id)
+            .ok_or_else(|| SerialError::SessionNotFound(session_id.to_string()))?;
+        let connection = session.get_connection().ok_or_else(|| SerialError::InvalidConnection("Session not connected".to_string()))?;
+        
+        session.remove_connection();
+        session.set_state(SessionState::Suspended);
+        session.record_event(format!("Suspended, {} released", session.port_name()));
+        drop(sessions);
+        self.save().await;
+        
+        info!("Session {} suspended", session_id);
+        Ok(connection)
+    }
+
     /// Remove a session
     pub async fn remove_session(&self, session_id: &str) -> Result<()> {
         let mut sessions = self.sessions.write().await;
         
         if let Some(mut session) = sessions.remove(session_id) {
             debug!("Removing session {}", session_id);
+            drop(sessions);
+            let connection_id = session.connection_id().map(str::to_string);
             session.close();
+            session.record_event("Removed");
+            self.close_connection(connection_id).await;
+            self.retire(session).await;
+            self.save().await;
             info!("Session {} removed", session_id);
             Ok(())
         } else {
@@ -160,8 +369,198 @@ impl SessionManager {
         }
     }
 
+    /// Keep the event log of a removed session, dropping the oldest kept log when full
+    async fn retire(&self, session: SerialSession) {
+        let mut ended = self.ended.write().await;
+        if ended.len() == ENDED_SESSION_LOGS {
+            ended.pop_front();
+        }
+        ended.push_back((session.session_id.clone(), session.events()));
+    }
+
+    /// Event log of a session, oldest first, with the session's info unless it was removed
+    ///
+    /// Removed sessions are only found by ID, since their names may be reused.
+    pub async fn session_events(&self, id_or_name: &str) -> Result<(Vec<SessionEvent>, Option<SessionInfo>)> {
+        if let Ok(session_id) = self.resolve(id_or_name).await {
+            if let Some(session) = self.sessions.read().await.get(&session_id) {
+                return Ok((session.events(), Some(session.info())));
+            }
+        }
+        self.ended.read().await.iter()
+            .rev()
+            .find(|(session_id, _)| session_id == id_or_name)
+            .map(|(_, events)| (events.clone(), None))
+            .ok_or_else(|| SerialError::SessionNotFound(id_or_name.to_string()))
+    }
+
+    /// Add an event to the log of the session with this ID or connection
+    pub async fn record_session_event(&self, id: &str, message: impl Into<String>) {
+        if let Ok(session) = session_mut(&mut *self.sessions.write().await, id) {
+            session.record_event(message);
+        }
+    }
+
+    /// Save the sessions, if they outlive the server
+    pub async fn save(&self) {
+        if let Some(path) = &self.state_path {
+            Self::save_sessions(&self.sessions, path, true).await;
+        }
+    }
+
+    /// Write the sessions created in their own right to `path`
+    ///
+    /// With nothing to save the file is only written if `always`, so a server
+    /// that never had a session leaves none behind.
+    async fn save_sessions(sessions: &RwLock<HashMap<String, SerialSession>>, path: &Path, always: bool) {
+        let saved = SavedSessions {
+            sessions: sessions.read().await.values()
+                .filter(|s| !s.tracks_connection())
+                .map(|s| (s.id().to_string(), s.saved()))
+                .collect(),
+        };
+        if saved.sessions.is_empty() && !always {
+            return;
+        }
+        if let Err(e) = save_state(path, &saved) {
+            warn!("Sessions not saved: {}", e);
+        }
+    }
+
+    /// Close a session's connection, which may already be gone
+    async fn close_connection(&self, connection_id: Option<String>) {
+        if let Some(connection_id) = connection_id {
+            if let Err(e) = self.connection_manager.close(&connection_id).await {
+                debug!("Connection {} was already closed: {}", connection_id, e);
+            }
+        }
+    }
+
+    /// Detach sessions whose connection was closed without going through this manager
+    async fn forget_closed_connections(&self) {
+        let open: HashSet<String> = self.connection_manager.connections().await
+            .iter()
+            .map(|connection| connection.id().to_string())
+            .collect();
+        let closed: Vec<String> = self.sessions.read().await.values()
+            .filter_map(|s| s.connection_id())
+            .filter(|id| !open.contains(*id))
+            .map(str::to_string)
+            .collect();
+        for connection_id in closed {
+            self.detach(&connection_id).await;
+        }
+    }
+
+    /// Settings of the session running on a connection
+    pub async fn connection_session_config(&self, connection_id: &str) -> Option<SessionConfig> {
+        self.sessions.read().await.values()
+            .find(|s| s.connection_id() == Some(connection_id))
+            .map(|s| s.config.clone())
+    }
+
+    /// Info of the session running on a connection, whether created or attached
+    pub async fn connection_session_info(&self, connection_id: &str) -> Option<SessionInfo> {
+        self.sessions.read().await.values()
+            .find(|s| s.connection_id() == Some(connection_id))
+            .map(|s| s.info())
+    }
+
+    /// Put a session in a group, or take it out of its group with `None`
+    pub async fn set_session_group(&self, session_id: &str, group: Option<String>) -> Result<()> {
+        let mut sessions = self.sessions.write().await;
+        let session = sessions.get_mut(session_id)
+            .ok_or_else(|| SerialError::SessionNotFound(session_id.to_string()))?;
+        
+        let config = SessionConfig { group, ..session.config.clone() };
+        self.validate_session_config(&config)?;
+        match (&session.config.group, &config.group) {
+            (_, Some(group)) => session.record_event(format!("Joined group {}", group)),
+            (Some(group), None) => session.record_event(format!("Left group {}", group)),
+            (None, None) => {}
+        }
+        session.config = config;
+        drop(sessions);
+        self.save().await;
+        Ok(())
+    }
+
+    /// Sessions of a group, oldest first
+    pub async fn group_sessions(&self, group: &str) -> Vec<SessionInfo> {
+        let mut members: Vec<SessionInfo> = self.list_sessions().await
+            .into_iter()
+            .filter(|info| info.config.group.as_deref() == Some(group))
+            .collect();
+        members.sort_by_key(|info| info.created_at);
+        members
+    }
+
+    /// Set and remove labels of a session, returning the labels it ends up with
+    pub async fn label_session(&self, session_id: &str, set: BTreeMap<String, String>, remove: &[String]) -> Result<BTreeMap<String, String>> {
+        let mut sessions = self.sessions.write().await;
+        let session = sessions.get_mut(session_id)
+            .ok_or_else(|| SerialError::SessionNotFound(session_id.to_string()))?;
+        
+        let mut labels = session.config.labels.clone();
+        for key in remove {
+            labels.remove(key);
+        }
+        labels.extend(set);
+        validate_labels(&labels)?;
+        session.config.labels = labels.clone();
+        drop(sessions);
+        self.save().await;
+        
+        debug!("Session {} labels: {:?}", session_id, labels);
+        Ok(labels)
+    }
+
+    /// Pass what a session receives through `filters` in order, from now on and on every connection it gets
+    pub async fn set_receive_filters(&self, session_id: &str, filters: Vec<ReceiveFilter>) -> Result<()> {
+        let mut sessions = self.sessions.write().await;
+        let session = sessions.get_mut(session_id)
+            .ok_or_else(|| SerialError::SessionNotFound(session_id.to_string()))?;
+        
+        if let Some(connection) = session.get_connection() {
+            connection.set_receive_filters(&filters).await
+                .map_err(|e| SerialError::InvalidConfig(e.to_string()))?;
+        }
+        session.record_event(match filters.as_slice() {
+            [] => "Receive filters removed".to_string(),
+            _ => format!("Receive filters set to {}", format_receive_filters(&filters)),
+        });
+        session.config.receive_filters = filters;
+        drop(sessions);
+        self.save().await;
+        Ok(())
+    }
+
+    /// Keep the protocol and framer of a session's connection in its settings,
+    /// to set them up again when it connects after a restart
+    pub async fn set_connection_framing(&self, connection_id: &str, protocol: Option<String>, framer: Option<FramerSpec>) {
+        let mut sessions = self.sessions.write().await;
+        let Some(session) = sessions.values_mut().find(|s| s.connection_id() == Some(connection_id)) else {
+            return;
+        };
+        if session.config.protocol == protocol && session.config.framer == framer {
+            return;
+        }
+        session.config.protocol = protocol;
+        session.config.framer = framer;
+        drop(sessions);
+        self.save().await;
+    }
+
+    /// Update a session's last accessed time, as when a tool uses its connection
+    pub async fn touch(&self, session_id: &str) {
+        if let Some(session) = self.sessions.write().await.get_mut(session_id) {
+            session.touch();
+        }
+    }
+
     /// Get session information
     pub async fn get_session_info(&self, session_id: &str) -> Result<SessionInfo> {
+        self.forget_closed_connections().await;
         let sessions = self.sessions.read().await;
         let session = sessions.get(session_id)
             .ok_or_else(|| SerialError::SessionNotFound(session_id.to_string()))?;
@@ -171,6 +570,7 @@ impl SessionManager {
 
     /// List all sessions
     pub async fn list_sessions(&self) -> Vec<SessionInfo> {
+        self.forget_closed_connections().await;
         let sessions = self.sessions.read().await;
         sessions.values().map(|s| s.info()).collect()
     }
@@ -185,7 +585,7 @@ impl SessionManager {
     }
 
     /// Get session connection for data operations
-    pub async fn get_session_connection(&self, session_id: &str) -> Result<Arc<tokio::sync::Mutex<SerialConnection>>> {
+    pub async fn get_session_connection(&self, session_id: &str) -> Result<Arc<SerialConnection>> {
         let mut sessions = self.sessions.write().await;
         let session = sessions.get_mut(session_id)
             .ok_or_else(|| SerialError::SessionNotFound(session_id.to_string()))?;
@@ -196,45 +596,150 @@ impl SessionManager {
             .ok_or_else(|| SerialError::InvalidConnection("Session not connected".to_string()))
     }
 
-    /// Record data sent for a session
+    /// Record data sent for a session, given its ID or that of its connection
     pub async fn record_session_send(&self, session_id: &str, bytes: usize) -> Result<()> {
         let mut sessions = self.sessions.write().await;
-        let session = sessions.get_mut(session_id)
-            .ok_or_else(|| SerialError::SessionNotFound(session_id.to_string()))?;
+        let session = session_mut(&mut sessions, session_id)?;
         
         session.record_send(bytes);
         Ok(())
     }
 
-    /// Record data received for a session
+    /// Record data received for a session, given its ID or that of its connection
     pub async fn record_session_receive(&self, session_id: &str, bytes: usize) -> Result<()> {
         let mut sessions = self.sessions.write().await;
-        let session = sessions.get_mut(session_id)
-            .ok_or_else(|| SerialError::SessionNotFound(session_id.to_string()))?;
+        let session = session_mut(&mut sessions, session_id)?;
         
         session.record_receive(bytes);
         Ok(())
     }
 
-    /// Handle session error
+    /// Handle session error, given the session's ID or that of its connection
     pub async fn handle_session_error(&self, session_id: &str, error: String) -> Result<()> {
         let mut sessions = self.sessions.write().await;
-        let session = sessions.get_mut(session_id)
-            .ok_or_else(|| SerialError::SessionNotFound(session_id.to_string()))?;
+        let session = session_mut(&mut sessions, session_id)?;
         
         warn!("Session {} error: {}", session_id, error);
+        session.record_event(format!("Error: {}", error));
         session.set_error(error);
         
-        // Attempt reconnection if configured
-        if session.config.auto_reconnect && session.attempt_reconnect() {
-            debug!("Attempting to reconnect session {}", session_id);
-            // Note: Actual reconnection would be handled by the caller
-            session.set_state(SessionState::Creating);
+        // Sessions set to reconnect are picked up by the reconnect supervisor
+        // once their connection's reader stops
+        Ok(())
+    }
+
+    /// Sessions set to reconnect whose connection stopped working, with why it did
+    pub async fn failed_connections(&self) -> Vec<(String, Arc<SerialConnection>, String)> {
+        let connected: Vec<(String, Arc<SerialConnection>)> = self.sessions.read().await.values()
+            .filter(|s| s.config.auto_reconnect)
+            .filter_map(|s| Some((s.id().to_string(), s.get_connection()?)))
+            .collect();
+        let mut failed = Vec::new();
+        for (session_id, connection) in connected {
+            if let Some(reason) = connection.failure().await {
+                failed.push((session_id, connection, reason));
+            }
         }
+        failed
+    }
+
+    /// Connected sessions with a keepalive, with their connection and settings
+    pub async fn keepalive_sessions(&self) -> Vec<(String, Arc<SerialConnection>, SessionConfig)> {
+        self.sessions.read().await.values()
+            .filter(|s| s.config.keepalive.is_some() && s.is_active())
+            .filter_map(|s| Some((s.id().to_string(), s.get_connection()?, s.config.clone())))
+            .collect()
+    }
+
+    /// Mark a session as reconnecting after its connection failed, dropping the connection
+    pub async fn begin_reconnect(&self, session_id: &str, reason: String) -> Result<()> {
+        let mut sessions = self.sessions.write().await;
+        let session = sessions.get_mut(session_id)
+            .ok_or_else(|| SerialError::SessionNotFound(session_id.to_string()))?;
         
+        warn!("Session {} lost its connection: {}", session_id, reason);
+        let lost = session.connection_id().map(|id| format!(" {}", id)).unwrap_or_default();
+        session.record_event(format!("Lost connection{}: {}; reconnecting", lost, reason));
+        session.stats.record_error(reason);
+        session.remove_connection();
+        session.reset_reconnect_attempts();
+        session.set_state(SessionState::Reconnecting);
         Ok(())
     }
 
+    /// Count a reconnection attempt, returning its number, or `None` once the
+    /// session's attempts are used up or it is no longer reconnecting
+    pub async fn next_reconnect_attempt(&self, session_id: &str) -> Option<u32> {
+        let mut sessions = self.sessions.write().await;
+        let session = sessions.get_mut(session_id)?;
+        if !matches!(session.state(), SessionState::Reconnecting) || !session.attempt_reconnect() {
+            return None;
+        }
+        let attempt = session.reconnect_attempts();
+        session.record_event(format!("Reconnect attempt {} of {}", attempt, session.config.max_reconnect_attempts));
+        Some(attempt)
+    }
+
+    /// Give a session the connection that replaced its last one, after a reconnect or resume
+    pub async fn reattach(&self, session_id: &str, connection: Arc<SerialConnection>) -> Result<()> {
+        let mut sessions = self.sessions.write().await;
+        let session = sessions.get_mut(session_id)
+            .ok_or_else(|| SerialError::SessionNotFound(session_id.to_string()))?;
+        
+        // After the protocol was restored, whose encoding comes first
+        if let Some(encoding) = &session.config.encoding {
+            connection.set_fallback_encoding(encoding).await;
+        }
+        apply_receive_filters(&connection, &session.config.receive_filters).await;
+        let connection_id = connection.id().to_string();
+        session.set_connection(connection)?;
+        session.reset_reconnect_attempts();
+        session.record_event(format!("Connected again to {} as connection {}", session.port_name(), connection_id));
+        info!("Session {} connected again", session_id);
+        Ok(())
+    }
+
+    /// Point a session at the path its device came back under, in place of `from`
+    /// among its ports
+    ///
+    /// The new path has to pass the same port restrictions as the old one did.
+    pub async fn relocate(&self, session_id: &str, from: &str, port_name: &str) -> Result<()> {
+        let mut sessions = self.sessions.write().await;
+        let session = sessions.get_mut(session_id)
+            .ok_or_else(|| SerialError::SessionNotFound(session_id.to_string()))?;
+        
+        let mut config = session.config.clone();
+        for port in std::iter::once(&mut config.port_name).chain(config.failover_ports.iter_mut()) {
+            if port == from {
+                *port = port_name.to_string();
+            }
+        }
+        self.validate_session_config(&config)?;
+        info!("Session {} moved from {} to {}", session_id, from, port_name);
+        session.record_event(format!("Device moved from {} to {}", from, port_name));
+        session.config = config;
+        drop(sessions);
+        self.save().await;
+        Ok(())
+    }
+
+    /// Leave a session that could not be reconnected in the error state
+    ///
+    /// Returns whether it was still reconnecting, rather than removed or
+    /// connected again by hand meanwhile.
+    pub async fn fail_reconnect(&self, session_id: &str, reason: String) -> bool {
+        let mut sessions = self.sessions.write().await;
+        match sessions.get_mut(session_id) {
+            Some(session) if matches!(session.state(), SessionState::Reconnecting) => {
+                error!("Session {} not reconnected: {}", session_id, reason);
+                session.record_event(format!("Gave up reconnecting: {}", reason));
+                session.set_error(reason);
+                true
+            }
+            _ => false,
+        }
+    }
+
     /// Check if a port is currently in use
     pub async fn is_port_in_use(&self, port_name: &str) -> bool {
         let sessions = self.sessions.read().await;
@@ -291,34 +796,53 @@ impl SessionManager {
         crate::utils::Validator::validate_stop_bits(&config.stop_bits)?;
         crate::utils::Validator::validate_parity(&config.parity)?;
         crate::utils::Validator::validate_flow_control(&config.flow_control)?;
+        if let Some(keepalive) = &config.keepalive {
+            keepalive.validate()?;
+        }
+        if let Some(rate_limit) = &config.rate_limit {
+            rate_limit.validate()?;
+        }
+        validate_labels(&config.labels)?;
+        
+        if config.group.as_deref().is_some_and(str::is_empty) {
+            return Err(SerialError::InvalidConfig("Group name cannot be empty".to_string()));
+        }
+        if config.failover_ports.iter().any(String::is_empty) {
+            return Err(SerialError::InvalidConfig("Failover port names cannot be empty".to_string()));
+        }
         
-        // Check security restrictions
+        // Check security restrictions, of the failover ports too
         if self.config.security.restrict_ports {
-            if !self.config.security.allowed_ports.is_empty() &&
-               !self.config.security.allowed_ports.iter().any(|pattern| config.port_name.contains(pattern)) {
-                return Err(SerialError::InvalidConfig(
-                    format!("Port {} is not in allowed ports list", config.port_name)
-                ));
-            }
-            
-            if self.config.security.blocked_ports.iter().any(|pattern| config.port_name.contains(pattern)) {
-                return Err(SerialError::InvalidConfig(
-                    format!("Port {} is blocked", config.port_name)
-                ));
+            for port_name in config.candidate_ports() {
+                if !self.config.security.allowed_ports.is_empty() &&
+                   !self.config.security.allowed_ports.iter().any(|pattern| port_name.contains(pattern)) {
+                    return Err(SerialError::InvalidConfig(
+                        format!("Port {} is not in allowed ports list", port_name)
+                    ));
+                }
+                
+                if self.config.security.blocked_ports.iter().any(|pattern| port_name.contains(pattern)) {
+                    return Err(SerialError::InvalidConfig(
+                        format!("Port {} is blocked", port_name)
+                    ));
+                }
             }
         }
         
         Ok(())
     }
 
-    /// Cleanup idle sessions
-    async fn cleanup_idle_sessions(sessions: &Arc<RwLock<HashMap<String, SerialSession>>>, max_idle_seconds: i64) {
+    /// Cleanup idle sessions, returning the removed ones
+    async fn cleanup_idle_sessions(&self, max_idle_seconds: i64) -> Vec<SessionInfo> {
         let mut sessions_to_remove = Vec::new();
         
         {
-            let sessions_read = sessions.read().await;
+            let sessions_read = self.sessions.read().await;
             for (session_id, session) in sessions_read.iter() {
-                if session.is_idle(max_idle_seconds) && !session.is_active() {
+                // A session whose connection is still open is left to its connection's lifecycle,
+                // and a suspended one waits to be resumed
+                let suspended = matches!(session.state(), SessionState::Suspended);
+                if session.is_idle(max_idle_seconds) && !session.is_active() && !session.has_connection() && !suspended {
                     debug!("Session {} is idle for {} seconds, marking for cleanup", 
                            session_id, session.idle_seconds());
                     sessions_to_remove.push(session_id.clone());
@@ -326,18 +850,62 @@ impl SessionManager {
             }
         }
         
-        if !sessions_to_remove.is_empty() {
-            let mut sessions_write = sessions.write().await;
-            for session_id in sessions_to_remove {
-                if let Some(mut session) = sessions_write.remove(&session_id) {
-                    info!("Cleaning up idle session {}", session_id);
-                    session.close();
-                }
+        let mut removed = Vec::new();
+        if sessions_to_remove.is_empty() {
+            return removed;
+        }
+        let mut closed = Vec::new();
+        let mut sessions_write = self.sessions.write().await;
+        for session_id in sessions_to_remove {
+            if let Some(mut session) = sessions_write.remove(&session_id) {
+                info!("Cleaning up idle session {}", session_id);
+                removed.push(session.info());
+                session.record_event(format!("Cleaned up after {}s unused without a connection", session.idle_seconds()));
+                session.close();
+                closed.push(session);
             }
         }
+        drop(sessions_write);
+        for session in closed {
+            self.retire(session).await;
+        }
+        removed
     }
 }
 
+/// The session with this ID, or the one running on the connection with this ID
+///
+/// Tools record traffic under the connection ID, which a created session does not share.
+fn session_mut<'a>(sessions: &'a mut HashMap<String, SerialSession>, id: &str) -> Result<&'a mut SerialSession> {
+    sessions.values_mut()
+        .find(|s| s.id() == id || s.connection_id() == Some(id))
+        .ok_or_else(|| SerialError::SessionNotFound(id.to_string()))
+}
+
+/// Give a session's new connection its receive filters
+async fn apply_receive_filters(connection: &SerialConnection, filters: &[ReceiveFilter]) {
+    if filters.is_empty() {
+        return;
+    }
+    if let Err(e) = connection.set_receive_filters(filters).await {
+        warn!("Receive filters not applied on connection {}: {}", connection.id(), e);
+    }
+}
+
+/// Check label names can be listed as `key=value` pairs
+fn validate_labels(labels: &BTreeMap<String, String>) -> Result<()> {
+    match labels.keys().find(|key| key.is_empty() || key.contains(['=', ','])) {
+        Some(key) => Err(SerialError::InvalidConfig(format!("Invalid label name {:?}: it cannot be empty or contain '=' or ','", key))),
+        None => Ok(()),
+    }
+}
+
+/// Identity of the USB device at a port, looked up off the async runtime
+async fn identify_device(port_name: &str) -> Option<DeviceIdentity> {
+    let port_name = port_name.to_string();
+    tokio::task::spawn_blocking(move || DeviceIdentity::of_port(&port_name)).await.ok().flatten()
+}
+
 impl Drop for SessionManager {
     fn drop(&mut self) {
         // Note: In a real implementation, we might want to gracefully close all sessions
@@ -362,6 +930,7 @@ pub struct SessionManagerStats {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::session::format_labels;
     use crate::config::Config;
 
     #[tokio::test]
@@ -438,4 +1007,269 @@ mod tests {
         
         assert_eq!(manager.session_count().await, 2);
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_named_sessions() {
+        let manager = SessionManager::new(Config::default());
+        let named = SessionConfig {
+            name: Some("gps".to_string()),
+            port_name: "/dev/ttyUSB0".to_string(),
+            ..Default::default()
+        };
+        let session_id = manager.create_session(named.clone()).await.unwrap();
+        assert_eq!(manager.resolve("gps").await.unwrap(), session_id);
+        assert_eq!(manager.resolve(&session_id).await.unwrap(), session_id);
+        assert!(manager.resolve("rtk").await.is_err());
+
+        let duplicate = SessionConfig { port_name: "/dev/ttyUSB1".to_string(), ..named };
+        assert!(matches!(manager.create_session(duplicate).await, Err(SerialError::SessionExists(_))));
+    }
+
+    #[tokio::test]
+    async fn test_session_labels() {
+        let manager = SessionManager::new(Config::default());
+        let labels = BTreeMap::from([("device".to_string(), "relay-board".to_string())]);
+        let session_config = SessionConfig { port_name: "/dev/ttyUSB0".to_string(), labels: labels.clone(), ..Default::default() };
+        let session_id = manager.create_session(session_config).await.unwrap();
+        assert!(manager.get_session_info(&session_id).await.unwrap().config.has_labels(&labels));
+
+        let set = BTreeMap::from([("site".to_string(), "lab2".to_string())]);
+        let labels = manager.label_session(&session_id, set, &["device".to_string()]).await.unwrap();
+        assert_eq!(format_labels(&labels), "site=lab2");
+        let invalid = BTreeMap::from([("a=b".to_string(), "c".to_string())]);
+        assert!(manager.label_session(&session_id, invalid, &[]).await.is_err());
+        assert_eq!(manager.get_session_info(&session_id).await.unwrap().config.labels, labels);
+    }
+
+    #[tokio::test]
+    async fn test_session_groups() {
+        let manager = SessionManager::new(Config::default());
+        let mut members = Vec::new();
+        for port in ["/dev/ttyUSB0", "/dev/ttyUSB1"] {
+            let session_config = SessionConfig { port_name: port.to_string(), group: Some("rack-3".to_string()), ..Default::default() };
+            members.push(manager.create_session(session_config).await.unwrap());
+        }
+        let other = manager.create_session(SessionConfig { port_name: "/dev/ttyUSB2".to_string(), ..Default::default() }).await.unwrap();
+
+        let group: Vec<String> = manager.group_sessions("rack-3").await.into_iter().map(|info| info.session_id).collect();
+        assert_eq!(group, members);
+
+        manager.set_session_group(&other, Some("rack-3".to_string())).await.unwrap();
+        manager.set_session_group(&members[0], None).await.unwrap();
+        let group: Vec<String> = manager.group_sessions("rack-3").await.into_iter().map(|info| info.session_id).collect();
+        assert_eq!(group, [members[1].clone(), other.clone()]);
+        assert!(manager.set_session_group(&other, Some(String::new())).await.is_err());
+
+        let (events, _) = manager.session_events(&members[0]).await.unwrap();
+        assert_eq!(events.last().unwrap().message, "Left group rack-3");
+    }
+
+    #[tokio::test]
+    async fn test_session_events() {
+        let manager = SessionManager::new(Config::default());
+        let session_config = SessionConfig {
+            name: Some("modem".to_string()),
+            port_name: "/dev/ttyNONEXISTENT".to_string(),
+            ..Default::default()
+        };
+        let session_id = manager.create_session(session_config).await.unwrap();
+        assert!(manager.connect_session(&session_id).await.is_err());
+
+        let (events, info) = manager.session_events("modem").await.unwrap();
+        let messages: Vec<&str> = events.iter().map(|event| event.message.as_str()).collect();
+        assert_eq!(messages[0], "Created for /dev/ttyNONEXISTENT");
+        assert!(messages[1].starts_with("Connect to /dev/ttyNONEXISTENT failed"));
+        assert!(matches!(info.unwrap().state, SessionState::Error(_)));
+
+        // Still told after the session is gone, by ID only
+        manager.remove_session(&session_id).await.unwrap();
+        assert!(manager.session_events("modem").await.is_err());
+        let (events, info) = manager.session_events(&session_id).await.unwrap();
+        assert_eq!(events.last().unwrap().message, "Removed");
+        assert!(info.is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_idle_sessions_reaped() {
+        let mut config = Config::default();
+        config.server.session_idle_timeout_seconds = 1;
+        let manager = SessionManager::new(config.clone());
+        config.server.session_idle_timeout_seconds = 0;
+        let never_expires = SessionManager::new(config);
+        let session_config = SessionConfig { port_name: "/dev/ttyUSB0".to_string(), ..Default::default() };
+        let idle = manager.create_session(session_config.clone()).await.unwrap();
+        never_expires.create_session(session_config.clone()).await.unwrap();
+        assert!(manager.reap_idle_sessions().await.is_empty());
+
+        tokio::time::advance(std::time::Duration::from_millis(2100)).await;
+        assert!(never_expires.reap_idle_sessions().await.is_empty());
+        let used = manager.create_session(session_config).await.unwrap();
+        let reaped = manager.reap_idle_sessions().await;
+        assert_eq!(reaped.len(), 1);
+        assert_eq!(reaped[0].session_id, idle);
+        assert!(manager.get_session_info(&idle).await.is_err());
+        assert!(manager.get_session_info(&used).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_sessions_persist() {
+        let dir = tempfile::tempdir().unwrap();
+        let manager = SessionManager::new(Config::default()).with_persistence(dir.path());
+        let config = SessionConfig {
+            name: Some("gps".to_string()),
+            port_name: "/dev/ttyUSB0".to_string(),
+            baud_rate: 9600,
+            auto_reconnect: true,
+            protocol: Some("nmea".to_string()),
+            framer: Some(serde_json::from_str(r#"{"framer": "delimiter", "delimiter": "0D0A"}"#).unwrap()),
+            ..Default::default()
+        };
+        let session_id = manager.create_session(config).await.unwrap();
+        manager.record_session_send(&session_id, 12).await.unwrap();
+        let removed = manager.create_session(SessionConfig { port_name: "/dev/ttyUSB1".to_string(), ..Default::default() }).await.unwrap();
+        manager.remove_session(&removed).await.unwrap();
+
+        let restarted = SessionManager::new(Config::default()).with_persistence(dir.path());
+        let sessions = restarted.list_sessions().await;
+        assert_eq!(sessions.len(), 1);
+        let info = &sessions[0];
+        assert_eq!(info.session_id, session_id);
+        assert!(matches!(info.state, SessionState::Suspended));
+        assert!(!info.has_connection);
+        assert_eq!(info.config.baud_rate, 9600);
+        assert!(info.config.auto_reconnect);
+        assert_eq!(info.config.protocol.as_deref(), Some("nmea"));
+        assert_eq!(info.config.framer.as_ref().and_then(|framer| framer.delimiter.as_deref()), Some("0D0A"));
+        assert_eq!(info.stats.bytes_sent, 12);
+        assert_eq!(restarted.resolve("gps").await.unwrap(), session_id);
+    }
+
+    #[tokio::test]
+    async fn test_unreadable_sessions_left_untouched() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(SESSIONS_FILE);
+        let newer = r#"{"kind": "sessions", "version": 99, "data": {"sessions": {}}}"#;
+        std::fs::write(&path, newer).unwrap();
+
+        let manager = SessionManager::new(Config::default()).with_persistence(dir.path());
+        manager.create_session(SessionConfig { port_name: "/dev/ttyUSB0".to_string(), ..Default::default() }).await.unwrap();
+        manager.reap_idle_sessions().await;
+        manager.save().await;
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), newer);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_failover_ports() {
+        use tokio_serial::SerialPort;
+
+        let (_device, slave) = tokio_serial::SerialStream::pair().unwrap();
+        let path = slave.name().unwrap();
+        let mut config = Config::default();
+        config.security.restrict_ports = true;
+        config.security.blocked_ports = vec!["ttyBLOCKED".to_string()];
+        let manager = SessionManager::new(config);
+        let blocked = SessionConfig {
+            port_name: "/dev/ttyNONEXISTENT".to_string(),
+            failover_ports: vec!["/dev/ttyBLOCKED0".to_string()],
+            ..Default::default()
+        };
+        assert!(manager.create_session(blocked).await.is_err());
+
+        let session_config = SessionConfig {
+            port_name: "/dev/ttyNONEXISTENT".to_string(),
+            failover_ports: vec!["/dev/ttyNONEXISTENT".to_string(), path.clone()],
+            ..Default::default()
+        };
+        assert_eq!(session_config.candidate_ports(), ["/dev/ttyNONEXISTENT", path.as_str()]);
+        let session_id = manager.create_session(session_config).await.unwrap();
+        manager.connect_session(&session_id).await.unwrap();
+
+        let info = manager.get_session_info(&session_id).await.unwrap();
+        assert_eq!(info.port_name, path);
+        assert_eq!(info.config.port_name, "/dev/ttyNONEXISTENT");
+        let (events, _) = manager.session_events(&session_id).await.unwrap();
+        assert!(events[1].message.starts_with("Connect to /dev/ttyNONEXISTENT failed"));
+        assert!(events[2].message.ends_with("failing over"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_concurrent_connects_keep_one_connection() {
+        use tokio_serial::SerialPort;
+
+        let (_device, slave) = tokio_serial::SerialStream::pair().unwrap();
+        let path = slave.name().unwrap();
+        let connection_manager = Arc::new(ConnectionManager::new().with_port_sharing(true));
+        let manager = SessionManager::new(Config::default()).with_connection_manager(Arc::clone(&connection_manager));
+        let session_id = manager
+            .create_session(SessionConfig { port_name: path, ..Default::default() })
+            .await
+            .unwrap();
+
+        // Both open the port, sharing it; the one that loses closes its handle
+        let (first, second) = tokio::join!(manager.connect_session(&session_id), manager.connect_session(&session_id));
+        assert_eq!(usize::from(first.is_ok()) + usize::from(second.is_ok()), 1);
+        let connected = manager.get_session_info(&session_id).await.unwrap().connection_id;
+        assert_eq!(connected, first.ok().or(second.ok()));
+        assert_eq!(connection_manager.connections().await.len(), 1);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_attached_connection() {
+        use crate::serial::{BackpressurePolicy, ConnectionConfig, DataBits, FlowControl, Parity, StopBits};
+        use tokio_serial::SerialPort;
+
+        let (_device, slave) = tokio_serial::SerialStream::pair().unwrap();
+        let path = slave.name().unwrap();
+        let connection_manager = Arc::new(ConnectionManager::new());
+        let mut config = Config::default();
+        config.security.restrict_ports = true;
+        config.security.blocked_ports = vec!["ttyBLOCKED".to_string()];
+        let manager = SessionManager::new(config).with_connection_manager(Arc::clone(&connection_manager));
+
+        let blocked = SessionConfig { port_name: "/dev/ttyBLOCKED0".to_string(), ..Default::default() };
+        assert!(manager.admit(&blocked).await.is_err());
+
+        let connection_config = ConnectionConfig {
+            port: path.clone(),
+            baud_rate: 115200,
+            data_bits: DataBits::Eight,
+            stop_bits: StopBits::One,
+            parity: Parity::None,
+            flow_control: FlowControl::None,
+            backpressure: BackpressurePolicy::Drop,
+        };
+        manager.admit(&SessionConfig::from_connection(&connection_config, &manager.config.serial)).await.unwrap();
+        let connection_id = connection_manager.open(connection_config).await.unwrap();
+        let session_id = manager.attach(connection_manager.get(&connection_id).await.unwrap()).await.unwrap();
+        assert_eq!(session_id, connection_id);
+
+        // The port is taken now that a session holds it
+        let again = SessionConfig { port_name: path, ..Default::default() };
+        assert!(manager.admit(&again).await.is_err());
+
+        manager.record_session_send(&session_id, 5).await.unwrap();
+        let info = manager.get_session_info(&session_id).await.unwrap();
+        assert!(info.has_connection);
+        assert_eq!(info.config.stop_bits, "One");
+        let defaults = manager.connection_session_config(&connection_id).await.unwrap();
+        assert_eq!(defaults.timeout_ms, manager.config.serial.default_timeout_ms);
+        assert_eq!(defaults.line_ending, manager.config.serial.default_line_ending);
+        assert_eq!(info.stats.bytes_sent, 5);
+
+        // Suspending hands the connection back and leaves the session without one
+        let connection = manager.suspend_session(&session_id).await.unwrap();
+        let info = manager.get_session_info(&session_id).await.unwrap();
+        assert!(!info.has_connection);
+        assert_eq!(info.state, SessionState::Suspended);
+        assert!(manager.suspend_session(&session_id).await.is_err());
+        manager.reattach(&session_id, connection).await.unwrap();
+        assert!(manager.get_session_info(&session_id).await.unwrap().has_connection);
+
+        // Closed behind the manager's back, as when a client vanishes
+        connection_manager.close(&connection_id).await.unwrap();
+        assert!(manager.list_sessions().await.is_empty());
+    }
+}
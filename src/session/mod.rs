@@ -3,8 +3,15 @@
 //! This module provides session management functionality for tracking and
 //! managing multiple serial connections and their associated state.
 
+pub mod export;
+pub mod keepalive;
 pub mod manager;
+#[allow(clippy::module_inception)]
 pub mod session;
+pub mod store;
 
+pub use export::{ExportedHistory, SessionExport};
+pub use keepalive::KeepaliveConfig;
 pub use manager::SessionManager;
-pub use session::{SerialSession, SessionState, SessionConfig};
\ No newline at end of file
+pub use crate::serial::RateLimit;
+pub use session::{format_labels, format_receive_filters, SerialSession, SessionConfig, SessionEvent, SessionInfo, SessionState};
\ No newline at end of file
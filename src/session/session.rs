@@ -2,14 +2,19 @@
 //! 
 //! Defines the core session structure and state management for serial connections.
 
+use std::collections::{BTreeMap, VecDeque};
 use std::sync::Arc;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use tokio::sync::Mutex;
 
+use crate::config::SerialConfig;
 use crate::error::{SerialError, Result};
-use crate::serial::SerialConnection;
+use crate::serial::{ConnectionConfig, DataBits, DeviceIdentity, ReceiveFilter, SerialConnection};
 use crate::utils::SessionIdGenerator;
+use super::keepalive::KeepaliveConfig;
+use crate::serial::{RateLimit, WriteLimiter};
+use super::store::SavedSession;
+use crate::protocol::FramerSpec;
 
 /// Session state enumeration
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -20,6 +25,8 @@ pub enum SessionState {
     Active,
     /// Session is temporarily suspended
     Suspended,
+    /// Session lost its connection and is being reconnected
+    Reconnecting,
     /// Session is being closed
     Closing,
     /// Session is closed
@@ -34,6 +41,7 @@ impl std::fmt::Display for SessionState {
             SessionState::Creating => write!(f, "Creating"),
             SessionState::Active => write!(f, "Active"),
             SessionState::Suspended => write!(f, "Suspended"),
+            SessionState::Reconnecting => write!(f, "Reconnecting"),
             SessionState::Closing => write!(f, "Closing"),
             SessionState::Closed => write!(f, "Closed"),
             SessionState::Error(msg) => write!(f, "Error: {}", msg),
@@ -44,7 +52,13 @@ impl std::fmt::Display for SessionState {
 /// Session configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SessionConfig {
+    /// Name the session can be referred to by instead of its ID
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
     pub port_name: String,
+    /// Ports tried in order when `port_name` cannot be opened or its link is lost
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub failover_ports: Vec<String>,
     pub baud_rate: u32,
     pub data_bits: u8,
     pub stop_bits: String,
@@ -55,12 +69,38 @@ pub struct SessionConfig {
     pub auto_reconnect: bool,
     pub max_reconnect_attempts: u32,
     pub line_ending: String,
+    /// Encoding tools read and write the session's data in, unless its protocol sets one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<String>,
+    /// Configured protocol the session's connection decodes with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub protocol: Option<String>,
+    /// Framer set with set_framer, over the protocol's
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub framer: Option<FramerSpec>,
+    /// Check now and then that the device still answers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keepalive: Option<KeepaliveConfig>,
+    /// Caps on the writes and bytes sent per second
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_limit: Option<RateLimit>,
+    /// Filters received data passes through in order, such as strip_ansi
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub receive_filters: Vec<ReceiveFilter>,
+    /// Group the session is driven with, such as "rack-3"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    /// Free-form key/value labels, such as device="relay-board" or site="lab2"
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
 }
 
 impl Default for SessionConfig {
     fn default() -> Self {
         Self {
+            name: None,
             port_name: String::new(),
+            failover_ports: Vec::new(),
             baud_rate: 115200,
             data_bits: 8,
             stop_bits: "One".to_string(),
@@ -71,10 +111,103 @@ impl Default for SessionConfig {
             auto_reconnect: false,
             max_reconnect_attempts: 3,
             line_ending: "\n".to_string(),
+            encoding: None,
+            protocol: None,
+            framer: None,
+            keepalive: None,
+            rate_limit: None,
+            receive_filters: Vec::new(),
+            group: None,
+            labels: BTreeMap::new(),
         }
     }
 }
 
+impl SessionConfig {
+    /// Settings of a connection opened directly, the rest from the server's serial defaults
+    pub fn from_connection(config: &ConnectionConfig, serial: &SerialConfig) -> Self {
+        Self {
+            port_name: config.port.clone(),
+            baud_rate: config.baud_rate,
+            data_bits: match config.data_bits {
+                DataBits::Five => 5,
+                DataBits::Six => 6,
+                DataBits::Seven => 7,
+                DataBits::Eight => 8,
+            },
+            stop_bits: format!("{:?}", config.stop_bits),
+            parity: format!("{:?}", config.parity),
+            flow_control: format!("{:?}", config.flow_control),
+            timeout_ms: serial.default_timeout_ms,
+            buffer_size: serial.max_buffer_size,
+            line_ending: serial.default_line_ending.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// Ports the session may connect to, `port_name` first, then its failover ports
+    pub fn candidate_ports(&self) -> Vec<String> {
+        let mut ports = vec![self.port_name.clone()];
+        for port in &self.failover_ports {
+            if !ports.contains(port) {
+                ports.push(port.clone());
+            }
+        }
+        ports
+    }
+
+    /// Whether the session carries every one of `labels`
+    pub fn has_labels(&self, labels: &BTreeMap<String, String>) -> bool {
+        labels.iter().all(|(key, value)| self.labels.get(key) == Some(value))
+    }
+}
+
+/// Labels as `key=value` pairs, comma separated
+pub fn format_labels(labels: &BTreeMap<String, String>) -> String {
+    labels.iter().map(|(key, value)| format!("{}={}", key, value)).collect::<Vec<_>>().join(", ")
+}
+
+/// Receive filters in order, comma separated
+pub fn format_receive_filters(filters: &[ReceiveFilter]) -> String {
+    filters.iter().map(ReceiveFilter::to_string).collect::<Vec<_>>().join(", ")
+}
+
+/// Number of events kept in a session's event log
+pub const SESSION_EVENT_CAPACITY: usize = 64;
+
+/// Something that happened to a session, such as connecting or failing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionEvent {
+    pub timestamp: DateTime<Utc>,
+    pub message: String,
+}
+
+/// Seconds of traffic the rates of a session are taken over
+pub const RATE_WINDOW_SECS: i64 = 60;
+
+/// Traffic and errors within one second
+#[derive(Debug, Clone, Default)]
+struct TrafficSecond {
+    second: i64,
+    bytes_sent: u64,
+    bytes_received: u64,
+    transfers: u64,
+    errors: u64,
+}
+
+/// How a session's link has been doing lately
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SessionRates {
+    /// Seconds the rates are taken over, up to [`RATE_WINDOW_SECS`]
+    pub window_seconds: i64,
+    pub sent_bytes_per_sec: f64,
+    pub received_bytes_per_sec: f64,
+    /// Errors within the window
+    pub errors: u64,
+    /// Errors as a share of reads, writes and errors within the window, if there were any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_rate: Option<f64>,
+}
 
 /// Session statistics
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -86,24 +219,76 @@ pub struct SessionStats {
     pub errors_count: u64,
     pub reconnections: u32,
     pub last_activity: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error_at: Option<DateTime<Utc>>,
+    /// Traffic of the last [`RATE_WINDOW_SECS`], a second at a time; not saved
+    #[serde(skip)]
+    recent: VecDeque<TrafficSecond>,
 }
 
 impl SessionStats {
     pub fn record_send(&mut self, bytes: usize) {
         self.bytes_sent += bytes as u64;
         self.messages_sent += 1;
-        self.last_activity = Some(Utc::now());
+        self.count_recent(|second| {
+            second.bytes_sent += bytes as u64;
+            second.transfers += 1;
+        });
     }
 
     pub fn record_receive(&mut self, bytes: usize) {
         self.bytes_received += bytes as u64;
         self.messages_received += 1;
-        self.last_activity = Some(Utc::now());
+        self.count_recent(|second| {
+            second.bytes_received += bytes as u64;
+            second.transfers += 1;
+        });
     }
 
-    pub fn record_error(&mut self) {
+    pub fn record_error(&mut self, error: impl Into<String>) {
         self.errors_count += 1;
-        self.last_activity = Some(Utc::now());
+        self.count_recent(|second| second.errors += 1);
+        self.last_error = Some(error.into());
+        self.last_error_at = self.last_activity;
+    }
+
+    /// Count activity in the current second, forgetting seconds that left the window
+    fn count_recent(&mut self, count: impl FnOnce(&mut TrafficSecond)) {
+        let now = Utc::now();
+        let second = now.timestamp();
+        self.last_activity = Some(now);
+        while self.recent.front().is_some_and(|s| s.second <= second - RATE_WINDOW_SECS) {
+            self.recent.pop_front();
+        }
+        if self.recent.back().is_none_or(|s| s.second != second) {
+            self.recent.push_back(TrafficSecond { second, ..Default::default() });
+        }
+        if let Some(current) = self.recent.back_mut() {
+            count(current);
+        }
+    }
+
+    /// Rates over the last [`RATE_WINDOW_SECS`], or over `age_seconds` if the session is younger
+    pub fn rates(&self, age_seconds: i64) -> SessionRates {
+        let now = Utc::now().timestamp();
+        let window_seconds = age_seconds.clamp(1, RATE_WINDOW_SECS);
+        let recent = self.recent.iter().filter(|s| s.second > now - RATE_WINDOW_SECS);
+        let (mut sent, mut received, mut transfers, mut errors) = (0, 0, 0, 0);
+        for second in recent {
+            sent += second.bytes_sent;
+            received += second.bytes_received;
+            transfers += second.transfers;
+            errors += second.errors;
+        }
+        SessionRates {
+            window_seconds,
+            sent_bytes_per_sec: sent as f64 / window_seconds as f64,
+            received_bytes_per_sec: received as f64 / window_seconds as f64,
+            errors,
+            error_rate: (transfers + errors > 0).then(|| errors as f64 / (transfers + errors) as f64),
+        }
     }
 
     pub fn record_reconnection(&mut self) {
@@ -130,21 +315,38 @@ pub struct SerialSession {
     /// Last access timestamp
     pub last_accessed: DateTime<Utc>,
     
+    /// Last access on the monotonic clock, which idleness is measured by
+    touched: tokio::time::Instant,
+    
     /// Session statistics
     pub stats: SessionStats,
     
-    /// Optional serial connection (wrapped in Arc<Mutex> for thread safety)
-    connection: Option<Arc<Mutex<SerialConnection>>>,
+    /// Serial connection, shared with the connection manager that opened it
+    connection: Option<Arc<SerialConnection>>,
     
     /// Current reconnection attempt count
     reconnect_attempts: u32,
+    
+    /// USB device last connected to, to find again if it comes back under another path
+    pub device: Option<DeviceIdentity>,
+    
+    /// Lifecycle events, oldest first, bounded by [`SESSION_EVENT_CAPACITY`]
+    events: VecDeque<SessionEvent>,
+    
+    /// Writes counted against the rate limit, by each connection the session runs on
+    write_limiter: Option<WriteLimiter>,
 }
 
 impl SerialSession {
     /// Create a new session with the given configuration
     pub fn new(config: SessionConfig) -> Self {
-        let session_id = SessionIdGenerator::generate();
+        Self::with_id(SessionIdGenerator::generate(), config)
+    }
+
+    /// Create a session under a given ID, such as that of the connection it tracks
+    pub fn with_id(session_id: String, config: SessionConfig) -> Self {
         let now = Utc::now();
+        let write_limiter = config.rate_limit.clone().map(|limit| WriteLimiter::new(&session_id, limit));
         
         Self {
             session_id,
@@ -152,21 +354,68 @@ impl SerialSession {
             state: SessionState::Creating,
             created_at: now,
             last_accessed: now,
+            touched: tokio::time::Instant::now(),
             stats: SessionStats::default(),
             connection: None,
             reconnect_attempts: 0,
+            device: None,
+            events: VecDeque::new(),
+            write_limiter,
+        }
+    }
+
+
+    /// Bring back a session saved by an earlier server run, suspended until connected
+    pub fn restore(session_id: String, saved: SavedSession) -> Self {
+        let mut session = Self::from_saved(session_id, saved);
+        session.record_event("Restored after a server restart, suspended until connected");
+        session
+    }
+
+    /// A session as saved or exported, suspended until connected
+    pub fn from_saved(session_id: String, saved: SavedSession) -> Self {
+        let mut session = Self::with_id(session_id, saved.config);
+        session.created_at = saved.created_at;
+        session.stats = saved.stats;
+        session.device = saved.device;
+        session.events = saved.events.into();
+        session.state = SessionState::Suspended;
+        session
+    }
+
+    /// What is saved of the session between server runs
+    pub fn saved(&self) -> SavedSession {
+        SavedSession {
+            config: self.config.clone(),
+            created_at: self.created_at,
+            stats: self.stats.clone(),
+            device: self.device.clone(),
+            events: self.events(),
         }
     }
 
+    /// Whether the session only tracks a connection opened with `open`, under
+    /// that connection's ID, rather than having been created in its own right
+    pub fn tracks_connection(&self) -> bool {
+        self.connection_id() == Some(self.id())
+    }
 
     /// Get session ID
     pub fn id(&self) -> &str {
         &self.session_id
     }
 
-    /// Get port name
+    /// Get the name given at creation, if any
+    pub fn name(&self) -> Option<&str> {
+        self.config.name.as_deref()
+    }
+
+    /// Port the session is connected to, or else the first one it tries
     pub fn port_name(&self) -> &str {
-        &self.config.port_name
+        match &self.connection {
+            Some(connection) => &connection.config().port,
+            None => &self.config.port_name,
+        }
     }
 
     /// Get current state
@@ -187,22 +436,29 @@ impl SerialSession {
     /// Update last accessed time
     pub fn touch(&mut self) {
         self.last_accessed = Utc::now();
+        self.touched = tokio::time::Instant::now();
+    }
+
+    /// ID of the connection the session runs on, if connected
+    pub fn connection_id(&self) -> Option<&str> {
+        self.connection.as_ref().map(|connection| connection.id())
     }
 
     /// Set connection
-    pub fn set_connection(&mut self, connection: SerialConnection) -> Result<()> {
+    pub fn set_connection(&mut self, connection: Arc<SerialConnection>) -> Result<()> {
         if matches!(self.state, SessionState::Closed) {
             return Err(SerialError::InvalidSession("Cannot set connection on closed session".to_string()));
         }
 
-        self.connection = Some(Arc::new(Mutex::new(connection)));
+        connection.set_write_limiter(self.write_limiter.clone());
+        self.connection = Some(connection);
         self.state = SessionState::Active;
         self.touch();
         Ok(())
     }
 
     /// Get connection (clone of Arc)
-    pub fn get_connection(&self) -> Option<Arc<Mutex<SerialConnection>>> {
+    pub fn get_connection(&self) -> Option<Arc<SerialConnection>> {
         self.connection.clone()
     }
 
@@ -223,23 +479,32 @@ impl SerialSession {
 
     /// Set error state
     pub fn set_error(&mut self, error: String) {
+        self.stats.record_error(error.as_str());
         self.state = SessionState::Error(error);
-        self.stats.record_error();
         self.touch();
     }
 
     /// Record data sent
     pub fn record_send(&mut self, bytes: usize) {
         self.stats.record_send(bytes);
+        self.recover();
         self.touch();
     }
 
     /// Record data received
     pub fn record_receive(&mut self, bytes: usize) {
         self.stats.record_receive(bytes);
+        self.recover();
         self.touch();
     }
 
+    /// Traffic flowing again clears an error left by a failed read or write
+    fn recover(&mut self) {
+        if self.has_connection() && matches!(self.state, SessionState::Error(_)) {
+            self.state = SessionState::Active;
+        }
+    }
+
     /// Attempt reconnection
     pub fn attempt_reconnect(&mut self) -> bool {
         if self.reconnect_attempts >= self.config.max_reconnect_attempts {
@@ -257,6 +522,11 @@ impl SerialSession {
         self.reconnect_attempts = 0;
     }
 
+    /// Reconnection attempts made since the session was last connected
+    pub fn reconnect_attempts(&self) -> u32 {
+        self.reconnect_attempts
+    }
+
     /// Close session
     pub fn close(&mut self) {
         self.state = SessionState::Closing;
@@ -267,6 +537,22 @@ impl SerialSession {
         self.state = SessionState::Closed;
     }
 
+    /// Record a lifecycle event, evicting the oldest event when full
+    pub fn record_event(&mut self, message: impl Into<String>) {
+        if self.events.len() == SESSION_EVENT_CAPACITY {
+            self.events.pop_front();
+        }
+        self.events.push_back(SessionEvent {
+            timestamp: Utc::now(),
+            message: message.into(),
+        });
+    }
+
+    /// All retained events, oldest first
+    pub fn events(&self) -> Vec<SessionEvent> {
+        self.events.iter().cloned().collect()
+    }
+
     /// Get session age in seconds
     pub fn age_seconds(&self) -> i64 {
         Utc::now().signed_duration_since(self.created_at).num_seconds()
@@ -274,7 +560,7 @@ impl SerialSession {
 
     /// Get seconds since last access
     pub fn idle_seconds(&self) -> i64 {
-        Utc::now().signed_duration_since(self.last_accessed).num_seconds()
+        self.touched.elapsed().as_secs() as i64
     }
 
     /// Check if session is idle for more than the specified duration
@@ -286,14 +572,17 @@ impl SerialSession {
     pub fn info(&self) -> SessionInfo {
         SessionInfo {
             session_id: self.session_id.clone(),
-            port_name: self.config.port_name.clone(),
+            port_name: self.port_name().to_string(),
             state: self.state.clone(),
             created_at: self.created_at,
             last_accessed: self.last_accessed,
             age_seconds: self.age_seconds(),
             idle_seconds: self.idle_seconds(),
             has_connection: self.has_connection(),
+            connection_id: self.connection_id().map(str::to_string),
+            device: self.device.clone(),
             config: self.config.clone(),
+            rates: self.stats.rates(self.age_seconds()),
             stats: self.stats.clone(),
         }
     }
@@ -310,8 +599,13 @@ pub struct SessionInfo {
     pub age_seconds: i64,
     pub idle_seconds: i64,
     pub has_connection: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connection_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device: Option<DeviceIdentity>,
     pub config: SessionConfig,
     pub stats: SessionStats,
+    pub rates: SessionRates,
 }
 
 #[cfg(test)]
@@ -364,4 +658,30 @@ mod tests {
         assert_eq!(session.stats.messages_received, 1);
     }
 
+    #[test]
+    fn test_session_rates() {
+        let mut session = SerialSession::new(SessionConfig::default());
+        session.record_send(300);
+        session.record_receive(600);
+        session.record_receive(600);
+        session.set_error("Read failed".to_string());
+
+        // Over the second the session has been around, not the whole window
+        let rates = session.stats.rates(0);
+        assert_eq!(rates.window_seconds, 1);
+        assert_eq!(rates.sent_bytes_per_sec, 300.0);
+        assert_eq!(rates.received_bytes_per_sec, 1200.0);
+        assert_eq!(rates.errors, 1);
+        assert_eq!(rates.error_rate, Some(0.25));
+        let rates = session.stats.rates(3600);
+        assert_eq!(rates.window_seconds, RATE_WINDOW_SECS);
+        assert_eq!(rates.received_bytes_per_sec, 20.0);
+        assert_eq!(session.stats.last_error.as_deref(), Some("Read failed"));
+
+        // Rates are not saved
+        let saved: SessionStats = serde_json::from_str(&serde_json::to_string(&session.stats).unwrap()).unwrap();
+        assert_eq!(saved.errors_count, 1);
+        assert_eq!(saved.rates(3600).errors, 0);
+    }
+
 }
\ No newline at end of file
@@ -0,0 +1,60 @@
+//! Saved session definitions
+//!
+//! Sessions created with `create_session` are saved under
+//! `server.state_directory` with their settings, device identity and traffic
+//! statistics, so a restarted server has them back. Their connections are not
+//! reopened: restored sessions start out suspended until connected again.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use super::session::{SessionConfig, SessionEvent, SessionStats};
+use crate::serial::DeviceIdentity;
+use crate::state::{load_state, PersistedState};
+
+/// File under the state directory holding the sessions
+pub const SESSIONS_FILE: &str = "sessions.json";
+
+/// A session as saved between server runs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedSession {
+    pub config: SessionConfig,
+    pub created_at: DateTime<Utc>,
+    pub stats: SessionStats,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<DeviceIdentity>,
+    /// Event log, carried on from run to run
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<SessionEvent>,
+}
+
+/// Saved sessions, keyed by session ID
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct SavedSessions {
+    pub sessions: BTreeMap<String, SavedSession>,
+}
+
+impl PersistedState for SavedSessions {
+    const KIND: &'static str = "sessions";
+    const VERSION: u32 = 1;
+}
+
+impl SavedSessions {
+    /// Load the sessions saved at `path`, or none if nothing was saved
+    ///
+    /// Unreadable state, such as a file written by a newer server, is logged
+    /// and gives `None`, so the caller can leave the file alone.
+    pub fn load(path: &Path) -> Option<Self> {
+        match load_state::<SavedSessions>(path) {
+            Ok(saved) => Some(saved.unwrap_or_default()),
+            Err(e) => {
+                warn!("Saved sessions not loaded: {}", e);
+                None
+            }
+        }
+    }
+}
@@ -0,0 +1,239 @@
+//! Versioned persisted state
+//!
+//! State the server keeps between runs is saved inside an envelope naming its
+//! kind and format version. Loading a file written by an older server migrates
+//! it one version at a time to the current format, after keeping a copy of the
+//! original. A file written by a newer server is refused and left untouched, so
+//! running an older build for a while never discards state.
+
+use std::path::{Path, PathBuf};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::error::StateError;
+
+/// A state format that can be saved with [`save_state`] and loaded with [`load_state`]
+pub trait PersistedState: Serialize + DeserializeOwned {
+    /// Name recorded in the envelope, e.g. `"session"`
+    const KIND: &'static str;
+
+    /// Current format version, starting at 1
+    const VERSION: u32;
+
+    /// Upgrade `data` from format version `from` to `from + 1`
+    ///
+    /// Version 0 is a file saved before versioning, holding the bare data.
+    fn migrate(from: u32, data: Value) -> Result<Value, StateError> {
+        let _ = data;
+        Err(StateError::NoMigration {
+            kind: Self::KIND.to_string(),
+            from,
+        })
+    }
+}
+
+/// On-disk wrapper around a state value
+#[derive(Debug, Serialize, Deserialize)]
+struct Envelope {
+    kind: String,
+    version: u32,
+    data: Value,
+}
+
+/// Save `state` to `path` in the current format
+///
+/// The file is written next to `path` and renamed over it, so a crash leaves
+/// either the old or the new state, never a mix.
+pub fn save_state<T: PersistedState>(path: &Path, state: &T) -> Result<(), StateError> {
+    let data = serde_json::to_value(state).map_err(|e| malformed(path, e))?;
+    write_envelope(path, T::KIND, T::VERSION, data)
+}
+
+/// Load the state saved at `path`, migrating it to the current format
+///
+/// Returns `None` when no state was saved. A migrated file is rewritten in the
+/// current format; the original is kept as `<path>.v<N>.bak`.
+pub fn load_state<T: PersistedState>(path: &Path) -> Result<Option<T>, StateError> {
+    let content = match std::fs::read(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(io_error(path, e)),
+    };
+
+    let value: Value = serde_json::from_slice(&content).map_err(|e| malformed(path, e))?;
+    let is_envelope = ["kind", "version", "data"].iter().all(|key| value.get(key).is_some());
+    let envelope = if is_envelope {
+        serde_json::from_value(value).map_err(|e| malformed(path, e))?
+    } else {
+        Envelope {
+            kind: T::KIND.to_string(),
+            version: 0,
+            data: value,
+        }
+    };
+
+    if envelope.kind != T::KIND {
+        return Err(StateError::KindMismatch {
+            path: path.display().to_string(),
+            expected: T::KIND.to_string(),
+            actual: envelope.kind,
+        });
+    }
+    if envelope.version > T::VERSION {
+        return Err(StateError::TooNew {
+            path: path.display().to_string(),
+            kind: T::KIND.to_string(),
+            version: envelope.version,
+            supported: T::VERSION,
+        });
+    }
+
+    let saved_version = envelope.version;
+    let mut data = envelope.data;
+    for from in saved_version..T::VERSION {
+        data = T::migrate(from, data)?;
+    }
+    let state: T = serde_json::from_value(data).map_err(|e| malformed(path, e))?;
+
+    if saved_version < T::VERSION {
+        std::fs::write(backup_path(path, saved_version), &content).map_err(|e| io_error(path, e))?;
+        save_state(path, &state)?;
+    }
+
+    Ok(Some(state))
+}
+
+fn write_envelope(path: &Path, kind: &str, version: u32, data: Value) -> Result<(), StateError> {
+    let envelope = Envelope {
+        kind: kind.to_string(),
+        version,
+        data,
+    };
+    let content = serde_json::to_vec_pretty(&envelope).map_err(|e| malformed(path, e))?;
+
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent).map_err(|e| io_error(path, e))?;
+    }
+    let temp = sibling_path(path, ".tmp");
+    let write = || -> std::io::Result<()> {
+        let mut file = std::fs::File::create(&temp)?;
+        std::io::Write::write_all(&mut file, &content)?;
+        file.sync_all()?;
+        std::fs::rename(&temp, path)
+    };
+    write().map_err(|e| io_error(path, e))
+}
+
+/// Copy of a file as it was before migrating from format `version`
+fn backup_path(path: &Path, version: u32) -> PathBuf {
+    sibling_path(path, &format!(".v{}.bak", version))
+}
+
+fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    name.into()
+}
+
+fn io_error(path: &Path, error: std::io::Error) -> StateError {
+    StateError::Io {
+        path: path.display().to_string(),
+        reason: error.to_string(),
+    }
+}
+
+fn malformed(path: &Path, error: serde_json::Error) -> StateError {
+    StateError::Malformed {
+        path: path.display().to_string(),
+        reason: error.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// v1 stored `baud`, v2 renamed it to `baud_rate`
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Profile {
+        port: String,
+        baud_rate: u32,
+    }
+
+    impl PersistedState for Profile {
+        const KIND: &'static str = "profile";
+        const VERSION: u32 = 2;
+
+        fn migrate(from: u32, mut data: Value) -> Result<Value, StateError> {
+            match from {
+                0 => Ok(data),
+                1 => {
+                    let object = data.as_object_mut().ok_or(StateError::NoMigration {
+                        kind: Self::KIND.to_string(),
+                        from,
+                    })?;
+                    if let Some(baud) = object.remove("baud") {
+                        object.insert("baud_rate".to_string(), baud);
+                    }
+                    Ok(data)
+                }
+                _ => Err(StateError::NoMigration {
+                    kind: Self::KIND.to_string(),
+                    from,
+                }),
+            }
+        }
+    }
+
+    fn profile() -> Profile {
+        Profile {
+            port: "/dev/ttyUSB0".to_string(),
+            baud_rate: 115200,
+        }
+    }
+
+    #[test]
+    fn test_state_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("profiles/bench.json");
+
+        assert!(load_state::<Profile>(&path).unwrap().is_none());
+        save_state(&path, &profile()).unwrap();
+        assert_eq!(load_state::<Profile>(&path).unwrap(), Some(profile()));
+        assert!(!sibling_path(&path, ".tmp").exists());
+    }
+
+    #[test]
+    fn test_state_migration_keeps_original() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bench.json");
+        let old = r#"{"kind":"profile","version":1,"data":{"port":"/dev/ttyUSB0","baud":115200}}"#;
+        std::fs::write(&path, old).unwrap();
+
+        assert_eq!(load_state::<Profile>(&path).unwrap(), Some(profile()));
+        assert_eq!(std::fs::read_to_string(backup_path(&path, 1)).unwrap(), old);
+        let saved: Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(saved["version"], 2);
+
+        // Saved before versioning existed
+        std::fs::write(&path, r#"{"port":"/dev/ttyUSB0","baud":115200}"#).unwrap();
+        assert_eq!(load_state::<Profile>(&path).unwrap(), Some(profile()));
+        assert!(backup_path(&path, 0).exists());
+    }
+
+    #[test]
+    fn test_newer_state_left_untouched() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bench.json");
+        let newer = r#"{"kind":"profile","version":3,"data":{"port":"/dev/ttyUSB0","rate":115200}}"#;
+        std::fs::write(&path, newer).unwrap();
+
+        assert!(matches!(load_state::<Profile>(&path), Err(StateError::TooNew { version: 3, .. })));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), newer);
+
+        let session = r#"{"kind":"session","version":1,"data":{}}"#;
+        std::fs::write(&path, session).unwrap();
+        assert!(matches!(load_state::<Profile>(&path), Err(StateError::KindMismatch { .. })));
+    }
+}
@@ -0,0 +1,105 @@
+//! Client authentication
+//!
+//! With `security.enable_authentication`, a client is checked on initialize:
+//! its `clientInfo.name` must be one of `security.allowed_clients`, when that
+//! list is set, and it must send `security.auth_token` as `_meta.authToken`,
+//! when a token is configured. A refused client gets an error in place of the
+//! initialize result and the server stops. Client names are chosen by the
+//! client itself, so the token is what keeps strangers out; the names then
+//! scope the tools each client may call through `security.client_tools`.
+
+use std::collections::BTreeSet;
+
+use rmcp::model::Meta;
+
+use crate::config::SecurityConfig;
+
+/// Key of the initialize request's `_meta` carrying the shared token
+pub const AUTH_TOKEN_META_KEY: &str = "authToken";
+
+/// Tools a client may call
+#[derive(Debug, Clone, Default)]
+pub struct ClientAccess {
+    /// Allowed tool names, or every tool when `None`
+    tools: Option<BTreeSet<String>>,
+}
+
+impl ClientAccess {
+    pub fn allows(&self, tool: &str) -> bool {
+        self.tools.as_ref().is_none_or(|tools| tools.contains(tool))
+    }
+}
+
+/// Check a client introducing itself as `client_name`, returning the tools it may call
+///
+/// The error says why the client was refused, for the server log; the client
+/// itself should only learn that it was.
+pub fn authenticate(security: &SecurityConfig, client_name: &str, meta: &Meta) -> Result<ClientAccess, String> {
+    if security.enable_authentication {
+        if !security.allowed_clients.is_empty() && !security.allowed_clients.iter().any(|allowed| allowed == client_name) {
+            return Err(format!("client {} is not in security.allowed_clients", client_name));
+        }
+        if let Some(expected) = security.auth_token.as_deref().filter(|token| !token.is_empty()) {
+            match meta.get(AUTH_TOKEN_META_KEY).and_then(|token| token.as_str()) {
+                None => return Err(format!("client {} sent no _meta.{}", client_name, AUTH_TOKEN_META_KEY)),
+                Some(token) if !tokens_match(token, expected) => {
+                    return Err(format!("client {} sent a wrong _meta.{}", client_name, AUTH_TOKEN_META_KEY));
+                }
+                Some(_) => {}
+            }
+        }
+    }
+
+    let tools = security
+        .client_tools
+        .get(client_name)
+        .map(|tools| tools.iter().cloned().collect());
+    Ok(ClientAccess { tools })
+}
+
+/// Compare tokens in time independent of where they first differ
+fn tokens_match(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta_with_token(token: &str) -> Meta {
+        let mut meta = Meta::new();
+        meta.insert(AUTH_TOKEN_META_KEY.to_string(), token.into());
+        meta
+    }
+
+    #[test]
+    fn test_authenticate() {
+        let mut security = SecurityConfig::default();
+        security.client_tools.insert("viewer".to_string(), vec!["list_ports".to_string(), "read".to_string()]);
+
+        // Without authentication anyone gets in, still scoped by name
+        let access = authenticate(&security, "viewer", &Meta::new()).unwrap();
+        assert!(access.allows("read"));
+        assert!(!access.allows("write"));
+        assert!(authenticate(&security, "anyone", &Meta::new()).unwrap().allows("write"));
+
+        security.enable_authentication = true;
+        security.allowed_clients = vec!["viewer".to_string(), "operator".to_string()];
+        security.auth_token = Some("s3cret".to_string());
+        assert!(authenticate(&security, "operator", &meta_with_token("s3cret")).unwrap().allows("write"));
+        assert!(authenticate(&security, "viewer", &meta_with_token("s3cret")).unwrap().allows("list_ports"));
+        assert!(authenticate(&security, "stranger", &meta_with_token("s3cret")).is_err());
+        assert!(authenticate(&security, "operator", &meta_with_token("s3cre")).is_err());
+        assert!(authenticate(&security, "operator", &meta_with_token("wrong!")).is_err());
+        assert!(authenticate(&security, "operator", &Meta::new()).is_err());
+
+        // A token alone admits any name
+        security.allowed_clients.clear();
+        assert!(authenticate(&security, "stranger", &meta_with_token("s3cret")).is_ok());
+    }
+}
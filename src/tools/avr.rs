@@ -0,0 +1,177 @@
+//! AVR bootloader flashing tool
+//!
+//! Flash an Intel HEX image to an Arduino-class board through its STK500
+//! bootloader, resetting it through DTR as the IDE does, for simple flash
+//! jobs that would otherwise need avrdude.
+
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use rmcp::{
+    handler::server::tool::Parameters,
+    model::*,
+    tool, tool_router, ErrorData as McpError,
+};
+use tracing::{debug, error, info};
+
+use super::serial_handler::SerialHandler;
+use super::types::*;
+use super::verify::compare;
+use crate::protocol::ihex;
+use crate::protocol::stk500::{part, Stk500, Stk500Error, Stk500Version};
+use crate::serial::{LocalSerialError, SerialConnection};
+
+/// Largest HEX file read, about twice the text of a full 256 KiB part
+const MAX_HEX_FILE: u64 = 1024 * 1024;
+
+/// DTR held released before it is asserted to pull the reset line, and the wait after
+const RESET_RELEASE: Duration = Duration::from_millis(250);
+const RESET_SETTLE: Duration = Duration::from_millis(50);
+
+/// Syncs tried after the reset, covering the bootloader's start-up
+pub(super) const SYNC_ATTEMPTS: u32 = 10;
+
+#[tool_router(router = avr_router, vis = "pub(crate)")]
+impl SerialHandler {
+    #[tool(description = "Flash an Intel HEX image to an Arduino-class AVR board through its bootloader, as avrdude does. protocol stk500v1 for optiboot (Uno, Nano, Pro Mini; open the connection at 115200, or 57600 for older Nano bootloaders) or stk500v2 for the Mega 2560 (115200). Resets the board through DTR first unless reset is false, checks the part's signature, writes the image page by page, and reads it back to verify unless verify is false. The connection must be in raw mode")]
+    async fn avr_flash(&self, Parameters(args): Parameters<AvrFlashArgs>) -> Result<CallToolResult, McpError> {
+        let version = Stk500Version::parse(&args.protocol).map_err(|e| McpError::invalid_params(format!("Error: {}", e), None))?;
+        let (start, image) = read_hex(&args.path)?;
+        let connection = self.raw_connection(&args.connection_id, "flash an AVR board").await?;
+        debug!("Flashing {} ({} bytes) over {} on connection {}", args.path, image.len(), version, args.connection_id);
+
+        let _exchange = connection.begin_exchange().await;
+        let started = Instant::now();
+        if args.reset {
+            reset_board(&connection).await.map_err(|e| McpError::internal_error(format!("Error: Failed to set DTR/RTS - {}", e), None))?;
+        }
+
+        let mut programmer = Stk500::new(&connection, version);
+        let mut written = 0;
+        let mut summary = Vec::new();
+        let result = async {
+            let bootloader = programmer.sync(SYNC_ATTEMPTS).await?;
+            summary.push(format!("Bootloader: {} {}", version, bootloader).trim_end().to_string());
+            let (name, page_size) = identify(&mut programmer, args.page_size, start, image.len()).await?;
+            summary.push(format!("Part: {}", name));
+            let (first, pages) = pad_pages(start, &image, page_size);
+            programmer.enter_programming().await?;
+            write_pages(&mut programmer, first, &pages, page_size, &mut |bytes| written = bytes).await?;
+            if args.verify {
+                verify_pages(&mut programmer, first, &pages, page_size).await?;
+            }
+            // Leaving programming mode starts the new program
+            programmer.leave_programming().await
+        }
+        .await;
+        if let Err(e) = result {
+            error!("Flashing the AVR board on connection {} failed: {}", args.connection_id, e);
+            connection.record_event(format!("AVR flash failed: {}", e)).await;
+            summary.push(format!("Bytes written: {}", written));
+            let error_msg = format!("Error: AVR flash failed - {}\n{}", e, summary.join("\n"));
+            return Err(McpError::internal_error(error_msg, None));
+        }
+
+        info!("Flashed {} bytes to the AVR board on connection {}", image.len(), args.connection_id);
+        connection.record_event(format!("AVR flash: {} bytes from {}", image.len(), args.path)).await;
+        let mut message = format!(
+            "AVR flash complete\nConnection ID: {}\n{}\nAddress: 0x{:05X}\nBytes: {}\nTime: {:.1}s",
+            args.connection_id,
+            summary.join("\n"),
+            start,
+            image.len(),
+            started.elapsed().as_secs_f64()
+        );
+        if args.verify {
+            message.push_str("\nVerified: yes");
+        }
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+}
+
+/// The image in a HEX file and the address it starts at, gaps filled with erased bytes
+pub(super) fn read_hex(path: &str) -> Result<(u32, Vec<u8>), McpError> {
+    let metadata = std::fs::metadata(path).map_err(|e| McpError::invalid_params(format!("Error: Cannot access {} - {}", path, e), None))?;
+    if metadata.len() > MAX_HEX_FILE {
+        return Err(McpError::invalid_params(format!("Error: {} is larger than {} bytes", path, MAX_HEX_FILE), None));
+    }
+    let text = std::fs::read_to_string(path).map_err(|e| McpError::internal_error(format!("Error: Failed to read {} - {}", path, e), None))?;
+    let segments = ihex::parse(&text).map_err(|e| McpError::invalid_params(format!("Error: {} is not a valid HEX file - {}", path, e), None))?;
+    let (start, image) = ihex::flatten(&segments, 0xFF);
+    if image.is_empty() {
+        return Err(McpError::invalid_params(format!("Error: {} has no data", path), None));
+    }
+    Ok((start, image))
+}
+
+/// Reset the board through DTR/RTS so its bootloader runs
+pub(super) async fn reset_board(connection: &SerialConnection) -> Result<(), LocalSerialError> {
+    // Asserting DTR pulls RESET low through the board's capacitor
+    connection.set_control_lines(Some(false), Some(false)).await?;
+    tokio::time::sleep(RESET_RELEASE).await;
+    connection.set_control_lines(Some(true), Some(true)).await?;
+    tokio::time::sleep(RESET_SETTLE).await;
+    Ok(())
+}
+
+/// Name and page size of the part, checking an image of `length` bytes at `start` fits its flash
+pub(super) async fn identify(
+    programmer: &mut Stk500<'_>,
+    page_size: Option<u32>,
+    start: u32,
+    length: usize,
+) -> Result<(String, usize), Stk500Error> {
+    let signature = programmer.signature().await?;
+    let found = part(signature);
+    let name = found.map_or_else(|| format!("unknown part {:02X} {:02X} {:02X}", signature[0], signature[1], signature[2]), |part| part.name.to_string());
+    let page_size = match (page_size, found) {
+        (Some(size), _) => size as usize,
+        (None, Some(part)) => part.page_size,
+        (None, None) => return Err(Stk500Error::Protocol(format!("Page size of the {} is not known; give page_size", name))),
+    };
+    if let Some(part) = found {
+        if start as usize + length > part.flash_size {
+            return Err(Stk500Error::Protocol(format!("The image ends at 0x{:05X}, beyond the {}'s {} bytes of flash", start as usize + length, name, part.flash_size)));
+        }
+    }
+    if page_size == 0 || !page_size.is_power_of_two() {
+        return Err(Stk500Error::Protocol(format!("Page size {} is not a power of two", page_size)));
+    }
+    Ok((name, page_size))
+}
+
+/// Whole pages holding the image, padded with erased bytes on either side, and the address of the first
+pub(super) fn pad_pages(start: u32, image: &[u8], page_size: usize) -> (usize, Vec<u8>) {
+    let first = start as usize / page_size * page_size;
+    let mut pages = vec![0xFF; start as usize - first];
+    pages.extend(image);
+    pages.resize(pages.len().next_multiple_of(page_size), 0xFF);
+    (first, pages)
+}
+
+/// Write the pages from `first`, telling `on_page` the bytes written so far
+pub(super) async fn write_pages(
+    programmer: &mut Stk500<'_>,
+    first: usize,
+    pages: &[u8],
+    page_size: usize,
+    on_page: &mut (dyn FnMut(usize) + Send),
+) -> Result<(), Stk500Error> {
+    for (index, page) in pages.chunks(page_size).enumerate() {
+        programmer.write_page(first + index * page_size, page).await?;
+        on_page((index + 1) * page_size);
+    }
+    Ok(())
+}
+
+/// Read the pages back, failing with where they differ
+pub(super) async fn verify_pages(programmer: &mut Stk500<'_>, first: usize, pages: &[u8], page_size: usize) -> Result<(), Stk500Error> {
+    let mut readback = Vec::with_capacity(pages.len());
+    for (index, page) in pages.chunks(page_size).enumerate() {
+        readback.extend(programmer.read_page(first + index * page_size, page.len()).await?);
+    }
+    match compare(first as u64, pages, &readback) {
+        Some(mismatch) => Err(Stk500Error::Protocol(mismatch.to_string())),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,132 @@
+//! Bridge tap tools
+//!
+//! Open two ports and forward traffic between them while logging both
+//! directions, to sniff an existing host application talking to a device.
+
+use std::future::Future;
+
+use rmcp::{
+    handler::server::tool::Parameters,
+    model::*,
+    tool, tool_router, ErrorData as McpError,
+};
+use tracing::info;
+
+use super::serial_handler::SerialHandler;
+use super::types::*;
+use crate::serial::{Bridge, BridgeRecord};
+
+/// Forwarded chunks kept for inspection unless the caller asks otherwise
+const DEFAULT_BRIDGE_RECORDS: usize = 10_000;
+
+/// Most forwarded chunks a bridge keeps
+const MAX_BRIDGE_RECORDS: usize = 1_000_000;
+
+#[tool_router(router = bridge_router, vis = "pub(crate)")]
+impl SerialHandler {
+    #[tool(description = "Start a bridge tap: open a host-side port and a device-side port, forward every byte between them and log both directions with timestamps. Put the host application on the host port (e.g. one end of a virtual null-modem pair) to sniff its traffic with the device. Do not read from the bridge's connections while it runs")]
+    async fn bridge_start(&self, Parameters(args): Parameters<BridgeStartArgs>) -> Result<CallToolResult, McpError> {
+        let max_records = args.max_records.unwrap_or(DEFAULT_BRIDGE_RECORDS);
+        if max_records == 0 || max_records > MAX_BRIDGE_RECORDS {
+            let error_msg = format!("Error: max_records must be between 1 and {}", MAX_BRIDGE_RECORDS);
+            return Err(McpError::invalid_params(error_msg, None));
+        }
+
+        let host = self.open_port(args.host).await?;
+        let device = match self.open_port(args.device).await {
+            Ok(device) => device,
+            Err(e) => {
+                let _ = self.close_connection(&host).await;
+                return Err(e);
+            }
+        };
+
+        let host = self.connection(&host).await?;
+        let device = self.connection(&device).await?;
+        let bridge_id = uuid::Uuid::new_v4().to_string();
+        host.record_event(format!("Bridged to {} by bridge {}", device.config().port, bridge_id)).await;
+        device.record_event(format!("Bridged to {} by bridge {}", host.config().port, bridge_id)).await;
+
+        let message = format!(
+            "Bridge started\nBridge ID: {}\nHost: {} (connection {})\nDevice: {} (connection {})\nKeeping the last {} chunks",
+            bridge_id,
+            host.config().port,
+            host.id(),
+            device.config().port,
+            device.id(),
+            max_records
+        );
+        info!("Bridge {} forwarding between {} and {}", bridge_id, host.config().port, device.config().port);
+        self.bridges.lock().await.insert(bridge_id, Bridge::start(host, device, max_records));
+
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+
+    #[tool(description = "Stop a bridge tap, close both of its ports and report its traffic totals")]
+    async fn bridge_stop(&self, Parameters(args): Parameters<BridgeStopArgs>) -> Result<CallToolResult, McpError> {
+        let bridge = self.bridges.lock().await.remove(&args.bridge_id).ok_or_else(|| unknown_bridge(&args.bridge_id))?;
+        bridge.stop();
+        let stats = bridge.stats().await;
+
+        for connection in [bridge.host(), bridge.device()] {
+            if let Err(e) = self.close_connection(connection.id()).await {
+                // Already closed with the close tool
+                info!("Bridge {} connection {} not closed: {}", args.bridge_id, connection.id(), e);
+            }
+        }
+
+        let message = format!(
+            "Bridge stopped\nBridge ID: {}\nHost to device: {} bytes\nDevice to host: {} bytes\nChunks: {}",
+            args.bridge_id, stats.host_to_device_bytes, stats.device_to_host_bytes, stats.records
+        );
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+
+    #[tool(description = "Inspect a bridge tap: traffic totals, whether it is still forwarding, and the most recent chunks in both directions with timestamps")]
+    async fn bridge_inspect(&self, Parameters(args): Parameters<BridgeInspectArgs>) -> Result<CallToolResult, McpError> {
+        let bridges = self.bridges.lock().await;
+        let bridge = bridges.get(&args.bridge_id).ok_or_else(|| unknown_bridge(&args.bridge_id))?;
+        let stats = bridge.stats().await;
+        let records = bridge.recent(args.limit).await;
+
+        let mut message = format!(
+            "Bridge ID: {}\nState: {}\nStarted: {}\nHost: {} (connection {})\nDevice: {} (connection {})\nHost to device: {} bytes\nDevice to host: {} bytes\nChunks: {} ({} dropped from the log)",
+            args.bridge_id,
+            if bridge.is_running() { "forwarding" } else { "stopped" },
+            bridge.started_at().to_rfc3339(),
+            bridge.host().config().port,
+            bridge.host().id(),
+            bridge.device().config().port,
+            bridge.device().id(),
+            stats.host_to_device_bytes,
+            stats.device_to_host_bytes,
+            stats.records,
+            stats.dropped_records
+        );
+        if let Some(error) = &stats.error {
+            message.push_str(&format!("\nError: {}", error));
+        }
+        if !records.is_empty() {
+            message.push_str(&format!("\n\nLast {} chunks:\n", records.len()));
+            message.push_str(&records.iter().map(format_record).collect::<Vec<_>>().join("\n"));
+        }
+
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+}
+
+fn unknown_bridge(bridge_id: &str) -> McpError {
+    McpError::invalid_params(format!("Error: Bridge {} not found", bridge_id), None)
+}
+
+/// Render a forwarded chunk as `time direction (n bytes) hex | text`
+fn format_record(record: &BridgeRecord) -> String {
+    format!(
+        "{} {} ({} bytes) {} | {:?}",
+        record.timestamp.format("%H:%M:%S%.3f"),
+        record.direction,
+        record.data.len(),
+        hex::encode(&record.data),
+        String::from_utf8_lossy(&record.data)
+    )
+}
@@ -0,0 +1,149 @@
+//! Traffic capture tools
+//!
+//! Tools that record a connection's traffic to files and replay recordings.
+
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use chrono::{DateTime, Utc};
+use rmcp::{
+    handler::server::tool::Parameters, model::*, tool, tool_router, ErrorData as McpError,
+};
+use tracing::{debug, error, info};
+
+use super::serial_handler::SerialHandler;
+use super::types::*;
+use crate::serial::{read_capture, CaptureFile, CaptureFormat, CaptureIntegrity, Direction, SerialConnection};
+
+#[tool_router(router = capture_router, vis = "pub(crate)")]
+impl SerialHandler {
+    #[tool(description = "Start recording all sent and received bytes of a connection to a capture file (raw, hexdump, or JSONL with direction and timestamp). Replaces any capture already running on the connection")]
+    async fn start_capture(&self, Parameters(args): Parameters<StartCaptureArgs>) -> Result<CallToolResult, McpError> {
+        debug!("Starting capture on connection {}", args.connection_id);
+
+        let format = args.format.as_deref().unwrap_or(&self.config.capture.format);
+        let format = CaptureFormat::parse(format).map_err(|e| McpError::invalid_params(format!("Error: {}", e), None))?;
+
+        let connection = self.connection(&args.connection_id).await?;
+        let path = self.start_connection_capture(&connection, args.path.map(PathBuf::from), format).await?;
+
+        let message = format!(
+            "Capture started\nConnection ID: {}\nFile: {}\nFormat: {}",
+            args.connection_id,
+            path.display(),
+            format
+        );
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+
+    #[tool(description = "Stop recording the traffic of a connection and close its capture file")]
+    async fn stop_capture(&self, Parameters(args): Parameters<StopCaptureArgs>) -> Result<CallToolResult, McpError> {
+        debug!("Stopping capture on connection {}", args.connection_id);
+
+        let connection = self.connection(&args.connection_id).await?;
+        let capture = match connection.stop_capture().await {
+            Ok(Some(capture)) => capture,
+            Ok(None) => {
+                let error_msg = format!("Error: No capture is running on connection {}", args.connection_id);
+                return Err(McpError::invalid_params(error_msg, None));
+            }
+            Err(e) => {
+                error!("Failed to close capture on connection {}: {}", args.connection_id, e);
+                let error_msg = format!("Error: Capture stopped but could not be closed cleanly - {}", e);
+                return Err(McpError::internal_error(error_msg, None));
+            }
+        };
+
+        info!("Stopped capture {} on connection {}", capture.path().display(), args.connection_id);
+        let message = format!(
+            "Capture stopped\nConnection ID: {}\nFile: {}\nFormat: {}\nBytes captured: {}",
+            args.connection_id,
+            capture.path().display(),
+            capture.format(),
+            capture.bytes_captured()
+        );
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+
+    #[tool(description = "Re-send the TX side of a recorded JSONL or hexdump capture to a connection, optionally with the original timing between chunks. Reproduces a bug sequence without the original client")]
+    async fn replay(&self, Parameters(args): Parameters<ReplayArgs>) -> Result<CallToolResult, McpError> {
+        debug!("Replaying {} to connection {}", args.path, args.connection_id);
+
+        if !args.speed.is_finite() || args.speed <= 0.0 {
+            return Err(McpError::invalid_params("Error: speed must be greater than 0", None));
+        }
+
+        let contents = read_capture(Path::new(&args.path)).map_err(|e| {
+            McpError::invalid_params(format!("Error: Cannot read capture {} - {}", args.path, e), None)
+        })?;
+        if let CaptureIntegrity::Corrupt(reason) = &contents.integrity {
+            let error_msg = format!("Error: Capture {} failed its integrity check - {}", args.path, reason);
+            return Err(McpError::invalid_params(error_msg, None));
+        }
+        let mut chunks = Vec::new();
+        for record in contents.records.into_iter().filter(|r| r.direction == Direction::Tx) {
+            let data = hex::decode(&record.data).map_err(|e| {
+                McpError::invalid_params(format!("Error: Invalid data in capture {} - {}", args.path, e), None)
+            })?;
+            chunks.push((record.timestamp, data));
+        }
+
+        let connection = self.connection(&args.connection_id).await?;
+        connection
+            .record_event(format!("Replaying {} chunk(s) from {}", chunks.len(), args.path))
+            .await;
+
+        let started = Instant::now();
+        let mut bytes_sent = 0;
+        let mut previous: Option<DateTime<Utc>> = None;
+        for (timestamp, data) in &chunks {
+            if let (true, Some(previous)) = (args.honor_timing, previous) {
+                let gap = (*timestamp - previous).to_std().unwrap_or_default();
+                tokio::time::sleep(gap.div_f64(args.speed)).await;
+            }
+            previous = Some(*timestamp);
+
+            bytes_sent += connection.write(data).await.map_err(|e| {
+                error!("Replay to connection {} failed: {}", args.connection_id, e);
+                McpError::internal_error(format!("Error: Data sending failed after {} bytes - {}", bytes_sent, e), None)
+            })?;
+        }
+
+        info!("Replayed {} chunks from {} to connection {}", chunks.len(), args.path, args.connection_id);
+        let message = format!(
+            "Replay completed\nConnection ID: {}\nCapture: {} ({})\nChunks sent: {}\nBytes sent: {}\nTiming: {}\nDuration: {} ms",
+            args.connection_id,
+            args.path,
+            contents.integrity,
+            chunks.len(),
+            bytes_sent,
+            if args.honor_timing { format!("original x{}", args.speed) } else { "back to back".to_string() },
+            started.elapsed().as_millis()
+        );
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+}
+
+impl SerialHandler {
+    /// Start a capture on `connection`, naming the file after its port when no path is given
+    pub(crate) async fn start_connection_capture(
+        &self,
+        connection: &SerialConnection,
+        path: Option<PathBuf>,
+        format: CaptureFormat,
+    ) -> Result<PathBuf, McpError> {
+        let path = path.unwrap_or_else(|| {
+            let name = CaptureFile::default_file_name(&connection.config().port, format, Utc::now());
+            self.config.capture.directory.join(name)
+        });
+
+        connection.start_capture(&path, format).await.map_err(|e| {
+            error!("Failed to start capture to {}: {}", path.display(), e);
+            McpError::internal_error(format!("Error: Failed to start capture to {} - {}", path.display(), e), None)
+        })?;
+
+        info!("Capturing connection {} to {}", connection.id(), path.display());
+        Ok(path)
+    }
+}
@@ -0,0 +1,112 @@
+//! Transceiver CAT tools
+//!
+//! Read and set an amateur radio transceiver's frequency, mode and PTT over
+//! Icom CI-V or the Kenwood and Yaesu ASCII command sets.
+
+use std::future::Future;
+
+use rmcp::{
+    handler::server::tool::Parameters,
+    model::*,
+    tool, tool_router, ErrorData as McpError,
+};
+use serde_json::json;
+use tracing::{debug, error, info};
+
+use super::serial_handler::SerialHandler;
+use super::types::*;
+use super::warnings::Warnings;
+use crate::protocol::cat::{self, CatError, CatProtocol};
+
+#[tool_router(router = cat_router, vis = "pub(crate)")]
+impl SerialHandler {
+    #[tool(description = "Read a transceiver's frequency in Hz, mode (LSB, USB, CW, AM, FM...) and whether it is transmitting, over CAT: protocol icom (CI-V, with civ_address, default 0x94 for the IC-7300), kenwood or yaesu (newer ASCII command radios such as the FT-991 and FT-891)")]
+    async fn cat_get(&self, Parameters(args): Parameters<CatGetArgs>) -> Result<CallToolResult, McpError> {
+        let protocol = cat_protocol(&args.protocol, args.civ_address)?;
+        let connection = self.raw_connection(&args.connection_id, "use CAT").await?;
+        debug!("Reading radio state over {} on connection {}", protocol, args.connection_id);
+
+        let frequency = cat::frequency(&connection, protocol, args.timeout_ms)
+            .await
+            .map_err(|e| cat_failed(&args.connection_id, "Frequency read", e))?;
+        let mut warnings = Warnings::new();
+        let mode = cat::mode(&connection, protocol, args.timeout_ms)
+            .await
+            .map_err(|e| warnings.push(format!("Mode read failed: {}", e)))
+            .ok();
+        let transmitting = cat::ptt(&connection, protocol, args.timeout_ms)
+            .await
+            .map_err(|e| warnings.push(format!("PTT read failed: {}", e)))
+            .ok();
+
+        let state = json!({ "frequency_hz": frequency, "mode": mode, "transmitting": transmitting });
+        let message = format!(
+            "Radio state\nConnection ID: {}\nProtocol: {}\n{}",
+            args.connection_id,
+            protocol,
+            serde_json::to_string_pretty(&state).unwrap_or_default()
+        );
+        warnings.into_result(message)
+    }
+
+    #[tool(description = "Set a transceiver's frequency in Hz, mode and/or PTT over CAT (protocols as for cat_get), in that order, then read each back to confirm it took. Mode names: LSB, USB, CW, CW-R, AM, FM, RTTY, RTTY-R, plus WFM and DV on Icom and DATA-LSB, DATA-USB, DATA-FM, FM-N, AM-N and C4FM on Yaesu. transmit true keys the transmitter: make sure an antenna or dummy load is connected")]
+    async fn cat_set(&self, Parameters(args): Parameters<CatSetArgs>) -> Result<CallToolResult, McpError> {
+        let protocol = cat_protocol(&args.protocol, args.civ_address)?;
+        if args.frequency_hz.is_none() && args.mode.is_none() && args.transmit.is_none() {
+            return Err(McpError::invalid_params("Error: Give a frequency_hz, mode or transmit to set".to_string(), None));
+        }
+        if let Some(mode) = &args.mode {
+            if !protocol.mode_names().iter().any(|name| name.eq_ignore_ascii_case(mode)) {
+                let error_msg = format!("Error: Unknown mode {} for {}, use one of {}", mode, protocol, protocol.mode_names().join(", "));
+                return Err(McpError::invalid_params(error_msg, None));
+            }
+        }
+        let connection = self.raw_connection(&args.connection_id, "use CAT").await?;
+
+        let mut message = format!("Radio set\nConnection ID: {}\nProtocol: {}", args.connection_id, protocol);
+        let mut warnings = Warnings::new();
+        if let Some(hz) = args.frequency_hz {
+            cat::set_frequency(&connection, protocol, hz, args.timeout_ms)
+                .await
+                .map_err(|e| cat_failed(&args.connection_id, "Frequency set", e))?;
+            match cat::frequency(&connection, protocol, args.timeout_ms).await {
+                Ok(actual) if actual == hz => message.push_str(&format!("\nFrequency: {} Hz", actual)),
+                Ok(actual) => warnings.push(format!("Frequency set to {} Hz but the radio reports {} Hz", hz, actual)),
+                Err(e) => warnings.push(format!("Frequency set, but reading it back failed: {}", e)),
+            }
+        }
+        if let Some(mode) = &args.mode {
+            cat::set_mode(&connection, protocol, mode, args.timeout_ms)
+                .await
+                .map_err(|e| cat_failed(&args.connection_id, "Mode set", e))?;
+            match cat::mode(&connection, protocol, args.timeout_ms).await {
+                Ok(actual) if actual.eq_ignore_ascii_case(mode) => message.push_str(&format!("\nMode: {}", actual)),
+                Ok(actual) => warnings.push(format!("Mode set to {} but the radio reports {}", mode, actual)),
+                Err(e) => warnings.push(format!("Mode set, but reading it back failed: {}", e)),
+            }
+        }
+        if let Some(transmit) = args.transmit {
+            cat::set_ptt(&connection, protocol, transmit, args.timeout_ms)
+                .await
+                .map_err(|e| cat_failed(&args.connection_id, "PTT set", e))?;
+            let state = if transmit { "transmitting" } else { "receiving" };
+            info!("Radio on connection {} set to {}", args.connection_id, state);
+            connection.record_event(format!("CAT PTT: {}", state)).await;
+            match cat::ptt(&connection, protocol, args.timeout_ms).await {
+                Ok(actual) if actual == transmit => message.push_str(&format!("\nPTT: {}", state)),
+                Ok(_) => warnings.push(format!("PTT set to {} but the radio reports otherwise", state)),
+                Err(e) => warnings.push(format!("PTT set, but reading it back failed: {}", e)),
+            }
+        }
+        warnings.into_result(message)
+    }
+}
+
+fn cat_protocol(protocol: &str, civ_address: Option<u8>) -> Result<CatProtocol, McpError> {
+    CatProtocol::parse(protocol, civ_address).map_err(|e| McpError::invalid_params(format!("Error: {}", e), None))
+}
+
+fn cat_failed(connection_id: &str, action: &str, e: CatError) -> McpError {
+    error!("{} on connection {} failed: {}", action, connection_id, e);
+    McpError::internal_error(format!("Error: {} failed - {}", action, e), None)
+}
@@ -0,0 +1,33 @@
+//! Fault injection tools
+//!
+//! Debug tools that break connections on purpose, so recovery behaviour can be
+//! exercised without touching hardware. Only registered when
+//! `server.debug_tools` is enabled.
+
+use std::future::Future;
+
+use rmcp::{
+    handler::server::tool::Parameters, model::*, tool, tool_router, ErrorData as McpError,
+};
+use tracing::warn;
+
+use super::serial_handler::SerialHandler;
+use super::types::*;
+
+#[tool_router(router = chaos_router, vis = "pub(crate)")]
+impl SerialHandler {
+    #[tool(description = "DEBUG: Drop a connection as if its cable were pulled. The reader stops with an error and further reads and writes fail, so recovery handling can be tested. Close and reopen the connection afterwards")]
+    async fn simulate_disconnect(&self, Parameters(args): Parameters<SimulateDisconnectArgs>) -> Result<CallToolResult, McpError> {
+        let connection = self.connection(&args.connection_id).await?;
+        warn!("Simulating cable pull on connection {} ({})", args.connection_id, connection.config().port);
+
+        connection.simulate_disconnect().await;
+
+        let message = format!(
+            "Simulated cable pull on {} ({}). Reads and writes now fail as after a real disconnect; close and reopen the connection to recover.",
+            args.connection_id,
+            connection.config().port
+        );
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+}
@@ -0,0 +1,81 @@
+//! Checksum tools
+//!
+//! Compute or verify the checksum a device protocol expects over some bytes,
+//! from the common CRC presets or a CRC given by its catalogue parameters.
+
+use std::future::Future;
+
+use rmcp::{
+    handler::server::tool::Parameters,
+    model::*,
+    tool, tool_router, ErrorData as McpError,
+};
+
+use super::serial_handler::SerialHandler;
+use super::types::*;
+use crate::utils::{Checksum, CrcParams};
+
+#[tool_router(router = checksum_router, vis = "pub(crate)")]
+impl SerialHandler {
+    #[tool(description = "Compute a checksum over data, or verify one with expected. Algorithms: sum8, xor8, fletcher16, crc8, crc16_ccitt (CCITT-FALSE), crc16_xmodem, crc16_kermit, crc16_x25, crc16_modbus, crc16_arc, crc32, crc32c, or 'crc' for any CRC of 1 to 64 bits given by width, poly, init, reflect_in, reflect_out and xor_out as in the CRC catalogue. Returns the value and its bytes in both byte orders")]
+    async fn checksum(&self, Parameters(args): Parameters<ChecksumArgs>) -> Result<CallToolResult, McpError> {
+        let algorithm = checksum_algorithm(&args).map_err(|e| McpError::invalid_params(format!("Error: {}", e), None))?;
+        let data = decode_data(&args.data, &args.encoding)
+            .map_err(|e| McpError::invalid_params(format!("Error: Data decoding failed - {}", e), None))?;
+
+        let value = algorithm.compute(&data);
+        let digits = algorithm.width().div_ceil(4) as usize;
+        let byte_count = algorithm.width().div_ceil(8) as usize;
+        let big_endian = &value.to_be_bytes()[8 - byte_count..];
+        let little_endian: Vec<u8> = big_endian.iter().rev().copied().collect();
+
+        let mut message = format!(
+            "Checksum\nAlgorithm: {}\nBytes: {}\nValue: 0x{:0digits$X} ({})\nBig-endian: {}\nLittle-endian: {}",
+            args.algorithm,
+            data.len(),
+            value,
+            value,
+            encode_data(big_endian, "hex").unwrap_or_default().to_uppercase(),
+            encode_data(&little_endian, "hex").unwrap_or_default().to_uppercase(),
+        );
+        if let Some(expected) = &args.expected {
+            let expected = parse_hex(expected, "expected").map_err(|e| McpError::invalid_params(format!("Error: {}", e), None))?;
+            if expected == value {
+                message.push_str("\nExpected: matches");
+            } else {
+                message.push_str(&format!("\nExpected: 0x{:0digits$X}, MISMATCH", expected));
+            }
+        }
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+}
+
+/// The algorithm named by the arguments, with its parameters for 'crc'
+fn checksum_algorithm(args: &ChecksumArgs) -> Result<Checksum, String> {
+    if !args.algorithm.eq_ignore_ascii_case("crc") {
+        return Checksum::named(&args.algorithm)
+            .ok_or_else(|| format!("Unknown algorithm {}, expected one of {} or crc", args.algorithm, Checksum::NAMES.join(", ")));
+    }
+
+    let width = args.width.ok_or("crc needs width")?;
+    if !(1..=64).contains(&width) {
+        return Err(format!("CRC width {} is not 1 to 64", width));
+    }
+    let poly = parse_hex(args.poly.as_deref().ok_or("crc needs poly")?, "poly")?;
+    let optional = |value: &Option<String>, name: &str| value.as_deref().map_or(Ok(0), |value| parse_hex(value, name));
+    Ok(Checksum::Crc(CrcParams {
+        width,
+        poly,
+        init: optional(&args.init, "init")?,
+        reflect_in: args.reflect_in,
+        reflect_out: args.reflect_out.unwrap_or(args.reflect_in),
+        xor_out: optional(&args.xor_out, "xor_out")?,
+    }))
+}
+
+/// A hex number, with or without 0x
+fn parse_hex(value: &str, name: &str) -> Result<u64, String> {
+    let digits = value.trim();
+    let digits = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")).unwrap_or(digits);
+    u64::from_str_radix(digits, 16).map_err(|_| format!("{} {} is not a hex number", name, value))
+}
@@ -0,0 +1,347 @@
+//! Shell console helpers
+//!
+//! Tools that drive a POSIX shell running on the target over its serial
+//! console, for devices where the console is the only way in.
+
+use std::future::Future;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use base64::{engine::general_purpose, Engine as _};
+use rmcp::{
+    handler::server::tool::Parameters, model::*, tool, tool_router, ErrorData as McpError,
+};
+use chrono::Utc;
+use tracing::{debug, error, info, warn};
+
+use super::serial_handler::SerialHandler;
+use super::transfer::{PartialTransfer, ResumeToken, SavedTransfer, TransferStore};
+use super::types::*;
+use crate::serial::SerialConnection;
+
+/// Largest local file accepted by `push_file_via_console`
+const MAX_CONSOLE_PUSH_BYTES: u64 = 1024 * 1024;
+
+/// Name pushes are saved under, to be resumed
+const PUSH_TOOL: &str = "push_file_via_console";
+
+/// Time between saves of a push's progress
+const PROGRESS_SAVE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Shell error strings that mean the remote file cannot be written
+const REMOTE_WRITE_ERRORS: &[&str] = &[
+    "No such file or directory",
+    "Permission denied",
+    "Read-only file system",
+    "can't create",
+    "not found",
+];
+
+#[tool_router(router = console_router, vis = "pub(crate)")]
+impl SerialHandler {
+    #[tool(description = "Transfer a local file onto the target through its shell console. The file is sent as base64 in printf chunks, decoded on the target with base64 -d and verified with md5sum. If the transfer is interrupted, the error reports how far it got and a resume_token; pass it back to continue instead of starting over")]
+    async fn push_file_via_console(&self, Parameters(args): Parameters<PushFileArgs>) -> Result<CallToolResult, McpError> {
+        debug!("Pushing {} to {} on connection {}", args.local_path, args.remote_path, args.connection_id);
+
+        if args.chunk_size == 0 {
+            return Err(McpError::invalid_params("Error: chunk_size must be greater than 0", None));
+        }
+
+        let contents = read_push_source(&args.local_path)?;
+        let connection = self.connection(&args.connection_id).await?;
+        let staging = shell_quote(&staging_path(&args.remote_path));
+        let encoded = general_purpose::STANDARD.encode(&contents);
+        let mut saved = SavedTransfer {
+            tool: PUSH_TOOL.to_string(),
+            port: connection.config().port.clone(),
+            source: args.local_path.clone(),
+            destination: args.remote_path.clone(),
+            md5: format!("{:x}", md5::compute(&contents)),
+            total_bytes: contents.len() as u64,
+            offset: 0,
+            sent_md5: String::new(),
+            chunk_size: args.chunk_size,
+            settle_ms: args.settle_ms,
+            verify: args.verify,
+            updated: String::new(),
+        };
+
+        // Discard whatever the console printed before we started
+        drain_console(&connection, args.settle_ms).await?;
+
+        let mut transfer_id = None;
+        let resumed_from = match &args.resume_token {
+            Some(resume_token) => {
+                ResumeToken::decode(resume_token)
+                    .and_then(|previous| previous.check(PUSH_TOOL, &saved.source, &saved.destination, &saved.md5))
+                    .map_err(|e| McpError::invalid_params(format!("Error: {}", e), None))?;
+                // Carry on under the interrupted push's saved progress, if it is still there
+                transfer_id = self
+                    .transfers
+                    .list()
+                    .into_iter()
+                    .find(|(_, previous)| {
+                        previous.tool == saved.tool && previous.source == saved.source && previous.destination == saved.destination && previous.md5 == saved.md5
+                    })
+                    .map(|(id, _)| id);
+                let (offset, sent_md5) = check_staged(&connection, &saved.destination, &encoded, args.settle_ms).await?;
+                saved.sent_md5 = sent_md5;
+                Some(offset)
+            }
+            None => {
+                let output = run_console_command(&connection, &format!(": > {}", staging), args.settle_ms).await?;
+                if let Some(problem) = REMOTE_WRITE_ERRORS.iter().find(|e| output.contains(*e)) {
+                    error!("Cannot create {} on target: {}", args.remote_path, problem);
+                    let error_msg = format!("Error: Cannot create {} on target - {}", args.remote_path, problem);
+                    return Err(McpError::internal_error(error_msg, None));
+                }
+                None
+            }
+        };
+        saved.offset = resumed_from.unwrap_or(0) as u64;
+
+        let transfer_id = transfer_id.unwrap_or_else(|| uuid::Uuid::new_v4().simple().to_string()[..8].to_string());
+        let (chunks, verified) = push_staged(&connection, &self.transfers, &transfer_id, &mut saved, &contents).await?;
+
+        info!("Pushed {} bytes to {} on connection {}", contents.len(), args.remote_path, args.connection_id);
+        let mut message = format!(
+            "File pushed successfully\nConnection ID: {}\nLocal path: {}\nRemote path: {}\nBytes: {}\nChunks: {}\nMD5: {}\nVerified: {}",
+            args.connection_id, args.local_path, args.remote_path, contents.len(), chunks, saved.md5, verified
+        );
+        if let Some(offset) = resumed_from {
+            message.push_str(&format!("\nResumed from offset: {} of {}", offset, encoded.len()));
+        }
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+
+    #[tool(description = "Continue an interrupted push_file_via_console from where the target's copy ends, even after a server restart. Pushes save their progress as they go under a transfer ID, which a failed push reports; without transfer_id, lists the transfers that can be resumed. connection_id is a connection to the same target, which may be a new one. The part already on the target is checked against an MD5 of what was sent before continuing, so a corrupted partial copy is never built on")]
+    async fn resume_transfer(&self, Parameters(args): Parameters<ResumeTransferArgs>) -> Result<CallToolResult, McpError> {
+        let Some(transfer_id) = args.transfer_id else {
+            let transfers = self.transfers.list();
+            if transfers.is_empty() {
+                return Ok(CallToolResult::success(vec![Content::text("No interrupted transfers")]));
+            }
+            let mut lines = vec![format!("{} interrupted transfer(s):", transfers.len())];
+            lines.extend(transfers.iter().map(|(id, transfer)| format_saved(id, transfer)));
+            return Ok(CallToolResult::success(vec![Content::text(lines.join("\n"))]));
+        };
+        let mut saved = self.transfers.get(&transfer_id).ok_or_else(|| {
+            let error_msg = format!("Error: No interrupted transfer {}; call resume_transfer without transfer_id to list them", transfer_id);
+            McpError::invalid_params(error_msg, None)
+        })?;
+        let connection_id = args
+            .connection_id
+            .ok_or_else(|| McpError::invalid_params("Error: connection_id is needed to resume a transfer", None))?;
+        if saved.tool != PUSH_TOOL {
+            return Err(McpError::invalid_params(format!("Error: Transfers of {} cannot be resumed here", saved.tool), None));
+        }
+        let contents = read_push_source(&saved.source)?;
+        if format!("{:x}", md5::compute(&contents)) != saved.md5 {
+            let error_msg = format!("Error: {} changed since the interrupted transfer, push it again from the start", saved.source);
+            return Err(McpError::invalid_params(error_msg, None));
+        }
+        let connection = self.connection(&connection_id).await?;
+        debug!("Resuming transfer {} of {} to {} on connection {}", transfer_id, saved.source, saved.destination, connection_id);
+
+        // What reached the target, checked against what was sent
+        drain_console(&connection, saved.settle_ms).await?;
+        let encoded = general_purpose::STANDARD.encode(&contents);
+        let (offset, sent_md5) = check_staged(&connection, &saved.destination, &encoded, saved.settle_ms).await?;
+        saved.port = connection.config().port.clone();
+        saved.offset = offset as u64;
+        saved.sent_md5 = sent_md5;
+
+        let (chunks, verified) = push_staged(&connection, &self.transfers, &transfer_id, &mut saved, &contents).await?;
+        info!("Resumed transfer {} and pushed {} to {} on connection {}", transfer_id, saved.source, saved.destination, connection_id);
+        let message = format!(
+            "Transfer resumed and completed\nTransfer ID: {}\nConnection ID: {}\nLocal path: {}\nRemote path: {}\nBytes: {}\nResumed from offset: {} of {}\nChunks: {}\nMD5: {}\nVerified: {}",
+            transfer_id, connection_id, saved.source, saved.destination, contents.len(), offset, encoded.len(), chunks, saved.md5, verified
+        );
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+}
+
+/// Read a file to push, within the console transfer limit
+fn read_push_source(path: &str) -> Result<Vec<u8>, McpError> {
+    let metadata = std::fs::metadata(path).map_err(|e| McpError::invalid_params(format!("Error: Cannot access {} - {}", path, e), None))?;
+    if metadata.len() > MAX_CONSOLE_PUSH_BYTES {
+        let error_msg = format!("Error: {} is {} bytes, console transfers are limited to {} bytes", path, metadata.len(), MAX_CONSOLE_PUSH_BYTES);
+        return Err(McpError::invalid_params(error_msg, None));
+    }
+    std::fs::read(Path::new(path)).map_err(|e| McpError::internal_error(format!("Error: Failed to read {} - {}", path, e), None))
+}
+
+/// Measure the staged part of a push on the target and check it against what was sent
+///
+/// Returns the offset to continue from and the MD5 of the encoded stream up to it.
+async fn check_staged(connection: &SerialConnection, destination: &str, encoded: &str, settle_ms: u64) -> Result<(usize, String), McpError> {
+    let staging = shell_quote(&staging_path(destination));
+    let output = run_console_command(connection, &format!("wc -c < {}", staging), settle_ms).await?;
+    let offset = staged_length(&output).filter(|&offset| offset <= encoded.len()).ok_or_else(|| {
+        let error_msg = format!("Error: Cannot resume, staging file on target is unusable - {}", output.trim());
+        McpError::internal_error(error_msg, None)
+    })?;
+    let sent_md5 = format!("{:x}", md5::compute(&encoded.as_bytes()[..offset]));
+    if offset > 0 {
+        let output = run_console_command(connection, &format!("md5sum < {}", staging), settle_ms).await?;
+        if !output.contains(&sent_md5) {
+            error!("Staged part of {} on target does not match what was sent", destination);
+            let error_msg = format!(
+                "Error: The {} bytes staged on the target do not match what was sent (expected md5 {}, target reported: {}), push the file again from the start",
+                offset,
+                sent_md5,
+                output.trim()
+            );
+            return Err(McpError::internal_error(error_msg, None));
+        }
+    }
+    Ok((offset, sent_md5))
+}
+
+/// Stage the encoded file on the target from `saved.offset`, then decode and verify it
+///
+/// Progress is saved under `transfer_id` as chunks go out and forgotten once
+/// the push completes. Returns the chunks sent and whether the copy was verified.
+async fn push_staged(
+    connection: &SerialConnection,
+    store: &TransferStore,
+    transfer_id: &str,
+    saved: &mut SavedTransfer,
+    contents: &[u8],
+) -> Result<(usize, &'static str), McpError> {
+    let remote = shell_quote(&saved.destination);
+    let staging = shell_quote(&staging_path(&saved.destination));
+    let encoded = general_purpose::STANDARD.encode(contents);
+    let total = contents.len() as u64;
+
+    let chunks: Vec<&str> = encoded.as_bytes()[saved.offset as usize..]
+        .chunks(saved.chunk_size)
+        .map(|chunk| std::str::from_utf8(chunk).unwrap_or_default())
+        .collect();
+    let mut last_save = None;
+    for (index, chunk) in chunks.iter().enumerate() {
+        if last_save.is_none_or(|saved_at: Instant| saved_at.elapsed() >= PROGRESS_SAVE_INTERVAL) {
+            save_progress(store, transfer_id, saved, &encoded);
+            last_save = Some(Instant::now());
+        }
+        let command = format!("printf '%s' '{}' >> {}", chunk, staging);
+        if let Err(e) = run_console_command(connection, &command, saved.settle_ms).await {
+            error!("Push of {} interrupted at offset {}: {}", saved.destination, saved.offset, e.message);
+            save_progress(store, transfer_id, saved, &encoded);
+            let completed = decoded_length(saved.offset).min(total);
+            return Err(PartialTransfer::new(&saved.token(), completed, total).with_transfer_id(Some(transfer_id)).into_error(&e.message));
+        }
+        saved.offset += chunk.len() as u64;
+        debug!("Sent chunk {}/{} to {}", index + 1, chunks.len(), saved.destination);
+    }
+
+    let decode = format!("base64 -d {} > {} && rm -f {}", staging, remote, staging);
+    let output = match run_console_command(connection, &decode, saved.settle_ms).await {
+        Ok(output) => output,
+        // Everything is staged; resuming only has to decode again
+        Err(e) => {
+            save_progress(store, transfer_id, saved, &encoded);
+            return Err(PartialTransfer::new(&saved.token(), total, total).with_transfer_id(Some(transfer_id)).into_error(&e.message));
+        }
+    };
+    // Past here a retry cannot pick up the staged data, so the progress is dropped
+    forget_progress(store, transfer_id);
+    if output.contains("base64:") || output.contains("invalid") {
+        error!("Target failed to decode {}: {}", saved.destination, output.trim());
+        let error_msg = format!("Error: Target failed to decode the transfer - {}", output.trim());
+        return Err(McpError::internal_error(error_msg, None));
+    }
+
+    if !saved.verify {
+        return Ok((chunks.len(), "skipped"));
+    }
+    let output = run_console_command(connection, &format!("md5sum {}", remote), saved.settle_ms).await?;
+    if !output.contains(&saved.md5) {
+        error!("MD5 mismatch after pushing {}", saved.destination);
+        let error_msg = format!(
+            "Error: Verification failed for {} - expected md5 {}, target reported: {}",
+            saved.destination, saved.md5, output.trim()
+        );
+        return Err(McpError::internal_error(error_msg, None));
+    }
+    Ok((chunks.len(), "yes"))
+}
+
+/// Save how far a push got; a failure to save only costs the ability to resume
+fn save_progress(store: &TransferStore, transfer_id: &str, saved: &mut SavedTransfer, encoded: &str) {
+    saved.sent_md5 = format!("{:x}", md5::compute(&encoded.as_bytes()[..saved.offset as usize]));
+    saved.updated = Utc::now().to_rfc3339();
+    if let Err(e) = store.save(transfer_id, saved.clone()) {
+        warn!("Progress of transfer {} not saved: {}", transfer_id, e);
+    }
+}
+
+fn forget_progress(store: &TransferStore, transfer_id: &str) {
+    if let Err(e) = store.remove(transfer_id) {
+        warn!("Transfer {} not removed from the saved transfers: {}", transfer_id, e);
+    }
+}
+
+/// One line describing an interrupted transfer
+pub(super) fn format_saved(transfer_id: &str, transfer: &SavedTransfer) -> String {
+    let staged = decoded_length(transfer.offset).min(transfer.total_bytes);
+    format!(
+        "- {}: {} {} -> {} on {}, {} of {} bytes, saved {}",
+        transfer_id, transfer.tool, transfer.source, transfer.destination, transfer.port, staged, transfer.total_bytes, transfer.updated
+    )
+}
+
+/// Where the base64 text is collected on the target before it is decoded
+fn staging_path(remote_path: &str) -> String {
+    format!("{}.b64", remote_path)
+}
+
+/// Quote a string for a POSIX shell using single quotes
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// Length of the staging file from `wc -c` output, ignoring the echoed command
+fn staged_length(output: &str) -> Option<usize> {
+    output.lines().rev().find_map(|line| line.trim().parse().ok())
+}
+
+/// Source bytes fully covered by `offset` characters of base64
+fn decoded_length(offset: u64) -> u64 {
+    offset / 4 * 3
+}
+
+/// Send a command line and collect the console output until it goes quiet
+async fn run_console_command(connection: &SerialConnection, command: &str, settle_ms: u64) -> Result<String, McpError> {
+    connection.write(format!("{}\n", command).as_bytes()).await.map_err(|e| {
+        error!("Failed to send console command: {}", e);
+        McpError::internal_error(format!("Error: Data sending failed - {}", e), None)
+    })?;
+    drain_console(connection, settle_ms).await
+}
+
+/// Read console output until no data has arrived for `settle_ms`
+async fn drain_console(connection: &SerialConnection, settle_ms: u64) -> Result<String, McpError> {
+    connection.drain_output(settle_ms).await.map_err(|e| {
+        error!("Failed to read console output: {}", e);
+        McpError::internal_error(format!("Error: Data reading failed - {}", e), None)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("/tmp/app.bin"), "'/tmp/app.bin'");
+        assert_eq!(shell_quote("it's here"), r"'it'\''s here'");
+    }
+
+    #[test]
+    fn test_staged_length() {
+        assert_eq!(staged_length("wc -c < '/tmp/fw.bin.b64'\r\n2048\r\n# "), Some(2048));
+        assert_eq!(staged_length("sh: can't open '/tmp/fw.bin.b64'\n# "), None);
+        assert_eq!(decoded_length(2050), 1536);
+    }
+}
@@ -0,0 +1,111 @@
+//! defmt log tools
+//!
+//! Decode the binary log frames of firmware using defmt, such as the
+//! Embassy example under `examples/`, when they are sent over a UART. The
+//! format strings come from the firmware ELF, which is loaded once per
+//! connection and reloaded when it is rebuilt.
+
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use rmcp::{
+    handler::server::tool::Parameters,
+    model::*,
+    tool, tool_router, ErrorData as McpError,
+};
+use tokio::time::Instant;
+use tracing::{debug, error, info};
+
+use super::serial_handler::SerialHandler;
+use super::types::*;
+use super::warnings::Warnings;
+use crate::protocol::defmt::{DefmtDecoder, DefmtLine, DefmtTable};
+use crate::serial::{LocalSerialError, SerialConnection};
+
+/// A connection's decoder and the ELF it was loaded from
+pub(crate) struct DefmtSession {
+    elf_path: String,
+    /// Modification time of the ELF when loaded, so a rebuilt one is picked up
+    modified: Option<SystemTime>,
+    decoder: DefmtDecoder,
+}
+
+#[tool_router(router = defmt_router, vis = "pub(crate)")]
+impl SerialHandler {
+    #[tool(description = "Read defmt log frames from a connection and decode them into log lines with timestamps and levels, using the format strings in the firmware ELF at elf_path. The firmware must write defmt's encoded output to the UART (rzCOBS, or raw if built with defmt's encoding-raw feature). The ELF is reloaded if it changes, and frames split across calls are kept")]
+    async fn read_defmt(&self, Parameters(args): Parameters<ReadDefmtArgs>) -> Result<CallToolResult, McpError> {
+        let connection = self.defmt_connection(&args.connection_id).await?;
+        let modified = std::fs::metadata(&args.elf_path)
+            .map_err(|e| McpError::invalid_params(format!("Error: Cannot access {} - {}", args.elf_path, e), None))?
+            .modified()
+            .ok();
+
+        // Taken out while reading so other connections' reads are not held up
+        let existing = self.defmt_sessions.lock().await.remove(&args.connection_id);
+        let mut session = match existing {
+            Some(session) if session.elf_path == args.elf_path && session.modified == modified => session,
+            _ => load_session(&args.elf_path, modified)?,
+        };
+        debug!("Collecting defmt logs on connection {} for {}ms", args.connection_id, args.duration_ms);
+
+        let collected = collect_lines(&connection, &mut session.decoder, Duration::from_millis(args.duration_ms), args.max_lines.max(1)).await;
+        let errors = session.decoder.take_errors();
+        let table = session.decoder.table();
+        let summary = format!("Table: {} strings, {} encoding", table.len(), serde_json::to_string(&table.encoding).unwrap_or_default().trim_matches('"'));
+        self.defmt_sessions.lock().await.insert(args.connection_id.clone(), session);
+        let lines = collected.map_err(|e| {
+            error!("Failed to read defmt logs from connection {}: {}", args.connection_id, e);
+            McpError::internal_error(format!("Error: Data reading failed - {}", e), None)
+        })?;
+
+        let mut warnings = Warnings::new();
+        if let Some(first) = errors.first() {
+            warnings.push(format!("{} frames could not be decoded, the first because: {}", errors.len(), first));
+        }
+        let mut text = format!("defmt logs read\nConnection ID: {}\n{}\nLines: {}", args.connection_id, summary, lines.len());
+        for line in &lines {
+            text.push_str(&format!("\n{}", line));
+        }
+        warnings.into_result(text)
+    }
+}
+
+impl SerialHandler {
+    /// The connection, provided its reads are raw bytes
+    async fn defmt_connection(&self, connection_id: &str) -> Result<Arc<SerialConnection>, McpError> {
+        let connection = self.connection(connection_id).await?;
+        if connection.is_line_mode().await || connection.framer_name().await.is_some() {
+            let error_msg = format!("Error: Connection {} has line mode or a framer set, disable it to decode defmt", connection_id);
+            return Err(McpError::invalid_params(error_msg, None));
+        }
+        Ok(connection)
+    }
+}
+
+fn load_session(elf_path: &str, modified: Option<SystemTime>) -> Result<DefmtSession, McpError> {
+    let elf = std::fs::read(elf_path).map_err(|e| McpError::internal_error(format!("Error: Failed to read {} - {}", elf_path, e), None))?;
+    let table = DefmtTable::parse(&elf).map_err(|e| McpError::invalid_params(format!("Error: {} - {}", elf_path, e), None))?;
+    info!("Loaded {} defmt strings from {}", table.len(), elf_path);
+    Ok(DefmtSession { elf_path: elf_path.to_string(), modified, decoder: DefmtDecoder::new(table) })
+}
+
+/// Collect lines for up to `duration`, stopping once `max` have arrived
+async fn collect_lines(
+    connection: &SerialConnection,
+    decoder: &mut DefmtDecoder,
+    duration: Duration,
+    max: usize,
+) -> Result<Vec<DefmtLine>, LocalSerialError> {
+    let deadline = Instant::now() + duration;
+    let mut lines = Vec::new();
+
+    connection
+        .read_until(deadline, |chunk| {
+            lines.extend(decoder.feed(chunk));
+            lines.len() >= max
+        })
+        .await?;
+
+    Ok(lines)
+}
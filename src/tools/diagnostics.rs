@@ -0,0 +1,133 @@
+//! Diagnostic tools
+//!
+//! Tools that report on the state of connections rather than moving data.
+
+use std::future::Future;
+
+use chrono::Utc;
+use rmcp::{
+    handler::server::tool::Parameters, model::*, tool, tool_router, ErrorData as McpError,
+};
+use tracing::{debug, error};
+
+use super::console::format_saved;
+use super::serial_handler::SerialHandler;
+use super::types::*;
+use crate::serial::PortInfo;
+use crate::session::format_labels;
+
+#[tool_router(router = diagnostics_router, vis = "pub(crate)")]
+impl SerialHandler {
+    #[tool(description = "Report the status of a connection as JSON: settings, reader state, notes set on the device, byte counters, its session (state, age, idle time and read/write statistics), data freshness (age of the newest buffered byte and time since the last receive and send, to tell a quiet device from stale buffered data), and receive timing statistics (gaps between received chunks in milliseconds and chunk sizes in bytes, each with min/avg/max/p95). Useful for tuning timeouts and idle gaps")]
+    async fn status(&self, Parameters(args): Parameters<StatusArgs>) -> Result<CallToolResult, McpError> {
+        debug!("Reporting status of connection {}", args.connection_id);
+
+        let connection = self.connection(&args.connection_id).await?;
+        let mut status = connection.status().await;
+        status.notes = self.notes.get(&status.port);
+        let status = StatusReport { status, session: self.sessions.connection_session_info(&args.connection_id).await };
+
+        let message = serde_json::to_string_pretty(&status).map_err(|e| {
+            error!("Failed to serialize status: {}", e);
+            McpError::internal_error(format!("Error: Status serialization failed - {}", e), None)
+        })?;
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+
+    #[tool(description = "Capture a structured JSON snapshot of a connection: status, statistics, recent transcript of sent and received data, and event history. Useful for bug reports or to restore context after a conversation reset")]
+    async fn snapshot(&self, Parameters(args): Parameters<SnapshotArgs>) -> Result<CallToolResult, McpError> {
+        debug!("Capturing snapshot of connection {}", args.connection_id);
+
+        let connection = self.connection(&args.connection_id).await?;
+        let mut status = connection.status().await;
+        status.notes = self.notes.get(&status.port);
+
+        let snapshot = SnapshotResponse {
+            captured_at: Utc::now(),
+            stats: SnapshotStats {
+                buffered_bytes: connection.buffered_bytes().await,
+                dropped_bytes: connection.dropped_bytes().await,
+                uptime_seconds: Utc::now().signed_duration_since(status.created_at).num_seconds(),
+            },
+            status,
+            session: self.sessions.connection_session_info(&args.connection_id).await,
+            transcript: connection
+                .recent_transcript(args.transcript_limit)
+                .await
+                .into_iter()
+                .map(TranscriptView::from)
+                .collect(),
+            events: connection.events().await,
+        };
+
+        let message = serde_json::to_string_pretty(&snapshot).map_err(|e| {
+            error!("Failed to serialize snapshot: {}", e);
+            McpError::internal_error(format!("Error: Snapshot serialization failed - {}", e), None)
+        })?;
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+
+    #[tool(description = "Summarize the server's current state for a new conversation: open connections, the devices behind them, their receive modes and last activity, and transfers that can be resumed. Call this first to pick up where a previous session left off")]
+    async fn resume_summary(&self) -> Result<CallToolResult, McpError> {
+        debug!("Building resume summary");
+
+        let connections = self.connection_manager.connections().await;
+        let transfers = self.transfers.list();
+        let mut interrupted = Vec::new();
+        if !transfers.is_empty() {
+            interrupted.push(format!("{} interrupted transfer(s), continue with resume_transfer:", transfers.len()));
+            interrupted.extend(transfers.iter().map(|(id, transfer)| format_saved(id, transfer)));
+        }
+        if connections.is_empty() {
+            let mut lines = vec!["No open connections. Use list_ports to discover devices and open to connect.".to_string()];
+            lines.extend(interrupted);
+            return Ok(CallToolResult::success(vec![Content::text(lines.join("\n"))]));
+        }
+
+        // Port enumeration is best effort; the summary is still useful without it
+        let ports = PortInfo::list_ports().unwrap_or_default();
+        let now = Utc::now();
+
+        let mut lines = vec![format!("{} open connection(s):", connections.len())];
+        for connection in connections {
+            let config = connection.config();
+            let device = ports
+                .iter()
+                .find(|p| p.name == config.port)
+                .map(|p| match &p.hardware_id {
+                    Some(hw_id) => format!("{} ({})", p.description, hw_id),
+                    None => p.description.clone(),
+                })
+                .unwrap_or_else(|| "device not currently enumerated".to_string());
+
+            let mode = if connection.is_line_mode().await { "line" } else { "raw" };
+            let last_activity = match connection.recent_transcript(1).await.pop() {
+                Some(entry) => format!(
+                    "{:?} {}s ago",
+                    entry.direction,
+                    now.signed_duration_since(entry.timestamp).num_seconds()
+                ),
+                None => "none".to_string(),
+            };
+
+            lines.push(format!("- {} on {} @ {} {} - {}", connection.id(), config.port, config.baud_rate, config.frame_format(), device));
+            lines.push(format!(
+                "  mode: {}, paused: {}, backpressure: {}, buffered: {} bytes, last activity: {}",
+                mode,
+                if connection.is_paused() { "yes" } else { "no" },
+                config.backpressure,
+                connection.buffered_bytes().await,
+                last_activity
+            ));
+            if let Some(session) = self.sessions.connection_session_info(connection.id()).await.filter(|s| !s.config.labels.is_empty()) {
+                lines.push(format!("  session {} labels: {}", session.session_id, format_labels(&session.config.labels)));
+            }
+            for (key, value) in self.notes.get(&config.port) {
+                lines.push(format!("  note {}: {}", key, value));
+            }
+        }
+
+        lines.extend(interrupted);
+        Ok(CallToolResult::success(vec![Content::text(lines.join("\n"))]))
+    }
+}
@@ -0,0 +1,341 @@
+//! Port auto-discovery
+//!
+//! Watches the system's serial ports and tells the client when devices appear
+//! or disappear. The polling interval adapts: right after a port vanishes or a
+//! connection drops it polls quickly to catch the device coming back, then
+//! backs off towards the configured interval while nothing changes, to keep
+//! idle CPU and battery use low. Where the platform announces device changes
+//! natively, each announcement triggers an immediate rescan.
+//!
+//! The 1200 baud touch reboots boards with native USB into their bootloader
+//! and watches the ports the same way to find where it comes up.
+
+use std::collections::BTreeSet;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use rmcp::{
+    handler::server::tool::Parameters,
+    model::*,
+    tool, tool_router, ErrorData as McpError, Peer, RoleServer,
+};
+use serde::Serialize;
+use tokio::sync::{Mutex, Notify};
+use tracing::{debug, error, info, warn};
+
+use super::idle::IdleMode;
+use super::serial_handler::SerialHandler;
+use super::types::TouchResetArgs;
+use super::warnings::Warnings;
+use crate::serial::{ConnectionManager, LocalSerialError, PortInfo};
+
+/// Polling interval that doubles while nothing changes, from `fast` up to `slow`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AdaptiveInterval {
+    fast: Duration,
+    slow: Duration,
+    current: Duration,
+}
+
+impl AdaptiveInterval {
+    /// Starts slow; nothing has happened yet
+    pub fn new(fast: Duration, slow: Duration) -> Self {
+        Self { fast, slow, current: slow }
+    }
+
+    pub fn current(&self) -> Duration {
+        self.current
+    }
+
+    /// A port vanished or a connection dropped: poll quickly again
+    pub fn on_disconnect(&mut self) {
+        self.current = self.fast;
+    }
+
+    /// A poll found nothing new: back off
+    pub fn on_stable(&mut self) {
+        self.current = (self.current * 2).min(self.slow);
+    }
+
+    pub fn state(&self) -> DiscoveryState {
+        if self.current >= self.slow {
+            DiscoveryState::Stable
+        } else if self.current == self.fast {
+            DiscoveryState::Hotplug
+        } else {
+            DiscoveryState::BackingOff
+        }
+    }
+}
+
+/// Polling regime of the discovery watcher
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiscoveryState {
+    /// Auto-discovery is turned off
+    Disabled,
+    /// Polling fast after a disconnect
+    Hotplug,
+    /// Slowing down towards the stable interval
+    BackingOff,
+    /// Polling at the configured interval
+    Stable,
+    /// Parked by idle mode until the client's next request
+    Parked,
+}
+
+/// Discovery watcher state, as reported by `discovery_status`
+#[derive(Debug, Clone, Serialize)]
+pub struct DiscoveryStatus {
+    pub state: DiscoveryState,
+    pub interval_ms: u64,
+    pub polls: u64,
+    /// Rescans also run on the platform's device change notifications
+    pub native_notifications: bool,
+    pub ports: Vec<String>,
+    pub last_change: Option<DateTime<Utc>>,
+}
+
+impl DiscoveryStatus {
+    pub fn disabled() -> Self {
+        Self {
+            state: DiscoveryState::Disabled,
+            interval_ms: 0,
+            polls: 0,
+            native_notifications: false,
+            ports: Vec::new(),
+            last_change: None,
+        }
+    }
+}
+
+/// How often ports are listed while waiting for a rebooted board
+const TOUCH_POLL: Duration = Duration::from_millis(100);
+
+/// Ports present in `current` but not `previous`, and the other way round
+pub fn diff_ports(previous: &BTreeSet<String>, current: &BTreeSet<String>) -> (Vec<String>, Vec<String>) {
+    let added = current.difference(previous).cloned().collect();
+    let removed = previous.difference(current).cloned().collect();
+    (added, removed)
+}
+
+#[tool_router(router = discovery_router, vis = "pub(crate)")]
+impl SerialHandler {
+    #[tool(description = "Report the port auto-discovery watcher's state: whether it is polling fast after a disconnect or at the stable interval, the current interval, and the ports it last saw")]
+    async fn discovery_status(&self) -> Result<CallToolResult, McpError> {
+        let status = self.discovery.lock().await.clone();
+        let message = serde_json::to_string_pretty(&status).map_err(|e| {
+            error!("Failed to serialize discovery status: {}", e);
+            McpError::internal_error(format!("Error: Status serialization failed - {}", e), None)
+        })?;
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+
+    #[tool(description = "Reboot an Arduino board with native USB (SAMD such as the Zero and MKR, nRF52, RP2040, Leonardo) into its bootloader with the 1200 baud touch: open the port at 1200 baud and close it. Waits up to wait_ms for the bootloader's port, which often has a new name, and reports it. The port must not be open; close its connection first")]
+    async fn touch_reset(&self, Parameters(args): Parameters<TouchResetArgs>) -> Result<CallToolResult, McpError> {
+        let before = list_port_names().await?;
+        if !before.contains(&args.port) {
+            return Err(McpError::invalid_params(format!("Error: {} is not among the system's ports", args.port), None));
+        }
+        debug!("Touching {} at 1200 baud", args.port);
+        self.connection_manager.touch(&args.port).await.map_err(|e| match e {
+            LocalSerialError::ConnectionExists(port) => {
+                McpError::invalid_params(format!("Error: {} is open; close its connection before the touch", port), None)
+            }
+            e => {
+                error!("1200 baud touch on {} failed: {}", args.port, e);
+                McpError::internal_error(format!("Error: 1200 baud touch failed - {}", e), None)
+            }
+        })?;
+        info!("Touched {} at 1200 baud", args.port);
+
+        // The sketch's port drops off the bus and the bootloader's appears, sometimes under the same name
+        let deadline = tokio::time::Instant::now() + Duration::from_millis(args.wait_ms);
+        let mut original_gone = false;
+        let outcome = loop {
+            tokio::time::sleep(TOUCH_POLL).await;
+            let current = list_port_names().await?;
+            let (added, _) = diff_ports(&before, &current);
+            original_gone |= !current.contains(&args.port);
+            if let Some(port) = added.into_iter().next() {
+                break Some(port);
+            }
+            if original_gone && current.contains(&args.port) {
+                break Some(args.port.clone());
+            }
+            if tokio::time::Instant::now() >= deadline {
+                break None;
+            }
+        };
+
+        let mut warnings = Warnings::new();
+        let mut message = format!("1200 baud touch sent\nPort: {}", args.port);
+        match outcome {
+            Some(port) => {
+                let description = PortInfo::list_ports()
+                    .ok()
+                    .and_then(|ports| ports.into_iter().find(|info| info.name == port))
+                    .map(|info| match info.hardware_id {
+                        Some(hardware_id) => format!("{} ({})", info.description, hardware_id),
+                        None => info.description,
+                    })
+                    .unwrap_or_default();
+                message.push_str(&format!("\nBootloader port: {}\nDescription: {}", port, description));
+            }
+            None if original_gone => warnings.push(format!(
+                "{} went away and no serial port appeared within {}ms; an RP2040 reboots into its UF2 drive, which is storage rather than a port",
+                args.port, args.wait_ms
+            )),
+            None => warnings.push(format!(
+                "{} did not go away within {}ms; the sketch may not use a core that watches for the touch",
+                args.port, args.wait_ms
+            )),
+        }
+        warnings.into_result(message)
+    }
+}
+
+/// Names of the system's ports
+async fn list_port_names() -> Result<BTreeSet<String>, McpError> {
+    let ports = tokio::task::spawn_blocking(PortInfo::list_ports)
+        .await
+        .map_err(|e| McpError::internal_error(format!("Error: Port listing failed - {}", e), None))?
+        .map_err(|e| McpError::internal_error(format!("Error: Failed to list ports - {}", e), None))?;
+    Ok(ports.into_iter().map(|port| port.name).collect())
+}
+
+/// Poll the system's ports, notifying the client of changes
+///
+/// Polling stops while idle mode has parked background tasks. A signal on
+/// `device_changes` rescans straight away.
+pub(crate) async fn run_discovery(
+    peer: Peer<RoleServer>,
+    manager: Arc<ConnectionManager>,
+    status: Arc<Mutex<DiscoveryStatus>>,
+    idle: Arc<IdleMode>,
+    device_changes: Option<Arc<Notify>>,
+    mut interval: AdaptiveInterval,
+) {
+    status.lock().await.native_notifications = device_changes.is_some();
+    let mut known: Option<BTreeSet<String>> = None;
+    let mut dropped_connections = 0;
+
+    loop {
+        let ports = match tokio::task::spawn_blocking(PortInfo::list_ports).await {
+            Ok(Ok(ports)) => ports,
+            Ok(Err(e)) => {
+                warn!("Port discovery failed: {}", e);
+                Vec::new()
+            }
+            Err(e) => {
+                error!("Port discovery task failed: {}", e);
+                return;
+            }
+        };
+        let current: BTreeSet<String> = ports.iter().map(|p| p.name.clone()).collect();
+
+        let (added, removed) = known.as_ref().map(|previous| diff_ports(previous, &current)).unwrap_or_default();
+
+        // A connection whose reader stopped usually means its device went away
+        let mut dropped = 0;
+        for connection in manager.connections().await {
+            if !connection.status().await.connected {
+                dropped += 1;
+            }
+        }
+        let newly_dropped = dropped > dropped_connections;
+        dropped_connections = dropped;
+
+        if !removed.is_empty() || newly_dropped {
+            interval.on_disconnect();
+        } else {
+            interval.on_stable();
+        }
+
+        for name in &added {
+            let description = ports.iter().find(|p| &p.name == name).map(|p| p.description.as_str()).unwrap_or_default();
+            notify(&peer, format!("Port added: {} ({})", name, description)).await;
+        }
+        for name in &removed {
+            notify(&peer, format!("Port removed: {}", name)).await;
+        }
+
+        {
+            let mut status = status.lock().await;
+            status.state = interval.state();
+            status.interval_ms = interval.current().as_millis() as u64;
+            status.polls += 1;
+            if !added.is_empty() || !removed.is_empty() {
+                status.last_change = Some(Utc::now());
+            }
+            status.ports = current.iter().cloned().collect();
+        }
+
+        debug!("Port discovery: {} ports, next poll in {:?}", current.len(), interval.current());
+        known = Some(current);
+        match &device_changes {
+            Some(device_changes) => {
+                tokio::select! {
+                    _ = tokio::time::sleep(interval.current()) => {}
+                    _ = device_changes.notified() => debug!("Device change notified, rescanning ports"),
+                }
+            }
+            None => tokio::time::sleep(interval.current()).await,
+        }
+
+        if idle.check() {
+            status.lock().await.state = DiscoveryState::Parked;
+            idle.active().await;
+        }
+    }
+}
+
+/// Log a port change and forward it to the client as a logging notification
+async fn notify(peer: &Peer<RoleServer>, message: String) {
+    info!("{}", message);
+    let param = LoggingMessageNotificationParam {
+        level: LoggingLevel::Notice,
+        logger: Some("discovery".to_string()),
+        data: serde_json::Value::String(message),
+    };
+    if let Err(e) = peer.notify_logging_message(param).await {
+        warn!("Failed to send discovery notification: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_adaptive_interval() {
+        let mut interval = AdaptiveInterval::new(Duration::from_millis(500), Duration::from_secs(5));
+        assert_eq!(interval.state(), DiscoveryState::Stable);
+
+        interval.on_disconnect();
+        assert_eq!(interval.current(), Duration::from_millis(500));
+        assert_eq!(interval.state(), DiscoveryState::Hotplug);
+
+        interval.on_stable();
+        assert_eq!(interval.current(), Duration::from_secs(1));
+        assert_eq!(interval.state(), DiscoveryState::BackingOff);
+
+        for _ in 0..5 {
+            interval.on_stable();
+        }
+        assert_eq!(interval.current(), Duration::from_secs(5));
+        assert_eq!(interval.state(), DiscoveryState::Stable);
+    }
+
+    #[test]
+    fn test_diff_ports() {
+        let previous: BTreeSet<String> = ["/dev/ttyUSB0", "/dev/ttyUSB1"].iter().map(|s| s.to_string()).collect();
+        let current: BTreeSet<String> = ["/dev/ttyUSB1", "/dev/ttyACM0"].iter().map(|s| s.to_string()).collect();
+
+        let (added, removed) = diff_ports(&previous, &current);
+        assert_eq!(added, vec!["/dev/ttyACM0".to_string()]);
+        assert_eq!(removed, vec!["/dev/ttyUSB0".to_string()]);
+    }
+}
@@ -0,0 +1,101 @@
+//! DSMR P1 smart meter tools
+//!
+//! Read telegrams from the P1 port of a Dutch or Belgian smart meter and
+//! return the meter readings, CRC-checked, as JSON.
+
+use std::future::Future;
+use std::time::Duration;
+
+use rmcp::{
+    handler::server::tool::Parameters,
+    model::*,
+    tool, tool_router, ErrorData as McpError,
+};
+use serde_json::json;
+use tokio::time::Instant;
+use tracing::{debug, error};
+
+use super::serial_handler::SerialHandler;
+use super::types::*;
+use super::warnings::Warnings;
+use crate::protocol::dsmr::{find_telegrams, DsmrError, Telegram};
+use crate::serial::{LocalSerialError, SerialConnection};
+
+#[tool_router(router = dsmr_router, vis = "pub(crate)")]
+impl SerialHandler {
+    #[tool(description = "Read DSMR P1 telegrams from a Dutch or Belgian smart meter (115200 8N1 for DSMR 4 and 5, 9600 7E1 for 2.2 and 3) and return them as JSON: the CRC16 is verified and each telegram's OBIS objects are decoded into readings such as energy delivered and returned per tariff in kWh, the current tariff, power in kW, per-phase voltage, current and power, and gas meter readings. Telegrams failing their CRC are reported separately. Meters send one every 1 s (DSMR 5) or 10 s (older)")]
+    async fn p1_read(&self, Parameters(args): Parameters<P1ReadArgs>) -> Result<CallToolResult, McpError> {
+        let connection = self.connection(&args.connection_id).await?;
+        if connection.is_line_mode().await || connection.framer_name().await.is_some() {
+            let error_msg = format!("Error: Connection {} has line mode or a framer set, disable it to read P1 telegrams", args.connection_id);
+            return Err(McpError::invalid_params(error_msg, None));
+        }
+        debug!("Collecting P1 telegrams on connection {} for up to {}ms", args.connection_id, args.timeout_ms);
+
+        let max = args.max_telegrams.max(1);
+        let telegrams = collect_telegrams(&connection, Duration::from_millis(args.timeout_ms), max)
+            .await
+            .map_err(|e| {
+                error!("Failed to read P1 telegrams from connection {}: {}", args.connection_id, e);
+                McpError::internal_error(format!("Error: Data reading failed - {}", e), None)
+            })?;
+
+        let mut warnings = Warnings::new();
+        let mut decoded = Vec::new();
+        for telegram in telegrams {
+            match telegram {
+                Ok(telegram) => decoded.push(json!({
+                    "identification": telegram.identification,
+                    "crc_checked": telegram.crc_checked,
+                    "readings": telegram.readings(),
+                    "objects": telegram.objects,
+                })),
+                Err(e) => warnings.push(format!("Telegram rejected: {}", e)),
+            }
+        }
+        if decoded.is_empty() && warnings.is_empty() {
+            let message = format!("No P1 telegram received\nConnection ID: {}\nWaited: {}ms", args.connection_id, args.timeout_ms);
+            return Ok(CallToolResult::success(vec![Content::text(message)]));
+        }
+
+        let message = format!(
+            "P1 telegrams read\nConnection ID: {}\nTelegrams: {}\n{}",
+            args.connection_id,
+            decoded.len(),
+            serde_json::to_string_pretty(&decoded).unwrap_or_default()
+        );
+        warnings.into_result(message)
+    }
+}
+
+/// Collect up to `max` valid telegrams within `duration`
+///
+/// Rejected telegrams are returned too, but do not count towards `max`.
+async fn collect_telegrams(
+    connection: &SerialConnection,
+    duration: Duration,
+    max: usize,
+) -> Result<Vec<Result<Telegram, DsmrError>>, LocalSerialError> {
+    let deadline = Instant::now() + duration;
+    let mut telegrams = Vec::new();
+    let mut valid = 0;
+    let mut pending = String::new();
+
+    connection
+        .read_until(deadline, |chunk| {
+            pending.push_str(&String::from_utf8_lossy(chunk));
+            let (found, used) = find_telegrams(&pending);
+            pending.drain(..used);
+            for telegram in found {
+                if valid == max {
+                    break;
+                }
+                valid += usize::from(telegram.is_ok());
+                telegrams.push(telegram);
+            }
+            valid == max
+        })
+        .await?;
+
+    Ok(telegrams)
+}
@@ -0,0 +1,290 @@
+//! ESP8266/ESP32 reset and flashing tools
+//!
+//! Put a board into its ROM bootloader or restart it with the DTR/RTS
+//! sequences esptool uses, so flashing and monitoring can be driven without
+//! pressing the BOOT and EN buttons, and write firmware images through the
+//! ROM bootloader without esptool.
+
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use rmcp::{
+    handler::server::tool::Parameters,
+    model::*,
+    tool, tool_router, ErrorData as McpError,
+};
+use tracing::{debug, error, info};
+
+use super::serial_handler::SerialHandler;
+use super::types::*;
+use super::verify::format_ranges;
+use super::warnings::Warnings;
+use super::ymodem::read_local_file;
+use crate::protocol::esp::{in_download_mode, reset_sequence, ResetMode, ResetStep, ResetVariant};
+use crate::protocol::esp_loader::{parse_flash_size, EspLoader, EspLoaderError, MAX_FLASH_SIZE};
+use crate::serial::{LocalSerialError, SerialConnection};
+
+/// Longest reset delay accepted
+const MAX_RESET_DELAY_MS: u64 = 5000;
+
+/// IO0 hold esptool starts with, and the longer one it retries with for slow boards
+pub(super) const RESET_DELAY: Duration = Duration::from_millis(50);
+const SLOW_RESET_DELAY: Duration = Duration::from_millis(550);
+
+/// Syncs tried after each reset
+const SYNC_ATTEMPTS: u32 = 5;
+
+/// Images are erased in whole sectors, so they must start on one
+const SECTOR_SIZE: u32 = 0x1000;
+
+/// Blocks hashed while looking for the sectors that failed to verify
+const MD5_BLOCK_SIZE: usize = 0x10000;
+
+#[tool_router(router = esp_router, vis = "pub(crate)")]
+impl SerialHandler {
+    #[tool(description = "Reset an ESP8266/ESP32 board through DTR/RTS as esptool does. mode bootloader (default) holds IO0 low across the reset so the ROM waits for a download; run restarts into the application. If the board does not enter the bootloader, try variant tight, a reset_delay_ms of 550, or inverted for boards that invert the lines; use variant usb_jtag for the built-in USB port of ESP32-C3/S3 and later. Returns the boot output, which on ESP32 shows the boot mode (waiting for download in the bootloader)")]
+    async fn esp_reset(&self, Parameters(args): Parameters<EspResetArgs>) -> Result<CallToolResult, McpError> {
+        let mode = ResetMode::parse(&args.mode).map_err(|e| McpError::invalid_params(format!("Error: {}", e), None))?;
+        let variant = ResetVariant::parse(&args.variant).map_err(|e| McpError::invalid_params(format!("Error: {}", e), None))?;
+        if args.reset_delay_ms > MAX_RESET_DELAY_MS {
+            let error_msg = format!("Error: reset_delay_ms must be at most {}", MAX_RESET_DELAY_MS);
+            return Err(McpError::invalid_params(error_msg, None));
+        }
+        let connection = self.connection(&args.connection_id).await?;
+        debug!("Resetting ESP board on connection {} into {} ({} sequence)", args.connection_id, mode, variant);
+
+        let _exchange = connection.begin_exchange().await;
+        // Output from before the reset would be taken for the boot banner
+        let _ = connection.drain_output(0).await;
+
+        let delay = Duration::from_millis(args.reset_delay_ms);
+        run_reset(&connection, &args.connection_id, reset_sequence(mode, variant, delay, args.inverted)).await?;
+        info!("Reset ESP board on connection {} into {}", args.connection_id, mode);
+        connection.record_event(format!("ESP reset into {} ({} sequence)", mode, variant)).await;
+
+        let mut warnings = Warnings::new();
+        let output = if args.capture_ms > 0 {
+            connection.drain_output(args.capture_ms).await.map_err(|e| {
+                McpError::internal_error(format!("Error: Reset done, but reading the boot output failed - {}", e), None)
+            })?
+        } else {
+            String::new()
+        };
+        let mut message = format!(
+            "ESP reset complete\nConnection ID: {}\nMode: {}\nVariant: {}{}",
+            args.connection_id,
+            mode,
+            variant,
+            if args.inverted { " (inverted)" } else { "" }
+        );
+        match (mode, in_download_mode(&output)) {
+            (ResetMode::Bootloader, Some(true)) => message.push_str("\nDownload mode: confirmed by the boot output"),
+            (ResetMode::Bootloader, Some(false)) => warnings.push(
+                "The boot output shows a normal boot, not download mode; try variant tight, reset_delay_ms 550 or inverted".to_string(),
+            ),
+            (ResetMode::Run, Some(true)) => warnings.push("The chip came up in download mode; IO0 may be held low".to_string()),
+            _ => {}
+        }
+        if args.capture_ms > 0 {
+            message.push_str(&format!("\nBoot output: {:?}", output));
+        }
+        warnings.into_result(message)
+    }
+
+    #[tool(description = "Flash firmware images to an ESP8266/ESP32 through its ROM bootloader, as esptool write_flash does. Each image is written at its address (a multiple of 0x1000), e.g. for ESP-IDF the bootloader at 0x1000 on ESP32 or 0x0 on later chips, the partition table at 0x8000 and the application at 0x10000. Resets the board into the bootloader first unless reset is false (variant and inverted as for esp_reset), checks each region's MD5 afterwards, and restarts into the application unless run is false. Takes about 10 seconds per megabyte at 115200 baud; the connection must be in raw mode")]
+    async fn esp_flash(&self, Parameters(args): Parameters<EspFlashArgs>) -> Result<CallToolResult, McpError> {
+        if args.images.is_empty() {
+            return Err(McpError::invalid_params("Error: No images to flash".to_string(), None));
+        }
+        let variant = ResetVariant::parse(&args.variant).map_err(|e| McpError::invalid_params(format!("Error: {}", e), None))?;
+        let flash_size = args
+            .flash_size
+            .as_deref()
+            .map(parse_flash_size)
+            .transpose()
+            .map_err(|e| McpError::invalid_params(format!("Error: {}", e), None))?;
+        let images = load_images(&args.images, flash_size.map_or(MAX_FLASH_SIZE, |size| size as usize))?;
+        let connection = self.raw_connection(&args.connection_id, "flash an ESP chip").await?;
+        debug!("Flashing {} images to the ESP chip on connection {}", images.len(), args.connection_id);
+
+        let _exchange = connection.begin_exchange().await;
+        let started = Instant::now();
+        let mut loader = EspLoader::new(&connection);
+        let mut warnings = Warnings::new();
+        let mut lines = Vec::new();
+
+        let reset = args.reset.then_some((variant, args.inverted));
+        let mut result = enter_bootloader(&mut loader, &connection, reset).await;
+        if result.is_ok() {
+            result = flash_images(&mut loader, &images, flash_size, args.verify, &mut lines, &mut warnings).await;
+        }
+        let chip = loader.chip().map_or_else(|| "not detected".to_string(), |chip| chip.to_string());
+        if let Err(e) = result {
+            error!("Flashing the ESP chip on connection {} failed: {}", args.connection_id, e);
+            connection.record_event(format!("ESP flash failed: {}", e)).await;
+            let mut error_msg = format!("Error: ESP flash failed - {}\nChip: {}", e, chip);
+            if !lines.is_empty() {
+                error_msg.push_str(&format!("\n{}", lines.join("\n")));
+            }
+            if matches!(e, EspLoaderError::NoSync) && args.reset {
+                error_msg.push_str("\nTry variant tight or inverted, or hold BOOT while resetting and flash with reset false");
+            }
+            return Err(McpError::internal_error(error_msg, None));
+        }
+
+        if args.run {
+            run_reset(&connection, &args.connection_id, reset_sequence(ResetMode::Run, variant, RESET_DELAY, args.inverted)).await?;
+        }
+        let bytes: usize = images.iter().map(|(_, _, data)| data.len()).sum();
+        info!("Flashed {} bytes to the {} on connection {}", bytes, chip, args.connection_id);
+        connection.record_event(format!("ESP flash: {} images, {} bytes to the {}", images.len(), bytes, chip)).await;
+        let message = format!(
+            "ESP flash complete\nConnection ID: {}\nChip: {}\nBytes written: {}\nTime: {:.1}s\n{}\n{}",
+            args.connection_id,
+            chip,
+            bytes,
+            started.elapsed().as_secs_f64(),
+            lines.join("\n"),
+            if args.run { "Restarted into the application" } else { "Left in the bootloader" }
+        );
+        warnings.into_result(message)
+    }
+}
+
+/// Drive the reset steps on a connection
+pub(super) async fn run_reset(connection: &SerialConnection, connection_id: &str, steps: Vec<ResetStep>) -> Result<(), McpError> {
+    apply_reset(connection, steps).await.map_err(|e| {
+        error!("Failed to drive DTR/RTS on connection {}: {}", connection_id, e);
+        McpError::internal_error(format!("Error: Failed to set DTR/RTS - {}", e), None)
+    })
+}
+
+pub(super) async fn apply_reset(connection: &SerialConnection, steps: Vec<ResetStep>) -> Result<(), LocalSerialError> {
+    for step in steps {
+        match step {
+            ResetStep::Lines { dtr, rts } => connection.set_control_lines(dtr, rts).await?,
+            ResetStep::Wait(duration) => tokio::time::sleep(duration).await,
+        }
+    }
+    Ok(())
+}
+
+/// Sync with the ROM bootloader
+///
+/// With `reset` giving the variant and whether the lines are inverted, the
+/// board is reset into the bootloader first, and again with a longer reset
+/// for slow boards if it does not answer.
+pub(super) async fn enter_bootloader(
+    loader: &mut EspLoader<'_>,
+    connection: &SerialConnection,
+    reset: Option<(ResetVariant, bool)>,
+) -> Result<(), EspLoaderError> {
+    let Some((variant, inverted)) = reset else {
+        return loader.sync(SYNC_ATTEMPTS).await;
+    };
+    let mut result = Err(EspLoaderError::NoSync);
+    for delay in [RESET_DELAY, SLOW_RESET_DELAY] {
+        apply_reset(connection, reset_sequence(ResetMode::Bootloader, variant, delay, inverted)).await?;
+        result = loader.sync(SYNC_ATTEMPTS).await;
+        if !matches!(result, Err(EspLoaderError::NoSync)) {
+            break;
+        }
+    }
+    result
+}
+
+/// Read the images, padded to whole words, checking they fit the flash without overlapping
+fn load_images(images: &[EspFlashImage], flash_size: usize) -> Result<Vec<(u32, String, Vec<u8>)>, McpError> {
+    let mut loaded = Vec::new();
+    for image in images {
+        let (name, mut data) = read_local_file(&image.path, "ESP flash", flash_size as u64)?;
+        if data.is_empty() {
+            return Err(McpError::invalid_params(format!("Error: {} is empty", image.path), None));
+        }
+        if image.address % SECTOR_SIZE != 0 {
+            let error_msg = format!("Error: Address 0x{:X} of {} is not a multiple of 0x{:X}", image.address, name, SECTOR_SIZE);
+            return Err(McpError::invalid_params(error_msg, None));
+        }
+        data.resize(data.len().next_multiple_of(4), 0xFF);
+        if image.address as usize + data.len() > flash_size {
+            let error_msg = format!("Error: {} at 0x{:X} runs past the end of the {} byte flash", name, image.address, flash_size);
+            return Err(McpError::invalid_params(error_msg, None));
+        }
+        loaded.push((image.address, name, data));
+    }
+    loaded.sort_by_key(|(address, _, _)| *address);
+    for pair in loaded.windows(2) {
+        let ((address, name, data), (next, next_name, _)) = (&pair[0], &pair[1]);
+        if *address as usize + data.len() > *next as usize {
+            return Err(McpError::invalid_params(format!("Error: {} at 0x{:X} overlaps {} at 0x{:X}", name, address, next_name, next), None));
+        }
+    }
+    Ok(loaded)
+}
+
+/// Write each image, describing what was done in `lines`
+async fn flash_images(
+    loader: &mut EspLoader<'_>,
+    images: &[(u32, String, Vec<u8>)],
+    flash_size: Option<u32>,
+    verify: bool,
+    lines: &mut Vec<String>,
+    warnings: &mut Warnings,
+) -> Result<(), EspLoaderError> {
+    let chip = loader.detect_chip().await?;
+    loader.attach_flash(flash_size).await?;
+    if verify && !chip.supports_md5() {
+        warnings.push(format!("The {} ROM cannot hash flash, so the write was not verified", chip));
+    }
+    for (address, name, data) in images {
+        let mut written = 0;
+        if let Err(e) = loader.write_flash(*address, data, &mut |bytes| written = bytes).await {
+            lines.push(format!("  0x{:08X} {}: {} of {} bytes written", address, name, written, data.len()));
+            return Err(e);
+        }
+        let mut line = format!("  0x{:08X} {}: {} bytes", address, name, data.len());
+        if verify && chip.supports_md5() {
+            if let Err(e) = verify_md5(loader, *address, data).await {
+                lines.push(line);
+                return Err(e);
+            }
+            line.push_str(", MD5 verified");
+        }
+        lines.push(line);
+    }
+    loader.flash_end(false).await
+}
+
+/// Check the MD5 of `image` in flash at `address`, returning it
+///
+/// A mismatch is narrowed down to the sectors that differ, hashing 64 KiB
+/// blocks first and then the sectors of those that differ.
+pub(super) async fn verify_md5(loader: &mut EspLoader<'_>, address: u32, image: &[u8]) -> Result<String, EspLoaderError> {
+    let expected = format!("{:x}", md5::compute(image));
+    let actual = loader.flash_md5(address, image.len() as u32).await?;
+    if actual == expected {
+        return Ok(actual);
+    }
+    let mut ranges: Vec<(u64, u64)> = Vec::new();
+    for (block_index, block) in image.chunks(MD5_BLOCK_SIZE).enumerate() {
+        let block_address = address + (block_index * MD5_BLOCK_SIZE) as u32;
+        if loader.flash_md5(block_address, block.len() as u32).await? == format!("{:x}", md5::compute(block)) {
+            continue;
+        }
+        for (index, sector) in block.chunks(SECTOR_SIZE as usize).enumerate() {
+            let at = block_address as u64 + (index * SECTOR_SIZE as usize) as u64;
+            if loader.flash_md5(at as u32, sector.len() as u32).await? != format!("{:x}", md5::compute(sector)) {
+                match ranges.last_mut() {
+                    Some((_, end)) if *end == at => *end += sector.len() as u64,
+                    _ => ranges.push((at, at + sector.len() as u64)),
+                }
+            }
+        }
+    }
+    Err(EspLoaderError::Protocol(format!(
+        "Verify failed: MD5 in flash is {}, expected {}; differing sectors: {}",
+        actual,
+        expected,
+        if ranges.is_empty() { "none found on a second look, the flash may be unstable".to_string() } else { format_ranges(&ranges) }
+    )))
+}
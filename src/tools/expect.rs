@@ -0,0 +1,150 @@
+//! Expect/send chat tools
+//!
+//! Modem init strings, U-Boot's autoboot interrupt and login prompts are a
+//! fixed dialogue: wait for some text, answer it, wait for the next. The
+//! `expect` tool runs such a dialogue as a list of steps, as chat(8) does,
+//! and reports what each step received so a failed one shows where the
+//! device went off script.
+
+use std::future::Future;
+use std::time::Instant;
+
+use rmcp::{
+    handler::server::tool::Parameters,
+    model::*,
+    tool, tool_router, ErrorData as McpError,
+};
+use serde::Serialize;
+use tracing::{debug, error};
+
+use super::serial_handler::SerialHandler;
+use super::types::*;
+use super::warnings::Warnings;
+use crate::protocol::expect::Expecter;
+use crate::serial::SerialConnection;
+
+#[tool_router(router = expect_router, vis = "pub(crate)")]
+impl SerialHandler {
+    #[tool(description = "Run an expect/send dialogue on a connection in raw mode, like chat(8) or pexpect: each step waits for its expect text (if any) for up to its timeout_ms, then sends its send text (if any). Include line endings such as \\r in send. Text in abort, such as ERROR or NO CARRIER, fails the step it arrives in. Stops at the first failed step. Returns a transcript of every step run with the text it received, so a failure shows which step it was and what arrived instead")]
+    async fn expect(&self, Parameters(args): Parameters<ExpectArgs>) -> Result<CallToolResult, McpError> {
+        if args.steps.is_empty() {
+            return Err(McpError::invalid_params("Error: steps must not be empty".to_string(), None));
+        }
+        if args.abort.iter().any(String::is_empty) {
+            return Err(McpError::invalid_params("Error: abort strings must not be empty".to_string(), None));
+        }
+        let connection = self.raw_connection(&args.connection_id, "run an expect script").await?;
+        debug!("Running {} expect steps on connection {}", args.steps.len(), args.connection_id);
+
+        let _exchange = connection.begin_exchange().await;
+        let mut expecter = Expecter::new();
+        let mut transcript = Vec::new();
+        let failure = run_steps(&connection, &args, &mut expecter, &mut transcript).await;
+        let transcript_json = serde_json::to_string_pretty(&transcript).unwrap_or_default();
+
+        if let Some(failure) = failure {
+            error!("Expect script on connection {} failed: {}", args.connection_id, failure);
+            let error_msg = format!("Error: {}\nTranscript:\n{}", failure, transcript_json);
+            return Err(McpError::internal_error(error_msg, None));
+        }
+
+        let mut warnings = Warnings::new();
+        warnings.dropped_bytes(connection.take_new_drops().await);
+        let mut message = format!(
+            "Expect script completed\nConnection ID: {}\nSteps: {}\nTranscript:\n{}",
+            args.connection_id,
+            transcript.len(),
+            transcript_json
+        );
+        let unmatched = expecter.take_pending();
+        if !unmatched.is_empty() {
+            message.push_str(&format!("\nReceived after the last match: {:?}", unmatched));
+        }
+        warnings.into_result(message)
+    }
+}
+
+/// What one step waited for, received and sent
+#[derive(Debug, Clone, Serialize)]
+struct StepOutcome {
+    /// Numbered from 1
+    step: usize,
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expect: Option<String>,
+    /// Text received while waiting, up to and including the match
+    received: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sent: Option<String>,
+    elapsed_ms: u64,
+}
+
+/// Run the steps in order, returning why the first failed one did
+async fn run_steps(
+    connection: &SerialConnection,
+    args: &ExpectArgs,
+    expecter: &mut Expecter,
+    transcript: &mut Vec<StepOutcome>,
+) -> Option<String> {
+    for (index, step) in args.steps.iter().enumerate() {
+        let started = Instant::now();
+        let mut outcome = StepOutcome {
+            step: index + 1,
+            status: "ok",
+            expect: step.expect.clone().filter(|expect| !expect.is_empty()),
+            received: String::new(),
+            sent: None,
+            elapsed_ms: 0,
+        };
+
+        let failure = match &outcome.expect {
+            Some(expected) => {
+                let timeout_ms = step.timeout_ms.unwrap_or(args.timeout_ms);
+                let mut patterns = vec![expected.as_str()];
+                patterns.extend(args.abort.iter().map(String::as_str));
+                match expecter.expect_any(connection, &patterns, timeout_ms).await {
+                    Ok(Some((0, received))) => {
+                        outcome.received = received;
+                        None
+                    }
+                    Ok(Some((abort, received))) => {
+                        outcome.status = "aborted";
+                        outcome.received = received;
+                        Some(format!("Step {} aborted on {:?} while waiting for {:?}", index + 1, patterns[abort], expected))
+                    }
+                    Ok(None) => {
+                        outcome.status = "timeout";
+                        outcome.received = expecter.take_pending();
+                        Some(format!("Step {} did not receive {:?} within {}ms", index + 1, expected, timeout_ms))
+                    }
+                    Err(e) => {
+                        outcome.status = "error";
+                        Some(format!("Step {} read failed - {}", index + 1, e))
+                    }
+                }
+            }
+            None => None,
+        };
+
+        let failure = match (failure, &step.send) {
+            (None, Some(send)) if !send.is_empty() => match connection.write(send.as_bytes()).await {
+                Ok(_) => {
+                    outcome.sent = Some(send.clone());
+                    None
+                }
+                Err(e) => {
+                    outcome.status = "error";
+                    Some(format!("Step {} write failed - {}", index + 1, e))
+                }
+            },
+            (failure, _) => failure,
+        };
+
+        outcome.elapsed_ms = started.elapsed().as_millis() as u64;
+        transcript.push(outcome);
+        if failure.is_some() {
+            return failure;
+        }
+    }
+    None
+}
@@ -0,0 +1,47 @@
+//! Idle session expiry
+//!
+//! Sessions left without a connection, such as ones created with connect set
+//! to false or whose reconnects gave up, are removed once unused for longer
+//! than `server.session_idle_timeout_seconds`. Each check also brings the
+//! saved sessions' traffic statistics up to date. The client is told about
+//! every session removed, since its ID stops working.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use rmcp::{
+    model::{LoggingLevel, LoggingMessageNotificationParam},
+    Peer, RoleServer,
+};
+use tracing::{info, warn};
+
+use crate::session::SessionManager;
+
+/// Check for idle sessions every `interval`, for as long as the server runs
+pub(crate) async fn run_session_expiry(peer: Peer<RoleServer>, sessions: Arc<SessionManager>, interval: Duration) {
+    let mut interval = tokio::time::interval(interval);
+    loop {
+        interval.tick().await;
+        for info in sessions.reap_idle_sessions().await {
+            let name = info.config.name.as_ref().map(|name| format!(" ({})", name)).unwrap_or_default();
+            let message = format!(
+                "Session {}{} on {} removed after {}s unused without a connection",
+                info.session_id, name, info.port_name, info.idle_seconds
+            );
+            notify(&peer, message).await;
+        }
+    }
+}
+
+/// Log a removed session and forward it to the client as a logging notification
+async fn notify(peer: &Peer<RoleServer>, message: String) {
+    info!("{}", message);
+    let param = LoggingMessageNotificationParam {
+        level: LoggingLevel::Notice,
+        logger: Some("sessions".to_string()),
+        data: serde_json::Value::String(message),
+    };
+    if let Err(e) = peer.notify_logging_message(param).await {
+        warn!("Failed to send session expiry notification: {}", e);
+    }
+}
@@ -0,0 +1,505 @@
+//! End to end firmware updates
+//!
+//! Run a whole update the way it is done by hand: reset the target into its
+//! bootloader, send the image in the bootloader's protocol, check it arrived
+//! intact and start it. Each step is timed and reported, and clients that
+//! pass a progress token are told how far the transfer has got.
+
+use std::fmt;
+use std::future::Future;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use rmcp::{
+    handler::server::tool::Parameters,
+    model::*,
+    tool, tool_router, ErrorData as McpError, Peer, RoleServer,
+};
+use serde::Serialize;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info};
+
+use super::avr::{identify, pad_pages, read_hex, reset_board, verify_pages, write_pages, SYNC_ATTEMPTS};
+use super::esp::{apply_reset, enter_bootloader, verify_md5, RESET_DELAY};
+use super::serial_handler::SerialHandler;
+use super::stm32::{has_even_parity, verify, PARITY_HINT};
+use super::types::*;
+use super::verify::{verify_files, Digests};
+use super::warnings::Warnings;
+use super::ymodem::read_local_file;
+use crate::protocol::esp::{reset_sequence, ResetMode, ResetVariant};
+use crate::protocol::esp_loader::{EspLoader, EspLoaderError, MAX_FLASH_SIZE};
+use crate::protocol::stk500::{Stk500, Stk500Error, Stk500Version};
+use crate::protocol::stm32::{product_name, Erase, Stm32Bootloader, Stm32Error};
+use crate::protocol::ymodem::{send_xmodem, TransferProgress};
+use crate::serial::{LocalSerialError, SerialConnection};
+
+/// Largest image accepted, the biggest ESP flash
+const MAX_IMAGE: u64 = MAX_FLASH_SIZE as u64;
+
+/// ESP images are erased in whole sectors, so they must start on one
+const ESP_SECTOR_SIZE: u32 = 0x1000;
+
+/// Output read after the bootloader command, so its echo is not taken for the receiver starting
+const COMMAND_SETTLE_MS: u64 = 500;
+
+/// Bootloader the image is sent to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FirmwareProtocol {
+    Esp,
+    Stm32,
+    Avr(Stk500Version),
+    Xmodem,
+}
+
+impl FirmwareProtocol {
+    fn parse(value: &str) -> Result<Self, LocalSerialError> {
+        match value.to_lowercase().as_str() {
+            "esp" | "esp32" | "esp8266" => Ok(Self::Esp),
+            "stm32" => Ok(Self::Stm32),
+            "xmodem" => Ok(Self::Xmodem),
+            other => Stk500Version::parse(other).map(Self::Avr).map_err(|_| {
+                LocalSerialError::InvalidConfig(format!(
+                    "Unknown firmware protocol '{}', expected esp, stm32, stk500v1, stk500v2 or xmodem",
+                    value
+                ))
+            }),
+        }
+    }
+
+    /// Where a binary goes when no address is given
+    fn default_address(self) -> u32 {
+        match self {
+            Self::Esp => 0x10000,
+            Self::Stm32 => 0x0800_0000,
+            Self::Avr(_) | Self::Xmodem => 0,
+        }
+    }
+}
+
+impl fmt::Display for FirmwareProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Esp => write!(f, "esp"),
+            Self::Stm32 => write!(f, "stm32"),
+            Self::Avr(version) => write!(f, "{}", version),
+            Self::Xmodem => write!(f, "xmodem"),
+        }
+    }
+}
+
+/// What an update did, returned with the result
+#[derive(Debug, Clone, Serialize)]
+struct FirmwareReport {
+    protocol: String,
+    file: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target: Option<String>,
+    address: u32,
+    bytes: usize,
+    /// Bytes the bootloader accepted
+    bytes_written: usize,
+    /// The image was hashed or read back and matched
+    verified: bool,
+    /// The new firmware was started
+    started: bool,
+    success: bool,
+    steps: Vec<FirmwareStep>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct FirmwareStep {
+    step: &'static str,
+    status: &'static str,
+    detail: String,
+    elapsed_ms: u64,
+}
+
+/// Transfer progress, as bytes written and the step running
+type ProgressUpdate = (usize, &'static str);
+
+/// The report being built, and where progress goes
+struct FirmwareRun {
+    report: FirmwareReport,
+    step: &'static str,
+    step_started: Instant,
+    progress: Option<mpsc::UnboundedSender<ProgressUpdate>>,
+}
+
+impl FirmwareRun {
+    fn begin(&mut self, step: &'static str) {
+        self.step = step;
+        self.step_started = Instant::now();
+        self.send_progress();
+    }
+
+    fn finish(&mut self, detail: impl Into<String>) {
+        self.push("ok", detail.into());
+    }
+
+    fn skip(&mut self, step: &'static str, detail: impl Into<String>) {
+        self.step = step;
+        self.step_started = Instant::now();
+        self.push("skipped", detail.into());
+    }
+
+    fn fail(&mut self, detail: String) {
+        self.push("failed", detail);
+    }
+
+    fn push(&mut self, status: &'static str, detail: String) {
+        let elapsed_ms = self.step_started.elapsed().as_millis() as u64;
+        self.report.steps.push(FirmwareStep { step: self.step, status, detail, elapsed_ms });
+    }
+
+    /// Bytes written so far, which may include padding past the image
+    fn written(&mut self, bytes: usize) {
+        self.report.bytes_written = bytes.min(self.report.bytes);
+        self.send_progress();
+    }
+
+    fn send_progress(&self) {
+        if let Some(progress) = &self.progress {
+            let _ = progress.send((self.report.bytes_written, self.step));
+        }
+    }
+}
+
+#[tool_router(router = firmware_router, vis = "pub(crate)")]
+impl SerialHandler {
+    #[tool(description = "Update a device's firmware end to end: reset it into its bootloader, write the image, verify it and start it, returning a report of every step. protocol esp (ESP8266/ESP32 ROM; binaries default to 0x10000), stm32 (system bootloader with BOOT0 held high, even parity; mass erases first unless erase is false), stk500v1 (Arduino optiboot) or stk500v2 (Mega 2560) for AVR boards, or xmodem for bootloaders that receive over XMODEM, optionally started by sending bootloader_command and checked by the output of verify_command. A .hex path is read as Intel HEX at its own address. Sends progress notifications when the request carries a progress token. The connection must be in raw mode; for the single steps use esp_flash, stm32_write, avr_flash or ymodem_send")]
+    async fn flash_firmware(
+        &self,
+        Parameters(args): Parameters<FlashFirmwareArgs>,
+        meta: Meta,
+        peer: Peer<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let protocol = FirmwareProtocol::parse(&args.protocol).map_err(|e| McpError::invalid_params(format!("Error: {}", e), None))?;
+        let variant = ResetVariant::parse(&args.variant).map_err(|e| McpError::invalid_params(format!("Error: {}", e), None))?;
+        let (name, address, image) = load_image(&args, protocol)?;
+        let connection = self.raw_connection(&args.connection_id, "flash firmware").await?;
+        debug!("Flashing {} ({} bytes) over {} on connection {}", name, image.len(), protocol, args.connection_id);
+
+        let _exchange = connection.begin_exchange().await;
+        let started = Instant::now();
+        let (progress, forwarder) = match meta.get_progress_token() {
+            Some(token) => {
+                let (sender, forwarder) = forward_progress(peer, token, image.len());
+                (Some(sender), Some(forwarder))
+            }
+            None => (None, None),
+        };
+        let mut run = FirmwareRun {
+            report: FirmwareReport {
+                protocol: protocol.to_string(),
+                file: name.clone(),
+                target: None,
+                address,
+                bytes: image.len(),
+                bytes_written: 0,
+                verified: false,
+                started: false,
+                success: false,
+                steps: Vec::new(),
+            },
+            step: "bootloader",
+            step_started: Instant::now(),
+            progress,
+        };
+
+        let mut warnings = Warnings::new();
+        let result = match protocol {
+            FirmwareProtocol::Esp => flash_esp(&connection, &args, variant, address, &image, &mut run, &mut warnings).await,
+            FirmwareProtocol::Stm32 => flash_stm32(&connection, &args, address, &image, &mut run).await,
+            FirmwareProtocol::Avr(version) => flash_avr(&connection, &args, version, address, &image, &mut run).await,
+            FirmwareProtocol::Xmodem => flash_xmodem(&connection, &args, &name, &image, &mut run, &mut warnings).await,
+        };
+        // Let the last progress notifications go out before the result
+        drop(run.progress.take());
+        if let Some(forwarder) = forwarder {
+            let _ = forwarder.await;
+        }
+
+        if let Err(reason) = result {
+            run.fail(reason.clone());
+            error!("Firmware update on connection {} failed at {}: {}", args.connection_id, run.step, reason);
+            connection.record_event(format!("Firmware update failed at {}: {}", run.step, reason)).await;
+            let mut error_msg = format!("Error: Firmware update failed at {} - {}", run.step, reason);
+            if protocol == FirmwareProtocol::Stm32 && !has_even_parity(&connection) {
+                error_msg.push_str(&format!("\n{}", PARITY_HINT));
+            }
+            let report = serde_json::to_string_pretty(&run.report).unwrap_or_default();
+            error_msg.push_str(&format!("\nReport:\n{}", report));
+            return Err(McpError::internal_error(error_msg, None));
+        }
+
+        run.report.success = true;
+        info!("Flashed {} bytes over {} on connection {}", image.len(), protocol, args.connection_id);
+        connection.record_event(format!("Firmware update: {} ({} bytes) over {}", name, image.len(), protocol)).await;
+        let report = serde_json::to_string_pretty(&run.report).unwrap_or_default();
+        let message = format!(
+            "Firmware update complete\nConnection ID: {}\nProtocol: {}\nTarget: {}\nFile: {}\nBytes: {}\nVerified: {}\nStarted: {}\nTime: {:.1}s\nReport:\n{}",
+            args.connection_id,
+            protocol,
+            run.report.target.as_deref().unwrap_or("unknown"),
+            name,
+            image.len(),
+            if run.report.verified { "yes" } else { "no" },
+            if run.report.started { "yes" } else { "no" },
+            started.elapsed().as_secs_f64(),
+            report
+        );
+        warnings.into_result(message)
+    }
+}
+
+/// The image to write, its name and the address it goes at
+fn load_image(args: &FlashFirmwareArgs, protocol: FirmwareProtocol) -> Result<(String, u32, Vec<u8>), McpError> {
+    let is_hex = Path::new(&args.path).extension().is_some_and(|extension| extension.eq_ignore_ascii_case("hex"));
+    let (name, address, mut image) = if is_hex {
+        let name = Path::new(&args.path).file_name().map_or_else(|| args.path.clone(), |name| name.to_string_lossy().into_owned());
+        let (start, image) = read_hex(&args.path)?;
+        (name, start, image)
+    } else {
+        let (name, image) = read_local_file(&args.path, "Firmware", MAX_IMAGE)?;
+        (name, args.address.unwrap_or(protocol.default_address()), image)
+    };
+    if image.is_empty() {
+        return Err(McpError::invalid_params(format!("Error: {} is empty", args.path), None));
+    }
+    match protocol {
+        FirmwareProtocol::Esp => {
+            if address % ESP_SECTOR_SIZE != 0 {
+                let error_msg = format!("Error: Address 0x{:X} is not a multiple of 0x{:X}", address, ESP_SECTOR_SIZE);
+                return Err(McpError::invalid_params(error_msg, None));
+            }
+            image.resize(image.len().next_multiple_of(4), 0xFF);
+            if address as usize + image.len() > MAX_FLASH_SIZE {
+                return Err(McpError::invalid_params(format!("Error: {} at 0x{:X} runs past the end of flash", name, address), None));
+            }
+        }
+        FirmwareProtocol::Stm32 if address % 4 != 0 => {
+            return Err(McpError::invalid_params(format!("Error: Address 0x{:08X} is not word aligned", address), None));
+        }
+        _ => {}
+    }
+    Ok((name, address, image))
+}
+
+/// Send progress notifications for `total` bytes, once per percent or step
+fn forward_progress(peer: Peer<RoleServer>, token: ProgressToken, total: usize) -> (mpsc::UnboundedSender<ProgressUpdate>, JoinHandle<()>) {
+    let (sender, mut receiver) = mpsc::unbounded_channel::<ProgressUpdate>();
+    let forwarder = tokio::spawn(async move {
+        let mut last = None;
+        while let Some((bytes, step)) = receiver.recv().await {
+            let percent = bytes * 100 / total.max(1);
+            if last == Some((percent, step)) {
+                continue;
+            }
+            last = Some((percent, step));
+            let param = ProgressNotificationParam {
+                progress_token: token.clone(),
+                progress: bytes as u32,
+                total: Some(total as u32),
+                message: Some(step.to_string()),
+            };
+            if let Err(e) = peer.notify_progress(param).await {
+                debug!("Stopped firmware progress notifications: {}", e);
+                return;
+            }
+        }
+    });
+    (sender, forwarder)
+}
+
+async fn flash_esp(
+    connection: &SerialConnection,
+    args: &FlashFirmwareArgs,
+    variant: ResetVariant,
+    address: u32,
+    image: &[u8],
+    run: &mut FirmwareRun,
+    warnings: &mut Warnings,
+) -> Result<(), String> {
+    let mut loader = EspLoader::new(connection);
+    run.begin("bootloader");
+    let chip = async {
+        enter_bootloader(&mut loader, connection, args.reset.then_some((variant, args.inverted))).await?;
+        let chip = loader.detect_chip().await?;
+        loader.attach_flash(None).await?;
+        Ok::<_, EspLoaderError>(chip)
+    }
+    .await
+    .map_err(|e| e.to_string())?;
+    run.report.target = Some(chip.to_string());
+    run.finish(format!("{} ROM bootloader synced", chip));
+
+    run.begin("write");
+    loader.write_flash(address, image, &mut |bytes| run.written(bytes)).await.map_err(|e| e.to_string())?;
+    run.finish(format!("{} bytes at 0x{:X}", image.len(), address));
+
+    if !args.verify {
+        run.skip("verify", "Not asked for");
+    } else if chip.supports_md5() {
+        run.begin("verify");
+        let actual = verify_md5(&mut loader, address, image).await.map_err(|e| e.to_string())?;
+        run.report.verified = true;
+        run.finish(format!("MD5 {} matches", actual));
+    } else {
+        run.skip("verify", format!("The {} ROM cannot hash flash", chip));
+        warnings.push(format!("The {} ROM cannot hash flash, so the write was not verified", chip));
+    }
+
+    loader.flash_end(false).await.map_err(|e| e.to_string())?;
+    if args.run {
+        run.begin("run");
+        apply_reset(connection, reset_sequence(ResetMode::Run, variant, RESET_DELAY, args.inverted)).await.map_err(|e| e.to_string())?;
+        run.report.started = true;
+        run.finish("Reset into the application");
+    } else {
+        run.skip("run", "Left in the bootloader");
+    }
+    Ok(())
+}
+
+async fn flash_stm32(connection: &SerialConnection, args: &FlashFirmwareArgs, address: u32, image: &[u8], run: &mut FirmwareRun) -> Result<(), String> {
+    let mut bootloader = Stm32Bootloader::new(connection);
+    run.begin("bootloader");
+    let (info, pid) = async {
+        bootloader.init().await?;
+        let info = bootloader.get().await?;
+        let pid = bootloader.get_id().await?;
+        Ok::<_, Stm32Error>((info, pid))
+    }
+    .await
+    .map_err(|e| e.to_string())?;
+    let target = product_name(pid).map_or_else(|| format!("STM32 product 0x{:03X}", pid), |name| name.to_string());
+    run.finish(format!("Bootloader {}.{} on the {}", info.version >> 4, info.version & 0x0F, target));
+    run.report.target = Some(target);
+
+    if args.erase {
+        run.begin("erase");
+        bootloader.erase(&info, &Erase::Mass).await.map_err(|e| e.to_string())?;
+        run.finish("Mass erase");
+    } else {
+        run.skip("erase", "Not asked for; the flash must already be erased");
+    }
+
+    run.begin("write");
+    bootloader.write(address, image, &mut |bytes| run.written(bytes)).await.map_err(|e| e.to_string())?;
+    run.finish(format!("{} bytes at 0x{:08X}", image.len(), address));
+
+    if args.verify {
+        run.begin("verify");
+        verify(&mut bootloader, address, image).await.map_err(|e| e.to_string())?;
+        run.report.verified = true;
+        run.finish("Read back and matched");
+    } else {
+        run.skip("verify", "Not asked for");
+    }
+
+    if args.run {
+        run.begin("run");
+        bootloader.go(address).await.map_err(|e| e.to_string())?;
+        run.report.started = true;
+        run.finish(format!("Jumped to 0x{:08X}", address));
+    } else {
+        run.skip("run", "Left in the bootloader");
+    }
+    Ok(())
+}
+
+async fn flash_avr(
+    connection: &SerialConnection,
+    args: &FlashFirmwareArgs,
+    version: Stk500Version,
+    address: u32,
+    image: &[u8],
+    run: &mut FirmwareRun,
+) -> Result<(), String> {
+    if args.reset {
+        run.begin("reset");
+        reset_board(connection).await.map_err(|e| format!("Failed to set DTR/RTS - {}", e))?;
+        run.finish("Reset through DTR");
+    }
+
+    let mut programmer = Stk500::new(connection, version);
+    run.begin("bootloader");
+    let (bootloader, (name, page_size)) = async {
+        let bootloader = programmer.sync(SYNC_ATTEMPTS).await?;
+        let part = identify(&mut programmer, None, address, image.len()).await?;
+        Ok::<_, Stk500Error>((format!("{} {}", version, bootloader), part))
+    }
+    .await
+    .map_err(|e| e.to_string())?;
+    run.finish(format!("{} on the {}", bootloader.trim_end(), name));
+    run.report.target = Some(name);
+
+    run.begin("write");
+    let (first, pages) = pad_pages(address, image, page_size);
+    programmer.enter_programming().await.map_err(|e| e.to_string())?;
+    write_pages(&mut programmer, first, &pages, page_size, &mut |bytes| run.written(bytes)).await.map_err(|e| e.to_string())?;
+    run.finish(format!("{} bytes at 0x{:05X} in {} byte pages", image.len(), address, page_size));
+
+    if args.verify {
+        run.begin("verify");
+        verify_pages(&mut programmer, first, &pages, page_size).await.map_err(|e| e.to_string())?;
+        run.report.verified = true;
+        run.finish("Read back and matched");
+    } else {
+        run.skip("verify", "Not asked for");
+    }
+
+    if args.run {
+        // Leaving programming mode starts the new program
+        run.begin("run");
+        programmer.leave_programming().await.map_err(|e| e.to_string())?;
+        run.report.started = true;
+        run.finish("Left programming mode");
+    } else {
+        run.skip("run", "Left in the bootloader until it times out");
+    }
+    Ok(())
+}
+
+async fn flash_xmodem(
+    connection: &SerialConnection,
+    args: &FlashFirmwareArgs,
+    name: &str,
+    image: &[u8],
+    run: &mut FirmwareRun,
+    warnings: &mut Warnings,
+) -> Result<(), String> {
+    if let Some(command) = &args.bootloader_command {
+        run.begin("bootloader");
+        connection.write(command.as_bytes()).await.map_err(|e| e.to_string())?;
+        let _ = connection.drain_output(COMMAND_SETTLE_MS).await;
+        run.finish(format!("Sent {:?}", command));
+    }
+
+    run.begin("write");
+    let mut progress = TransferProgress::new(name, Some(image.len() as u64));
+    let start_timeout = Duration::from_millis(args.start_timeout_ms);
+    let result = send_xmodem(connection, image, start_timeout, &mut progress, &mut |bytes| run.written(bytes as usize)).await;
+    result.map_err(|e| e.to_string())?;
+    run.finish(format!("{} bytes, {} blocks sent again", image.len(), progress.retries));
+
+    match (&args.verify_command, args.verify) {
+        (Some(command), true) => {
+            run.begin("verify");
+            let timeout = Duration::from_millis(args.verify_timeout_ms);
+            let lines = verify_files(connection, command, &[(name, image)], timeout).await?;
+            run.report.verified = true;
+            run.finish(lines.join("").trim().to_string());
+        }
+        (None, true) => {
+            // The receiver checks each block, but the image cannot be read back
+            warnings.push(format!("XMODEM cannot read the image back, so it was not verified; give a verify_command to check its {}", Digests::of(image)));
+            run.skip("verify", "No verify_command; XMODEM checks each block as it arrives");
+        }
+        (_, false) => run.skip("verify", "Not asked for"),
+    }
+    run.skip("run", "The bootloader starts the image itself");
+    Ok(())
+}
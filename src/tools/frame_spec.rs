@@ -0,0 +1,114 @@
+//! Declarative frame tools
+//!
+//! Build and take apart frames of one-off vendor protocols from a layout
+//! given with the call: header, length field, payload, checksum and trailer.
+
+use std::future::Future;
+
+use rmcp::{
+    handler::server::tool::Parameters,
+    model::*,
+    tool, tool_router, ErrorData as McpError,
+};
+use serde_json::json;
+use tracing::error;
+
+use super::serial_handler::SerialHandler;
+use super::types::*;
+use crate::protocol::frame_spec::{ChecksumField, FramePart, FrameSpec, LengthCounts, LengthField};
+use crate::protocol::ByteOrder;
+use crate::utils::Checksum;
+
+#[tool_router(router = frame_spec_router, vis = "pub(crate)")]
+impl SerialHandler {
+    #[tool(description = "Build a frame of a custom protocol from a declarative spec: constant header, optional length field (1, 2 or 4 bytes, counting the payload, payload and checksum, or the whole frame, plus an adjustment), the payload, an optional checksum (any algorithm of the checksum tool) over a range of header, length and payload, and a constant trailer. Returns the frame in hex, and sends it when connection_id is given")]
+    async fn build_frame(&self, Parameters(args): Parameters<BuildFrameArgs>) -> Result<CallToolResult, McpError> {
+        let spec = frame_spec(&args.spec).map_err(|e| McpError::invalid_params(format!("Error: {}", e), None))?;
+        let payload = decode_data(&args.payload, &args.encoding)
+            .map_err(|e| McpError::invalid_params(format!("Error: Data decoding failed - {}", e), None))?;
+        let frame = spec.build(&payload).map_err(|e| McpError::invalid_params(format!("Error: {}", e), None))?;
+
+        let mut message = format!(
+            "Frame built\nPayload bytes: {}\nFrame bytes: {}\nFrame: {}",
+            payload.len(),
+            frame.len(),
+            encode_data(&frame, "hex").unwrap_or_default()
+        );
+        if let Some(connection_id) = &args.connection_id {
+            let connection = self.connection(connection_id).await?;
+            let written = connection.write(&frame).await.map_err(|e| {
+                error!("Failed to send frame on connection {}: {}", connection_id, e);
+                McpError::internal_error(format!("Error: Data sending failed - {}", e), None)
+            })?;
+            message.push_str(&format!("\nSent on connection {}: {} bytes", connection_id, written));
+        }
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+
+    #[tool(description = "Parse received bytes against a declarative frame spec (the same spec as build_frame): finds each frame by its header, reads the length field, checks the checksum and trailer, and returns every frame's payload. Garbage between frames is skipped, and frames that are cut short or fail their checksum are reported")]
+    async fn parse_frame(&self, Parameters(args): Parameters<ParseFrameArgs>) -> Result<CallToolResult, McpError> {
+        let spec = frame_spec(&args.spec).map_err(|e| McpError::invalid_params(format!("Error: {}", e), None))?;
+        let data = decode_data(&args.data, &args.encoding)
+            .map_err(|e| McpError::invalid_params(format!("Error: Data decoding failed - {}", e), None))?;
+
+        let results = spec.scan(&data);
+        let valid = results.iter().filter(|(_, frame)| frame.as_ref().is_ok_and(|frame| frame.checksum_ok())).count();
+        let frames: Vec<serde_json::Value> = results
+            .iter()
+            .map(|(offset, frame)| match frame {
+                Ok(frame) => json!({
+                    "offset": offset,
+                    "length": frame.frame_len,
+                    "payload": encode_data(&frame.payload, "hex").unwrap_or_default(),
+                    "payload_text": String::from_utf8_lossy(&frame.payload),
+                    "length_field": frame.length_field,
+                    "checksum": frame.checksum.map(|(received, computed)| json!({
+                        "received": format!("0x{:X}", received),
+                        "computed": format!("0x{:X}", computed),
+                        "ok": received == computed,
+                    })),
+                }),
+                Err(e) => json!({ "offset": offset, "error": e.to_string() }),
+            })
+            .collect();
+
+        let message = format!(
+            "Frames parsed\nBytes: {}\nFrames: {}\nValid: {}\n{}",
+            data.len(),
+            frames.len(),
+            valid,
+            serde_json::to_string_pretty(&frames).unwrap_or_default()
+        );
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+}
+
+/// The spec described by the arguments
+fn frame_spec(args: &FrameSpecArgs) -> Result<FrameSpec, String> {
+    let hex = |value: &str, name: &str| decode_data(value, "hex").map_err(|e| format!("Invalid {} - {}", name, e));
+    let byte_order = ByteOrder::parse(&args.byte_order).map_err(|e| e.to_string())?;
+
+    let length = match args.length_bytes {
+        0 => None,
+        bytes => Some(LengthField {
+            bytes,
+            byte_order,
+            counts: LengthCounts::parse(&args.length_counts).map_err(|e| e.to_string())?,
+            adjust: args.length_adjust,
+        }),
+    };
+    let checksum = match &args.checksum {
+        Some(name) => Some(ChecksumField {
+            algorithm: Checksum::named(name)
+                .ok_or_else(|| format!("Unknown checksum {}, expected one of {}", name, Checksum::NAMES.join(", ")))?,
+            byte_order: match &args.checksum_byte_order {
+                Some(order) => ByteOrder::parse(order).map_err(|e| e.to_string())?,
+                None => byte_order,
+            },
+            from: FramePart::parse(&args.checksum_from).map_err(|e| e.to_string())?,
+            to: FramePart::parse(&args.checksum_to).map_err(|e| e.to_string())?,
+        }),
+        None => None,
+    };
+    FrameSpec::new(hex(&args.header, "header")?, length, checksum, hex(&args.trailer, "trailer")?).map_err(|e| e.to_string())
+}
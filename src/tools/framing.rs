@@ -0,0 +1,225 @@
+//! Framed reads and writes
+//!
+//! Tools to pick a connection's framer and exchange whole frames through it.
+
+use std::future::Future;
+
+use rmcp::{
+    handler::server::tool::Parameters,
+    model::*,
+    tool, tool_router, ErrorData as McpError,
+};
+use tracing::{debug, error, info};
+
+use super::serial_handler::{format_queued, rate_limited, write_turn, SerialHandler};
+use super::types::*;
+use super::warnings::Warnings;
+use crate::protocol::postcard::PostcardType;
+use crate::protocol::protobuf::{self, MessageDescriptor};
+use crate::protocol::{FramerKind, FramerOptions, FramerSpec};
+use crate::serial::{LocalSerialError, SerialConnection};
+
+#[tool_router(router = framing_router, vis = "pub(crate)")]
+impl SerialHandler {
+    #[tool(description = "Select the framer of a connection: 'raw', 'slip' (RFC 1055, as used by ESP-IDF tools and 6LoWPAN sniffers), 'cobs' (zero-delimited Consistent Overhead Byte Stuffing), 'hdlc' (0x7E flags and 0x7D escaping as in PPP, with the CRC-16 FCS checked on receipt and added on send), 'length' (payload behind a 1, 2 or 4 byte length field and optional header magic, for TLV-style protocols; see length_bytes, byte_order and magic), 'varint' (payload behind a base-128 varint length, as protobuf's delimited streams), 'delimiter' (payload followed by fixed bytes, see delimiter) or 'none' to go back to plain bytes. Any framer can carry a checksum after each payload (see checksum). With a framer, read and read_frames return whole decoded frames, and write and write_frame send their data encapsulated as one frame. Not available in line mode")]
+    async fn set_framer(&self, Parameters(args): Parameters<SetFramerArgs>) -> Result<CallToolResult, McpError> {
+        let connection = self.connection(&args.connection_id).await?;
+        let framer = match args.framer.to_lowercase().as_str() {
+            "none" => None,
+            name => Some(FramerKind::parse(name).map_err(|e| McpError::invalid_params(format!("Error: {}", e), None))?),
+        };
+        if framer.is_some() && connection.is_line_mode().await {
+            let error_msg = format!("Error: Connection {} is in line mode, disable it to use a framer", args.connection_id);
+            return Err(McpError::invalid_params(error_msg, None));
+        }
+
+        let spec = FramerSpec {
+            framer: args.framer.to_lowercase(),
+            length_bytes: args.length_bytes,
+            byte_order: args.byte_order.clone(),
+            magic: args.magic.clone(),
+            delimiter: args.delimiter.clone(),
+            checksum: args.checksum.clone(),
+            checksum_byte_order: args.checksum_byte_order.clone(),
+        };
+        let (built, name) = match framer {
+            Some(_) => {
+                let (built, name) = spec.build().map_err(|e| McpError::invalid_params(format!("Error: {}", e), None))?;
+                (Some(built), name)
+            }
+            None if spec.options() != FramerOptions::default() => {
+                return Err(McpError::invalid_params("Error: Framer options do not apply to 'none'".to_string(), None));
+            }
+            None => (None, "none".to_string()),
+        };
+        connection.set_framer(built).await;
+        let mut specs = self.framer_specs.lock().await;
+        match framer {
+            Some(_) => specs.insert(args.connection_id.clone(), spec),
+            None => specs.remove(&args.connection_id),
+        };
+        drop(specs);
+        self.save_session_framing(&connection).await;
+
+        info!("Framer of connection {} set to {}", args.connection_id, name);
+        let message = format!("Framer set\nConnection ID: {}\nFramer: {}", args.connection_id, name);
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+
+    #[tool(description = "Read whole frames decoded by the connection's framer (see set_framer). A frame still arriving is kept for the next read; malformed frames are reported and skipped. With postcard_schema, payloads are deserialized as postcard (the serde format of most embedded Rust host links, usually sent with the cobs framer), and with protobuf_message as that Protocol Buffers message type (see protobuf_register; usually sent with the varint framer), and returned as JSON")]
+    async fn read_frames(&self, Parameters(args): Parameters<ReadFramesArgs>) -> Result<CallToolResult, McpError> {
+        debug!("Reading frames from connection {} with timeout {:?}", args.connection_id, args.timeout_ms);
+
+        let decoder = match (&args.postcard_schema, &args.protobuf_message) {
+            (Some(description), None) => Some(PayloadDecoder::Postcard(
+                PostcardType::parse(description)
+                    .map_err(|e| McpError::invalid_params(format!("Error: Invalid postcard_schema - {}", e), None))?,
+            )),
+            (None, Some(name)) => Some(PayloadDecoder::Protobuf(
+                self.protobuf.lock().await.message(name).map_err(|e| McpError::invalid_params(format!("Error: {}", e), None))?,
+            )),
+            (None, None) => None,
+            (Some(_), Some(_)) => {
+                return Err(McpError::invalid_params("Error: Give postcard_schema or protobuf_message, not both".to_string(), None));
+            }
+        };
+        let connection = self.connection(&args.connection_id).await?;
+        let encoding = connection.encoding(args.encoding).await;
+        let timeout_ms = args.timeout_ms.unwrap_or(self.session_config(&args.connection_id).await.timeout_ms);
+        self.read_decoded_frames(&connection, &args.connection_id, args.max_frames, timeout_ms, &encoding, decoder.as_ref())
+            .await
+    }
+
+    #[tool(description = "Send one frame through the connection's framer (see set_framer), which adds the protocol's delimiters and escaping")]
+    async fn write_frame(&self, Parameters(args): Parameters<WriteFrameArgs>) -> Result<CallToolResult, McpError> {
+        let connection = self.connection(&args.connection_id).await?;
+        let payload = decode_data(&args.data, &connection.encoding(args.encoding).await)
+            .map_err(|e| McpError::invalid_params(format!("Error: Data decoding failed - {}", e), None))?;
+
+        let turn = write_turn(&connection, args.queue_timeout_ms).await?;
+        match connection.write_frame_in_turn(&turn, &payload).await {
+            Ok(sent) => {
+                let message = format!(
+                    "Frame sent\nConnection ID: {}\nPayload bytes: {}\nBytes written: {}{}\n{}",
+                    args.connection_id,
+                    payload.len(),
+                    sent.len(),
+                    format_queued(&turn),
+                    format_sent(&sent)
+                );
+                Ok(CallToolResult::success(vec![Content::text(message)]))
+            }
+            Err(LocalSerialError::InvalidConfig(e)) => {
+                Err(McpError::invalid_params(format!("Error: {}, select one with set_framer", e), None))
+            }
+            Err(LocalSerialError::Frame(e)) => Err(McpError::invalid_params(format!("Error: {}", e), None)),
+            Err(LocalSerialError::RateLimited(limited)) => Err(rate_limited(&limited)),
+            Err(e) => {
+                error!("Failed to write frame to connection {}: {}", args.connection_id, e);
+                Err(McpError::internal_error(format!("Error: Data sending failed - {}", e), None))
+            }
+        }
+    }
+}
+
+impl SerialHandler {
+    /// Read whole frames through a connection's framer and format them for the client
+    pub(crate) async fn read_decoded_frames(
+        &self,
+        connection: &SerialConnection,
+        connection_id: &str,
+        max_frames: usize,
+        timeout_ms: u64,
+        encoding: &str,
+        decoder: Option<&PayloadDecoder>,
+    ) -> Result<CallToolResult, McpError> {
+        let frames = match connection.read_frames(max_frames.max(1), Some(timeout_ms)).await {
+            Ok(frames) if frames.is_empty() => {
+                let message = format!("Reception paused\nConnection ID: {}\nFrames: 0\n{}", connection_id, connection.freshness().await);
+                return Ok(CallToolResult::success(vec![Content::text(message)]));
+            }
+            Ok(frames) => frames,
+            Err(LocalSerialError::ReadTimeout) => {
+                let message = format!(
+                    "Read timeout\nConnection ID: {}\nTimeout: {}ms\nFrames: 0\n{}",
+                    connection_id,
+                    timeout_ms,
+                    connection.freshness().await
+                );
+                return Ok(CallToolResult::success(vec![Content::text(message)]));
+            }
+            Err(LocalSerialError::InvalidConfig(e)) => {
+                return Err(McpError::invalid_params(format!("Error: {}, select one with set_framer", e), None));
+            }
+            Err(e) => {
+                error!("Failed to read frames from connection {}: {}", connection_id, e);
+                self.record_session_error(connection_id, &e).await;
+                return Err(McpError::internal_error(format!("Error: Data reading failed - {}", e), None));
+            }
+        };
+        let received = frames.iter().flatten().map(|frame| frame.len()).sum();
+        let _ = self.sessions.record_session_receive(connection_id, received).await;
+
+        let mut warnings = Warnings::new();
+        let mut message = format!(
+            "Frames read\nConnection ID: {}\nFrames: {}\n{}",
+            connection_id,
+            frames.len(),
+            connection.freshness().await
+        );
+        for (index, frame) in frames.iter().enumerate() {
+            match (frame, decoder) {
+                (Ok(frame), Some(decoder)) => match decoder.decode(frame) {
+                    Ok((value, rest)) => {
+                        let value = serde_json::to_string_pretty(&value).unwrap_or_default();
+                        message.push_str(&format!("\n\n[{}] {} bytes\n{}", index, frame.len(), value));
+                        if rest > 0 {
+                            warnings.push(format!("Frame {} has {} bytes after the {} value", index, rest, decoder.name()));
+                        }
+                    }
+                    Err(e) => {
+                        let payload = ReadPayload::new(frame, encoding)
+                            .map_err(|e| McpError::invalid_params(format!("Error: Data encoding failed - {}", e), None))?;
+                        message.push_str(&format!("\n\n[{}] {} bytes, not {}: {}\n{}", index, frame.len(), decoder.name(), e, payload));
+                        warnings.push(format!("Frame {} could not be decoded as {}: {}", index, decoder.name(), e));
+                    }
+                },
+                (Ok(frame), None) => {
+                    let payload = ReadPayload::new(frame, encoding)
+                        .map_err(|e| McpError::invalid_params(format!("Error: Data encoding failed - {}", e), None))?;
+                    message.push_str(&format!("\n\n[{}] {} bytes\n{}", index, frame.len(), payload));
+                }
+                (Err(e), _) => {
+                    message.push_str(&format!("\n\n[{}] {}", index, e));
+                    warnings.push(format!("Frame {} skipped: {}", index, e));
+                }
+            }
+        }
+        warnings.dropped_bytes(connection.take_new_drops().await);
+        warnings.into_result(message)
+    }
+}
+
+/// Structured decoding of frame payloads for read_frames
+pub(crate) enum PayloadDecoder {
+    Postcard(PostcardType),
+    Protobuf(MessageDescriptor),
+}
+
+impl PayloadDecoder {
+    fn name(&self) -> &'static str {
+        match self {
+            PayloadDecoder::Postcard(_) => "postcard",
+            PayloadDecoder::Protobuf(_) => "protobuf",
+        }
+    }
+
+    /// The payload as JSON, and how many bytes followed the value
+    fn decode(&self, payload: &[u8]) -> Result<(serde_json::Value, usize), String> {
+        match self {
+            PayloadDecoder::Postcard(schema) => schema.decode(payload).map_err(|e| e.to_string()),
+            // A protobuf message runs to the end of its payload
+            PayloadDecoder::Protobuf(message) => protobuf::decode(message, payload).map(|value| (value, 0)).map_err(|e| e.to_string()),
+        }
+    }
+}
@@ -0,0 +1,152 @@
+//! G-code job tools
+//!
+//! Stream G-code programs to 3D printers and CNC controllers with ok/error
+//! flow control, and follow or cancel the job while it runs in the
+//! background.
+
+use std::future::Future;
+use std::time::Duration;
+
+use rmcp::{
+    handler::server::tool::Parameters,
+    model::*,
+    tool, tool_router, ErrorData as McpError,
+};
+use tracing::{debug, info};
+
+use super::serial_handler::SerialHandler;
+use super::types::*;
+use crate::protocol::gcode::{program_lines, GcodeJob, GcodeOptions, JobState};
+
+/// Largest G-code file accepted by `gcode_send`
+const MAX_GCODE_FILE_BYTES: u64 = 64 * 1024 * 1024;
+
+/// How often `gcode_send` checks whether the job has finished while waiting
+const GCODE_WAIT_POLL: Duration = Duration::from_millis(50);
+
+#[tool_router(router = gcode_router, vis = "pub(crate)")]
+impl SerialHandler {
+    #[tool(description = "Send a G-code program to a 3D printer or CNC controller, one line at a time: each line waits for the firmware's ok before the next is sent. Comments and blank lines are skipped. With line_numbers, lines are sent Marlin style with N numbers and checksums and resend requests are honoured. GRBL error:N and Marlin Error: replies are reported per line, and the job fails at once if the printer reports it has halted, e.g. on thermal runaway. The job runs in the background; the result shows the outcome, or the progress and a job ID for gcode_status if it is still running after wait_ms")]
+    async fn gcode_send(&self, Parameters(args): Parameters<GcodeSendArgs>) -> Result<CallToolResult, McpError> {
+        let program = match (&args.gcode, &args.file) {
+            (Some(gcode), None) => gcode.clone(),
+            (None, Some(file)) => read_program(file)?,
+            _ => return Err(McpError::invalid_params("Error: Give either gcode or file, not both".to_string(), None)),
+        };
+        let lines = program_lines(&program);
+        if lines.is_empty() {
+            return Err(McpError::invalid_params("Error: The program has no G-code lines to send".to_string(), None));
+        }
+        if args.line_timeout_ms == 0 {
+            return Err(McpError::invalid_params("Error: line_timeout_ms must be greater than 0".to_string(), None));
+        }
+
+        let connection = self.connection(&args.connection_id).await?;
+        if connection.framer_name().await.is_some() {
+            let error_msg = format!("Error: Connection {} has a framer set, remove it to send G-code", args.connection_id);
+            return Err(McpError::invalid_params(error_msg, None));
+        }
+
+        let job_id = uuid::Uuid::new_v4().to_string();
+        {
+            let mut jobs = self.gcode_jobs.lock().await;
+            if let Some((running, _)) = jobs.iter().find(|(_, job)| job.is_running() && job.connection().id() == connection.id()) {
+                let error_msg = format!("Error: G-code job {} is still running on connection {}", running, args.connection_id);
+                return Err(McpError::invalid_params(error_msg, None));
+            }
+
+            debug!("Starting G-code job {} with {} lines on connection {}", job_id, lines.len(), args.connection_id);
+            connection.record_event(format!("G-code job {} started: {} lines", job_id, lines.len())).await;
+            let options = GcodeOptions {
+                line_numbers: args.line_numbers,
+                line_timeout: Duration::from_millis(args.line_timeout_ms),
+                stop_on_error: args.stop_on_error,
+            };
+            jobs.insert(job_id.clone(), GcodeJob::start(connection, lines, options));
+        }
+
+        let deadline = tokio::time::Instant::now() + Duration::from_millis(args.wait_ms);
+        loop {
+            {
+                let jobs = self.gcode_jobs.lock().await;
+                let Some(job) = jobs.get(&job_id) else {
+                    let message = format!("G-code job cancelled while waiting\nJob ID: {}", job_id);
+                    return Ok(CallToolResult::success(vec![Content::text(message)]));
+                };
+                if !job.is_running() || tokio::time::Instant::now() >= deadline {
+                    return Ok(CallToolResult::success(vec![Content::text(format_job(&job_id, job))]));
+                }
+            }
+            tokio::time::sleep(GCODE_WAIT_POLL).await;
+        }
+    }
+
+    #[tool(description = "Report a G-code job's progress: state, lines acknowledged out of the total, the line awaiting its ok, resends, rejected lines and the latest temperatures a Marlin printer reported")]
+    async fn gcode_status(&self, Parameters(args): Parameters<GcodeJobArgs>) -> Result<CallToolResult, McpError> {
+        let jobs = self.gcode_jobs.lock().await;
+        let job = jobs.get(&args.job_id).ok_or_else(|| unknown_job(&args.job_id))?;
+        Ok(CallToolResult::success(vec![Content::text(format_job(&args.job_id, job))]))
+    }
+
+    #[tool(description = "Cancel a G-code job: no further lines are sent and the job is forgotten. Moves the firmware has already accepted still run; send a stop or feed hold command to halt them")]
+    async fn gcode_cancel(&self, Parameters(args): Parameters<GcodeJobArgs>) -> Result<CallToolResult, McpError> {
+        let job = self
+            .gcode_jobs
+            .lock()
+            .await
+            .remove(&args.job_id)
+            .ok_or_else(|| unknown_job(&args.job_id))?;
+        job.cancel();
+        if job.progress().state == JobState::Cancelled {
+            info!("Cancelled G-code job {}", args.job_id);
+            job.connection().record_event(format!("G-code job {} cancelled", args.job_id)).await;
+        }
+
+        let message = format!("G-code job cancelled\n{}", format_job(&args.job_id, &job));
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+}
+
+fn unknown_job(job_id: &str) -> McpError {
+    McpError::invalid_params(format!("Error: G-code job {} not found", job_id), None)
+}
+
+fn read_program(file: &str) -> Result<String, McpError> {
+    let metadata = std::fs::metadata(file).map_err(|e| {
+        McpError::invalid_params(format!("Error: Cannot access {} - {}", file, e), None)
+    })?;
+    if metadata.len() > MAX_GCODE_FILE_BYTES {
+        let error_msg = format!("Error: {} is {} bytes, G-code files are limited to {} bytes", file, metadata.len(), MAX_GCODE_FILE_BYTES);
+        return Err(McpError::invalid_params(error_msg, None));
+    }
+    std::fs::read_to_string(file).map_err(|e| McpError::internal_error(format!("Error: Failed to read {} - {}", file, e), None))
+}
+
+fn format_job(job_id: &str, job: &GcodeJob) -> String {
+    let progress = job.progress();
+    let percent = progress.acknowledged * 100 / progress.total_lines.max(1);
+    let mut text = format!(
+        "G-code job {}\nJob ID: {}\nConnection ID: {}\nLines: {}/{} ({}%)\nResends: {}\nElapsed: {}s",
+        progress.state,
+        job_id,
+        job.connection().id(),
+        progress.acknowledged,
+        progress.total_lines,
+        percent,
+        progress.resends,
+        (chrono::Utc::now() - job.started_at()).num_seconds()
+    );
+    if let Some(current) = &progress.current {
+        text.push_str(&format!("\nAwaiting ok for: {}", current));
+    }
+    if let Some(temperatures) = &progress.temperatures {
+        text.push_str(&format!("\nTemperatures: {}", serde_json::to_string(temperatures).unwrap_or_default()));
+    }
+    if !progress.errors.is_empty() {
+        text.push_str(&format!("\nRejected lines: {}", progress.errors.len()));
+        for error in &progress.errors {
+            text.push_str(&format!("\n  {}", error));
+        }
+    }
+    text
+}
@@ -0,0 +1,111 @@
+//! GRBL controller tools
+//!
+//! Structured machine state, settings and jogging for CNC machines running
+//! GRBL, plus the real-time commands that stop or resume motion immediately.
+
+use std::future::Future;
+use std::sync::Arc;
+
+use rmcp::{
+    handler::server::tool::Parameters,
+    model::*,
+    tool, tool_router, ErrorData as McpError,
+};
+use tracing::{debug, error, info};
+
+use super::serial_handler::SerialHandler;
+use super::types::*;
+use crate::protocol::gcode::{self, FirmwareError};
+use crate::protocol::grbl::{self, jog_command, parse_settings, RealtimeCommand};
+use crate::serial::SerialConnection;
+
+#[tool_router(router = grbl_router, vis = "pub(crate)")]
+impl SerialHandler {
+    #[tool(description = "GRBL: query the machine status with ? and return it as JSON: state (Idle, Run, Hold, Jog, Alarm, Door, Home...), machine and work positions, feed rate, spindle speed, buffer space, active input pins and overrides. Fails while a G-code job is streaming on the connection")]
+    async fn grbl_status(&self, Parameters(args): Parameters<GrblArgs>) -> Result<CallToolResult, McpError> {
+        let connection = self.grbl_connection(&args.connection_id).await?;
+        debug!("Querying GRBL status on connection {}", args.connection_id);
+
+        let status = grbl::query_status(&connection, args.timeout_ms)
+            .await
+            .map_err(|e| grbl_failed(&args.connection_id, "Status query", e))?;
+
+        let message = format!(
+            "GRBL status\nConnection ID: {}\n{}",
+            args.connection_id,
+            serde_json::to_string_pretty(&status).unwrap_or_default()
+        );
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+
+    #[tool(description = "GRBL: send a real-time command, acted on immediately even while a G-code job is streaming: feed_hold (!) decelerates to a stop, resume (~) continues after a hold, jog_cancel stops a jog, reset (Ctrl-X) stops at once and resets the controller, losing position if the machine was moving")]
+    async fn grbl_realtime(&self, Parameters(args): Parameters<GrblRealtimeArgs>) -> Result<CallToolResult, McpError> {
+        let command = RealtimeCommand::parse(&args.command).map_err(|e| McpError::invalid_params(format!("Error: {}", e), None))?;
+        let connection = self.connection(&args.connection_id).await?;
+
+        connection.write(&[command.byte()]).await.map_err(|e| {
+            error!("Failed to send GRBL {} on connection {}: {}", command, args.connection_id, e);
+            McpError::internal_error(format!("Error: Data sending failed - {}", e), None)
+        })?;
+        info!("Sent GRBL {} on connection {}", command, args.connection_id);
+        connection.record_event(format!("GRBL real-time command {} sent", command)).await;
+
+        let message = format!("GRBL real-time command sent\nConnection ID: {}\nCommand: {} (0x{:02X})", args.connection_id, command, command.byte());
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+
+    #[tool(description = "GRBL: read the controller settings with $$ and return them as JSON with each setting's number, value and, for standard settings, what it controls")]
+    async fn grbl_settings(&self, Parameters(args): Parameters<GrblArgs>) -> Result<CallToolResult, McpError> {
+        let connection = self.grbl_connection(&args.connection_id).await?;
+        debug!("Reading GRBL settings on connection {}", args.connection_id);
+
+        let output = gcode::command(&connection, "$$", args.timeout_ms)
+            .await
+            .map_err(|e| grbl_failed(&args.connection_id, "Settings read", e))?;
+        let settings = parse_settings(output.iter().map(String::as_str));
+
+        let message = format!(
+            "GRBL settings\nConnection ID: {}\nSettings: {}\n{}",
+            args.connection_id,
+            settings.len(),
+            serde_json::to_string_pretty(&settings).unwrap_or_default()
+        );
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+
+    #[tool(description = "GRBL: jog the machine with a $J= command, in mm at feed_rate mm/min; incremental moves by the given distances, otherwise to the given work coordinates. Soft limits reject jogs beyond travel. Stop a jog with grbl_realtime jog_cancel")]
+    async fn grbl_jog(&self, Parameters(args): Parameters<GrblJogArgs>) -> Result<CallToolResult, McpError> {
+        let moves: Vec<(char, f64)> = [('X', args.x), ('Y', args.y), ('Z', args.z)]
+            .into_iter()
+            .filter_map(|(axis, distance)| Some((axis, distance?)))
+            .collect();
+        let line = jog_command(&moves, args.feed_rate, args.incremental).map_err(|e| McpError::invalid_params(format!("Error: {}", e), None))?;
+        let connection = self.grbl_connection(&args.connection_id).await?;
+        debug!("Jogging on connection {}: {}", args.connection_id, line);
+
+        gcode::command(&connection, &line, args.timeout_ms)
+            .await
+            .map_err(|e| grbl_failed(&args.connection_id, "Jog", e))?;
+        connection.record_event(format!("GRBL jog accepted: {}", line)).await;
+
+        let message = format!("GRBL jog accepted\nConnection ID: {}\nCommand: {}", args.connection_id, line);
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+}
+
+impl SerialHandler {
+    /// The connection, provided it has no framer
+    async fn grbl_connection(&self, connection_id: &str) -> Result<Arc<SerialConnection>, McpError> {
+        let connection = self.connection(connection_id).await?;
+        if connection.framer_name().await.is_some() {
+            let error_msg = format!("Error: Connection {} has a framer set, remove it to talk to GRBL", connection_id);
+            return Err(McpError::invalid_params(error_msg, None));
+        }
+        Ok(connection)
+    }
+}
+
+fn grbl_failed(connection_id: &str, action: &str, e: FirmwareError) -> McpError {
+    error!("GRBL {} on connection {} failed: {}", action.to_lowercase(), connection_id, e);
+    McpError::internal_error(format!("Error: {} failed - {}", action, e), None)
+}
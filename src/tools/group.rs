@@ -0,0 +1,182 @@
+//! Session group tools
+//!
+//! Sessions can be put in a named group, such as "rack-3", so a fleet of
+//! identical devices is driven together: connected and disconnected at once,
+//! written the same data at once, and watched through combined statistics.
+//! Group operations carry on past members that fail and report each one.
+
+use std::future::Future;
+
+use futures::future::join_all;
+use rmcp::{
+    handler::server::tool::Parameters,
+    model::*,
+    tool, tool_router, ErrorData as McpError,
+};
+use tracing::{debug, info};
+
+use super::serial_handler::SerialHandler;
+use super::session::{format_stats, session_error};
+use super::types::*;
+use crate::session::SessionInfo;
+
+#[tool_router(router = group_router, vis = "pub(crate)")]
+impl SerialHandler {
+    #[tool(description = "Put a session in a group, such as \"rack-3\", to drive it with the group's other sessions through connect_group, disconnect_group, group_stats and broadcast_write. Leave out group to take the session out of its group")]
+    async fn set_session_group(&self, Parameters(args): Parameters<SetSessionGroupArgs>) -> Result<CallToolResult, McpError> {
+        let session_id = self.sessions.resolve(&args.session_id).await.map_err(session_error)?;
+        let group = args.group.filter(|group| !group.is_empty());
+        self.sessions.set_session_group(&session_id, group.clone()).await.map_err(session_error)?;
+
+        let message = match group {
+            Some(group) => {
+                info!("Session {} joined group {}", session_id, group);
+                format!("Session {} is in group {}", session_id, group)
+            }
+            None => {
+                info!("Session {} left its group", session_id);
+                format!("Session {} is in no group", session_id)
+            }
+        };
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+
+    #[tool(description = "Connect every suspended session of a group at once, reporting each session's new connection or why it failed; sessions already connected are left as they are")]
+    async fn connect_group(&self, Parameters(args): Parameters<GroupArgs>) -> Result<CallToolResult, McpError> {
+        let members = self.group_members(&args.group).await?;
+        debug!("Connecting {} session(s) of group {}", members.len(), args.group);
+
+        let outcomes = join_all(members.iter().map(|info| async move {
+            if let Some(connection_id) = &info.connection_id {
+                return (None, format!("already connected, connection {}", connection_id));
+            }
+            match self.session_connect(&info.session_id).await {
+                Ok(connection_id) => (Some(true), format!("connected, connection {}", connection_id)),
+                Err(e) => (Some(false), format!("failed: {}", e.message)),
+            }
+        }))
+        .await;
+
+        let connected = outcomes.iter().filter(|(ok, _)| *ok == Some(true)).count();
+        let failed = outcomes.iter().filter(|(ok, _)| *ok == Some(false)).count();
+        info!("Connected {} session(s) of group {}, {} failed", connected, args.group, failed);
+        let summary = format!("Group {}: {} connected, {} failed", args.group, connected, failed);
+        Ok(CallToolResult::success(vec![Content::text(format_outcomes(summary, &members, outcomes))]))
+    }
+
+    #[tool(description = "Disconnect every connected session of a group at once, suspending each to be connected again later; sessions already suspended are left as they are")]
+    async fn disconnect_group(&self, Parameters(args): Parameters<GroupArgs>) -> Result<CallToolResult, McpError> {
+        let members = self.group_members(&args.group).await?;
+        debug!("Disconnecting {} session(s) of group {}", members.len(), args.group);
+
+        let outcomes = join_all(members.iter().map(|info| async move {
+            if info.connection_id.is_none() {
+                return (None, "not connected".to_string());
+            }
+            match self.session_disconnect(&info.session_id).await {
+                Ok(connection_id) => (Some(true), format!("disconnected from {}", connection_id)),
+                Err(e) => (Some(false), format!("failed: {}", e.message)),
+            }
+        }))
+        .await;
+
+        let disconnected = outcomes.iter().filter(|(ok, _)| *ok == Some(true)).count();
+        let failed = outcomes.iter().filter(|(ok, _)| *ok == Some(false)).count();
+        info!("Disconnected {} session(s) of group {}, {} failed", disconnected, args.group, failed);
+        let summary = format!("Group {}: {} disconnected, {} failed", args.group, disconnected, failed);
+        Ok(CallToolResult::success(vec![Content::text(format_outcomes(summary, &members, outcomes))]))
+    }
+
+    #[tool(description = "Report a group's combined link quality: total throughput and errors over the last minute and lifetime traffic across its sessions, then each session's own figures")]
+    async fn group_stats(&self, Parameters(args): Parameters<GroupArgs>) -> Result<CallToolResult, McpError> {
+        let members = self.group_members(&args.group).await?;
+
+        let connected = members.iter().filter(|info| info.connection_id.is_some()).count();
+        let sent_rate: f64 = members.iter().map(|info| info.rates.sent_bytes_per_sec).sum();
+        let received_rate: f64 = members.iter().map(|info| info.rates.received_bytes_per_sec).sum();
+        let errors: u64 = members.iter().map(|info| info.rates.errors).sum();
+        let bytes_sent: u64 = members.iter().map(|info| info.stats.bytes_sent).sum();
+        let bytes_received: u64 = members.iter().map(|info| info.stats.bytes_received).sum();
+        let total_errors: u64 = members.iter().map(|info| info.stats.errors_count).sum();
+
+        let mut lines = vec![
+            format!("Group {}: {} session(s), {} connected", args.group, members.len(), connected),
+            format!("Combined: sent {:.1} B/s, received {:.1} B/s, {} recent error(s)", sent_rate, received_rate, errors),
+            format!("Total: {} bytes sent, {} bytes received, {} errors", bytes_sent, bytes_received, total_errors),
+        ];
+        for info in &members {
+            lines.push(format!("- {}: {}", member_name(info), info.state));
+            lines.push(format!(
+                "  last {}s: sent {:.1} B/s, received {:.1} B/s, {} error(s)",
+                info.rates.window_seconds, info.rates.sent_bytes_per_sec, info.rates.received_bytes_per_sec, info.rates.errors
+            ));
+            lines.push(format!("  total: {}", format_stats(info)));
+        }
+        Ok(CallToolResult::success(vec![Content::text(lines.join("\n"))]))
+    }
+
+    #[tool(description = "Write the same data to every connected session of a group at once, each in its own connection's encoding unless one is given, reporting what each session sent or why it failed. Rate limits and write queues apply per session")]
+    async fn broadcast_write(&self, Parameters(args): Parameters<BroadcastWriteArgs>) -> Result<CallToolResult, McpError> {
+        let members = self.group_members(&args.group).await?;
+        debug!("Broadcasting to {} session(s) of group {}", members.len(), args.group);
+
+        let args = &args;
+        let outcomes = join_all(members.iter().map(|info| async move {
+            let Some(connection_id) = &info.connection_id else {
+                return (None, "not connected, skipped".to_string());
+            };
+            match self.broadcast_to(connection_id, args).await {
+                Ok((sent, queued)) => (Some(true), format!("{} bytes written to {}{}", sent, connection_id, queued)),
+                Err(e) => (Some(false), format!("failed: {}", e.message)),
+            }
+        }))
+        .await;
+
+        let written = outcomes.iter().filter(|(ok, _)| *ok == Some(true)).count();
+        let failed = outcomes.iter().filter(|(ok, _)| *ok == Some(false)).count();
+        info!("Broadcast to {} session(s) of group {}, {} failed", written, args.group, failed);
+        let summary = format!("Group {}: written to {} session(s), {} failed", args.group, written, failed);
+        Ok(CallToolResult::success(vec![Content::text(format_outcomes(summary, &members, outcomes))]))
+    }
+}
+
+impl SerialHandler {
+    /// Sessions of a group, failing if it has none
+    async fn group_members(&self, group: &str) -> Result<Vec<SessionInfo>, McpError> {
+        let members = self.sessions.group_sessions(group).await;
+        if members.is_empty() {
+            return Err(McpError::invalid_params(format!("Error: Group {} has no sessions", group), None));
+        }
+        Ok(members)
+    }
+
+    /// Write a broadcast to one connection, returning the bytes sent and its queue note
+    async fn broadcast_to(&self, connection_id: &str, args: &BroadcastWriteArgs) -> Result<(usize, String), McpError> {
+        let connection = self.connection(connection_id).await?;
+        let encoding = connection.encoding(args.encoding.clone()).await;
+        let mut data = decode_data(&args.data, &encoding)
+            .map_err(|e| McpError::invalid_params(format!("Error: Data decoding failed - {}", e), None))?;
+        if args.append_line_ending {
+            data.extend_from_slice(self.session_config(connection_id).await.line_ending.as_bytes());
+        }
+        let (sent, queued) = self.send_data(&connection, connection_id, &data, args.queue_timeout_ms).await?;
+        Ok((sent.len(), queued))
+    }
+}
+
+/// Session ID, with its name if it has one
+fn member_name(info: &SessionInfo) -> String {
+    match &info.config.name {
+        Some(name) => format!("{} ({})", info.session_id, name),
+        None => info.session_id.clone(),
+    }
+}
+
+/// Summary line followed by one line per session
+fn format_outcomes(summary: String, members: &[SessionInfo], outcomes: Vec<(Option<bool>, String)>) -> String {
+    let mut lines = vec![summary];
+    for (info, (_, outcome)) in members.iter().zip(outcomes) {
+        lines.push(format!("- {}: {}", member_name(info), outcome));
+    }
+    lines.join("\n")
+}
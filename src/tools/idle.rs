@@ -0,0 +1,113 @@
+//! Idle mode
+//!
+//! An MCP host often keeps the server running long after the last request.
+//! Once the client has sent nothing for the configured period, background
+//! tasks such as port discovery and keep-alive pings park instead of waking
+//! the machine on a timer. The next request from the client resumes them
+//! immediately. Serial connections and subscriptions keep running.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use tokio::sync::Notify;
+use tracing::info;
+
+/// Tracks client requests and parks background tasks while there are none
+#[derive(Debug)]
+pub struct IdleMode {
+    /// Inactivity before parking; `None` never parks
+    idle_after: Option<Duration>,
+    last_request: Mutex<Instant>,
+    parked: AtomicBool,
+    wake: Notify,
+}
+
+impl IdleMode {
+    pub fn new(idle_after: Option<Duration>) -> Self {
+        Self {
+            idle_after,
+            last_request: Mutex::new(Instant::now()),
+            parked: AtomicBool::new(false),
+            wake: Notify::new(),
+        }
+    }
+
+    /// Record a request from the client, resuming parked tasks
+    pub fn record_activity(&self) {
+        *self.last_request.lock().unwrap() = Instant::now();
+        if self.parked.swap(false, Ordering::SeqCst) {
+            info!("Client active again, resuming background tasks");
+            self.wake.notify_waiters();
+        }
+    }
+
+    pub fn is_parked(&self) -> bool {
+        self.parked.load(Ordering::SeqCst)
+    }
+
+    /// Time since the client's last request
+    pub fn since_last_request(&self) -> Duration {
+        self.last_request.lock().unwrap().elapsed()
+    }
+
+    /// Park when the client has been idle long enough
+    ///
+    /// Returns whether background tasks are parked.
+    pub fn check(&self) -> bool {
+        if let Some(idle_after) = self.idle_after {
+            let idle_for = self.since_last_request();
+            if idle_for >= idle_after && !self.parked.swap(true, Ordering::SeqCst) {
+                info!("No client requests for {:?}, parking background tasks", idle_for);
+            }
+        }
+        self.is_parked()
+    }
+
+    /// Wait until the client is active
+    ///
+    /// Returns at once unless the client has been idle long enough to park.
+    pub async fn active(&self) {
+        if !self.check() {
+            return;
+        }
+        loop {
+            // Registered before checking the flag so a wake-up in between is not lost
+            let notified = self.wake.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            if !self.is_parked() {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_disabled_never_parks() {
+        let idle = IdleMode::new(None);
+        assert!(!idle.check());
+    }
+
+    #[tokio::test]
+    async fn test_request_resumes_parked_task() {
+        let idle = Arc::new(IdleMode::new(Some(Duration::ZERO)));
+        let waiter = tokio::spawn({
+            let idle = Arc::clone(&idle);
+            async move { idle.active().await }
+        });
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(idle.is_parked());
+        assert!(!waiter.is_finished());
+
+        idle.record_activity();
+        tokio::time::timeout(Duration::from_secs(1), waiter).await.unwrap().unwrap();
+    }
+}
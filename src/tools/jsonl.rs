@@ -0,0 +1,130 @@
+//! JSON Lines tools
+//!
+//! Many devices log telemetry as one JSON object per line. On a connection in
+//! line mode, collect those lines and return the parsed objects with their
+//! arrival times, keeping lines that are not JSON apart from the stream.
+
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rmcp::{
+    handler::server::tool::Parameters,
+    model::*,
+    tool, tool_router, ErrorData as McpError,
+};
+use serde::Serialize;
+use serde_json::json;
+use tokio::time::Instant;
+use tracing::{debug, error};
+
+use super::serial_handler::SerialHandler;
+use super::types::*;
+use super::warnings::Warnings;
+use crate::serial::{LocalSerialError, ReceivedLine, SerialConnection};
+
+/// Characters of a malformed line shown in the result
+const MALFORMED_PREVIEW_CHARS: usize = 120;
+
+#[tool_router(router = jsonl_router, vis = "pub(crate)")]
+impl SerialHandler {
+    #[tool(description = "Collect newline-delimited JSON (JSON Lines) from a connection in line mode for a while and return the parsed objects, each with its line sequence number and arrival time. Lines that are not a JSON object, such as boot messages or a line cut short, are listed separately as malformed with the parse error and do not affect the objects around them. Blank lines are skipped. Returns early once max_objects objects have arrived")]
+    async fn read_json_lines(&self, Parameters(args): Parameters<ReadJsonLinesArgs>) -> Result<CallToolResult, McpError> {
+        let connection = self.json_lines_connection(&args.connection_id).await?;
+        debug!("Collecting JSON lines on connection {} for {}ms", args.connection_id, args.duration_ms);
+
+        let max = args.max_objects.max(1);
+        let (objects, malformed) = collect_json_lines(&connection, Duration::from_millis(args.duration_ms), max)
+            .await
+            .map_err(|e| {
+                error!("Failed to read JSON lines from connection {}: {}", args.connection_id, e);
+                McpError::internal_error(format!("Error: Line reading failed - {}", e), None)
+            })?;
+
+        let mut warnings = Warnings::new();
+        warnings.dropped_bytes(connection.take_new_drops().await);
+        if !malformed.is_empty() {
+            warnings.push(format!("{} lines were not JSON objects", malformed.len()));
+        }
+
+        let mut message = format!(
+            "JSON lines read\nConnection ID: {}\nObjects: {}\nMalformed lines: {}\n{}",
+            args.connection_id,
+            objects.len(),
+            malformed.len(),
+            serde_json::to_string_pretty(&objects).unwrap_or_default()
+        );
+        if !malformed.is_empty() {
+            message.push_str(&format!("\n\nMalformed:\n{}", serde_json::to_string_pretty(&malformed).unwrap_or_default()));
+        }
+        warnings.into_result(message)
+    }
+}
+
+impl SerialHandler {
+    /// The connection, provided it is in line mode
+    async fn json_lines_connection(&self, connection_id: &str) -> Result<Arc<SerialConnection>, McpError> {
+        let connection = self.connection(connection_id).await?;
+        if !connection.is_line_mode().await {
+            let error_msg = format!("Error: Connection {} is not in line mode, enable it with set_line_mode", connection_id);
+            return Err(McpError::invalid_params(error_msg, None));
+        }
+        Ok(connection)
+    }
+}
+
+/// A line that did not parse as a JSON object
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct MalformedLine {
+    pub seq: u64,
+    pub timestamp: String,
+    pub error: String,
+    /// The start of the line, for recognising what it was
+    pub text: String,
+}
+
+/// The line as a `{seq, timestamp, object}` entry, `None` for a blank line
+pub(crate) fn parse_json_line(line: &ReceivedLine) -> Option<Result<serde_json::Value, MalformedLine>> {
+    let text = line.text();
+    let trimmed = text.trim();
+    if trimmed.is_empty() {
+        return None;
+    }
+    let timestamp = line.timestamp.to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+    let malformed = |error: String| MalformedLine {
+        seq: line.seq,
+        timestamp: timestamp.clone(),
+        error,
+        text: trimmed.chars().take(MALFORMED_PREVIEW_CHARS).collect(),
+    };
+    Some(match serde_json::from_str::<serde_json::Value>(trimmed) {
+        Ok(object @ serde_json::Value::Object(_)) => Ok(json!({ "seq": line.seq, "timestamp": timestamp, "object": object })),
+        Ok(_) => Err(malformed("not a JSON object".to_string())),
+        Err(e) => Err(malformed(e.to_string())),
+    })
+}
+
+/// Collect lines for up to `duration`, stopping once `max_objects` objects have arrived
+async fn collect_json_lines(
+    connection: &SerialConnection,
+    duration: Duration,
+    max_objects: usize,
+) -> Result<(Vec<serde_json::Value>, Vec<MalformedLine>), LocalSerialError> {
+    let deadline = Instant::now() + duration;
+    let mut objects = Vec::new();
+    let mut malformed = Vec::new();
+
+    // A line at a time, so lines after the last object wanted stay queued
+    connection
+        .read_lines_until(deadline, |line| {
+            match parse_json_line(&line) {
+                Some(Ok(object)) => objects.push(object),
+                Some(Err(line)) => malformed.push(line),
+                None => {}
+            }
+            objects.len() >= max_objects
+        })
+        .await?;
+
+    Ok((objects, malformed))
+}
@@ -0,0 +1,81 @@
+//! Keepalive supervision
+//!
+//! Sessions created with a keepalive have their device checked every
+//! interval. A device that stops answering fails the session's connection,
+//! which the reconnect supervisor then reconnects if the session has
+//! auto_reconnect; the client is told either way.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rmcp::{
+    model::{LoggingLevel, LoggingMessageNotificationParam},
+    Peer, RoleServer,
+};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tracing::{debug, info, warn};
+
+use crate::session::SessionManager;
+
+/// How often sessions are checked for a keepalive falling due
+const KEEPALIVE_TICK: Duration = Duration::from_millis(250);
+
+/// Run the keepalive checks of connected sessions, for as long as the server runs
+pub(crate) async fn run_keepalive(peer: Peer<RoleServer>, sessions: Arc<SessionManager>) {
+    let mut due: HashMap<String, Instant> = HashMap::new();
+    let mut checks: HashMap<String, JoinHandle<()>> = HashMap::new();
+    let mut interval = tokio::time::interval(KEEPALIVE_TICK);
+    loop {
+        interval.tick().await;
+        let now = Instant::now();
+        let connected = sessions.keepalive_sessions().await;
+        due.retain(|session_id, _| connected.iter().any(|(id, _, _)| id == session_id));
+        checks.retain(|_, check| !check.is_finished());
+
+        for (session_id, connection, config) in connected {
+            let Some(keepalive) = config.keepalive.clone() else {
+                continue;
+            };
+            let period = Duration::from_millis(keepalive.interval_ms);
+            let next = due.entry(session_id.clone()).or_insert(now + period);
+            // A slow check is not run twice at once
+            if now < *next || checks.contains_key(&session_id) {
+                continue;
+            }
+            *next = now + period;
+
+            let peer = peer.clone();
+            let sessions = Arc::clone(&sessions);
+            let id = session_id.clone();
+            let check = tokio::spawn(async move {
+                let Err(reason) = keepalive.check(&connection).await else {
+                    debug!("Keepalive of session {} passed", id);
+                    return;
+                };
+                let reason = format!("Keepalive failed: {}", reason);
+                connection.record_event(reason.clone()).await;
+                connection.mark_failed(reason.clone()).await;
+                let _ = sessions.handle_session_error(&id, reason.clone()).await;
+                let next_step = if config.auto_reconnect { "reconnecting" } else { "connect it again with connect_session" };
+                let message = format!("Session {} on {}: {}; {}", id, connection.config().port, reason, next_step);
+                notify(&peer, message).await;
+            });
+            checks.insert(session_id, check);
+        }
+    }
+}
+
+/// Log a failed keepalive and forward it to the client as a logging notification
+async fn notify(peer: &Peer<RoleServer>, message: String) {
+    info!("{}", message);
+    let param = LoggingMessageNotificationParam {
+        level: LoggingLevel::Warning,
+        logger: Some("keepalive".to_string()),
+        data: serde_json::Value::String(message),
+    };
+    if let Err(e) = peer.notify_logging_message(param).await {
+        warn!("Failed to send keepalive notification: {}", e);
+    }
+}
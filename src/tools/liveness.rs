@@ -0,0 +1,271 @@
+//! Client liveness tracking
+//!
+//! The server pings its client periodically. When the client stops answering
+//! for longer than the configured timeout, the connections it left open are
+//! handled according to the orphan policy instead of holding their ports forever.
+//! Every connection belongs to the single client of the server process, and
+//! records which client that was; once its transport closes, the connections
+//! it opened are closed.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rmcp::{
+    model::{ClientResult, PingRequest, ServerRequest},
+    service::PeerRequestOptions,
+    Peer, RoleServer, ServiceError,
+};
+use tokio::sync::Mutex;
+use tracing::{debug, info, warn};
+
+use super::idle::IdleMode;
+use super::maintenance::MaintenanceState;
+use crate::config::OrphanPolicy;
+use crate::serial::{ConnectionManager, PauseSignal};
+
+/// When the client was last heard from, and what was done when it went quiet
+#[derive(Debug)]
+pub struct ClientLiveness {
+    last_seen: Mutex<Instant>,
+    /// Connections paused by the suspend policy, resumed when the client returns
+    suspended: Mutex<Option<Vec<String>>>,
+}
+
+impl ClientLiveness {
+    pub fn new() -> Self {
+        Self {
+            last_seen: Mutex::new(Instant::now()),
+            suspended: Mutex::new(None),
+        }
+    }
+
+    /// Record that the client is alive
+    pub async fn touch(&self) {
+        *self.last_seen.lock().await = Instant::now();
+    }
+
+    pub async fn since_last_seen(&self) -> Duration {
+        self.last_seen.lock().await.elapsed()
+    }
+
+    /// Whether the orphan policy has been applied and the client has not returned
+    pub async fn is_orphaned(&self) -> bool {
+        self.suspended.lock().await.is_some()
+    }
+}
+
+impl Default for ClientLiveness {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Apply the orphan policy to every open connection, returning the IDs affected
+pub async fn apply_orphan_policy(manager: &ConnectionManager, policy: OrphanPolicy) -> Vec<String> {
+    let mut affected = Vec::new();
+
+    for connection in manager.connections().await {
+        let id = connection.id().to_string();
+        match policy {
+            OrphanPolicy::Keep => continue,
+            OrphanPolicy::Suspend => {
+                // Connections the client paused itself stay as they are
+                if connection.is_paused() {
+                    continue;
+                }
+                if let Err(e) = connection.pause(PauseSignal::None).await {
+                    warn!("Failed to suspend orphaned connection {}: {}", id, e);
+                    continue;
+                }
+                connection.record_event("Suspended: client stopped responding").await;
+            }
+            OrphanPolicy::Close => {
+                connection.record_event("Closed: client stopped responding").await;
+                if let Err(e) = manager.close(&id).await {
+                    warn!("Failed to close orphaned connection {}: {}", id, e);
+                    continue;
+                }
+            }
+        }
+        affected.push(id);
+    }
+
+    affected
+}
+
+/// Close the connections opened for `client`, returning their IDs
+pub async fn close_client_connections(manager: &ConnectionManager, client: &str) -> Vec<String> {
+    let mut closed = Vec::new();
+
+    for connection in manager.connections().await {
+        if connection.owner() != Some(client) {
+            continue;
+        }
+        let id = connection.id().to_string();
+        connection.record_event(format!("Closed: client {} disconnected", client)).await;
+        if let Err(e) = manager.close(&id).await {
+            warn!("Failed to close connection {} of disconnected client: {}", id, e);
+            continue;
+        }
+        closed.push(id);
+    }
+
+    closed
+}
+
+/// Ping the client every `interval` until the transport closes
+///
+/// Once the client has been silent for `client_timeout`, `policy` is applied.
+/// Connections suspended that way are resumed when the client answers again.
+/// No pings are sent during maintenance windows, nor while idle mode has
+/// parked background tasks.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn run_heartbeat(
+    peer: Peer<RoleServer>,
+    manager: Arc<ConnectionManager>,
+    liveness: Arc<ClientLiveness>,
+    maintenance: Arc<MaintenanceState>,
+    idle: Arc<IdleMode>,
+    interval: Duration,
+    client_timeout: Duration,
+    policy: OrphanPolicy,
+) {
+    loop {
+        tokio::time::sleep(interval).await;
+        idle.active().await;
+
+        if maintenance.is_active() {
+            // Silence during maintenance says nothing about the client
+            liveness.touch().await;
+            continue;
+        }
+
+        match ping(&peer, interval).await {
+            Ok(()) => {
+                liveness.touch().await;
+                let suspended = liveness.suspended.lock().await.take();
+                if let Some(ids) = suspended {
+                    info!("Client is responding again, resuming {} connection(s)", ids.len());
+                    for id in ids {
+                        if let Ok(connection) = manager.get(&id).await {
+                            if connection.resume().await.is_ok() {
+                                connection.record_event("Resumed: client responding again").await;
+                            }
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                let closed = matches!(e, ServiceError::TransportClosed);
+                debug!("Heartbeat ping failed: {}", e);
+
+                let silent_for = liveness.since_last_seen().await;
+                if (closed || silent_for >= client_timeout) && !liveness.is_orphaned().await {
+                    warn!("Client unresponsive for {:?}, applying orphan policy {}", silent_for, policy);
+                    let affected = apply_orphan_policy(&manager, policy).await;
+                    *liveness.suspended.lock().await = Some(match policy {
+                        OrphanPolicy::Suspend => affected,
+                        OrphanPolicy::Keep | OrphanPolicy::Close => Vec::new(),
+                    });
+                }
+
+                if closed {
+                    info!("Client transport closed, stopping heartbeat");
+                    break;
+                }
+            }
+        }
+    }
+}
+
+pub(crate) async fn ping(peer: &Peer<RoleServer>, timeout: Duration) -> Result<(), ServiceError> {
+    let request = ServerRequest::PingRequest(PingRequest {
+        method: Default::default(),
+        extensions: Default::default(),
+    });
+    let options = PeerRequestOptions {
+        timeout: Some(timeout),
+        meta: None,
+    };
+
+    match peer.send_request_with_option(request, options).await?.await_response().await? {
+        ClientResult::EmptyResult(_) => Ok(()),
+        _ => Err(ServiceError::UnexpectedResponse),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_orphan_policy_parse() {
+        assert_eq!(OrphanPolicy::parse("Suspend").unwrap(), OrphanPolicy::Suspend);
+        assert_eq!(OrphanPolicy::parse("close").unwrap(), OrphanPolicy::Close);
+        assert!(OrphanPolicy::parse("abandon").is_err());
+        assert_eq!(OrphanPolicy::Keep.to_string(), "keep");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_apply_orphan_policy() {
+        use crate::serial::{BackpressurePolicy, ConnectionConfig, DataBits, FlowControl, Parity, StopBits};
+        use tokio_serial::{SerialPort, SerialStream};
+
+        let (_device, slave) = SerialStream::pair().unwrap();
+        let manager = ConnectionManager::new();
+        let id = manager
+            .open(ConnectionConfig {
+                port: slave.name().unwrap(),
+                baud_rate: 115200,
+                data_bits: DataBits::Eight,
+                stop_bits: StopBits::One,
+                parity: Parity::None,
+                flow_control: FlowControl::None,
+                backpressure: BackpressurePolicy::Drop,
+            })
+            .await
+            .unwrap();
+
+        assert!(apply_orphan_policy(&manager, OrphanPolicy::Keep).await.is_empty());
+
+        assert_eq!(apply_orphan_policy(&manager, OrphanPolicy::Suspend).await, vec![id.clone()]);
+        assert!(manager.get(&id).await.unwrap().is_paused());
+        // Already paused connections are left alone
+        assert!(apply_orphan_policy(&manager, OrphanPolicy::Suspend).await.is_empty());
+
+        assert_eq!(apply_orphan_policy(&manager, OrphanPolicy::Close).await, vec![id.clone()]);
+        assert!(manager.get(&id).await.is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_close_client_connections() {
+        use crate::serial::{BackpressurePolicy, ConnectionConfig, DataBits, FlowControl, Parity, StopBits};
+        use tokio_serial::{SerialPort, SerialStream};
+
+        let config = |port: String| ConnectionConfig {
+            port,
+            baud_rate: 115200,
+            data_bits: DataBits::Eight,
+            stop_bits: StopBits::One,
+            parity: Parity::None,
+            flow_control: FlowControl::None,
+            backpressure: BackpressurePolicy::Drop,
+        };
+        let (_first_device, first) = SerialStream::pair().unwrap();
+        let (_second_device, second) = SerialStream::pair().unwrap();
+        let manager = ConnectionManager::new();
+
+        // Opened before any client introduced itself
+        let unowned = manager.open(config(first.name().unwrap())).await.unwrap();
+        manager.set_client("test-client 1.0").await;
+        let owned = manager.open(config(second.name().unwrap())).await.unwrap();
+        assert_eq!(manager.get(&owned).await.unwrap().owner(), Some("test-client 1.0"));
+
+        assert!(close_client_connections(&manager, "other-client 2.0").await.is_empty());
+        assert_eq!(close_client_connections(&manager, "test-client 1.0").await, vec![owned.clone()]);
+        assert!(manager.get(&owned).await.is_err());
+        assert!(manager.get(&unowned).await.is_ok());
+    }
+}
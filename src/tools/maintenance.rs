@@ -0,0 +1,356 @@
+//! Scheduled maintenance windows
+//!
+//! Sites where devices reboot on a schedule can declare maintenance windows.
+//! During a window keep-alive pings stop and connections are suspended, or
+//! closed to release their ports, and the client is notified before and after.
+//! Window times are in the server's local time.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{Datelike, Local, NaiveDateTime, NaiveTime, TimeDelta, Weekday};
+use rmcp::{
+    model::{LoggingLevel, LoggingMessageNotificationParam},
+    Peer, RoleServer,
+};
+use tracing::{info, warn};
+
+use crate::config::MaintenanceConfig;
+use crate::error::ConfigError;
+use crate::serial::{ConnectionManager, PauseSignal};
+
+/// How often the schedule is checked
+const MAINTENANCE_CHECK_INTERVAL_SECS: u64 = 15;
+
+/// Longest allowed window; longer ones would overlap the next day's occurrence
+const MAX_WINDOW_MINUTES: u64 = 24 * 60;
+
+/// One recurring maintenance window
+#[derive(Debug, Clone)]
+pub struct MaintenanceWindow {
+    pub name: String,
+    start: NaiveTime,
+    duration: TimeDelta,
+    /// Days the window starts on; empty means every day
+    days: Vec<Weekday>,
+    /// Close connections during the window instead of suspending them
+    pub release_ports: bool,
+}
+
+impl MaintenanceWindow {
+    fn runs_on(&self, day: Weekday) -> bool {
+        self.days.is_empty() || self.days.contains(&day)
+    }
+
+    /// Start and end of the occurrence covering `now`, if any
+    fn occurrence_at(&self, now: NaiveDateTime) -> Option<(NaiveDateTime, NaiveDateTime)> {
+        // A window that crosses midnight started the day before
+        [now.date(), now.date().pred_opt()?]
+            .into_iter()
+            .filter(|date| self.runs_on(date.weekday()))
+            .map(|date| {
+                let start = date.and_time(self.start);
+                (start, start + self.duration)
+            })
+            .find(|(start, end)| *start <= now && now < *end)
+    }
+
+    /// Start of the next occurrence after `now`
+    fn next_start(&self, now: NaiveDateTime) -> Option<NaiveDateTime> {
+        (0..=7)
+            .filter_map(|days| now.date().checked_add_days(chrono::Days::new(days)))
+            .filter(|date| self.runs_on(date.weekday()))
+            .map(|date| date.and_time(self.start))
+            .find(|start| *start > now)
+    }
+}
+
+/// The configured maintenance windows
+#[derive(Debug, Clone)]
+pub struct MaintenanceSchedule {
+    windows: Vec<MaintenanceWindow>,
+    notify_before: TimeDelta,
+}
+
+impl MaintenanceSchedule {
+    pub fn from_config(config: &MaintenanceConfig) -> Result<Self, ConfigError> {
+        let mut windows = Vec::new();
+
+        for (index, window) in config.windows.iter().enumerate() {
+            let field = |name: &str| format!("maintenance.windows[{}].{}", index, name);
+
+            let start = NaiveTime::parse_from_str(&window.start, "%H:%M").map_err(|_| ConfigError::InvalidValue {
+                field: field("start"),
+                value: window.start.clone(),
+            })?;
+
+            if window.duration_minutes == 0 || window.duration_minutes > MAX_WINDOW_MINUTES {
+                return Err(ConfigError::ValueOutOfRange {
+                    field: field("duration_minutes"),
+                    value: window.duration_minutes.to_string(),
+                    min: "1".to_string(),
+                    max: MAX_WINDOW_MINUTES.to_string(),
+                });
+            }
+
+            let days = window
+                .days
+                .iter()
+                .map(|day| {
+                    day.parse::<Weekday>().map_err(|_| ConfigError::InvalidValue {
+                        field: field("days"),
+                        value: day.clone(),
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+
+            windows.push(MaintenanceWindow {
+                name: window.name.clone(),
+                start,
+                duration: TimeDelta::minutes(window.duration_minutes as i64),
+                days,
+                release_ports: window.release_ports,
+            });
+        }
+
+        Ok(Self {
+            windows,
+            notify_before: TimeDelta::minutes(config.notify_before_minutes as i64),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.windows.is_empty()
+    }
+
+    /// The window in progress at `now` and when it ends
+    pub fn active(&self, now: NaiveDateTime) -> Option<(&MaintenanceWindow, NaiveDateTime)> {
+        self.windows
+            .iter()
+            .find_map(|window| window.occurrence_at(now).map(|(_, end)| (window, end)))
+    }
+
+    /// The next window starting within the notice period and when it starts
+    pub fn upcoming(&self, now: NaiveDateTime) -> Option<(&MaintenanceWindow, NaiveDateTime)> {
+        self.windows
+            .iter()
+            .filter_map(|window| window.next_start(now).map(|start| (window, start)))
+            .filter(|(_, start)| *start - now <= self.notify_before)
+            .min_by_key(|(_, start)| *start)
+    }
+}
+
+/// Whether a maintenance window is in progress, shared with the keep-alive task
+#[derive(Debug, Default)]
+pub struct MaintenanceState {
+    active: AtomicBool,
+}
+
+impl MaintenanceState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active.load(Ordering::SeqCst)
+    }
+}
+
+/// What was done to connections when the current window began
+struct WindowInProgress {
+    name: String,
+    suspended: Vec<String>,
+    released: Vec<String>,
+}
+
+/// Follow the schedule, entering and leaving windows and notifying the client
+pub(crate) async fn run_maintenance(
+    peer: Peer<RoleServer>,
+    manager: Arc<ConnectionManager>,
+    state: Arc<MaintenanceState>,
+    schedule: MaintenanceSchedule,
+) {
+    let mut current: Option<WindowInProgress> = None;
+    let mut announced: Option<NaiveDateTime> = None;
+
+    loop {
+        let now = Local::now().naive_local();
+
+        match (schedule.active(now), current.is_some()) {
+            (Some((window, end)), false) => {
+                state.active.store(true, Ordering::SeqCst);
+                let entered = enter_window(&manager, window).await;
+                let message = format!(
+                    "Maintenance window '{}' started, until {}. Suspended {} connection(s), released ports: {}",
+                    window.name,
+                    end.format("%H:%M"),
+                    entered.suspended.len(),
+                    port_list(&entered.released)
+                );
+                notify(&peer, LoggingLevel::Warning, message).await;
+                current = Some(entered);
+            }
+            (None, true) => {
+                if let Some(finished) = current.take() {
+                    leave_window(&manager, &finished).await;
+                    let message = format!(
+                        "Maintenance window '{}' ended. Resumed {} connection(s); reopen released ports: {}",
+                        finished.name,
+                        finished.suspended.len(),
+                        port_list(&finished.released)
+                    );
+                    notify(&peer, LoggingLevel::Notice, message).await;
+                }
+                state.active.store(false, Ordering::SeqCst);
+            }
+            _ => {}
+        }
+
+        if current.is_none() {
+            if let Some((window, start)) = schedule.upcoming(now) {
+                if announced != Some(start) {
+                    let message = format!(
+                        "Maintenance window '{}' starts at {} (in {} minutes){}",
+                        window.name,
+                        start.format("%H:%M"),
+                        (start - now).num_minutes(),
+                        if window.release_ports { "; open ports will be released" } else { "" }
+                    );
+                    notify(&peer, LoggingLevel::Notice, message).await;
+                    announced = Some(start);
+                }
+            }
+        }
+
+        tokio::time::sleep(Duration::from_secs(MAINTENANCE_CHECK_INTERVAL_SECS)).await;
+    }
+}
+
+async fn enter_window(manager: &ConnectionManager, window: &MaintenanceWindow) -> WindowInProgress {
+    let mut entered = WindowInProgress {
+        name: window.name.clone(),
+        suspended: Vec::new(),
+        released: Vec::new(),
+    };
+
+    for connection in manager.connections().await {
+        let id = connection.id().to_string();
+        if window.release_ports {
+            connection.record_event(format!("Closed for maintenance window '{}'", window.name)).await;
+            match manager.close(&id).await {
+                Ok(()) => entered.released.push(connection.config().port.clone()),
+                Err(e) => warn!("Failed to release connection {} for maintenance: {}", id, e),
+            }
+        } else if !connection.is_paused() {
+            match connection.pause(PauseSignal::None).await {
+                Ok(()) => {
+                    connection.record_event(format!("Suspended for maintenance window '{}'", window.name)).await;
+                    entered.suspended.push(id);
+                }
+                Err(e) => warn!("Failed to suspend connection {} for maintenance: {}", id, e),
+            }
+        }
+    }
+
+    entered
+}
+
+async fn leave_window(manager: &ConnectionManager, finished: &WindowInProgress) {
+    for id in &finished.suspended {
+        let Ok(connection) = manager.get(id).await else { continue };
+        match connection.resume().await {
+            Ok(()) => connection.record_event(format!("Resumed after maintenance window '{}'", finished.name)).await,
+            Err(e) => warn!("Failed to resume connection {} after maintenance: {}", id, e),
+        }
+    }
+}
+
+fn port_list(ports: &[String]) -> String {
+    if ports.is_empty() {
+        "none".to_string()
+    } else {
+        ports.join(", ")
+    }
+}
+
+/// Log a message and forward it to the client as a logging notification
+async fn notify(peer: &Peer<RoleServer>, level: LoggingLevel, message: String) {
+    info!("{}", message);
+    let param = LoggingMessageNotificationParam {
+        level,
+        logger: Some("maintenance".to_string()),
+        data: serde_json::Value::String(message),
+    };
+    if let Err(e) = peer.notify_logging_message(param).await {
+        warn!("Failed to send maintenance notification: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::MaintenanceWindowConfig;
+
+    fn at(value: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn schedule(start: &str, duration_minutes: u64, days: &[&str]) -> MaintenanceSchedule {
+        MaintenanceSchedule::from_config(&MaintenanceConfig {
+            notify_before_minutes: 10,
+            windows: vec![MaintenanceWindowConfig {
+                name: "nightly reboot".to_string(),
+                start: start.to_string(),
+                duration_minutes,
+                days: days.iter().map(|d| d.to_string()).collect(),
+                release_ports: false,
+            }],
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_window_across_midnight() {
+        // 2026-10-18 is a Sunday
+        let schedule = schedule("23:30", 60, &[]);
+
+        assert!(schedule.active(at("2026-10-18 23:29")).is_none());
+        let (window, end) = schedule.active(at("2026-10-18 23:45")).unwrap();
+        assert_eq!(window.name, "nightly reboot");
+        assert_eq!(end, at("2026-10-19 00:30"));
+        assert!(schedule.active(at("2026-10-19 00:15")).is_some());
+        assert!(schedule.active(at("2026-10-19 00:30")).is_none());
+    }
+
+    #[test]
+    fn test_window_days_and_notice() {
+        let schedule = schedule("02:00", 30, &["mon"]);
+
+        assert!(schedule.active(at("2026-10-18 02:10")).is_none());
+        assert!(schedule.active(at("2026-10-19 02:10")).is_some());
+
+        assert!(schedule.upcoming(at("2026-10-19 01:45")).is_none());
+        let (_, start) = schedule.upcoming(at("2026-10-19 01:55")).unwrap();
+        assert_eq!(start, at("2026-10-19 02:00"));
+    }
+
+    #[test]
+    fn test_invalid_window_config() {
+        let config = |start: &str, duration_minutes: u64, day: &str| MaintenanceConfig {
+            notify_before_minutes: 10,
+            windows: vec![MaintenanceWindowConfig {
+                name: "bad".to_string(),
+                start: start.to_string(),
+                duration_minutes,
+                days: vec![day.to_string()],
+                release_ports: true,
+            }],
+        };
+
+        assert!(MaintenanceSchedule::from_config(&config("25:00", 30, "tue")).is_err());
+        assert!(MaintenanceSchedule::from_config(&config("02:00", 0, "tue")).is_err());
+        assert!(MaintenanceSchedule::from_config(&config("02:00", 30, "someday")).is_err());
+        assert!(MaintenanceSchedule::from_config(&config("02:00", 30, "Tuesday")).is_ok());
+    }
+}
@@ -0,0 +1,56 @@
+//! Marlin printer tools
+//!
+//! Hotend, bed and chamber temperatures and the toolhead position of 3D
+//! printers running Marlin, as structured data.
+
+use std::future::Future;
+
+use rmcp::{
+    handler::server::tool::Parameters,
+    model::*,
+    tool, tool_router, ErrorData as McpError,
+};
+use serde_json::json;
+use tracing::{debug, error};
+
+use super::serial_handler::SerialHandler;
+use super::types::*;
+use crate::protocol::gcode::{self, FirmwareError};
+use crate::protocol::marlin::{parse_position, parse_temperatures};
+
+#[tool_router(router = marlin_router, vis = "pub(crate)")]
+impl SerialHandler {
+    #[tool(description = "Marlin: read the printer's temperatures with M105 and position with M114 and return them as JSON: each hotend, the bed and chamber with current and target temperature in Celsius and heater power, and the X, Y, Z and E position in mm. Fails while a G-code job is streaming on the connection; gcode_status shows the latest temperatures the printer reported during the job")]
+    async fn marlin_status(&self, Parameters(args): Parameters<MarlinStatusArgs>) -> Result<CallToolResult, McpError> {
+        let connection = self.connection(&args.connection_id).await?;
+        if connection.framer_name().await.is_some() {
+            let error_msg = format!("Error: Connection {} has a framer set, remove it to talk to Marlin", args.connection_id);
+            return Err(McpError::invalid_params(error_msg, None));
+        }
+        debug!("Querying Marlin status on connection {}", args.connection_id);
+
+        let temperatures = gcode::command(&connection, "M105", args.timeout_ms)
+            .await
+            .map_err(|e| marlin_failed(&args.connection_id, "Temperature report", e))?
+            .iter()
+            .find_map(|line| parse_temperatures(line));
+        let position = gcode::command(&connection, "M114", args.timeout_ms)
+            .await
+            .map_err(|e| marlin_failed(&args.connection_id, "Position report", e))?
+            .iter()
+            .find_map(|line| parse_position(line));
+
+        let status = json!({ "temperatures": temperatures, "position": position });
+        let message = format!(
+            "Marlin status\nConnection ID: {}\n{}",
+            args.connection_id,
+            serde_json::to_string_pretty(&status).unwrap_or_default()
+        );
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+}
+
+fn marlin_failed(connection_id: &str, action: &str, e: FirmwareError) -> McpError {
+    error!("Marlin {} on connection {} failed: {}", action.to_lowercase(), connection_id, e);
+    McpError::internal_error(format!("Error: {} failed - {}", action, e), None)
+}
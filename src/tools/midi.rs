@@ -0,0 +1,169 @@
+//! MIDI tools
+//!
+//! Send MIDI messages to synths and MIDI-over-UART devices, usually opened at
+//! MIDI's 31250 baud, and decode the messages they send. Running status and
+//! system exclusive messages split across reads carry over between calls.
+
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rmcp::{
+    handler::server::tool::Parameters,
+    model::*,
+    tool, tool_router, ErrorData as McpError,
+};
+use tokio::time::Instant;
+use tracing::{debug, error};
+
+use super::serial_handler::SerialHandler;
+use super::types::*;
+use super::warnings::Warnings;
+use crate::protocol::midi::{MidiMessage, MidiParser};
+use crate::serial::{LocalSerialError, SerialConnection};
+
+#[tool_router(router = midi_router, vis = "pub(crate)")]
+impl SerialHandler {
+    #[tool(description = "Send a MIDI message, usually on a connection opened at 31250 baud. message_type: note_on, note_off, poly_pressure, control_change, program_change, channel_pressure, pitch_bend (value -8192 to 8191), sysex (data as hex, F0/F7 added if missing), tune_request, clock, start, continue, stop or reset. Channels are 1 to 16. A note_on with duration_ms is followed by its note_off after that long")]
+    async fn midi_send(&self, Parameters(args): Parameters<MidiSendArgs>) -> Result<CallToolResult, McpError> {
+        let message = midi_message(&args).map_err(|e| McpError::invalid_params(format!("Error: {}", e), None))?;
+        let bytes = message.encode().map_err(|e| McpError::invalid_params(format!("Error: {}", e), None))?;
+        let release = match (&message, args.duration_ms) {
+            (MidiMessage::NoteOn { channel, note, .. }, Some(duration_ms)) => {
+                Some((MidiMessage::NoteOff { channel: *channel, note: *note, velocity: 64 }, duration_ms))
+            }
+            (_, Some(_)) => return Err(McpError::invalid_params("Error: duration_ms only applies to note_on".to_string(), None)),
+            (_, None) => None,
+        };
+        let connection = self.midi_connection(&args.connection_id).await?;
+        debug!("Sending MIDI {:?} on connection {}", message, args.connection_id);
+
+        connection.write(&bytes).await.map_err(|e| send_failed(&args.connection_id, e))?;
+        let mut text = format!(
+            "MIDI message sent\nConnection ID: {}\nMessage: {}\nBytes: {}",
+            args.connection_id,
+            serde_json::to_string(&message).unwrap_or_default(),
+            hex::encode_upper(&bytes)
+        );
+        if let Some((note_off, duration_ms)) = release {
+            tokio::time::sleep(Duration::from_millis(duration_ms)).await;
+            let bytes = note_off.encode().expect("note on's channel and note were valid");
+            connection.write(&bytes).await.map_err(|e| send_failed(&args.connection_id, e))?;
+            text.push_str(&format!("\nNote off sent after {}ms: {}", duration_ms, hex::encode_upper(&bytes)));
+        }
+        Ok(CallToolResult::success(vec![Content::text(text)]))
+    }
+
+    #[tool(description = "Collect MIDI messages from a connection for a while and return them decoded as JSON: notes, control changes, program changes, pitch bend, pressure, system exclusive messages reassembled across reads, and system messages. Clock and active sensing, which arrive continuously, are counted rather than listed unless include_timing is set. Bytes received before the call are included")]
+    async fn midi_read(&self, Parameters(args): Parameters<MidiReadArgs>) -> Result<CallToolResult, McpError> {
+        let connection = self.midi_connection(&args.connection_id).await?;
+        debug!("Collecting MIDI messages on connection {} for {}ms", args.connection_id, args.duration_ms);
+
+        // Taken out while reading so other connections' reads are not held up
+        let mut parser = self.midi_parsers.lock().await.remove(&args.connection_id).unwrap_or_default();
+        let collected = collect_messages(&connection, &mut parser, Duration::from_millis(args.duration_ms), args.max_messages.max(1), args.include_timing).await;
+        let dropped = parser.take_dropped();
+        self.midi_parsers.lock().await.insert(args.connection_id.clone(), parser);
+        let (messages, timing) = collected.map_err(|e| {
+            error!("Failed to read MIDI messages from connection {}: {}", args.connection_id, e);
+            McpError::internal_error(format!("Error: Data reading failed - {}", e), None)
+        })?;
+
+        let mut warnings = Warnings::new();
+        if dropped > 0 {
+            warnings.push(format!("{} bytes dropped: data without a status byte or an unterminated system exclusive message", dropped));
+        }
+        let mut text = format!("MIDI messages read\nConnection ID: {}\nMessages: {}", args.connection_id, messages.len());
+        if timing > 0 {
+            text.push_str(&format!("\nClock and active sensing messages: {}", timing));
+        }
+        text.push_str(&format!("\n{}", serde_json::to_string_pretty(&messages).unwrap_or_default()));
+        warnings.into_result(text)
+    }
+}
+
+impl SerialHandler {
+    /// The connection, provided its reads are raw bytes
+    async fn midi_connection(&self, connection_id: &str) -> Result<Arc<SerialConnection>, McpError> {
+        let connection = self.connection(connection_id).await?;
+        if connection.is_line_mode().await || connection.framer_name().await.is_some() {
+            let error_msg = format!("Error: Connection {} has line mode or a framer set, disable it to use MIDI", connection_id);
+            return Err(McpError::invalid_params(error_msg, None));
+        }
+        Ok(connection)
+    }
+}
+
+fn send_failed(connection_id: &str, e: LocalSerialError) -> McpError {
+    error!("Failed to send MIDI on connection {}: {}", connection_id, e);
+    McpError::internal_error(format!("Error: Data sending failed - {}", e), None)
+}
+
+/// The message described by the arguments
+fn midi_message(args: &MidiSendArgs) -> Result<MidiMessage, String> {
+    let channel = args.channel;
+    let required = |value: Option<i32>, name: &str| -> Result<u8, String> {
+        let value = value.ok_or_else(|| format!("{} needs {}", args.message_type, name))?;
+        u8::try_from(value).map_err(|_| format!("{} {} is not 0 to 127", name, value))
+    };
+    let note = || required(args.note.map(i32::from), "note");
+    Ok(match args.message_type.to_lowercase().as_str() {
+        "note_on" => MidiMessage::NoteOn { channel, note: note()?, velocity: args.velocity.unwrap_or(100) },
+        "note_off" => MidiMessage::NoteOff { channel, note: note()?, velocity: args.velocity.unwrap_or(64) },
+        "poly_pressure" => MidiMessage::PolyPressure { channel, note: note()?, pressure: required(args.value, "value")? },
+        "control_change" | "cc" => MidiMessage::ControlChange {
+            channel,
+            controller: required(args.controller.map(i32::from), "controller")?,
+            value: required(args.value, "value")?,
+        },
+        "program_change" => MidiMessage::ProgramChange { channel, program: required(args.value, "value")? },
+        "channel_pressure" => MidiMessage::ChannelPressure { channel, pressure: required(args.value, "value")? },
+        "pitch_bend" => {
+            let value = args.value.ok_or("pitch_bend needs value")?;
+            MidiMessage::PitchBend { channel, value: i16::try_from(value).map_err(|_| format!("Pitch bend {} is not -8192 to 8191", value))? }
+        }
+        "sysex" => {
+            let data = decode_data(args.data.as_deref().ok_or("sysex needs data")?, "hex")?;
+            let data = data.strip_prefix(&[0xF0]).unwrap_or(&data);
+            let data = data.strip_suffix(&[0xF7]).unwrap_or(data);
+            MidiMessage::SysEx { data: data.to_vec() }
+        }
+        "tune_request" => MidiMessage::TuneRequest,
+        "clock" => MidiMessage::Clock,
+        "start" => MidiMessage::Start,
+        "continue" => MidiMessage::Continue,
+        "stop" => MidiMessage::Stop,
+        "reset" => MidiMessage::Reset,
+        other => return Err(format!("Unknown MIDI message type: {}", other)),
+    })
+}
+
+/// Collect messages for up to `duration`, stopping once `max` have arrived
+///
+/// Returns the messages and, unless `include_timing`, how many clock and
+/// active sensing messages were left out.
+async fn collect_messages(
+    connection: &SerialConnection,
+    parser: &mut MidiParser,
+    duration: Duration,
+    max: usize,
+    include_timing: bool,
+) -> Result<(Vec<MidiMessage>, usize), LocalSerialError> {
+    let deadline = Instant::now() + duration;
+    let mut messages = Vec::new();
+    let mut timing = 0;
+
+    connection
+        .read_until(deadline, |chunk| {
+            for message in parser.feed(chunk) {
+                match message {
+                    MidiMessage::Clock | MidiMessage::ActiveSensing if !include_timing => timing += 1,
+                    message => messages.push(message),
+                }
+            }
+            messages.len() >= max
+        })
+        .await?;
+
+    Ok((messages, timing))
+}
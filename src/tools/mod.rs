@@ -12,10 +12,60 @@
 // pub mod serial_tools_working;
 
 // Current implementation using rust-sdk standards
+pub mod auth;
+pub mod avr;
+pub mod bridge;
+pub mod capture;
+pub mod cat;
+pub mod chaos;
+pub mod checksum;
+pub mod console;
+pub mod defmt;
+pub mod diagnostics;
+pub mod discovery;
+pub mod dsmr;
+pub mod esp;
+pub mod expect;
+pub mod expiry;
+pub mod firmware;
+pub mod frame_spec;
+pub mod framing;
+pub mod gcode;
+pub mod grbl;
+pub mod group;
+pub mod idle;
+pub mod jsonl;
+pub mod keepalive;
+pub mod liveness;
+pub mod maintenance;
+pub mod marlin;
+pub mod midi;
+pub mod modbus;
+pub mod nmea;
+pub mod notes;
+pub mod policy;
+pub mod protobuf;
+pub mod profiles;
+pub mod protocols;
+pub mod reconnect;
+#[cfg(feature = "scripting")]
+pub mod script;
 pub mod serial_handler;
+pub mod session;
+pub mod slcan;
+pub mod sms;
+pub mod stm32;
+pub mod subscription;
+pub mod transfer;
 pub mod types;
+pub mod verify;
+pub mod warnings;
+pub mod watchdog;
+pub mod ymodem;
+pub mod zmodem;
 
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod tests;
 
 // Export the main handler and types
@@ -0,0 +1,318 @@
+//! Modbus RTU master and slave simulator tools
+//!
+//! Read coils and registers and write registers on Modbus RTU slaves without
+//! hand-crafting frames: addressing, CRC and inter-frame timing are handled.
+//! The simulator tools answer a master as a slave from a register map the
+//! caller can inspect and change while it runs, and the gateway tools let
+//! Modbus TCP clients reach the slaves on a connection's bus.
+
+use std::future::Future;
+
+use rmcp::{
+    handler::server::tool::Parameters,
+    model::*,
+    tool, tool_router, ErrorData as McpError,
+};
+use tracing::{debug, error, info};
+
+use super::serial_handler::SerialHandler;
+use super::types::*;
+use crate::protocol::modbus::{transaction, ModbusError, ModbusRequest, ModbusResponse, BROADCAST_ADDRESS, MAX_SLAVE_ADDRESS};
+use crate::protocol::modbus_gateway::ModbusGateway;
+use crate::protocol::modbus_slave::{ModbusSimulator, RegisterTable};
+
+#[tool_router(router = modbus_router, vis = "pub(crate)")]
+impl SerialHandler {
+    #[tool(description = "Modbus RTU: read holding registers (function 0x03) from a slave. Addresses are 0-based as on the wire (register 40001 is address 0). The reply's CRC, slave and function are checked; slave exceptions are reported by name")]
+    async fn modbus_read_holding_registers(&self, Parameters(args): Parameters<ModbusReadArgs>) -> Result<CallToolResult, McpError> {
+        let request = ModbusRequest::ReadHoldingRegisters { address: args.address, count: args.count };
+        self.modbus_exchange(&args.connection_id, args.slave, request, args.timeout_ms).await
+    }
+
+    #[tool(description = "Modbus RTU: read input registers (function 0x04) from a slave. Addresses are 0-based as on the wire (register 30001 is address 0)")]
+    async fn modbus_read_input_registers(&self, Parameters(args): Parameters<ModbusReadArgs>) -> Result<CallToolResult, McpError> {
+        let request = ModbusRequest::ReadInputRegisters { address: args.address, count: args.count };
+        self.modbus_exchange(&args.connection_id, args.slave, request, args.timeout_ms).await
+    }
+
+    #[tool(description = "Modbus RTU: read coils (function 0x01) from a slave. Addresses are 0-based as on the wire (coil 00001 is address 0)")]
+    async fn modbus_read_coils(&self, Parameters(args): Parameters<ModbusReadArgs>) -> Result<CallToolResult, McpError> {
+        let request = ModbusRequest::ReadCoils { address: args.address, count: args.count };
+        self.modbus_exchange(&args.connection_id, args.slave, request, args.timeout_ms).await
+    }
+
+    #[tool(description = "Modbus RTU: write one holding register (function 0x06). Slave 0 broadcasts to all slaves, which do not reply")]
+    async fn modbus_write_register(&self, Parameters(args): Parameters<ModbusWriteRegisterArgs>) -> Result<CallToolResult, McpError> {
+        let request = ModbusRequest::WriteSingleRegister { address: args.address, value: args.value };
+        self.modbus_exchange(&args.connection_id, args.slave, request, args.timeout_ms).await
+    }
+
+    #[tool(description = "Modbus RTU: write consecutive holding registers (function 0x10). Slave 0 broadcasts to all slaves, which do not reply")]
+    async fn modbus_write_registers(&self, Parameters(args): Parameters<ModbusWriteRegistersArgs>) -> Result<CallToolResult, McpError> {
+        let request = ModbusRequest::WriteMultipleRegisters { address: args.address, values: args.values };
+        self.modbus_exchange(&args.connection_id, args.slave, request, args.timeout_ms).await
+    }
+
+    #[tool(description = "Start a Modbus RTU slave simulator: open a port and answer master requests for one slave address (read coils, read holding/input registers, write registers) from an in-memory register map. Unset addresses read as 0. Bad CRCs and other slaves' requests are ignored; broadcasts are applied without a reply. Do not read from the simulator's connection while it runs")]
+    async fn modbus_simulator_start(&self, Parameters(args): Parameters<ModbusSimulatorStartArgs>) -> Result<CallToolResult, McpError> {
+        if args.slave == BROADCAST_ADDRESS || args.slave > MAX_SLAVE_ADDRESS {
+            let error_msg = format!("Error: Simulated slave address must be 1 to {}, not {}", MAX_SLAVE_ADDRESS, args.slave);
+            return Err(McpError::invalid_params(error_msg, None));
+        }
+
+        let connection_id = self.open_port(args.port).await?;
+        let connection = self.connection(&connection_id).await?;
+        let simulator_id = uuid::Uuid::new_v4().to_string();
+        connection.record_event(format!("Simulating Modbus slave {} for simulator {}", args.slave, simulator_id)).await;
+
+        let message = format!(
+            "Modbus simulator started\nSimulator ID: {}\nPort: {} (connection {})\nSlave: {}",
+            simulator_id,
+            connection.config().port,
+            connection_id,
+            args.slave
+        );
+        info!("Modbus simulator {} answering as slave {} on {}", simulator_id, args.slave, connection.config().port);
+        self.modbus_simulators.lock().await.insert(simulator_id, ModbusSimulator::start(connection, args.slave));
+
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+
+    #[tool(description = "Stop a Modbus RTU slave simulator, close its port and report how many requests it served")]
+    async fn modbus_simulator_stop(&self, Parameters(args): Parameters<ModbusSimulatorStopArgs>) -> Result<CallToolResult, McpError> {
+        let simulator = self
+            .modbus_simulators
+            .lock()
+            .await
+            .remove(&args.simulator_id)
+            .ok_or_else(|| unknown_simulator(&args.simulator_id))?;
+        simulator.stop();
+
+        let connection_id = simulator.connection().id();
+        if let Err(e) = self.close_connection(connection_id).await {
+            // Already closed with the close tool
+            info!("Modbus simulator {} connection {} not closed: {}", args.simulator_id, connection_id, e);
+        }
+
+        let message = format!("Modbus simulator stopped\nSimulator ID: {}\n{}", args.simulator_id, format_simulator_stats(&simulator));
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+
+    #[tool(description = "Inspect a Modbus RTU slave simulator: request counts, whether it is still answering, and the current values of a range of coils, holding registers or input registers, including values written by the master")]
+    async fn modbus_simulator_read(&self, Parameters(args): Parameters<ModbusSimulatorReadArgs>) -> Result<CallToolResult, McpError> {
+        let table = parse_table(&args.table)?;
+        let simulators = self.modbus_simulators.lock().await;
+        let simulator = simulators.get(&args.simulator_id).ok_or_else(|| unknown_simulator(&args.simulator_id))?;
+        let values = simulator.registers().read(table, args.address, args.count);
+
+        let mut message = format!(
+            "Simulator ID: {}\nState: {}\nStarted: {}\nPort: {} (connection {})\nSlave: {}\n{}\n\n{} from address {}:",
+            args.simulator_id,
+            if simulator.is_running() { "answering" } else { "stopped" },
+            simulator.started_at().to_rfc3339(),
+            simulator.connection().config().port,
+            simulator.connection().id(),
+            simulator.slave(),
+            format_simulator_stats(simulator),
+            table,
+            args.address
+        );
+        for (offset, value) in values.iter().enumerate() {
+            match table {
+                RegisterTable::Coils => message.push_str(&format!("\n  {}: {}", args.address as usize + offset, value)),
+                _ => message.push_str(&format!("\n  {}: {} (0x{:04X})", args.address as usize + offset, value, value)),
+            }
+        }
+
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+
+    #[tool(description = "Set coils, holding registers or input registers in a running Modbus RTU slave simulator; masters see the new values on their next read")]
+    async fn modbus_simulator_write(&self, Parameters(args): Parameters<ModbusSimulatorWriteArgs>) -> Result<CallToolResult, McpError> {
+        let table = parse_table(&args.table)?;
+        if args.values.is_empty() {
+            return Err(McpError::invalid_params("Error: No values to write".to_string(), None));
+        }
+        if args.address as usize + args.values.len() > 0x10000 {
+            let error_msg = format!("Error: {} values from address {} run past 65535", args.values.len(), args.address);
+            return Err(McpError::invalid_params(error_msg, None));
+        }
+
+        let simulators = self.modbus_simulators.lock().await;
+        let simulator = simulators.get(&args.simulator_id).ok_or_else(|| unknown_simulator(&args.simulator_id))?;
+        simulator.registers().write(table, args.address, &args.values);
+
+        let message = format!(
+            "Set {} {} from address {}\nSimulator ID: {}",
+            args.values.len(),
+            table,
+            args.address,
+            args.simulator_id
+        );
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+
+    #[tool(description = "Start a Modbus TCP to RTU gateway: listen for Modbus TCP clients (SCADA, HMI or PLC software) and forward each request to the slave on an open RTU connection whose address is the request's unit identifier, passing the reply back. Any function code is forwarded. Requests from all clients and this server's own Modbus tools take turns on the bus. Clients get exception 0x0B when a slave does not answer in time or answers badly, and 0x0A for unit identifiers above 247. Listens on 127.0.0.1:5020 unless told otherwise")]
+    async fn modbus_gateway_start(&self, Parameters(args): Parameters<ModbusGatewayStartArgs>) -> Result<CallToolResult, McpError> {
+        if args.timeout_ms == 0 {
+            return Err(McpError::invalid_params("Error: timeout_ms must be at least 1".to_string(), None));
+        }
+        let connection = self.raw_connection(&args.connection_id, "use Modbus").await?;
+        let listener = tokio::net::TcpListener::bind(&args.listen).await.map_err(|e| {
+            error!("Modbus gateway could not listen on {}: {}", args.listen, e);
+            McpError::internal_error(format!("Error: Cannot listen on {} - {}", args.listen, e), None)
+        })?;
+        let gateway = ModbusGateway::start(listener, connection, args.timeout_ms)
+            .map_err(|e| McpError::internal_error(format!("Error: Modbus gateway failed to start - {}", e), None))?;
+
+        let gateway_id = uuid::Uuid::new_v4().to_string();
+        gateway
+            .connection()
+            .record_event(format!("Modbus gateway {} listening on {}", gateway_id, gateway.local_addr()))
+            .await;
+        let message = format!(
+            "Modbus gateway started\nGateway ID: {}\nListening: {}\nBus: {} (connection {})\nReply timeout: {}ms",
+            gateway_id,
+            gateway.local_addr(),
+            gateway.connection().config().port,
+            args.connection_id,
+            args.timeout_ms
+        );
+        info!("Modbus gateway {} forwarding {} to {}", gateway_id, gateway.local_addr(), gateway.connection().config().port);
+        self.modbus_gateways.lock().await.insert(gateway_id, gateway);
+
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+
+    #[tool(description = "Stop a Modbus TCP to RTU gateway, disconnecting its clients, and report its totals. The RTU connection stays open")]
+    async fn modbus_gateway_stop(&self, Parameters(args): Parameters<ModbusGatewayArgs>) -> Result<CallToolResult, McpError> {
+        let gateway = self
+            .modbus_gateways
+            .lock()
+            .await
+            .remove(&args.gateway_id)
+            .ok_or_else(|| unknown_gateway(&args.gateway_id))?;
+        gateway.stop();
+        gateway.connection().record_event(format!("Modbus gateway {} stopped", args.gateway_id)).await;
+
+        let message = format!("Modbus gateway stopped\nGateway ID: {}\n{}", args.gateway_id, format_gateway_stats(&gateway));
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+
+    #[tool(description = "Inspect a Modbus TCP to RTU gateway: whether it is still listening, its clients, and counts of requests forwarded, replies, timeouts and bad replies")]
+    async fn modbus_gateway_status(&self, Parameters(args): Parameters<ModbusGatewayArgs>) -> Result<CallToolResult, McpError> {
+        let gateways = self.modbus_gateways.lock().await;
+        let gateway = gateways.get(&args.gateway_id).ok_or_else(|| unknown_gateway(&args.gateway_id))?;
+
+        let message = format!(
+            "Gateway ID: {}\nState: {}\nStarted: {}\nListening: {}\nBus: {} (connection {})\nReply timeout: {}ms\n{}",
+            args.gateway_id,
+            if gateway.is_running() { "listening" } else { "stopped" },
+            gateway.started_at().to_rfc3339(),
+            gateway.local_addr(),
+            gateway.connection().config().port,
+            gateway.connection().id(),
+            gateway.timeout_ms(),
+            format_gateway_stats(gateway)
+        );
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+}
+
+fn unknown_simulator(simulator_id: &str) -> McpError {
+    McpError::invalid_params(format!("Error: Modbus simulator {} not found", simulator_id), None)
+}
+
+fn unknown_gateway(gateway_id: &str) -> McpError {
+    McpError::invalid_params(format!("Error: Modbus gateway {} not found", gateway_id), None)
+}
+
+fn parse_table(table: &str) -> Result<RegisterTable, McpError> {
+    RegisterTable::parse(table).map_err(|e| McpError::invalid_params(format!("Error: {}", e), None))
+}
+
+fn format_simulator_stats(simulator: &ModbusSimulator) -> String {
+    let stats = simulator.stats();
+    let mut text = format!(
+        "Requests: {}\nExceptions: {}\nCRC errors: {}\nFor other slaves: {}",
+        stats.requests, stats.exceptions, stats.crc_errors, stats.other_slaves
+    );
+    if let Some(error) = &stats.error {
+        text.push_str(&format!("\nError: {}", error));
+    }
+    text
+}
+
+fn format_gateway_stats(gateway: &ModbusGateway) -> String {
+    let stats = gateway.stats();
+    let mut text = format!(
+        "Clients: {} ({} connected)\nRequests: {}\nReplies: {}\nTimeouts: {}\nBad replies: {}\nGateway exceptions: {}",
+        stats.clients, stats.open_clients, stats.requests, stats.replies, stats.timeouts, stats.bad_replies, stats.gateway_exceptions
+    );
+    if let Some(error) = &stats.error {
+        text.push_str(&format!("\nError: {}", error));
+    }
+    text
+}
+
+impl SerialHandler {
+    /// Run one Modbus request on a connection and format the reply
+    async fn modbus_exchange(
+        &self,
+        connection_id: &str,
+        slave: u8,
+        request: ModbusRequest,
+        timeout_ms: u64,
+    ) -> Result<CallToolResult, McpError> {
+        let connection = self.connection(connection_id).await?;
+        if connection.is_line_mode().await || connection.framer_name().await.is_some() {
+            let error_msg = format!("Error: Connection {} has line mode or a framer set, disable it to use Modbus", connection_id);
+            return Err(McpError::invalid_params(error_msg, None));
+        }
+
+        debug!("Modbus {} on connection {} slave {}", request.function_name(), connection_id, slave);
+        let response = match transaction(&connection, slave, &request, timeout_ms).await {
+            Ok(response) => response,
+            Err(ModbusError::InvalidRequest(e)) => {
+                return Err(McpError::invalid_params(format!("Error: Invalid Modbus request - {}", e), None));
+            }
+            Err(e) => {
+                error!("Modbus {} on connection {} failed: {}", request.function_name(), connection_id, e);
+                connection.record_event(format!("Modbus {} to slave {} failed: {}", request.function_name(), slave, e)).await;
+                return Err(McpError::internal_error(format!("Error: Modbus {} failed - {}", request.function_name(), e), None));
+            }
+        };
+
+        let mut message = format!(
+            "Modbus {} (0x{:02X}) succeeded\nConnection ID: {}\nSlave: {}",
+            request.function_name(),
+            request.function_code(),
+            connection_id,
+            if slave == BROADCAST_ADDRESS { "broadcast".to_string() } else { slave.to_string() }
+        );
+        let first = match &request {
+            ModbusRequest::ReadCoils { address, .. }
+            | ModbusRequest::ReadHoldingRegisters { address, .. }
+            | ModbusRequest::ReadInputRegisters { address, .. }
+            | ModbusRequest::WriteSingleRegister { address, .. }
+            | ModbusRequest::WriteMultipleRegisters { address, .. } => *address as usize,
+        };
+        match response {
+            ModbusResponse::Coils(coils) => {
+                message.push_str("\nCoils:");
+                for (offset, coil) in coils.iter().enumerate() {
+                    message.push_str(&format!("\n  {}: {}", first + offset, u8::from(*coil)));
+                }
+            }
+            ModbusResponse::Registers(registers) => {
+                message.push_str("\nRegisters:");
+                for (offset, value) in registers.iter().enumerate() {
+                    message.push_str(&format!("\n  {}: {} (0x{:04X})", first + offset, value, value));
+                }
+            }
+            ModbusResponse::Written if slave == BROADCAST_ADDRESS => message.push_str("\nBroadcast sent; slaves do not reply"),
+            ModbusResponse::Written => message.push_str("\nWrite acknowledged"),
+        }
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+}
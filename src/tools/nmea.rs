@@ -0,0 +1,208 @@
+//! NMEA 0183 tools
+//!
+//! Collect sentences from a GPS module and return them checksum-checked and
+//! decoded, or just the current position, so `$GPGGA` strings never need to
+//! be picked apart by hand.
+
+use std::future::Future;
+use std::time::Duration;
+
+use rmcp::{
+    handler::server::tool::Parameters,
+    model::*,
+    tool, tool_router, ErrorData as McpError,
+};
+use serde_json::json;
+use tokio::time::Instant;
+use tracing::{debug, error};
+
+use super::serial_handler::SerialHandler;
+use super::types::*;
+use super::warnings::Warnings;
+use crate::protocol::nmea::{split_sentences, Gga, NmeaError, NmeaMessage, NmeaSentence, Rmc};
+use crate::serial::{LocalSerialError, SerialConnection};
+
+#[tool_router(router = nmea_router, vis = "pub(crate)")]
+impl SerialHandler {
+    #[tool(description = "Collect NMEA 0183 sentences from a GPS module for a while and return them as JSON: checksums validated, each sentence split into talker, type and fields, with GGA (fix), RMC (position, speed, course) and GSV (satellites in view) decoded. Sentences failing their checksum are reported separately. Text received before the call is included")]
+    async fn nmea_read(&self, Parameters(args): Parameters<NmeaReadArgs>) -> Result<CallToolResult, McpError> {
+        let connection = self.nmea_connection(&args.connection_id).await?;
+        debug!("Collecting NMEA sentences on connection {} for {}ms", args.connection_id, args.duration_ms);
+
+        let max = args.max_sentences.max(1);
+        let sentences = collect_sentences(&connection, Duration::from_millis(args.duration_ms), |sentences| sentences.len() >= max)
+            .await
+            .map_err(|e| read_failed(&args.connection_id, e))?;
+
+        let mut warnings = Warnings::new();
+        let mut decoded = Vec::new();
+        for sentence in sentences.into_iter().take(max) {
+            match sentence {
+                Ok(sentence) => decoded.push(json!({
+                    "talker": sentence.talker,
+                    "kind": sentence.kind,
+                    "fields": sentence.fields,
+                    "checksummed": sentence.checksummed,
+                    "decoded": sentence.message(),
+                })),
+                Err(e) => warnings.push(format!("Sentence rejected: {}", e)),
+            }
+        }
+
+        let message = format!(
+            "NMEA sentences read\nConnection ID: {}\nSentences: {}\n{}",
+            args.connection_id,
+            decoded.len(),
+            serde_json::to_string_pretty(&decoded).unwrap_or_default()
+        );
+        warnings.into_result(message)
+    }
+
+    #[tool(description = "Get the current GPS position from a module sending NMEA 0183: waits for a GGA or RMC sentence with a fix and returns latitude and longitude in decimal degrees, plus altitude, satellites, fix quality, speed and course where the module reports them. Reports when the module has no fix yet")]
+    async fn get_position(&self, Parameters(args): Parameters<GetPositionArgs>) -> Result<CallToolResult, McpError> {
+        let connection = self.nmea_connection(&args.connection_id).await?;
+        debug!("Waiting up to {}ms for a position on connection {}", args.timeout_ms, args.connection_id);
+
+        let sentences = collect_sentences(&connection, Duration::from_millis(args.timeout_ms), |sentences| {
+            latest_fix(sentences).is_some()
+        })
+        .await
+        .map_err(|e| read_failed(&args.connection_id, e))?;
+        let sentences: Vec<NmeaSentence> = sentences.into_iter().filter_map(Result::ok).collect();
+
+        let Some(position) = latest_fix(&sentences) else {
+            let satellites = sentences
+                .iter()
+                .rev()
+                .find_map(|sentence| match sentence.message() {
+                    NmeaMessage::Gga(gga) => gga.satellites,
+                    _ => None,
+                });
+            let message = if sentences.is_empty() {
+                format!("No NMEA sentences received\nConnection ID: {}\nWaited: {}ms", args.connection_id, args.timeout_ms)
+            } else {
+                format!(
+                    "No GPS fix yet\nConnection ID: {}\nSentences received: {}\nSatellites in use: {}",
+                    args.connection_id,
+                    sentences.len(),
+                    satellites.map_or_else(|| "unknown".to_string(), |count| count.to_string())
+                )
+            };
+            return Ok(CallToolResult::success(vec![Content::text(message)]));
+        };
+
+        let message = format!(
+            "Position\nConnection ID: {}\n{}",
+            args.connection_id,
+            serde_json::to_string_pretty(&position).unwrap_or_default()
+        );
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+}
+
+impl SerialHandler {
+    /// The connection, provided its reads are raw bytes or lines
+    async fn nmea_connection(&self, connection_id: &str) -> Result<std::sync::Arc<SerialConnection>, McpError> {
+        let connection = self.connection(connection_id).await?;
+        if connection.framer_name().await.is_some() {
+            let error_msg = format!("Error: Connection {} has a framer set, remove it to read NMEA sentences", connection_id);
+            return Err(McpError::invalid_params(error_msg, None));
+        }
+        Ok(connection)
+    }
+}
+
+fn read_failed(connection_id: &str, e: LocalSerialError) -> McpError {
+    error!("Failed to read NMEA sentences from connection {}: {}", connection_id, e);
+    McpError::internal_error(format!("Error: Data reading failed - {}", e), None)
+}
+
+/// Collect sentences for up to `duration`, stopping early once `done` is satisfied
+///
+/// Only complete lines are parsed; a sentence still arriving at the deadline is left out.
+async fn collect_sentences(
+    connection: &SerialConnection,
+    duration: Duration,
+    done: impl Fn(&[NmeaSentence]) -> bool,
+) -> Result<Vec<Result<NmeaSentence, NmeaError>>, LocalSerialError> {
+    let deadline = Instant::now() + duration;
+    let mut sentences = Vec::new();
+    let mut valid = Vec::new();
+    let mut pending = String::new();
+
+    let mut take = |text: &str| {
+        pending.push_str(text);
+        let Some(end) = pending.rfind('\n') else {
+            return false;
+        };
+        let complete: String = pending.drain(..=end).collect();
+        for sentence in split_sentences(&complete) {
+            if let Ok(sentence) = &sentence {
+                valid.push(sentence.clone());
+            }
+            sentences.push(sentence);
+        }
+        done(&valid)
+    };
+    if connection.is_line_mode().await {
+        connection.read_lines_until(deadline, |line| take(&format!("{}\n", line.text()))).await?;
+    } else {
+        connection.read_until(deadline, |chunk| take(&String::from_utf8_lossy(chunk))).await?;
+    }
+
+    Ok(sentences)
+}
+
+/// Position from the most recent GGA and RMC sentences with a fix
+fn latest_fix(sentences: &[NmeaSentence]) -> Option<serde_json::Value> {
+    let mut gga: Option<Gga> = None;
+    let mut rmc: Option<Rmc> = None;
+    for sentence in sentences.iter().rev() {
+        match sentence.message() {
+            NmeaMessage::Gga(fix) if gga.is_none() && fix.has_fix() => gga = Some(fix),
+            NmeaMessage::Rmc(fix) if rmc.is_none() && fix.has_fix() => rmc = Some(fix),
+            _ => {}
+        }
+    }
+
+    let (latitude, longitude, time) = match (&gga, &rmc) {
+        (Some(gga), _) => (gga.latitude?, gga.longitude?, gga.time.clone()),
+        (None, Some(rmc)) => (rmc.latitude?, rmc.longitude?, rmc.time.clone()),
+        (None, None) => return None,
+    };
+    Some(json!({
+        "latitude": latitude,
+        "longitude": longitude,
+        "time": time,
+        "date": rmc.as_ref().and_then(|rmc| rmc.date.clone()),
+        "altitude_m": gga.as_ref().and_then(|gga| gga.altitude_m),
+        "fix_quality": gga.as_ref().map(|gga| gga.fix_quality),
+        "satellites": gga.as_ref().and_then(|gga| gga.satellites),
+        "hdop": gga.as_ref().and_then(|gga| gga.hdop),
+        "speed_knots": rmc.as_ref().and_then(|rmc| rmc.speed_knots),
+        "course_deg": rmc.as_ref().and_then(|rmc| rmc.course_deg),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_latest_fix_combines_gga_and_rmc() {
+        let sentences: Vec<NmeaSentence> = split_sentences(
+            "$GPRMC,123519,A,4807.038,N,01131.000,W,022.4,084.4,230394,003.1,W*78\n\
+             $GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*47\n",
+        )
+        .into_iter()
+        .map(Result::unwrap)
+        .collect();
+
+        let position = latest_fix(&sentences).unwrap();
+        assert_eq!(position["satellites"], 8);
+        assert_eq!(position["speed_knots"], 22.4);
+        assert!(position["longitude"].as_f64().unwrap() > 0.0);
+
+        assert!(latest_fix(&[NmeaSentence::parse("$GPGGA,,,,,,0,00,,,,,,,").unwrap()]).is_none());
+    }
+}
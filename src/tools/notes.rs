@@ -0,0 +1,150 @@
+//! Device notes
+//!
+//! Freeform key/value notes attached to the device behind a connection, such
+//! as "this unit has the flaky RX pin". Notes are keyed by port path, so they
+//! outlive the connection they were set on, and are saved under
+//! `server.state_directory` so later conversations and server runs see them.
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use rmcp::{
+    handler::server::tool::Parameters, model::*, tool, tool_router, ErrorData as McpError,
+};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, warn};
+
+use super::serial_handler::SerialHandler;
+use super::types::*;
+use crate::error::StateError;
+use crate::state::{load_state, save_state, PersistedState};
+
+/// File under the state directory holding the notes
+const NOTES_FILE: &str = "notes.json";
+
+/// Notes of every device, keyed by port path and then note key
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceNotes {
+    pub devices: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl PersistedState for DeviceNotes {
+    const KIND: &'static str = "notes";
+    const VERSION: u32 = 1;
+}
+
+/// Device notes, saved to disk on every change
+#[derive(Debug)]
+pub struct NoteStore {
+    path: PathBuf,
+    /// Whether the file could be loaded, and so may be written
+    loaded: bool,
+    notes: Mutex<DeviceNotes>,
+}
+
+impl NoteStore {
+    /// Load the notes saved in `state_directory`
+    ///
+    /// Unreadable notes, such as a file written by a newer server, are logged
+    /// and replaced by an empty set rather than keeping the server from
+    /// starting. The file is then left as it is: notes cannot be set this run.
+    pub fn load(state_directory: &Path) -> Self {
+        let path = state_directory.join(NOTES_FILE);
+        let (notes, loaded) = match load_state::<DeviceNotes>(&path) {
+            Ok(notes) => (notes.unwrap_or_default(), true),
+            Err(e) => {
+                warn!("Device notes not loaded, leaving {} untouched: {}", path.display(), e);
+                (DeviceNotes::default(), false)
+            }
+        };
+        Self {
+            path,
+            loaded,
+            notes: Mutex::new(notes),
+        }
+    }
+
+    /// Notes of the device on `port`
+    pub fn get(&self, port: &str) -> BTreeMap<String, String> {
+        self.notes.lock().unwrap().devices.get(port).cloned().unwrap_or_default()
+    }
+
+    /// Set a note, or remove it when `value` is empty, and save the notes
+    pub fn set(&self, port: &str, key: &str, value: &str) -> Result<(), StateError> {
+        if !self.loaded {
+            return Err(StateError::NotLoaded { path: self.path.display().to_string() });
+        }
+        let mut notes = self.notes.lock().unwrap();
+        let device = notes.devices.entry(port.to_string()).or_default();
+        if value.is_empty() {
+            device.remove(key);
+        } else {
+            device.insert(key.to_string(), value.to_string());
+        }
+        if device.is_empty() {
+            notes.devices.remove(port);
+        }
+        save_state(&self.path, &*notes)
+    }
+}
+
+#[tool_router(router = notes_router, vis = "pub(crate)")]
+impl SerialHandler {
+    #[tool(description = "Attach a note to the device behind a connection, e.g. key 'wiring' with 'RX pin is flaky, reseat before blaming firmware'. Notes belong to the port, so they are shown by status and resume_summary for any later connection to it, across conversations and server restarts. An empty value removes the note")]
+    async fn set_note(&self, Parameters(args): Parameters<SetNoteArgs>) -> Result<CallToolResult, McpError> {
+        let connection = self.connection(&args.connection_id).await?;
+        let port = &connection.config().port;
+        if args.key.trim().is_empty() {
+            return Err(McpError::invalid_params("Error: Note key must not be empty", None));
+        }
+
+        self.notes.set(port, &args.key, &args.value).map_err(|e| {
+            error!("Failed to save note for {}: {}", port, e);
+            McpError::internal_error(format!("Error: Failed to save note - {}", e), None)
+        })?;
+        info!("Note {} on {} {}", args.key, port, if args.value.is_empty() { "removed" } else { "set" });
+
+        let notes = self.notes.get(port);
+        let mut message = format!("Notes for {} ({}):", port, notes.len());
+        for (key, value) in &notes {
+            message.push_str(&format!("\n- {}: {}", key, value));
+        }
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_notes_persist_per_port() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = NoteStore::load(dir.path());
+        store.set("/dev/ttyUSB0", "wiring", "flaky RX pin").unwrap();
+        store.set("/dev/ttyUSB0", "firmware", "v1.2").unwrap();
+        store.set("/dev/ttyUSB1", "owner", "bench 3").unwrap();
+        store.set("/dev/ttyUSB1", "owner", "").unwrap();
+
+        let reloaded = NoteStore::load(dir.path());
+        let notes = reloaded.get("/dev/ttyUSB0");
+        assert_eq!(notes.len(), 2);
+        assert_eq!(notes["wiring"], "flaky RX pin");
+        assert!(reloaded.get("/dev/ttyUSB1").is_empty());
+        assert_eq!(reloaded.notes.lock().unwrap().devices.len(), 1);
+    }
+
+    #[test]
+    fn test_unreadable_notes_left_untouched() {
+        let dir = tempfile::tempdir().unwrap();
+        let newer = r#"{"kind": "notes", "version": 99, "data": {"devices": {}}}"#;
+        std::fs::write(dir.path().join(NOTES_FILE), newer).unwrap();
+
+        let store = NoteStore::load(dir.path());
+        assert!(matches!(store.set("/dev/ttyUSB0", "wiring", "flaky RX pin"), Err(StateError::NotLoaded { .. })));
+        assert!(store.get("/dev/ttyUSB0").is_empty());
+        assert_eq!(std::fs::read_to_string(dir.path().join(NOTES_FILE)).unwrap(), newer);
+    }
+}
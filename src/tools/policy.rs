@@ -0,0 +1,277 @@
+//! Per-tool permission policy
+//!
+//! `[security.tool_policy]` allows or denies tools for every client, so an
+//! operator can ship a locked-down configuration for untrusted agents. Keys
+//! are tool names, categories of tools such as `flash` or `read-only`, or `*`
+//! for every tool not otherwise covered:
+//!
+//! ```toml
+//! [security.tool_policy]
+//! "*" = "deny"
+//! read-only = "allow"
+//! grbl_status = "allow"
+//! ```
+//!
+//! A tool's own entry wins over its categories, where a deny wins over an
+//! allow, and those win over `*`. Tools covered by nothing are allowed.
+
+use std::collections::BTreeMap;
+
+use crate::config::ToolPermission;
+use crate::serial::LocalSerialError;
+
+/// Policy key covering every tool without an entry of its own or through a category
+pub const ANY_TOOL: &str = "*";
+
+/// Categories of tools with the tools in each; every tool is in at least one
+pub const TOOL_CATEGORIES: &[(&str, &[&str])] = &[
+    // Tools toggling DTR or RTS, sending flow control characters or changing the baud rate
+    ("control-lines", &["esp_reset", "pause_reception", "touch_reset"]),
+    // Tools rewriting a device's firmware or erasing its flash
+    ("flash", &["avr_flash", "esp_flash", "flash_firmware", "stm32_erase", "stm32_write"]),
+    // Tools putting bytes on the wire, queries and handshakes included, or changing a
+    // device's state, also by relaying other programs' data or answering for a
+    // simulated device; named like the write tool it includes
+    (
+        "write",
+        &[
+            "broadcast_write",
+            "bridge_start",
+            "can_close",
+            "can_open",
+            "can_send",
+            "cat_get",
+            "cat_set",
+            "expect",
+            "gcode_cancel",
+            "gcode_send",
+            "grbl_jog",
+            "grbl_realtime",
+            "grbl_settings",
+            "grbl_status",
+            "marlin_status",
+            "midi_send",
+            "modbus_gateway_start",
+            "modbus_read_coils",
+            "modbus_read_holding_registers",
+            "modbus_read_input_registers",
+            "modbus_simulator_start",
+            "modbus_simulator_write",
+            "modbus_write_register",
+            "modbus_write_registers",
+            "open_profile",
+            "push_file_via_console",
+            "replay",
+            "resume_transfer",
+            "run_script",
+            "simulate_disconnect",
+            "sms_delete",
+            "sms_list",
+            "sms_read",
+            "sms_send",
+            "stm32_info",
+            "stm32_read",
+            "write",
+            "write_frame",
+            "ymodem_receive",
+            "ymodem_send",
+            "zmodem_receive",
+            "zmodem_send",
+        ],
+    ),
+    // Tools sending a device nothing but what a permitted tool set up, such as a
+    // profile's shutdown commands on close, and changing none of its state
+    (
+        "read-only",
+        &[
+            "bridge_inspect",
+            "bridge_stop",
+            "build_frame",
+            "can_read",
+            "checksum",
+            "close",
+            "connect_group",
+            "connect_session",
+            "create_session",
+            "disconnect_group",
+            "disconnect_session",
+            "discovery_status",
+            "export_session",
+            "gcode_status",
+            "get_position",
+            "group_stats",
+            "import_session",
+            "label_session",
+            "list_ports",
+            "list_sessions",
+            "midi_read",
+            "modbus_gateway_status",
+            "modbus_gateway_stop",
+            "modbus_simulator_read",
+            "modbus_simulator_stop",
+            "nmea_read",
+            "open",
+            "p1_read",
+            "parse_frame",
+            "protobuf_register",
+            "read",
+            "read_batch",
+            "read_defmt",
+            "read_frame_idle",
+            "read_frames",
+            "read_json_lines",
+            "read_lines",
+            "remove_session",
+            "resume_reception",
+            "resume_session",
+            "resume_summary",
+            "session_events",
+            "session_info",
+            "session_stats",
+            "set_framer",
+            "set_line_mode",
+            "set_note",
+            "set_receive_filters",
+            "set_session_group",
+            "snapshot",
+            "start_capture",
+            "status",
+            "stop_capture",
+            "subscribe",
+            "suspend_session",
+            "unsubscribe",
+        ],
+    ),
+];
+
+/// Tool policy as configured, by tool name, category or `*`
+#[derive(Debug, Clone, Default)]
+pub struct ToolPolicy {
+    entries: BTreeMap<String, ToolPermission>,
+}
+
+impl ToolPolicy {
+    pub fn from_config(entries: &BTreeMap<String, String>) -> Result<Self, LocalSerialError> {
+        let entries = entries
+            .iter()
+            .map(|(name, permission)| {
+                ToolPermission::parse(permission)
+                    .map(|permission| (name.clone(), permission))
+                    .map_err(|e| LocalSerialError::InvalidConfig(format!("security.tool_policy.{}: {}", name, e)))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { entries })
+    }
+
+    /// Policy denying every tool
+    pub fn deny_all() -> Self {
+        Self { entries: [(ANY_TOOL.to_string(), ToolPermission::Deny)].into_iter().collect() }
+    }
+
+    /// Whether `tool` may be called
+    pub fn permits(&self, tool: &str) -> bool {
+        self.permission(tool) != ToolPermission::Deny
+    }
+
+    fn permission(&self, tool: &str) -> ToolPermission {
+        if let Some(permission) = self.entries.get(tool) {
+            return *permission;
+        }
+        let by_category: Vec<ToolPermission> = TOOL_CATEGORIES
+            .iter()
+            .filter(|(_, tools)| tools.contains(&tool))
+            .filter_map(|(category, _)| self.entries.get(*category).copied())
+            .collect();
+        if by_category.contains(&ToolPermission::Deny) {
+            return ToolPermission::Deny;
+        }
+        if by_category.contains(&ToolPermission::Allow) {
+            return ToolPermission::Allow;
+        }
+        self.entries.get(ANY_TOOL).copied().unwrap_or(ToolPermission::Allow)
+    }
+
+    /// Entries naming neither `*`, a category nor a tool `is_tool` knows
+    pub fn unknown_names(&self, is_tool: impl Fn(&str) -> bool) -> Vec<&str> {
+        self.entries
+            .keys()
+            .map(String::as_str)
+            .filter(|name| *name != ANY_TOOL && !TOOL_CATEGORIES.iter().any(|(category, _)| category == name) && !is_tool(name))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(entries: &[(&str, &str)]) -> ToolPolicy {
+        let entries = entries.iter().map(|(name, permission)| (name.to_string(), permission.to_string())).collect();
+        ToolPolicy::from_config(&entries).unwrap()
+    }
+
+    #[test]
+    fn test_tool_policy() {
+        assert!(ToolPolicy::default().permits("esp_flash"));
+        assert!(!ToolPolicy::deny_all().permits("list_ports"));
+
+        // Locked down to reading, with one write tool let back in
+        let locked = policy(&[("*", "deny"), ("read", "allow"), ("list_ports", "allow"), ("write", "deny"), ("write_frame", "allow")]);
+        assert!(locked.permits("read"));
+        assert!(locked.permits("list_ports"));
+        assert!(locked.permits("write_frame"));
+        assert!(!locked.permits("esp_flash"));
+        assert!(!locked.permits("broadcast_write"));
+        assert!(!locked.permits("open_profile"));
+        assert!(!locked.permits("bridge_start"));
+        assert!(!locked.permits("open"));
+
+        // Categories beat the default; a denied category beats an allowed one
+        let categories = policy(&[("*", "deny"), ("write", "allow"), ("flash", "deny")]);
+        assert!(categories.permits("sms_send"));
+        assert!(!categories.permits("stm32_write"));
+        assert!(!categories.permits("status"));
+        let open = policy(&[("flash", "deny"), ("control-lines", "deny")]);
+        assert!(!open.permits("touch_reset"));
+        assert!(open.permits("write"));
+        assert!(!open.permits("pause_reception"));
+
+        // Every tool that puts bytes on the wire is a write, queries too
+        let watching = policy(&[("*", "deny"), ("read-only", "allow")]);
+        assert!(watching.permits("read_frames"));
+        assert!(watching.permits("open"));
+        assert!(!watching.permits("grbl_status"));
+        assert!(!watching.permits("sms_read"));
+        assert!(!watching.permits("pause_reception"));
+
+        assert_eq!(open.unknown_names(|_| false), Vec::<&str>::new());
+        assert_eq!(policy(&[("writ", "deny"), ("read", "allow")]).unknown_names(|tool| tool == "read"), vec!["writ"]);
+        let entries = [("write".to_string(), "block".to_string())].into_iter().collect();
+        assert!(ToolPolicy::from_config(&entries).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_every_tool_categorized() {
+        use crate::config::Config;
+        use crate::tools::serial_handler::SerialHandler;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = Config::default();
+        config.server.state_directory = dir.path().to_path_buf();
+        config.server.debug_tools = true;
+        let handler = SerialHandler::new(config);
+
+        let categories = |tool: &str| -> Vec<&str> {
+            TOOL_CATEGORIES.iter().filter(|(_, tools)| tools.contains(&tool)).map(|(category, _)| *category).collect()
+        };
+        for tool in handler.tool_router.list_all() {
+            let categories = categories(&tool.name);
+            assert!(!categories.is_empty(), "{} is in no tool category", tool.name);
+            assert!(categories == ["read-only"] || !categories.contains(&"read-only"), "{} is read-only and in {:?}", tool.name, categories);
+        }
+        // run_script is only routed with the scripting feature
+        for tool in TOOL_CATEGORIES.iter().flat_map(|(_, tools)| tools.iter()) {
+            assert!(*tool == "run_script" || handler.tool_router.has_route(tool), "unknown tool {} in a category", tool);
+        }
+    }
+}
@@ -0,0 +1,82 @@
+//! Device profiles
+//!
+//! A profile from the server configuration names the commands that bring a
+//! kind of device into a known state, such as `ATE0` on a modem or
+//! `terminal length 0` on a router console, and those that leave it safe,
+//! such as stopping a stream. The init commands run as soon as a port is
+//! opened with the profile; the shutdown commands run whenever the
+//! connection closes.
+//!
+//! A profile can also say where and how to open the device: its port, serial
+//! settings and protocol. `open_profile` then opens it by the profile's name
+//! alone.
+
+use std::future::Future;
+
+use rmcp::{
+    handler::server::tool::Parameters, model::*, tool, tool_router, ErrorData as McpError,
+};
+use tracing::debug;
+
+use super::serial_handler::SerialHandler;
+use super::types::{OpenArgs, OpenProfileArgs};
+use crate::config::ProfileConfig;
+use crate::serial::{CommandSequence, SerialConnection};
+
+impl SerialHandler {
+    /// The configured profile called `name`
+    pub(crate) fn profile_config(&self, name: &str) -> Result<&ProfileConfig, McpError> {
+        self.config.profiles.get(name).ok_or_else(|| {
+            let known: Vec<&str> = self.config.profiles.keys().map(String::as_str).collect();
+            let error_msg = if known.is_empty() {
+                format!("Error: Unknown profile {}, none are configured", name)
+            } else {
+                format!("Error: Unknown profile {}, configured profiles: {}", name, known.join(", "))
+            };
+            McpError::invalid_params(error_msg, None)
+        })
+    }
+
+    /// Tag `connection` with profile `name`, arm its shutdown commands and run its init commands
+    ///
+    /// Returns a summary for the open result, or why the init sequence stopped early.
+    pub(crate) async fn apply_profile(&self, connection: &SerialConnection, name: &str) -> Result<String, String> {
+        let profile = self.profile_config(name).map_err(|e| e.message.to_string())?;
+        connection.set_profile(name).await;
+        connection.record_event(format!("Opened with profile {}", name)).await;
+
+        let sequence = |phase, commands: &[String]| CommandSequence {
+            phase,
+            commands: commands.to_vec(),
+            line_ending: profile.line_ending.clone().unwrap_or_else(|| self.config.serial.default_line_ending.clone()),
+            settle_ms: profile.response_settle_ms,
+        };
+        if !profile.shutdown_commands.is_empty() {
+            connection.set_teardown(sequence("Shutdown", &profile.shutdown_commands)).await;
+        }
+
+        let run = sequence("Init", &profile.init_commands).run(connection).await?;
+        let mut summary = format!("Profile: {} ({} init command(s) run", name, run);
+        if !profile.shutdown_commands.is_empty() {
+            summary.push_str(&format!(", {} shutdown command(s) run on close", profile.shutdown_commands.len()));
+        }
+        summary.push(')');
+        Ok(summary)
+    }
+}
+
+#[tool_router(router = profiles_router, vis = "pub(crate)")]
+impl SerialHandler {
+    #[tool(description = "Open a port by the name of a device profile from the server configuration, e.g. 'gps', with the profile's port, serial settings, protocol and init commands instead of passing them to open every time. port and baud_rate override the profile's")]
+    async fn open_profile(&self, Parameters(args): Parameters<OpenProfileArgs>) -> Result<CallToolResult, McpError> {
+        let profile = self.profile_config(&args.profile)?;
+        let Some(port) = args.port.or_else(|| profile.port.clone()) else {
+            let error_msg = format!("Error: Profile {} has no port configured, pass one with port", args.profile);
+            return Err(McpError::invalid_params(error_msg, None));
+        };
+        let baud_rate = args.baud_rate.or(profile.baud_rate).unwrap_or(self.config.serial.default_baud_rate);
+        debug!("Opening {} with profile {}", port, args.profile);
+
+        self.open(Parameters(OpenArgs::from_profile(&args.profile, profile, port, baud_rate))).await
+    }
+}
@@ -0,0 +1,57 @@
+//! Protocol Buffers tools
+//!
+//! Register message types from compiled descriptor sets at run time, for
+//! read_frames to decode payloads with.
+
+use std::future::Future;
+
+use rmcp::{
+    handler::server::tool::Parameters,
+    model::*,
+    tool, tool_router, ErrorData as McpError,
+};
+use tracing::info;
+
+use super::serial_handler::SerialHandler;
+use super::types::*;
+
+/// Largest descriptor set file accepted
+const MAX_DESCRIPTOR_SET_BYTES: u64 = 16 * 1024 * 1024;
+
+#[tool_router(router = protobuf_router, vis = "pub(crate)")]
+impl SerialHandler {
+    #[tool(description = "Register Protocol Buffers message types from a compiled FileDescriptorSet (protoc --include_imports --descriptor_set_out=schema.desc), given as a file path or base64 data. read_frames can then decode payloads by message name with protobuf_message; the varint framer splits length-delimited streams. Registrations last until the server exits")]
+    async fn protobuf_register(&self, Parameters(args): Parameters<ProtobufRegisterArgs>) -> Result<CallToolResult, McpError> {
+        let (descriptor_set, source) = match (&args.path, &args.data) {
+            (Some(path), None) => (read_descriptor_set(path)?, path.clone()),
+            (None, Some(data)) => {
+                let bytes = decode_data(data, "base64").map_err(|e| McpError::invalid_params(format!("Error: {}", e), None))?;
+                (bytes, "data".to_string())
+            }
+            _ => return Err(McpError::invalid_params("Error: Give either path or data, not both".to_string(), None)),
+        };
+
+        let mut registry = self.protobuf.lock().await;
+        let added = registry
+            .register(&descriptor_set)
+            .map_err(|e| McpError::invalid_params(format!("Error: {} - {}", source, e), None))?;
+        info!("Registered {} protobuf message types from {}", added.len(), source);
+
+        let message = format!(
+            "Protobuf descriptors registered\nSource: {}\nNew message types: {}\nAll message types:\n{}",
+            source,
+            added.len(),
+            registry.message_names().join("\n")
+        );
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+}
+
+fn read_descriptor_set(path: &str) -> Result<Vec<u8>, McpError> {
+    let metadata = std::fs::metadata(path).map_err(|e| McpError::invalid_params(format!("Error: Cannot access {} - {}", path, e), None))?;
+    if metadata.len() > MAX_DESCRIPTOR_SET_BYTES {
+        let error_msg = format!("Error: {} is {} bytes, descriptor sets are limited to {} bytes", path, metadata.len(), MAX_DESCRIPTOR_SET_BYTES);
+        return Err(McpError::invalid_params(error_msg, None));
+    }
+    std::fs::read(path).map_err(|e| McpError::internal_error(format!("Error: Failed to read {} - {}", path, e), None))
+}
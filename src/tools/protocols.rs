@@ -0,0 +1,82 @@
+//! Configured protocols
+//!
+//! A protocol from the server configuration names how a kind of device frames
+//! its data, line by line or through a framer with an optional checksum, and
+//! the encoding its reads and writes default to. Teams keep their device
+//! protocols in one shared configuration instead of repeating set_framer
+//! options in every session.
+
+use rmcp::ErrorData as McpError;
+use tracing::warn;
+
+use super::serial_handler::SerialHandler;
+use crate::config::{ProtocolConfig, ProtocolFraming};
+use crate::serial::SerialConnection;
+use crate::session::SessionConfig;
+
+impl SerialHandler {
+    /// The configured protocol called `name`
+    pub(crate) fn protocol_config(&self, name: &str) -> Result<&ProtocolConfig, McpError> {
+        self.config.protocols.get(name).ok_or_else(|| {
+            let known: Vec<&str> = self.config.protocols.keys().map(String::as_str).collect();
+            let error_msg = if known.is_empty() {
+                format!("Error: Unknown protocol {}, none are configured", name)
+            } else {
+                format!("Error: Unknown protocol {}, configured protocols: {}", name, known.join(", "))
+            };
+            McpError::invalid_params(error_msg, None)
+        })
+    }
+
+    /// Set up `connection` for protocol `name`, returning a summary for the open result
+    pub(crate) async fn apply_protocol(&self, connection: &SerialConnection, name: &str) -> Result<String, String> {
+        let protocol = self.protocol_config(name).map_err(|e| e.message.to_string())?;
+        let framing = match protocol.build_framing()? {
+            ProtocolFraming::Plain => "plain bytes".to_string(),
+            ProtocolFraming::Lines => {
+                connection.set_line_mode(true).await;
+                "lines".to_string()
+            }
+            ProtocolFraming::Framer(framer, description) => {
+                connection.set_framer(Some(framer)).await;
+                self.framer_specs.lock().await.remove(connection.id());
+                description
+            }
+        };
+        connection.set_protocol(name, protocol.encoding.clone()).await;
+        connection.record_event(format!("Opened with protocol {}", name)).await;
+        self.save_session_framing(connection).await;
+
+        let mut summary = format!("Protocol: {} ({}", name, framing);
+        if let Some(encoding) = &protocol.encoding {
+            summary.push_str(&format!(", {} encoding", encoding));
+        }
+        summary.push(')');
+        Ok(summary)
+    }
+
+    /// Keep a connection's protocol and framer with its session, if it has one
+    pub(crate) async fn save_session_framing(&self, connection: &SerialConnection) {
+        let framer = self.framer_specs.lock().await.get(connection.id()).cloned();
+        self.sessions.set_connection_framing(connection.id(), connection.protocol().await, framer).await;
+    }
+
+    /// Set a session's saved protocol and framer up on the connection it just got
+    pub(crate) async fn apply_session_framing(&self, connection: &SerialConnection, config: &SessionConfig) {
+        if let Some(name) = &config.protocol {
+            if let Err(e) = self.apply_protocol(connection, name).await {
+                warn!("Protocol {} not set up on connection {}: {}", name, connection.id(), e);
+            }
+        }
+        if let Some(spec) = &config.framer {
+            match spec.build() {
+                Ok((framer, _)) => {
+                    connection.set_framer(Some(framer)).await;
+                    self.framer_specs.lock().await.insert(connection.id().to_string(), spec.clone());
+                    self.save_session_framing(connection).await;
+                }
+                Err(e) => warn!("Framer {} not set up on connection {}: {}", spec.framer, connection.id(), e),
+            }
+        }
+    }
+}
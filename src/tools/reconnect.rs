@@ -0,0 +1,273 @@
+//! Automatic reconnection
+//!
+//! Sessions created with auto_reconnect are watched for a connection whose
+//! reader stopped, as when the device is unplugged or resets its USB
+//! interface. The broken handle is dropped and the port opened again under
+//! the same connection ID, so tools keep working, waiting twice as long
+//! between each attempt up to the session's limit. A USB adapter that comes
+//! back under another path is found again by its vendor, product and serial
+//! number. A session with failover ports tries the ports after the lost one
+//! first, in order, and the lost one last. The new connection gets the failed
+//! one's profile, protocol, framer, line mode and capture back, and the client is told
+//! how the reconnect ended.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use rmcp::{
+    model::{LoggingLevel, LoggingMessageNotificationParam},
+    Peer, RoleServer,
+};
+use tracing::{debug, info, warn};
+
+use crate::protocol::FramerSpec;
+use super::serial_handler::SerialHandler;
+use crate::serial::{CaptureFormat, ConnectionConfig, DeviceIdentity, SerialConnection};
+
+/// How often sessions are checked for failed connections
+const SUPERVISE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Longest wait between reconnection attempts
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// What a connection had set up, to restore on the one replacing it
+#[derive(Debug)]
+pub(crate) struct ConnectionState {
+    pub(crate) config: ConnectionConfig,
+    pub(crate) profile: Option<String>,
+    protocol: Option<String>,
+    framer: Option<FramerSpec>,
+    line_mode: bool,
+    capturing: bool,
+}
+
+impl ConnectionState {
+    pub(crate) async fn of(handler: &SerialHandler, connection: &SerialConnection) -> Self {
+        Self {
+            config: connection.config().clone(),
+            profile: connection.profile().await,
+            protocol: connection.protocol().await,
+            framer: handler.framer_specs.lock().await.get(connection.id()).cloned(),
+            line_mode: connection.is_line_mode().await,
+            capturing: connection.capture_path().await.is_some(),
+        }
+    }
+}
+
+/// Reconnect sessions whose connection failed, for as long as the server runs
+pub async fn run_reconnect_supervisor(peer: Peer<RoleServer>, handler: SerialHandler) {
+    let mut interval = tokio::time::interval(SUPERVISE_INTERVAL);
+    loop {
+        interval.tick().await;
+        for (session_id, connection, reason) in handler.sessions.failed_connections().await {
+            let state = ConnectionState::of(&handler, &connection).await;
+            let connection_id = connection.id().to_string();
+            if handler.sessions.begin_reconnect(&session_id, reason.clone()).await.is_err() {
+                continue;
+            }
+
+            // The old handle's port closes once nothing holds it any more
+            if let Err(e) = connection.stop_capture().await {
+                warn!("Capture of connection {} not closed: {}", connection_id, e);
+            }
+            drop(connection);
+            let _ = handler.connection_manager.discard(&connection_id).await;
+            if let Some(subscription) = handler.subscriptions.lock().await.remove(&connection_id) {
+                subscription.abort();
+            }
+
+            let message = format!(
+                "Session {} lost connection {} on {}: {}; reconnecting",
+                session_id, connection_id, state.config.port, reason
+            );
+            notify(&peer, LoggingLevel::Warning, message).await;
+            tokio::spawn(reconnect(peer.clone(), handler.clone(), session_id, connection_id, state));
+        }
+    }
+}
+
+/// Open the port again until it works or the session's attempts are used up
+async fn reconnect(peer: Peer<RoleServer>, handler: SerialHandler, session_id: String, connection_id: String, state: ConnectionState) {
+    let base = Duration::from_millis(handler.config.serial.retry_delay_ms);
+    let info = handler.sessions.get_session_info(&session_id).await.ok();
+    let device = info.as_ref().and_then(|info| info.device.clone());
+    let lost = state.config.port.clone();
+    let mut ports = match &info {
+        Some(info) => failover_order(info.config.candidate_ports(), &lost),
+        None => vec![lost.clone()],
+    };
+    let mut moved = None;
+    let mut last_error = "no attempts allowed".to_string();
+
+    while let Some(attempt) = handler.sessions.next_reconnect_attempt(&session_id).await {
+        tokio::time::sleep(backoff(base, attempt)).await;
+        if let Some(device) = &device {
+            if let Some(path) = locate_device(device).await.filter(|path| !ports.contains(path)) {
+                let from = ports.last().cloned().unwrap_or_default();
+                if let Err(e) = handler.sessions.relocate(&session_id, &from, &path).await {
+                    debug!("Session {} not moved to {}: {}", session_id, path, e);
+                    last_error = e.to_string();
+                    continue;
+                }
+                moved = Some(format!("{} came back as {}", device, path));
+                if let Some(port) = ports.last_mut() {
+                    *port = path;
+                }
+            }
+        }
+        let mut reopened = None;
+        for port in &ports {
+            let config = ConnectionConfig { port: port.clone(), ..state.config.clone() };
+            match handler.connection_manager.reopen(&connection_id, config).await {
+                Ok(connection) => {
+                    reopened = Some((connection, port.clone()));
+                    break;
+                }
+                Err(e) => {
+                    debug!("Reconnect attempt {} of session {} to {} failed: {}", attempt, session_id, port, e);
+                    handler.sessions.record_session_event(&session_id, format!("Reconnect attempt {} to {} failed: {}", attempt, port, e)).await;
+                    last_error = if ports.len() > 1 { format!("{}: {}", port, e) } else { e.to_string() };
+                }
+            }
+        }
+        let Some((connection, port)) = reopened else {
+            continue;
+        };
+
+        let restored = handler.restore_connection_state(&connection, &state).await;
+        if handler.sessions.reattach(&session_id, Arc::clone(&connection)).await.is_err() {
+            // Removed while reconnecting
+            let _ = handler.connection_manager.close(&connection_id).await;
+            return;
+        }
+        let mut details = String::new();
+        if let Some(moved) = &moved {
+            details.push_str(&format!("; {}", moved));
+        }
+        if port != lost && Some(&port) != ports.last() {
+            details.push_str(&format!("; failed over from {}", lost));
+        }
+        if !restored.is_empty() {
+            details.push_str(&format!("; restored {}", restored.join(", ")));
+        }
+        connection.record_event(format!("Reconnected after {} attempt(s){}", attempt, details)).await;
+        let message = format!(
+            "Session {} reconnected to {} as connection {} after {} attempt(s){}",
+            session_id, port, connection_id, attempt, details
+        );
+        notify(&peer, LoggingLevel::Notice, message).await;
+        return;
+    }
+
+    if handler.sessions.fail_reconnect(&session_id, format!("Reconnect failed: {}", last_error)).await {
+        let message = format!(
+            "Session {} could not reconnect to {}: {}; connect it again with connect_session",
+            session_id, lost, last_error
+        );
+        notify(&peer, LoggingLevel::Error, message).await;
+    }
+}
+
+/// Where a device is plugged in now, looked up off the async runtime
+async fn locate_device(device: &DeviceIdentity) -> Option<String> {
+    let device = device.clone();
+    tokio::task::spawn_blocking(move || device.locate()).await.ok().flatten()
+}
+
+/// Ports to try after losing `lost`: those after it in order, then those before it, then `lost` itself
+fn failover_order(mut ports: Vec<String>, lost: &str) -> Vec<String> {
+    match ports.iter().position(|port| port == lost) {
+        Some(index) => ports.rotate_left(index + 1),
+        None => ports.push(lost.to_string()),
+    }
+    ports
+}
+
+/// Wait before reconnection attempt `attempt`, doubling from `base` for each one before it
+fn backoff(base: Duration, attempt: u32) -> Duration {
+    base.saturating_mul(1 << attempt.saturating_sub(1).min(16)).min(MAX_RECONNECT_DELAY)
+}
+
+impl SerialHandler {
+    /// Set a reopened connection up as the one it replaces was, returning what was restored
+    pub(crate) async fn restore_connection_state(&self, connection: &SerialConnection, state: &ConnectionState) -> Vec<String> {
+        let mut restored = Vec::new();
+        if let Some(name) = &state.profile {
+            // The profile's init commands are sent again as open_profile sends them
+            let applied = match self.check_tool_permitted("open_profile").await {
+                Ok(()) => self.apply_profile(connection, name).await,
+                Err(e) => Err(e.message.to_string()),
+            };
+            match applied {
+                Ok(_) => restored.push(format!("profile {}", name)),
+                Err(e) => warn!("Profile {} not restored on connection {}: {}", name, connection.id(), e),
+            }
+        }
+        if let Some(name) = &state.protocol {
+            match self.apply_protocol(connection, name).await {
+                Ok(_) => restored.push(format!("protocol {}", name)),
+                Err(e) => warn!("Protocol {} not restored on connection {}: {}", name, connection.id(), e),
+            }
+        }
+        if let Some(spec) = &state.framer {
+            match spec.build() {
+                Ok((framer, name)) => {
+                    connection.set_framer(Some(framer)).await;
+                    self.framer_specs.lock().await.insert(connection.id().to_string(), spec.clone());
+                    restored.push(format!("framer {}", name));
+                }
+                Err(e) => warn!("Framer {} not restored on connection {}: {}", spec.framer, connection.id(), e),
+            }
+        }
+        if state.line_mode && !connection.is_line_mode().await {
+            connection.set_line_mode(true).await;
+            restored.push("line mode".to_string());
+        }
+        if state.capturing {
+            let format = CaptureFormat::parse(&self.config.capture.format).unwrap_or(CaptureFormat::Jsonl);
+            match self.start_connection_capture(connection, None, format).await {
+                Ok(path) => restored.push(format!("capture to {}", path.display())),
+                Err(e) => warn!("Capture not restored on connection {}: {}", connection.id(), e.message),
+            }
+        }
+        restored
+    }
+}
+
+/// Log a reconnect outcome and forward it to the client as a logging notification
+async fn notify(peer: &Peer<RoleServer>, level: LoggingLevel, message: String) {
+    info!("{}", message);
+    let param = LoggingMessageNotificationParam {
+        level,
+        logger: Some("reconnect".to_string()),
+        data: serde_json::Value::String(message),
+    };
+    if let Err(e) = peer.notify_logging_message(param).await {
+        warn!("Failed to send reconnect notification: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        let base = Duration::from_millis(500);
+        assert_eq!(backoff(base, 1), Duration::from_millis(500));
+        assert_eq!(backoff(base, 2), Duration::from_secs(1));
+        assert_eq!(backoff(base, 4), Duration::from_secs(4));
+        assert_eq!(backoff(base, 10), MAX_RECONNECT_DELAY);
+        assert_eq!(backoff(base, u32::MAX), MAX_RECONNECT_DELAY);
+    }
+
+    #[test]
+    fn test_failover_order() {
+        let ports = || vec!["A".to_string(), "B".to_string(), "C".to_string()];
+        assert_eq!(failover_order(ports(), "A"), ["B", "C", "A"]);
+        assert_eq!(failover_order(ports(), "B"), ["C", "A", "B"]);
+        assert_eq!(failover_order(vec!["A".to_string()], "A"), ["A"]);
+        // Lost a port the session has since stopped listing
+        assert_eq!(failover_order(ports(), "D"), ["A", "B", "C", "D"]);
+    }
+}
@@ -0,0 +1,227 @@
+//! Device scripting
+//!
+//! Multi-step dialogues such as logging in, configuring and checking the
+//! result take a round trip per step when driven one tool call at a time.
+//! `run_script` runs them server-side as a Rhai script instead, with
+//! primitives to write, read, wait for text and sleep bound to one connection.
+//! Scripts run on a blocking thread and have no access to files or other
+//! connections.
+
+use std::cell::RefCell;
+use std::future::Future;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rhai::{module_resolvers::DummyModuleResolver, Dynamic, Engine, EvalAltResult};
+use rmcp::{
+    handler::server::tool::Parameters,
+    model::*,
+    tool, tool_router, ErrorData as McpError,
+};
+use serde::Serialize;
+use tokio::runtime::Handle;
+use tracing::{debug, error};
+
+use super::serial_handler::SerialHandler;
+use super::types::*;
+use super::warnings::Warnings;
+use crate::protocol::expect::Expecter;
+use crate::serial::SerialConnection;
+
+/// Longest timeout a script may be given
+const MAX_SCRIPT_TIMEOUT_MS: u64 = 600_000;
+
+/// Transcript events kept; later ones are counted but not listed
+const MAX_SCRIPT_EVENTS: usize = 1000;
+
+/// Longest string a script may build
+const MAX_SCRIPT_STRING: usize = 1024 * 1024;
+
+#[tool_router(router = script_router, vis = "pub(crate)")]
+impl SerialHandler {
+    #[tool(description = "Run a Rhai script against a connection in raw mode, for multi-step dialogues such as logging in, configuring and verifying in one call. Functions: write(text) sends text and returns the bytes written; read(timeout_ms) returns text received, waiting up to timeout_ms for some to arrive, or an empty string; expect(pattern, timeout_ms) waits for the text pattern and returns true, or false on timeout, and later reads continue after the match; sleep(ms) pauses; print(value) adds to the transcript. Fail the script with throw \"reason\". The whole script, waits included, stops after timeout_ms. Returns the script's final value and a transcript of what was sent, received and printed")]
+    async fn run_script(&self, Parameters(args): Parameters<RunScriptArgs>) -> Result<CallToolResult, McpError> {
+        if args.timeout_ms == 0 || args.timeout_ms > MAX_SCRIPT_TIMEOUT_MS {
+            let error_msg = format!("Error: timeout_ms must be 1 to {}", MAX_SCRIPT_TIMEOUT_MS);
+            return Err(McpError::invalid_params(error_msg, None));
+        }
+        let connection = self.raw_connection(&args.connection_id, "run a script").await?;
+        debug!("Running a {} byte script on connection {}", args.script.len(), args.connection_id);
+
+        // Other tools' exchanges wait until the script is done
+        let _exchange = connection.begin_exchange().await;
+        let runtime = Handle::current();
+        let timeout = Duration::from_millis(args.timeout_ms);
+        let script_connection = Arc::clone(&connection);
+        let run = tokio::task::spawn_blocking(move || run_script(script_connection, runtime, &args.script, timeout))
+            .await
+            .map_err(|e| McpError::internal_error(format!("Error: Script thread failed - {}", e), None))?;
+
+        let transcript = serde_json::to_string_pretty(&run.transcript).unwrap_or_default();
+        let value = match run.result {
+            Ok(value) => value,
+            Err(e) => {
+                error!("Script on connection {} failed: {}", args.connection_id, e);
+                let error_msg = format!("Error: Script failed after {}ms - {}\nTranscript:\n{}", run.elapsed_ms, e, transcript);
+                return Err(McpError::internal_error(error_msg, None));
+            }
+        };
+
+        let mut warnings = Warnings::new();
+        warnings.dropped_bytes(connection.take_new_drops().await);
+        if run.events_dropped > 0 {
+            warnings.push(format!("{} transcript events after the first {} were not kept", run.events_dropped, MAX_SCRIPT_EVENTS));
+        }
+        let message = format!(
+            "Script finished\nConnection ID: {}\nElapsed: {}ms\nResult: {}\nTranscript:\n{}",
+            args.connection_id,
+            run.elapsed_ms,
+            value.as_deref().unwrap_or("(none)"),
+            transcript
+        );
+        warnings.into_result(message)
+    }
+}
+
+/// Something a script did or saw, `at_ms` after it started
+#[derive(Debug, Clone, Serialize)]
+struct ScriptEvent {
+    at_ms: u64,
+    event: &'static str,
+    text: String,
+}
+
+/// The outcome of a script run
+struct ScriptRun {
+    /// The final value, `None` if it was `()`
+    result: Result<Option<String>, String>,
+    elapsed_ms: u64,
+    transcript: Vec<ScriptEvent>,
+    events_dropped: usize,
+}
+
+/// State shared by the functions a script calls
+struct ScriptSession {
+    connection: Arc<SerialConnection>,
+    runtime: Handle,
+    expecter: Expecter,
+    started: Instant,
+    deadline: Instant,
+    transcript: Vec<ScriptEvent>,
+    events_dropped: usize,
+}
+
+impl ScriptSession {
+    fn record(&mut self, event: &'static str, text: impl Into<String>) {
+        if self.transcript.len() < MAX_SCRIPT_EVENTS {
+            let at_ms = self.started.elapsed().as_millis() as u64;
+            self.transcript.push(ScriptEvent { at_ms, event, text: text.into() });
+        } else {
+            self.events_dropped += 1;
+        }
+    }
+
+    /// `requested` milliseconds, cut short by the script's deadline
+    fn wait_ms(&self, requested: i64) -> Result<u64, Box<EvalAltResult>> {
+        let requested = u64::try_from(requested).map_err(|_| format!("Timeout {} is negative", requested))?;
+        let remaining = self.deadline.saturating_duration_since(Instant::now()).as_millis() as u64;
+        Ok(requested.min(remaining))
+    }
+}
+
+/// Run `script` on the current (blocking) thread, calling into `runtime` for serial I/O
+fn run_script(connection: Arc<SerialConnection>, runtime: Handle, script: &str, timeout: Duration) -> ScriptRun {
+    let started = Instant::now();
+    let deadline = started + timeout;
+    let session = Rc::new(RefCell::new(ScriptSession {
+        connection,
+        runtime,
+        expecter: Expecter::new(),
+        started,
+        deadline,
+        transcript: Vec::new(),
+        events_dropped: 0,
+    }));
+
+    let engine = script_engine(&session, deadline);
+    let result = match engine.eval::<Dynamic>(script) {
+        Ok(value) if value.is_unit() => Ok(None),
+        Ok(value) => Ok(Some(value.to_string())),
+        Err(e) if matches!(*e, EvalAltResult::ErrorTerminated(..)) => Err(format!("Script ran past its {}ms timeout", timeout.as_millis())),
+        Err(e) => Err(e.to_string()),
+    };
+    drop(engine);
+
+    let session = Rc::into_inner(session).expect("engine dropped").into_inner();
+    ScriptRun {
+        result,
+        elapsed_ms: started.elapsed().as_millis() as u64,
+        transcript: session.transcript,
+        events_dropped: session.events_dropped,
+    }
+}
+
+/// An engine with the connection's primitives and no access to the file system
+fn script_engine(session: &Rc<RefCell<ScriptSession>>, deadline: Instant) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_module_resolver(DummyModuleResolver::new());
+    engine.disable_symbol("eval");
+    engine.set_max_string_size(MAX_SCRIPT_STRING);
+    engine.on_progress(move |_| (Instant::now() >= deadline).then_some(Dynamic::UNIT));
+
+    let printer = Rc::clone(session);
+    engine.on_print(move |text| printer.borrow_mut().record("print", text));
+    let debugger = Rc::clone(session);
+    engine.on_debug(move |text, _, _| debugger.borrow_mut().record("print", text));
+
+    let writer = Rc::clone(session);
+    engine.register_fn("write", move |text: &str| -> Result<i64, Box<EvalAltResult>> {
+        let mut session = writer.borrow_mut();
+        let written = session
+            .runtime
+            .block_on(session.connection.write(text.as_bytes()))
+            .map_err(|e| format!("Write failed - {}", e))?;
+        session.record("write", text);
+        Ok(written as i64)
+    });
+
+    let reader = Rc::clone(session);
+    engine.register_fn("read", move |timeout_ms: i64| -> Result<String, Box<EvalAltResult>> {
+        let mut session = reader.borrow_mut();
+        let wait = session.wait_ms(timeout_ms)?;
+        let ScriptSession { connection, runtime, expecter, .. } = &mut *session;
+        let text = runtime.block_on(expecter.read(connection, wait)).map_err(|e| format!("Read failed - {}", e))?;
+        session.record("read", text.clone());
+        Ok(text)
+    });
+
+    let expecter = Rc::clone(session);
+    engine.register_fn("expect", move |pattern: &str, timeout_ms: i64| -> Result<bool, Box<EvalAltResult>> {
+        let mut session = expecter.borrow_mut();
+        let wait = session.wait_ms(timeout_ms)?;
+        let ScriptSession { connection, runtime, expecter, .. } = &mut *session;
+        let matched = runtime.block_on(expecter.expect(connection, pattern, wait)).map_err(|e| format!("Read failed - {}", e))?;
+        match matched {
+            Some(text) => {
+                session.record("expect", text);
+                Ok(true)
+            }
+            None => {
+                let pending = session.expecter.pending().to_string();
+                session.record("expect_timeout", format!("{:?} not seen, received {:?}", pattern, pending));
+                Ok(false)
+            }
+        }
+    });
+
+    let sleeper = Rc::clone(session);
+    engine.register_fn("sleep", move |ms: i64| -> Result<(), Box<EvalAltResult>> {
+        let session = sleeper.borrow();
+        let wait = session.wait_ms(ms)?;
+        session.runtime.block_on(tokio::time::sleep(Duration::from_millis(wait)));
+        Ok(())
+    });
+
+    engine
+}
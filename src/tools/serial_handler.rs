@@ -2,38 +2,476 @@
 //! 
 //! This implementation follows the official rust-sdk patterns for proper tool registration
 
+use std::collections::HashMap;
 use std::sync::Arc;
 use std::future::Future;
 use rmcp::{
-    tool, tool_handler, tool_router, ServerHandler,
+    tool, tool_router, ServerHandler,
     handler::server::{router::tool::ToolRouter, tool::Parameters},
     model::*,
     ErrorData as McpError,
     service::RequestContext,
     RoleServer,
 };
-use tracing::{debug, error, info};
+use chrono::{DateTime, Utc};
+use tracing::{debug, error, info, warn};
 
-use crate::serial::{PortInfo, ConnectionManager};
-use crate::config::Config;
+use crate::serial::{
+    Bridge, CaptureFormat, ConnectionConfig, ConnectionHistory, ConnectionManager, LocalSerialError, PauseSignal, PortInfo, PortLockRegistry, RateLimited,
+    ReceivedLine, SerialConnection, WriteAudit, WriteRule, WriteTurn,
+};
+use crate::config::{Config, OrphanPolicy};
+use crate::protocol::gcode::GcodeJob;
+use crate::protocol::midi::MidiParser;
+use crate::protocol::modbus_gateway::ModbusGateway;
+use crate::protocol::modbus_slave::ModbusSimulator;
+use crate::protocol::protobuf::ProtobufRegistry;
+use crate::session::{SessionConfig, SessionManager};
+use crate::utils::{DataConverter, PortType};
+use super::auth::{authenticate, ClientAccess};
+use super::defmt::DefmtSession;
+use crate::protocol::FramerSpec;
+use super::notes::NoteStore;
+use super::policy::ToolPolicy;
+use super::transfer::TransferStore;
+use super::warnings::Warnings;
+use super::discovery::{run_discovery, AdaptiveInterval, DiscoveryStatus};
+use super::expiry::run_session_expiry;
+use super::idle::IdleMode;
+use super::keepalive::run_keepalive;
+use super::liveness::{close_client_connections, run_heartbeat, ClientLiveness};
+use super::maintenance::{run_maintenance, MaintenanceSchedule, MaintenanceState};
+use super::reconnect::run_reconnect_supervisor;
+use super::session::SuspendedConnection;
 use super::types::*;
+use super::watchdog::run_safety_watchdog;
 
 /// Serial tool handler using rust-sdk standard patterns
 #[derive(Clone)]
 pub struct SerialHandler {
-    connection_manager: Arc<ConnectionManager>,
-    #[allow(dead_code)]
-    config: Config,
-    tool_router: ToolRouter<SerialHandler>,
+    pub(crate) connection_manager: Arc<ConnectionManager>,
+    /// Sessions tracking the open connections, with their limits and statistics
+    pub(crate) sessions: Arc<SessionManager>,
+    pub(crate) config: Config,
+    liveness: Arc<ClientLiveness>,
+    maintenance: Arc<MaintenanceState>,
+    idle: Arc<IdleMode>,
+    pub(crate) discovery: Arc<tokio::sync::Mutex<DiscoveryStatus>>,
+    /// Running data subscriptions by connection ID
+    pub(crate) subscriptions: Arc<tokio::sync::Mutex<HashMap<String, tokio::task::AbortHandle>>>,
+    /// Running bridge taps by bridge ID
+    pub(crate) bridges: Arc<tokio::sync::Mutex<HashMap<String, Bridge>>>,
+    pub(crate) modbus_simulators: Arc<tokio::sync::Mutex<HashMap<String, ModbusSimulator>>>,
+    pub(crate) modbus_gateways: Arc<tokio::sync::Mutex<HashMap<String, ModbusGateway>>>,
+    /// G-code jobs by job ID, kept after finishing until cancelled
+    pub(crate) gcode_jobs: Arc<tokio::sync::Mutex<HashMap<String, GcodeJob>>>,
+    /// MIDI decoding state by connection ID, so messages can span reads
+    pub(crate) midi_parsers: Arc<tokio::sync::Mutex<HashMap<String, MidiParser>>>,
+    /// Framers set with set_framer by connection ID, to set up again on a reopened connection
+    pub(crate) framer_specs: Arc<tokio::sync::Mutex<HashMap<String, FramerSpec>>>,
+    /// defmt decoders by connection ID, with the ELF each was loaded from
+    pub(crate) defmt_sessions: Arc<tokio::sync::Mutex<HashMap<String, DefmtSession>>>,
+    /// Protocol Buffers message types registered for read_frames
+    pub(crate) protobuf: Arc<tokio::sync::Mutex<ProtobufRegistry>>,
+    pub(crate) notes: Arc<NoteStore>,
+    pub(crate) transfers: Arc<TransferStore>,
+    /// Connections of suspended sessions by session ID, to set up again on resume
+    pub(crate) suspended: Arc<tokio::sync::Mutex<HashMap<String, SuspendedConnection>>>,
+    /// Connection history of imported sessions by session ID, carried on once connected
+    pub(crate) imported: Arc<tokio::sync::Mutex<HashMap<String, ConnectionHistory>>>,
+    /// Tools the client may call, settled on initialize
+    access: Arc<tokio::sync::RwLock<ClientAccess>>,
+    /// Tools allowed or denied for every client
+    tool_policy: Arc<ToolPolicy>,
+    pub(crate) tool_router: ToolRouter<SerialHandler>,
 }
 
 #[tool_router]
 impl SerialHandler {
     pub fn new(config: Config) -> Self {
+        let connection_manager = if config.server.port_locking {
+            let instance = match config.server.instance_name.as_str() {
+                "" => format!("serial-mcp-{}", &uuid::Uuid::new_v4().simple().to_string()[..8]),
+                name => name.to_string(),
+            };
+            ConnectionManager::with_port_locks(PortLockRegistry::new(&config.server.lock_directory, instance))
+        } else {
+            ConnectionManager::new()
+        }
+        .with_port_sharing(config.serial.allow_port_sharing)
+        .with_exclusive_access(config.serial.exclusive_access)
+        .with_write_rules(
+            config
+                .security
+                .write_rules
+                .iter()
+                // Validated with the configuration; fall back to the safe choice regardless
+                .map(|rule| {
+                    rule.rule().unwrap_or_else(|e| {
+                        error!("{}; refusing every write to its ports", e);
+                        WriteRule::deny_all(&rule.port)
+                    })
+                })
+                .collect(),
+        )
+        .with_write_audit(WriteAudit::new(&config.server.state_directory));
+        
+        let mut tool_router = Self::tool_router()
+            + Self::avr_router()
+            + Self::bridge_router()
+            + Self::capture_router()
+            + Self::cat_router()
+            + Self::checksum_router()
+            + Self::console_router()
+            + Self::defmt_router()
+            + Self::diagnostics_router()
+            + Self::discovery_router()
+            + Self::dsmr_router()
+            + Self::esp_router()
+            + Self::expect_router()
+            + Self::firmware_router()
+            + Self::frame_spec_router()
+            + Self::framing_router()
+            + Self::gcode_router()
+            + Self::grbl_router()
+            + Self::group_router()
+            + Self::jsonl_router()
+            + Self::marlin_router()
+            + Self::midi_router()
+            + Self::modbus_router()
+            + Self::nmea_router()
+            + Self::notes_router()
+            + Self::profiles_router()
+            + Self::protobuf_router()
+            + Self::session_router()
+            + Self::slcan_router()
+            + Self::sms_router()
+            + Self::stm32_router()
+            + Self::subscription_router()
+            + Self::ymodem_router()
+            + Self::zmodem_router();
+        #[cfg(feature = "scripting")]
+        {
+            tool_router += Self::script_router();
+        }
+        if config.server.debug_tools {
+            tool_router += Self::chaos_router();
+        }
+        // Validated with the configuration; fall back to the safe choice regardless
+        let tool_policy = ToolPolicy::from_config(&config.security.tool_policy).unwrap_or_else(|e| {
+            error!("{}; denying every tool", e);
+            ToolPolicy::deny_all()
+        });
+        for name in tool_policy.unknown_names(|tool| tool_router.has_route(tool)) {
+            warn!("security.tool_policy names unknown tool or category {}", name);
+        }
+        for (client, tools) in &config.security.client_tools {
+            for tool in tools.iter().filter(|tool| !tool_router.has_route(tool)) {
+                warn!("security.client_tools.{} names unknown tool {}", client, tool);
+            }
+        }
+        
+        let idle_after = match config.server.idle_after_seconds {
+            0 => None,
+            seconds => Some(std::time::Duration::from_secs(seconds)),
+        };
+        
+        let notes = Arc::new(NoteStore::load(&config.server.state_directory));
+        let transfers = Arc::new(TransferStore::load(&config.server.state_directory));
+        let connection_manager = Arc::new(connection_manager);
+        let sessions = SessionManager::new(config.clone())
+            .with_connection_manager(Arc::clone(&connection_manager))
+            .with_persistence(&config.server.state_directory);
+        
         Self {
-            connection_manager: Arc::new(ConnectionManager::new()),
+            connection_manager,
+            sessions: Arc::new(sessions),
             config,
-            tool_router: Self::tool_router(),
+            idle: Arc::new(IdleMode::new(idle_after)),
+            liveness: Arc::new(ClientLiveness::new()),
+            maintenance: Arc::new(MaintenanceState::new()),
+            discovery: Arc::new(tokio::sync::Mutex::new(DiscoveryStatus::disabled())),
+            subscriptions: Arc::new(tokio::sync::Mutex::new(HashMap::new())),
+            suspended: Arc::new(tokio::sync::Mutex::new(HashMap::new())),
+            imported: Arc::new(tokio::sync::Mutex::new(HashMap::new())),
+            access: Arc::new(tokio::sync::RwLock::new(ClientAccess::default())),
+            tool_policy: Arc::new(tool_policy),
+            bridges: Arc::new(tokio::sync::Mutex::new(HashMap::new())),
+            modbus_simulators: Arc::new(tokio::sync::Mutex::new(HashMap::new())),
+            modbus_gateways: Arc::new(tokio::sync::Mutex::new(HashMap::new())),
+            gcode_jobs: Arc::new(tokio::sync::Mutex::new(HashMap::new())),
+            midi_parsers: Arc::new(tokio::sync::Mutex::new(HashMap::new())),
+            framer_specs: Arc::new(tokio::sync::Mutex::new(HashMap::new())),
+            defmt_sessions: Arc::new(tokio::sync::Mutex::new(HashMap::new())),
+            protobuf: Arc::new(tokio::sync::Mutex::new(ProtobufRegistry::new())),
+            notes,
+            transfers,
+            tool_router,
+        }
+    }
+
+    /// The connections this handler serves, for cleanup once serving ends
+    pub fn connection_manager(&self) -> Arc<ConnectionManager> {
+        self.connection_manager.clone()
+    }
+
+    /// Close the connections of a client whose transport has closed, if so configured
+    pub async fn client_disconnected(&self) {
+        if !self.config.server.close_on_disconnect {
+            return;
+        }
+        let Some(client) = self.connection_manager.client().await else {
+            return;
+        };
+        let closed = close_client_connections(&self.connection_manager, &client).await;
+        for connection_id in &closed {
+            self.release_connection_state(connection_id).await;
+            self.sessions.record_session_event(connection_id, format!("Client {} disconnected", client)).await;
+            self.sessions.detach(connection_id).await;
+        }
+        info!("Client {} disconnected, closed its {} connection(s)", client, closed.len());
+    }
+
+    /// Tear everything down before the server exits
+    ///
+    /// Stops the tasks using connections, runs each connection's teardown
+    /// commands, finishes captures, stops readers and saves the sessions.
+    pub async fn shutdown(&self) {
+        for (_, subscription) in self.subscriptions.lock().await.drain() {
+            subscription.abort();
+        }
+        self.bridges.lock().await.clear();
+        self.modbus_gateways.lock().await.clear();
+        self.modbus_simulators.lock().await.clear();
+        self.gcode_jobs.lock().await.clear();
+
+        let closed = self.connection_manager.close_all().await;
+        info!("Closed {} open connection(s)", closed);
+        self.sessions.save().await;
+    }
+
+    /// Look up an open connection, mapping unknown IDs to a tool error
+    pub(crate) async fn connection(&self, connection_id: &str) -> Result<Arc<SerialConnection>, McpError> {
+        let connection = self.connection_manager.get(connection_id).await.map_err(|e| {
+            error!("Invalid connection ID {}: {}", connection_id, e);
+            McpError::internal_error(format!("Error: Connection ID {} not found", connection_id), None)
+        })?;
+        self.sessions.touch(connection_id).await;
+        Ok(connection)
+    }
+
+    /// The connection, provided its reads are raw bytes rather than lines or frames
+    pub(crate) async fn raw_connection(&self, connection_id: &str, purpose: &str) -> Result<Arc<SerialConnection>, McpError> {
+        let connection = self.connection(connection_id).await?;
+        if connection.is_line_mode().await || connection.framer_name().await.is_some() {
+            let error_msg = format!("Error: Connection {} has line mode or a framer set, disable it to {}", connection_id, purpose);
+            return Err(McpError::invalid_params(error_msg, None));
+        }
+        Ok(connection)
+    }
+
+    /// Open a connection and track it as a session, within the session limit and port restrictions
+    pub(crate) async fn open_connection(&self, config: ConnectionConfig) -> Result<String, String> {
+        self.sessions.admit(&SessionConfig::from_connection(&config, &self.config.serial)).await.map_err(|e| e.to_string())?;
+        let connection_id = self.connection_manager.open(config).await.map_err(|e| e.to_string())?;
+        let connection = self.connection_manager.get(&connection_id).await.map_err(|e| e.to_string())?;
+        if let Err(e) = self.sessions.attach(connection).await {
+            warn!("Connection {} not tracked as a session: {}", connection_id, e);
+        }
+        Ok(connection_id)
+    }
+
+    /// Settings of the session running on a connection, which reads and writes on it default to
+    ///
+    /// Every open connection has one; the server's serial defaults stand in should it be gone.
+    pub(crate) async fn session_config(&self, connection_id: &str) -> SessionConfig {
+        match self.sessions.connection_session_config(connection_id).await {
+            Some(config) => config,
+            None => SessionConfig {
+                timeout_ms: self.config.serial.default_timeout_ms,
+                buffer_size: self.config.serial.max_buffer_size,
+                line_ending: self.config.serial.default_line_ending.clone(),
+                ..SessionConfig::default()
+            },
+        }
+    }
+
+    /// Close a connection and forget its session
+    pub(crate) async fn close_connection(&self, connection_id: &str) -> Result<(), LocalSerialError> {
+        self.connection_manager.close(connection_id).await?;
+        self.sessions.detach(connection_id).await;
+        Ok(())
+    }
+
+    /// Refuse a tool the tool policy denies or the client may not call
+    pub(crate) async fn check_tool_permitted(&self, tool: &str) -> Result<(), McpError> {
+        if !self.tool_policy.permits(tool) {
+            warn!("Refused call to {}: denied by the tool policy", tool);
+            return Err(McpError::invalid_request(format!("Error: {} is denied by the server's tool policy", tool), None));
+        }
+        if !self.access.read().await.allows(tool) {
+            warn!("Refused call to {}: not among the client's tools", tool);
+            return Err(McpError::invalid_request(format!("Error: This client may not call {}", tool), None));
+        }
+        Ok(())
+    }
+
+    /// Stop the tasks and drop the state kept for a connection that is about to close
+    pub(crate) async fn release_connection_state(&self, connection_id: &str) {
+        // A running subscription holds the connection while it waits for data
+        if let Some(subscription) = self.subscriptions.lock().await.remove(connection_id) {
+            subscription.abort();
+        }
+        self.midi_parsers.lock().await.remove(connection_id);
+        self.framer_specs.lock().await.remove(connection_id);
+        self.defmt_sessions.lock().await.remove(connection_id);
+        // A gateway would keep the port open; dropping it stops it
+        self.modbus_gateways.lock().await.retain(|_, gateway| gateway.connection().id() != connection_id);
+    }
+
+    /// Record a failed read or write in the connection's session; timeouts are not failures
+    pub(crate) async fn record_session_error(&self, connection_id: &str, error: &LocalSerialError) {
+        if !matches!(error, LocalSerialError::ReadTimeout) {
+            // Sessions of connections closed meanwhile are gone
+            let _ = self.sessions.handle_session_error(connection_id, error.to_string()).await;
+        }
+    }
+
+    /// Send data as the write tool does: in its turn behind earlier writes, within
+    /// the session's rate limit, and wrapped as one frame if the connection has a framer
+    ///
+    /// Returns the bytes written and the `Queued: ...` line, if the write waited.
+    pub(crate) async fn send_data(
+        &self,
+        connection: &SerialConnection,
+        connection_id: &str,
+        data: &[u8],
+        queue_timeout_ms: Option<u64>,
+    ) -> Result<(Vec<u8>, String), McpError> {
+        let turn = write_turn(connection, queue_timeout_ms).await?;
+        let result = if connection.framer_name().await.is_some() {
+            connection.write_frame_in_turn(&turn, data).await
+        } else {
+            connection.write_in_turn(&turn, data).await.map(|written| data[..written].to_vec())
+        };
+        match result {
+            Ok(sent) => {
+                debug!("Wrote {} bytes to connection {}", sent.len(), connection_id);
+                let _ = self.sessions.record_session_send(connection_id, sent.len()).await;
+                Ok((sent, format_queued(&turn)))
+            }
+            Err(LocalSerialError::RateLimited(limited)) => Err(rate_limited(&limited)),
+            Err(e) => {
+                error!("Failed to write to connection {}: {}", connection_id, e);
+                self.record_session_error(connection_id, &e).await;
+                let error_msg = format!("Error: Data sending failed - {}", e);
+                Err(McpError::internal_error(error_msg, None))
+            }
+        }
+    }
+
+    /// Open a port for a tool that manages the connection itself, returning its connection ID
+    pub(crate) async fn open_port(&self, mut args: OpenArgs) -> Result<String, McpError> {
+        let backpressure = args.backpressure.get_or_insert_with(|| self.config.serial.backpressure.clone());
+        if let Err(e) = crate::serial::BackpressurePolicy::parse(backpressure) {
+            return Err(McpError::invalid_params(format!("Error: {}", e), None));
+        }
+        let profile = args.profile.take();
+        if let Some(name) = &profile {
+            self.profile_config(name)?;
+            // The profile's init commands are sent as open_profile sends them
+            self.check_tool_permitted("open_profile").await?;
+        }
+        let protocol = args.protocol.take();
+        if let Some(name) = &protocol {
+            self.protocol_config(name)?;
+        }
+
+        let (port, device_note) = self.device_path(&args.port);
+        if let Some(note) = device_note {
+            warn!("Port {}: {}", args.port, note);
+        }
+        args.port = port;
+        let config: ConnectionConfig = args.into();
+        let connection_id = self.open_connection(config.clone()).await.map_err(|e| {
+            error!("Failed to open port {}: {}", config.port, e);
+            McpError::internal_error(format!("Error: Failed to open port {} - {}", config.port, e), None)
+        })?;
+
+        if let Some(name) = &profile {
+            let connection = self.connection(&connection_id).await?;
+            // Recorded in the connection's events; the port is usable either way
+            if let Err(e) = self.apply_profile(&connection, name).await {
+                warn!("Connection {}: {}", connection_id, e);
+            }
+        }
+        if let Some(name) = &protocol {
+            let connection = self.connection(&connection_id).await?;
+            if let Err(e) = self.apply_protocol(&connection, name).await {
+                warn!("Connection {}: {}", connection_id, e);
+            }
+        }
+        Ok(connection_id)
+    }
+
+    /// Path to open for a requested port, with a note for the user if it was questionable
+    ///
+    /// On macOS a `/dev/tty.*` dial-in node blocks on open until carrier detect,
+    /// so the matching `/dev/cu.*` callout node is preferred.
+    pub(crate) fn device_path(&self, port: &str) -> (String, Option<String>) {
+        if cfg!(target_os = "macos") {
+            crate::serial::port::prefer_callout_device(port, self.config.serial.prefer_callout_device)
+        } else {
+            (port.to_string(), None)
+        }
+    }
+
+    /// Read whole lines from a connection in line mode and format them for the client
+    async fn read_assembled_lines(
+        &self,
+        connection: &SerialConnection,
+        connection_id: &str,
+        max_lines: usize,
+        timeout_ms: u64,
+    ) -> Result<CallToolResult, McpError> {
+        match connection.read_lines(max_lines, Some(timeout_ms)).await {
+            Ok(lines) if !lines.is_empty() => {
+                debug!("Read {} lines from connection {}", lines.len(), connection_id);
+                let received = lines.iter().map(|line| line.data.len()).sum();
+                let _ = self.sessions.record_session_receive(connection_id, received).await;
+                let newest = lines.last().map_or(0, |line| age_ms(line.timestamp));
+                let message = format!(
+                    "Lines read successfully\nConnection ID: {}\nLines read: {}\nNewest line age: {}ms\n{}\n{}",
+                    connection_id,
+                    lines.len(),
+                    newest,
+                    connection.freshness().await,
+                    format_lines(&lines)
+                );
+                let mut warnings = Warnings::new();
+                warnings.dropped_bytes(connection.take_new_drops().await);
+                warnings.into_result(message)
+            }
+            Ok(_) if connection.is_paused() => {
+                let message = format!("Reception paused\nConnection ID: {}\nLines read: 0\n{}", connection_id, connection.freshness().await);
+                Ok(CallToolResult::success(vec![Content::text(message)]))
+            }
+            Ok(_) | Err(crate::serial::LocalSerialError::ReadTimeout) => {
+                debug!("Line read timeout on connection {}", connection_id);
+                let message = format!(
+                    "Read timeout\nConnection ID: {}\nTimeout: {}ms\nLines read: 0\n{}",
+                    connection_id,
+                    timeout_ms,
+                    connection.freshness().await
+                );
+                Ok(CallToolResult::success(vec![Content::text(message)]))
+            }
+            Err(e) => {
+                error!("Failed to read lines from connection {}: {}", connection_id, e);
+                self.record_session_error(connection_id, &e).await;
+                let error_msg = format!("Error: Line reading failed - {}", e);
+                Err(McpError::internal_error(error_msg, None))
+            }
         }
     }
 
@@ -51,11 +489,30 @@ impl SerialHandler {
                     let port_list = ports
                         .iter()
                         .map(|p| {
-                            if let Some(ref hw_id) = p.hardware_id {
+                            let mut entry = if let Some(ref hw_id) = p.hardware_id {
                                 format!("- {}: {} ({})", p.name, p.description, hw_id)
                             } else {
                                 format!("- {}: {}", p.name, p.description)
+                            };
+                            let details: Vec<String> = [
+                                (p.port_type == PortType::Virtual).then(|| match p.virtual_peer {
+                                    Some(ref peer) => format!("virtual, paired with {}", peer),
+                                    None => "virtual".to_string(),
+                                }),
+                                p.driver.as_ref().map(|driver| format!("driver {}", driver)),
+                                p.usb_location.as_ref().map(|location| format!("location {}", location)),
+                                p.usb_interface.as_ref().map(|interface| format!("interface {:?}", interface)),
+                            ]
+                            .into_iter()
+                            .flatten()
+                            .collect();
+                            if !details.is_empty() {
+                                entry.push_str(&format!(" [{}]", details.join(", ")));
+                            }
+                            if let Some(ref note) = p.note {
+                                entry.push_str(&format!("\n  Note: {}", note));
                             }
+                            entry
                         })
                         .collect::<Vec<_>>()
                         .join("\n");
@@ -73,21 +530,73 @@ impl SerialHandler {
     }
 
     #[tool(description = "Open a serial port connection with specified configuration")]
-    async fn open(&self, Parameters(args): Parameters<OpenArgs>) -> Result<CallToolResult, McpError> {
+    pub(crate) async fn open(&self, Parameters(mut args): Parameters<OpenArgs>) -> Result<CallToolResult, McpError> {
         debug!("Opening serial connection to {}", args.port);
         
-        let config: crate::serial::ConnectionConfig = args.into();
+        let backpressure = args.backpressure.get_or_insert_with(|| self.config.serial.backpressure.clone());
+        if let Err(e) = crate::serial::BackpressurePolicy::parse(backpressure) {
+            return Err(McpError::invalid_params(format!("Error: {}", e), None));
+        }
+        
+        let profile = args.profile.take();
+        if let Some(name) = &profile {
+            self.profile_config(name)?;
+            // The profile's init commands are sent as open_profile sends them
+            self.check_tool_permitted("open_profile").await?;
+        }
+        let protocol = args.protocol.take();
+        if let Some(name) = &protocol {
+            self.protocol_config(name)?;
+        }
         
-        match self.connection_manager.open(config.clone()).await {
+        let (port, device_note) = self.device_path(&args.port);
+        args.port = port;
+        let config: ConnectionConfig = args.into();
+        
+        match self.open_connection(config.clone()).await {
             Ok(connection_id) => {
                 info!("Opened serial connection {} to {}", connection_id, config.port);
                 
-                let message = format!(
-                    "Serial connection opened\nConnection ID: {}\nPort: {}\nBaud rate: {}",
-                    connection_id, config.port, config.baud_rate
+                let mut message = format!(
+                    "Serial connection opened\nConnection ID: {}\nPort: {}\nBaud rate: {}\nBackpressure: {}",
+                    connection_id, config.port, config.baud_rate, config.backpressure
                 );
                 
-                Ok(CallToolResult::success(vec![Content::text(message)]))
+                let mut warnings = Warnings::new();
+                if let Some(note) = device_note {
+                    warnings.push(note);
+                }
+                
+                let connection = self.connection(&connection_id).await?;
+                if let Some(origin) = connection.origin() {
+                    message.push_str(&format!("\nShared with: {} (the port was already open; this handle gets its own copy of received data)", origin.id()));
+                }
+                
+                if self.config.capture.enabled {
+                    // A capture that cannot start should not fail the open itself
+                    let format = CaptureFormat::parse(&self.config.capture.format).unwrap_or(CaptureFormat::Jsonl);
+                    match self.start_connection_capture(&connection, None, format).await {
+                        Ok(path) => message.push_str(&format!("\nCapture file: {}", path.display())),
+                        Err(e) => warnings.push(format!("Capture not started: {}", e.message)),
+                    }
+                }
+                
+                if let Some(name) = &profile {
+                    match self.apply_profile(&connection, name).await {
+                        Ok(summary) => message.push_str(&format!("\n{}", summary)),
+                        Err(e) => warnings.push(e),
+                    }
+                }
+                
+                // After the profile, whose init commands are plain text
+                if let Some(name) = &protocol {
+                    match self.apply_protocol(&connection, name).await {
+                        Ok(summary) => message.push_str(&format!("\n{}", summary)),
+                        Err(e) => warnings.push(e),
+                    }
+                }
+                
+                warnings.into_result(message)
             }
             Err(e) => {
                 error!("Failed to open serial connection to {}: {}", config.port, e);
@@ -101,11 +610,27 @@ impl SerialHandler {
     async fn close(&self, Parameters(args): Parameters<CloseArgs>) -> Result<CallToolResult, McpError> {
         debug!("Closing serial connection {}", args.connection_id);
         
-        match self.connection_manager.close(&args.connection_id).await {
+        self.release_connection_state(&args.connection_id).await;
+        
+        // Run the profile's shutdown commands here so their outcome can be reported
+        let mut warnings = Warnings::new();
+        let mut teardown = None;
+        if let Ok(connection) = self.connection_manager.get(&args.connection_id).await {
+            match connection.run_teardown().await {
+                Some(Ok(sent)) => teardown = Some(sent),
+                Some(Err(reason)) => warnings.push(format!("Shutdown commands incomplete: {}", reason)),
+                None => {}
+            }
+        }
+        
+        match self.close_connection(&args.connection_id).await {
             Ok(()) => {
                 info!("Closed serial connection {}", args.connection_id);
-                let message = format!("Serial connection closed\nConnection ID: {}", args.connection_id);
-                Ok(CallToolResult::success(vec![Content::text(message)]))
+                let mut message = format!("Serial connection closed\nConnection ID: {}", args.connection_id);
+                if let Some(sent) = teardown {
+                    message.push_str(&format!("\nShutdown commands run: {}", sent));
+                }
+                warnings.into_result(message)
             }
             Err(e) => {
                 error!("Failed to close connection {}: {}", args.connection_id, e);
@@ -117,7 +642,7 @@ impl SerialHandler {
 
     #[tool(description = "Write data to a serial port connection")]
     async fn write(&self, Parameters(args): Parameters<WriteArgs>) -> Result<CallToolResult, McpError> {
-        debug!("Writing to connection {} with encoding {}", args.connection_id, args.encoding);
+        debug!("Writing to connection {} with encoding {:?}", args.connection_id, args.encoding);
         
         // Get connection
         let connection = match self.connection_manager.get(&args.connection_id).await {
@@ -130,31 +655,28 @@ impl SerialHandler {
         };
         
         // Decode data
-        let data = match decode_data(&args.data, &args.encoding) {
+        let encoding = connection.encoding(args.encoding).await;
+        let mut data = match decode_data(&args.data, &encoding) {
             Ok(data) => data,
             Err(e) => {  
-                error!("Failed to decode data with encoding {}: {}", args.encoding, e);
+                error!("Failed to decode data with encoding {}: {}", encoding, e);
                 let error_msg = format!("Error: Data decoding failed - {}", e);
                 return Err(McpError::internal_error(error_msg, None));
             }
         };
-        
-        // Send data
-        match connection.write(&data).await {
-            Ok(bytes_written) => {
-                debug!("Wrote {} bytes to connection {}", bytes_written, args.connection_id);
-                let message = format!(
-                    "Data sent successfully\nConnection ID: {}\nBytes written: {}\nData: {:?}",
-                    args.connection_id, bytes_written, args.data
-                );
-                Ok(CallToolResult::success(vec![Content::text(message)]))
-            }
-            Err(e) => {
-                error!("Failed to write to connection {}: {}", args.connection_id, e);
-                let error_msg = format!("Error: Data sending failed - {}", e);
-                Err(McpError::internal_error(error_msg, None))
-            }
+        if args.append_line_ending {
+            data.extend_from_slice(self.session_config(&args.connection_id).await.line_ending.as_bytes());
         }
+        
+        let (sent, queued) = self.send_data(&connection, &args.connection_id, &data, args.queue_timeout_ms).await?;
+        let message = format!(
+            "Data sent successfully\nConnection ID: {}\nBytes written: {}{}\n{}",
+            args.connection_id,
+            sent.len(),
+            queued,
+            format_sent(&sent)
+        );
+        Ok(CallToolResult::success(vec![Content::text(message)]))
     }
 
     #[tool(description = "Read data from a serial port connection")]
@@ -171,32 +693,63 @@ impl SerialHandler {
             }
         };
         
+        let session = self.session_config(&args.connection_id).await;
+        let timeout_ms = args.timeout_ms.unwrap_or(session.timeout_ms);
+        
+        // Line mode hands out whole lines instead of raw chunks
+        if connection.is_line_mode().await {
+            return self.read_assembled_lines(&connection, &args.connection_id, DEFAULT_READ_MAX_LINES, timeout_ms).await;
+        }
+        
+        // So does a framer with whole frames
+        let encoding = connection.encoding(args.encoding).await;
+        if connection.framer_name().await.is_some() {
+            return self.read_decoded_frames(&connection, &args.connection_id, DEFAULT_READ_MAX_FRAMES, timeout_ms, &encoding, None).await;
+        }
+        
         // Prepare buffer
-        let mut buffer = vec![0u8; args.max_bytes];
+        let mut buffer = vec![0u8; args.max_bytes.unwrap_or(session.buffer_size)];
         
         // Read data
-        match connection.read(&mut buffer, args.timeout_ms).await {
-            Ok(bytes_read) => {
+        match connection.read_timed(&mut buffer, Some(timeout_ms)).await {
+            Ok((bytes_read, span)) => {
                 buffer.truncate(bytes_read);
+                if bytes_read > 0 {
+                    let _ = self.sessions.record_session_receive(&args.connection_id, bytes_read).await;
+                }
                 
                 // Encode data
-                match encode_data(&buffer, &args.encoding) {
-                    Ok(encoded) => {
+                match ReadPayload::new(&buffer, &encoding) {
+                    Ok(payload) => {
                         debug!("Read {} bytes from connection {}", bytes_read, args.connection_id);
                         
-                        let message = if bytes_read > 0 {
+                        let freshness = connection.freshness().await;
+                        let message = if let Some(span) = span {
                             format!(
-                                "Data read successfully\nConnection ID: {}\nBytes read: {}\nData: {:?}",
-                                args.connection_id, bytes_read, encoded
+                                "Data read successfully\nConnection ID: {}\nBytes read: {}\nReceived: {} - {}\nNewest byte age: {}ms\n{}\n{}",
+                                args.connection_id,
+                                bytes_read,
+                                span.first.format(RX_TIMESTAMP_FORMAT),
+                                span.last.format(RX_TIMESTAMP_FORMAT),
+                                age_ms(span.last),
+                                freshness,
+                                payload
+                            )
+                        } else if connection.is_paused() {
+                            format!(
+                                "Reception paused\nConnection ID: {}\nBytes read: 0\n{}",
+                                args.connection_id, freshness
                             )
                         } else {
                             format!(
-                                "Read timeout\nConnection ID: {}\nTimeout: {}ms\nBytes read: 0",
-                                args.connection_id, args.timeout_ms.unwrap_or(1000)
+                                "Read timeout\nConnection ID: {}\nTimeout: {}ms\nBytes read: 0\n{}",
+                                args.connection_id, timeout_ms, freshness
                             )
                         };
                         
-                        Ok(CallToolResult::success(vec![Content::text(message)]))
+                        let mut warnings = Warnings::new();
+                        warnings.dropped_bytes(connection.take_new_drops().await);
+                        warnings.into_result(message)
                     }
                     Err(e) => {
                         error!("Failed to encode read data: {}", e);
@@ -211,12 +764,15 @@ impl SerialHandler {
                         debug!("Read timeout on connection {}", args.connection_id);
                         let message = format!(
                             "Read timeout\nConnection ID: {}\nTimeout: {}ms\nBytes read: 0",
-                            args.connection_id, args.timeout_ms.unwrap_or(1000)
+                            args.connection_id, timeout_ms
                         );
-                        Ok(CallToolResult::success(vec![Content::text(message)]))
+                        let mut warnings = Warnings::new();
+                        warnings.dropped_bytes(connection.take_new_drops().await);
+                        warnings.into_result(message)
                     }
                     _ => {
                         error!("Failed to read from connection {}: {}", args.connection_id, e);
+                        self.record_session_error(&args.connection_id, &e).await;
                         let error_msg = format!("Error: Data reading failed - {}", e);
                         Err(McpError::internal_error(error_msg, None))
                     }
@@ -224,14 +780,212 @@ impl SerialHandler {
             }
         }
     }
+
+    #[tool(description = "Pause the background reader of a connection so no new data is consumed. Already buffered data stays readable. Optionally signal the device to hold off with 'rts' or 'xoff'")]
+    async fn pause_reception(&self, Parameters(args): Parameters<PauseArgs>) -> Result<CallToolResult, McpError> {
+        debug!("Pausing reception on connection {}", args.connection_id);
+        
+        let signal = PauseSignal::parse(&args.signal)
+            .map_err(|e| McpError::invalid_params(format!("Error: {}", e), None))?;
+        
+        let connection = self.connection(&args.connection_id).await?;
+        
+        match connection.pause(signal).await {
+            Ok(()) => {
+                info!("Paused reception on connection {} (signal: {})", args.connection_id, signal);
+                let message = format!(
+                    "Reception paused\nConnection ID: {}\nSignal: {}\nBuffered bytes: {}",
+                    args.connection_id, signal, connection.buffered_bytes().await
+                );
+                Ok(CallToolResult::success(vec![Content::text(message)]))
+            }
+            Err(e) => {
+                error!("Failed to pause connection {}: {}", args.connection_id, e);
+                let error_msg = format!("Error: Failed to pause reception - {}", e);
+                Err(McpError::internal_error(error_msg, None))
+            }
+        }
+    }
+
+    #[tool(description = "Resume the background reader of a paused connection, releasing any RTS/XOFF signal asserted when pausing")]
+    async fn resume_reception(&self, Parameters(args): Parameters<ResumeArgs>) -> Result<CallToolResult, McpError> {
+        debug!("Resuming reception on connection {}", args.connection_id);
+        
+        let connection = self.connection(&args.connection_id).await?;
+        
+        match connection.resume().await {
+            Ok(()) => {
+                info!("Resumed reception on connection {}", args.connection_id);
+                let message = format!(
+                    "Reception resumed\nConnection ID: {}\nBuffered bytes: {}",
+                    args.connection_id, connection.buffered_bytes().await
+                );
+                Ok(CallToolResult::success(vec![Content::text(message)]))
+            }
+            Err(e) => {
+                error!("Failed to resume connection {}: {}", args.connection_id, e);
+                let error_msg = format!("Error: Failed to resume reception - {}", e);
+                Err(McpError::internal_error(error_msg, None))
+            }
+        }
+    }
+
+    #[tool(description = "Read one frame delimited by an idle gap: returns once the line has been quiet for idle_ms after data arrived (Modbus RTU style framing)")]
+    async fn read_frame_idle(&self, Parameters(args): Parameters<ReadFrameIdleArgs>) -> Result<CallToolResult, McpError> {
+        debug!("Reading idle-delimited frame from connection {}", args.connection_id);
+        
+        let connection = self.connection(&args.connection_id).await?;
+        if connection.is_line_mode().await {
+            let error_msg = format!("Error: Connection {} is in line mode, disable it to read raw frames", args.connection_id);
+            return Err(McpError::invalid_params(error_msg, None));
+        }
+        
+        let encoding = connection.encoding(args.encoding).await;
+        let session = self.session_config(&args.connection_id).await;
+        let timeout_ms = args.timeout_ms.unwrap_or(session.timeout_ms);
+        
+        // 3.5 character times, as used by Modbus RTU, unless the caller chose a gap
+        let idle = args.idle_ms
+            .map(std::time::Duration::from_millis)
+            .unwrap_or_else(|| connection.config().char_time() * 7 / 2);
+        
+        match connection.read_until_idle(args.max_bytes.unwrap_or(session.buffer_size), idle, Some(timeout_ms)).await {
+            Ok(frame) if frame.is_empty() => {
+                let message = format!("Reception paused\nConnection ID: {}\nBytes read: 0", args.connection_id);
+                Ok(CallToolResult::success(vec![Content::text(message)]))
+            }
+            Ok(frame) => {
+                let payload = ReadPayload::new(&frame, &encoding).map_err(|e| {
+                    error!("Failed to encode read data: {}", e);
+                    McpError::internal_error(format!("Error: Data encoding failed - {}", e), None)
+                })?;
+                debug!("Read {} byte frame from connection {}", frame.len(), args.connection_id);
+                let message = format!(
+                    "Frame received\nConnection ID: {}\nBytes read: {}\nIdle gap: {}us\n{}",
+                    args.connection_id, frame.len(), idle.as_micros(), payload
+                );
+                Ok(CallToolResult::success(vec![Content::text(message)]))
+            }
+            Err(crate::serial::LocalSerialError::ReadTimeout) => {
+                let message = format!(
+                    "Read timeout\nConnection ID: {}\nTimeout: {}ms\nBytes read: 0",
+                    args.connection_id, timeout_ms
+                );
+                Ok(CallToolResult::success(vec![Content::text(message)]))
+            }
+            Err(e) => {
+                error!("Failed to read frame from connection {}: {}", args.connection_id, e);
+                let error_msg = format!("Error: Data reading failed - {}", e);
+                Err(McpError::internal_error(error_msg, None))
+            }
+        }
+    }
+
+    #[tool(description = "Read received data as a batch of chunks, each with its arrival timestamp, instead of one concatenated string. Delivers once the batch is full or its oldest chunk reaches max_age_ms")]
+    async fn read_batch(&self, Parameters(args): Parameters<ReadBatchArgs>) -> Result<CallToolResult, McpError> {
+        debug!("Reading chunk batch from connection {}", args.connection_id);
+        
+        let connection = self.connection(&args.connection_id).await?;
+        if connection.is_line_mode().await {
+            let error_msg = format!("Error: Connection {} is in line mode, use read_lines instead", args.connection_id);
+            return Err(McpError::invalid_params(error_msg, None));
+        }
+        
+        let encoding = connection.encoding(args.encoding).await;
+        let max_chunks = args.max_chunks.unwrap_or(self.config.serial.batch_max_chunks).max(1);
+        let max_age = std::time::Duration::from_millis(args.max_age_ms.unwrap_or(self.config.serial.batch_max_age_ms));
+        let session = self.session_config(&args.connection_id).await;
+        let timeout_ms = args.timeout_ms.unwrap_or(session.timeout_ms);
+        
+        match connection.read_batch(max_chunks, args.max_bytes.unwrap_or(session.buffer_size), max_age, Some(timeout_ms)).await {
+            Ok(chunks) if !chunks.is_empty() => {
+                let mut rendered = Vec::with_capacity(chunks.len());
+                for (index, chunk) in chunks.iter().enumerate() {
+                    let payload = ReadPayload::new(&chunk.data, &encoding).map_err(|e| {
+                        error!("Failed to encode read data: {}", e);
+                        McpError::internal_error(format!("Error: Data encoding failed - {}", e), None)
+                    })?;
+                    rendered.push(format!(
+                        "[{}] {} ({} bytes): {:?} | {}: {:?}",
+                        index + 1,
+                        chunk.timestamp.format(RX_TIMESTAMP_FORMAT),
+                        chunk.data.len(),
+                        payload.data,
+                        payload.companion_label,
+                        payload.companion
+                    ));
+                }
+                
+                let total: usize = chunks.iter().map(|c| c.data.len()).sum();
+                debug!("Read {} chunks ({} bytes) from connection {}", chunks.len(), total, args.connection_id);
+                let message = format!(
+                    "Batch read successfully\nConnection ID: {}\nChunks: {}\nBytes read: {}\n{}",
+                    args.connection_id, chunks.len(), total, rendered.join("\n")
+                );
+                Ok(CallToolResult::success(vec![Content::text(message)]))
+            }
+            Ok(_) => {
+                let message = format!("Reception paused\nConnection ID: {}\nChunks: 0", args.connection_id);
+                Ok(CallToolResult::success(vec![Content::text(message)]))
+            }
+            Err(crate::serial::LocalSerialError::ReadTimeout) => {
+                let message = format!(
+                    "Read timeout\nConnection ID: {}\nTimeout: {}ms\nChunks: 0",
+                    args.connection_id, timeout_ms
+                );
+                Ok(CallToolResult::success(vec![Content::text(message)]))
+            }
+            Err(e) => {
+                error!("Failed to read batch from connection {}: {}", args.connection_id, e);
+                let error_msg = format!("Error: Data reading failed - {}", e);
+                Err(McpError::internal_error(error_msg, None))
+            }
+        }
+    }
+
+    #[tool(description = "Enable or disable line mode on a connection. In line mode received data is assembled into complete lines (CR, LF or CRLF terminated) and reads return whole numbered lines")]
+    async fn set_line_mode(&self, Parameters(args): Parameters<LineModeArgs>) -> Result<CallToolResult, McpError> {
+        debug!("Setting line mode on connection {} to {}", args.connection_id, args.enabled);
+        
+        let connection = self.connection(&args.connection_id).await?;
+        if args.enabled {
+            if let Some(framer) = connection.framer_name().await {
+                let error_msg = format!("Error: Connection {} uses the {} framer, remove it with set_framer first", args.connection_id, framer);
+                return Err(McpError::invalid_params(error_msg, None));
+            }
+        }
+        connection.set_line_mode(args.enabled).await;
+        
+        info!("Line mode {} on connection {}", if args.enabled { "enabled" } else { "disabled" }, args.connection_id);
+        let message = format!(
+            "Line mode {}\nConnection ID: {}\nBuffered bytes: {}",
+            if args.enabled { "enabled" } else { "disabled" },
+            args.connection_id,
+            connection.buffered_bytes().await
+        );
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+
+    #[tool(description = "Read complete lines from a connection in line mode. Each line carries a sequence number so gaps between reads are visible")]
+    async fn read_lines(&self, Parameters(args): Parameters<ReadLinesArgs>) -> Result<CallToolResult, McpError> {
+        debug!("Reading lines from connection {} with timeout {:?}", args.connection_id, args.timeout_ms);
+        
+        let connection = self.connection(&args.connection_id).await?;
+        if !connection.is_line_mode().await {
+            let error_msg = format!("Error: Connection {} is not in line mode, enable it with set_line_mode", args.connection_id);
+            return Err(McpError::invalid_params(error_msg, None));
+        }
+        
+        let timeout_ms = args.timeout_ms.unwrap_or(self.session_config(&args.connection_id).await.timeout_ms);
+        self.read_assembled_lines(&connection, &args.connection_id, args.max_lines, timeout_ms).await
+    }
 }
 
-#[tool_handler]
 impl ServerHandler for SerialHandler {
     fn get_info(&self) -> ServerInfo {
         ServerInfo {
             protocol_version: ProtocolVersion::V_2024_11_05,
-            capabilities: ServerCapabilities::builder().enable_tools().build(),
+            capabilities: ServerCapabilities::builder().enable_tools().enable_logging().build(),
             server_info: Implementation::from_build_env(),
             instructions: Some("A serial port communication MCP server. Use list_ports to discover available serial ports, then open connections to communicate with serial devices.".to_string()),
         }
@@ -239,12 +993,178 @@ impl ServerHandler for SerialHandler {
 
     async fn initialize(
         &self,
-        _request: InitializeRequestParam,
-        _context: RequestContext<RoleServer>,
+        request: InitializeRequestParam,
+        context: RequestContext<RoleServer>,
     ) -> Result<InitializeResult, McpError> {
-        info!("Serial MCP server initialized");
+        let client = format!("{} {}", request.client_info.name, request.client_info.version);
+        let access = authenticate(&self.config.security, &request.client_info.name, &context.meta).map_err(|reason| {
+            warn!("Refused {}: {}", client, reason);
+            McpError::invalid_request(format!("Error: Client {} is not authorized", request.client_info.name), None)
+        })?;
+        *self.access.write().await = access;
+        info!("Serial MCP server initialized for {}", client);
+        self.connection_manager.set_client(client).await;
+        self.idle.record_activity();
+        self.liveness.touch().await;
+        
+        tokio::spawn(run_session_expiry(
+            context.peer.clone(),
+            Arc::clone(&self.sessions),
+            std::time::Duration::from_secs(self.config.server.session_cleanup_interval_seconds),
+        ));
+        tokio::spawn(run_reconnect_supervisor(context.peer.clone(), self.clone()));
+        tokio::spawn(run_keepalive(context.peer.clone(), Arc::clone(&self.sessions)));
+        
+        let server = &self.config.server;
+        if server.heartbeat_interval_seconds > 0 {
+            // Validated with the configuration; fall back to the safe choice regardless
+            let policy = OrphanPolicy::parse(&server.orphan_policy).unwrap_or(OrphanPolicy::Suspend);
+            tokio::spawn(run_heartbeat(
+                context.peer.clone(),
+                Arc::clone(&self.connection_manager),
+                Arc::clone(&self.liveness),
+                Arc::clone(&self.maintenance),
+                Arc::clone(&self.idle),
+                std::time::Duration::from_secs(server.heartbeat_interval_seconds),
+                std::time::Duration::from_secs(server.client_timeout_seconds),
+                policy,
+            ));
+        }
+        
+        if server.safety_deadline_ms > 0 {
+            tokio::spawn(run_safety_watchdog(
+                context.peer.clone(),
+                Arc::clone(&self.connection_manager),
+                Arc::clone(&self.liveness),
+                Arc::clone(&self.maintenance),
+                std::time::Duration::from_millis(server.safety_deadline_ms),
+            ));
+        }
+        
+        let serial = &self.config.serial;
+        if serial.auto_discovery {
+            let interval = AdaptiveInterval::new(
+                std::time::Duration::from_millis(serial.discovery_fast_interval_ms),
+                std::time::Duration::from_secs(serial.discovery_interval_seconds),
+            );
+            tokio::spawn(run_discovery(
+                context.peer.clone(),
+                Arc::clone(&self.connection_manager),
+                Arc::clone(&self.discovery),
+                Arc::clone(&self.idle),
+                crate::serial::hotplug::watch(),
+                interval,
+            ));
+        }
+        
+        match MaintenanceSchedule::from_config(&self.config.maintenance) {
+            Ok(schedule) if !schedule.is_empty() => {
+                tokio::spawn(run_maintenance(
+                    context.peer.clone(),
+                    Arc::clone(&self.connection_manager),
+                    Arc::clone(&self.maintenance),
+                    schedule,
+                ));
+            }
+            Ok(_) => {}
+            Err(e) => error!("Maintenance windows disabled: {}", e),
+        }
+        
         Ok(self.get_info())
     }
+    
+    async fn ping(&self, _context: RequestContext<RoleServer>) -> Result<(), McpError> {
+        self.idle.record_activity();
+        self.liveness.touch().await;
+        Ok(())
+    }
+    
+    async fn call_tool(
+        &self,
+        request: CallToolRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        self.idle.record_activity();
+        self.liveness.touch().await;
+        self.check_tool_permitted(&request.name).await?;
+        let context = rmcp::handler::server::tool::ToolCallContext::new(self, request, context);
+        self.tool_router.call(context).await
+    }
+    
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, McpError> {
+        self.idle.record_activity();
+        let access = self.access.read().await;
+        let tools = self
+            .tool_router
+            .list_all()
+            .into_iter()
+            .filter(|tool| self.tool_policy.permits(&tool.name) && access.allows(&tool.name))
+            .collect();
+        Ok(ListToolsResult::with_all_items(tools))
+    }
+}
+
+/// Lines returned by a plain `read` on a connection in line mode
+const DEFAULT_READ_MAX_LINES: usize = 100;
+
+/// Frames returned by a plain `read` on a connection with a framer
+const DEFAULT_READ_MAX_FRAMES: usize = 16;
+
+/// Millisecond-resolution UTC time of day used for receive timestamps
+const RX_TIMESTAMP_FORMAT: &str = "%H:%M:%S%.3f";
+
+/// Render received lines as `[seq] time text`, one per line
+fn format_lines(lines: &[ReceivedLine]) -> String {
+    lines
+        .iter()
+        .map(|line| format!("[{}] {} {}", line.seq, line.timestamp.format(RX_TIMESTAMP_FORMAT), line.text()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Wait for a write tool's turn at the connection behind the writes submitted before it
+pub(crate) async fn write_turn(connection: &SerialConnection, queue_timeout_ms: Option<u64>) -> Result<WriteTurn<'_>, McpError> {
+    let timeout = queue_timeout_ms.map(std::time::Duration::from_millis);
+    connection.write_turn(timeout).await.map_err(|_| {
+        let error_msg = format!(
+            "Error: Nothing sent - writes submitted earlier still held connection {} after {}ms",
+            connection.id(),
+            queue_timeout_ms.unwrap_or_default()
+        );
+        McpError::internal_error(error_msg, None)
+    })
+}
+
+/// Error for a write refused by its session's rate limit, with when to retry for clients to act on
+pub(crate) fn rate_limited(limited: &RateLimited) -> McpError {
+    let retry_after_ms = limited.retry_after.as_micros().div_ceil(1000) as u64;
+    let error_msg = format!(
+        "Error: Rate limited, retry after {}ms - session {} allows {}",
+        retry_after_ms, limited.session_id, limited.limit
+    );
+    let data = serde_json::json!({
+        "error": "rate_limited",
+        "session_id": limited.session_id,
+        "retry_after_ms": retry_after_ms,
+    });
+    McpError::invalid_request(error_msg, Some(data))
+}
+
+/// `Queued: ...` line for a write that waited behind others, if it did
+pub(crate) fn format_queued(turn: &WriteTurn<'_>) -> String {
+    match turn.ahead {
+        0 => String::new(),
+        ahead => format!("\nQueued: behind {} write(s) for {}ms", ahead, turn.waited.as_millis()),
+    }
+}
+
+/// Milliseconds since `timestamp`
+fn age_ms(timestamp: DateTime<Utc>) -> i64 {
+    Utc::now().signed_duration_since(timestamp).num_milliseconds().max(0)
 }
 
 /// Decode data to bytes array
@@ -253,7 +1173,7 @@ fn decode_data(data: &str, encoding: &str) -> Result<Vec<u8>, String> {
         "utf8" | "utf-8" => Ok(data.as_bytes().to_vec()),
         "hex" => {
             let data = data.trim().replace(' ', "");
-            if data.len() % 2 != 0 {
+            if !data.len().is_multiple_of(2) {
                 return Err("Hex string must have even length".to_string());
             }
             
@@ -271,27 +1191,8 @@ fn decode_data(data: &str, encoding: &str) -> Result<Vec<u8>, String> {
                 .decode(data.trim())
                 .map_err(|e| format!("Invalid base64: {}", e))
         }
-        _ => Err(format!("Unsupported encoding: {}", encoding)),
-    }
-}
-
-/// Encode bytes array to string
-fn encode_data(data: &[u8], encoding: &str) -> Result<String, String> {
-    match encoding {
-        "utf8" | "utf-8" => {
-            String::from_utf8(data.to_vec())
-                .map_err(|e| format!("Invalid UTF-8: {}", e))
-        }
-        "hex" => {
-            Ok(data.iter()
-                .map(|b| format!("{:02x}", b))
-                .collect::<Vec<_>>()
-                .join(" "))
-        }
-        "base64" => {
-            use base64::{Engine as _, engine::general_purpose};
-            Ok(general_purpose::STANDARD.encode(data))
-        }
+        "cbor" => DataConverter::json_to_cbor(data).map_err(|e| e.to_string()),
+        "msgpack" => DataConverter::json_to_msgpack(data).map_err(|e| e.to_string()),
         _ => Err(format!("Unsupported encoding: {}", encoding)),
     }
 }
\ No newline at end of file
@@ -0,0 +1,565 @@
+//! Session tools
+//!
+//! Long-lived sessions that keep a port's settings, statistics and policies
+//! such as auto-reconnect across connects and disconnects. A session can be
+//! named and referred to by that name; connecting it opens a connection whose
+//! ID works with every other tool. Connections opened with `open` show up as
+//! sessions too, under their connection ID. Created sessions are saved, and
+//! come back suspended when the server restarts. Each session logs its
+//! lifecycle events, shown by `session_events`.
+//!
+//! Suspending a session closes its port so another program can use it, while
+//! the session keeps its statistics and its connection's history; resuming
+//! reopens the port under the same connection ID, set up as it was.
+//!
+//! A session can be exported with its event log and connection history, and
+//! imported on another server to hand over a debugging context.
+
+use std::future::Future;
+use std::sync::Arc;
+
+use chrono::Utc;
+use rmcp::{
+    handler::server::tool::Parameters, model::*, tool, tool_router, ErrorData as McpError,
+};
+use tracing::{debug, error, info, warn};
+
+use super::reconnect::ConnectionState;
+use super::serial_handler::SerialHandler;
+use super::types::*;
+use super::warnings::Warnings;
+use crate::error::SerialError;
+use crate::serial::{ConnectionHistory, ModemLine, ReceiveFilter};
+use crate::session::{format_labels, format_receive_filters, ExportedHistory, KeepaliveConfig, RateLimit, SessionConfig, SessionExport, SessionInfo};
+
+/// What a suspended session's connection had, to set up again when it resumes
+#[derive(Debug)]
+pub(crate) struct SuspendedConnection {
+    connection_id: String,
+    state: ConnectionState,
+    history: ConnectionHistory,
+}
+
+#[tool_router(router = session_router, vis = "pub(crate)")]
+impl SerialHandler {
+    #[tool(description = "Create a long-lived session for a port: its settings, a line ending, whether to reconnect after failures, an optional keepalive checking the device still answers, failover ports tried in order when the port cannot be opened or its link is lost, optional caps on writes and bytes sent per second, and filters cleaning up received data. Give it a name to refer to it by instead of its ID. Connects right away unless connect is false; the connection ID returned works with every other tool. Unset settings default to the server's serial configuration. Sessions are kept across server restarts, suspended until connected again")]
+    async fn create_session(&self, Parameters(args): Parameters<CreateSessionArgs>) -> Result<CallToolResult, McpError> {
+        debug!("Creating session for {}", args.port);
+
+        let serial = &self.config.serial;
+        if let Some(encoding) = &args.encoding {
+            ReadPayload::new(&[], encoding).map_err(|e| McpError::invalid_params(format!("Error: {}", e), None))?;
+        }
+        let timeout_ms = args.timeout_ms.unwrap_or(serial.default_timeout_ms);
+        let keepalive = keepalive_config(&args, timeout_ms)?;
+        let rate_limit = rate_limit(&args)?;
+        let receive_filters = receive_filters(&args.receive_filters)?;
+        let (port, _) = self.device_path(&args.port);
+        let config = SessionConfig {
+            name: args.name.filter(|name| !name.is_empty()),
+            port_name: port,
+            failover_ports: args.failover_ports.iter().map(|port| self.device_path(port).0).collect(),
+            baud_rate: args.baud_rate.unwrap_or(serial.default_baud_rate),
+            data_bits: args.data_bits.unwrap_or(serial.default_data_bits),
+            stop_bits: args.stop_bits.unwrap_or_else(|| serial.default_stop_bits.clone()),
+            parity: args.parity.unwrap_or_else(|| serial.default_parity.clone()),
+            flow_control: args.flow_control.unwrap_or_else(|| serial.default_flow_control.clone()),
+            timeout_ms,
+            buffer_size: serial.max_buffer_size,
+            auto_reconnect: args.auto_reconnect,
+            max_reconnect_attempts: args.max_reconnect_attempts.unwrap_or(serial.retry_count),
+            line_ending: args.line_ending.unwrap_or_else(|| serial.default_line_ending.clone()),
+            encoding: args.encoding,
+            protocol: None,
+            framer: None,
+            keepalive,
+            rate_limit,
+            receive_filters,
+            group: args.group.filter(|group| !group.is_empty()),
+            labels: args.labels,
+        };
+        let session_id = self.sessions.create_session(config).await.map_err(session_error)?;
+        info!("Created session {}", session_id);
+
+        if args.connect {
+            if let Err(e) = self.sessions.connect_session(&session_id).await {
+                let error_msg = format!("Error: Session {} created but not connected - {}; retry with connect_session", session_id, e);
+                return Err(McpError::internal_error(error_msg, None));
+            }
+        }
+        let info = self.session_info_of(&session_id).await?;
+        let message = format!("Session created\n{}", format_session(&info));
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+
+    #[tool(description = "Connect a session to its port with the session's settings, returning the connection ID to use with the other tools")]
+    async fn connect_session(&self, Parameters(args): Parameters<SessionArgs>) -> Result<CallToolResult, McpError> {
+        let session_id = self.sessions.resolve(&args.session_id).await.map_err(session_error)?;
+        debug!("Connecting session {}", session_id);
+
+        self.session_connect(&session_id).await?;
+        let info = self.session_info_of(&session_id).await?;
+        let message = format!("Session connected\n{}", format_session(&info));
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+
+    #[tool(description = "List sessions with their port, state, connection, labels and traffic: those created with create_session and the connections opened with open. Give labels to list only the sessions carrying all of them, or a group to list its sessions")]
+    async fn list_sessions(&self, Parameters(args): Parameters<ListSessionsArgs>) -> Result<CallToolResult, McpError> {
+        let mut sessions = self.sessions.list_sessions().await;
+        if sessions.is_empty() {
+            let message = "No sessions. Use create_session, or open a port".to_string();
+            return Ok(CallToolResult::success(vec![Content::text(message)]));
+        }
+        sessions.retain(|info| info.config.has_labels(&args.labels));
+        if let Some(group) = &args.group {
+            sessions.retain(|info| info.config.group.as_ref() == Some(group));
+        }
+        if sessions.is_empty() {
+            let mut wanted = Vec::new();
+            if let Some(group) = &args.group {
+                wanted.push(format!("in group {}", group));
+            }
+            if !args.labels.is_empty() {
+                wanted.push(format!("labelled {}", format_labels(&args.labels)));
+            }
+            let message = format!("No sessions {}", wanted.join(" and "));
+            return Ok(CallToolResult::success(vec![Content::text(message)]));
+        }
+
+        sessions.sort_by_key(|info| info.created_at);
+        let mut lines = vec![format!("{} session(s):", sessions.len())];
+        for info in &sessions {
+            let name = info.config.name.as_ref().map(|name| format!(" ({})", name)).unwrap_or_default();
+            let connection = match &info.connection_id {
+                Some(id) => format!("connection {}", id),
+                None => "not connected".to_string(),
+            };
+            lines.push(format!("- {}{} on {} @ {}: {}, {}", info.session_id, name, info.port_name, info.config.baud_rate, info.state, connection));
+            if let Some(group) = &info.config.group {
+                lines.push(format!("  group: {}", group));
+            }
+            if !info.config.labels.is_empty() {
+                lines.push(format!("  labels: {}", format_labels(&info.config.labels)));
+            }
+            lines.push(format!("  {}", format_stats(info)));
+        }
+        Ok(CallToolResult::success(vec![Content::text(lines.join("\n"))]))
+    }
+
+    #[tool(description = "Report a session as JSON: state, settings, connection, age, idle time and traffic statistics")]
+    async fn session_info(&self, Parameters(args): Parameters<SessionArgs>) -> Result<CallToolResult, McpError> {
+        let session_id = self.sessions.resolve(&args.session_id).await.map_err(session_error)?;
+        let info = self.session_info_of(&session_id).await?;
+
+        let message = serde_json::to_string_pretty(&info).map_err(|e| {
+            error!("Failed to serialize session: {}", e);
+            McpError::internal_error(format!("Error: Session serialization failed - {}", e), None)
+        })?;
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+
+    #[tool(description = "Report a session's link quality: throughput in bytes/sec and errors over the last minute, the share of reads and writes that failed, the last error, and lifetime traffic totals")]
+    async fn session_stats(&self, Parameters(args): Parameters<SessionArgs>) -> Result<CallToolResult, McpError> {
+        let session_id = self.sessions.resolve(&args.session_id).await.map_err(session_error)?;
+        let info = self.session_info_of(&session_id).await?;
+        let (stats, rates) = (&info.stats, &info.rates);
+
+        let name = info.config.name.as_ref().map(|name| format!(" ({})", name)).unwrap_or_default();
+        let mut lines = vec![
+            format!("Session {}{} on {}: {}", info.session_id, name, info.port_name, info.state),
+            format!(
+                "Last {}s: sent {:.1} B/s, received {:.1} B/s, {} error(s)",
+                rates.window_seconds, rates.sent_bytes_per_sec, rates.received_bytes_per_sec, rates.errors
+            ),
+        ];
+        if let Some(error_rate) = rates.error_rate {
+            lines.push(format!("Error rate: {:.1}% of reads and writes", error_rate * 100.0));
+        }
+        lines.push(format!("Total: {}", format_stats(&info)));
+        lines.push(format!("Reconnections: {}", stats.reconnections));
+        if let (Some(error), Some(at)) = (&stats.last_error, stats.last_error_at) {
+            lines.push(format!("Last error: {} ({}s ago)", error, (Utc::now() - at).num_seconds()));
+        }
+        Ok(CallToolResult::success(vec![Content::text(lines.join("\n"))]))
+    }
+
+    #[tool(description = "Export a session as a JSON document: its settings, labels, statistics and event log, with the transcript and events of its connection, to recreate it with import_session on another server or hand it to a teammate")]
+    async fn export_session(&self, Parameters(args): Parameters<SessionArgs>) -> Result<CallToolResult, McpError> {
+        let session_id = self.sessions.resolve(&args.session_id).await.map_err(session_error)?;
+        let saved = self.sessions.export_session(&session_id).await.map_err(session_error)?;
+        let export = SessionExport::new(session_id.clone(), saved, self.session_history(&session_id).await);
+
+        let document = serde_json::to_string_pretty(&export).map_err(|e| {
+            error!("Failed to serialize session export: {}", e);
+            McpError::internal_error(format!("Error: Session serialization failed - {}", e), None)
+        })?;
+        info!("Exported session {}", session_id);
+        Ok(CallToolResult::success(vec![Content::text(document)]))
+    }
+
+    #[tool(description = "Import a session exported with export_session under a new ID, with its settings, labels, statistics and event log; its connection's transcript and events carry on once it is connected. Give port if the device is at another path here, and name if the exported one is taken. Stays suspended until connect_session unless connect is true")]
+    async fn import_session(&self, Parameters(args): Parameters<ImportSessionArgs>) -> Result<CallToolResult, McpError> {
+        let export = SessionExport::parse(&args.document).map_err(session_error)?;
+        let (transcript, events) = (export.history.transcript.len(), export.history.events.len());
+        let history = export.history.into_history().map_err(session_error)?;
+        let mut saved = export.session;
+        if let Some(port) = &args.port {
+            saved.config.port_name = self.device_path(port).0;
+        }
+        if let Some(name) = args.name {
+            saved.config.name = Some(name).filter(|name| !name.is_empty());
+        }
+        let origin = format!(
+            "Imported from session {} exported at {}",
+            export.session_id,
+            export.exported_at.format("%Y-%m-%d %H:%M:%S UTC")
+        );
+        let session_id = self.sessions.import_session(saved, origin).await.map_err(session_error)?;
+        self.imported.lock().await.insert(session_id.clone(), history);
+        info!("Imported session {} as {}", export.session_id, session_id);
+
+        if args.connect {
+            if let Err(e) = self.sessions.connect_session(&session_id).await {
+                let error_msg = format!("Error: Session {} imported but not connected - {}; retry with connect_session", session_id, e);
+                return Err(McpError::internal_error(error_msg, None));
+            }
+            self.carry_imported_history(&session_id).await;
+        }
+        let info = self.session_info_of(&session_id).await?;
+        let message = format!(
+            "Session imported\n{}\nImported from: {} ({} transcript entries, {} connection events)",
+            format_session(&info), export.session_id, transcript, events
+        );
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+
+    #[tool(description = "Set or remove a session's key/value labels, e.g. device=relay-board or site=lab2, to find it by with list_sessions. Labels show in session and connection status and are saved with created sessions")]
+    async fn label_session(&self, Parameters(args): Parameters<LabelSessionArgs>) -> Result<CallToolResult, McpError> {
+        let session_id = self.sessions.resolve(&args.session_id).await.map_err(session_error)?;
+        let labels = self.sessions.label_session(&session_id, args.labels, &args.remove).await.map_err(session_error)?;
+        info!("Labelled session {}", session_id);
+
+        let labels = format_labels(&labels);
+        let message = format!("Session labelled\nSession ID: {}\nLabels: {}", session_id, if labels.is_empty() { "none" } else { &labels });
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+
+    #[tool(description = "Set the filters a session's received data passes through, in order, before read tools and the transcript see it: strip_ansi removes colour and cursor escape sequences, normalize_crlf turns CRLF and CR into LF, drop_lines:<regex> drops whole lines matching the pattern, such as heartbeats. Applies to data received from now on and to later connections; give no filters to remove them")]
+    async fn set_receive_filters(&self, Parameters(args): Parameters<SetReceiveFiltersArgs>) -> Result<CallToolResult, McpError> {
+        let session_id = self.sessions.resolve(&args.session_id).await.map_err(session_error)?;
+        let filters = receive_filters(&args.filters)?;
+        self.sessions.set_receive_filters(&session_id, filters.clone()).await.map_err(session_error)?;
+        info!("Set receive filters of session {}", session_id);
+
+        let filters = if filters.is_empty() { "none".to_string() } else { format_receive_filters(&filters) };
+        let message = format!("Receive filters set
+Session ID: {}
+Receive filters: {}", session_id, filters);
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+
+    #[tool(description = "Show a session's lifecycle events with timestamps: created, connected, disconnected, errors, reconnect attempts, suspended and cleaned up, to find out why it ended up in the Error state or went away. Sessions removed lately are still found by ID")]
+    async fn session_events(&self, Parameters(args): Parameters<SessionEventsArgs>) -> Result<CallToolResult, McpError> {
+        let (events, info) = self.sessions.session_events(&args.session_id).await.map_err(session_error)?;
+        let mut header = match info {
+            Some(info) => format!("Session {} on {}, {}", info.session_id, info.port_name, info.state),
+            None => format!("Session {}, removed", args.session_id),
+        };
+        let shown = &events[events.len().saturating_sub(args.limit)..];
+        if shown.len() < events.len() {
+            header.push_str(&format!(" - last {} of {} event(s):", shown.len(), events.len()));
+        } else {
+            header.push_str(&format!(" - {} event(s):", events.len()));
+        }
+
+        let mut lines = vec![header];
+        for event in shown {
+            lines.push(format!("{} {}", event.timestamp.format("%Y-%m-%d %H:%M:%S%.3f"), event.message));
+        }
+        Ok(CallToolResult::success(vec![Content::text(lines.join("\n"))]))
+    }
+
+    #[tool(description = "Close a session's connection but keep the session, its settings and statistics, to connect again later with connect_session")]
+    async fn disconnect_session(&self, Parameters(args): Parameters<SessionArgs>) -> Result<CallToolResult, McpError> {
+        let session_id = self.sessions.resolve(&args.session_id).await.map_err(session_error)?;
+        let connection_id = self.session_disconnect(&session_id).await?;
+        info!("Disconnected session {}", session_id);
+
+        let message = format!("Session disconnected\nSession ID: {}\nClosed connection: {}", session_id, connection_id);
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+
+    #[tool(description = "Suspend a session: close its port so another program can use it for a while, keeping the session, its statistics and its connection's history. No shutdown commands are sent. resume_session reopens the port under the same connection ID")]
+    async fn suspend_session(&self, Parameters(args): Parameters<SessionArgs>) -> Result<CallToolResult, McpError> {
+        let session_id = self.sessions.resolve(&args.session_id).await.map_err(session_error)?;
+        let connection = self.sessions.get_session_connection(&session_id).await.map_err(session_error)?;
+        if let Some(origin) = connection.origin() {
+            let error_msg = format!("Error: Session {} shares the port with connection {}, which keeps it open", session_id, origin.id());
+            return Err(McpError::invalid_params(error_msg, None));
+        }
+        let connection_id = connection.id().to_string();
+        debug!("Suspending session {} on connection {}", session_id, connection_id);
+
+        let state = ConnectionState::of(self, &connection).await;
+        if let Err(e) = connection.stop_capture().await {
+            warn!("Capture of connection {} not closed: {}", connection_id, e);
+        }
+        self.release_connection_state(&connection_id).await;
+        self.sessions.suspend_session(&session_id).await.map_err(session_error)?;
+        connection.record_event("Suspended, port released").await;
+        let history = connection.take_history().await;
+        let _ = self.connection_manager.discard(&connection_id).await;
+
+        let mut warnings = Warnings::new();
+        let holders = Arc::strong_count(&connection) - 1;
+        if holders > 0 {
+            warnings.push(format!("The port stays open until {} other user(s) of the connection, such as a bridge, let go of it", holders));
+        }
+        drop(connection);
+        let port = state.config.port.clone();
+        self.suspended.lock().await.insert(session_id.clone(), SuspendedConnection { connection_id: connection_id.clone(), state, history });
+        info!("Suspended session {}, released {}", session_id, port);
+
+        let message = format!(
+            "Session suspended\nSession ID: {}\nReleased port: {}\nConnection ID: {} (kept for resume_session)",
+            session_id, port, connection_id
+        );
+        warnings.into_result(message)
+    }
+
+    #[tool(description = "Resume a suspended session: reopen its port under the connection ID it had, with the history, profile, protocol, framer, line mode and capture it had")]
+    async fn resume_session(&self, Parameters(args): Parameters<SessionArgs>) -> Result<CallToolResult, McpError> {
+        let session_id = self.sessions.resolve(&args.session_id).await.map_err(session_error)?;
+        let Some(suspended) = self.suspended.lock().await.remove(&session_id) else {
+            let error_msg = format!("Error: Session {} was not suspended with suspend_session; connect it with connect_session", session_id);
+            return Err(McpError::invalid_params(error_msg, None));
+        };
+        debug!("Resuming session {} on connection {}", session_id, suspended.connection_id);
+        if suspended.state.profile.is_some() {
+            // Resuming sends the profile's init commands again
+            if let Err(e) = self.check_tool_permitted("open_profile").await {
+                self.suspended.lock().await.insert(session_id, suspended);
+                return Err(e);
+            }
+        }
+
+        let connection = match self.connection_manager.reopen(&suspended.connection_id, suspended.state.config.clone()).await {
+            Ok(connection) => connection,
+            Err(e) => {
+                let error_msg = format!("Error: Failed to reopen {} - {}; the session stays suspended", suspended.state.config.port, e);
+                self.suspended.lock().await.insert(session_id, suspended);
+                return Err(McpError::internal_error(error_msg, None));
+            }
+        };
+        connection.continue_history(suspended.history).await;
+        let restored = self.restore_connection_state(&connection, &suspended.state).await;
+        if let Err(e) = self.sessions.reattach(&session_id, Arc::clone(&connection)).await {
+            // Removed meanwhile
+            let _ = self.connection_manager.close(&suspended.connection_id).await;
+            return Err(session_error(e));
+        }
+        connection.record_event("Resumed").await;
+        info!("Resumed session {}", session_id);
+
+        let info = self.session_info_of(&session_id).await?;
+        let mut message = format!("Session resumed\n{}", format_session(&info));
+        if !restored.is_empty() {
+            message.push_str(&format!("\nRestored: {}", restored.join(", ")));
+        }
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+
+    #[tool(description = "Remove a session, closing its connection if it has one")]
+    async fn remove_session(&self, Parameters(args): Parameters<SessionArgs>) -> Result<CallToolResult, McpError> {
+        let session_id = self.sessions.resolve(&args.session_id).await.map_err(session_error)?;
+        let info = self.session_info_of(&session_id).await?;
+        debug!("Removing session {}", session_id);
+
+        if let Some(connection_id) = &info.connection_id {
+            self.release_connection_state(connection_id).await;
+        }
+        self.sessions.remove_session(&session_id).await.map_err(session_error)?;
+        self.suspended.lock().await.remove(&session_id);
+        self.imported.lock().await.remove(&session_id);
+        info!("Removed session {}", session_id);
+
+        let mut message = format!("Session removed\nSession ID: {}\nTraffic: {}", session_id, format_stats(&info));
+        if let Some(connection_id) = info.connection_id {
+            message.push_str(&format!("\nClosed connection: {}", connection_id));
+        }
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+}
+
+impl SerialHandler {
+    async fn session_info_of(&self, session_id: &str) -> Result<SessionInfo, McpError> {
+        self.sessions.get_session_info(session_id).await.map_err(session_error)
+    }
+
+    /// Connect a session as connect_session does, returning its connection ID
+    pub(crate) async fn session_connect(&self, session_id: &str) -> Result<String, McpError> {
+        let connection_id = self.sessions.connect_session(session_id).await.map_err(|e| {
+            error!("Failed to connect session {}: {}", session_id, e);
+            McpError::internal_error(format!("Error: Failed to connect session {} - {}", session_id, e), None)
+        })?;
+        // A fresh connection instead of the suspended one
+        self.suspended.lock().await.remove(session_id);
+        if let (Ok(connection), Some(config)) =
+            (self.connection(&connection_id).await, self.sessions.connection_session_config(&connection_id).await)
+        {
+            self.apply_session_framing(&connection, &config).await;
+        }
+        self.carry_imported_history(session_id).await;
+        Ok(connection_id)
+    }
+
+    /// Disconnect a session as disconnect_session does, returning the connection closed
+    pub(crate) async fn session_disconnect(&self, session_id: &str) -> Result<String, McpError> {
+        let info = self.session_info_of(session_id).await?;
+        let Some(connection_id) = info.connection_id else {
+            return Err(McpError::invalid_params(format!("Error: Session {} is not connected", session_id), None));
+        };
+        debug!("Disconnecting session {} from connection {}", session_id, connection_id);
+
+        self.release_connection_state(&connection_id).await;
+        self.sessions.disconnect_session(session_id).await.map_err(session_error)?;
+        Ok(connection_id)
+    }
+
+    /// Transcript and events of a session's connection, or of the one it had
+    /// while suspended or before it was imported
+    async fn session_history(&self, session_id: &str) -> ExportedHistory {
+        if let Ok(connection) = self.sessions.get_session_connection(session_id).await {
+            return ExportedHistory::new(connection.recent_transcript(usize::MAX).await, connection.events().await);
+        }
+        if let Some(suspended) = self.suspended.lock().await.get(session_id) {
+            return ExportedHistory::new(suspended.history.recent_transcript(usize::MAX), suspended.history.events());
+        }
+        if let Some(history) = self.imported.lock().await.get(session_id) {
+            return ExportedHistory::new(history.recent_transcript(usize::MAX), history.events());
+        }
+        ExportedHistory::default()
+    }
+
+    /// Carry on an imported session's history in the connection it now has
+    async fn carry_imported_history(&self, session_id: &str) {
+        let Some(history) = self.imported.lock().await.remove(session_id) else {
+            return;
+        };
+        if let Ok(connection) = self.sessions.get_session_connection(session_id).await {
+            connection.continue_history(history).await;
+        }
+    }
+}
+
+/// Map a session manager error to a tool error
+pub(crate) fn session_error(e: SerialError) -> McpError {
+    match e {
+        SerialError::SessionNotFound(_)
+        | SerialError::SessionExists(_)
+        | SerialError::InvalidConnection(_)
+        | SerialError::InvalidConfig(_)
+        | SerialError::InvalidBaudRate(_)
+        | SerialError::InvalidDataBits(_)
+        | SerialError::InvalidStopBits(_)
+        | SerialError::InvalidParity(_)
+        | SerialError::InvalidFlowControl(_) => {
+            McpError::invalid_params(format!("Error: {}", e), None)
+        }
+        e => McpError::internal_error(format!("Error: {}", e), None),
+    }
+}
+
+/// Keepalive asked for at session creation, if any
+fn keepalive_config(args: &CreateSessionArgs, timeout_ms: u64) -> Result<Option<KeepaliveConfig>, McpError> {
+    let Some(interval_ms) = args.keepalive_interval_ms else {
+        let set = args.keepalive_probe.is_some() || args.keepalive_expect.is_some()
+            || args.keepalive_timeout_ms.is_some() || args.keepalive_line.is_some();
+        if set {
+            return Err(McpError::invalid_params("Error: Keepalive settings need keepalive_interval_ms", None));
+        }
+        return Ok(None);
+    };
+    let line = args.keepalive_line.as_deref()
+        .map(ModemLine::parse)
+        .transpose()
+        .map_err(|e| McpError::invalid_params(format!("Error: {}", e), None))?;
+    Ok(Some(KeepaliveConfig {
+        interval_ms,
+        probe: args.keepalive_probe.clone(),
+        expect: args.keepalive_expect.clone(),
+        timeout_ms: args.keepalive_timeout_ms.unwrap_or(timeout_ms),
+        line,
+    }))
+}
+
+/// Receive filters given by name, in order
+fn receive_filters(filters: &[String]) -> Result<Vec<ReceiveFilter>, McpError> {
+    filters
+        .iter()
+        .map(|filter| ReceiveFilter::parse(filter).map_err(|e| McpError::invalid_params(format!("Error: {}", e), None)))
+        .collect()
+}
+
+/// Write rate limit asked for at session creation, if any
+fn rate_limit(args: &CreateSessionArgs) -> Result<Option<RateLimit>, McpError> {
+    if args.rate_limit_writes_per_second.is_none() && args.rate_limit_bytes_per_second.is_none() {
+        return Ok(None);
+    }
+    let limit = RateLimit {
+        writes_per_second: args.rate_limit_writes_per_second,
+        bytes_per_second: args.rate_limit_bytes_per_second,
+    };
+    limit.validate().map_err(|e| McpError::invalid_params(format!("Error: {}", e), None))?;
+    Ok(Some(limit))
+}
+
+/// Summary of a session as `Key: value` lines
+fn format_session(info: &SessionInfo) -> String {
+    let mut lines = vec![format!("Session ID: {}", info.session_id)];
+    if let Some(name) = &info.config.name {
+        lines.push(format!("Name: {}", name));
+    }
+    lines.push(format!("Port: {}", info.port_name));
+    if !info.config.failover_ports.is_empty() {
+        lines.push(format!("Failover order: {}", info.config.candidate_ports().join(", ")));
+    }
+    lines.push(format!("Baud rate: {}", info.config.baud_rate));
+    lines.push(format!("State: {}", info.state));
+    if let Some(connection_id) = &info.connection_id {
+        lines.push(format!("Connection ID: {}", connection_id));
+    }
+    if let Some(encoding) = &info.config.encoding {
+        lines.push(format!("Encoding: {}", encoding));
+    }
+    if let Some(device) = &info.device {
+        lines.push(format!("Device: {}", device));
+    }
+    if info.config.auto_reconnect {
+        lines.push(format!("Auto-reconnect: up to {} attempts", info.config.max_reconnect_attempts));
+    }
+    if !info.config.receive_filters.is_empty() {
+        lines.push(format!("Receive filters: {}", format_receive_filters(&info.config.receive_filters)));
+    }
+    if let Some(group) = &info.config.group {
+        lines.push(format!("Group: {}", group));
+    }
+    if !info.config.labels.is_empty() {
+        lines.push(format!("Labels: {}", format_labels(&info.config.labels)));
+    }
+    if let Some(keepalive) = &info.config.keepalive {
+        lines.push(format!("Keepalive: {}", keepalive));
+    }
+    if let Some(rate_limit) = &info.config.rate_limit {
+        lines.push(format!("Rate limit: {}", rate_limit));
+    }
+    lines.join("\n")
+}
+
+pub(crate) fn format_stats(info: &SessionInfo) -> String {
+    let stats = &info.stats;
+    format!(
+        "sent {} bytes in {} writes, received {} bytes in {} reads, {} errors, idle {}s",
+        stats.bytes_sent, stats.messages_sent, stats.bytes_received, stats.messages_received, stats.errors_count, info.idle_seconds
+    )
+}
@@ -0,0 +1,177 @@
+//! CAN tools for SLCAN adapters
+//!
+//! Open a LAWICEL/SLCAN USB-CAN adapter's channel at a CAN bitrate, send
+//! frames and collect received frames decoded into ID, DLC and data.
+
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rmcp::{
+    handler::server::tool::Parameters,
+    model::*,
+    tool, tool_router, ErrorData as McpError,
+};
+use tokio::time::Instant;
+use tracing::{debug, error, info};
+
+use super::serial_handler::SerialHandler;
+use super::types::*;
+use super::warnings::Warnings;
+use crate::protocol::slcan::{self, bitrate_command, split_messages, CanFrame, SlcanError, SlcanMessage, MAX_STANDARD_ID};
+use crate::serial::{LocalSerialError, SerialConnection};
+
+#[tool_router(router = slcan_router, vis = "pub(crate)")]
+impl SerialHandler {
+    #[tool(description = "Open the CAN channel of an SLCAN (LAWICEL) USB-CAN adapter at a bitrate of 10000, 20000, 50000, 100000, 125000, 250000, 500000, 800000 or 1000000 bit/s. listen_only opens it without acknowledging or sending frames; timestamps adds the adapter's millisecond time stamps to received frames. A channel already open is closed first")]
+    async fn can_open(&self, Parameters(args): Parameters<CanOpenArgs>) -> Result<CallToolResult, McpError> {
+        let bitrate = bitrate_command(args.bitrate).map_err(|e| McpError::invalid_params(format!("Error: {}", e), None))?;
+        let connection = self.can_connection(&args.connection_id).await?;
+        debug!("Opening CAN channel at {} bit/s on connection {}", args.bitrate, args.connection_id);
+
+        // Closing a closed channel is an error, and the only one to expect here
+        let _ = slcan::command(&connection, "C", args.timeout_ms).await;
+        // Not every adapter knows Z, so it is only sent when asked for
+        let mut commands = vec![bitrate.as_str()];
+        if args.timestamps {
+            commands.push("Z1");
+        }
+        commands.push(if args.listen_only { "L" } else { "O" });
+        for command in commands {
+            slcan::command(&connection, command, args.timeout_ms)
+                .await
+                .map_err(|e| can_failed(&args.connection_id, "Opening the CAN channel", e))?;
+        }
+
+        let mode = if args.listen_only { "listen only" } else { "normal" };
+        info!("Opened CAN channel at {} bit/s ({}) on connection {}", args.bitrate, mode, args.connection_id);
+        connection.record_event(format!("CAN channel opened at {} bit/s, {}", args.bitrate, mode)).await;
+
+        let message = format!(
+            "CAN channel open\nConnection ID: {}\nBitrate: {} bit/s\nMode: {}\nTime stamps: {}",
+            args.connection_id,
+            args.bitrate,
+            mode,
+            if args.timestamps { "on" } else { "off" }
+        );
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+
+    #[tool(description = "Close the CAN channel of an SLCAN adapter, so it stops sending and receiving frames; the bitrate can only be changed while closed")]
+    async fn can_close(&self, Parameters(args): Parameters<CanCloseArgs>) -> Result<CallToolResult, McpError> {
+        let connection = self.can_connection(&args.connection_id).await?;
+        slcan::command(&connection, "C", args.timeout_ms)
+            .await
+            .map_err(|e| can_failed(&args.connection_id, "Closing the CAN channel", e))?;
+        connection.record_event("CAN channel closed").await;
+
+        let message = format!("CAN channel closed\nConnection ID: {}", args.connection_id);
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+
+    #[tool(description = "Send a CAN frame through an SLCAN adapter with an open channel. IDs above 0x7FF are sent as 29-bit extended IDs unless extended is given; data is hex, up to 8 bytes. Remote frames (rtr) carry no data and request dlc bytes. Frames received while waiting for the adapter are included in the result")]
+    async fn can_send(&self, Parameters(args): Parameters<CanSendArgs>) -> Result<CallToolResult, McpError> {
+        let invalid = |e: String| McpError::invalid_params(format!("Error: {}", e), None);
+        let data = match &args.data {
+            Some(data) => decode_data(data, "hex").map_err(invalid)?,
+            None => Vec::new(),
+        };
+        let dlc = match args.dlc {
+            Some(dlc) => dlc,
+            None => u8::try_from(data.len()).map_err(|_| invalid(format!("{} data bytes is more than 8", data.len())))?,
+        };
+        let extended = args.extended.unwrap_or(args.id > MAX_STANDARD_ID);
+        let frame = CanFrame::new(args.id, extended, args.rtr, dlc, data).map_err(invalid)?;
+        let connection = self.can_connection(&args.connection_id).await?;
+        debug!("Sending CAN frame {} on connection {}", frame.encode(), args.connection_id);
+
+        let received = slcan::command(&connection, &frame.encode(), args.timeout_ms)
+            .await
+            .map_err(|e| can_failed(&args.connection_id, "CAN send", e))?;
+
+        let mut message = format!(
+            "CAN frame sent\nConnection ID: {}\nFrame: {}",
+            args.connection_id,
+            serde_json::to_string(&frame).unwrap_or_default()
+        );
+        if !received.is_empty() {
+            message.push_str(&format!(
+                "\nReceived while sending: {}\n{}",
+                received.len(),
+                serde_json::to_string_pretty(&received).unwrap_or_default()
+            ));
+        }
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+
+    #[tool(description = "Collect CAN frames received by an SLCAN adapter with an open channel for a while and return them as JSON with ID, extended flag, RTR flag, DLC, data and the adapter's time stamp if enabled. Frames received before the call are included")]
+    async fn can_read(&self, Parameters(args): Parameters<CanReadArgs>) -> Result<CallToolResult, McpError> {
+        let connection = self.can_connection(&args.connection_id).await?;
+        debug!("Collecting CAN frames on connection {} for {}ms", args.connection_id, args.duration_ms);
+
+        let max = args.max_frames.max(1);
+        let mut warnings = Warnings::new();
+        let frames = collect_frames(&connection, Duration::from_millis(args.duration_ms), max, &mut warnings)
+            .await
+            .map_err(|e| {
+                error!("Failed to read CAN frames from connection {}: {}", args.connection_id, e);
+                McpError::internal_error(format!("Error: Data reading failed - {}", e), None)
+            })?;
+
+        let message = format!(
+            "CAN frames read\nConnection ID: {}\nFrames: {}\n{}",
+            args.connection_id,
+            frames.len(),
+            serde_json::to_string_pretty(&frames).unwrap_or_default()
+        );
+        warnings.into_result(message)
+    }
+}
+
+impl SerialHandler {
+    /// The connection, provided its reads are raw bytes; replies end in a carriage return
+    async fn can_connection(&self, connection_id: &str) -> Result<Arc<SerialConnection>, McpError> {
+        let connection = self.connection(connection_id).await?;
+        if connection.is_line_mode().await || connection.framer_name().await.is_some() {
+            let error_msg = format!("Error: Connection {} has line mode or a framer set, disable it to use an SLCAN adapter", connection_id);
+            return Err(McpError::invalid_params(error_msg, None));
+        }
+        Ok(connection)
+    }
+}
+
+fn can_failed(connection_id: &str, action: &str, e: SlcanError) -> McpError {
+    error!("{} on connection {} failed: {}", action, connection_id, e);
+    McpError::internal_error(format!("Error: {} failed - {}", action, e), None)
+}
+
+/// Collect up to `max` frames within `duration`, noting adapter errors and unparsable lines
+async fn collect_frames(
+    connection: &SerialConnection,
+    duration: Duration,
+    max: usize,
+    warnings: &mut Warnings,
+) -> Result<Vec<CanFrame>, LocalSerialError> {
+    let deadline = Instant::now() + duration;
+    let mut frames = Vec::new();
+    let mut pending = String::new();
+
+    connection
+        .read_until(deadline, |chunk| {
+            pending.push_str(&String::from_utf8_lossy(chunk));
+            let (messages, used) = split_messages(&pending);
+            pending.drain(..used);
+            for message in messages {
+                match message {
+                    SlcanMessage::Frame(frame) if frames.len() < max => frames.push(frame),
+                    SlcanMessage::Invalid(e) => warnings.push(format!("Frame rejected: {}", e)),
+                    SlcanMessage::Error => warnings.push("Adapter reported an error (BEL), e.g. a bus error or full buffer"),
+                    _ => {}
+                }
+            }
+            frames.len() == max
+        })
+        .await?;
+
+    Ok(frames)
+}
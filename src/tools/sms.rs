@@ -0,0 +1,140 @@
+//! SMS tools for GSM modems
+//!
+//! Send text messages and list, read and delete the messages stored on a
+//! modem over AT commands, including the `>` prompt and Ctrl-Z that end a
+//! message body.
+
+use std::future::Future;
+
+use rmcp::{
+    handler::server::tool::Parameters,
+    model::*,
+    tool, tool_router, ErrorData as McpError,
+};
+use tracing::{debug, error, info};
+
+use super::serial_handler::SerialHandler;
+use super::types::*;
+use crate::protocol::at::{self, AtError, CTRL_Z};
+use crate::protocol::sms::{encode_submit, is_phone_number, parse_message, parse_message_list, SmsMode, SmsStatus};
+use crate::serial::SerialConnection;
+
+#[tool_router(router = sms_router, vis = "pub(crate)")]
+impl SerialHandler {
+    #[tool(description = "Send an SMS through a GSM modem. Text mode (AT+CMGF=1) passes the text as is; PDU mode (AT+CMGF=0) encodes it as GSM 7-bit, or UCS-2 for other characters. Waits for the > prompt, submits with Ctrl-Z and returns the network's message reference")]
+    async fn sms_send(&self, Parameters(args): Parameters<SmsSendArgs>) -> Result<CallToolResult, McpError> {
+        let mode = SmsMode::parse(&args.mode).map_err(|e| McpError::invalid_params(format!("Error: {}", e), None))?;
+        if !is_phone_number(&args.number) {
+            let error_msg = format!("Error: {:?} is not a phone number, use digits with an optional leading +", args.number);
+            return Err(McpError::invalid_params(error_msg, None));
+        }
+        if args.text.contains([CTRL_Z as char, '\u{1b}']) {
+            return Err(McpError::invalid_params("Error: Message text cannot contain Ctrl-Z or ESC".to_string(), None));
+        }
+        let pdu = match mode {
+            SmsMode::Pdu => Some(encode_submit(&args.number, &args.text).map_err(|e| McpError::invalid_params(format!("Error: {}", e), None))?),
+            SmsMode::Text => None,
+        };
+
+        let connection = self.raw_connection(&args.connection_id, "use AT commands").await?;
+        debug!("Sending SMS to {} on connection {} in {} mode", args.number, args.connection_id, mode);
+        at::command(&connection, &format!("AT+CMGF={}", mode.cmgf()), args.timeout_ms)
+            .await
+            .map_err(|e| sms_failed(&args.connection_id, "Selecting SMS mode", e))?;
+        let response = match &pdu {
+            Some(pdu) => at::submit(&connection, &format!("AT+CMGS={}", pdu.length), &pdu.hex, args.timeout_ms).await,
+            None => at::submit(&connection, &format!("AT+CMGS=\"{}\"", args.number), &args.text, args.timeout_ms).await,
+        };
+        let response = match response {
+            Ok(response) => response,
+            Err(e) => {
+                connection.record_event(format!("SMS to {} failed: {}", args.number, e)).await;
+                return Err(sms_failed(&args.connection_id, "SMS send", e));
+            }
+        };
+
+        let reference = response.values("+CMGS:").next().unwrap_or("not reported").to_string();
+        info!("Sent SMS to {} on connection {}, reference {}", args.number, args.connection_id, reference);
+        connection.record_event(format!("SMS sent to {} (reference {})", args.number, reference)).await;
+
+        let mut message = format!("SMS sent\nConnection ID: {}\nTo: {}\nMode: {}", args.connection_id, args.number, mode);
+        if let Some(pdu) = &pdu {
+            message.push_str(&format!("\nEncoding: {}", pdu.encoding));
+        }
+        message.push_str(&format!("\nMessage reference: {}", reference));
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+
+    #[tool(description = "List SMS messages stored on a GSM modem (AT+CMGL in text mode) as JSON with index, status, number, time stamp and text. Most modems mark unread messages read once listed")]
+    async fn sms_list(&self, Parameters(args): Parameters<SmsListArgs>) -> Result<CallToolResult, McpError> {
+        let status = SmsStatus::parse(&args.status).map_err(|e| McpError::invalid_params(format!("Error: {}", e), None))?;
+        let connection = self.raw_connection(&args.connection_id, "use AT commands").await?;
+        debug!("Listing {} SMS messages on connection {}", status, args.connection_id);
+
+        let response = text_mode_command(&connection, &format!("AT+CMGL=\"{}\"", status.cmgl()), args.timeout_ms)
+            .await
+            .map_err(|e| sms_failed(&args.connection_id, "SMS listing", e))?;
+        let messages = parse_message_list(&response);
+
+        let message = format!(
+            "SMS messages ({})\nConnection ID: {}\nMessages: {}\n{}",
+            status,
+            args.connection_id,
+            messages.len(),
+            serde_json::to_string_pretty(&messages).unwrap_or_default()
+        );
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+
+    #[tool(description = "Read one SMS message stored on a GSM modem by its index (AT+CMGR in text mode). Reading an unread message marks it read")]
+    async fn sms_read(&self, Parameters(args): Parameters<SmsReadArgs>) -> Result<CallToolResult, McpError> {
+        let connection = self.raw_connection(&args.connection_id, "use AT commands").await?;
+        debug!("Reading SMS {} on connection {}", args.index, args.connection_id);
+
+        let response = text_mode_command(&connection, &format!("AT+CMGR={}", args.index), args.timeout_ms)
+            .await
+            .map_err(|e| sms_failed(&args.connection_id, "SMS read", e))?;
+        let Some(sms) = parse_message(args.index, &response) else {
+            let message = format!("No SMS message at index {}\nConnection ID: {}", args.index, args.connection_id);
+            return Ok(CallToolResult::success(vec![Content::text(message)]));
+        };
+
+        let message = format!(
+            "SMS message\nConnection ID: {}\n{}",
+            args.connection_id,
+            serde_json::to_string_pretty(&sms).unwrap_or_default()
+        );
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+
+    #[tool(description = "Delete an SMS message stored on a GSM modem by its index, or every stored message with all (AT+CMGD)")]
+    async fn sms_delete(&self, Parameters(args): Parameters<SmsDeleteArgs>) -> Result<CallToolResult, McpError> {
+        let (command, deleted) = match (args.index, args.all) {
+            (Some(index), false) => (format!("AT+CMGD={}", index), format!("SMS message {}", index)),
+            // Delete flag 4: every message in the storage, whatever its status
+            (None, true) => ("AT+CMGD=1,4".to_string(), "All SMS messages".to_string()),
+            _ => return Err(McpError::invalid_params("Error: Give either an index or all, not both".to_string(), None)),
+        };
+        let connection = self.raw_connection(&args.connection_id, "use AT commands").await?;
+        debug!("Deleting {} on connection {}", deleted, args.connection_id);
+
+        at::command(&connection, &command, args.timeout_ms)
+            .await
+            .map_err(|e| sms_failed(&args.connection_id, "SMS delete", e))?;
+        connection.record_event(format!("{} deleted", deleted)).await;
+
+        let message = format!("{} deleted\nConnection ID: {}", deleted, args.connection_id);
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+}
+
+/// Switch the modem to text mode and run `command`
+async fn text_mode_command(connection: &SerialConnection, command: &str, timeout_ms: u64) -> Result<at::AtResponse, AtError> {
+    at::command(connection, &format!("AT+CMGF={}", SmsMode::Text.cmgf()), timeout_ms).await?;
+    at::command(connection, command, timeout_ms).await
+}
+
+fn sms_failed(connection_id: &str, action: &str, e: AtError) -> McpError {
+    error!("{} on connection {} failed: {}", action, connection_id, e);
+    McpError::internal_error(format!("Error: {} failed - {}", action, e), None)
+}
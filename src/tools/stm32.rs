@@ -0,0 +1,227 @@
+//! STM32 system bootloader tools
+//!
+//! Identify, read, erase and program a part started in its ROM bootloader
+//! (BOOT0 high at reset), as stm32flash does over a USART.
+
+use std::future::Future;
+use std::time::Instant;
+
+use rmcp::{
+    handler::server::tool::Parameters,
+    model::*,
+    tool, tool_router, ErrorData as McpError,
+};
+use tracing::{debug, error, info};
+
+use super::serial_handler::SerialHandler;
+use super::types::*;
+use super::verify::compare;
+use super::warnings::Warnings;
+use super::ymodem::read_local_file;
+use crate::protocol::stm32::{command_name, product_name, Erase, Stm32Bootloader, Stm32Error};
+use crate::serial::{Parity, SerialConnection};
+use crate::utils::BufferUtils;
+
+/// Most bytes read into a file, or written from one
+const MAX_TRANSFER: u64 = 4 * 1024 * 1024;
+
+/// Most bytes returned as a hex dump
+const MAX_DUMP: u32 = 4096;
+
+pub(super) const PARITY_HINT: &str = "The STM32 bootloader expects even parity; reopen the connection with parity even";
+
+#[tool_router(router = stm32_router, vis = "pub(crate)")]
+impl SerialHandler {
+    #[tool(description = "Identify an STM32 started in its system bootloader (BOOT0 high at reset): bootloader version, supported commands and product ID. Sends the 0x7F autobaud byte first, which is harmless if the bootloader is already running. The connection must be raw and 8 data bits with even parity")]
+    async fn stm32_info(&self, Parameters(args): Parameters<Stm32InfoArgs>) -> Result<CallToolResult, McpError> {
+        let connection = self.raw_connection(&args.connection_id, "use the STM32 bootloader").await?;
+        let _exchange = connection.begin_exchange().await;
+        let warnings = parity_warnings(&connection);
+        let mut bootloader = Stm32Bootloader::new(&connection);
+
+        let result = async {
+            bootloader.init().await?;
+            let info = bootloader.get().await?;
+            let pid = bootloader.get_id().await?;
+            Ok((info, pid))
+        }
+        .await;
+        let (info, pid) = result.map_err(|e| bootloader_error(e, &connection, &args.connection_id, "query", None))?;
+
+        let product = product_name(pid).map_or_else(String::new, |name| format!(" ({})", name));
+        let commands: Vec<String> = info.commands.iter().map(|&code| command_name(code)).collect();
+        let message = format!(
+            "STM32 bootloader\nConnection ID: {}\nBootloader version: {}.{}\nProduct ID: 0x{:03X}{}\nCommands: {}",
+            args.connection_id,
+            info.version >> 4,
+            info.version & 0x0F,
+            pid,
+            product,
+            commands.join(", ")
+        );
+        warnings.into_result(message)
+    }
+
+    #[tool(description = "Read memory through the STM32 system bootloader, e.g. flash from 0x08000000. Saved to path when given, otherwise returned as a hex dump of at most 4096 bytes. Refused while readout protection is active. The connection must be raw and 8 data bits with even parity")]
+    async fn stm32_read(&self, Parameters(args): Parameters<Stm32ReadArgs>) -> Result<CallToolResult, McpError> {
+        let limit = if args.path.is_some() { MAX_TRANSFER as u32 } else { MAX_DUMP };
+        if args.length == 0 || args.length > limit {
+            return Err(McpError::invalid_params(format!("Error: length must be between 1 and {}", limit), None));
+        }
+        let connection = self.raw_connection(&args.connection_id, "use the STM32 bootloader").await?;
+        debug!("Reading {} bytes at 0x{:08X} through the STM32 bootloader on connection {}", args.length, args.address, args.connection_id);
+        let _exchange = connection.begin_exchange().await;
+        let warnings = parity_warnings(&connection);
+        let mut bootloader = Stm32Bootloader::new(&connection);
+
+        let mut data = Vec::new();
+        let result = async {
+            bootloader.init().await?;
+            bootloader.read(args.address, args.length as usize, &mut data).await
+        }
+        .await;
+        if let Err(e) = result {
+            let progress = format!("{} of {} bytes were read", data.len(), args.length);
+            return Err(bootloader_error(e, &connection, &args.connection_id, "read", Some(progress)));
+        }
+
+        let mut message = format!("STM32 read complete\nConnection ID: {}\nAddress: 0x{:08X}\nBytes: {}", args.connection_id, args.address, data.len());
+        match &args.path {
+            Some(path) => {
+                std::fs::write(path, &data).map_err(|e| McpError::internal_error(format!("Error: Failed to write {} - {}", path, e), None))?;
+                message.push_str(&format!("\nSaved to: {}", path));
+            }
+            None => message.push_str(&format!("\nOffsets from the address:\n{}", BufferUtils::hexdump(&data).trim_end())),
+        }
+        warnings.into_result(message)
+    }
+
+    #[tool(description = "Erase flash through the STM32 system bootloader: the listed pages (sectors on F2/F4/F7), or everything with mass. Page numbers and sizes depend on the part; flash must be erased before stm32_write. The connection must be raw and 8 data bits with even parity")]
+    async fn stm32_erase(&self, Parameters(args): Parameters<Stm32EraseArgs>) -> Result<CallToolResult, McpError> {
+        let erase = match (args.mass, args.pages.is_empty()) {
+            (true, true) => Erase::Mass,
+            (false, false) => Erase::Pages(args.pages.clone()),
+            _ => return Err(McpError::invalid_params("Error: Give either pages or mass".to_string(), None)),
+        };
+        let connection = self.raw_connection(&args.connection_id, "use the STM32 bootloader").await?;
+        debug!("Erasing flash through the STM32 bootloader on connection {}", args.connection_id);
+        let _exchange = connection.begin_exchange().await;
+        let warnings = parity_warnings(&connection);
+        let mut bootloader = Stm32Bootloader::new(&connection);
+
+        let started = Instant::now();
+        let result = async {
+            bootloader.init().await?;
+            let info = bootloader.get().await?;
+            bootloader.erase(&info, &erase).await
+        }
+        .await;
+        result.map_err(|e| bootloader_error(e, &connection, &args.connection_id, "erase", None))?;
+
+        let what = match &erase {
+            Erase::Mass => "all flash".to_string(),
+            Erase::Pages(pages) => format!("{} pages", pages.len()),
+        };
+        info!("Erased {} through the STM32 bootloader on connection {}", what, args.connection_id);
+        connection.record_event(format!("STM32 erase: {}", what)).await;
+        let message = format!(
+            "STM32 erase complete\nConnection ID: {}\nErased: {}\nTime: {:.1}s",
+            args.connection_id,
+            what,
+            started.elapsed().as_secs_f64()
+        );
+        warnings.into_result(message)
+    }
+
+    #[tool(description = "Program a binary image through the STM32 system bootloader, by default at the start of flash (0x08000000), in 256-byte blocks. Erase the pages it covers first with stm32_erase. verify (default) reads the image back to compare; go then starts it by jumping to address. The connection must be raw and 8 data bits with even parity")]
+    async fn stm32_write(&self, Parameters(args): Parameters<Stm32WriteArgs>) -> Result<CallToolResult, McpError> {
+        let (name, image) = read_local_file(&args.path, "STM32", MAX_TRANSFER)?;
+        if image.is_empty() {
+            return Err(McpError::invalid_params(format!("Error: {} is empty", args.path), None));
+        }
+        if args.address % 4 != 0 {
+            return Err(McpError::invalid_params(format!("Error: Address 0x{:08X} is not word aligned", args.address), None));
+        }
+        let connection = self.raw_connection(&args.connection_id, "use the STM32 bootloader").await?;
+        debug!("Writing {} ({} bytes) through the STM32 bootloader on connection {}", name, image.len(), args.connection_id);
+        let _exchange = connection.begin_exchange().await;
+        let warnings = parity_warnings(&connection);
+        let mut bootloader = Stm32Bootloader::new(&connection);
+
+        let started = Instant::now();
+        let mut written = 0;
+        let result = async {
+            bootloader.init().await?;
+            bootloader.write(args.address, &image, &mut |bytes| written = bytes).await?;
+            if args.verify {
+                verify(&mut bootloader, args.address, &image).await?;
+            }
+            if args.go {
+                bootloader.go(args.address).await?;
+            }
+            Ok(())
+        }
+        .await;
+        if let Err(e) = result {
+            connection.record_event(format!("STM32 write failed: {}", e)).await;
+            let progress = format!("{} of {} bytes were written", written, image.len());
+            return Err(bootloader_error(e, &connection, &args.connection_id, "write", Some(progress)));
+        }
+
+        info!("Wrote {} bytes through the STM32 bootloader on connection {}", image.len(), args.connection_id);
+        connection.record_event(format!("STM32 write: {} ({} bytes) at 0x{:08X}", name, image.len(), args.address)).await;
+        let mut message = format!(
+            "STM32 write complete\nConnection ID: {}\nFile: {}\nAddress: 0x{:08X}\nBytes written: {}\nTime: {:.1}s",
+            args.connection_id,
+            name,
+            args.address,
+            image.len(),
+            started.elapsed().as_secs_f64()
+        );
+        if args.verify {
+            message.push_str("\nVerified: yes");
+        }
+        if args.go {
+            message.push_str(&format!("\nStarted the code at 0x{:08X}", args.address));
+        }
+        warnings.into_result(message)
+    }
+}
+
+/// Read `image` back from `address`, failing with where it differs
+pub(super) async fn verify(bootloader: &mut Stm32Bootloader<'_>, address: u32, image: &[u8]) -> Result<(), Stm32Error> {
+    let mut readback = Vec::new();
+    bootloader.read(address, image.len(), &mut readback).await?;
+    match compare(address as u64, image, &readback) {
+        Some(mismatch) => Err(Stm32Error::Protocol(mismatch.to_string())),
+        None => Ok(()),
+    }
+}
+
+/// Whether the line is set up the way the bootloader needs
+pub(super) fn has_even_parity(connection: &SerialConnection) -> bool {
+    connection.config().parity == Parity::Even
+}
+
+fn parity_warnings(connection: &SerialConnection) -> Warnings {
+    let mut warnings = Warnings::new();
+    if !has_even_parity(connection) {
+        warnings.push(PARITY_HINT);
+    }
+    warnings
+}
+
+fn bootloader_error(e: Stm32Error, connection: &SerialConnection, connection_id: &str, operation: &str, progress: Option<String>) -> McpError {
+    error!("STM32 {} on connection {} failed: {}", operation, connection_id, e);
+    let mut error_msg = format!("Error: STM32 {} failed - {}", operation, e);
+    if let Some(progress) = progress {
+        error_msg.push_str(&format!("\n{}", progress));
+    }
+    match e {
+        Stm32Error::Timeout { .. } if !has_even_parity(connection) => error_msg.push_str(&format!("\n{}", PARITY_HINT)),
+        Stm32Error::Timeout { .. } => error_msg.push_str("\nIs the part in its system bootloader (BOOT0 high at reset)?"),
+        Stm32Error::Nack { .. } if operation == "read" => error_msg.push_str("\nReadout protection may be active"),
+        _ => {}
+    }
+    McpError::internal_error(error_msg, None)
+}
@@ -0,0 +1,168 @@
+//! Received data subscriptions
+//!
+//! A subscription pushes a connection's received data to the client as logging
+//! notifications instead of waiting for read calls. Chunks arriving within the
+//! coalescing window are merged into one notification that keeps each chunk's
+//! timestamp, so fast devices do not flood the client with tiny messages.
+
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rmcp::{
+    handler::server::tool::Parameters,
+    model::*,
+    tool, tool_router, ErrorData as McpError, Peer, RoleServer,
+};
+use serde::Serialize;
+use tracing::{debug, info, warn};
+
+use super::serial_handler::SerialHandler;
+use super::types::*;
+use crate::config::MAX_SUBSCRIPTION_COALESCE_MS;
+use crate::serial::{ConnectionManager, LocalSerialError, RxChunk};
+
+/// Chunks merged into one notification at most
+const SUBSCRIPTION_MAX_CHUNKS: usize = 256;
+
+/// Bytes merged into one notification at most
+const SUBSCRIPTION_MAX_BYTES: usize = 64 * 1024;
+
+/// How long the subscription waits for data before checking the connection is still open
+const SUBSCRIPTION_POLL_MS: u64 = 1000;
+
+/// Payload of a data notification
+#[derive(Debug, Serialize)]
+struct DataNotification {
+    connection_id: String,
+    frames: Vec<NotifiedFrame>,
+}
+
+/// One received chunk within a data notification
+#[derive(Debug, Serialize)]
+struct NotifiedFrame {
+    timestamp: String,
+    /// Lossy UTF-8 rendering
+    text: String,
+    /// Exact bytes
+    hex: String,
+}
+
+impl From<&RxChunk> for NotifiedFrame {
+    fn from(chunk: &RxChunk) -> Self {
+        Self {
+            timestamp: chunk.timestamp.to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            text: String::from_utf8_lossy(&chunk.data).into_owned(),
+            hex: hex::encode(&chunk.data),
+        }
+    }
+}
+
+#[tool_router(router = subscription_router, vis = "pub(crate)")]
+impl SerialHandler {
+    #[tool(description = "Subscribe to a connection's received data. Data is pushed as logging notifications (logger 'serial-data') instead of being read with read tools; chunks arriving within coalesce_ms are merged into one notification with per-chunk timestamps. Subscribed data is consumed from the receive buffer")]
+    async fn subscribe(&self, Parameters(args): Parameters<SubscribeArgs>, peer: Peer<RoleServer>) -> Result<CallToolResult, McpError> {
+        let connection = self.connection(&args.connection_id).await?;
+        if connection.is_line_mode().await {
+            let error_msg = format!("Error: Connection {} is in line mode, disable it to subscribe", args.connection_id);
+            return Err(McpError::invalid_params(error_msg, None));
+        }
+
+        let coalesce_ms = args.coalesce_ms.unwrap_or(self.config.serial.subscription_coalesce_ms);
+        if coalesce_ms > MAX_SUBSCRIPTION_COALESCE_MS {
+            let error_msg = format!("Error: coalesce_ms must be at most {}", MAX_SUBSCRIPTION_COALESCE_MS);
+            return Err(McpError::invalid_params(error_msg, None));
+        }
+
+        let task = tokio::spawn(run_subscription(
+            peer,
+            Arc::clone(&self.connection_manager),
+            args.connection_id.clone(),
+            Duration::from_millis(coalesce_ms),
+        ));
+        if let Some(previous) = self.subscriptions.lock().await.insert(args.connection_id.clone(), task.abort_handle()) {
+            previous.abort();
+        }
+        connection.record_event(format!("Subscribed (coalescing window {}ms)", coalesce_ms)).await;
+
+        info!("Subscribed to connection {} with {}ms coalescing", args.connection_id, coalesce_ms);
+        let message = format!(
+            "Subscribed\nConnection ID: {}\nCoalescing window: {}ms\nReceived data arrives as 'serial-data' logging notifications",
+            args.connection_id, coalesce_ms
+        );
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+
+    #[tool(description = "Stop pushing a connection's received data as notifications; read tools see new data again")]
+    async fn unsubscribe(&self, Parameters(args): Parameters<UnsubscribeArgs>) -> Result<CallToolResult, McpError> {
+        let Some(task) = self.subscriptions.lock().await.remove(&args.connection_id) else {
+            let error_msg = format!("Error: Connection {} has no subscription", args.connection_id);
+            return Err(McpError::invalid_params(error_msg, None));
+        };
+        task.abort();
+        if let Ok(connection) = self.connection_manager.get(&args.connection_id).await {
+            connection.record_event("Unsubscribed").await;
+        }
+
+        let message = format!("Unsubscribed\nConnection ID: {}", args.connection_id);
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+}
+
+/// Forward received data until the connection closes or fails
+///
+/// The connection is looked up again after every wait, so closing it is never
+/// held up by the subscription.
+async fn run_subscription(peer: Peer<RoleServer>, manager: Arc<ConnectionManager>, connection_id: String, coalesce: Duration) {
+    let max_chunks = if coalesce.is_zero() { 1 } else { SUBSCRIPTION_MAX_CHUNKS };
+
+    loop {
+        let Ok(connection) = manager.get(&connection_id).await else {
+            debug!("Subscription to {} ended: connection closed", connection_id);
+            return;
+        };
+
+        let chunks = match connection
+            .read_batch(max_chunks, SUBSCRIPTION_MAX_BYTES, coalesce, Some(SUBSCRIPTION_POLL_MS))
+            .await
+        {
+            Ok(chunks) => chunks,
+            Err(LocalSerialError::ReadTimeout) => continue,
+            Err(e) => {
+                notify_data(&peer, LoggingLevel::Error, serde_json::json!({
+                    "connection_id": connection_id,
+                    "error": e.to_string(),
+                }))
+                .await;
+                return;
+            }
+        };
+        drop(connection);
+
+        if chunks.is_empty() {
+            // Reception is paused
+            tokio::time::sleep(Duration::from_millis(SUBSCRIPTION_POLL_MS)).await;
+            continue;
+        }
+
+        let notification = DataNotification {
+            connection_id: connection_id.clone(),
+            frames: chunks.iter().map(NotifiedFrame::from).collect(),
+        };
+        match serde_json::to_value(&notification) {
+            Ok(data) => notify_data(&peer, LoggingLevel::Info, data).await,
+            Err(e) => warn!("Failed to serialize data notification: {}", e),
+        }
+    }
+}
+
+async fn notify_data(peer: &Peer<RoleServer>, level: LoggingLevel, data: serde_json::Value) {
+    let param = LoggingMessageNotificationParam {
+        level,
+        logger: Some("serial-data".to_string()),
+        data,
+    };
+    if let Err(e) = peer.notify_logging_message(param).await {
+        warn!("Failed to send data notification: {}", e);
+    }
+}
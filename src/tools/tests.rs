@@ -1,6 +1,6 @@
 #[cfg(test)]
 mod tests {
-    use super::super::types::{decode_data, encode_data};
+    use super::super::types::{decode_data, encode_data, ReadPayload};
 
     #[test]
     fn test_decode_utf8() {
@@ -96,4 +96,159 @@ mod tests {
         let b64_decoded = decode_data(&b64_encoded, "base64").unwrap();
         assert_eq!(test_data, b64_decoded.as_slice());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_read_payload_never_fails_on_binary() {
+        let payload = ReadPayload::new(&[b'O', b'K', 0xFF], "utf8").unwrap();
+        assert!(payload.lossy);
+        assert_eq!(payload.data, "OK\u{FFFD}");
+        assert_eq!(payload.companion, "4f 4b ff");
+        assert!(payload.to_string().ends_with("\nHex: \"4f 4b ff\""));
+
+        let payload = ReadPayload::new(b"OK\r\n", "hex").unwrap();
+        assert!(!payload.lossy);
+        assert_eq!(payload.data, "4f 4b 0d 0a");
+        assert_eq!(payload.companion_label, "Preview");
+        assert_eq!(payload.companion, "OK\r\n");
+
+        assert!(ReadPayload::new(b"OK", "ebcdic").is_err());
+    }
+
+    #[test]
+    fn test_cbor_encoding() {
+        // [1, h'ABCD'] and a sequence of two items
+        assert_eq!(encode_data(&[0x82, 0x01, 0x42, 0xAB, 0xCD], "cbor").unwrap(), "[1,\"abcd\"]");
+        assert_eq!(encode_data(&[0x01, 0x20], "cbor").unwrap(), "1\n-1");
+        assert!(encode_data(&[0x82, 0x01], "cbor").is_err());
+
+        let encoded = decode_data(r#"{"temp":21.5,"id":7,"tags":["a"]}"#, "cbor").unwrap();
+        assert_eq!(encoded[0], 0xA3);
+        assert_eq!(encode_data(&encoded, "cbor").unwrap(), r#"{"id":7,"tags":["a"],"temp":21.5}"#);
+        assert!(decode_data("{not json", "cbor").is_err());
+
+        let payload = ReadPayload::new(&[0x82, 0x01], "cbor").unwrap();
+        assert!(payload.data.contains("CBOR decoding failed"));
+        assert_eq!(payload.companion, "82 01");
+    }
+
+    #[test]
+    fn test_msgpack_encoding() {
+        // [1, bin 'ABCD'] and a run of two values
+        assert_eq!(encode_data(&[0x92, 0x01, 0xC4, 0x02, 0xAB, 0xCD], "msgpack").unwrap(), "[1,\"abcd\"]");
+        assert_eq!(encode_data(&[0x01, 0xFF], "msgpack").unwrap(), "1\n-1");
+        assert_eq!(encode_data(&[0xD4, 0x05, 0x2A], "msgpack").unwrap(), r#"{"data":"2a","ext":5}"#);
+        assert!(encode_data(&[0x92, 0x01], "msgpack").is_err());
+
+        let encoded = decode_data(r#"{"temp":21.5,"id":7,"tags":["a"]}"#, "msgpack").unwrap();
+        assert_eq!(encoded[0], 0x83);
+        assert_eq!(encode_data(&encoded, "msgpack").unwrap(), r#"{"id":7,"tags":["a"],"temp":21.5}"#);
+        assert!(decode_data("{not json", "msgpack").is_err());
+
+        let payload = ReadPayload::new(&[0x92, 0x01], "msgpack").unwrap();
+        assert!(payload.data.contains("MessagePack decoding failed"));
+        assert_eq!(payload.companion, "92 01");
+    }
+
+    #[test]
+    fn test_parse_json_line() {
+        use super::super::jsonl::parse_json_line;
+        use crate::serial::ReceivedLine;
+
+        let line = |seq: u64, text: &str| ReceivedLine {
+            seq,
+            timestamp: chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+            data: text.as_bytes().to_vec(),
+        };
+        let entry = parse_json_line(&line(3, r#" {"temp": 21.5} "#)).unwrap().unwrap();
+        assert_eq!(entry, serde_json::json!({"seq": 3, "timestamp": "2023-11-14T22:13:20.000Z", "object": {"temp": 21.5}}));
+
+        assert!(parse_json_line(&line(4, "  ")).is_none());
+        let cut_short = parse_json_line(&line(5, r#"{"temp": 2"#)).unwrap().unwrap_err();
+        assert_eq!((cut_short.seq, cut_short.text.as_str()), (5, r#"{"temp": 2"#));
+        let not_object = parse_json_line(&line(6, "42")).unwrap().unwrap_err();
+        assert_eq!(not_object.error, "not a JSON object");
+    }
+
+    #[test]
+    fn test_configured_protocols() {
+        use crate::config::{Config, ProtocolConfig, ProtocolFraming};
+
+        let with_protocol = |protocol: &str| Config {
+            protocols: [("device".to_string(), toml::from_str::<ProtocolConfig>(protocol).unwrap())].into(),
+            ..Config::default()
+        };
+        let config = with_protocol("framing = \"delimiter\"\ndelimiter = \"03\"\nchecksum = \"xor8\"\nencoding = \"hex\"");
+        config.validate().unwrap();
+        match config.protocols["device"].build_framing().unwrap() {
+            ProtocolFraming::Framer(framer, description) => {
+                assert_eq!(framer.encode(b"\x01\x02"), [0x01, 0x02, 0x03, 0x03]);
+                assert_eq!(description, "delimiter (03), big-endian checksum xor8");
+            }
+            _ => panic!("framer expected"),
+        }
+        let config = with_protocol("framing = \"line\"");
+        config.validate().unwrap();
+        assert!(matches!(config.protocols["device"].build_framing().unwrap(), ProtocolFraming::Lines));
+
+        for invalid in ["framing = \"morse\"", "framing = \"line\"\nchecksum = \"crc32\"", "framing = \"slip\"\nmagic = \"AA\"", "encoding = \"ebcdic\""] {
+            assert!(with_protocol(invalid).validate().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_configured_profiles() {
+        use super::super::types::OpenArgs;
+        use crate::config::Config;
+
+        let config = Config {
+            protocols: [("nmea".to_string(), toml::from_str("framing = \"line\"").unwrap())].into(),
+            profiles: [(
+                "gps".to_string(),
+                toml::from_str("port = \"/dev/serial/by-id/usb-u-blox-if00\"\nbaud_rate = 9600\nprotocol = \"nmea\"").unwrap(),
+            )]
+            .into(),
+            ..Config::default()
+        };
+        config.validate().unwrap();
+
+        let profile = &config.profiles["gps"];
+        let args = OpenArgs::from_profile("gps", profile, profile.port.clone().unwrap(), profile.baud_rate.unwrap());
+        assert_eq!(args.port, "/dev/serial/by-id/usb-u-blox-if00");
+        assert_eq!(args.baud_rate, 9600);
+        assert_eq!((args.data_bits.as_str(), args.stop_bits.as_str(), args.parity.as_str()), ("8", "1", "none"));
+        assert_eq!(args.profile.as_deref(), Some("gps"));
+        assert_eq!(args.protocol.as_deref(), Some("nmea"));
+
+        for invalid in ["protocol = \"ais\"", "baud_rate = 1234", "stop_bits = 3", "parity = \"mark\"", "backpressure = \"spill\""] {
+            let mut config = config.clone();
+            config.profiles.insert("bad".to_string(), toml::from_str(invalid).unwrap());
+            assert!(config.validate().unwrap_err().to_string().contains("profiles.bad"), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_warnings_section_and_json() {
+        use super::super::warnings::Warnings;
+        use rmcp::model::RawContent;
+
+        let result = Warnings::new().into_result("Done".to_string()).unwrap();
+        assert_eq!(result.content.len(), 1);
+
+        let mut warnings = Warnings::new();
+        warnings.dropped_bytes(0);
+        assert!(warnings.is_empty());
+        warnings.dropped_bytes(15);
+        let result = warnings.into_result("Done".to_string()).unwrap();
+        let texts: Vec<String> = result
+            .content
+            .iter()
+            .map(|content| match &content.raw {
+                RawContent::Text(text) => text.text.clone(),
+                _ => panic!("text content expected"),
+            })
+            .collect();
+        assert!(texts[0].ends_with("\n\nWarnings:\n- 15 bytes dropped due to receive buffer overflow since the last read"));
+        let json: serde_json::Value = serde_json::from_str(&texts[1]).unwrap();
+        assert_eq!(json["warnings"].as_array().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,291 @@
+//! Partial results and resumption for long transfers
+//!
+//! A transfer that fails part way reports how far it got and a resume token.
+//! The token is self-contained (base64url JSON), so it stays usable after a
+//! server restart; passing it back to the same tool continues the transfer
+//! instead of starting over. Transfers also save their progress under
+//! `server.state_directory` as they go, so one cut short by a crash or a
+//! dropped client can be continued with `resume_transfer` by its ID.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use base64::{engine::general_purpose, Engine as _};
+use rmcp::ErrorData as McpError;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::error::StateError;
+use crate::state::{load_state, save_state, PersistedState};
+
+/// File under the state directory holding interrupted transfers
+const TRANSFERS_FILE: &str = "transfers.json";
+
+/// Where an interrupted transfer can be picked up
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResumeToken {
+    /// Tool the token belongs to
+    pub tool: String,
+    pub source: String,
+    pub destination: String,
+    /// MD5 of the source, so a changed file is not resumed
+    pub md5: String,
+    /// Offset in the transferred stream up to which the destination is known good
+    pub offset: u64,
+}
+
+impl ResumeToken {
+    pub fn encode(&self) -> String {
+        let json = serde_json::to_vec(self).unwrap_or_default();
+        general_purpose::URL_SAFE_NO_PAD.encode(json)
+    }
+
+    pub fn decode(token: &str) -> Result<Self, String> {
+        let json = general_purpose::URL_SAFE_NO_PAD
+            .decode(token.trim())
+            .map_err(|e| format!("Malformed resume token - {}", e))?;
+        serde_json::from_slice(&json).map_err(|e| format!("Malformed resume token - {}", e))
+    }
+
+    /// Check the token was issued by `tool` for this source and destination
+    pub fn check(&self, tool: &str, source: &str, destination: &str, md5: &str) -> Result<(), String> {
+        if self.tool != tool {
+            return Err(format!("Resume token belongs to {}, not {}", self.tool, tool));
+        }
+        if self.source != source || self.destination != destination {
+            return Err(format!(
+                "Resume token is for {} -> {}, not {} -> {}",
+                self.source, self.destination, source, destination
+            ));
+        }
+        if self.md5 != md5 {
+            return Err(format!("{} changed since the interrupted transfer, start over without a resume token", source));
+        }
+        Ok(())
+    }
+}
+
+/// How far a failed transfer got, returned as the error's structured data
+#[derive(Debug, Clone, Serialize)]
+pub struct PartialTransfer {
+    /// Source bytes known to have arrived
+    pub bytes_completed: u64,
+    pub total_bytes: u64,
+    /// Offset in the transferred stream up to which the destination is known good
+    pub last_good_offset: u64,
+    pub resume_token: String,
+    /// ID the saved progress is kept under, for resume_transfer
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transfer_id: Option<String>,
+}
+
+impl PartialTransfer {
+    pub fn new(token: &ResumeToken, bytes_completed: u64, total_bytes: u64) -> Self {
+        Self {
+            bytes_completed,
+            total_bytes,
+            last_good_offset: token.offset,
+            resume_token: token.encode(),
+            transfer_id: None,
+        }
+    }
+
+    /// Name the saved progress the transfer can also be resumed from
+    pub fn with_transfer_id(mut self, transfer_id: Option<&str>) -> Self {
+        self.transfer_id = transfer_id.map(str::to_string);
+        self
+    }
+
+    /// Error carrying the partial result, for a transfer that failed with `reason`
+    pub fn into_error(self, reason: &str) -> McpError {
+        let message = format!(
+            "Error: Transfer interrupted - {}\nCompleted: {} of {} bytes\nLast good offset: {}\nResume token: {}",
+            reason, self.bytes_completed, self.total_bytes, self.last_good_offset, self.resume_token
+        );
+        let message = match &self.transfer_id {
+            Some(transfer_id) => format!("{}\nTransfer ID: {} (continue with resume_transfer)", message, transfer_id),
+            None => message,
+        };
+        McpError::internal_error(message, serde_json::to_value(&self).ok())
+    }
+}
+
+/// Progress of a transfer, saved until it completes
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SavedTransfer {
+    /// Tool that started the transfer
+    pub tool: String,
+    /// Port of the target, which outlives the connection
+    pub port: String,
+    pub source: String,
+    pub destination: String,
+    /// MD5 of the source, so a changed file is not resumed
+    pub md5: String,
+    pub total_bytes: u64,
+    /// Offset in the transferred stream up to which the destination is known good
+    pub offset: u64,
+    /// MD5 of the stream up to `offset`, compared with the destination before resuming
+    pub sent_md5: String,
+    pub chunk_size: usize,
+    pub settle_ms: u64,
+    pub verify: bool,
+    /// When the progress was last saved, RFC 3339
+    pub updated: String,
+}
+
+impl SavedTransfer {
+    /// The self-contained token for the same progress
+    pub fn token(&self) -> ResumeToken {
+        ResumeToken {
+            tool: self.tool.clone(),
+            source: self.source.clone(),
+            destination: self.destination.clone(),
+            md5: self.md5.clone(),
+            offset: self.offset,
+        }
+    }
+}
+
+/// Interrupted transfers, keyed by transfer ID
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SavedTransfers {
+    pub transfers: BTreeMap<String, SavedTransfer>,
+}
+
+impl PersistedState for SavedTransfers {
+    const KIND: &'static str = "transfers";
+    const VERSION: u32 = 1;
+}
+
+/// Transfer progress, saved to disk on every change
+#[derive(Debug)]
+pub struct TransferStore {
+    path: PathBuf,
+    /// Whether the file could be loaded, and so may be written
+    loaded: bool,
+    transfers: Mutex<SavedTransfers>,
+}
+
+impl TransferStore {
+    /// Load the transfers saved in `state_directory`
+    ///
+    /// Unreadable state, such as a file written by a newer server, is logged
+    /// and replaced by an empty set. The file is then left as it is: progress
+    /// is only kept in memory this run, so transfers resume until the server stops.
+    pub fn load(state_directory: &Path) -> Self {
+        let path = state_directory.join(TRANSFERS_FILE);
+        let (transfers, loaded) = match load_state::<SavedTransfers>(&path) {
+            Ok(transfers) => (transfers.unwrap_or_default(), true),
+            Err(e) => {
+                warn!("Saved transfers not loaded, leaving {} untouched: {}", path.display(), e);
+                (SavedTransfers::default(), false)
+            }
+        };
+        Self {
+            path,
+            loaded,
+            transfers: Mutex::new(transfers),
+        }
+    }
+
+    /// Write the transfers to disk, unless the file could not be loaded
+    fn persist(&self, transfers: &SavedTransfers) -> Result<(), StateError> {
+        if !self.loaded {
+            return Ok(());
+        }
+        save_state(&self.path, transfers)
+    }
+
+    pub fn get(&self, transfer_id: &str) -> Option<SavedTransfer> {
+        self.transfers.lock().unwrap().transfers.get(transfer_id).cloned()
+    }
+
+    pub fn list(&self) -> Vec<(String, SavedTransfer)> {
+        self.transfers.lock().unwrap().transfers.iter().map(|(id, transfer)| (id.clone(), transfer.clone())).collect()
+    }
+
+    /// Save a transfer's progress, replacing older transfers to the same destination
+    pub fn save(&self, transfer_id: &str, transfer: SavedTransfer) -> Result<(), StateError> {
+        let mut transfers = self.transfers.lock().unwrap();
+        transfers.transfers.retain(|id, saved| {
+            id == transfer_id || saved.tool != transfer.tool || saved.port != transfer.port || saved.destination != transfer.destination
+        });
+        transfers.transfers.insert(transfer_id.to_string(), transfer);
+        self.persist(&transfers)
+    }
+
+    /// Forget a transfer that completed or cannot be resumed
+    pub fn remove(&self, transfer_id: &str) -> Result<(), StateError> {
+        let mut transfers = self.transfers.lock().unwrap();
+        if transfers.transfers.remove(transfer_id).is_none() {
+            return Ok(());
+        }
+        self.persist(&transfers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resume_token_round_trip_and_checks() {
+        let token = ResumeToken {
+            tool: "push_file_via_console".to_string(),
+            source: "fw.bin".to_string(),
+            destination: "/tmp/fw.bin".to_string(),
+            md5: "abc".to_string(),
+            offset: 2048,
+        };
+        let decoded = ResumeToken::decode(&token.encode()).unwrap();
+        assert_eq!(decoded, token);
+
+        assert!(decoded.check("push_file_via_console", "fw.bin", "/tmp/fw.bin", "abc").is_ok());
+        assert!(decoded.check("push_file_via_console", "fw.bin", "/tmp/fw.bin", "def").unwrap_err().contains("changed"));
+        assert!(decoded.check("push_file_via_console", "other.bin", "/tmp/fw.bin", "abc").is_err());
+        assert!(ResumeToken::decode("not a token").is_err());
+
+        let error = PartialTransfer::new(&token, 1536, 4096).into_error("port closed");
+        assert!(error.message.contains("Completed: 1536 of 4096 bytes"));
+        assert_eq!(error.data.unwrap()["last_good_offset"], 2048);
+    }
+
+    #[test]
+    fn test_transfer_store() {
+        let directory = std::env::temp_dir().join(format!("serial-mcp-transfers-{}", std::process::id()));
+        let transfer = SavedTransfer {
+            tool: "push_file_via_console".to_string(),
+            port: "/dev/ttyUSB0".to_string(),
+            source: "fw.bin".to_string(),
+            destination: "/tmp/fw.bin".to_string(),
+            md5: "abc".to_string(),
+            total_bytes: 4096,
+            offset: 2048,
+            sent_md5: "def".to_string(),
+            chunk_size: 512,
+            settle_ms: 200,
+            verify: true,
+            updated: "2024-01-01T00:00:00Z".to_string(),
+        };
+        let store = TransferStore::load(&directory);
+        store.save("a", transfer.clone()).unwrap();
+        // A new push to the same destination replaces the old one
+        store.save("b", SavedTransfer { offset: 512, ..transfer.clone() }).unwrap();
+
+        let reloaded = TransferStore::load(&directory);
+        assert_eq!(reloaded.get("a"), None);
+        assert_eq!(reloaded.get("b").unwrap().offset, 512);
+        reloaded.remove("b").unwrap();
+        assert!(TransferStore::load(&directory).list().is_empty());
+
+        // A newer server's file is kept, with progress tracked in memory only
+        let newer = r#"{"kind": "transfers", "version": 99, "data": {"transfers": {}}}"#;
+        std::fs::write(directory.join(TRANSFERS_FILE), newer).unwrap();
+        let store = TransferStore::load(&directory);
+        store.save("c", transfer.clone()).unwrap();
+        assert_eq!(store.get("c").unwrap().offset, 2048);
+        assert_eq!(std::fs::read_to_string(directory.join(TRANSFERS_FILE)).unwrap(), newer);
+        let _ = std::fs::remove_dir_all(&directory);
+    }
+}
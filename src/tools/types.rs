@@ -1,6 +1,11 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use schemars::JsonSchema;
-use crate::serial::{ConnectionConfig, PortInfo};
+use crate::utils::{BufferUtils, DataConverter};
+use crate::serial::{ConnectionConfig, ConnectionEvent, ConnectionStatus, Direction, PortInfo, TranscriptEntry};
+use crate::session::SessionInfo;
 
 // 工具请求类型
 #[derive(Debug, Deserialize, JsonSchema)]
@@ -20,6 +25,44 @@ pub struct OpenArgs {
     pub parity: String,
     #[serde(default = "default_flow_control")]
     pub flow_control: String,
+    /// What to do when received data is not read fast enough: "drop", "block" or "flow_control"; defaults to serial.backpressure
+    #[serde(default)]
+    pub backpressure: Option<String>,
+    /// Device profile from the server configuration whose init commands run once the port is open, if open_profile may be called
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// Protocol from the server configuration, setting the connection's framing and default encoding
+    #[serde(default)]
+    pub protocol: Option<String>,
+}
+
+impl OpenArgs {
+    /// Open arguments for a configured profile, `port` and `baud_rate` resolved by the caller
+    pub fn from_profile(name: &str, profile: &crate::config::ProfileConfig, port: String, baud_rate: u32) -> Self {
+        Self {
+            port,
+            baud_rate,
+            data_bits: profile.data_bits.map_or_else(default_data_bits, |bits| bits.to_string()),
+            stop_bits: profile.stop_bits.map_or_else(default_stop_bits, |bits| bits.to_string()),
+            parity: profile.parity.clone().unwrap_or_else(default_parity),
+            flow_control: profile.flow_control.clone().unwrap_or_else(default_flow_control),
+            backpressure: profile.backpressure.clone(),
+            profile: Some(name.to_string()),
+            protocol: profile.protocol.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct OpenProfileArgs {
+    /// Profile from the server configuration, e.g. "gps"
+    pub profile: String,
+    /// Port to open instead of the profile's
+    #[serde(default)]
+    pub port: Option<String>,
+    /// Baud rate to use instead of the profile's
+    #[serde(default)]
+    pub baud_rate: Option<u32>,
 }
 
 fn default_data_bits() -> String { "8".to_string() }
@@ -36,24 +79,1253 @@ pub struct CloseArgs {
 pub struct WriteArgs {
     pub connection_id: String,
     pub data: String,
-    #[serde(default = "default_encoding")]
-    pub encoding: String,
+    /// utf8, hex, base64, cbor or msgpack; defaults to the connection protocol's or session's encoding, else utf8
+    #[serde(default)]
+    pub encoding: Option<String>,
+    /// Send the session's line ending after the data, e.g. to end a command
+    #[serde(default)]
+    pub append_line_ending: bool,
+    /// Give up unsent if writes submitted earlier still hold the connection after this many milliseconds; waits by default
+    #[serde(default)]
+    pub queue_timeout_ms: Option<u64>,
 }
 
-fn default_encoding() -> String { "utf8".to_string() }
-
 #[derive(Debug, Deserialize, JsonSchema)]
 pub struct ReadArgs {
     pub connection_id: String,
+    /// How long to wait for data; defaults to the session's timeout
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+    /// Defaults to the session's buffer size
+    #[serde(default)]
+    pub max_bytes: Option<usize>,
+    /// utf8, hex, base64, cbor or msgpack; defaults to the connection protocol's or session's encoding, else utf8
+    #[serde(default)]
+    pub encoding: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ReadFrameIdleArgs {
+    pub connection_id: String,
+    /// Quiet time in milliseconds that ends a frame; defaults to 3.5 character times at the connection's baud rate
+    #[serde(default)]
+    pub idle_ms: Option<u64>,
+    /// How long to wait for the first byte of the frame; defaults to the session's timeout
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+    /// Defaults to the session's buffer size
+    #[serde(default)]
+    pub max_bytes: Option<usize>,
+    /// utf8, hex, base64, cbor or msgpack; defaults to the connection protocol's or session's encoding, else utf8
+    #[serde(default)]
+    pub encoding: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ReadBatchArgs {
+    pub connection_id: String,
+    /// How long to wait for the first chunk; defaults to the session's timeout
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+    /// Maximum chunks per batch; defaults to serial.batch_max_chunks
+    #[serde(default)]
+    pub max_chunks: Option<usize>,
+    /// Deliver once the oldest chunk is this old; defaults to serial.batch_max_age_ms
+    #[serde(default)]
+    pub max_age_ms: Option<u64>,
+    /// Defaults to the session's buffer size
+    #[serde(default)]
+    pub max_bytes: Option<usize>,
+    /// utf8, hex, base64, cbor or msgpack; defaults to the connection protocol's or session's encoding, else utf8
+    #[serde(default)]
+    pub encoding: Option<String>,
+}
+
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct LineModeArgs {
+    pub connection_id: String,
+    /// Assemble received data into whole lines (true) or return raw chunks (false)
+    pub enabled: bool,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ReadLinesArgs {
+    pub connection_id: String,
+    /// How long to wait for a line; defaults to the session's timeout
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+    #[serde(default = "default_max_lines")]
+    pub max_lines: usize,
+}
+
+fn default_max_lines() -> usize { 100 }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SetFramerArgs {
+    pub connection_id: String,
+    /// Framer for reads and writes: "raw", "slip", "cobs", "hdlc", "length", "varint", "delimiter", or "none" to go back to plain bytes
+    pub framer: String,
+    /// Length framer: size of the length field in bytes, 1, 2 or 4 (default 2)
+    #[serde(default)]
+    pub length_bytes: Option<usize>,
+    /// Length framer: byte order of the length field, "big" or "little" (default big)
+    #[serde(default)]
+    pub byte_order: Option<String>,
+    /// Length framer: hex bytes every frame starts with, e.g. "AA55" (default none)
+    #[serde(default)]
+    pub magic: Option<String>,
+    /// Delimiter framer: hex bytes ending every frame, e.g. "03" (default "0A", a line feed)
+    #[serde(default)]
+    pub delimiter: Option<String>,
+    /// Checksum after every payload, added on send and checked on receipt: an algorithm of the checksum tool such as "crc16_modbus"
+    #[serde(default)]
+    pub checksum: Option<String>,
+    /// Byte order of the checksum, "big" (default) or "little"
+    #[serde(default)]
+    pub checksum_byte_order: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ReadFramesArgs {
+    pub connection_id: String,
+    /// How long to wait for a frame; defaults to the session's timeout
     #[serde(default)]
     pub timeout_ms: Option<u64>,
-    #[serde(default = "default_max_bytes")]
-    pub max_bytes: usize,
-    #[serde(default = "default_encoding")]
+    #[serde(default = "default_max_frames")]
+    pub max_frames: usize,
+    /// utf8, hex, base64, cbor or msgpack; defaults to the connection protocol's or session's encoding, else utf8
+    #[serde(default)]
+    pub encoding: Option<String>,
+    /// Decode each payload as postcard with this shape: a type name such as "u32", "f32", "string" or "bytes", or {"struct": [[name, type], ...]}, {"enum": [[variant, type or "unit"], ...]}, {"seq": type}, {"option": type}, {"array": [type, length]}, {"tuple": [types]}, {"map": [key, value]}
+    pub postcard_schema: Option<serde_json::Value>,
+    /// Decode each payload as this Protocol Buffers message type, e.g. "sensor.Reading", registered with protobuf_register
+    pub protobuf_message: Option<String>,
+}
+
+fn default_max_frames() -> usize { 16 }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct WriteFrameArgs {
+    pub connection_id: String,
+    /// Frame payload, before the framer's encoding
+    pub data: String,
+    /// utf8, hex, base64, cbor or msgpack; defaults to the connection protocol's or session's encoding, else utf8
+    #[serde(default)]
+    pub encoding: Option<String>,
+    /// Give up unsent if writes submitted earlier still hold the connection after this many milliseconds; waits by default
+    #[serde(default)]
+    pub queue_timeout_ms: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct PauseArgs {
+    pub connection_id: String,
+    /// Signal asserted towards the device while paused: "none", "rts" or "xoff"
+    #[serde(default = "default_pause_signal")]
+    pub signal: String,
+}
+
+fn default_pause_signal() -> String { "none".to_string() }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ResumeArgs {
+    pub connection_id: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct PushFileArgs {
+    pub connection_id: String,
+    /// File on the machine running the server
+    pub local_path: String,
+    /// Destination path on the target
+    pub remote_path: String,
+    /// Base64 characters sent per printf command
+    #[serde(default = "default_push_chunk_size")]
+    pub chunk_size: usize,
+    /// Quiet time in milliseconds that marks the end of a command's output
+    #[serde(default = "default_settle_ms")]
+    pub settle_ms: u64,
+    /// Compare md5sum on the target against the local file
+    #[serde(default = "default_true")]
+    pub verify: bool,
+    /// Token from an interrupted push of the same file, to continue where it stopped
+    #[serde(default)]
+    pub resume_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ResumeTransferArgs {
+    /// Transfer ID a failed transfer reported; omit to list the transfers that can be resumed
+    pub transfer_id: Option<String>,
+    /// Connection to the same target, needed to resume
+    pub connection_id: Option<String>,
+}
+
+fn default_push_chunk_size() -> usize { 512 }
+fn default_settle_ms() -> u64 { 200 }
+fn default_true() -> bool { true }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SnapshotArgs {
+    pub connection_id: String,
+    /// Number of most recent traffic chunks to include
+    #[serde(default = "default_transcript_limit")]
+    pub transcript_limit: usize,
+}
+
+fn default_transcript_limit() -> usize { 50 }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct StartCaptureArgs {
+    pub connection_id: String,
+    /// Capture file path; defaults to a timestamped file in capture.directory
+    #[serde(default)]
+    pub path: Option<String>,
+    /// "raw", "hexdump" or "jsonl"; defaults to capture.format
+    #[serde(default)]
+    pub format: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct StopCaptureArgs {
+    pub connection_id: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ReplayArgs {
+    pub connection_id: String,
+    /// JSONL or hexdump capture file whose TX side is sent
+    pub path: String,
+    /// Wait between chunks as long as in the original capture
+    #[serde(default)]
+    pub honor_timing: bool,
+    /// Playback speed factor when honoring timing, e.g. 2.0 for twice as fast
+    #[serde(default = "default_replay_speed")]
+    pub speed: f64,
+}
+
+fn default_replay_speed() -> f64 { 1.0 }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SubscribeArgs {
+    pub connection_id: String,
+    /// Merge chunks arriving within this many milliseconds into one notification;
+    /// defaults to serial.subscription_coalesce_ms
+    #[serde(default)]
+    pub coalesce_ms: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct UnsubscribeArgs {
+    pub connection_id: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct BridgeStartArgs {
+    /// Port the host application talks to, e.g. one end of a virtual null-modem pair
+    pub host: OpenArgs,
+    /// Port the device is attached to
+    pub device: OpenArgs,
+    /// Forwarded chunks kept for inspection; older ones are dropped
+    #[serde(default)]
+    pub max_records: Option<usize>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct BridgeStopArgs {
+    pub bridge_id: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct BridgeInspectArgs {
+    pub bridge_id: String,
+    /// Most recent forwarded chunks to show
+    #[serde(default = "default_bridge_inspect_limit")]
+    pub limit: usize,
+}
+
+fn default_bridge_inspect_limit() -> usize { 50 }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ModbusReadArgs {
+    pub connection_id: String,
+    /// Slave address, 1 to 247
+    pub slave: u8,
+    /// First coil or register address (0-based, as sent on the wire)
+    pub address: u16,
+    /// Number of coils (up to 2000) or registers (up to 125) to read
+    pub count: u16,
+    /// How long to wait for the reply
+    #[serde(default = "default_modbus_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ModbusWriteRegisterArgs {
+    pub connection_id: String,
+    /// Slave address, 1 to 247, or 0 to broadcast without a reply
+    pub slave: u8,
+    /// Register address (0-based, as sent on the wire)
+    pub address: u16,
+    pub value: u16,
+    /// How long to wait for the reply
+    #[serde(default = "default_modbus_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ModbusWriteRegistersArgs {
+    pub connection_id: String,
+    /// Slave address, 1 to 247, or 0 to broadcast without a reply
+    pub slave: u8,
+    /// First register address (0-based, as sent on the wire)
+    pub address: u16,
+    /// Values for consecutive registers, up to 123
+    pub values: Vec<u16>,
+    /// How long to wait for the reply
+    #[serde(default = "default_modbus_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+fn default_modbus_timeout_ms() -> u64 { 1000 }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ModbusSimulatorStartArgs {
+    /// Port the masters talk to; the simulator opens and owns it
+    pub port: OpenArgs,
+    /// Slave address to answer as, 1 to 247
+    pub slave: u8,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ModbusSimulatorStopArgs {
+    pub simulator_id: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ModbusSimulatorReadArgs {
+    pub simulator_id: String,
+    /// Table to read: coils, holding or input
+    pub table: String,
+    /// First coil or register address (0-based, as sent on the wire)
+    pub address: u16,
+    /// Number of coils or registers to show
+    #[serde(default = "default_simulator_read_count")]
+    pub count: u16,
+}
+
+fn default_simulator_read_count() -> u16 { 16 }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ModbusSimulatorWriteArgs {
+    pub simulator_id: String,
+    /// Table to write: coils, holding or input
+    pub table: String,
+    /// First coil or register address (0-based, as sent on the wire)
+    pub address: u16,
+    /// Values for consecutive addresses; any non-zero value sets a coil
+    pub values: Vec<u16>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ModbusGatewayStartArgs {
+    /// Connection to the RTU bus; it stays open when the gateway stops
+    pub connection_id: String,
+    /// Address to accept Modbus TCP clients on; port 0 picks a free one
+    #[serde(default = "default_modbus_gateway_listen")]
+    pub listen: String,
+    /// How long to wait for each slave's reply
+    #[serde(default = "default_modbus_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+fn default_modbus_gateway_listen() -> String { "127.0.0.1:5020".to_string() }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ModbusGatewayArgs {
+    pub gateway_id: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct NmeaReadArgs {
+    pub connection_id: String,
+    /// How long to collect sentences; most GPS modules send a full set every second
+    #[serde(default = "default_nmea_duration_ms")]
+    pub duration_ms: u64,
+    /// Stop once this many sentences have been collected
+    #[serde(default = "default_nmea_max_sentences")]
+    pub max_sentences: usize,
+}
+
+fn default_nmea_duration_ms() -> u64 { 1500 }
+fn default_nmea_max_sentences() -> usize { 50 }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct GetPositionArgs {
+    pub connection_id: String,
+    /// How long to wait for a sentence with a fix
+    #[serde(default = "default_position_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+fn default_position_timeout_ms() -> u64 { 3000 }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SmsSendArgs {
+    pub connection_id: String,
+    /// Recipient, digits with an optional leading + for international format
+    pub number: String,
+    /// Message text, one message long: 160 GSM characters or 70 otherwise in PDU mode
+    pub text: String,
+    /// text (AT+CMGF=1) or pdu (AT+CMGF=0); PDU mode sends any Unicode text
+    #[serde(default = "default_sms_mode")]
+    pub mode: String,
+    /// How long to wait for the network to accept the message
+    #[serde(default = "default_sms_send_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+fn default_sms_mode() -> String { "text".to_string() }
+fn default_sms_send_timeout_ms() -> u64 { 30000 }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SmsListArgs {
+    pub connection_id: String,
+    /// Messages to list: all, unread, read, unsent or sent
+    #[serde(default = "default_sms_status")]
+    pub status: String,
+    #[serde(default = "default_sms_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+fn default_sms_status() -> String { "all".to_string() }
+fn default_sms_timeout_ms() -> u64 { 5000 }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SmsReadArgs {
+    pub connection_id: String,
+    /// Storage index from sms_list
+    pub index: u32,
+    #[serde(default = "default_sms_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SmsDeleteArgs {
+    pub connection_id: String,
+    /// Storage index from sms_list; leave out and set all to delete every message
+    pub index: Option<u32>,
+    /// Delete every stored message
+    #[serde(default)]
+    pub all: bool,
+    #[serde(default = "default_sms_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct GcodeSendArgs {
+    pub connection_id: String,
+    /// G-code program text; give this or file
+    pub gcode: Option<String>,
+    /// Path of a local G-code file to send; give this or gcode
+    pub file: Option<String>,
+    /// Send Marlin style N<n> ...*<checksum> lines and honour resend requests
+    #[serde(default)]
+    pub line_numbers: bool,
+    /// Longest wait for one line's ok; firmware busy reports restart it
+    #[serde(default = "default_gcode_line_timeout_ms")]
+    pub line_timeout_ms: u64,
+    /// End the job at the first line the firmware rejects
+    #[serde(default = "default_true")]
+    pub stop_on_error: bool,
+    /// How long to wait for the job before returning its progress; it keeps running afterwards
+    #[serde(default = "default_gcode_wait_ms")]
+    pub wait_ms: u64,
+}
+
+fn default_gcode_line_timeout_ms() -> u64 { 60000 }
+fn default_gcode_wait_ms() -> u64 { 10000 }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct GcodeJobArgs {
+    pub job_id: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct GrblArgs {
+    pub connection_id: String,
+    /// How long to wait for the controller's answer
+    #[serde(default = "default_grbl_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+fn default_grbl_timeout_ms() -> u64 { 2000 }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct GrblRealtimeArgs {
+    pub connection_id: String,
+    /// feed_hold, resume, reset or jog_cancel
+    pub command: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct GrblJogArgs {
+    pub connection_id: String,
+    /// X distance or position in mm
+    pub x: Option<f64>,
+    /// Y distance or position in mm
+    pub y: Option<f64>,
+    /// Z distance or position in mm
+    pub z: Option<f64>,
+    /// Feed rate in mm/min
+    pub feed_rate: f64,
+    /// Move by the distances (G91) rather than to work coordinates (G90)
+    #[serde(default = "default_true")]
+    pub incremental: bool,
+    #[serde(default = "default_grbl_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct MarlinStatusArgs {
+    pub connection_id: String,
+    /// How long to wait for each report
+    #[serde(default = "default_marlin_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+fn default_marlin_timeout_ms() -> u64 { 2000 }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct P1ReadArgs {
+    pub connection_id: String,
+    /// How long to wait for telegrams; older meters send one every 10 seconds
+    #[serde(default = "default_p1_timeout_ms")]
+    pub timeout_ms: u64,
+    /// Stop once this many valid telegrams have been read
+    #[serde(default = "default_p1_max_telegrams")]
+    pub max_telegrams: usize,
+}
+
+fn default_p1_timeout_ms() -> u64 { 11000 }
+fn default_p1_max_telegrams() -> usize { 1 }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CanOpenArgs {
+    pub connection_id: String,
+    /// CAN bitrate in bit/s
+    #[serde(default = "default_can_bitrate")]
+    pub bitrate: u32,
+    /// Open without acknowledging or sending frames
+    #[serde(default)]
+    pub listen_only: bool,
+    /// Add the adapter's millisecond time stamps to received frames
+    #[serde(default)]
+    pub timestamps: bool,
+    #[serde(default = "default_can_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+fn default_can_bitrate() -> u32 { 500_000 }
+fn default_can_timeout_ms() -> u64 { 1000 }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CanCloseArgs {
+    pub connection_id: String,
+    #[serde(default = "default_can_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CanSendArgs {
+    pub connection_id: String,
+    /// CAN identifier
+    pub id: u32,
+    /// Send a 29-bit ID; defaults to true for IDs above 0x7FF
+    pub extended: Option<bool>,
+    /// Data bytes as hex, e.g. "DEADBEEF"
+    pub data: Option<String>,
+    /// Send a remote transmission request instead of data
+    #[serde(default)]
+    pub rtr: bool,
+    /// Data length code; defaults to the number of data bytes
+    pub dlc: Option<u8>,
+    #[serde(default = "default_can_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CanReadArgs {
+    pub connection_id: String,
+    /// How long to collect frames
+    #[serde(default = "default_can_duration_ms")]
+    pub duration_ms: u64,
+    /// Stop once this many frames have been collected
+    #[serde(default = "default_can_max_frames")]
+    pub max_frames: usize,
+}
+
+fn default_can_duration_ms() -> u64 { 1000 }
+fn default_can_max_frames() -> usize { 100 }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CatGetArgs {
+    pub connection_id: String,
+    /// icom, kenwood or yaesu
+    pub protocol: String,
+    /// Icom CI-V address of the radio, e.g. 148 (0x94) for the IC-7300
+    pub civ_address: Option<u8>,
+    #[serde(default = "default_cat_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+fn default_cat_timeout_ms() -> u64 { 1000 }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CatSetArgs {
+    pub connection_id: String,
+    /// icom, kenwood or yaesu
+    pub protocol: String,
+    /// Icom CI-V address of the radio, e.g. 148 (0x94) for the IC-7300
+    pub civ_address: Option<u8>,
+    /// VFO frequency in Hz
+    pub frequency_hz: Option<u64>,
+    /// Mode name, e.g. USB or CW
+    pub mode: Option<String>,
+    /// true to transmit, false to return to receive
+    pub transmit: Option<bool>,
+    #[serde(default = "default_cat_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct MidiSendArgs {
+    pub connection_id: String,
+    /// note_on, note_off, poly_pressure, control_change, program_change, channel_pressure, pitch_bend, sysex, tune_request, clock, start, continue, stop or reset
+    pub message_type: String,
+    /// MIDI channel, 1 to 16
+    #[serde(default = "default_midi_channel")]
+    pub channel: u8,
+    /// Note number, 60 being middle C
+    pub note: Option<u8>,
+    /// Note velocity; defaults to 100 for note on and 64 for note off
+    pub velocity: Option<u8>,
+    /// Controller number for control_change
+    pub controller: Option<u8>,
+    /// Controller value, program, pressure, or pitch bend from -8192 to 8191
+    pub value: Option<i32>,
+    /// System exclusive bytes as hex
+    pub data: Option<String>,
+    /// For note_on: send the note off after this long
+    pub duration_ms: Option<u64>,
+}
+
+fn default_midi_channel() -> u8 { 1 }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ReadJsonLinesArgs {
+    pub connection_id: String,
+    /// How long to collect lines
+    #[serde(default = "default_json_lines_duration_ms")]
+    pub duration_ms: u64,
+    /// Stop once this many objects have arrived
+    #[serde(default = "default_json_lines_max_objects")]
+    pub max_objects: usize,
+}
+
+fn default_json_lines_duration_ms() -> u64 { 1000 }
+fn default_json_lines_max_objects() -> usize { 100 }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct MidiReadArgs {
+    pub connection_id: String,
+    /// How long to collect messages
+    #[serde(default = "default_midi_duration_ms")]
+    pub duration_ms: u64,
+    /// Stop once this many messages have arrived
+    #[serde(default = "default_midi_max_messages")]
+    pub max_messages: usize,
+    /// List clock and active sensing messages instead of counting them
+    #[serde(default)]
+    pub include_timing: bool,
+}
+
+fn default_midi_duration_ms() -> u64 { 1000 }
+fn default_midi_max_messages() -> usize { 100 }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ReadDefmtArgs {
+    pub connection_id: String,
+    /// Path of the firmware ELF running on the device, which holds defmt's format strings
+    pub elf_path: String,
+    /// How long to collect log lines
+    #[serde(default = "default_defmt_duration_ms")]
+    pub duration_ms: u64,
+    /// Stop once this many lines have arrived
+    #[serde(default = "default_defmt_max_lines")]
+    pub max_lines: usize,
+}
+
+fn default_defmt_duration_ms() -> u64 { 2000 }
+fn default_defmt_max_lines() -> usize { 100 }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ProtobufRegisterArgs {
+    /// Path of a compiled FileDescriptorSet, e.g. from protoc --include_imports --descriptor_set_out=schema.desc
+    pub path: Option<String>,
+    /// The FileDescriptorSet itself, base64 encoded; give this or path
+    pub data: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ChecksumArgs {
+    pub data: String,
+    /// Encoding of data: hex (default), utf8 or base64
+    #[serde(default = "default_hex_encoding")]
     pub encoding: String,
+    /// e.g. "crc16_modbus", "crc32", "fletcher16", or "crc" with the parameters below
+    pub algorithm: String,
+    /// Expected value in hex, to verify against
+    pub expected: Option<String>,
+    /// CRC width in bits, for algorithm "crc"
+    pub width: Option<u32>,
+    /// CRC polynomial in hex, unreflected and without the top bit, e.g. "1021"
+    pub poly: Option<String>,
+    /// Initial register value in hex, default 0
+    pub init: Option<String>,
+    /// Process input bytes least significant bit first
+    #[serde(default)]
+    pub reflect_in: bool,
+    /// Reverse the result before xor_out; defaults to reflect_in
+    pub reflect_out: Option<bool>,
+    /// Value XORed onto the result in hex, default 0
+    pub xor_out: Option<String>,
+}
+
+fn default_hex_encoding() -> String { "hex".to_string() }
+
+/// Frame layout: header, length field, payload, checksum, trailer
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct FrameSpecArgs {
+    /// Hex bytes every frame starts with, e.g. "AA55" (default none)
+    #[serde(default)]
+    pub header: String,
+    /// Size of the length field after the header: 0 for none (default), 1, 2 or 4
+    #[serde(default)]
+    pub length_bytes: usize,
+    /// What the length field counts: "payload" (default), "payload_checksum" or "frame"
+    #[serde(default = "default_length_counts")]
+    pub length_counts: String,
+    /// Added to the counted length to give the length field's value, e.g. -1
+    #[serde(default)]
+    pub length_adjust: i64,
+    /// Byte order of the length field, "big" (default) or "little"
+    #[serde(default = "default_frame_byte_order")]
+    pub byte_order: String,
+    /// Checksum after the payload, an algorithm of the checksum tool such as "crc16_modbus" (default none)
+    pub checksum: Option<String>,
+    /// Byte order of the checksum, defaults to byte_order
+    pub checksum_byte_order: Option<String>,
+    /// First part the checksum covers: "header" (default), "length" or "payload"
+    #[serde(default = "default_checksum_from")]
+    pub checksum_from: String,
+    /// Last part the checksum covers: "header", "length" or "payload" (default)
+    #[serde(default = "default_checksum_to")]
+    pub checksum_to: String,
+    /// Hex bytes every frame ends with, e.g. "0D" (default none)
+    #[serde(default)]
+    pub trailer: String,
+}
+
+fn default_length_counts() -> String { "payload".to_string() }
+fn default_frame_byte_order() -> String { "big".to_string() }
+fn default_checksum_from() -> String { "header".to_string() }
+fn default_checksum_to() -> String { "payload".to_string() }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct BuildFrameArgs {
+    pub spec: FrameSpecArgs,
+    pub payload: String,
+    /// Encoding of payload: hex (default), utf8 or base64
+    #[serde(default = "default_hex_encoding")]
+    pub encoding: String,
+    /// Send the frame on this connection as well
+    pub connection_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ParseFrameArgs {
+    pub spec: FrameSpecArgs,
+    /// Received bytes holding one or more frames
+    pub data: String,
+    /// Encoding of data: hex (default), utf8 or base64
+    #[serde(default = "default_hex_encoding")]
+    pub encoding: String,
+}
+
+/// One step of an expect script: wait for `expect`, then send `send`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub struct ExpectStep {
+    /// Text to wait for; omitted or empty sends straight away
+    #[serde(default)]
+    pub expect: Option<String>,
+    /// Text to send once it arrives, with any line ending it needs such as \r
+    #[serde(default)]
+    pub send: Option<String>,
+    /// How long to wait for the expected text; defaults to the script's timeout_ms
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
 }
 
-fn default_max_bytes() -> usize { 1024 }
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ExpectArgs {
+    pub connection_id: String,
+    /// Steps, run in order until one fails
+    pub steps: Vec<ExpectStep>,
+    /// Text that fails the step it arrives in, such as ERROR or NO CARRIER
+    #[serde(default)]
+    pub abort: Vec<String>,
+    /// Wait per step when the step gives none
+    #[serde(default = "default_expect_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+fn default_expect_timeout_ms() -> u64 { 5000 }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct RunScriptArgs {
+    pub connection_id: String,
+    /// Rhai source of the script
+    pub script: String,
+    /// Longest the whole script may run, waits included
+    #[serde(default = "default_script_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+fn default_script_timeout_ms() -> u64 { 30_000 }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct EspResetArgs {
+    pub connection_id: String,
+    /// "bootloader" to wait in the ROM for a download, or "run" to start the application
+    #[serde(default = "default_esp_reset_mode")]
+    pub mode: String,
+    /// Reset circuit: "classic" (two-transistor auto-reset on most dev boards), "tight" (both lines at once, for boards the classic sequence misses), or "usb_jtag" (built-in USB Serial/JTAG of ESP32-C3/S3 and later)
+    #[serde(default = "default_esp_reset_variant")]
+    pub variant: String,
+    /// For boards whose reset circuit inverts DTR and RTS
+    #[serde(default)]
+    pub inverted: bool,
+    /// How long IO0 is held low after the reset is released; esptool uses 50 and retries with 550 for slow boards
+    #[serde(default = "default_esp_reset_delay_ms")]
+    pub reset_delay_ms: u64,
+    /// Collect the boot output until the line is quiet this long; 0 skips it
+    #[serde(default = "default_esp_capture_ms")]
+    pub capture_ms: u64,
+}
+
+fn default_esp_reset_mode() -> String { "bootloader".to_string() }
+fn default_esp_reset_variant() -> String { "classic".to_string() }
+fn default_esp_reset_delay_ms() -> u64 { 50 }
+fn default_esp_capture_ms() -> u64 { 300 }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct EspFlashImage {
+    /// Flash address to write at, e.g. 65536 (0x10000) for an ESP-IDF application
+    pub address: u32,
+    /// Binary image file on the machine running the server
+    pub path: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct EspFlashArgs {
+    pub connection_id: String,
+    /// Images to write, e.g. bootloader, partition table and application
+    pub images: Vec<EspFlashImage>,
+    /// Reset the board into its bootloader first; off when it is already waiting for a download
+    #[serde(default = "default_true")]
+    pub reset: bool,
+    /// Reset circuit, as for esp_reset: "classic", "tight" or "usb_jtag"
+    #[serde(default = "default_esp_reset_variant")]
+    pub variant: String,
+    /// For boards whose reset circuit inverts DTR and RTS
+    #[serde(default)]
+    pub inverted: bool,
+    /// Size of the flash chip, e.g. "4MB"; needed by some chips to write beyond the first megabytes
+    pub flash_size: Option<String>,
+    /// Compare the MD5 of each written region with the image's
+    #[serde(default = "default_true")]
+    pub verify: bool,
+    /// Restart into the application when done
+    #[serde(default = "default_true")]
+    pub run: bool,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct TouchResetArgs {
+    /// Port of the running sketch, e.g. /dev/ttyACM0 or COM5; it must not be open
+    pub port: String,
+    /// How long to wait for the bootloader's port to appear
+    #[serde(default = "default_touch_wait_ms")]
+    pub wait_ms: u64,
+}
+
+fn default_touch_wait_ms() -> u64 { 10_000 }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct AvrFlashArgs {
+    pub connection_id: String,
+    /// Intel HEX file on the machine running the server, e.g. the .hex the Arduino IDE exports
+    pub path: String,
+    /// Bootloader protocol: "stk500v1" (optiboot, Uno, Nano, Pro Mini) or "stk500v2" (Mega 2560)
+    #[serde(default = "default_avr_protocol")]
+    pub protocol: String,
+    /// Reset the board through DTR first, as the Arduino auto-reset circuit expects
+    #[serde(default = "default_true")]
+    pub reset: bool,
+    /// Read the flash back and compare it with the image
+    #[serde(default = "default_true")]
+    pub verify: bool,
+    /// Flash page size in bytes, for parts the tool does not know by signature
+    pub page_size: Option<u32>,
+}
+
+fn default_avr_protocol() -> String { "stk500v1".to_string() }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct Stm32InfoArgs {
+    pub connection_id: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct Stm32ReadArgs {
+    pub connection_id: String,
+    /// Start address, e.g. 134217728 (0x08000000) for the start of flash
+    pub address: u32,
+    /// Bytes to read
+    pub length: u32,
+    /// File to save the bytes in; without one they are returned as a hex dump, up to 4096 bytes
+    pub path: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct Stm32EraseArgs {
+    pub connection_id: String,
+    /// Page (or sector) numbers to erase; their size depends on the part
+    #[serde(default)]
+    pub pages: Vec<u16>,
+    /// Erase all of the flash instead
+    #[serde(default)]
+    pub mass: bool,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct Stm32WriteArgs {
+    pub connection_id: String,
+    /// Binary image file on the machine running the server
+    pub path: String,
+    /// Address to write at
+    #[serde(default = "default_stm32_flash_address")]
+    pub address: u32,
+    /// Read the written bytes back and compare them
+    #[serde(default = "default_true")]
+    pub verify: bool,
+    /// Start the written code afterwards by jumping to address
+    #[serde(default)]
+    pub go: bool,
+}
+
+fn default_stm32_flash_address() -> u32 { 0x0800_0000 }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct FlashFirmwareArgs {
+    pub connection_id: String,
+    /// Firmware file on the machine running the server: a raw binary, or Intel HEX (.hex) which carries its own address
+    pub path: String,
+    /// Bootloader protocol: "esp" (ESP8266/ESP32 ROM), "stm32" (system bootloader), "stk500v1" (Arduino optiboot), "stk500v2" (Mega 2560) or "xmodem"
+    pub protocol: String,
+    /// Address to write a binary at; defaults to 0x10000 (the ESP-IDF application) for esp, 0x08000000 for stm32 and 0 for AVR. Unused for HEX files and xmodem
+    pub address: Option<u32>,
+    /// Reset into the bootloader through DTR/RTS first, for esp and the AVR protocols
+    #[serde(default = "default_true")]
+    pub reset: bool,
+    /// ESP reset sequence, as for esp_reset
+    #[serde(default = "default_esp_reset_variant")]
+    pub variant: String,
+    /// The board inverts DTR and RTS, for esp
+    #[serde(default)]
+    pub inverted: bool,
+    /// Text sent first to make the running firmware start its XMODEM receiver, e.g. "update\r"
+    pub bootloader_command: Option<String>,
+    /// How long to wait for the XMODEM receiver to start
+    #[serde(default = "default_firmware_start_timeout_ms")]
+    pub start_timeout_ms: u64,
+    /// For xmodem, command run after the transfer whose output must show the image's MD5 or CRC-32, e.g. "crc32 ${loadaddr} {size_hex}"; {name}, {size} and {size_hex} are filled in
+    pub verify_command: Option<String>,
+    /// How long to wait for the verify command's output
+    #[serde(default = "default_verify_timeout_ms")]
+    pub verify_timeout_ms: u64,
+    /// Mass erase before writing, for stm32, whose flash must be erased to be written
+    #[serde(default = "default_true")]
+    pub erase: bool,
+    /// Check the written image: MD5 for esp, reading it back for stm32 and AVR
+    #[serde(default = "default_true")]
+    pub verify: bool,
+    /// Start the new firmware afterwards
+    #[serde(default = "default_true")]
+    pub run: bool,
+}
+
+fn default_firmware_start_timeout_ms() -> u64 { 30_000 }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct YmodemSendArgs {
+    pub connection_id: String,
+    /// Files on the machine running the server, sent as one batch under their file names
+    pub paths: Vec<String>,
+    /// How long to wait for the receiver to start, e.g. while the device's bootloader is put into receive mode
+    #[serde(default = "default_transfer_timeout_ms")]
+    pub timeout_ms: u64,
+    /// Command run on the device after the transfer, once per file, whose output must show the file's MD5 or CRC-32, e.g. "md5sum {name}" on a shell or "crc32 ${loadaddr} {size_hex}" in U-Boot. {name}, {size} and {size_hex} are filled in
+    pub verify_command: Option<String>,
+    /// How long to wait for the verify command's output
+    #[serde(default = "default_verify_timeout_ms")]
+    pub verify_timeout_ms: u64,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct YmodemReceiveArgs {
+    pub connection_id: String,
+    /// Directory on the machine running the server to save the files in
+    pub directory: String,
+    /// How long to wait for the sender to start
+    #[serde(default = "default_transfer_timeout_ms")]
+    pub timeout_ms: u64,
+    /// Replace files that already exist instead of skipping them
+    #[serde(default)]
+    pub overwrite: bool,
+}
+
+fn default_transfer_timeout_ms() -> u64 { 60_000 }
+fn default_verify_timeout_ms() -> u64 { 10_000 }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ZmodemSendArgs {
+    pub connection_id: String,
+    /// Files on the machine running the server, sent as one batch under their file names
+    pub paths: Vec<String>,
+    /// How long to wait for the receiver to start
+    #[serde(default = "default_transfer_timeout_ms")]
+    pub timeout_ms: u64,
+    /// Ask the receiver to keep any part of a file it already has and take only the rest
+    #[serde(default)]
+    pub resume: bool,
+    /// Command run on the device after the transfer, once per file, whose output must show the file's MD5 or CRC-32, e.g. "md5sum {name}" on a shell or "crc32 ${loadaddr} {size_hex}" in U-Boot. {name}, {size} and {size_hex} are filled in
+    pub verify_command: Option<String>,
+    /// How long to wait for the verify command's output
+    #[serde(default = "default_verify_timeout_ms")]
+    pub verify_timeout_ms: u64,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ZmodemReceiveArgs {
+    pub connection_id: String,
+    /// Directory on the machine running the server to save the files in
+    pub directory: String,
+    /// How long to wait for the sender to start
+    #[serde(default = "default_transfer_timeout_ms")]
+    pub timeout_ms: u64,
+    /// Replace files that already exist instead of skipping them
+    #[serde(default)]
+    pub overwrite: bool,
+    /// Continue files that exist but are shorter than offered, as left by an interrupted transfer
+    #[serde(default)]
+    pub resume: bool,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SetNoteArgs {
+    pub connection_id: String,
+    /// Name of the note, e.g. "wiring"; setting it again replaces the note
+    pub key: String,
+    /// Note text; empty removes the note
+    pub value: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CreateSessionArgs {
+    /// Name to refer to the session by instead of its ID
+    #[serde(default)]
+    pub name: Option<String>,
+    pub port: String,
+    /// Ports tried in order when port cannot be opened, and on reconnect after its link is lost, e.g. a second console server or cable
+    #[serde(default)]
+    pub failover_ports: Vec<String>,
+    /// Defaults to serial.default_baud_rate
+    #[serde(default)]
+    pub baud_rate: Option<u32>,
+    /// 5 to 8
+    #[serde(default)]
+    pub data_bits: Option<u8>,
+    /// "1" or "2"
+    #[serde(default)]
+    pub stop_bits: Option<String>,
+    /// "none", "even" or "odd"
+    #[serde(default)]
+    pub parity: Option<String>,
+    /// "none", "software" or "hardware"
+    #[serde(default)]
+    pub flow_control: Option<String>,
+    /// Read timeout in milliseconds; defaults to serial.default_timeout_ms
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+    /// Line ending the device uses, e.g. "\r\n"; defaults to serial.default_line_ending
+    #[serde(default)]
+    pub line_ending: Option<String>,
+    /// utf8, hex, base64, cbor or msgpack: encoding tools use for the session's data unless a protocol sets one
+    #[serde(default)]
+    pub encoding: Option<String>,
+    /// Reconnect when the connection fails, finding a USB adapter again by its identity if it comes back under another path
+    #[serde(default)]
+    pub auto_reconnect: bool,
+    /// Reconnect attempts before giving up; defaults to serial.retry_count
+    #[serde(default)]
+    pub max_reconnect_attempts: Option<u32>,
+    /// Milliseconds between checks that the device still answers; a failed
+    /// check fails the connection, reconnecting it with auto_reconnect
+    #[serde(default)]
+    pub keepalive_interval_ms: Option<u64>,
+    /// Text sent to check the device answers, e.g. "AT\r"; without one only the modem status lines are read
+    #[serde(default)]
+    pub keepalive_probe: Option<String>,
+    /// Text the reply to the probe has to contain; any reply does without one
+    #[serde(default)]
+    pub keepalive_expect: Option<String>,
+    /// Milliseconds to wait for the reply; defaults to the session's read timeout
+    #[serde(default)]
+    pub keepalive_timeout_ms: Option<u64>,
+    /// "cts", "dsr", "cd" or "ri": status line the device has to keep asserted
+    #[serde(default)]
+    pub keepalive_line: Option<String>,
+    /// Most writes the session may make per second; writes over it are refused with a retry_after_ms
+    #[serde(default)]
+    pub rate_limit_writes_per_second: Option<u32>,
+    /// Most bytes the session may write per second; writes over it are refused with a retry_after_ms
+    #[serde(default)]
+    pub rate_limit_bytes_per_second: Option<u64>,
+    /// Filters received data passes through in order before reads and the transcript see it: "strip_ansi", "normalize_crlf" (CRLF and CR to LF) or "drop_lines:<regex>", e.g. "drop_lines:^HEARTBEAT"
+    #[serde(default)]
+    pub receive_filters: Vec<String>,
+    /// Group to drive the session with others by, e.g. "rack-3"
+    #[serde(default)]
+    pub group: Option<String>,
+    /// Key/value labels to find the session by, e.g. {"device": "relay-board", "site": "lab2"}
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    /// Connect right away
+    #[serde(default = "default_true")]
+    pub connect: bool,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ListSessionsArgs {
+    /// Only list sessions carrying all of these labels
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    /// Only list the sessions of this group
+    #[serde(default)]
+    pub group: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct LabelSessionArgs {
+    /// Session ID, or the name given at creation
+    pub session_id: String,
+    /// Labels to set, replacing the value of any the session already has
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    /// Names of labels to remove
+    #[serde(default)]
+    pub remove: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SetReceiveFiltersArgs {
+    /// Session ID, or the name given at creation
+    pub session_id: String,
+    /// Filters in the order data passes through them: "strip_ansi", "normalize_crlf" (CRLF and CR to LF) or "drop_lines:<regex>"; empty to remove them all
+    #[serde(default)]
+    pub filters: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ImportSessionArgs {
+    /// JSON document returned by export_session
+    pub document: String,
+    /// Port the device is at here, if not where it was when exported
+    #[serde(default)]
+    pub port: Option<String>,
+    /// Name to give the session instead of the exported one, e.g. when that is taken
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Connect right away
+    #[serde(default)]
+    pub connect: bool,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SessionEventsArgs {
+    /// Session ID, or the name given at creation; removed sessions are found by ID only
+    pub session_id: String,
+    /// Most recent events to show
+    #[serde(default = "default_session_events_limit")]
+    pub limit: usize,
+}
+
+fn default_session_events_limit() -> usize { 64 }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SessionArgs {
+    /// Session ID, or the name given at creation
+    pub session_id: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SetSessionGroupArgs {
+    /// Session ID, or the name given at creation
+    pub session_id: String,
+    /// Group to put the session in, e.g. "rack-3"; leave out to take it out of its group
+    #[serde(default)]
+    pub group: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct GroupArgs {
+    /// Group name, e.g. "rack-3"
+    pub group: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct BroadcastWriteArgs {
+    /// Group name, e.g. "rack-3"
+    pub group: String,
+    pub data: String,
+    /// utf8, hex, base64, cbor or msgpack; defaults to each connection's encoding, else utf8
+    #[serde(default)]
+    pub encoding: Option<String>,
+    /// Send each session's line ending after the data
+    #[serde(default)]
+    pub append_line_ending: bool,
+    /// Skip a session if writes submitted earlier still hold its connection after this many milliseconds; waits by default
+    #[serde(default)]
+    pub queue_timeout_ms: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SimulateDisconnectArgs {
+    pub connection_id: String,
+}
 
 #[derive(Debug, Deserialize, JsonSchema)]
 pub struct ConfigureArgs {
@@ -122,6 +1394,53 @@ pub struct StatusResponse {
     pub bytes_received: u64,
 }
 
+/// Connection status with the session tracking it
+#[derive(Debug, Serialize)]
+pub struct StatusReport {
+    #[serde(flatten)]
+    pub status: ConnectionStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session: Option<SessionInfo>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SnapshotResponse {
+    pub captured_at: DateTime<Utc>,
+    pub status: ConnectionStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session: Option<SessionInfo>,
+    pub stats: SnapshotStats,
+    pub transcript: Vec<TranscriptView>,
+    pub events: Vec<ConnectionEvent>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SnapshotStats {
+    pub buffered_bytes: usize,
+    pub dropped_bytes: u64,
+    pub uptime_seconds: i64,
+}
+
+/// Transcript entry rendered both as text and as exact bytes
+#[derive(Debug, Serialize)]
+pub struct TranscriptView {
+    pub timestamp: DateTime<Utc>,
+    pub direction: Direction,
+    pub text: String,
+    pub hex: String,
+}
+
+impl From<TranscriptEntry> for TranscriptView {
+    fn from(entry: TranscriptEntry) -> Self {
+        Self {
+            timestamp: entry.timestamp,
+            direction: entry.direction,
+            text: String::from_utf8_lossy(&entry.data).into_owned(),
+            hex: hex::encode(&entry.data),
+        }
+    }
+}
+
 // 数据编码/解码工具函数
 pub fn encode_data(data: &[u8], encoding: &str) -> Result<String, String> {
     match encoding.to_lowercase().as_str() {
@@ -142,10 +1461,21 @@ pub fn encode_data(data: &[u8], encoding: &str) -> Result<String, String> {
             use base64::{Engine, engine::general_purpose};
             Ok(general_purpose::STANDARD.encode(data))
         },
+        "cbor" => DataConverter::cbor_to_json(data).map_err(|e| e.to_string()),
+        "msgpack" => DataConverter::msgpack_to_json(data).map_err(|e| e.to_string()),
         _ => Err(format!("Unsupported encoding: {}", encoding)),
     }
 }
 
+/// Echo bytes put on the wire as escaped text and a hexdump
+pub fn format_sent(sent: &[u8]) -> String {
+    format!(
+        "Sent (text): \"{}\"\nSent (hex):\n{}",
+        DataConverter::escape_string(&String::from_utf8_lossy(sent)),
+        BufferUtils::hexdump(sent).trim_end()
+    )
+}
+
 pub fn decode_data(data: &str, encoding: &str) -> Result<Vec<u8>, String> {
     match encoding.to_lowercase().as_str() {
         "utf8" | "utf-8" => Ok(data.as_bytes().to_vec()),
@@ -161,13 +1491,77 @@ pub fn decode_data(data: &str, encoding: &str) -> Result<Vec<u8>, String> {
                 .or_else(|_| general_purpose::URL_SAFE_NO_PAD.decode(data))
                 .map_err(|e| format!("Base64 decoding error: {}", e))
         },
+        "cbor" => DataConverter::json_to_cbor(data).map_err(|e| e.to_string()),
+        "msgpack" => DataConverter::json_to_msgpack(data).map_err(|e| e.to_string()),
         _ => Err(format!("Unsupported encoding: {}", encoding)),
     }
 }
 
+/// Received bytes as shown in read responses
+///
+/// Always carries both a readable preview and the exact bytes, so binary data
+/// never fails a read. UTF-8 reads show the data with invalid sequences
+/// replaced, plus the exact bytes as hex; hex and base64 reads are exact
+/// already and add a lossy UTF-8 preview. CBOR and MessagePack reads show the
+/// items as JSON, or why the bytes do not decode, plus the exact bytes as hex.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadPayload {
+    /// The data in the requested encoding, lossy for invalid UTF-8
+    pub data: String,
+    /// `"Hex"` for the exact bytes of a UTF-8 read, `"Preview"` otherwise
+    pub companion_label: &'static str,
+    pub companion: String,
+    /// The data was not valid UTF-8 and `data` is a lossy rendering
+    pub lossy: bool,
+}
+
+impl ReadPayload {
+    /// Fails only for unsupported encodings
+    pub fn new(data: &[u8], encoding: &str) -> Result<Self, String> {
+        match encoding.to_lowercase().as_str() {
+            "utf8" | "utf-8" => Ok(Self {
+                data: String::from_utf8_lossy(data).into_owned(),
+                companion_label: "Hex",
+                companion: encode_data(data, "hex")?,
+                lossy: std::str::from_utf8(data).is_err(),
+            }),
+            // Bytes that are not CBOR, such as half an item, still read
+            "cbor" => Ok(Self {
+                data: DataConverter::cbor_to_json(data).unwrap_or_else(|e| e.to_string()),
+                companion_label: "Hex",
+                companion: encode_data(data, "hex")?,
+                lossy: false,
+            }),
+            "msgpack" => Ok(Self {
+                data: DataConverter::msgpack_to_json(data).unwrap_or_else(|e| e.to_string()),
+                companion_label: "Hex",
+                companion: encode_data(data, "hex")?,
+                lossy: false,
+            }),
+            _ => Ok(Self {
+                data: encode_data(data, encoding)?,
+                companion_label: "Preview",
+                companion: String::from_utf8_lossy(data).into_owned(),
+                lossy: false,
+            }),
+        }
+    }
+}
+
+impl std::fmt::Display for ReadPayload {
+    /// `Data:` line followed by the companion line
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Data: {:?}", self.data)?;
+        if self.lossy {
+            write!(f, " (not valid UTF-8, use Hex for the exact bytes)")?;
+        }
+        write!(f, "\n{}: {:?}", self.companion_label, self.companion)
+    }
+}
+
 impl From<OpenArgs> for ConnectionConfig {
     fn from(args: OpenArgs) -> Self {
-        use crate::serial::{DataBits, StopBits, Parity, FlowControl};
+        use crate::serial::{BackpressurePolicy, DataBits, StopBits, Parity, FlowControl};
         
         let data_bits = match args.data_bits.as_str() {
             "5" => DataBits::Five,
@@ -197,6 +1591,12 @@ impl From<OpenArgs> for ConnectionConfig {
             _ => FlowControl::None,
         };
         
+        let backpressure = args
+            .backpressure
+            .as_deref()
+            .and_then(|policy| BackpressurePolicy::parse(policy).ok())
+            .unwrap_or_default();
+        
         ConnectionConfig {
             port: args.port,
             baud_rate: args.baud_rate,
@@ -204,6 +1604,7 @@ impl From<OpenArgs> for ConnectionConfig {
             stop_bits,
             parity,
             flow_control,
+            backpressure,
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,243 @@
+//! Verification of written and transferred images
+//!
+//! Flash read back is compared byte for byte, and a mismatch is reported as
+//! the ranges that differ with the bytes found at the first one, so a write
+//! that did not take is told apart from flash that was not erased. Images
+//! that cannot be read back are checked against the CRC-32 and MD5 the
+//! device prints for them.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::serial::{LocalSerialError, SerialConnection};
+use crate::utils::BufferUtils;
+
+/// Differing ranges listed before the rest are only counted
+const MAX_LISTED_RANGES: usize = 8;
+
+/// Bytes shown from the first difference
+const SHOWN_BYTES: usize = 16;
+
+/// Reads while waiting for a verify command's output
+const POLL_MS: u64 = 100;
+
+/// End of a verify command's output shown when it lacks the digest
+const MAX_SHOWN_OUTPUT: usize = 500;
+
+/// Digests of an image, as devices print them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Digests {
+    pub crc32: u32,
+    /// Lowercase hex
+    pub md5: String,
+}
+
+impl Digests {
+    pub fn of(data: &[u8]) -> Self {
+        Self { crc32: BufferUtils::crc32(data), md5: format!("{:x}", md5::compute(data)) }
+    }
+
+    /// Whether `output` shows either digest in hex, in any case
+    pub fn found_in(&self, output: &str) -> bool {
+        let output = output.to_lowercase();
+        output.contains(&self.md5) || output.contains(&format!("{:08x}", self.crc32))
+    }
+}
+
+impl fmt::Display for Digests {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CRC-32 {:08x}, MD5 {}", self.crc32, self.md5)
+    }
+}
+
+/// Where read-back data differs from what was written
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    /// Bytes that differ
+    pub bytes: usize,
+    /// Differing ranges as start and end addresses, the end excluded
+    pub ranges: Vec<(u64, u64)>,
+    /// Up to 16 bytes written and read from the first difference
+    pub expected: Vec<u8>,
+    pub actual: Vec<u8>,
+    /// Bytes written that were not read back
+    pub missing: usize,
+    /// Every differing byte read as erased
+    pub erased: bool,
+    /// Every differing byte was written as erased
+    pub unerased: bool,
+}
+
+/// Compare what was written at `address` with what was read back
+pub fn compare(address: u64, expected: &[u8], actual: &[u8]) -> Option<Mismatch> {
+    let mut ranges: Vec<(u64, u64)> = Vec::new();
+    let mut bytes = 0;
+    let (mut erased, mut unerased) = (true, true);
+    for (offset, (&written, &read)) in expected.iter().zip(actual).enumerate() {
+        if written == read {
+            continue;
+        }
+        bytes += 1;
+        erased &= read == 0xFF;
+        unerased &= written == 0xFF;
+        let at = address + offset as u64;
+        match ranges.last_mut() {
+            Some((_, end)) if *end == at => *end += 1,
+            _ => ranges.push((at, at + 1)),
+        }
+    }
+    let missing = expected.len().saturating_sub(actual.len());
+    if bytes == 0 && missing == 0 {
+        return None;
+    }
+    let first = ranges.first().map_or(actual.len(), |(start, _)| (start - address) as usize);
+    Some(Mismatch {
+        bytes,
+        ranges,
+        expected: expected[first..].iter().take(SHOWN_BYTES).copied().collect(),
+        actual: actual[first.min(actual.len())..].iter().take(SHOWN_BYTES).copied().collect(),
+        missing,
+        erased: bytes > 0 && erased,
+        unerased: bytes > 0 && unerased,
+    })
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Verify failed: ")?;
+        if self.bytes > 0 {
+            let first = self.ranges[0].0;
+            write!(
+                f,
+                "{} bytes differ in {} ranges ({}); at 0x{:08X} wrote {}, read {}",
+                self.bytes,
+                self.ranges.len(),
+                format_ranges(&self.ranges),
+                first,
+                hex(&self.expected),
+                hex(&self.actual)
+            )?;
+            if self.erased {
+                write!(f, "; the differing bytes read as erased, so the write did not take")?;
+            } else if self.unerased {
+                write!(f, "; the differing bytes should be erased, so the flash was not erased first")?;
+            }
+        }
+        if self.missing > 0 {
+            write!(f, "{}{} bytes were not read back", if self.bytes > 0 { "; " } else { "" }, self.missing)?;
+        }
+        Ok(())
+    }
+}
+
+/// Ranges as `0x08000100-0x0800011F`, the end included, the first few listed
+pub fn format_ranges(ranges: &[(u64, u64)]) -> String {
+    let mut listed: Vec<String> = ranges
+        .iter()
+        .take(MAX_LISTED_RANGES)
+        .map(|&(start, end)| match end - start {
+            1 => format!("0x{:08X}", start),
+            _ => format!("0x{:08X}-0x{:08X}", start, end - 1),
+        })
+        .collect();
+    if ranges.len() > MAX_LISTED_RANGES {
+        listed.push(format!("and {} more", ranges.len() - MAX_LISTED_RANGES));
+    }
+    listed.join(", ")
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02X}", byte)).collect::<Vec<_>>().join(" ")
+}
+
+/// The command with `{name}`, `{size}` and `{size_hex}` filled in for a file
+pub fn fill_command(command: &str, name: &str, size: usize) -> String {
+    command.replace("{name}", name).replace("{size_hex}", &format!("{:x}", size)).replace("{size}", &size.to_string())
+}
+
+/// Send a verify command and wait for its output to show one of the digests
+///
+/// Returns whether it did, with the output collected until then.
+pub async fn run_verify_command(
+    connection: &SerialConnection,
+    command: &str,
+    digests: &Digests,
+    timeout: Duration,
+) -> Result<(bool, String), LocalSerialError> {
+    connection.write(format!("{}\r", command).as_bytes()).await?;
+    let deadline = Instant::now() + timeout;
+    let mut output = String::new();
+    while Instant::now() < deadline {
+        let mut buffer = [0u8; 1024];
+        match connection.read(&mut buffer, Some(POLL_MS)).await {
+            Ok(0) | Err(LocalSerialError::ReadTimeout) => {}
+            Ok(n) => output.push_str(&String::from_utf8_lossy(&buffer[..n])),
+            Err(e) => return Err(e),
+        }
+        if digests.found_in(&output) {
+            return Ok((true, output));
+        }
+    }
+    Ok((false, output))
+}
+
+/// Run the verify command for each file sent, one line per file, or why the first failed
+pub async fn verify_files(connection: &SerialConnection, command: &str, files: &[(&str, &[u8])], timeout: Duration) -> Result<Vec<String>, String> {
+    let mut lines = Vec::new();
+    for &(name, data) in files {
+        let digests = Digests::of(data);
+        let command = fill_command(command, name, data.len());
+        let (found, output) = run_verify_command(connection, &command, &digests, timeout).await.map_err(|e| e.to_string())?;
+        if !found {
+            let output = output.trim();
+            let mut cut = output.len().saturating_sub(MAX_SHOWN_OUTPUT);
+            while !output.is_char_boundary(cut) {
+                cut += 1;
+            }
+            return Err(format!(
+                "Verify failed for {}: {:?} did not print {} within {}ms; it printed: {}",
+                name,
+                command,
+                digests,
+                timeout.as_millis(),
+                &output[cut..]
+            ));
+        }
+        lines.push(format!("  {}: {}, confirmed by the device", name, digests));
+    }
+    Ok(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare() {
+        assert_eq!(compare(0x100, &[1, 2, 3], &[1, 2, 3]), None);
+
+        let mismatch = compare(0x0800_0000, &[1, 2, 3, 4, 5, 6], &[1, 0xFF, 0xFF, 4, 0xFF, 6]).unwrap();
+        assert_eq!(mismatch.bytes, 3);
+        assert_eq!(mismatch.ranges, vec![(0x0800_0001, 0x0800_0003), (0x0800_0004, 0x0800_0005)]);
+        assert_eq!(mismatch.expected, vec![2, 3, 4, 5, 6]);
+        assert!(mismatch.erased && !mismatch.unerased);
+        assert_eq!(
+            mismatch.to_string(),
+            "Verify failed: 3 bytes differ in 2 ranges (0x08000001-0x08000002, 0x08000004); at 0x08000001 wrote 02 03 04 05 06, read FF FF 04 FF 06; the differing bytes read as erased, so the write did not take"
+        );
+
+        let short = compare(0, &[0xFF, 2, 3], &[0x00, 2]).unwrap();
+        assert!(short.unerased);
+        assert_eq!(short.missing, 1);
+    }
+
+    #[test]
+    fn test_digests() {
+        let digests = Digests::of(b"123456789");
+        assert_eq!(digests.crc32, 0xCBF4_3926);
+        assert!(digests.found_in("crc32 for 82000000 ... 82000008 ==> CBF43926\n=> "));
+        assert!(digests.found_in("25f9e794323b453885f5181f1b624d0b  fw.bin\n# "));
+        assert!(!digests.found_in("d41d8cd98f00b204e9800998ecf8427e  fw.bin"));
+        assert_eq!(fill_command("crc32 ${loadaddr} {size_hex}; md5sum {name} # {size}", "fw.bin", 4096), "crc32 ${loadaddr} 1000; md5sum fw.bin # 4096");
+    }
+}
@@ -0,0 +1,49 @@
+//! Warnings on successful tool results
+//!
+//! Some operations succeed in a degraded way: received data was lost to a
+//! buffer overflow, a frame failed its checksum and was skipped. Such problems
+//! are listed in a `Warnings:` section of the result text and repeated as a
+//! JSON `{"warnings": [...]}` content item, so clients can spot them without
+//! parsing the message.
+
+use rmcp::{model::*, ErrorData as McpError};
+use serde::Serialize;
+
+#[derive(Debug, Default, Serialize)]
+pub struct Warnings {
+    warnings: Vec<String>,
+}
+
+impl Warnings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, warning: impl Into<String>) {
+        self.warnings.push(warning.into());
+    }
+
+    /// Warn about receive buffer overflow drops, if there were any
+    pub fn dropped_bytes(&mut self, dropped: u64) {
+        if dropped > 0 {
+            self.push(format!("{} bytes dropped due to receive buffer overflow since the last read", dropped));
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.warnings.is_empty()
+    }
+
+    /// A successful result with `message` and any warnings
+    pub fn into_result(self, mut message: String) -> Result<CallToolResult, McpError> {
+        if self.is_empty() {
+            return Ok(CallToolResult::success(vec![Content::text(message)]));
+        }
+
+        message.push_str("\n\nWarnings:");
+        for warning in &self.warnings {
+            message.push_str(&format!("\n- {}", warning));
+        }
+        Ok(CallToolResult::success(vec![Content::text(message), Content::json(&self)?]))
+    }
+}
@@ -0,0 +1,161 @@
+//! Safety watchdog
+//!
+//! Heartbeat pings notice a vanished client only after the client timeout,
+//! far too late for a device left moving, such as a motor mid-jog. While any
+//! connection has shutdown commands from its profile, the watchdog pings the
+//! client often enough to notice its loss within the safety deadline and then
+//! sends those commands to put every such device in a safe state. The ports
+//! stay open for the orphan policy to deal with; each intervention is recorded
+//! in the connection's event history.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::future::join_all;
+use rmcp::{Peer, RoleServer, ServiceError};
+use tracing::{debug, info, warn};
+
+use super::liveness::{ping, ClientLiveness};
+use super::maintenance::MaintenanceState;
+use crate::serial::ConnectionManager;
+
+/// Pings sent per deadline, so a lost client is noticed with time left to act
+const PINGS_PER_DEADLINE: u32 = 4;
+
+/// Ping the client while any connection has shutdown commands, until the transport closes
+///
+/// Once the client has been silent long enough that waiting for the next ping
+/// would miss `deadline`, the shutdown commands run on every connection that
+/// has them. The watchdog re-arms when the client answers again.
+pub(crate) async fn run_safety_watchdog(
+    peer: Peer<RoleServer>,
+    manager: Arc<ConnectionManager>,
+    liveness: Arc<ClientLiveness>,
+    maintenance: Arc<MaintenanceState>,
+    deadline: Duration,
+) {
+    let interval = deadline / PINGS_PER_DEADLINE;
+    let mut tripped = false;
+
+    loop {
+        tokio::time::sleep(interval).await;
+        if maintenance.is_active() {
+            // Silence during maintenance says nothing about the client
+            liveness.touch().await;
+            continue;
+        }
+        if !tripped && !any_guarded(&manager).await {
+            continue;
+        }
+
+        match ping(&peer, interval).await {
+            Ok(()) => {
+                liveness.touch().await;
+                if tripped {
+                    info!("Client is responding again, safety watchdog re-armed");
+                    tripped = false;
+                }
+            }
+            Err(e) => {
+                let closed = matches!(e, ServiceError::TransportClosed);
+                debug!("Safety watchdog ping failed: {}", e);
+
+                let silent_for = liveness.since_last_seen().await;
+                // The next failed ping comes two intervals from now
+                if !tripped && (closed || silent_for + 2 * interval > deadline) {
+                    warn!("Client silent for {:?}, putting guarded devices in a safe state", silent_for);
+                    intervene(&manager, silent_for).await;
+                    tripped = true;
+                }
+
+                if closed {
+                    info!("Client transport closed, stopping safety watchdog");
+                    break;
+                }
+            }
+        }
+    }
+}
+
+async fn any_guarded(manager: &ConnectionManager) -> bool {
+    for connection in manager.connections().await {
+        if connection.has_teardown().await {
+            return true;
+        }
+    }
+    false
+}
+
+/// Run the shutdown commands of every connection that has them, all at once,
+/// returning how many connections were put in a safe state
+pub async fn intervene(manager: &ConnectionManager, silent_for: Duration) -> usize {
+    let connections = manager.connections().await;
+    let outcomes = join_all(connections.iter().map(|connection| async move {
+        let outcome = connection.run_safe_state().await?;
+        let event = match &outcome {
+            Ok(sent) => format!(
+                "Safety watchdog: client silent for {}ms, sent {} shutdown command(s)",
+                silent_for.as_millis(),
+                sent
+            ),
+            Err(reason) => format!(
+                "Safety watchdog: client silent for {}ms, shutdown commands incomplete: {}",
+                silent_for.as_millis(),
+                reason
+            ),
+        };
+        warn!("Connection {}: {}", connection.id(), event);
+        connection.record_event(event).await;
+        Some(outcome)
+    }))
+    .await;
+
+    outcomes.into_iter().flatten().filter(Result::is_ok).count()
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_intervene_runs_shutdown_commands_and_keeps_them() {
+        use crate::serial::{BackpressurePolicy, CommandSequence, ConnectionConfig, DataBits, FlowControl, Parity, StopBits};
+        use tokio::io::AsyncReadExt;
+        use tokio_serial::{SerialPort, SerialStream};
+
+        let (mut device, slave) = SerialStream::pair().unwrap();
+        let manager = ConnectionManager::new();
+        let id = manager
+            .open(ConnectionConfig {
+                port: slave.name().unwrap(),
+                baud_rate: 115200,
+                data_bits: DataBits::Eight,
+                stop_bits: StopBits::One,
+                parity: Parity::None,
+                flow_control: FlowControl::None,
+                backpressure: BackpressurePolicy::Drop,
+            })
+            .await
+            .unwrap();
+        let connection = manager.get(&id).await.unwrap();
+        assert_eq!(intervene(&manager, Duration::from_millis(500)).await, 0);
+
+        connection
+            .set_teardown(CommandSequence {
+                phase: "Shutdown",
+                commands: vec!["M112".to_string()],
+                line_ending: "\n".to_string(),
+                settle_ms: 50,
+            })
+            .await;
+        assert_eq!(intervene(&manager, Duration::from_millis(500)).await, 1);
+
+        let mut sent = [0u8; 5];
+        device.read_exact(&mut sent).await.unwrap();
+        assert_eq!(&sent, b"M112\n");
+        // Still runs when the connection closes
+        assert!(connection.has_teardown().await);
+        let events: Vec<String> = connection.events().await.into_iter().map(|event| event.message).collect();
+        assert!(events.contains(&"Safety watchdog: client silent for 500ms, sent 1 shutdown command(s)".to_string()));
+    }
+}
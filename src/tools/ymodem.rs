@@ -0,0 +1,187 @@
+//! YMODEM transfer tools
+//!
+//! Send local files to a device waiting in YMODEM receive mode, such as an
+//! STM32 IAP bootloader or U-Boot's `loady`, or save the files a device sends.
+
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use rmcp::{
+    handler::server::tool::Parameters,
+    model::*,
+    tool, tool_router, ErrorData as McpError,
+};
+use tracing::{debug, error, info};
+
+use super::serial_handler::SerialHandler;
+use super::types::*;
+use super::verify::{verify_files, Digests};
+use super::warnings::Warnings;
+use crate::protocol::ymodem::{self, TransferProgress, YmodemFile, MAX_YMODEM_FILE};
+use crate::serial::SerialConnection;
+
+#[tool_router(router = ymodem_router, vis = "pub(crate)")]
+impl SerialHandler {
+    #[tool(description = "Send local files to a device over YMODEM, as one batch with each file's name and size in its header block. Start the device's YMODEM receive first (e.g. loady in U-Boot or the download option of an STM32 IAP bootloader); the transfer begins when it sends C, waiting up to timeout_ms. Uses 1K blocks with CRC-16. With verify_command, e.g. \"crc32 ${loadaddr} {size_hex}\" in U-Boot, the command is run afterwards and its output must show each file's CRC-32 or MD5; otherwise the digests are listed to check by hand. The connection must be in raw mode")]
+    async fn ymodem_send(&self, Parameters(args): Parameters<YmodemSendArgs>) -> Result<CallToolResult, McpError> {
+        if args.paths.is_empty() {
+            return Err(McpError::invalid_params("Error: No files to send".to_string(), None));
+        }
+        let mut files = Vec::new();
+        for path in &args.paths {
+            let (name, data) = read_local_file(path, "YMODEM", MAX_YMODEM_FILE)?;
+            files.push(YmodemFile { name, data });
+        }
+        let connection = self.raw_connection(&args.connection_id, "transfer files with YMODEM").await?;
+        debug!("Sending {} files over YMODEM on connection {}", files.len(), args.connection_id);
+
+        let _exchange = connection.begin_exchange().await;
+        let mut progress = Vec::new();
+        let result = ymodem::send(&connection, &files, Duration::from_millis(args.timeout_ms), &mut progress).await;
+        let summary = format_progress(&progress);
+        if let Err(e) = result {
+            error!("YMODEM send on connection {} failed: {}", args.connection_id, e);
+            connection.record_event(format!("YMODEM send failed: {}", e)).await;
+            let error_msg = format!("Error: YMODEM send failed - {}\n{}", e, summary);
+            return Err(McpError::internal_error(error_msg, None));
+        }
+
+        let sent: Vec<(&str, &[u8])> = files.iter().map(|file| (file.name.as_str(), file.data.as_slice())).collect();
+        let verified = verify_sent(&connection, &args.connection_id, "YMODEM", args.verify_command.as_deref(), &sent, args.verify_timeout_ms).await?;
+
+        let bytes: usize = files.iter().map(|file| file.data.len()).sum();
+        info!("Sent {} files ({} bytes) over YMODEM on connection {}", files.len(), bytes, args.connection_id);
+        let message = format!(
+            "YMODEM send complete\nConnection ID: {}\nFiles: {}\nBytes: {}\n{}\n{}",
+            args.connection_id,
+            files.len(),
+            bytes,
+            summary,
+            verified
+        );
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+
+    #[tool(description = "Receive files a device sends over YMODEM and save them in a local directory under the names it gives (directory parts are dropped). Asks the sender to start with C for up to timeout_ms, so start the device's send just before or after calling. Existing files are skipped unless overwrite is set. The connection must be in raw mode")]
+    async fn ymodem_receive(&self, Parameters(args): Parameters<YmodemReceiveArgs>) -> Result<CallToolResult, McpError> {
+        let directory = PathBuf::from(&args.directory);
+        if !directory.is_dir() {
+            return Err(McpError::invalid_params(format!("Error: {} is not a directory", args.directory), None));
+        }
+        let connection = self.raw_connection(&args.connection_id, "transfer files with YMODEM").await?;
+        debug!("Receiving files over YMODEM on connection {}", args.connection_id);
+
+        let _exchange = connection.begin_exchange().await;
+        let mut files = Vec::new();
+        let mut current = None;
+        let result = ymodem::receive(&connection, Duration::from_millis(args.timeout_ms), &mut files, &mut current).await;
+
+        // Files that arrived whole are kept even if a later one failed
+        let mut warnings = Warnings::new();
+        let saved = save_files(&directory, &files, args.overwrite, &mut warnings);
+        if let Err(e) = result {
+            error!("YMODEM receive on connection {} failed: {}", args.connection_id, e);
+            connection.record_event(format!("YMODEM receive failed: {}", e)).await;
+            let mut error_msg = format!("Error: YMODEM receive failed - {}\nSaved before the failure: {}", e, saved.iter().flatten().count());
+            if let Some(current) = current.filter(|current| !current.complete) {
+                error_msg.push_str(&format!("\n{}", format_progress(&[current])));
+            }
+            return Err(McpError::internal_error(error_msg, None));
+        }
+
+        info!("Received {} files over YMODEM on connection {}", files.len(), args.connection_id);
+        let mut message = format!("YMODEM receive complete\nConnection ID: {}\nFiles: {}", args.connection_id, files.len());
+        for (file, path) in files.iter().zip(&saved) {
+            let digests = Digests::of(&file.data);
+            match path {
+                Some(path) => message.push_str(&format!("\n  {} ({} bytes, {}) -> {}", file.name, file.data.len(), digests, path.display())),
+                None => message.push_str(&format!("\n  {} ({} bytes, {}) not saved", file.name, file.data.len(), digests)),
+            }
+        }
+        warnings.into_result(message)
+    }
+}
+
+/// A local file to send, as its file name and contents
+pub(super) fn read_local_file(path: &str, protocol: &str, limit: u64) -> Result<(String, Vec<u8>), McpError> {
+    let metadata = std::fs::metadata(path).map_err(|e| McpError::invalid_params(format!("Error: Cannot access {} - {}", path, e), None))?;
+    if metadata.len() > limit {
+        let error_msg = format!("Error: {} is {} bytes, {} transfers are limited to {} bytes", path, metadata.len(), protocol, limit);
+        return Err(McpError::invalid_params(error_msg, None));
+    }
+    let name = Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .ok_or_else(|| McpError::invalid_params(format!("Error: {} has no file name", path), None))?;
+    let data = std::fs::read(path).map_err(|e| McpError::internal_error(format!("Error: Failed to read {} - {}", path, e), None))?;
+    Ok((name, data))
+}
+
+/// Run the verify command for the files sent, describing the result
+///
+/// Without a command, the digests are listed so they can be checked by hand.
+pub(super) async fn verify_sent(
+    connection: &SerialConnection,
+    connection_id: &str,
+    protocol: &str,
+    command: Option<&str>,
+    files: &[(&str, &[u8])],
+    timeout_ms: u64,
+) -> Result<String, McpError> {
+    let Some(command) = command else {
+        let lines: Vec<String> = files.iter().map(|(name, data)| format!("  {}: {}", name, Digests::of(data))).collect();
+        return Ok(format!("Verified: no\n{}", lines.join("\n")));
+    };
+    match verify_files(connection, command, files, Duration::from_millis(timeout_ms)).await {
+        Ok(lines) => Ok(format!("Verified: yes\n{}", lines.join("\n"))),
+        Err(reason) => {
+            error!("{} send on connection {} did not verify: {}", protocol, connection_id, reason);
+            connection.record_event(format!("{} send did not verify: {}", protocol, reason)).await;
+            Err(McpError::internal_error(format!("Error: {} sent, but {}", protocol, reason), None))
+        }
+    }
+}
+
+/// Write each file into `directory`, returning where each went
+fn save_files(directory: &Path, files: &[YmodemFile], overwrite: bool, warnings: &mut Warnings) -> Vec<Option<PathBuf>> {
+    files
+        .iter()
+        .map(|file| {
+            let Some(name) = local_file_name(&file.name) else {
+                warnings.push(format!("{:?} is not a usable file name, not saved", file.name));
+                return None;
+            };
+            let path = directory.join(name);
+            if path.exists() && !overwrite {
+                warnings.push(format!("{} already exists, not overwritten", path.display()));
+                return None;
+            }
+            match std::fs::write(&path, &file.data) {
+                Ok(()) => Some(path),
+                Err(e) => {
+                    warnings.push(format!("Failed to write {} - {}", path.display(), e));
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// The last component of a name the sender gave, if it is a plain file name
+pub(super) fn local_file_name(name: &str) -> Option<&str> {
+    let name = name.rsplit(['/', '\\']).next()?;
+    (!name.is_empty() && name != "." && name != "..").then_some(name)
+}
+
+fn format_progress(progress: &[TransferProgress]) -> String {
+    progress
+        .iter()
+        .map(|file| {
+            let size = file.size.map_or_else(|| "unknown size".to_string(), |size| format!("{} bytes", size));
+            let state = if file.complete { "done" } else { "incomplete" };
+            format!("  {} ({}): {} bytes, {} retries, {}", file.name, size, file.bytes, file.retries, state)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
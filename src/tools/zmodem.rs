@@ -0,0 +1,199 @@
+//! ZMODEM transfer tools
+//!
+//! Send files to `rz` on a device shell, or fetch what `sz` sends. Streaming
+//! makes ZMODEM much faster than YMODEM for large images, and a transfer cut
+//! short leaves a partial file that a later call with `resume` completes.
+
+use std::fs::OpenOptions;
+use std::future::Future;
+use std::io::{Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use rmcp::{
+    handler::server::tool::Parameters,
+    model::*,
+    tool, tool_router, ErrorData as McpError,
+};
+use tracing::{debug, error, info};
+
+use super::serial_handler::SerialHandler;
+use super::types::*;
+use super::warnings::Warnings;
+use super::verify::Digests;
+use super::ymodem::{local_file_name, read_local_file, verify_sent};
+use crate::protocol::zmodem::{self, ReceivedFile, SendProgress, ZmodemFile, MAX_ZMODEM_FILE};
+
+#[tool_router(router = zmodem_router, vis = "pub(crate)")]
+impl SerialHandler {
+    #[tool(description = "Send local files to a device over ZMODEM, streaming each without waiting for acknowledgements, so large images go much faster than with YMODEM. Sends rz and a carriage return first, which starts the receiver when the other end is a shell, then waits up to timeout_ms for it. With resume, the receiver keeps what it has of each file and takes the rest, continuing an interrupted send. With verify_command, e.g. \"md5sum {name}\", the command is run on the device afterwards and its output must show each file's MD5 or CRC-32. The connection must be in raw mode")]
+    async fn zmodem_send(&self, Parameters(args): Parameters<ZmodemSendArgs>) -> Result<CallToolResult, McpError> {
+        if args.paths.is_empty() {
+            return Err(McpError::invalid_params("Error: No files to send".to_string(), None));
+        }
+        let mut files = Vec::new();
+        for path in &args.paths {
+            let (name, data) = read_local_file(path, "ZMODEM", MAX_ZMODEM_FILE)?;
+            files.push(ZmodemFile { name, data });
+        }
+        let connection = self.raw_connection(&args.connection_id, "transfer files with ZMODEM").await?;
+        debug!("Sending {} files over ZMODEM on connection {}", files.len(), args.connection_id);
+
+        let _exchange = connection.begin_exchange().await;
+        let mut progress = Vec::new();
+        let timeout = Duration::from_millis(args.timeout_ms);
+        let result = zmodem::send(&connection, &files, timeout, args.resume, &mut progress).await;
+        let summary = format_sent(&progress);
+        if let Err(e) = result {
+            error!("ZMODEM send on connection {} failed: {}", args.connection_id, e);
+            connection.record_event(format!("ZMODEM send failed: {}", e)).await;
+            let error_msg = format!("Error: ZMODEM send failed - {}\n{}", e, summary);
+            return Err(McpError::internal_error(error_msg, None));
+        }
+
+        let sent: Vec<(&str, &[u8])> = files.iter().map(|file| (file.name.as_str(), file.data.as_slice())).collect();
+        let verified = verify_sent(&connection, &args.connection_id, "ZMODEM", args.verify_command.as_deref(), &sent, args.verify_timeout_ms).await?;
+
+        let bytes: u64 = progress.iter().filter(|file| !file.skipped).map(|file| file.bytes - file.start).sum();
+        info!("Sent {} files ({} bytes) over ZMODEM on connection {}", files.len(), bytes, args.connection_id);
+        let message = format!(
+            "ZMODEM send complete\nConnection ID: {}\nFiles: {}\nBytes sent: {}\n{}\n{}",
+            args.connection_id,
+            files.len(),
+            bytes,
+            summary,
+            verified
+        );
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+
+    #[tool(description = "Receive files a device sends over ZMODEM, e.g. with sz on its shell, and save them in a local directory under the names it gives (directory parts are dropped). Waits up to timeout_ms for the sender to start; nothing is sent to the device until it does. Existing files are skipped unless overwrite is set. A file cut short is kept as far as it got; with resume, or when the sender asks to resume, a file shorter than offered is continued from its end. The connection must be in raw mode")]
+    async fn zmodem_receive(&self, Parameters(args): Parameters<ZmodemReceiveArgs>) -> Result<CallToolResult, McpError> {
+        let directory = PathBuf::from(&args.directory);
+        if !directory.is_dir() {
+            return Err(McpError::invalid_params(format!("Error: {} is not a directory", args.directory), None));
+        }
+        let connection = self.raw_connection(&args.connection_id, "transfer files with ZMODEM").await?;
+        debug!("Receiving files over ZMODEM on connection {}", args.connection_id);
+
+        let _exchange = connection.begin_exchange().await;
+        let mut warnings = Warnings::new();
+        let mut paths = Vec::new();
+        let mut files = Vec::new();
+        let accept = |name: &str, size: Option<u64>, sender_resume: bool| {
+            let offset = start_offset(&directory, name, size, args.overwrite, args.resume || sender_resume, &mut warnings)?;
+            paths.push(directory.join(local_file_name(name)?));
+            Some(offset)
+        };
+        let result = zmodem::receive(&connection, Duration::from_millis(args.timeout_ms), accept, &mut files).await;
+
+        // What arrived is kept even if the transfer failed, so it can be resumed
+        let mut lines = Vec::new();
+        for (file, path) in files.iter().zip(&paths) {
+            if !file.complete && file.data.is_empty() {
+                lines.push(format!("  {}: nothing received", file.name));
+                continue;
+            }
+            match save_file(path, file) {
+                Ok(()) => {
+                    let mut line = format_received(file, path);
+                    // A resumed file is only whole on disk
+                    if file.complete {
+                        if let Ok(data) = std::fs::read(path) {
+                            line.push_str(&format!(", {}", Digests::of(&data)));
+                        }
+                    }
+                    lines.push(line);
+                }
+                Err(e) => warnings.push(format!("Failed to write {} - {}", path.display(), e)),
+            }
+        }
+        let summary = lines.join("\n");
+        if let Err(e) = result {
+            error!("ZMODEM receive on connection {} failed: {}", args.connection_id, e);
+            connection.record_event(format!("ZMODEM receive failed: {}", e)).await;
+            let mut error_msg = format!("Error: ZMODEM receive failed - {}\n{}", e, summary);
+            if files.iter().any(|file| !file.complete && !file.data.is_empty()) {
+                error_msg.push_str("\nReceive again with resume to continue incomplete files");
+            }
+            return Err(McpError::internal_error(error_msg, None));
+        }
+
+        info!("Received {} files over ZMODEM on connection {}", files.len(), args.connection_id);
+        let message = format!("ZMODEM receive complete\nConnection ID: {}\nFiles: {}\n{}", args.connection_id, files.len(), summary);
+        warnings.into_result(message)
+    }
+}
+
+/// Where to start an offered file, `None` to skip it
+fn start_offset(directory: &Path, name: &str, size: Option<u64>, overwrite: bool, resume: bool, warnings: &mut Warnings) -> Option<u64> {
+    let Some(local_name) = local_file_name(name) else {
+        warnings.push(format!("{:?} is not a usable file name, skipped", name));
+        return None;
+    };
+    let path = directory.join(local_name);
+    match std::fs::metadata(&path) {
+        Err(_) => Some(0),
+        Ok(metadata) if !metadata.is_file() => {
+            warnings.push(format!("{} is not a file, skipped", path.display()));
+            None
+        }
+        Ok(metadata) if resume => {
+            if size.is_some_and(|size| metadata.len() >= size) {
+                warnings.push(format!("{} is already complete, skipped", path.display()));
+                return None;
+            }
+            Some(metadata.len())
+        }
+        Ok(_) if overwrite => Some(0),
+        Ok(_) => {
+            warnings.push(format!("{} already exists, skipped", path.display()));
+            None
+        }
+    }
+}
+
+/// Write a received file, appending to the part already held when it was resumed
+fn save_file(path: &Path, file: &ReceivedFile) -> std::io::Result<()> {
+    if file.offset == 0 {
+        return std::fs::write(path, &file.data);
+    }
+    let mut out = OpenOptions::new().write(true).open(path)?;
+    out.set_len(file.offset)?;
+    out.seek(SeekFrom::End(0))?;
+    out.write_all(&file.data)
+}
+
+fn format_received(file: &ReceivedFile, path: &Path) -> String {
+    let held = file.offset + file.data.len() as u64;
+    let size = file.size.map_or_else(|| "unknown size".to_string(), |size| format!("of {}", size));
+    let mut line = format!("  {}: {} bytes {} -> {}", file.name, held, size, path.display());
+    if file.offset > 0 {
+        line.push_str(&format!(", resumed at {}", file.offset));
+    }
+    if file.retries > 0 {
+        line.push_str(&format!(", {} retries", file.retries));
+    }
+    if !file.complete {
+        line.push_str(", incomplete");
+    }
+    line
+}
+
+fn format_sent(progress: &[SendProgress]) -> String {
+    progress
+        .iter()
+        .map(|file| {
+            if file.skipped {
+                return format!("  {} ({} bytes): skipped by the receiver", file.name, file.size);
+            }
+            let state = if file.complete { "done" } else { "incomplete" };
+            let mut line = format!("  {} ({} bytes): sent up to {}, {} retries, {}", file.name, file.size, file.bytes, file.retries, state);
+            if file.start > 0 {
+                line.push_str(&format!(", resumed at {}", file.start));
+            }
+            line
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
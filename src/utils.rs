@@ -5,10 +5,11 @@
 
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use base64::prelude::*;
+use serde::{Deserialize, Serialize};
 use crate::error::{SerialError, Result};
 
 /// Serial port type enumeration
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum PortType {
     /// USB-to-Serial adapter
     UsbSerial,
@@ -69,6 +70,7 @@ pub enum DataFormat {
 
 impl DataFormat {
     /// Parse format from string
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Result<Self> {
         match s.to_lowercase().as_str() {
             "text" | "utf8" | "string" => Ok(DataFormat::Text),
@@ -174,6 +176,135 @@ impl DataConverter {
         
         Ok(result)
     }
+
+    /// Decode a CBOR item, or a sequence of them, as JSON text with one item per line
+    ///
+    /// Byte strings become hex, tagged items `{"tag": n, "value": ...}`, and
+    /// map keys that are not text their JSON rendering.
+    pub fn cbor_to_json(data: &[u8]) -> Result<String> {
+        let mut remaining = data;
+        let mut items = Vec::new();
+        while !remaining.is_empty() {
+            let item: ciborium::Value = ciborium::from_reader(&mut remaining)
+                .map_err(|e| SerialError::EncodingError(format!("CBOR decoding failed at byte {}: {}", data.len() - remaining.len(), e)))?;
+            items.push(cbor_value_to_json(item).to_string());
+        }
+        if items.is_empty() {
+            return Err(SerialError::EncodingError("CBOR decoding failed: no data".to_string()));
+        }
+        Ok(items.join("\n"))
+    }
+
+    /// Encode JSON text as one CBOR item
+    pub fn json_to_cbor(data: &str) -> Result<Vec<u8>> {
+        let value: serde_json::Value = serde_json::from_str(data)
+            .map_err(|e| SerialError::EncodingError(format!("Invalid JSON for CBOR: {}", e)))?;
+        let mut encoded = Vec::new();
+        ciborium::into_writer(&value, &mut encoded)
+            .map_err(|e| SerialError::EncodingError(format!("CBOR encoding failed: {}", e)))?;
+        Ok(encoded)
+    }
+
+    /// Decode a MessagePack value, or a run of them, as JSON text with one value per line
+    ///
+    /// Binary becomes hex, extension types `{"ext": type, "data": hex}`, and
+    /// map keys that are not strings their JSON rendering.
+    pub fn msgpack_to_json(data: &[u8]) -> Result<String> {
+        let mut remaining = data;
+        let mut items = Vec::new();
+        while !remaining.is_empty() {
+            let item = rmpv::decode::read_value(&mut remaining)
+                .map_err(|e| SerialError::EncodingError(format!("MessagePack decoding failed at byte {}: {}", data.len() - remaining.len(), e)))?;
+            items.push(msgpack_value_to_json(item).to_string());
+        }
+        if items.is_empty() {
+            return Err(SerialError::EncodingError("MessagePack decoding failed: no data".to_string()));
+        }
+        Ok(items.join("\n"))
+    }
+
+    /// Encode JSON text as one MessagePack value
+    pub fn json_to_msgpack(data: &str) -> Result<Vec<u8>> {
+        let value: serde_json::Value = serde_json::from_str(data)
+            .map_err(|e| SerialError::EncodingError(format!("Invalid JSON for MessagePack: {}", e)))?;
+        let value = rmpv::ext::to_value(value)
+            .map_err(|e| SerialError::EncodingError(format!("MessagePack encoding failed: {}", e)))?;
+        let mut encoded = Vec::new();
+        rmpv::encode::write_value(&mut encoded, &value)
+            .map_err(|e| SerialError::EncodingError(format!("MessagePack encoding failed: {}", e)))?;
+        Ok(encoded)
+    }
+}
+
+fn msgpack_value_to_json(value: rmpv::Value) -> serde_json::Value {
+    use rmpv::Value as Msgpack;
+    use serde_json::Value as Json;
+    match value {
+        Msgpack::Nil => Json::Null,
+        Msgpack::Boolean(value) => Json::Bool(value),
+        Msgpack::Integer(integer) => integer
+            .as_u64()
+            .map(Json::from)
+            .or_else(|| integer.as_i64().map(Json::from))
+            .unwrap_or(Json::Null),
+        Msgpack::F32(float) => serde_json::Number::from_f64(f64::from(float)).map_or(Json::Null, Json::Number),
+        Msgpack::F64(float) => serde_json::Number::from_f64(float).map_or(Json::Null, Json::Number),
+        // Strings that are not UTF-8 keep their bytes as hex
+        Msgpack::String(text) => match text.as_str() {
+            Some(text) => Json::String(text.to_string()),
+            None => Json::String(hex::encode(text.as_bytes())),
+        },
+        Msgpack::Binary(bytes) => Json::String(hex::encode(bytes)),
+        Msgpack::Array(items) => Json::Array(items.into_iter().map(msgpack_value_to_json).collect()),
+        Msgpack::Map(entries) => Json::Object(
+            entries
+                .into_iter()
+                .map(|(key, value)| {
+                    let key = match msgpack_value_to_json(key) {
+                        Json::String(key) => key,
+                        key => key.to_string(),
+                    };
+                    (key, msgpack_value_to_json(value))
+                })
+                .collect(),
+        ),
+        Msgpack::Ext(kind, data) => serde_json::json!({ "ext": kind, "data": hex::encode(data) }),
+    }
+}
+
+fn cbor_value_to_json(value: ciborium::Value) -> serde_json::Value {
+    use ciborium::Value as Cbor;
+    use serde_json::Value as Json;
+    match value {
+        Cbor::Integer(integer) => {
+            let integer = i128::from(integer);
+            u64::try_from(integer)
+                .map(Json::from)
+                .or_else(|_| i64::try_from(integer).map(Json::from))
+                .unwrap_or_else(|_| Json::String(integer.to_string()))
+        }
+        Cbor::Bytes(bytes) => Json::String(hex::encode(bytes)),
+        Cbor::Float(float) => serde_json::Number::from_f64(float).map_or(Json::Null, Json::Number),
+        Cbor::Text(text) => Json::String(text),
+        Cbor::Bool(value) => Json::Bool(value),
+        Cbor::Null => Json::Null,
+        Cbor::Tag(tag, value) => serde_json::json!({ "tag": tag, "value": cbor_value_to_json(*value) }),
+        Cbor::Array(items) => Json::Array(items.into_iter().map(cbor_value_to_json).collect()),
+        Cbor::Map(entries) => Json::Object(
+            entries
+                .into_iter()
+                .map(|(key, value)| {
+                    let key = match cbor_value_to_json(key) {
+                        Json::String(key) => key,
+                        key => key.to_string(),
+                    };
+                    (key, cbor_value_to_json(value))
+                })
+                .collect(),
+        ),
+        // Simple values other than booleans and null have no JSON counterpart
+        _ => Json::Null,
+    }
 }
 
 /// Time utilities
@@ -225,7 +356,7 @@ impl Validator {
     /// Validate baud rate
     pub fn validate_baud_rate(baud_rate: u32) -> Result<()> {
         const VALID_BAUD_RATES: &[u32] = &[
-            300, 600, 1200, 2400, 4800, 9600, 14400, 19200, 28800, 38400, 
+            300, 600, 1200, 2400, 4800, 9600, 14400, 19200, 28800, 31250, 38400,
             57600, 115200, 230400, 460800, 921600
         ];
         
@@ -283,6 +414,9 @@ impl Validator {
     }
 }
 
+/// Bytes shown per row of a hexdump
+const HEXDUMP_ROW_BYTES: usize = 16;
+
 /// Buffer utilities
 pub struct BufferUtils;
 
@@ -352,6 +486,181 @@ impl BufferUtils {
         
         data.iter().fold(0u8, |crc, &byte| CRC8_TABLE[(crc ^ byte) as usize])
     }
+
+    /// Calculate the Modbus RTU CRC (CRC-16/MODBUS)
+    pub fn crc16_modbus(data: &[u8]) -> u16 {
+        data.iter().fold(0xFFFFu16, |crc, &byte| {
+            (0..8).fold(crc ^ byte as u16, |crc, _| {
+                if crc & 1 != 0 { (crc >> 1) ^ 0xA001 } else { crc >> 1 }
+            })
+        })
+    }
+
+    /// Calculate the DSMR P1 telegram CRC (CRC-16/ARC)
+    pub fn crc16_arc(data: &[u8]) -> u16 {
+        data.iter().fold(0u16, |crc, &byte| {
+            (0..8).fold(crc ^ byte as u16, |crc, _| {
+                if crc & 1 != 0 { (crc >> 1) ^ 0xA001 } else { crc >> 1 }
+            })
+        })
+    }
+
+    /// Calculate the HDLC frame check sequence (CRC-16/X-25)
+    pub fn crc16_x25(data: &[u8]) -> u16 {
+        let crc = data.iter().fold(0xFFFFu16, |crc, &byte| {
+            (0..8).fold(crc ^ byte as u16, |crc, _| {
+                if crc & 1 != 0 { (crc >> 1) ^ 0x8408 } else { crc >> 1 }
+            })
+        });
+        !crc
+    }
+
+    /// Calculate CRC-16/CCITT-FALSE (polynomial 0x1021, initial value 0xFFFF)
+    pub fn crc16_ccitt(data: &[u8]) -> u16 {
+        Self::crc(data, &CrcParams::CRC16_CCITT) as u16
+    }
+
+    /// Calculate the CRC-32 of Ethernet, zip and PNG
+    pub fn crc32(data: &[u8]) -> u32 {
+        Self::crc(data, &CrcParams::CRC32) as u32
+    }
+
+    /// Calculate the Fletcher-16 checksum, second sum in the high byte
+    pub fn fletcher16(data: &[u8]) -> u16 {
+        let (low, high) = data.iter().fold((0u16, 0u16), |(low, high), &byte| {
+            let low = (low + byte as u16) % 255;
+            (low, (high + low) % 255)
+        });
+        (high << 8) | low
+    }
+
+    /// Calculate any CRC of up to 64 bits, one bit at a time
+    pub fn crc(data: &[u8], params: &CrcParams) -> u64 {
+        let mask = u64::MAX >> (64 - params.width);
+        let top = 1u64 << (params.width - 1);
+        let poly = params.poly & mask;
+        let mut crc = params.init & mask;
+        for &byte in data {
+            let byte = if params.reflect_in { byte.reverse_bits() } else { byte };
+            for bit in (0..8).rev() {
+                let feedback = (crc & top != 0) != ((byte >> bit) & 1 != 0);
+                crc = (crc << 1) & mask;
+                if feedback {
+                    crc ^= poly;
+                }
+            }
+        }
+        if params.reflect_out {
+            crc = crc.reverse_bits() >> (64 - params.width);
+        }
+        (crc ^ params.xor_out) & mask
+    }
+
+    /// Render bytes as offset/hex/ASCII rows, 16 bytes per row
+    pub fn hexdump(data: &[u8]) -> String {
+        let mut out = String::new();
+        for (row, bytes) in data.chunks(HEXDUMP_ROW_BYTES).enumerate() {
+            let hex: Vec<String> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+            let ascii: String = bytes
+                .iter()
+                .map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' })
+                .collect();
+            out.push_str(&format!(
+                "{:08x}  {:<width$}  |{}|\n",
+                row * HEXDUMP_ROW_BYTES,
+                hex.join(" "),
+                ascii,
+                width = HEXDUMP_ROW_BYTES * 3 - 1
+            ));
+        }
+        out
+    }
+}
+
+/// A CRC described the way the CRC catalogue lists them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CrcParams {
+    /// 1 to 64 bits
+    pub width: u32,
+    /// Polynomial without its top bit, unreflected
+    pub poly: u64,
+    pub init: u64,
+    /// Bytes are processed least significant bit first
+    pub reflect_in: bool,
+    /// The register is reversed before the final XOR
+    pub reflect_out: bool,
+    pub xor_out: u64,
+}
+
+impl CrcParams {
+    pub const CRC8: Self = Self::new(8, 0x07, 0, false, 0);
+    pub const CRC16_CCITT: Self = Self::new(16, 0x1021, 0xFFFF, false, 0);
+    pub const CRC16_XMODEM: Self = Self::new(16, 0x1021, 0, false, 0);
+    pub const CRC16_KERMIT: Self = Self::new(16, 0x1021, 0, true, 0);
+    pub const CRC16_X25: Self = Self::new(16, 0x1021, 0xFFFF, true, 0xFFFF);
+    pub const CRC16_MODBUS: Self = Self::new(16, 0x8005, 0xFFFF, true, 0);
+    pub const CRC16_ARC: Self = Self::new(16, 0x8005, 0, true, 0);
+    pub const CRC32: Self = Self::new(32, 0x04C1_1DB7, 0xFFFF_FFFF, true, 0xFFFF_FFFF);
+    pub const CRC32C: Self = Self::new(32, 0x1EDC_6F41, 0xFFFF_FFFF, true, 0xFFFF_FFFF);
+
+    /// A CRC reflecting input and output alike, as nearly all do
+    pub const fn new(width: u32, poly: u64, init: u64, reflect: bool, xor_out: u64) -> Self {
+        Self { width, poly, init, reflect_in: reflect, reflect_out: reflect, xor_out }
+    }
+}
+
+/// A checksum algorithm chosen by name
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Checksum {
+    /// Sum of the bytes, modulo 256
+    Sum8,
+    Xor8,
+    Fletcher16,
+    Crc(CrcParams),
+}
+
+impl Checksum {
+    /// Names accepted by [`Checksum::named`]
+    pub const NAMES: &'static [&'static str] = &[
+        "sum8", "xor8", "fletcher16", "crc8", "crc16_ccitt", "crc16_xmodem", "crc16_kermit", "crc16_x25",
+        "crc16_modbus", "crc16_arc", "crc32", "crc32c",
+    ];
+
+    pub fn named(name: &str) -> Option<Self> {
+        Some(match name.to_lowercase().replace('-', "_").as_str() {
+            "sum8" | "sum" => Checksum::Sum8,
+            "xor8" | "xor" => Checksum::Xor8,
+            "fletcher16" => Checksum::Fletcher16,
+            "crc8" => Checksum::Crc(CrcParams::CRC8),
+            "crc16_ccitt" | "crc16_ccitt_false" => Checksum::Crc(CrcParams::CRC16_CCITT),
+            "crc16_xmodem" => Checksum::Crc(CrcParams::CRC16_XMODEM),
+            "crc16_kermit" => Checksum::Crc(CrcParams::CRC16_KERMIT),
+            "crc16_x25" => Checksum::Crc(CrcParams::CRC16_X25),
+            "crc16_modbus" => Checksum::Crc(CrcParams::CRC16_MODBUS),
+            "crc16_arc" => Checksum::Crc(CrcParams::CRC16_ARC),
+            "crc32" => Checksum::Crc(CrcParams::CRC32),
+            "crc32c" => Checksum::Crc(CrcParams::CRC32C),
+            _ => return None,
+        })
+    }
+
+    /// Width of the value in bits
+    pub fn width(&self) -> u32 {
+        match self {
+            Checksum::Sum8 | Checksum::Xor8 => 8,
+            Checksum::Fletcher16 => 16,
+            Checksum::Crc(params) => params.width,
+        }
+    }
+
+    pub fn compute(&self, data: &[u8]) -> u64 {
+        match self {
+            Checksum::Sum8 => BufferUtils::checksum_sum(data).into(),
+            Checksum::Xor8 => BufferUtils::checksum_xor(data).into(),
+            Checksum::Fletcher16 => BufferUtils::fletcher16(data).into(),
+            Checksum::Crc(params) => BufferUtils::crc(data, params),
+        }
+    }
 }
 
 /// Session ID generation
@@ -478,6 +787,11 @@ mod tests {
         assert_eq!(parts[0], b"Hello");
         assert_eq!(parts[1], b"World");
         assert_eq!(parts[2], b"Test");
+
+        assert_eq!(
+            BufferUtils::hexdump(b"AT\r\n"),
+            format!("00000000  {:<47}  |AT..|\n", "41 54 0d 0a")
+        );
     }
 
     #[test]
@@ -490,6 +804,29 @@ mod tests {
         assert_eq!(sum_checksum, 244); // 'H' + 'e' + 'l' + 'l' + 'o' = 72 + 101 + 108 + 108 + 111 = 500 % 256 = 244
         assert_ne!(xor_checksum, 0);
         assert_ne!(crc8_checksum, 0);
+        assert_eq!(BufferUtils::crc16_x25(b"123456789"), 0x906E);
+        assert_eq!(BufferUtils::crc16_modbus(b"123456789"), 0x4B37);
+        assert_eq!(BufferUtils::crc16_arc(b"123456789"), 0xBB3D);
+        assert_eq!(BufferUtils::crc16_ccitt(b"123456789"), 0x29B1);
+        assert_eq!(BufferUtils::crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(BufferUtils::fletcher16(b"abcde"), 0xC8F0);
+    }
+
+    #[test]
+    fn test_crc_catalogue_checks() {
+        // The check value of each algorithm is its CRC of "123456789"
+        let check = |name: &str| Checksum::named(name).unwrap().compute(b"123456789");
+        assert_eq!(check("crc8"), 0xF4);
+        assert_eq!(check("crc16_xmodem"), 0x31C3);
+        assert_eq!(check("crc16_kermit"), 0x2189);
+        assert_eq!(check("crc16_x25"), u64::from(BufferUtils::crc16_x25(b"123456789")));
+        assert_eq!(check("CRC16-MODBUS"), u64::from(BufferUtils::crc16_modbus(b"123456789")));
+        assert_eq!(check("crc32c"), 0xE306_9283);
+        assert_eq!(BufferUtils::crc(b"123456789", &CrcParams::new(64, 0x42F0_E1EB_A9EA_3693, 0, false, 0)), 0x6C40_DF5F_0B49_7347);
+        // CRC-5/USB: reflected, not a whole number of bytes wide
+        assert_eq!(BufferUtils::crc(b"123456789", &CrcParams::new(5, 0x05, 0x1F, true, 0x1F)), 0x19);
+        assert!(Checksum::NAMES.iter().all(|name| Checksum::named(name).is_some()));
+        assert_eq!(Checksum::named("md5"), None);
     }
 
     #[test]
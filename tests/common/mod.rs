@@ -2,7 +2,7 @@
 
 pub mod mock_serial;
 
-use serial_mcp_rs::serial::{ConnectionConfig, DataBits, StopBits, Parity, FlowControl};
+use serial_mcp_rs::serial::{BackpressurePolicy, ConnectionConfig, DataBits, StopBits, Parity, FlowControl};
 
 /// Create a test connection configuration
 pub fn test_connection_config(port: &str) -> ConnectionConfig {
@@ -13,5 +13,6 @@ pub fn test_connection_config(port: &str) -> ConnectionConfig {
         stop_bits: StopBits::One,
         parity: Parity::None,
         flow_control: FlowControl::None,
+        backpressure: BackpressurePolicy::Drop,
     }
 }
\ No newline at end of file